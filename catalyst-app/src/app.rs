@@ -1,13 +1,14 @@
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use std::{
+    cell::RefCell,
     io::{BufReader, IsTerminal, Read, Write},
     ops::Range,
     path::PathBuf,
     process::Stdio,
     rc::Rc,
     sync::{
-        Arc,
+        Arc, Once,
         atomic::AtomicU64,
         mpsc::{SyncSender, channel, sync_channel},
     },
@@ -74,8 +75,8 @@
         WindowCommand,
     },
     config::{
-        LapceConfig, color::LapceColor, icon::LapceIcons, ui::TabSeparatorHeight,
-        watcher::ConfigWatcher,
+        LapceConfig, color::LapceColor, icon::LapceIcons, log::LogConfig,
+        ui::TabSeparatorHeight, watcher::ConfigWatcher,
     },
     db::LapceDb,
     debug::RunDebugMode,
@@ -85,6 +86,7 @@
         view::editor_container_view,
     },
     editor_tab::{EditorTabChild, EditorTabData},
+    first_paint::first_paint,
     focus_text::focus_text,
     id::{EditorTabId, SplitId},
     keymap::keymap_view,
@@ -100,6 +102,15 @@
     },
     panel::{position::PanelContainerPosition, view::panel_container_view},
     plugin::{PluginData, plugin_info_view},
+    plugin_api::{
+        AssistantLookup, ControlServer, CredentialStore,
+        DEFAULT_ALLOC_METRICS_INTERVAL, DEFAULT_SAMPLE_INTERVAL, Determinism,
+        LogController, MemoryPressure, OnboardingRecord, PluginConfig,
+        PluginManager, ScopeGuard, StartupProfiler, WebhookListener,
+        WebhookNotification,
+        alloc_tracking_enabled, configure_global_pool, register_onboarding_panel,
+        start_allocation_metrics, startup_scope, webhook_token,
+    },
     settings::{settings_view, theme_color_settings_view},
     status::status,
     text_input::TextInputBuilder,
@@ -111,6 +122,7 @@
     workspace::{LapceWorkspace, LapceWorkspaceType},
 };
 
+mod cli;
 mod grammars;
 mod logging;
 
@@ -119,8 +131,9 @@
 #[clap(version=meta::VERSION)]
 #[derive(Debug)]
 struct Cli {
-    /// Launch new window even if Lapce is already running
-    #[clap(short, long, action)]
+    /// Start a separate instance even if Catalyst is already running,
+    /// instead of opening the paths in a new window of the running one
+    #[clap(short, long, visible_alias = "new-instance", action)]
     new: bool,
     /// Don't return instantly when opened in a terminal
     #[clap(short, long, action)]
@@ -133,6 +146,12 @@ struct Cli {
     #[clap(long, action)]
     plugin_path: Vec<PathBuf>,
 
+    /// Sample the first SECONDS of startup (10 by default) into a folded
+    /// stack file in the logs directory, readable by flamegraph tools
+    #[clap(long, value_name = "SECONDS")]
+    #[clap(num_args = 0..=1, default_missing_value = "10")]
+    profile_startup: Option<u64>,
+
     /// Paths to file(s) and/or folder(s) to open.
     /// When path is a file (that exists or not),
     /// it accepts `path:line:column` syntax
@@ -140,6 +159,9 @@ struct Cli {
     #[clap(value_parser = catalyst_proxy::cli::parse_file_line_column)]
     #[clap(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<PathObject>,
+
+    #[clap(subcommand)]
+    command: Option<cli::CliCommand>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -176,6 +198,13 @@ impl AppData {
     pub fn reload_config(&self) {
         let config =
             LapceConfig::load(&LapceWorkspace::default(), &[], &self.plugin_paths);
+        if config.log != self.config.get_untracked().log {
+            if let Some(controller) = LogController::global() {
+                if let Err(err) = controller.apply_config(config.log.clone()) {
+                    tracing::error!("Invalid log settings: {err:#}");
+                }
+            }
+        }
         self.config.set(Arc::new(config));
         let windows = self.windows.get_untracked();
         for (_, window) in windows {
@@ -279,6 +308,9 @@ pub fn run_app_command(&self, cmd: AppCommand) {
                     .try_update(|windows| windows.remove(&window_id))
                     .unwrap();
                 if let Some(window_data) = window_data {
+                    for (_, window_tab) in window_data.window_tabs.get_untracked() {
+                        window_tab.close();
+                    }
                     window_data.scope.dispose();
                 }
                 if let Err(err) = db.save_app(self) {
@@ -635,6 +667,10 @@ fn app_view(
             .on_event_stop(EventListener::WindowResized, move |event| {
                 if let Event::WindowResized(size) = event {
                     window_size.set(*size);
+                    // Minimized windows are resized to nothing
+                    if size.width == 0.0 || size.height == 0.0 {
+                        release_plugin_memory(MemoryPressure::Critical);
+                    }
                 }
             })
             .on_event_stop(EventListener::WindowMoved, move |event| {
@@ -645,6 +681,9 @@ fn app_view(
             .on_event_stop(EventListener::WindowGotFocus, move |_| {
                 app_command.send(AppCommand::WindowGotFocus(window_id));
             })
+            .on_event_stop(EventListener::WindowLostFocus, move |_| {
+                release_plugin_memory(MemoryPressure::Moderate);
+            })
             .on_event_stop(EventListener::WindowClosed, move |_| {
                 app_command.send(AppCommand::WindowClosed(window_id));
             })
@@ -2561,6 +2600,7 @@ fn palette_item(
         | PaletteItemContent::ColorTheme { .. }
         | PaletteItemContent::SCMReference { .. }
         | PaletteItemContent::TerminalProfile { .. }
+        | PaletteItemContent::Credential { .. }
         | PaletteItemContent::IconTheme { .. } => {
             let text = item.filter_text;
             let indices = item.indices;
@@ -3328,6 +3368,8 @@ fn window_tab(window_tab_data: Rc<WindowTabData>) -> impl View {
         palette(window_tab_data.clone()),
         about::about_popup(window_tab_data.clone()),
         alert::alert_box(window_tab_data.alert_data.clone()),
+        first_paint(on_first_frame)
+            .style(|s| s.absolute().size_full().pointer_events_none()),
     ))
     .on_cleanup(move || {
         window_tab_scope.dispose();
@@ -3357,6 +3399,40 @@ fn window_tab(window_tab_data: Rc<WindowTabData>) -> impl View {
     view
 }
 
+thread_local! {
+    /// Scope of the launch, ended once the first frame is painted
+    static LAUNCH_SCOPE: RefCell<Option<ScopeGuard>> = const { RefCell::new(None) };
+}
+
+/// Ask the plugins to release memory they can rebuild, while a window is
+/// in the background or minimized
+fn release_plugin_memory(pressure: MemoryPressure) {
+    if let Some(manager) = PluginManager::global() {
+        manager.read().on_low_memory(pressure);
+    }
+}
+
+/// Start the work held back until the first window is painted, once per
+/// process
+fn on_first_frame() {
+    static FIRST_FRAME: Once = Once::new();
+    FIRST_FRAME.call_once(|| {
+        drop(LAUNCH_SCOPE.with(|scope| scope.borrow_mut().take()));
+        let Some(manager) = PluginManager::global() else {
+            return;
+        };
+        let warm_up = manager.read().warm_up_servers();
+        std::thread::Builder::new()
+            .name("WarmUpServers".to_owned())
+            .spawn(move || {
+                if let Err(err) = warm_up.wait().and_then(|r| r.into_result()) {
+                    tracing::warn!("Failed to warm up MCP servers: {err:#}");
+                }
+            })
+            .unwrap();
+    });
+}
+
 fn workspace_title(workspace: &LapceWorkspace) -> Option<String> {
     let p = workspace.path.as_ref()?;
     let dir = p.file_name().unwrap_or(p.as_os_str()).to_string_lossy();
@@ -3709,7 +3785,15 @@ fn window(window_data: WindowData) -> impl View {
 }
 
 pub fn launch() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(cli::args());
+
+    if let Some(command) = cli.command {
+        if let Err(err) = cli::run(command) {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
     if !cli.wait {
         logging::panic_hook();
@@ -3793,16 +3877,49 @@ pub fn launch() {
         return;
     }
 
+    // Keep the profiler running in the background; it stops by itself and
+    // writes the profile once the requested duration has elapsed, or early
+    // when it is dropped on exit
+    let startup_profiler = cli.profile_startup.and_then(|seconds| {
+        let output = StartupProfiler::default_output(&Directory::logs_directory()?);
+        match StartupProfiler::start(
+            output,
+            std::time::Duration::from_secs(seconds),
+            DEFAULT_SAMPLE_INTERVAL,
+        ) {
+            Ok(profiler) => Some(profiler),
+            Err(err) => {
+                tracing::error!("Failed to start startup profiling: {err:#}");
+                None
+            }
+        }
+    });
+    if alloc_tracking_enabled() {
+        start_allocation_metrics(DEFAULT_ALLOC_METRICS_INTERVAL);
+    }
+    let launch_scope = startup_scope("launch");
+
     // If the cli is not requesting a new window, and we're not developing a plugin, we try to open
     // in the existing Lapce process
     if !cli.new {
         match get_socket() {
-            Ok(socket) => {
-                if let Err(e) = try_open_in_existing_process(socket, &cli.paths) {
-                    trace!(TraceLevel::ERROR, "failed to open path(s): {e}");
-                };
-                return;
-            }
+            Ok(socket) => match try_open_in_existing_process(socket, &cli.paths) {
+                Ok(()) => {
+                    trace!(
+                        TraceLevel::INFO,
+                        "Opened path(s) in the running instance"
+                    );
+                    return;
+                }
+                // The running instance is hung or shutting down, so start
+                // a new one rather than leave the user with nothing
+                Err(e) => {
+                    trace!(
+                        TraceLevel::ERROR,
+                        "failed to open path(s) in the running instance: {e}"
+                    );
+                }
+            },
             Err(err) => {
                 tracing::error!("{:?}", err);
             }
@@ -3815,6 +3932,7 @@ pub fn launch() {
     if let Err(err) = catalyst_proxy::register_lapce_path() {
         tracing::error!("{:?}", err);
     }
+    let db_scope = startup_scope("open_db");
     let db = match LapceDb::new() {
         Ok(db) => Arc::new(db),
         Err(e) => {
@@ -3822,9 +3940,11 @@ pub fn launch() {
             logging::error_modal("Error", &format!("Failed to create LapceDb: {e}"));
 
             trace!(TraceLevel::ERROR, "Failed to create LapceDb: {e}");
+            drop(startup_profiler);
             std::process::exit(1);
         }
     };
+    drop(db_scope);
     let scope = Scope::new();
     provide_context(db.clone());
 
@@ -3834,6 +3954,7 @@ pub fn launch() {
 
     let plugin_paths = Arc::new(cli.plugin_path);
 
+    let watch_scope = startup_scope("watch_config");
     let (tx, rx) = channel();
     let mut watcher = notify::recommended_watcher(ConfigWatcher::new(tx)).unwrap();
     if let Some(path) = LapceConfig::settings_file() {
@@ -3857,8 +3978,58 @@ pub fn launch() {
         }
     }
 
+    drop(watch_scope);
+
     let windows = scope.create_rw_signal(im::HashMap::new());
-    let config = LapceConfig::load(&LapceWorkspace::default(), &[], &plugin_paths);
+    let config = {
+        let _scope = startup_scope("load_config");
+        LapceConfig::load(&LapceWorkspace::default(), &[], &plugin_paths)
+    };
+
+    let log_controller = LogController::new(LogConfig::default(), {
+        let handle = reload_handle.clone();
+        move |targets| handle.reload(targets).map_err(|err| anyhow!("{err}"))
+    });
+    if let Err(err) = log_controller.apply_config(config.log.clone()) {
+        tracing::error!("Invalid log settings: {err:#}");
+    }
+    if let Err(err) = LogController::install(log_controller) {
+        tracing::error!("{err:#}");
+    }
+
+    // Integration tests start the app with a seed so a failing run can be
+    // reproduced
+    if let Some(determinism) = Determinism::from_env() {
+        tracing::info!("Running deterministically with seed {}", determinism.seed);
+        determinism.install();
+    }
+
+    // Installed before the windows, whose workspaces become the roots of
+    // the MCP servers
+    {
+        let _scope = startup_scope("plugin_manager");
+        let plugin_config = PluginConfig::load_default();
+        // Before the windows, whose documents highlight on the global pool
+        if let Err(err) = configure_global_pool(&plugin_config.concurrency) {
+            tracing::error!("Failed to size the background thread pool: {err:#}");
+        }
+        let mut plugin_manager = PluginManager::new(plugin_config);
+        if let Err(err) = plugin_manager.initialize() {
+            tracing::error!("Failed to initialize the plugin manager: {err:#}");
+        }
+        // Offered until the onboarding completes once
+        if let Some(record_path) = OnboardingRecord::default_path() {
+            if let Err(err) = register_onboarding_panel(
+                plugin_manager.get_sidebar_registry(),
+                &record_path,
+            ) {
+                tracing::error!("Failed to offer the onboarding: {err:#}");
+            }
+        }
+        if let Err(err) = PluginManager::install(plugin_manager) {
+            tracing::error!("{err:#}");
+        }
+    }
 
     // Restore scale from config
     window_scale.set(config.ui.scale());
@@ -3877,7 +4048,12 @@ pub fn launch() {
         plugin_paths,
     };
 
-    let app = app_data.create_windows(db.clone(), cli.paths);
+    let app = {
+        let _scope = startup_scope("create_windows");
+        app_data.create_windows(db.clone(), cli.paths)
+    };
+    // Painting the first frame is part of the launch
+    LAUNCH_SCOPE.with(|scope| *scope.borrow_mut() = Some(launch_scope));
 
     {
         let app_data = app_data.clone();
@@ -3983,10 +4159,21 @@ pub fn launch() {
         let app_data = app_data.clone();
         create_effect(move |_| {
             if let Some(CoreNotification::OpenPaths { paths }) = notification.get() {
-                if let Some(window_tab) = app_data.active_window_tab() {
-                    window_tab.open_paths(&paths);
-                    // focus window after open doc
-                    floem::action::focus_window();
+                // Folders handed over by another launch get their own window,
+                // like a second process would have opened
+                let (folders, files): (Vec<PathObject>, Vec<PathObject>) =
+                    paths.into_iter().partition(|p| p.is_dir);
+                for folder in folders {
+                    app_data.app_command.send(AppCommand::NewWindow {
+                        folder: Some(folder.path),
+                    });
+                }
+                if !files.is_empty() {
+                    if let Some(window_tab) = app_data.active_window_tab() {
+                        window_tab.open_paths(&files);
+                        // focus window after open doc
+                        floem::action::focus_window();
+                    }
                 }
             }
         });
@@ -4000,6 +4187,63 @@ pub fn launch() {
             .unwrap();
     }
 
+    // Headless clients, such as the CLI, drive the assistants and the tools
+    // of the MCP servers through the control socket
+    {
+        let assistants: AssistantLookup = Arc::new(|assistant: Option<&str>| {
+            let manager = PluginManager::global()?.read();
+            match assistant {
+                Some(id) => manager.get_ai_assistant(id),
+                None => manager.active_ai_assistant(),
+            }
+        });
+        let server = match PluginManager::global() {
+            Some(manager) => ControlServer::with_registry(
+                assistants,
+                manager.read().get_mcp_registry().clone(),
+            ),
+            None => ControlServer::new(assistants),
+        };
+        std::thread::Builder::new()
+            .name("ListenControlSocket".to_owned())
+            .spawn(move || {
+                if let Err(err) = server.listen() {
+                    tracing::error!("{:?}", err);
+                }
+            })
+            .unwrap();
+    }
+
+    // CI jobs and other external systems post to the notification center
+    // through the webhook listener, once it is enabled in the settings
+    if let Some(settings) = PluginManager::global()
+        .map(|manager| manager.read().get_config().webhook.clone())
+        .filter(|settings| settings.enabled)
+    {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let notification = create_signal_from_channel(rx);
+        let app_data = app_data.clone();
+        create_effect(move |_| {
+            let Some(notification) = notification.get() else {
+                return;
+            };
+            if let Some(window_tab) = app_data.active_window_tab() {
+                window_tab.show_message(
+                    &notification.title,
+                    &notification.show_message_params(),
+                );
+            }
+        });
+        std::thread::Builder::new()
+            .name("WebhookListener".to_owned())
+            .spawn(move || {
+                if let Err(err) = serve_webhooks(settings.port, tx) {
+                    tracing::error!("Webhook listener stopped: {err:#}");
+                }
+            })
+            .unwrap();
+    }
+
     {
         let app_data = app_data.clone();
         app_data.app_command.listen(move |command| {
@@ -4007,12 +4251,26 @@ pub fn launch() {
         });
     }
 
+    let startup_profiler = RefCell::new(startup_profiler);
     app.on_event(move |event| match event {
         floem::AppEvent::WillTerminate => {
+            drop(startup_profiler.borrow_mut().take());
             app_data.app_terminated.set(true);
             if let Err(err) = db.insert_app(app_data.clone()) {
                 tracing::error!("{:?}", err);
             }
+            // Flushes the stores, saves the panels and stops the MCP
+            // servers, exiting at the latest once the shutdown deadline
+            // passes
+            if let Some(manager) = PluginManager::global() {
+                let panel_state = Directory::data_local_directory()
+                    .map(|dir| dir.join("panel_state.json"));
+                let coordinator =
+                    manager.read().shutdown_sequence(panel_state.as_deref());
+                let report = Directory::logs_directory()
+                    .map(|dir| dir.join("shutdown.json"));
+                coordinator.run_and_exit(report.as_deref(), 0);
+            }
         }
         floem::AppEvent::Reopen {
             has_visible_windows,
@@ -4160,6 +4418,20 @@ fn listen_local_socket(tx: SyncSender<CoreNotification>) -> Result<()> {
     Ok(())
 }
 
+/// Serve the webhook listener on `port`, sending posted notifications to
+/// `tx`
+///
+/// No automation engine runs in the app yet, so hook and GitHub deliveries
+/// are refused.
+fn serve_webhooks(
+    port: u16,
+    tx: crossbeam_channel::Sender<WebhookNotification>,
+) -> Result<()> {
+    let token = webhook_token(CredentialStore::global())?;
+    let listener = WebhookListener::bind(port)?;
+    WebhookListener::new(token, None, tx).serve(listener)
+}
+
 pub fn window_menu(
     lapce_command: Listener<LapceCommand>,
     workbench_command: Listener<LapceWorkbenchCommand>,
@@ -0,0 +1,394 @@
+use std::{
+    ffi::OsString,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use clap::Subcommand;
+
+use crate::plugin_api::{
+    BaselineStore, BenchBaseline, BenchComparison, BundleCategory, BundleEntry,
+    ConflictResolution, CredentialStore, DEFAULT_BENCH_SAMPLES,
+    DEFAULT_MAX_REGRESSION_PERCENT, MCP_REGISTRATION_FILE, MachineFingerprint,
+    McpScaffold, ModelCatalog, Report, ReportFormat, RoutingPolicy, SettingsBundle,
+    UsageSimulation, UsageStore, builtin_benchmarks,
+};
+
+/// Commands that run in the terminal without opening a window
+#[derive(Subcommand, Debug)]
+pub(super) enum CliCommand {
+    /// Time the indexing and retrieval code against this machine's baseline
+    Bench {
+        /// Fail when a p95 regressed by more than --max-regression, or when
+        /// this machine has no baseline
+        #[clap(long, action)]
+        check: bool,
+        /// Record the results as this machine's baseline, once --check passed
+        #[clap(long, action)]
+        save: bool,
+        /// Allowed p95 slowdown in percent
+        #[clap(long, default_value_t = DEFAULT_MAX_REGRESSION_PERCENT)]
+        max_regression: f64,
+        /// Timed runs of each benchmark
+        #[clap(long, default_value_t = DEFAULT_BENCH_SAMPLES)]
+        samples: usize,
+        /// Directory of baselines, by default in the local data directory
+        #[clap(long)]
+        baseline_dir: Option<PathBuf>,
+        /// Output format (text, json, html)
+        #[clap(long, default_value = "text")]
+        format: String,
+    },
+    /// Manage the user configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Develop MCP servers
+    Mcp {
+        #[clap(subcommand)]
+        command: McpCommand,
+    },
+    /// Show the tokens and cost of assistant requests
+    Usage {
+        #[clap(subcommand)]
+        command: Option<UsageCommand>,
+        /// Output format (text, json, html)
+        #[clap(long, default_value = "text", global = true)]
+        format: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum ConfigCommand {
+    /// Export settings, keymaps and the prompt library to a bundle file
+    Export {
+        /// File to write the bundle to
+        output: PathBuf,
+        /// Encrypt the bundle with a passphrase
+        #[clap(long, action)]
+        encrypt: bool,
+        /// Include credentials, which requires --encrypt
+        #[clap(long, action)]
+        include_credentials: bool,
+        /// Only export these categories (settings, keymaps, prompts, credentials)
+        #[clap(long, value_delimiter = ',')]
+        only: Vec<String>,
+    },
+    /// Import a bundle created by `config export`
+    Import {
+        /// Bundle file to import
+        input: PathBuf,
+        /// Import credentials into the credential store
+        #[clap(long, action)]
+        include_credentials: bool,
+        /// Only import these categories (settings, keymaps, prompts, credentials)
+        #[clap(long, value_delimiter = ',')]
+        only: Vec<String>,
+        /// Overwrite conflicting files without asking
+        #[clap(long, action, conflicts_with = "skip_existing")]
+        overwrite: bool,
+        /// Keep existing files without asking
+        #[clap(long, action)]
+        skip_existing: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum McpCommand {
+    /// Create a Rust MCP server project with an example tool and compliance
+    /// tests
+    New {
+        /// Name of the server and its crate
+        name: String,
+        /// Directory to create the project in, by default ./<name>
+        #[clap(long)]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum UsageCommand {
+    /// Estimate the cost and latency of a recorded session under other
+    /// routing policies
+    Simulate {
+        /// Usage key of the session, e.g. project_chat/12
+        #[clap(long)]
+        session: String,
+        /// Routing policy to compare: recorded, a model name, or
+        /// tiered:<small>,<large>,<max small input tokens>; by default the
+        /// recorded models and each model of the catalog
+        #[clap(long)]
+        policy: Vec<String>,
+    },
+}
+
+/// Get the command line arguments, treating a first argument that names both
+/// a command and an existing file or folder as a path to open
+pub(super) fn args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let shadowed = args.get(1).and_then(|arg| arg.to_str()).is_some_and(|arg| {
+        CliCommand::has_subcommand(arg) && Path::new(arg).exists()
+    });
+    if shadowed {
+        args.insert(1, OsString::from("--"));
+    }
+    args
+}
+
+pub(super) fn run(command: CliCommand) -> Result<()> {
+    match command {
+        CliCommand::Bench {
+            check,
+            save,
+            max_regression,
+            samples,
+            baseline_dir,
+            format,
+        } => run_bench(check, save, max_regression, samples, baseline_dir, &format),
+        CliCommand::Config { command } => run_config(command),
+        CliCommand::Mcp { command } => run_mcp(command),
+        CliCommand::Usage { command, format } => run_usage(command, &format),
+    }
+}
+
+fn run_usage(command: Option<UsageCommand>, format: &str) -> Result<()> {
+    let format = ReportFormat::from_name(format)?;
+    let store = UsageStore::open_default()?;
+    match command {
+        None => print!("{}", store.report().render(format)?),
+        Some(UsageCommand::Simulate { session, policy }) => {
+            let records = store.session_records(&session);
+            if records.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No requests recorded for session '{}'",
+                    session
+                ));
+            }
+            let catalog = ModelCatalog::load_default()?;
+            let policies = if policy.is_empty() {
+                UsageSimulation::default_policies(&catalog)
+            } else {
+                policy
+                    .iter()
+                    .map(|spec| RoutingPolicy::from_spec(spec))
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let simulation =
+                UsageSimulation::simulate(&session, &records, &catalog, &policies)?;
+            print!("{}", simulation.render(format)?);
+        }
+    }
+    Ok(())
+}
+
+fn run_bench(
+    check: bool,
+    save: bool,
+    max_regression: f64,
+    samples: usize,
+    baseline_dir: Option<PathBuf>,
+    format: &str,
+) -> Result<()> {
+    let format = ReportFormat::from_name(format)?;
+    let store = match baseline_dir {
+        Some(dir) => BaselineStore::new(dir),
+        None => BaselineStore::open_default()?,
+    };
+    let machine = MachineFingerprint::current();
+    let baseline = store.load(&machine)?;
+
+    let scratch = tempfile::tempdir()?;
+    let results = builtin_benchmarks(scratch.path())
+        .iter_mut()
+        .map(|benchmark| benchmark.measure(samples))
+        .collect::<Result<Vec<_>>>()?;
+
+    let comparison = BenchComparison::new(
+        machine.clone(),
+        baseline.as_ref(),
+        &results,
+        max_regression,
+    );
+    print!("{}", comparison.render(format)?);
+
+    // A run that fails the check never replaces the baseline it failed
+    if check {
+        comparison.check()?;
+    }
+
+    if save {
+        let path = store.save(&BenchBaseline::new(machine, &results))?;
+        eprintln!("Saved baseline to {}", path.display());
+    } else if baseline.is_none() {
+        eprintln!("No baseline for this machine yet, record one with --save");
+    }
+    Ok(())
+}
+
+fn run_mcp(command: McpCommand) -> Result<()> {
+    match command {
+        McpCommand::New { name, path } => {
+            let scaffold = McpScaffold::new(&name)?;
+            let dir = path.unwrap_or_else(|| PathBuf::from(&name));
+            for path in scaffold.write(&dir)? {
+                println!("Created {}", path.display());
+            }
+            println!(
+                "Run `cargo test` in {} to check the server, then register it \
+                 with the snippet in {}",
+                dir.display(),
+                MCP_REGISTRATION_FILE
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_config(command: ConfigCommand) -> Result<()> {
+    let config_dir = Directory::config_directory()
+        .ok_or_else(|| anyhow::anyhow!("Config directory is unavailable"))?;
+
+    match command {
+        ConfigCommand::Export {
+            output,
+            encrypt,
+            include_credentials,
+            only,
+        } => {
+            let categories = parse_categories(&only, include_credentials)?;
+            if include_credentials && !encrypt {
+                return Err(anyhow::anyhow!(
+                    "Exporting credentials requires --encrypt"
+                ));
+            }
+
+            let bundle = SettingsBundle::collect(
+                &config_dir,
+                CredentialStore::global(),
+                &categories,
+            )?;
+            let passphrase = if encrypt {
+                let passphrase = rpassword::prompt_password("Passphrase: ")?;
+                if passphrase != rpassword::prompt_password("Repeat passphrase: ")? {
+                    return Err(anyhow::anyhow!("Passphrases do not match"));
+                }
+                Some(passphrase)
+            } else {
+                None
+            };
+
+            std::fs::write(&output, bundle.to_bytes(passphrase.as_deref())?)?;
+            println!(
+                "Exported {} files to {}",
+                bundle.entries.len(),
+                output.display()
+            );
+        }
+        ConfigCommand::Import {
+            input,
+            include_credentials,
+            only,
+            overwrite,
+            skip_existing,
+        } => {
+            let bytes = std::fs::read(&input)?;
+            let passphrase = if SettingsBundle::is_encrypted(&bytes) {
+                Some(rpassword::prompt_password("Passphrase: ")?)
+            } else {
+                None
+            };
+            let bundle = SettingsBundle::from_bytes(&bytes, passphrase.as_deref())?;
+
+            let categories = parse_categories(&only, include_credentials)?;
+            let summary = bundle.import(
+                &config_dir,
+                CredentialStore::global(),
+                &categories,
+                |entry| {
+                    if overwrite {
+                        Ok(ConflictResolution::Overwrite)
+                    } else if skip_existing {
+                        Ok(ConflictResolution::Skip)
+                    } else {
+                        prompt_conflict(entry)
+                    }
+                },
+            )?;
+            if !include_credentials && bundle.has_credentials() {
+                println!(
+                    "Skipped credentials, import them with --include-credentials"
+                );
+            }
+
+            for path in &summary.written {
+                println!("Imported {}", path.display());
+            }
+            for path in &summary.skipped {
+                println!("Skipped {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse the selected categories, which only include credentials when
+/// `include_credentials` is set
+fn parse_categories(
+    names: &[String],
+    include_credentials: bool,
+) -> Result<Vec<BundleCategory>> {
+    let mut categories = if names.is_empty() {
+        BundleCategory::ALL
+            .into_iter()
+            .filter(|category| *category != BundleCategory::Credentials)
+            .collect()
+    } else {
+        names
+            .iter()
+            .map(|name| BundleCategory::from_name(name.trim()))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let listed = categories.contains(&BundleCategory::Credentials);
+    if listed && !include_credentials {
+        return Err(anyhow::anyhow!(
+            "Credentials are only included with --include-credentials"
+        ));
+    }
+    if include_credentials && !listed {
+        categories.push(BundleCategory::Credentials);
+    }
+    Ok(categories)
+}
+
+fn prompt_conflict(entry: &BundleEntry) -> Result<ConflictResolution> {
+    let stdin = std::io::stdin();
+    loop {
+        if entry.category == BundleCategory::Credentials {
+            print!(
+                "Some credentials are already stored with other values. \
+                 [o]verwrite, [s]kip or keep [b]oth? "
+            );
+        } else {
+            print!(
+                "{} already exists. [o]verwrite, [s]kip or keep [b]oth? ",
+                entry.path.display()
+            );
+        }
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer)? == 0 {
+            return Ok(ConflictResolution::Skip);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "o" | "overwrite" => return Ok(ConflictResolution::Overwrite),
+            "s" | "skip" => return Ok(ConflictResolution::Skip),
+            "b" | "both" => return Ok(ConflictResolution::KeepBoth),
+            _ => {}
+        }
+    }
+}
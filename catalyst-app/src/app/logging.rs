@@ -3,7 +3,7 @@
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{filter::Targets, reload::Handle};
 
-use crate::tracing::*;
+use crate::{config::log::LogConfig, tracing::*};
 
 #[inline(always)]
 pub(super) fn logging() -> (Handle<Targets>, Option<WorkerGuard>) {
@@ -25,11 +25,12 @@ pub(super) fn logging() -> (Handle<Targets>, Option<WorkerGuard>) {
         None => (None, None),
     };
 
-    let log_file_filter_targets = filter::Targets::new()
-        .with_target("lapce_app", LevelFilter::DEBUG)
-        .with_target("catalyst_proxy", LevelFilter::DEBUG)
-        .with_target("catalyst_core", LevelFilter::DEBUG)
-        .with_default(LevelFilter::from_level(TraceLevel::INFO));
+    // The levels of the settings are applied once they are loaded
+    let log_file_filter_targets =
+        LogConfig::default().targets().unwrap_or_else(|_| {
+            filter::Targets::new()
+                .with_default(LevelFilter::from_level(TraceLevel::INFO))
+        });
     let (log_file_filter, reload_handle) =
         reload::Subscriber::new(log_file_filter_targets);
 
@@ -2,6 +2,11 @@
 
 use catalyst_app::app;
 
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static ALLOCATOR: catalyst_app::plugin_api::TrackingAllocator =
+    catalyst_app::plugin_api::TrackingAllocator::new();
+
 pub fn main() {
     app::launch();
 }
@@ -29,6 +29,7 @@
     editor_tab::EditorTabChild,
     id::EditorTabId,
     main_split::{SplitDirection, SplitMoveDirection, TabCloseKind},
+    plugin_api::PresentedError,
     workspace::LapceWorkspace,
 };
 
@@ -249,6 +250,14 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Open Settings Directory")]
     OpenSettingsDirectory,
 
+    #[strum(serialize = "open_credentials_file")]
+    #[strum(message = "Open Credentials File")]
+    OpenCredentialsFile,
+
+    #[strum(serialize = "update_credentials")]
+    #[strum(message = "Update Credentials")]
+    UpdateCredentials,
+
     #[strum(serialize = "open_theme_color_settings")]
     #[strum(message = "Open Theme Color Settings")]
     OpenThemeColorSettings,
@@ -325,6 +334,14 @@ pub enum LapceWorkbenchCommand {
     #[strum(message = "Previous Terminal Tab")]
     PreviousTerminalTab,
 
+    #[strum(serialize = "share_terminal_selection")]
+    #[strum(message = "Share Terminal Selection with Agent")]
+    ShareTerminalSelection,
+
+    #[strum(serialize = "toggle_focus_mode")]
+    #[strum(message = "Toggle Focus Mode")]
+    ToggleFocusMode,
+
     #[strum(serialize = "next_window_tab")]
     #[strum(message = "Go To Next Window Tab")]
     NextWindowTab,
@@ -757,6 +774,15 @@ pub enum InternalCommand {
         msg: String,
         buttons: Vec<AlertButton>,
     },
+    /// Show an error in a modal dialog, for failures of something the user
+    /// just did
+    ShowError {
+        error: PresentedError,
+    },
+    /// Show an error as a notification, for failures in the background
+    NotifyError {
+        error: PresentedError,
+    },
     HideAlert,
     SaveScratchDoc {
         doc: Rc<Doc>,
@@ -6,7 +6,10 @@
 
 use ::core::slice;
 use catalyst_core::directory::Directory;
-use catalyst_proxy::plugin::wasi::find_all_volts;
+use catalyst_proxy::{
+    http::{HttpSettings, set_http_settings},
+    plugin::wasi::find_all_volts,
+};
 use catalyst_rpc::plugin::VoltID;
 use floem::{peniko::Color, prelude::palette::css};
 use itertools::Itertools;
@@ -24,6 +27,7 @@
     editor::{EditorConfig, SCALE_OR_SIZE_LIMIT, WrapStyle},
     icon::LapceIcons,
     icon_theme::IconThemeConfig,
+    log::LogConfig,
     svg::SvgStore,
     terminal::TerminalConfig,
     ui::UIConfig,
@@ -36,6 +40,7 @@
 pub mod editor;
 pub mod icon;
 pub mod icon_theme;
+pub mod log;
 pub mod svg;
 pub mod terminal;
 pub mod ui;
@@ -104,6 +109,8 @@ pub struct LapceConfig {
     pub color_theme: ColorThemeConfig,
     #[serde(default)]
     pub icon_theme: IconThemeConfig,
+    #[serde(default)]
+    pub log: LogConfig,
     #[serde(flatten)]
     pub plugins: HashMap<String, HashMap<String, serde_json::Value>>,
     #[serde(skip)]
@@ -173,10 +180,27 @@ pub fn load(
         ];
 
         lapce_config.terminal.get_indexed_colors();
+        lapce_config.apply_http_settings();
 
         lapce_config
     }
 
+    /// Apply the `[http]` section to the clients used for outbound requests
+    fn apply_http_settings(&self) {
+        let settings = self
+            .plugins
+            .get("http")
+            .map(|http| {
+                serde_json::to_value(http)
+                    .and_then(serde_json::from_value::<HttpSettings>)
+            })
+            .transpose();
+        match settings {
+            Ok(settings) => set_http_settings(&settings.unwrap_or_default()),
+            Err(error) => error!("Failed to read the [http] settings: {error}"),
+        }
+    }
+
     fn merge_config(
         workspace: &LapceWorkspace,
         color_theme_config: Option<config::Config>,
@@ -0,0 +1,65 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use structdesc::FieldNames;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+
+#[derive(FieldNames, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(default)]
+pub struct LogConfig {
+    #[field_names(
+        desc = "Set the level of the log file (off, error, warn, info, debug, trace)"
+    )]
+    pub level: String,
+    /// Levels of single modules, e.g. `catalyst_proxy::plugin = "trace"`
+    #[field_names(skip)]
+    pub modules: BTreeMap<String, String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            modules: ["catalyst_app", "catalyst_proxy", "catalyst_core"]
+                .into_iter()
+                .map(|module| (module.to_string(), "debug".to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Set the level of a module, or the default level for `None`
+    pub fn set_level(&mut self, module: Option<&str>, level: &str) -> Result<()> {
+        parse_level(level)?;
+        match module {
+            Some(module) => {
+                self.modules.insert(module.to_string(), level.to_string());
+            }
+            None => self.level = level.to_string(),
+        }
+        Ok(())
+    }
+
+    /// Build the filter of the log file
+    pub fn targets(&self) -> Result<Targets> {
+        let mut targets = Targets::new().with_default(parse_level(&self.level)?);
+        for (module, level) in &self.modules {
+            targets = targets.with_target(module.clone(), parse_level(level)?);
+        }
+        Ok(targets)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown log level '{}', expected one of off, error, warn, info, \
+             debug, trace",
+            level
+        )
+    })
+}
@@ -84,6 +84,7 @@
         document_symbol::{SymbolData, SymbolInformationItemData},
         kind::PanelKind,
     },
+    plugin_api::{AllocTag, FileCoverage, alloc_scope},
     window_tab::{CommonData, Focus},
     workspace::LapceWorkspace,
 };
@@ -196,6 +197,8 @@ pub struct Doc {
     /// Stores information about different versions of the document from source control.
     histories: RwSignal<im::HashMap<String, DocumentHistory>>,
     pub head_changes: RwSignal<im::Vector<DiffLines>>,
+    /// Line coverage from the latest coverage report, shown in the gutter
+    pub coverage: RwSignal<Option<Arc<FileCoverage>>>,
 
     line_styles: Rc<RefCell<LineStyles>>,
     pub parser: Rc<RefCell<BracketParser>>,
@@ -251,6 +254,7 @@ pub fn new(
             loaded: cx.create_rw_signal(false),
             histories: cx.create_rw_signal(im::HashMap::new()),
             head_changes: cx.create_rw_signal(im::Vector::new()),
+            coverage: cx.create_rw_signal(None),
             sticky_headers: Rc::new(RefCell::new(HashMap::new())),
             code_actions: cx.create_rw_signal(im::HashMap::new()),
             find_result: FindResult::new(cx),
@@ -301,6 +305,7 @@ pub fn new_content(
             content: cx.create_rw_signal(content),
             histories: cx.create_rw_signal(im::HashMap::new()),
             head_changes: cx.create_rw_signal(im::Vector::new()),
+            coverage: cx.create_rw_signal(None),
             sticky_headers: Rc::new(RefCell::new(HashMap::new())),
             loaded: cx.create_rw_signal(true),
             find_result: FindResult::new(cx),
@@ -354,6 +359,7 @@ pub fn new_history(
             loaded: cx.create_rw_signal(true),
             histories: cx.create_rw_signal(im::HashMap::new()),
             head_changes: cx.create_rw_signal(im::Vector::new()),
+            coverage: cx.create_rw_signal(None),
             code_actions: cx.create_rw_signal(im::HashMap::new()),
             find_result: FindResult::new(cx),
             preedit: PreeditData::new(cx),
@@ -606,6 +612,11 @@ pub fn apply_deltas(&self, deltas: &[(Rope, RopeDelta, InvalLines)]) {
                 self.update_find_result(delta);
                 if let DocContent::File { path, .. } = self.content.get_untracked() {
                     self.update_breakpoints(delta, &path, &inval.old_text);
+                    let (iv, _) = delta.summary();
+                    self.common.attention.record_edit(
+                        &path,
+                        inval.old_text.line_of_offset(iv.start()),
+                    );
                     self.common.proxy.update(
                         path,
                         delta.clone(),
@@ -795,6 +806,7 @@ fn update_inlay_hints(&self, delta: &RopeDelta) {
         });
         let mut syntax = self.syntax.get_untracked();
         rayon::spawn(move || {
+            let _scope = alloc_scope(AllocTag::Highlight);
             syntax.parse(rev, text, edits.as_deref());
             send(syntax);
         });
@@ -72,6 +72,41 @@ fn paint_head_changes(
         }
     }
 
+    fn paint_coverage(
+        &self,
+        cx: &mut PaintCx,
+        viewport: Rect,
+        is_normal: bool,
+        config: &LapceConfig,
+    ) {
+        if !is_normal {
+            return;
+        }
+        let Some(coverage) = self.editor.doc().coverage.get_untracked() else {
+            return;
+        };
+
+        let line_height = config.editor.line_height() as f64;
+        let gutter_padding_right = self.gutter_padding_right.get_untracked() as f64;
+        let covered = config.color(LapceColor::COVERAGE_COVERED);
+        let uncovered = config.color(LapceColor::COVERAGE_UNCOVERED);
+        self.editor.screen_lines().with_untracked(|screen_lines| {
+            for (line, y) in screen_lines.iter_lines_y() {
+                let Some(hits) = coverage.hits(line as u32 + 1) else {
+                    continue;
+                };
+                cx.fill(
+                    &Size::new(2.0, line_height).to_rect().with_origin(Point::new(
+                        self.width + 1.0 - gutter_padding_right,
+                        y - viewport.y0,
+                    )),
+                    if hits > 0 { covered } else { uncovered },
+                    0.0,
+                )
+            }
+        });
+    }
+
     fn paint_sticky_headers(
         &self,
         cx: &mut PaintCx,
@@ -203,6 +238,7 @@ fn paint(&mut self, cx: &mut floem::context::PaintCx) {
         });
 
         self.paint_head_changes(cx, &self.editor, viewport, kind_is_normal, &config);
+        self.paint_coverage(cx, viewport, kind_is_normal, &config);
         self.paint_sticky_headers(cx, kind_is_normal, &config);
     }
 
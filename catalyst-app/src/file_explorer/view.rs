@@ -136,10 +136,10 @@ fn file_node_text_color(
         if node.is_dir {
             file_diffs
                 .keys()
-                .find(|p| p.as_path().starts_with(path))
+                .find(|p| p.starts_with(path))
                 .map(|_| FileDiffKind::Modified)
         } else {
-            file_diffs.get(path).map(|(diff, _)| diff.kind())
+            file_diffs.get(path.as_path()).map(|(diff, _)| diff.kind())
         }
     });
 
@@ -0,0 +1,27 @@
+use floem::{View, ViewId};
+
+/// Create an empty view that runs `on_first_paint` the first time it is
+/// painted, to start work that shouldn't delay the first frame
+pub fn first_paint(on_first_paint: impl FnOnce() + 'static) -> FirstPaint {
+    FirstPaint {
+        id: ViewId::new(),
+        on_first_paint: Some(Box::new(on_first_paint)),
+    }
+}
+
+pub struct FirstPaint {
+    id: ViewId,
+    on_first_paint: Option<Box<dyn FnOnce()>>,
+}
+
+impl View for FirstPaint {
+    fn id(&self) -> ViewId {
+        self.id
+    }
+
+    fn paint(&mut self, _cx: &mut floem::context::PaintCx) {
+        if let Some(on_first_paint) = self.on_first_paint.take() {
+            on_first_paint();
+        }
+    }
+}
@@ -13,6 +13,7 @@
 pub mod editor_tab;
 pub mod file_explorer;
 pub mod find;
+pub mod first_paint;
 pub mod focus_text;
 pub mod global_search;
 pub mod history;
@@ -54,6 +54,7 @@
     },
     keypress::{EventRef, KeyPressData, KeyPressHandle},
     panel::implementation_view::ReferencesRoot,
+    plugin_api::{CoverageStore, JobScheduler},
     window_tab::{CommonData, Focus, WindowTabData},
 };
 
@@ -410,6 +411,8 @@ pub struct MainSplitData {
     pub current_location: RwSignal<usize>,
     pub width: RwSignal<f64>,
     pub code_lens: RwSignal<CodeLensData>,
+    /// Latest coverage report, applied to documents as they open
+    pub coverage: CoverageStore,
     pub common: Rc<CommonData>,
 }
 
@@ -470,6 +473,56 @@ pub fn new(cx: Scope, common: Rc<CommonData>) -> Self {
             Some(editor)
         });
 
+        {
+            let attention = common.attention.clone();
+            cx.create_effect(move |_| {
+                let Some(editor) = active_editor.get() else {
+                    return;
+                };
+                let doc = editor.doc_signal().get();
+                let Some(path) = doc.content.with(|content| content.path().cloned())
+                else {
+                    return;
+                };
+                let offset = editor.cursor().with(|cursor| cursor.offset());
+                let line = doc
+                    .buffer
+                    .with_untracked(|buffer| buffer.line_of_offset(offset));
+                attention.record_cursor(&path, line);
+            });
+        }
+
+        if let Some(sparse_index) = common.sparse_index.clone() {
+            cx.create_effect(move |last_path: Option<Option<PathBuf>>| {
+                let path = active_editor.get().and_then(|editor| {
+                    editor
+                        .doc_signal()
+                        .get()
+                        .content
+                        .with(|content| content.path().cloned())
+                });
+                let Some(opened) = path.clone() else {
+                    return path;
+                };
+                if last_path.flatten().as_ref() == Some(&opened)
+                    || sparse_index.is_indexed(&opened)
+                {
+                    return path;
+                }
+                let sparse_index = sparse_index.clone();
+                JobScheduler::global().spawn_blocking(move || {
+                    if let Err(err) = sparse_index.expand(&opened) {
+                        event!(
+                            Level::DEBUG,
+                            "failed to expand the sparse index: {err:#}"
+                        );
+                    }
+                    Ok(())
+                });
+                path
+            });
+        }
+
         {
             let buffer = find_editor.doc().buffer;
             let find = common.find.clone();
@@ -497,12 +550,29 @@ pub fn new(cx: Scope, common: Rc<CommonData>) -> Self {
             current_location,
             width: cx.create_rw_signal(0.0),
             code_lens: cx.create_rw_signal(CodeLensData::new(common.clone())),
+            coverage: CoverageStore::new(),
             common,
             references,
             implementations,
         }
     }
 
+    /// Reload the coverage report of the workspace and show it in the
+    /// gutters of the open documents
+    pub fn reload_coverage(&self, workspace_root: &Path) {
+        self.coverage.reload(workspace_root);
+        let report = self.coverage.report();
+        self.docs.with_untracked(|docs| {
+            for (path, doc) in docs.iter() {
+                doc.coverage.set(
+                    report
+                        .as_ref()
+                        .and_then(|report| report.file(path).cloned()),
+                );
+            }
+        });
+    }
+
     pub fn key_down<'a>(
         &self,
         event: impl Into<EventRef<'a>>,
@@ -635,6 +705,9 @@ pub fn get_doc(
                 self.common.clone(),
             );
             let doc = Rc::new(doc);
+            if let Some(report) = self.coverage.report() {
+                doc.coverage.set(report.file(&path).cloned());
+            }
             self.docs.update(|docs| {
                 docs.insert(path.clone(), doc.clone());
             });
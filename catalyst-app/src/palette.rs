@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     path::PathBuf,
     rc::Rc,
     sync::{
@@ -52,6 +52,7 @@
     keypress::{condition::Condition, KeyPressData, KeyPressFocus},
     lsp::path_from_url,
     main_split::MainSplitData,
+    plugin_api::{AI_PROVIDERS, CredentialStore, PresentedError, env_key},
     source_control::SourceControlData,
     window_tab::{CommonData, Focus},
     workspace::{LapceWorkspace, LapceWorkspaceType, SshHost},
@@ -108,6 +109,8 @@ pub struct PaletteData {
     pub source_control: SourceControlData,
     pub common: Rc<CommonData>,
     left_diff_path: RwSignal<Option<PathBuf>>,
+    /// Credential whose new value is being typed
+    credential_key: RwSignal<Option<String>>,
 }
 
 impl std::fmt::Debug for PaletteData {
@@ -215,6 +218,7 @@ pub fn new(
 
         let clicked_index = cx.create_rw_signal(Option::<usize>::None);
         let left_diff_path = cx.create_rw_signal(None);
+        let credential_key = cx.create_rw_signal(None);
 
         let palette = Self {
             run_id_counter,
@@ -239,6 +243,7 @@ pub fn new(
             source_control,
             common,
             left_diff_path,
+            credential_key,
         };
 
         {
@@ -355,6 +360,13 @@ pub fn placeholder_text(&self) -> &'static str {
                     "Seleft left file"
                 }
             }
+            PaletteKind::Credential => {
+                if self.credential_key.with(Option::is_some) {
+                    "Type the new value and press Enter"
+                } else {
+                    "Select or type the name of the credential to update"
+                }
+            }
             _ => "",
         }
     }
@@ -417,6 +429,7 @@ fn run_inner(&self, kind: PaletteKind) {
                 self.get_scm_references();
             }
             PaletteKind::TerminalProfile => self.get_terminal_profiles(),
+            PaletteKind::Credential => self.get_credentials(),
         }
     }
 
@@ -1111,6 +1124,34 @@ fn get_terminal_profiles(&self) {
         self.items.set(items);
     }
 
+    /// List the stored credentials and the API keys of the AI providers, or
+    /// nothing once one was picked, so its new value isn't matched against
+    /// them
+    fn get_credentials(&self) {
+        if self.credential_key.with_untracked(Option::is_some) {
+            self.items.set(im::Vector::new());
+            return;
+        }
+        let mut keys: BTreeSet<String> =
+            CredentialStore::global().entries().into_keys().collect();
+        keys.extend(
+            AI_PROVIDERS
+                .iter()
+                .filter_map(|provider| provider.api_key_env)
+                .map(env_key),
+        );
+        let items = keys
+            .into_iter()
+            .map(|key| PaletteItem {
+                filter_text: key.clone(),
+                content: PaletteItemContent::Credential { key },
+                score: 0,
+                indices: Vec::new(),
+            })
+            .collect();
+        self.items.set(items);
+    }
+
     fn preselect_matching(&self, items: &im::Vector<PaletteItem>, matching: &str) {
         let Some((idx, _)) = items
             .iter()
@@ -1330,6 +1371,27 @@ fn select(&self) {
                     .send(InternalCommand::NewTerminal {
                         profile: Some(profile.to_owned()),
                     }),
+                PaletteItemContent::Credential { key } => {
+                    self.credential_key.set(Some(key.clone()));
+                    self.run(PaletteKind::Credential);
+                }
+            }
+        } else if self.kind.get_untracked() == PaletteKind::Credential {
+            let input = self.input.with_untracked(|input| input.input.clone());
+            if let Some(key) = self.credential_key.try_update(Option::take).flatten()
+            {
+                // Stored like every other secret, in the system keychain
+                // where one is available
+                if let Err(err) = CredentialStore::global().set(&key, &input) {
+                    self.common.internal_command.send(InternalCommand::ShowError {
+                        error: PresentedError::new(
+                            &err.context(format!("Failed to update {key}")),
+                        ),
+                    });
+                }
+            } else if !input.is_empty() {
+                self.credential_key.set(Some(input));
+                self.run(PaletteKind::Credential);
             }
         } else if self.kind.get_untracked() == PaletteKind::SshHost {
             let input = self.input.with_untracked(|input| input.input.clone());
@@ -1462,6 +1524,7 @@ fn preview(&self) {
                     }),
                 PaletteItemContent::SCMReference { .. } => {}
                 PaletteItemContent::TerminalProfile { .. } => {}
+                PaletteItemContent::Credential { .. } => {}
             }
         }
     }
@@ -1478,6 +1541,7 @@ fn cancel(&self) {
         }
 
         self.left_diff_path.set(None);
+        self.credential_key.set(None);
         self.close();
     }
 
@@ -84,4 +84,7 @@ pub enum PaletteItemContent {
         name: String,
         profile: catalyst_rpc::terminal::TerminalProfile,
     },
+    Credential {
+        key: String,
+    },
 }
@@ -24,6 +24,7 @@ pub enum PaletteKind {
     TerminalProfile,
     DiffFiles,
     HelpAndFile,
+    Credential,
 }
 
 impl PaletteKind {
@@ -48,7 +49,8 @@ pub fn symbol(&self) -> &'static str {
             | PaletteKind::LineEnding
             | PaletteKind::SCMReferences
             | PaletteKind::HelpAndFile
-            | PaletteKind::DiffFiles => "",
+            | PaletteKind::DiffFiles
+            | PaletteKind::Credential => "",
             #[cfg(windows)]
             PaletteKind::WslHost => "",
         }
@@ -103,6 +105,9 @@ pub fn command(self) -> Option<LapceWorkbenchCommand> {
             }
             PaletteKind::TerminalProfile => None, // InternalCommand::NewTerminal
             PaletteKind::DiffFiles => Some(LapceWorkbenchCommand::DiffFiles),
+            PaletteKind::Credential => {
+                Some(LapceWorkbenchCommand::UpdateCredentials)
+            }
         }
     }
 
@@ -130,7 +135,8 @@ pub fn get_input<'a>(&self, input: &'a str) -> &'a str {
             | PaletteKind::Language
             | PaletteKind::LineEnding
             | PaletteKind::SCMReferences | PaletteKind::HelpAndFile
-            | PaletteKind::DiffFiles => input,
+            | PaletteKind::DiffFiles
+            | PaletteKind::Credential => input,
             PaletteKind::PaletteHelp
             | PaletteKind::Command
             | PaletteKind::Workspace
@@ -1,9 +1,6 @@
 use std::{ops::Range, rc::Rc};
 
-use catalyst_rpc::{
-    core::CoreRpcHandler,
-    plugin::{VoltID, VoltInfo},
-};
+use catalyst_rpc::plugin::{VoltID, VoltInfo};
 use floem::{
     IntoView, View,
     event::EventListener,
@@ -71,7 +68,6 @@ pub fn plugin_panel(
 ) -> impl View {
     let config = window_tab_data.common.config;
     let plugin = window_tab_data.plugin.clone();
-    let core_rpc = window_tab_data.proxy.core_rpc.clone();
 
     PanelBuilder::new(config, position)
         .add(
@@ -81,7 +77,7 @@ pub fn plugin_panel(
         )
         .add(
             "Available",
-            available_view(plugin.clone(), core_rpc),
+            available_view(plugin.clone()),
             window_tab_data.panel.section_open(PanelSection::Available),
         )
         .build()
@@ -211,7 +207,7 @@ fn installed_view(plugin: PluginData) -> impl View {
     })
 }
 
-fn available_view(plugin: PluginData, core_rpc: CoreRpcHandler) -> impl View {
+fn available_view(plugin: PluginData) -> impl View {
     let ui_line_height = plugin.common.ui_line_height;
     let volts = plugin.available.volts;
     let installed = plugin.installed;
@@ -388,7 +384,7 @@ fn available_view(plugin: PluginData, core_rpc: CoreRpcHandler) -> impl View {
             })
             .on_scroll(move |rect| {
                 if rect.y1 + 30.0 > content_rect.get_untracked().y1 {
-                    plugin.load_more_available(core_rpc.clone());
+                    plugin.load_more_available();
                 }
             })
             .style(|s| s.absolute().size_pct(100.0, 100.0))
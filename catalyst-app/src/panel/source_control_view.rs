@@ -1,4 +1,4 @@
-use std::{path::PathBuf, rc::Rc};
+use std::rc::Rc;
 
 use catalyst_core::buffer::rope_text::RopeText;
 use catalyst_rpc::source_control::FileDiff;
@@ -26,6 +26,7 @@
     command::{CommandKind, InternalCommand, LapceCommand, LapceWorkbenchCommand},
     config::{color::LapceColor, icon::LapceIcons},
     editor::view::editor_view,
+    plugin_api::SharedPath,
     settings::checkbox,
     source_control::SourceControlData,
     window_tab::{Focus, WindowTabData},
@@ -206,7 +207,7 @@ fn file_diffs_view(source_control: SourceControlData) -> impl View {
     let lapce_command = source_control.common.lapce_command;
     let internal_command = source_control.common.internal_command;
 
-    let view_fn = move |(path, (diff, checked)): (PathBuf, (FileDiff, bool))| {
+    let view_fn = move |(path, (diff, checked)): (SharedPath, (FileDiff, bool))| {
         let diff_for_style = diff.clone();
         let full_path = path.clone();
         let diff_for_menu = diff.clone();
@@ -217,7 +218,7 @@ fn file_diffs_view(source_control: SourceControlData) -> impl View {
                 .unwrap_or(&full_path)
                 .to_path_buf()
         } else {
-            path
+            path.to_path_buf()
         };
         let file_name = path
             .file_name()
@@ -309,7 +310,7 @@ fn file_diffs_view(source_control: SourceControlData) -> impl View {
         ))
         .on_click_stop(move |_| {
             internal_command.send(InternalCommand::OpenFileChanges {
-                path: path_for_click.clone(),
+                path: path_for_click.to_path_buf(),
             });
         })
         .on_event_cont(EventListener::PointerDown, move |event| {
@@ -349,9 +350,7 @@ fn file_diffs_view(source_control: SourceControlData) -> impl View {
         scroll({
             dyn_stack(
                 move || file_diffs.get(),
-                |(path, (diff, checked))| {
-                    (path.to_path_buf(), diff.clone(), *checked)
-                },
+                |(path, (diff, checked))| (path.clone(), diff.clone(), *checked),
                 view_fn,
             )
             .style(|s| s.line_height(1.6).flex_col().width_pct(100.0))
@@ -7,10 +7,7 @@
 use anyhow::Result;
 use catalyst_core::{command::EditCommand, directory::Directory, mode::Mode};
 use catalyst_proxy::plugin::{download_volt, volt_icon, wasi::find_all_volts};
-use catalyst_rpc::{
-    core::{CoreNotification, CoreRpcHandler},
-    plugin::{VoltID, VoltInfo, VoltMetadata},
-};
+use catalyst_rpc::plugin::{VoltID, VoltInfo, VoltMetadata};
 use floem::{
     IntoView, View,
     action::show_context_menu,
@@ -29,12 +26,11 @@
     },
 };
 use indexmap::IndexMap;
-use lsp_types::MessageType;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::{
-    command::{CommandExecuted, CommandKind},
+    command::{CommandExecuted, CommandKind, InternalCommand},
     config::{LapceConfig, color::LapceColor},
     db::LapceDb,
     editor::EditorData,
@@ -42,6 +38,7 @@
     main_split::Editors,
     markdown::{MarkdownContent, parse_markdown},
     panel::plugin_view::VOLT_DEFAULT_PNG,
+    plugin_api::{CatalystError, PresentedError},
     web_link::web_link,
     window_tab::CommonData,
 };
@@ -161,7 +158,6 @@ pub fn new(
         workspace_disabled: HashSet<VoltID>,
         editors: Editors,
         common: Rc<CommonData>,
-        core_rpc: CoreRpcHandler,
     ) -> Self {
         let installed = cx.create_rw_signal(IndexMap::new());
         let available = AvailableVoltList {
@@ -183,7 +179,7 @@ pub fn new(
             common,
         };
 
-        plugin.load_available_volts("", 0, core_rpc.clone());
+        plugin.load_available_volts("", 0);
 
         {
             let plugin = plugin.clone();
@@ -233,7 +229,7 @@ pub fn new(
                 plugin.available.query_id.update(|id| *id += 1);
                 plugin.available.loading.set(false);
                 plugin.available.volts.update(|v| v.clear());
-                plugin.load_available_volts(&query, 0, core_rpc.clone());
+                plugin.load_available_volts(&query, 0);
                 query
             });
         }
@@ -334,12 +330,7 @@ pub fn volt_removed(&self, volt: &VoltInfo) {
         }
     }
 
-    fn load_available_volts(
-        &self,
-        query: &str,
-        offset: usize,
-        core_rpc: CoreRpcHandler,
-    ) {
+    fn load_available_volts(&self, query: &str, offset: usize) {
         if self.available.loading.get_untracked() {
             return;
         }
@@ -352,6 +343,7 @@ fn load_available_volts(
         let query_id = self.available.query_id;
         let current_query_id = self.available.query_id.get_untracked();
         let all = self.all;
+        let internal_command = self.common.internal_command;
         let send =
             create_ext_action(self.common.scope, move |new: Result<VoltsInfo>| {
                 loading.set(false);
@@ -392,13 +384,10 @@ fn load_available_volts(
                         volts_total.set(new.total);
                     }
                     Err(err) => {
-                        tracing::error!("{:?}", err);
-                        core_rpc.notification(CoreNotification::ShowMessage {
-                            title: "Request Available Plugins".to_string(),
-                            message: lsp_types::ShowMessageParams {
-                                typ: MessageType::ERROR,
-                                message: err.to_string(),
-                            },
+                        internal_command.send(InternalCommand::NotifyError {
+                            error: PresentedError::new(
+                                &err.context("Failed to request available plugins"),
+                            ),
                         });
                     }
                 }
@@ -471,7 +460,16 @@ fn query_volts(query: &str, offset: usize) -> Result<VoltsInfo> {
         let url = format!(
             "https://plugins.lapce.dev/api/v1/plugins?q={query}&offset={offset}"
         );
-        let plugins: VoltsInfo = catalyst_proxy::get_url(url, None)?.json()?;
+        let resp = catalyst_proxy::get_url(url, None)?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(CatalystError::from_status(
+                status.as_u16(),
+                format!("Plugin registry answered {status}"),
+            )
+            .into());
+        }
+        let plugins: VoltsInfo = resp.json()?;
         Ok(plugins)
     }
 
@@ -480,7 +478,7 @@ fn all_loaded(&self) -> bool {
             >= self.available.total.get_untracked()
     }
 
-    pub fn load_more_available(&self, core_rpc: CoreRpcHandler) {
+    pub fn load_more_available(&self) {
         if self.all_loaded() {
             return;
         }
@@ -492,7 +490,7 @@ pub fn load_more_available(&self, core_rpc: CoreRpcHandler) {
             .buffer
             .with_untracked(|buffer| buffer.to_string());
         let offset = self.available.volts.with_untracked(|v| v.len());
-        self.load_available_volts(&query, offset, core_rpc);
+        self.load_available_volts(&query, offset);
     }
 
     pub fn install_volt(&self, info: VoltInfo) {
@@ -0,0 +1,486 @@
+//! Agent Record and Replay
+//!
+//! This module makes agent runs reproducible without a model. A
+//! [`RecordingAssistant`] wraps a real assistant and keeps every request it
+//! was sent and what came back in an [`AgentRecording`], along with the
+//! [`Determinism`] seed the run used. A [`ReplayAssistant`] answers the same
+//! requests from the recording, with the process made deterministic with
+//! the recorded seed, so times, random numbers and job order come out as
+//! they did when recording. A request that differs from the recorded one
+//! fails, pointing at the first step where the agent loop went another way.
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    AiAssistantPlugin, AiAuthData, AiAuthResult, AiCapability, AiMessageRequest,
+    AiMessageResponse, AiPluginInfo, AiStreamChunk, AiStreamCompletion,
+    AiStreamEvent, AiStreamSender, AiUsageInfo, DEFAULT_AI_STREAM_CAPACITY,
+    Determinism, DeterminismGuard, ai_stream_channel, write_atomic,
+};
+
+/// Requests and responses of an agent run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRecording {
+    pub seed: u64,
+    /// Start of the frozen clock, in seconds since the Unix epoch
+    pub start_secs: u64,
+    /// Assistant the run was recorded against
+    pub plugin: AiPluginInfo,
+    pub exchanges: Vec<RecordedExchange>,
+}
+
+/// One request of a recorded run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    pub request: AiMessageRequest,
+    pub response: RecordedResponse,
+}
+
+/// What the assistant answered a recorded request with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedResponse {
+    Message {
+        response: AiMessageResponse,
+    },
+    Stream {
+        chunks: Vec<AiStreamChunk>,
+        completion: AiStreamCompletion,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl AgentRecording {
+    pub fn new(determinism: Determinism, plugin: AiPluginInfo) -> Self {
+        Self {
+            seed: determinism.seed,
+            start_secs: determinism
+                .start
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            plugin,
+            exchanges: Vec::new(),
+        }
+    }
+
+    /// Get the settings the run was recorded with
+    pub fn determinism(&self) -> Determinism {
+        Determinism::new(self.seed).starting_at(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(self.start_secs),
+        )
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).map_err(|err| {
+            anyhow::anyhow!("Failed to read recording {}: {}", path.display(), err)
+        })?;
+        serde_json::from_slice(&bytes).map_err(|err| {
+            anyhow::anyhow!("Invalid recording {}: {}", path.display(), err)
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Assistant recording the requests and responses of the assistant it wraps
+pub struct RecordingAssistant {
+    inner: Arc<dyn AiAssistantPlugin>,
+    recording: Mutex<AgentRecording>,
+}
+
+impl RecordingAssistant {
+    /// Start recording `inner`, with the process deterministic until the
+    /// returned guard is dropped
+    pub fn start(
+        inner: Arc<dyn AiAssistantPlugin>,
+        determinism: Determinism,
+    ) -> (Self, DeterminismGuard) {
+        let guard = determinism.enter();
+        let recording = AgentRecording::new(determinism, inner.plugin_info());
+        (
+            Self {
+                inner,
+                recording: Mutex::new(recording),
+            },
+            guard,
+        )
+    }
+
+    /// Get the run recorded so far
+    pub fn recording(&self) -> AgentRecording {
+        self.recording.lock().clone()
+    }
+
+    fn record(&self, request: AiMessageRequest, response: RecordedResponse) {
+        self.recording
+            .lock()
+            .exchanges
+            .push(RecordedExchange { request, response });
+    }
+}
+
+impl AiAssistantPlugin for RecordingAssistant {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn plugin_info(&self) -> AiPluginInfo {
+        self.inner.plugin_info()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.inner.is_authenticated()
+    }
+
+    fn send_message(&self, request: AiMessageRequest) -> Result<AiMessageResponse> {
+        let result = self.inner.send_message(request.clone());
+        let recorded = match &result {
+            Ok(response) => RecordedResponse::Message {
+                response: response.clone(),
+            },
+            Err(err) => RecordedResponse::Error {
+                message: format!("{err:#}"),
+            },
+        };
+        self.record(request, recorded);
+        result
+    }
+
+    fn stream_message(
+        &self,
+        request: AiMessageRequest,
+        sender: &AiStreamSender,
+    ) -> Result<AiStreamCompletion> {
+        // The wrapped assistant streams into a channel of our own so every
+        // chunk can be kept on its way through
+        let (inner_sender, stream) = ai_stream_channel(DEFAULT_AI_STREAM_CAPACITY);
+        let mut chunks = Vec::new();
+        let result = std::thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                let result =
+                    self.inner.stream_message(request.clone(), &inner_sender);
+                drop(inner_sender);
+                result
+            });
+            for event in stream {
+                let AiStreamEvent::Chunk(chunk) = event else {
+                    break;
+                };
+                chunks.push(chunk.clone());
+                if sender.send(chunk).is_err() {
+                    // The consumer went away; dropping the stream cancels
+                    // the wrapped assistant too
+                    break;
+                }
+            }
+            producer
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("The assistant panicked")))
+        });
+        let recorded = match &result {
+            Ok(completion) => RecordedResponse::Stream {
+                chunks,
+                completion: completion.clone(),
+            },
+            Err(err) => RecordedResponse::Error {
+                message: format!("{err:#}"),
+            },
+        };
+        self.record(request, recorded);
+        result
+    }
+
+    fn get_capabilities(&self) -> Vec<AiCapability> {
+        self.inner.get_capabilities()
+    }
+
+    fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+        Err(anyhow::anyhow!(
+            "Authenticate the recorded assistant before recording"
+        ))
+    }
+
+    fn get_usage_info(&self) -> Option<AiUsageInfo> {
+        self.inner.get_usage_info()
+    }
+}
+
+/// Assistant answering requests from a recording
+pub struct ReplayAssistant {
+    recording: AgentRecording,
+    next: Mutex<usize>,
+}
+
+impl ReplayAssistant {
+    /// Start replaying `recording`, with the process deterministic with the
+    /// recorded seed until the returned guard is dropped
+    pub fn start(recording: AgentRecording) -> (Self, DeterminismGuard) {
+        let guard = recording.determinism().enter();
+        (
+            Self {
+                recording,
+                next: Mutex::new(0),
+            },
+            guard,
+        )
+    }
+
+    /// Check that every recorded request was made
+    pub fn finish(&self) -> Result<()> {
+        let next = *self.next.lock();
+        let total = self.recording.exchanges.len();
+        if next < total {
+            return Err(anyhow::anyhow!(
+                "Only {} of {} recorded requests were made",
+                next,
+                total
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get the recorded answer to the next request, which must match the
+    /// recorded one
+    fn answer(&self, request: &AiMessageRequest) -> Result<&RecordedResponse> {
+        let mut next = self.next.lock();
+        let exchange = self.recording.exchanges.get(*next).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Request {} was not recorded; the run made {} requests",
+                *next + 1,
+                self.recording.exchanges.len()
+            )
+        })?;
+        let expected = serde_json::to_value(&exchange.request)?;
+        let actual = serde_json::to_value(request)?;
+        if actual != expected {
+            return Err(anyhow::anyhow!(
+                "Request {} differs from the recording\nrecorded: {}\nreplayed: {}",
+                *next + 1,
+                expected,
+                actual
+            ));
+        }
+        *next += 1;
+        Ok(&exchange.response)
+    }
+}
+
+impl AiAssistantPlugin for ReplayAssistant {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn plugin_info(&self) -> AiPluginInfo {
+        self.recording.plugin.clone()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        true
+    }
+
+    fn send_message(&self, request: AiMessageRequest) -> Result<AiMessageResponse> {
+        match self.answer(&request)? {
+            RecordedResponse::Message { response } => Ok(response.clone()),
+            RecordedResponse::Stream { .. } => Err(anyhow::anyhow!(
+                "The recorded request was streamed, not sent"
+            )),
+            RecordedResponse::Error { message } => {
+                Err(anyhow::anyhow!(message.clone()))
+            }
+        }
+    }
+
+    fn stream_message(
+        &self,
+        request: AiMessageRequest,
+        sender: &AiStreamSender,
+    ) -> Result<AiStreamCompletion> {
+        match self.answer(&request)? {
+            RecordedResponse::Stream { chunks, completion } => {
+                for chunk in chunks {
+                    sender.send(chunk.clone())?;
+                }
+                Ok(completion.clone())
+            }
+            RecordedResponse::Message { .. } => Err(anyhow::anyhow!(
+                "The recorded request was sent, not streamed"
+            )),
+            RecordedResponse::Error { message } => {
+                Err(anyhow::anyhow!(message.clone()))
+            }
+        }
+    }
+
+    fn get_capabilities(&self) -> Vec<AiCapability> {
+        Vec::new()
+    }
+
+    fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+        Err(anyhow::anyhow!(
+            "A replayed assistant needs no authentication"
+        ))
+    }
+
+    fn get_usage_info(&self) -> Option<AiUsageInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        AiMessage, MessageRole, current_time, random_u64, spawn_ai_stream,
+    };
+
+    /// Assistant whose answers depend on the clock and random numbers, so
+    /// a replay only matches when the run is deterministic
+    struct StampingAssistant;
+
+    impl AiAssistantPlugin for StampingAssistant {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn plugin_info(&self) -> AiPluginInfo {
+            AiPluginInfo {
+                name: "Stamping".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                provider: "test".to_string(),
+                supports_streaming: true,
+                supports_tools: false,
+                supports_vision: false,
+            }
+        }
+
+        fn is_authenticated(&self) -> bool {
+            true
+        }
+
+        fn send_message(
+            &self,
+            request: AiMessageRequest,
+        ) -> Result<AiMessageResponse> {
+            Ok(AiMessageResponse {
+                content: format!("{} answers", request.messages.len()),
+                tool_calls: None,
+                usage: None,
+                model: "stamping".to_string(),
+                finish_reason: Some("stop".to_string()),
+            })
+        }
+
+        fn stream_message(
+            &self,
+            request: AiMessageRequest,
+            sender: &AiStreamSender,
+        ) -> Result<AiStreamCompletion> {
+            sender.send_text(format!("{} ", request.messages.len()))?;
+            sender.send_text("streamed")?;
+            Ok(AiStreamCompletion {
+                model: "stamping".to_string(),
+                finish_reason: Some("stop".to_string()),
+                usage: None,
+            })
+        }
+
+        fn get_capabilities(&self) -> Vec<AiCapability> {
+            Vec::new()
+        }
+
+        fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+            Err(anyhow::anyhow!("Not supported"))
+        }
+
+        fn get_usage_info(&self) -> Option<AiUsageInfo> {
+            None
+        }
+    }
+
+    /// A small agent loop: each turn's question carries the time and a
+    /// random number
+    fn run_agent(assistant: Arc<dyn AiAssistantPlugin>) -> Result<Vec<String>> {
+        let mut messages = Vec::new();
+        let mut answers = Vec::new();
+        for turn in 0..3 {
+            messages.push(AiMessage {
+                role: MessageRole::User,
+                content: format!("turn {turn} nonce {}", random_u64()),
+                timestamp: Some(current_time()),
+                images: Vec::new(),
+            });
+            let request = AiMessageRequest {
+                messages: messages.clone(),
+                context: None,
+                tools: None,
+                model: None,
+                max_tokens: None,
+                temperature: None,
+            };
+            let response = if turn % 2 == 0 {
+                assistant.send_message(request)?
+            } else {
+                spawn_ai_stream(assistant.clone(), request, 4).collect_response()?
+            };
+            messages.push(AiMessage {
+                role: MessageRole::Assistant,
+                content: response.content.clone(),
+                timestamp: Some(current_time()),
+                images: Vec::new(),
+            });
+            answers.push(response.content);
+        }
+        Ok(answers)
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let (recorder, guard) = RecordingAssistant::start(
+            Arc::new(StampingAssistant),
+            Determinism::new(9),
+        );
+        let recorder = Arc::new(recorder);
+        let answers = run_agent(recorder.clone()).unwrap();
+        drop(guard);
+        assert_eq!(answers, ["1 answers", "3 streamed", "5 answers"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.json");
+        recorder.recording().save(&path).unwrap();
+        let recording = AgentRecording::load(&path).unwrap();
+        assert_eq!(recording.seed, 9);
+        assert_eq!(recording.exchanges.len(), 3);
+        assert!(matches!(
+            recording.exchanges[1].response,
+            RecordedResponse::Stream { ref chunks, .. } if chunks.len() == 2
+        ));
+
+        // The same seed reproduces the run without the assistant
+        let (replay, guard) = ReplayAssistant::start(recording.clone());
+        let replay = Arc::new(replay);
+        assert_eq!(run_agent(replay.clone()).unwrap(), answers);
+        replay.finish().unwrap();
+        drop(guard);
+
+        // Another seed changes the questions, which the replay points out
+        let mut other = recording;
+        other.seed = 10;
+        let (replay, _guard) = ReplayAssistant::start(other);
+        let err = run_agent(Arc::new(replay)).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Request 1 differs from the recording"),
+            "{err:#}"
+        );
+    }
+}
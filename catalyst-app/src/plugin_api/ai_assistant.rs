@@ -5,8 +5,17 @@
 //! while keeping the core editor agnostic to specific AI providers.
 
 use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::plugin_api::{AllocTag, ContextSource, ToolEffect, alloc_scope};
+
+/// Chunks buffered between a streaming provider and its consumer before the
+/// provider is made to wait
+pub const DEFAULT_AI_STREAM_CAPACITY: usize = 64;
 
 /// Trait that AI assistant plugins must implement
 pub trait AiAssistantPlugin: Send + Sync {
@@ -23,11 +32,15 @@ pub trait AiAssistantPlugin: Send + Sync {
     fn send_message(&self, request: AiMessageRequest) -> Result<AiMessageResponse>;
 
     /// Stream a message from the AI assistant
+    ///
+    /// Chunks go through `sender`, which blocks while the consumer's buffer
+    /// is full and fails once the consumer has gone away, at which point the
+    /// provider should stop. Use [`spawn_ai_stream`] to consume the stream.
     fn stream_message(
         &self,
         request: AiMessageRequest,
-        callback: Box<dyn Fn(AiStreamChunk) + Send>,
-    ) -> Result<()>;
+        sender: &AiStreamSender,
+    ) -> Result<AiStreamCompletion>;
 
     /// Get available tools/capabilities
     fn get_capabilities(&self) -> Vec<AiCapability>;
@@ -68,6 +81,19 @@ pub struct AiMessage {
     pub role: MessageRole,
     pub content: String,
     pub timestamp: Option<std::time::SystemTime>,
+    /// Images sent along with the message, for assistants that support
+    /// vision
+    #[serde(default)]
+    pub images: Vec<AiImage>,
+}
+
+/// An image attached to a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AiImage {
+    /// MIME type, e.g. `image/png`
+    pub media_type: String,
+    /// Base64 encoded image data
+    pub data: String,
 }
 
 /// Role of the message sender
@@ -84,7 +110,18 @@ pub struct EditorContext {
     pub current_file: Option<FileContext>,
     pub selection: Option<SelectionContext>,
     pub project: Option<ProjectContext>,
+    /// Every root of a multi-root workspace, the primary root first;
+    /// `project` describes the root of the current file
+    #[serde(default)]
+    pub workspace_roots: Vec<ProjectContext>,
     pub open_files: Vec<String>,
+    /// Workspace memories relevant to the request
+    #[serde(default)]
+    pub memories: Vec<String>,
+    /// Retrieved file ranges and tool results, numbered so the answer can
+    /// cite them; see [`sources_prompt`](crate::plugin_api::sources_prompt)
+    #[serde(default)]
+    pub sources: Vec<ContextSource>,
 }
 
 /// Context about the current file
@@ -111,6 +148,8 @@ pub struct ProjectContext {
     pub name: String,
     pub language: Option<String>,
     pub dependencies: Vec<String>,
+    /// Name of the monorepo sub-project the context is scoped to
+    pub sub_project: Option<String>,
 }
 
 /// Position in a file
@@ -126,6 +165,8 @@ pub struct ToolDefinition {
     pub name: String,
     pub description: String,
     pub parameters: serde_json::Value,
+    #[serde(default)]
+    pub effect: ToolEffect,
 }
 
 /// Response from AI assistant
@@ -162,6 +203,158 @@ pub struct AiStreamChunk {
     pub finished: bool,
 }
 
+/// Item of a response stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AiStreamEvent {
+    Chunk(AiStreamChunk),
+    /// The stream ended successfully; always the last item
+    Completed(AiStreamCompletion),
+    /// The stream failed; always the last item
+    Error(String),
+}
+
+/// Summary sent when a stream ends
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AiStreamCompletion {
+    pub model: String,
+    pub finish_reason: Option<String>,
+    pub usage: Option<UsageInfo>,
+}
+
+/// Producer side of a response stream
+#[derive(Clone)]
+pub struct AiStreamSender {
+    tx: Sender<AiStreamEvent>,
+    sent: Arc<AtomicU64>,
+}
+
+impl AiStreamSender {
+    /// Send a chunk, waiting while the consumer's buffer is full
+    pub fn send(&self, chunk: AiStreamChunk) -> Result<()> {
+        self.tx
+            .send(AiStreamEvent::Chunk(chunk))
+            .map_err(|_| anyhow::anyhow!("The stream consumer went away"))?;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Send a text chunk
+    pub fn send_text(&self, content: impl Into<String>) -> Result<()> {
+        self.send(AiStreamChunk {
+            content: Some(content.into()),
+            tool_call: None,
+            finished: false,
+        })
+    }
+
+    /// Get the number of chunks sent so far, including by clones
+    pub fn sent_count(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+/// Consumer side of a response stream
+///
+/// Iterating yields chunks until a final `Completed` or `Error` item.
+/// Dropping the stream makes the provider's next send fail, cancelling it.
+pub struct AiStream {
+    rx: Receiver<AiStreamEvent>,
+    finished: bool,
+}
+
+impl AiStream {
+    /// Get the channel events arrive on, for use in a `select!`
+    pub fn receiver(&self) -> &Receiver<AiStreamEvent> {
+        &self.rx
+    }
+
+    /// Wait for the whole response
+    pub fn collect_response(self) -> Result<AiMessageResponse> {
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for event in self {
+            match event {
+                AiStreamEvent::Chunk(chunk) => {
+                    if let Some(text) = chunk.content {
+                        content.push_str(&text);
+                    }
+                    tool_calls.extend(chunk.tool_call);
+                }
+                AiStreamEvent::Completed(completion) => {
+                    return Ok(AiMessageResponse {
+                        content,
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        usage: completion.usage,
+                        model: completion.model,
+                        finish_reason: completion.finish_reason,
+                    });
+                }
+                AiStreamEvent::Error(err) => return Err(anyhow::anyhow!(err)),
+            }
+        }
+        Err(anyhow::anyhow!("The stream ended without completing"))
+    }
+}
+
+impl Iterator for AiStream {
+    type Item = AiStreamEvent;
+
+    fn next(&mut self) -> Option<AiStreamEvent> {
+        if self.finished {
+            return None;
+        }
+        match self.rx.recv() {
+            Ok(event) => {
+                self.finished = !matches!(event, AiStreamEvent::Chunk(_));
+                Some(event)
+            }
+            Err(_) => {
+                self.finished = true;
+                Some(AiStreamEvent::Error(
+                    "The stream ended without completing".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// Create a stream buffering at most `capacity` chunks
+pub fn ai_stream_channel(capacity: usize) -> (AiStreamSender, AiStream) {
+    let (tx, rx) = crossbeam_channel::bounded(capacity.max(1));
+    (
+        AiStreamSender {
+            tx,
+            sent: Arc::new(AtomicU64::new(0)),
+        },
+        AiStream {
+            rx,
+            finished: false,
+        },
+    )
+}
+
+/// Run [`AiAssistantPlugin::stream_message`] on a background thread and
+/// return the stream of its events, ending with its completion or error
+pub fn spawn_ai_stream(
+    plugin: Arc<dyn AiAssistantPlugin>,
+    request: AiMessageRequest,
+    capacity: usize,
+) -> AiStream {
+    let (sender, stream) = ai_stream_channel(capacity);
+    // Keep the correlation id of the request on the provider's log lines
+    let span = tracing::Span::current();
+    std::thread::spawn(move || {
+        let _span = span.entered();
+        let _scope = alloc_scope(AllocTag::Ai);
+        let last = match plugin.stream_message(request, &sender) {
+            Ok(completion) => AiStreamEvent::Completed(completion),
+            Err(err) => AiStreamEvent::Error(format!("{err:#}")),
+        };
+        let _ = sender.tx.send(last);
+    });
+    stream
+}
+
 /// AI assistant capability
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiCapability {
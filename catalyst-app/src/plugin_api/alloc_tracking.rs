@@ -0,0 +1,465 @@
+//! Allocation Tracking
+//!
+//! This module attributes heap memory to the subsystems that allocated it so
+//! the idle memory budget can be checked per subsystem instead of only for
+//! the whole process. Code that does a subsystem's work runs inside an
+//! [`alloc_scope`], which tags the current thread; [`TrackingAllocator`]
+//! stores that tag in a small header in front of every allocation, so the
+//! bytes are released from the same subsystem wherever they are freed.
+//!
+//! The allocator is only installed when the `alloc-tracking` feature is
+//! enabled. Scopes are always present and cost a thread-local write, so
+//! builds without the feature simply report nothing.
+
+use anyhow::Result;
+use floem::View;
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::plugin_api::{
+    MetricsRegistry, PanelCommand, PanelCommandResult, SidebarPanelInfo,
+    SidebarPanelPlugin, SidebarPosition, text_panel_view,
+};
+
+/// Default time between two publications of the allocation metrics
+pub const DEFAULT_ALLOC_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
+const TAG_COUNT: usize = 5;
+
+static GLOBAL_COUNTERS: AllocationCounters = AllocationCounters::new();
+
+thread_local! {
+    static CURRENT_TAG: Cell<u8> = const { Cell::new(AllocTag::Other as u8) };
+}
+
+/// Subsystem an allocation is attributed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum AllocTag {
+    /// Anything not running in a tagged scope
+    Other,
+    /// Workspace indexing and retrieval
+    Index,
+    /// Syntax highlighting
+    Highlight,
+    /// MCP servers and tool calls
+    Mcp,
+    /// AI assistant requests
+    Ai,
+}
+
+impl AllocTag {
+    pub const ALL: [AllocTag; TAG_COUNT] = [
+        AllocTag::Other,
+        AllocTag::Index,
+        AllocTag::Highlight,
+        AllocTag::Mcp,
+        AllocTag::Ai,
+    ];
+
+    /// Get the name used in metric names
+    pub fn name(&self) -> &'static str {
+        match self {
+            AllocTag::Other => "other",
+            AllocTag::Index => "index",
+            AllocTag::Highlight => "highlight",
+            AllocTag::Mcp => "mcp",
+            AllocTag::Ai => "ai",
+        }
+    }
+
+    fn from_u8(tag: u8) -> Self {
+        AllocTag::ALL
+            .get(tag as usize)
+            .copied()
+            .unwrap_or(AllocTag::Other)
+    }
+}
+
+/// Attributes allocations on the current thread to a subsystem until dropped
+pub struct AllocScope {
+    previous: u8,
+}
+
+impl Drop for AllocScope {
+    fn drop(&mut self) {
+        let _ = CURRENT_TAG.try_with(|tag| tag.set(self.previous));
+    }
+}
+
+/// Attribute allocations on the current thread to `tag` until the returned
+/// guard is dropped
+pub fn alloc_scope(tag: AllocTag) -> AllocScope {
+    let previous = CURRENT_TAG
+        .try_with(|current| current.replace(tag as u8))
+        .unwrap_or(AllocTag::Other as u8);
+    AllocScope { previous }
+}
+
+/// Get the subsystem allocations on the current thread are attributed to
+pub fn current_alloc_tag() -> AllocTag {
+    AllocTag::from_u8(
+        CURRENT_TAG
+            .try_with(|tag| tag.get())
+            .unwrap_or(AllocTag::Other as u8),
+    )
+}
+
+/// Check if the tracking allocator is installed in this build
+pub fn alloc_tracking_enabled() -> bool {
+    cfg!(feature = "alloc-tracking")
+}
+
+/// Memory held by one subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubsystemAllocation {
+    pub tag: AllocTag,
+    pub live_bytes: usize,
+    pub live_allocations: usize,
+}
+
+/// Live bytes and allocations per subsystem
+pub struct AllocationCounters {
+    live_bytes: [AtomicUsize; TAG_COUNT],
+    live_allocations: [AtomicUsize; TAG_COUNT],
+}
+
+impl AllocationCounters {
+    pub const fn new() -> Self {
+        Self {
+            live_bytes: [const { AtomicUsize::new(0) }; TAG_COUNT],
+            live_allocations: [const { AtomicUsize::new(0) }; TAG_COUNT],
+        }
+    }
+
+    /// Get the counters updated by the installed allocator
+    pub fn global() -> &'static AllocationCounters {
+        &GLOBAL_COUNTERS
+    }
+
+    fn record_alloc(&self, tag: u8, size: usize) {
+        let tag = AllocTag::from_u8(tag) as usize;
+        self.live_bytes[tag].fetch_add(size, Ordering::Relaxed);
+        self.live_allocations[tag].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(&self, tag: u8, size: usize) {
+        let tag = AllocTag::from_u8(tag) as usize;
+        self.live_bytes[tag].fetch_sub(size, Ordering::Relaxed);
+        self.live_allocations[tag].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Get the memory held by every subsystem
+    pub fn snapshot(&self) -> Vec<SubsystemAllocation> {
+        AllocTag::ALL
+            .iter()
+            .map(|tag| SubsystemAllocation {
+                tag: *tag,
+                live_bytes: self.live_bytes[*tag as usize].load(Ordering::Relaxed),
+                live_allocations: self.live_allocations[*tag as usize]
+                    .load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Publish the live bytes and allocations of every subsystem to the
+    /// metrics registry under `memory.*`
+    pub fn publish(&self, metrics: &MetricsRegistry) {
+        let snapshot = self.snapshot();
+        for allocation in &snapshot {
+            let name = allocation.tag.name();
+            metrics.set_gauge(
+                &format!("memory.{name}.live_bytes"),
+                allocation.live_bytes as f64,
+            );
+            metrics.set_gauge(
+                &format!("memory.{name}.live_allocations"),
+                allocation.live_allocations as f64,
+            );
+        }
+        let total: usize = snapshot.iter().map(|a| a.live_bytes).sum();
+        metrics.set_gauge("memory.total.live_bytes", total as f64);
+    }
+}
+
+impl Default for AllocationCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publish the allocation metrics in the background every `interval`
+pub fn start_allocation_metrics(interval: Duration) {
+    std::thread::Builder::new()
+        .name("AllocationMetrics".to_string())
+        .spawn(move || {
+            loop {
+                AllocationCounters::global().publish(MetricsRegistry::global());
+                std::thread::sleep(interval);
+            }
+        })
+        .ok();
+}
+
+/// System allocator that attributes every allocation to the subsystem of
+/// the scope it was made in
+///
+/// The tag is stored in the byte right before the returned pointer, in
+/// `align` bytes of padding, so a reallocation keeps the tag of the original
+/// allocation.
+pub struct TrackingAllocator {
+    counters: &'static AllocationCounters,
+}
+
+impl TrackingAllocator {
+    pub const fn new() -> Self {
+        Self::with_counters(&GLOBAL_COUNTERS)
+    }
+
+    /// Create an allocator updating other counters than the global ones
+    pub const fn with_counters(counters: &'static AllocationCounters) -> Self {
+        Self { counters }
+    }
+
+    fn padded(layout: Layout) -> Option<(Layout, usize)> {
+        let offset = layout.align();
+        let padded =
+            Layout::from_size_align(layout.size().checked_add(offset)?, offset)
+                .ok()?;
+        Some((padded, offset))
+    }
+
+    /// Write the tag in front of a new allocation and return its user pointer
+    unsafe fn tag(&self, base: *mut u8, offset: usize, size: usize) -> *mut u8 {
+        if base.is_null() {
+            return base;
+        }
+        let tag = CURRENT_TAG
+            .try_with(|tag| tag.get())
+            .unwrap_or(AllocTag::Other as u8);
+        unsafe {
+            let ptr = base.add(offset);
+            ptr.sub(1).write(tag);
+            self.counters.record_alloc(tag, size);
+            ptr
+        }
+    }
+}
+
+impl Default for TrackingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((padded, offset)) = Self::padded(layout) else {
+            return std::ptr::null_mut();
+        };
+        unsafe { self.tag(System.alloc(padded), offset, layout.size()) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let Some((padded, offset)) = Self::padded(layout) else {
+            return std::ptr::null_mut();
+        };
+        unsafe { self.tag(System.alloc_zeroed(padded), offset, layout.size()) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // The layout was checked when the pointer was allocated
+        let Some((padded, offset)) = Self::padded(layout) else {
+            return;
+        };
+        unsafe {
+            self.counters
+                .record_dealloc(ptr.sub(1).read(), layout.size());
+            System.dealloc(ptr.sub(offset), padded);
+        }
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let Some((padded, offset)) = Self::padded(layout) else {
+            return std::ptr::null_mut();
+        };
+        let Some(new_padded_size) = new_size.checked_add(offset) else {
+            return std::ptr::null_mut();
+        };
+        unsafe {
+            let tag = ptr.sub(1).read();
+            let base = System.realloc(ptr.sub(offset), padded, new_padded_size);
+            if base.is_null() {
+                return base;
+            }
+            // The tag was copied along with the rest of the allocation
+            self.counters.record_dealloc(tag, layout.size());
+            self.counters.record_alloc(tag, new_size);
+            base.add(offset)
+        }
+    }
+}
+
+/// Sidebar panel showing the memory held by every subsystem
+#[derive(Default)]
+pub struct MemoryPanel;
+
+impl MemoryPanel {
+    pub const ID: &'static str = "catalyst.memory";
+
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Format a byte count in megabytes
+pub fn format_bytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+impl SidebarPanelPlugin for MemoryPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Memory".to_string(),
+            description: "Heap memory held by each subsystem".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        text_panel_view(|| {
+            if !alloc_tracking_enabled() {
+                return "Allocation tracking is off. Build with \
+                        `--features alloc-tracking` to enable it."
+                    .to_string();
+            }
+            let snapshot = AllocationCounters::global().snapshot();
+            let total: usize = snapshot.iter().map(|a| a.live_bytes).sum();
+            snapshot
+                .iter()
+                .map(|allocation| {
+                    format!(
+                        "{:<10} {:>10}  ({} allocations)",
+                        allocation.tag.name(),
+                        format_bytes(allocation.live_bytes),
+                        allocation.live_allocations
+                    )
+                })
+                .chain(std::iter::once(format!(
+                    "{:<10} {:>10}",
+                    "total",
+                    format_bytes(total)
+                )))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(AllocationCounters::global().snapshot())
+            .unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match command.command_id.as_str() {
+            "snapshot" => PanelCommandResult {
+                success: true,
+                result: Some(self.get_state()),
+                error: None,
+            },
+            other => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!("Unknown memory panel command '{}'", other)),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static COUNTERS: AllocationCounters = AllocationCounters::new();
+
+    fn live(tag: AllocTag) -> (usize, usize) {
+        let allocation = COUNTERS.snapshot()[tag as usize];
+        (allocation.live_bytes, allocation.live_allocations)
+    }
+
+    #[test]
+    fn test_tracking_allocator() {
+        let allocator = TrackingAllocator::with_counters(&COUNTERS);
+        let layout = Layout::from_size_align(100, 16).unwrap();
+
+        let (ptr, zeroed) = {
+            let _index = alloc_scope(AllocTag::Index);
+            {
+                let _ai = alloc_scope(AllocTag::Ai);
+                assert_eq!(current_alloc_tag(), AllocTag::Ai);
+            }
+            assert_eq!(current_alloc_tag(), AllocTag::Index);
+            let ptr = unsafe { allocator.alloc(layout) };
+            assert_eq!(ptr as usize % 16, 0);
+            let zeroed = unsafe { allocator.alloc_zeroed(Layout::new::<[u8; 8]>()) };
+            assert_eq!(unsafe { zeroed.cast::<[u8; 8]>().read() }, [0; 8]);
+            (ptr, zeroed)
+        };
+        assert_eq!(current_alloc_tag(), AllocTag::Other);
+        assert_eq!(live(AllocTag::Index), (108, 2));
+
+        // Growing an allocation outside the scope keeps its subsystem
+        let ptr = unsafe { allocator.realloc(ptr, layout, 300) };
+        assert_eq!(live(AllocTag::Index), (308, 2));
+        assert_eq!(live(AllocTag::Other), (0, 0));
+
+        unsafe {
+            allocator.dealloc(ptr, Layout::from_size_align(300, 16).unwrap());
+            allocator.dealloc(zeroed, Layout::new::<[u8; 8]>());
+        }
+        assert_eq!(live(AllocTag::Index), (0, 0));
+
+        let metrics = MetricsRegistry::default();
+        COUNTERS.publish(&metrics);
+        assert_eq!(metrics.get("memory.index.live_bytes"), Some(0.0));
+        assert_eq!(metrics.get("memory.total.live_bytes"), Some(0.0));
+    }
+}
@@ -0,0 +1,569 @@
+//! Plugin API Contracts
+//!
+//! This module keeps plugins built against earlier minor versions of the
+//! plugin API working. Each minor version records what its plugins exchange
+//! with the host as JSON fixtures in `contract_fixtures/<version>.json`; the
+//! contract check reads every fixture with the current types and fails when
+//! a field was renamed, removed or changed meaning. New fields are fine as
+//! long as old data still reads, usually through `#[serde(default)]`.
+//!
+//! The trait side of the contract is recorded in the same fixtures: the
+//! signature of every method of the plugin traits at that version. The
+//! tests read the current traits from their source and fail when a method
+//! a plugin could implement changed, or a method it didn't have to
+//! implement became required. Reference plugins in the tests implement
+//! only the methods required at 0.4 and check the host still drives them.
+//! Versions older than [`OLDEST_PLUGIN_API_VERSION`] broke the traits and
+//! are refused. WASI volts are covered by the volt loader's tests.
+
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+use crate::plugin_api::{
+    AiMessageRequest, AiMessageResponse, AiPluginInfo, AiStreamChunk, McpRequest,
+    McpResourceContent, McpResponse, McpServerInfo, McpTool, McpToolResult,
+};
+
+/// Version of the plugin API implemented by this host
+pub const PLUGIN_API_VERSION: PluginApiVersion =
+    PluginApiVersion { major: 0, minor: 4 };
+
+/// Oldest plugin API version whose plugins this host runs
+///
+/// 0.4 replaced the callback `AiAssistantPlugin::stream_message` took with a
+/// bounded channel, so plugins built against 0.3 no longer build.
+pub const OLDEST_PLUGIN_API_VERSION: PluginApiVersion =
+    PluginApiVersion { major: 0, minor: 4 };
+
+/// Major and minor version of the plugin API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PluginApiVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PluginApiVersion {
+    pub fn parse(version: &str) -> Result<Self> {
+        let (major, minor) = version
+            .split_once('.')
+            .and_then(|(major, minor)| {
+                Some((major.parse().ok()?, minor.parse().ok()?))
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid plugin API version '{}': expected major.minor",
+                    version
+                )
+            })?;
+        Ok(Self { major, minor })
+    }
+
+    /// Check a host of this version runs plugins built against `plugin`
+    pub fn supports(&self, plugin: PluginApiVersion) -> bool {
+        plugin.major == self.major && plugin.minor <= self.minor
+    }
+}
+
+/// Check this host runs plugins built against `plugin`
+pub fn is_supported_plugin_api(plugin: PluginApiVersion) -> bool {
+    PLUGIN_API_VERSION.supports(plugin) && plugin >= OLDEST_PLUGIN_API_VERSION
+}
+
+impl fmt::Display for PluginApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Data exchanged with the host by plugins of one API version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractFixtures {
+    pub api_version: String,
+    pub fixtures: Vec<ContractFixture>,
+    /// Plugin traits as they were at this version
+    #[serde(default)]
+    pub traits: Vec<TraitSurface>,
+}
+
+/// Methods of a plugin trait, as one-line signatures such as
+/// `fn is_running(&self) -> bool`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraitSurface {
+    pub name: String,
+    /// Methods plugins must implement
+    pub required: Vec<String>,
+    /// Methods with a default plugins may override
+    #[serde(default)]
+    pub provided: Vec<String>,
+}
+
+impl TraitSurface {
+    /// Read the methods of trait `name` from the source defining it
+    ///
+    /// Expects rustfmt's layout, with the trait's items indented by four
+    /// spaces and its closing brace at the start of a line. Leading
+    /// underscores of parameter names are dropped, since renaming a
+    /// parameter doesn't break implementations.
+    pub fn parse(source: &str, name: &str) -> Option<Self> {
+        let start = source.find(&format!("pub trait {}", name))?;
+        let body = &source[start..];
+        let body = &body[..body.find("\n}")?];
+        let mut surface = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+        // Signature read so far, over several lines
+        let mut signature = String::new();
+        for line in body.lines().skip(1) {
+            let line = line.strip_prefix("    ").unwrap_or(line);
+            if signature.is_empty() && !line.starts_with("fn ") {
+                continue;
+            }
+            let line = line.trim();
+            if let Some(end) = line.strip_suffix(';') {
+                signature.push_str(end);
+                surface.required.push(normalize_signature(&signature));
+            } else if let Some(end) =
+                line.strip_suffix("{}").or_else(|| line.strip_suffix('{'))
+            {
+                signature.push_str(end);
+                surface.provided.push(normalize_signature(&signature));
+            } else {
+                signature.push_str(line);
+                signature.push(' ');
+                continue;
+            }
+            signature.clear();
+        }
+        Some(surface)
+    }
+}
+
+/// Join a signature written over several lines into one
+fn normalize_signature(signature: &str) -> String {
+    signature
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace("(_", "(")
+        .replace(", _", ", ")
+}
+
+/// Get the name of the method with this signature
+fn method_name(signature: &str) -> &str {
+    let name = signature.strip_prefix("fn ").unwrap_or(signature);
+    name.split(['(', '<']).next().unwrap_or(name)
+}
+
+/// One value a plugin sends to or gets from the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractFixture {
+    /// Name of the plugin API type, e.g. `AiMessageRequest`
+    pub kind: String,
+    pub value: Value,
+}
+
+impl ContractFixtures {
+    /// Check the current host still honors every fixture, returning what
+    /// broke
+    pub fn check(&self) -> Result<Vec<String>> {
+        let version = PluginApiVersion::parse(&self.api_version)?;
+        if !is_supported_plugin_api(version) {
+            return Err(anyhow!(
+                "Plugin API {} doesn't support plugins built against {}",
+                PLUGIN_API_VERSION,
+                version
+            ));
+        }
+        Ok(self
+            .fixtures
+            .iter()
+            .filter_map(|fixture| {
+                check_fixture(fixture)
+                    .err()
+                    .map(|err| format!("{} {}: {:#}", version, fixture.kind, err))
+            })
+            .collect())
+    }
+
+    /// Check plugins of this version still implement the `current` plugin
+    /// traits, returning what broke
+    ///
+    /// Every method they could implement must keep its signature, and a
+    /// method they didn't have to implement can't become required.
+    pub fn check_traits(&self, current: &[TraitSurface]) -> Vec<String> {
+        let mut broken = Vec::new();
+        for recorded in &self.traits {
+            let Some(surface) = current.iter().find(|t| t.name == recorded.name)
+            else {
+                broken.push(format!(
+                    "{} {} was removed",
+                    self.api_version, recorded.name
+                ));
+                continue;
+            };
+            for signature in recorded.required.iter().chain(&recorded.provided) {
+                if !surface.required.contains(signature)
+                    && !surface.provided.contains(signature)
+                {
+                    broken.push(format!(
+                        "{} {}::{} changed from `{}`",
+                        self.api_version,
+                        recorded.name,
+                        method_name(signature),
+                        signature
+                    ));
+                }
+            }
+            for signature in &surface.required {
+                let name = method_name(signature);
+                let was_required = recorded
+                    .required
+                    .iter()
+                    .any(|recorded| method_name(recorded) == name);
+                if !was_required {
+                    broken.push(format!(
+                        "{} {}::{} is now required",
+                        self.api_version, recorded.name, name
+                    ));
+                }
+            }
+        }
+        broken
+    }
+}
+
+fn check_fixture(fixture: &ContractFixture) -> Result<()> {
+    let value = &fixture.value;
+    match fixture.kind.as_str() {
+        "AiPluginInfo" => check_value::<AiPluginInfo>(value),
+        "AiMessageRequest" => check_value::<AiMessageRequest>(value),
+        "AiMessageResponse" => check_value::<AiMessageResponse>(value),
+        "AiStreamChunk" => check_value::<AiStreamChunk>(value),
+        "McpServerInfo" => check_value::<McpServerInfo>(value),
+        "McpRequest" => check_value::<McpRequest>(value),
+        "McpResponse" => check_value::<McpResponse>(value),
+        "McpTool" => check_value::<McpTool>(value),
+        "McpToolResult" => check_value::<McpToolResult>(value),
+        "McpResourceContent" => check_value::<McpResourceContent>(value),
+        kind => Err(anyhow!("Unknown plugin API type '{}'", kind)),
+    }
+}
+
+/// Read a value with the current type and check writing it back keeps every
+/// field it had
+fn check_value<T: Serialize + DeserializeOwned>(value: &Value) -> Result<()> {
+    let current: T = serde_json::from_value(value.clone())
+        .map_err(|err| anyhow!("can no longer be read: {}", err))?;
+    let written = serde_json::to_value(&current)?;
+    match changed_field(value, &written, "") {
+        Some(path) => Err(anyhow!("'{}' changed meaning", path)),
+        None => Ok(()),
+    }
+}
+
+/// Find the first field of `old` that `new` lost or changed
+///
+/// Fields `new` adds are ignored, and a missing field equals `null` since
+/// optional fields may be skipped when empty.
+fn changed_field(old: &Value, new: &Value, path: &str) -> Option<String> {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            old.iter().find_map(|(key, old)| {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                changed_field(old, new.get(key).unwrap_or(&Value::Null), &path)
+            })
+        }
+        (Value::Array(old), Value::Array(new)) if old.len() == new.len() => {
+            old.iter().zip(new).enumerate().find_map(|(i, (old, new))| {
+                changed_field(old, new, &format!("{}[{}]", path, i))
+            })
+        }
+        (old, new) if old == new => None,
+        _ => Some(if path.is_empty() {
+            "value".to_string()
+        } else {
+            path.to_string()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        AiAssistantPlugin, AiAuthData, AiAuthResult, AiCapability,
+        AiStreamCompletion, AiStreamSender, AiUsageInfo, McpResource,
+        McpServerCapabilities, McpServerHealth, McpServerPlugin, McpServerRegistry,
+        McpServerStatus,
+    };
+    use std::path::Path;
+
+    /// Assistant implementing only the methods required by plugin API 0.4
+    struct LegacyAssistant;
+
+    impl AiAssistantPlugin for LegacyAssistant {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn plugin_info(&self) -> AiPluginInfo {
+            serde_json::from_value(fixture("0.4", "AiPluginInfo")).unwrap()
+        }
+
+        fn is_authenticated(&self) -> bool {
+            true
+        }
+
+        fn send_message(
+            &self,
+            _request: AiMessageRequest,
+        ) -> Result<AiMessageResponse> {
+            Ok(serde_json::from_value(fixture("0.4", "AiMessageResponse")).unwrap())
+        }
+
+        fn stream_message(
+            &self,
+            _request: AiMessageRequest,
+            sender: &AiStreamSender,
+        ) -> Result<AiStreamCompletion> {
+            sender.send(serde_json::from_value(fixture("0.4", "AiStreamChunk"))?)?;
+            Ok(AiStreamCompletion {
+                model: "legacy-model".to_string(),
+                finish_reason: None,
+                usage: None,
+            })
+        }
+
+        fn get_capabilities(&self) -> Vec<AiCapability> {
+            Vec::new()
+        }
+
+        fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+            Ok(AiAuthResult {
+                success: true,
+                message: String::new(),
+                expires_at: None,
+            })
+        }
+
+        fn get_usage_info(&self) -> Option<AiUsageInfo> {
+            None
+        }
+    }
+
+    /// MCP server implementing only the methods required by plugin API 0.4
+    struct LegacyMcpServer;
+
+    impl McpServerPlugin for LegacyMcpServer {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn server_info(&self) -> McpServerInfo {
+            serde_json::from_value(fixture("0.4", "McpServerInfo")).unwrap()
+        }
+
+        fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn is_running(&self) -> bool {
+            true
+        }
+
+        fn health_check(&self) -> McpServerHealth {
+            McpServerHealth {
+                status: McpServerStatus::Running,
+                last_error: None,
+                uptime: None,
+                request_count: 0,
+                error_count: 0,
+            }
+        }
+
+        fn send_request(&self, _request: McpRequest) -> Result<McpResponse> {
+            Ok(serde_json::from_value(fixture("0.4", "McpResponse")).unwrap())
+        }
+
+        fn get_tools(&self) -> Result<Vec<McpTool>> {
+            Ok(vec![serde_json::from_value(fixture("0.4", "McpTool"))?])
+        }
+
+        fn get_resources(&self) -> Result<Vec<McpResource>> {
+            Ok(Vec::new())
+        }
+
+        fn call_tool(
+            &self,
+            _tool_name: &str,
+            _arguments: Value,
+        ) -> Result<McpToolResult> {
+            Ok(serde_json::from_value(fixture("0.4", "McpToolResult"))?)
+        }
+
+        fn read_resource(&self, _resource_uri: &str) -> Result<McpResourceContent> {
+            Ok(serde_json::from_value(fixture(
+                "0.4",
+                "McpResourceContent",
+            ))?)
+        }
+
+        fn subscribe_to_resource(&self, _resource_uri: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn unsubscribe_from_resource(&self, _resource_uri: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fixtures_dir() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/plugin_api/contract_fixtures")
+    }
+
+    fn load_fixtures(path: &Path) -> ContractFixtures {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn fixture(version: &str, kind: &str) -> Value {
+        load_fixtures(&fixtures_dir().join(format!("{}.json", version)))
+            .fixtures
+            .into_iter()
+            .find(|fixture| fixture.kind == kind)
+            .unwrap()
+            .value
+    }
+
+    /// Plugin traits of this host, read from their source
+    fn current_traits() -> Vec<TraitSurface> {
+        vec![
+            TraitSurface::parse(
+                include_str!("ai_assistant.rs"),
+                "AiAssistantPlugin",
+            )
+            .unwrap(),
+            TraitSurface::parse(include_str!("mcp_server.rs"), "McpServerPlugin")
+                .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_plugin_api_contracts() {
+        // Every supported version still reads
+        for entry in std::fs::read_dir(fixtures_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let fixtures = load_fixtures(&path);
+            let version = PluginApiVersion::parse(&fixtures.api_version).unwrap();
+            if !is_supported_plugin_api(version) {
+                continue;
+            }
+            let failures = fixtures.check().unwrap();
+            assert!(failures.is_empty(), "{}: {:#?}", path.display(), failures);
+        }
+
+        // Renamed fields and newer versions are caught
+        let renamed = ContractFixtures {
+            api_version: "0.4".to_string(),
+            fixtures: vec![ContractFixture {
+                kind: "McpTool".to_string(),
+                value: serde_json::json!({
+                    "name": "search",
+                    "description": null,
+                    "input_schema": {},
+                    "read_only": true,
+                }),
+            }],
+            traits: Vec::new(),
+        };
+        assert_eq!(renamed.check().unwrap().len(), 1);
+        let newer = ContractFixtures {
+            api_version: format!("0.{}", PLUGIN_API_VERSION.minor + 1),
+            fixtures: Vec::new(),
+            traits: Vec::new(),
+        };
+        assert!(newer.check().is_err());
+
+        // Plugins written against 0.4 still work through the host
+        let registry = McpServerRegistry::new();
+        let handle = registry
+            .register_server("legacy".to_string(), Box::new(LegacyMcpServer))
+            .unwrap();
+        let chunk = handle
+            .read()
+            .read_resource_range("file:///notes.md", 2, 3)
+            .unwrap();
+        assert_eq!(chunk.data, b"Not");
+        let capabilities: McpServerCapabilities =
+            handle.read().server_info().capabilities;
+        assert!(capabilities.tools);
+        let assistant = LegacyAssistant;
+        assert!(!assistant.plugin_info().supports_vision);
+        let request =
+            serde_json::from_value(fixture("0.4", "AiMessageRequest")).unwrap();
+        let response = assistant.send_message(request).unwrap();
+        assert_eq!(response.tool_calls.unwrap()[0].name, "read_file");
+    }
+
+    #[test]
+    fn test_plugin_api_trait_surfaces() {
+        // Plugins of supported versions still build against the traits, and
+        // refused versions really broke them
+        let current = current_traits();
+        for entry in std::fs::read_dir(fixtures_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let fixtures = load_fixtures(&path);
+            let version = PluginApiVersion::parse(&fixtures.api_version).unwrap();
+            let broken = fixtures.check_traits(&current);
+            if is_supported_plugin_api(version) {
+                assert!(broken.is_empty(), "{}: {:#?}", path.display(), broken);
+            } else {
+                assert!(
+                    !broken.is_empty(),
+                    "{} is refused but still builds",
+                    version
+                );
+                assert!(fixtures.check().is_err());
+            }
+        }
+
+        // 0.3 plugins streamed through a callback
+        let old = load_fixtures(&fixtures_dir().join("0.3.json"));
+        assert_eq!(
+            old.check_traits(&current),
+            vec![
+                "0.3 AiAssistantPlugin::stream_message changed from `fn \
+                 stream_message(&self, request: AiMessageRequest, callback: \
+                 Box<dyn Fn(AiStreamChunk) + Send>) -> Result<()>`"
+            ]
+        );
+
+        // A changed default and a new required method are caught
+        let mut changed = current.clone();
+        changed[1]
+            .provided
+            .retain(|signature| method_name(signature) != "read_resource_range");
+        changed[1]
+            .required
+            .push("fn restart(&mut self) -> Result<()>".to_string());
+        let fixtures = load_fixtures(&fixtures_dir().join("0.4.json"));
+        let broken = fixtures.check_traits(&changed);
+        assert_eq!(broken.len(), 2);
+        assert!(broken[0].starts_with("0.4 McpServerPlugin::read_resource_range"));
+        assert_eq!(broken[1], "0.4 McpServerPlugin::restart is now required");
+    }
+}
@@ -0,0 +1,448 @@
+//! Chat Attachments
+//!
+//! This module turns files dropped onto a chat and images pasted into it
+//! into attachments of the next message. Images are recognized by their
+//! leading bytes and sent to the assistant as images, which only assistants
+//! with vision can read. Text files become numbered context sources the
+//! answer can cite; files over the size limit are cut at a line boundary,
+//! and the attachment keeps a short preview so the user sees what was sent.
+//! Anything else, such as archives or executables, is refused with an error
+//! naming the file.
+//!
+//! Terminal output is only attached when the user shares a selection. It
+//! goes through the [`Redactor`] first, and the attachment keeps what was
+//! replaced, so the user can review exactly what will be sent.
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    AiImage, Redaction, Redactor, SourceLocation, SourceTracker,
+};
+
+/// Largest image sent to the assistant
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Largest part of a text file sent to the assistant
+pub const DEFAULT_MAX_TEXT_BYTES: usize = 128 * 1024;
+/// Largest file read at all; bigger ones are refused rather than truncated
+const MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Lines of a text attachment shown in its preview
+const PREVIEW_LINES: usize = 8;
+/// Bytes looked at to tell text from binary data
+const SNIFF_BYTES: usize = 8192;
+
+/// Size limits of attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentLimits {
+    pub max_image_bytes: usize,
+    pub max_text_bytes: usize,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_image_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            max_text_bytes: DEFAULT_MAX_TEXT_BYTES,
+        }
+    }
+}
+
+/// A file or image attached to a chat message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChatAttachment {
+    Image {
+        /// File name, or `None` for a pasted image
+        name: Option<String>,
+        image: AiImage,
+        size: usize,
+    },
+    Text {
+        path: PathBuf,
+        content: String,
+        /// Size of the whole file, in bytes
+        size: usize,
+        /// Whether only the beginning of the file is attached
+        truncated: bool,
+    },
+    /// Output selected in a terminal, with its secrets redacted
+    Terminal {
+        /// Title of the terminal
+        title: String,
+        content: String,
+        /// Secrets replaced in the content
+        redactions: Vec<Redaction>,
+        /// Whether only the end of the selection is attached
+        truncated: bool,
+    },
+}
+
+impl ChatAttachment {
+    /// Attach a file, detecting whether it is an image or text
+    pub fn from_file(path: &Path, limits: &AttachmentLimits) -> Result<Self> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|err| anyhow!("Can't attach {}: {}", path.display(), err))?;
+        if metadata.is_dir() {
+            return Err(anyhow!(
+                "Can't attach {}: folders can't be attached",
+                path.display()
+            ));
+        }
+        if metadata.len() > MAX_FILE_BYTES {
+            return Err(anyhow!(
+                "Can't attach {}: the file is {}, more than the {} limit",
+                path.display(),
+                format_size(metadata.len() as usize),
+                format_size(MAX_FILE_BYTES as usize)
+            ));
+        }
+        let bytes = std::fs::read(path)
+            .map_err(|err| anyhow!("Can't attach {}: {}", path.display(), err))?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+
+        if let Some(media_type) = image_media_type(&bytes) {
+            return Self::image(name, media_type, &bytes, limits);
+        }
+        if !looks_like_text(&bytes) {
+            return Err(anyhow!(
+                "Can't attach {}: only images (PNG, JPEG, GIF, WebP) and text \
+                 files are supported",
+                path.display()
+            ));
+        }
+        let size = bytes.len();
+        let mut content = String::from_utf8_lossy(&bytes).into_owned();
+        let truncated = content.len() > limits.max_text_bytes;
+        if truncated {
+            let mut end = limits.max_text_bytes;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            // Don't send half a line
+            if let Some(newline) = content[..end].rfind('\n') {
+                end = newline + 1;
+            }
+            content.truncate(end);
+        }
+        Ok(Self::Text {
+            path: path.to_path_buf(),
+            content,
+            size,
+            truncated,
+        })
+    }
+
+    /// Attach an image pasted from the clipboard
+    pub fn from_clipboard_image(
+        bytes: &[u8],
+        limits: &AttachmentLimits,
+    ) -> Result<Self> {
+        let media_type = image_media_type(bytes).ok_or_else(|| {
+            anyhow!(
+                "Can't attach the pasted data: only PNG, JPEG, GIF and WebP \
+                 images are supported"
+            )
+        })?;
+        Self::image(None, media_type, bytes, limits)
+    }
+
+    fn image(
+        name: Option<String>,
+        media_type: &str,
+        bytes: &[u8],
+        limits: &AttachmentLimits,
+    ) -> Result<Self> {
+        if bytes.len() > limits.max_image_bytes {
+            return Err(anyhow!(
+                "Can't attach {}: the image is {}, more than the {} limit",
+                name.as_deref().unwrap_or("the pasted image"),
+                format_size(bytes.len()),
+                format_size(limits.max_image_bytes)
+            ));
+        }
+        Ok(Self::Image {
+            name,
+            image: AiImage {
+                media_type: media_type.to_string(),
+                data: general_purpose::STANDARD.encode(bytes),
+            },
+            size: bytes.len(),
+        })
+    }
+
+    /// Attach output selected in a terminal, with its secrets replaced by
+    /// `redactor`
+    ///
+    /// Selections over the text limit keep their last lines, where the
+    /// errors usually are.
+    pub fn from_terminal(
+        title: &str,
+        selection: &str,
+        redactor: &Redactor,
+        limits: &AttachmentLimits,
+    ) -> Result<Self> {
+        if selection.trim().is_empty() {
+            return Err(anyhow!("Nothing is selected in the terminal"));
+        }
+        let redacted = redactor.redact(selection.trim_end());
+        let mut content = redacted.text;
+        let truncated = content.len() > limits.max_text_bytes;
+        if truncated {
+            let mut start = content.len() - limits.max_text_bytes;
+            while !content.is_char_boundary(start) {
+                start += 1;
+            }
+            // Don't send half a line
+            if let Some(newline) = content[start..].find('\n') {
+                start += newline + 1;
+            }
+            content.drain(..start);
+        }
+        Ok(Self::Terminal {
+            title: title.to_string(),
+            content,
+            redactions: redacted.redactions,
+            truncated,
+        })
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(self, Self::Image { .. })
+    }
+
+    /// Get the text shown for the attachment in the chat
+    pub fn label(&self) -> String {
+        match self {
+            Self::Image { name, size, .. } => format!(
+                "{} ({})",
+                name.as_deref().unwrap_or("pasted image"),
+                format_size(*size)
+            ),
+            Self::Text {
+                path,
+                content,
+                size,
+                truncated: true,
+            } => format!(
+                "{} (first {} of {})",
+                path.display(),
+                format_size(content.len()),
+                format_size(*size)
+            ),
+            Self::Text { path, size, .. } => {
+                format!("{} ({})", path.display(), format_size(*size))
+            }
+            Self::Terminal { title, content, .. } => {
+                format!("terminal {} ({})", title, format_size(content.len()))
+            }
+        }
+    }
+
+    /// Get the first lines of a text attachment, marked when the file was
+    /// truncated or secrets were redacted
+    pub fn preview(&self) -> Option<String> {
+        let (content, truncated, redacted) = match self {
+            Self::Image { .. } => return None,
+            Self::Text {
+                content, truncated, ..
+            } => (content, truncated, 0),
+            Self::Terminal {
+                content,
+                redactions,
+                truncated,
+                ..
+            } => (
+                content,
+                truncated,
+                redactions.iter().map(|redaction| redaction.count).sum(),
+            ),
+        };
+        let mut preview = content
+            .lines()
+            .take(PREVIEW_LINES)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if content.lines().count() > PREVIEW_LINES {
+            preview.push_str("\n...");
+        }
+        if *truncated {
+            preview.push_str("\n[truncated]");
+        }
+        if redacted > 0 {
+            preview.push_str(&format!("\n[{} redacted]", redacted));
+        }
+        Some(preview)
+    }
+
+    /// Add a text attachment as a context source, returning its source id
+    pub fn add_source(&self, tracker: &mut SourceTracker) -> Option<usize> {
+        match self {
+            Self::Image { .. } => None,
+            Self::Text { path, content, .. } => Some(tracker.add(
+                SourceLocation::File {
+                    path: path.clone(),
+                    start_line: 1,
+                    end_line: content.lines().count().max(1),
+                },
+                content.clone(),
+            )),
+            Self::Terminal { title, content, .. } => Some(tracker.add(
+                SourceLocation::Terminal {
+                    title: title.clone(),
+                },
+                content.clone(),
+            )),
+        }
+    }
+}
+
+/// Get the images of attachments, failing when the assistant can't read
+/// images
+pub fn attachment_images(
+    attachments: &[ChatAttachment],
+    supports_vision: bool,
+) -> Result<Vec<AiImage>> {
+    let images: Vec<AiImage> = attachments
+        .iter()
+        .filter_map(|attachment| match attachment {
+            ChatAttachment::Image { image, .. } => Some(image.clone()),
+            ChatAttachment::Text { .. } | ChatAttachment::Terminal { .. } => None,
+        })
+        .collect();
+    if !images.is_empty() && !supports_vision {
+        return Err(anyhow!(
+            "The configured assistant can't read images; remove the image \
+             attachments or switch to a model with vision"
+        ));
+    }
+    Ok(images)
+}
+
+/// Detect an image format from its leading bytes
+fn image_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP"
+    {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn looks_like_text(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The sniffed bytes may end inside a character
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachments_by_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = AttachmentLimits {
+            max_image_bytes: 64,
+            max_text_bytes: 20,
+        };
+
+        let png = dir.path().join("shot.png");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n0000").unwrap();
+        let image = ChatAttachment::from_file(&png, &limits).unwrap();
+        assert!(image.is_image());
+        assert!(attachment_images(&[image.clone()], false).is_err());
+        let images = attachment_images(&[image], true).unwrap();
+        assert_eq!(images[0].media_type, "image/png");
+
+        let text = dir.path().join("notes.txt");
+        std::fs::write(&text, "first line\nsecond line\nthird line\n").unwrap();
+        let attachment = ChatAttachment::from_file(&text, &limits).unwrap();
+        let ChatAttachment::Text {
+            content, truncated, ..
+        } = &attachment
+        else {
+            panic!("expected a text attachment");
+        };
+        assert_eq!(content, "first line\n");
+        assert!(truncated);
+        assert_eq!(attachment.preview().unwrap(), "first line\n[truncated]");
+        let mut sources = SourceTracker::new();
+        assert_eq!(attachment.add_source(&mut sources), Some(1));
+
+        let binary = dir.path().join("app.bin");
+        std::fs::write(&binary, [0x7f, b'E', b'L', b'F', 0, 1]).unwrap();
+        let err = ChatAttachment::from_file(&binary, &limits).unwrap_err();
+        assert!(err.to_string().contains("only images"));
+        assert!(ChatAttachment::from_clipboard_image(&[0; 70], &limits).is_err());
+    }
+
+    #[test]
+    fn test_terminal_selection_is_redacted() {
+        let limits = AttachmentLimits {
+            max_image_bytes: 64,
+            max_text_bytes: 80,
+        };
+        let selection = "$ cargo publish\nnote: using API_TOKEN=abc123secret\n\
+                         error: failed to publish\n\n";
+        let attachment = ChatAttachment::from_terminal(
+            "zsh",
+            selection,
+            &Redactor::new(),
+            &limits,
+        )
+        .unwrap();
+        let ChatAttachment::Terminal {
+            content,
+            redactions,
+            truncated,
+            ..
+        } = &attachment
+        else {
+            panic!("expected a terminal attachment");
+        };
+        // The end of the selection is kept
+        assert_eq!(
+            content,
+            "note: using API_TOKEN=[REDACTED:assignment]\nerror: failed to publish"
+        );
+        assert!(truncated);
+        assert_eq!(redactions[0].count, 1);
+        assert_eq!(
+            attachment.preview().unwrap(),
+            format!("{}\n[truncated]\n[1 redacted]", content)
+        );
+        let mut sources = SourceTracker::new();
+        assert_eq!(attachment.add_source(&mut sources), Some(1));
+        assert_eq!(sources.sources()[0].location.label(), "terminal zsh");
+
+        let empty =
+            ChatAttachment::from_terminal("zsh", " \n", &Redactor::new(), &limits);
+        assert!(empty.is_err());
+    }
+}
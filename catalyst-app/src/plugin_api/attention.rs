@@ -0,0 +1,341 @@
+//! Attention Signal
+//!
+//! This module keeps track of what the user has been working on: the files
+//! they viewed and edited, and the lines the cursor stayed on. Each event
+//! adds to a file's attention score, which decays with a half-life of
+//! minutes, so the signal follows the current task rather than the whole
+//! session. Retrieval boosts chunks of files with attention, and chunks near
+//! the lines the user dwelled on most, so the assistant prioritizes the code
+//! in front of the user without being told.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::RankedChunk;
+
+/// Time after which attention is halved
+const HALF_LIFE: Duration = Duration::from_secs(20 * 60);
+/// Time the cursor must stay on a line before it counts as dwelling
+const MIN_DWELL: Duration = Duration::from_secs(3);
+/// Dwell time credited at most for one stay of the cursor
+const MAX_DWELL: Duration = Duration::from_secs(5 * 60);
+/// Files tracked at once; the ones with the least attention are dropped
+const MAX_FILES: usize = 200;
+/// Dwell locations tracked per file
+const MAX_DWELL_LOCATIONS: usize = 20;
+/// Lines around a dwell location that share its attention
+const DWELL_RADIUS: usize = 20;
+
+const VIEW_WEIGHT: f32 = 1.0;
+const EDIT_WEIGHT: f32 = 3.0;
+/// Weight of a minute of dwelling
+const DWELL_WEIGHT: f32 = 2.0;
+
+/// A score that decays over time
+#[derive(Debug, Clone, Copy)]
+struct Decaying {
+    value: f32,
+    at: Instant,
+}
+
+impl Decaying {
+    fn new(now: Instant) -> Self {
+        Self {
+            value: 0.0,
+            at: now,
+        }
+    }
+
+    fn get(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.at);
+        self.value * 0.5f32.powf(elapsed.as_secs_f32() / HALF_LIFE.as_secs_f32())
+    }
+
+    fn add(&mut self, amount: f32, now: Instant) {
+        self.value = self.get(now) + amount;
+        self.at = now;
+    }
+}
+
+#[derive(Debug)]
+struct FileAttention {
+    score: Decaying,
+    /// Line and score of the places the cursor stayed on
+    dwell: Vec<(usize, Decaying)>,
+}
+
+#[derive(Debug, Default)]
+struct AttentionInner {
+    files: HashMap<PathBuf, FileAttention>,
+    /// Where the cursor is now, and since when
+    focus: Option<(PathBuf, usize, Instant)>,
+}
+
+impl AttentionInner {
+    fn file(&mut self, path: &Path, now: Instant) -> &mut FileAttention {
+        if !self.files.contains_key(path) && self.files.len() >= MAX_FILES {
+            let weakest = self
+                .files
+                .iter()
+                .min_by(|a, b| a.1.score.get(now).total_cmp(&b.1.score.get(now)))
+                .map(|(path, _)| path.clone());
+            if let Some(weakest) = weakest {
+                self.files.remove(&weakest);
+            }
+        }
+        self.files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| FileAttention {
+                score: Decaying::new(now),
+                dwell: Vec::new(),
+            })
+    }
+
+    fn add_dwell(&mut self, path: &Path, line: usize, amount: f32, now: Instant) {
+        let file = self.file(path, now);
+        file.score.add(amount, now);
+        match file
+            .dwell
+            .iter_mut()
+            .find(|(dwell_line, _)| dwell_line.abs_diff(line) <= DWELL_RADIUS / 2)
+        {
+            Some((_, score)) => score.add(amount, now),
+            None => {
+                if file.dwell.len() >= MAX_DWELL_LOCATIONS {
+                    file.dwell
+                        .sort_by(|a, b| b.1.get(now).total_cmp(&a.1.get(now)));
+                    file.dwell.truncate(MAX_DWELL_LOCATIONS - 1);
+                }
+                let mut score = Decaying::new(now);
+                score.add(amount, now);
+                file.dwell.push((line, score));
+            }
+        }
+    }
+
+    fn max_score(&self, now: Instant) -> f32 {
+        self.files
+            .values()
+            .map(|file| file.score.get(now))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Tracks the files and lines the user has been working on
+#[derive(Debug, Default)]
+pub struct AttentionTracker {
+    inner: Mutex<AttentionInner>,
+}
+
+impl AttentionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a file was opened or brought to the front
+    pub fn record_view(&self, path: &Path) {
+        self.record_view_at(path, Instant::now());
+    }
+
+    /// Record an edit of a file at a line, 0-based
+    pub fn record_edit(&self, path: &Path, line: usize) {
+        self.record_edit_at(path, line, Instant::now());
+    }
+
+    /// Record where the cursor is, 0-based
+    ///
+    /// The time the cursor spent at its previous place is credited to it
+    /// once it stayed long enough to count. Moving to another file counts
+    /// as viewing it.
+    pub fn record_cursor(&self, path: &Path, line: usize) {
+        self.record_cursor_at(path, line, Instant::now());
+    }
+
+    fn record_view_at(&self, path: &Path, now: Instant) {
+        self.inner
+            .lock()
+            .file(path, now)
+            .score
+            .add(VIEW_WEIGHT, now);
+    }
+
+    fn record_edit_at(&self, path: &Path, line: usize, now: Instant) {
+        self.inner.lock().add_dwell(path, line, EDIT_WEIGHT, now);
+    }
+
+    fn record_cursor_at(&self, path: &Path, line: usize, now: Instant) {
+        let mut inner = self.inner.lock();
+        if let Some((focus_path, focus_line, _)) = &inner.focus {
+            if focus_path == path && *focus_line == line {
+                return;
+            }
+        }
+        let same_file = match inner.focus.take() {
+            Some((previous_path, previous_line, since)) => {
+                let stay = now.saturating_duration_since(since);
+                if stay >= MIN_DWELL {
+                    let minutes = stay.min(MAX_DWELL).as_secs_f32() / 60.0;
+                    inner.add_dwell(
+                        &previous_path,
+                        previous_line,
+                        DWELL_WEIGHT * minutes,
+                        now,
+                    );
+                }
+                previous_path == path
+            }
+            None => false,
+        };
+        if !same_file {
+            inner.file(path, now).score.add(VIEW_WEIGHT, now);
+        }
+        inner.focus = Some((path.to_path_buf(), line, now));
+    }
+
+    /// Get the attention of a file, from 0 to 1 relative to the file with
+    /// the most attention
+    pub fn file_score(&self, path: &Path) -> f32 {
+        self.file_score_at(path, Instant::now())
+    }
+
+    fn file_score_at(&self, path: &Path, now: Instant) -> f32 {
+        let inner = self.inner.lock();
+        let max = inner.max_score(now);
+        match inner.files.get(path) {
+            Some(file) if max > 0.0 => file.score.get(now) / max,
+            _ => 0.0,
+        }
+    }
+
+    /// Get the attention of a range of lines, 1-based and inclusive, from 0
+    /// to 1
+    ///
+    /// Half comes from the file's attention and half from the dwell
+    /// locations near the range.
+    pub fn range_score(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+    ) -> f32 {
+        self.range_score_at(path, start_line, end_line, Instant::now())
+    }
+
+    fn range_score_at(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+        now: Instant,
+    ) -> f32 {
+        let inner = self.inner.lock();
+        let max = inner.max_score(now);
+        let Some(file) = inner.files.get(path).filter(|_| max > 0.0) else {
+            return 0.0;
+        };
+        let file_score = file.score.get(now);
+        let start = start_line.saturating_sub(1 + DWELL_RADIUS);
+        let end = end_line.saturating_sub(1) + DWELL_RADIUS;
+        let near: f32 = file
+            .dwell
+            .iter()
+            .filter(|(line, _)| (start..=end).contains(line))
+            .map(|(_, score)| score.get(now))
+            .sum();
+        let near = if file_score > 0.0 {
+            (near / file_score).min(1.0)
+        } else {
+            0.0
+        };
+        (file_score / max) * (0.5 + 0.5 * near)
+    }
+
+    /// Get the files with the most attention, most first
+    pub fn recent_files(&self, limit: usize) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let inner = self.inner.lock();
+        let mut files: Vec<(&PathBuf, f32)> = inner
+            .files
+            .iter()
+            .map(|(path, file)| (path, file.score.get(now)))
+            .collect();
+        files.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        files
+            .into_iter()
+            .take(limit)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Rank chunks the user has been working on higher
+    ///
+    /// A chunk's score grows by up to `boost` times, in proportion to the
+    /// attention of its lines.
+    pub fn boost(&self, ranked: &mut [RankedChunk], boost: f32) {
+        if boost <= 0.0 {
+            return;
+        }
+        let now = Instant::now();
+        for chunk in ranked.iter_mut() {
+            let attention = self.range_score_at(
+                &chunk.chunk.path,
+                chunk.chunk.start_line,
+                chunk.chunk.end_line,
+                now,
+            );
+            chunk.score *= 1.0 + boost * attention;
+        }
+        ranked.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.chunk.key().cmp(&b.chunk.key()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{ContextChunk, RetrievalSource, SharedPath};
+
+    #[test]
+    fn test_attention_follows_recent_work() {
+        let tracker = AttentionTracker::new();
+        let start = Instant::now();
+        let (a, b) = (Path::new("/w/a.rs"), Path::new("/w/b.rs"));
+
+        tracker.record_view_at(b, start);
+        tracker.record_cursor_at(a, 100, start);
+        // Dwelling a minute on line 100, then moving on
+        tracker.record_cursor_at(a, 300, start + Duration::from_secs(60));
+        tracker.record_edit_at(a, 105, start + Duration::from_secs(61));
+
+        let now = start + Duration::from_secs(62);
+        assert_eq!(tracker.file_score_at(a, now), 1.0);
+        assert!(tracker.file_score_at(b, now) < 0.5);
+        assert!(
+            tracker.range_score_at(a, 95, 110, now)
+                > tracker.range_score_at(a, 500, 520, now)
+        );
+        // Attention fades
+        let score = tracker.inner.lock().files[b].score;
+        assert!((score.get(now + HALF_LIFE) - score.get(now) / 2.0).abs() < 1e-4);
+
+        let chunk = |path: &str, score| RankedChunk {
+            chunk: ContextChunk {
+                path: SharedPath::from(path),
+                start_line: 95,
+                end_line: 110,
+                content: String::new(),
+                modified: None,
+            },
+            score,
+            sources: vec![RetrievalSource::Vector],
+        };
+        let mut ranked = vec![chunk("/w/c.rs", 1.0), chunk("/w/a.rs", 0.8)];
+        tracker.boost(&mut ranked, 0.5);
+        assert_eq!(ranked[0].chunk.path, Path::new("/w/a.rs"));
+    }
+}
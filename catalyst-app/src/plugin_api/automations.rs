@@ -0,0 +1,1239 @@
+//! Automations
+//!
+//! This module runs agent tasks and tool pipelines without the user asking
+//! each time. An automation binds a trigger (a cron schedule, a saved file
+//! matching some globs, diagnostics with errors, a newly opened pull
+//! request) to an action. The engine receives editor events, starts the
+//! automations they trigger on the blocking pool within the configured
+//! concurrency limits, and records every run in a per-workspace history the
+//! automation panel shows next to the enable/disable switches.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use globset::{Glob, GlobSetBuilder};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    DegradationTracker, FocusMode, JobHandle, JobScheduler, JournalOptions,
+    JournalState, JournaledStore, McpServerRegistry, McpToolResult, PanelCommand,
+    PanelCommandResult, SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition,
+    ToolTarget, current_time, text_panel_view,
+};
+
+/// Runs kept in the history, across all automations
+const MAX_RUN_HISTORY: usize = 500;
+/// Automation runs allowed at once unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_RUNS: usize = 2;
+/// Placeholder in prompts and tool arguments replaced by the triggering event
+pub const EVENT_PLACEHOLDER: &str = "{event}";
+/// Longest webhook payload included in an event description
+const MAX_DESCRIBED_PAYLOAD: usize = 2000;
+/// Server polled for new pull requests
+pub const GITHUB_SERVER_ID: &str = "github";
+
+/// A five-field cron expression: minute, hour, day of month, month and day
+/// of week (0 or 7 is Sunday)
+///
+/// Fields accept `*`, numbers, ranges (`1-5`), lists (`1,15`) and steps
+/// (`*/10`, `0-30/5`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    weekdays: BTreeSet<u32>,
+    /// Whether the day-of-month and day-of-week fields were both restricted,
+    /// in which case either may match
+    either_day: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow::anyhow!(
+                "Cron expression '{}' must have 5 fields",
+                expression
+            ));
+        }
+        let mut weekdays = parse_cron_field(fields[4], 0, 7)?;
+        if weekdays.remove(&7) {
+            weekdays.insert(0);
+        }
+        Ok(Self {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            weekdays,
+            either_day: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+
+    /// Check if the schedule fires in the minute of `time`
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self
+            .weekdays
+            .contains(&time.weekday().num_days_from_sunday());
+        let day_matches = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+            && self.months.contains(&time.month())
+            && day_matches
+    }
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<BTreeSet<u32>> {
+    let invalid = || anyhow::anyhow!("Invalid cron field '{}'", field);
+    let mut values = BTreeSet::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| invalid())?,
+                end.parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let value: u32 = range.parse().map_err(|_| invalid())?;
+            (value, value)
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        values.extend((start..=end).step_by(step));
+    }
+    Ok(values)
+}
+
+/// What starts an automation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationTrigger {
+    /// A cron expression in local time
+    Schedule { cron: String },
+    /// A file matching one of the globs, relative to the workspace root, was
+    /// saved
+    FileSaved { globs: Vec<String> },
+    /// Diagnostics with at least `min_errors` errors were received for a file
+    /// matching one of the globs; no globs matches every file
+    DiagnosticsReceived {
+        #[serde(default = "default_min_errors")]
+        min_errors: usize,
+        #[serde(default)]
+        globs: Vec<String>,
+    },
+    /// A pull request was opened in an `owner/name` repository
+    PullRequestOpened { repository: String },
+    /// The named hook of the webhook listener was called; see
+    /// [`WebhookListener`](crate::plugin_api::WebhookListener)
+    Webhook { hook: String },
+}
+
+fn default_min_errors() -> usize {
+    1
+}
+
+impl AutomationTrigger {
+    /// Check that the trigger's cron expression and globs are valid
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            AutomationTrigger::Schedule { cron } => {
+                CronSchedule::parse(cron)?;
+            }
+            AutomationTrigger::FileSaved { globs }
+            | AutomationTrigger::DiagnosticsReceived { globs, .. } => {
+                for glob in globs {
+                    Glob::new(glob)?;
+                }
+            }
+            AutomationTrigger::PullRequestOpened { repository } => {
+                if repository.split_once('/').is_none() {
+                    return Err(anyhow::anyhow!(
+                        "Repository '{}' must be given as owner/name",
+                        repository
+                    ));
+                }
+            }
+            AutomationTrigger::Webhook { hook } => {
+                if hook.is_empty()
+                    || !hook
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return Err(anyhow::anyhow!(
+                        "Webhook name '{}' may only contain letters, digits, \
+                         '-' and '_'",
+                        hook
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if an event starts an automation with this trigger
+    pub fn matches(&self, event: &AutomationEvent) -> bool {
+        match (self, event) {
+            (
+                AutomationTrigger::Schedule { cron },
+                AutomationEvent::Tick { time },
+            ) => CronSchedule::parse(cron)
+                .is_ok_and(|schedule| schedule.matches(time)),
+            (
+                AutomationTrigger::FileSaved { globs },
+                AutomationEvent::FileSaved { path },
+            ) => !globs.is_empty() && globs_match(globs, path),
+            (
+                AutomationTrigger::DiagnosticsReceived { min_errors, globs },
+                AutomationEvent::DiagnosticsReceived { path, errors },
+            ) => {
+                *errors >= (*min_errors).max(1)
+                    && (globs.is_empty() || globs_match(globs, path))
+            }
+            (
+                AutomationTrigger::PullRequestOpened { repository },
+                AutomationEvent::PullRequestOpened {
+                    repository: opened_in,
+                    ..
+                },
+            ) => repository.eq_ignore_ascii_case(opened_in),
+            (
+                AutomationTrigger::Webhook { hook },
+                AutomationEvent::Webhook { hook: called, .. },
+            ) => hook == called,
+            _ => false,
+        }
+    }
+}
+
+fn globs_match(globs: &[String], path: &Path) -> bool {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        match Glob::new(glob) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => tracing::warn!("Skipping automation glob: {}", err),
+        }
+    }
+    builder.build().is_ok_and(|set| set.is_match(path))
+}
+
+/// A tool call of a pipeline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineStep {
+    /// Qualified tool name, e.g. `git__status`
+    pub tool: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// What an automation does when triggered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationAction {
+    /// Run the agent in Agent mode with the tools of `servers`
+    AgentTask {
+        prompt: String,
+        #[serde(default)]
+        servers: BTreeSet<String>,
+    },
+    /// Call server tools in order, stopping at the first error
+    ToolPipeline { steps: Vec<PipelineStep> },
+}
+
+/// A trigger bound to an action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Automation {
+    pub id: u64,
+    pub name: String,
+    pub trigger: AutomationTrigger,
+    pub action: AutomationAction,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Runs of this automation allowed at once; further triggers are skipped
+    #[serde(default = "default_max_runs")]
+    pub max_concurrent_runs: usize,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_runs() -> usize {
+    1
+}
+
+/// Something that happened in the editor which may trigger automations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutomationEvent {
+    /// A minute passed, in local time
+    Tick {
+        time: NaiveDateTime,
+    },
+    /// A file was saved; the path is relative to the workspace root
+    FileSaved {
+        path: PathBuf,
+    },
+    DiagnosticsReceived {
+        path: PathBuf,
+        errors: usize,
+    },
+    PullRequestOpened {
+        repository: String,
+        number: u64,
+        title: String,
+        url: Option<String>,
+    },
+    /// A hook of the webhook listener was called with a JSON payload
+    Webhook {
+        hook: String,
+        payload: serde_json::Value,
+    },
+    /// The user ran the automation from the panel
+    Manual,
+}
+
+impl AutomationEvent {
+    /// Describe the event for the run history and the `{event}` placeholder
+    pub fn describe(&self) -> String {
+        match self {
+            AutomationEvent::Tick { time } => {
+                format!("Scheduled run at {}", time.format("%Y-%m-%d %H:%M"))
+            }
+            AutomationEvent::FileSaved { path } => {
+                format!("Saved {}", path.display())
+            }
+            AutomationEvent::DiagnosticsReceived { path, errors } => {
+                format!("{} error(s) reported in {}", errors, path.display())
+            }
+            AutomationEvent::PullRequestOpened {
+                repository,
+                number,
+                title,
+                url,
+            } => {
+                let mut description = format!(
+                    "Pull request {}#{} opened: {}",
+                    repository, number, title
+                );
+                if let Some(url) = url {
+                    description.push_str(&format!(" ({})", url));
+                }
+                description
+            }
+            AutomationEvent::Webhook { hook, payload } => {
+                let mut payload = payload.to_string();
+                if payload.len() > MAX_DESCRIBED_PAYLOAD {
+                    let mut end = MAX_DESCRIBED_PAYLOAD;
+                    while !payload.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    payload.truncate(end);
+                    payload.push('…');
+                }
+                format!("Webhook '{}' called with {}", hook, payload)
+            }
+            AutomationEvent::Manual => "Started manually".to_string(),
+        }
+    }
+}
+
+/// Outcome of an automation run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "message", rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded(String),
+    Failed(String),
+    /// Not started because a concurrency limit was reached
+    Skipped(String),
+}
+
+/// An entry of the run history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutomationRun {
+    pub id: u64,
+    pub automation_id: u64,
+    /// Description of the triggering event
+    pub trigger: String,
+    pub status: RunStatus,
+    pub started_at: SystemTime,
+    #[serde(default)]
+    pub finished_at: Option<SystemTime>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AutomationFile {
+    next_id: u64,
+    next_run_id: u64,
+    automations: Vec<Automation>,
+    runs: Vec<AutomationRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum AutomationOp {
+    /// Add a new automation or replace the one with the same id
+    Put(Automation),
+    Delete(u64),
+    /// Add a new run or replace the run with the same id
+    PutRun(AutomationRun),
+}
+
+impl JournalState for AutomationFile {
+    type Op = AutomationOp;
+
+    fn apply(&mut self, op: &AutomationOp) {
+        match op {
+            AutomationOp::Put(automation) => {
+                self.next_id = self.next_id.max(automation.id);
+                match self.automations.iter_mut().find(|a| a.id == automation.id) {
+                    Some(existing) => *existing = automation.clone(),
+                    None => self.automations.push(automation.clone()),
+                }
+            }
+            AutomationOp::Delete(id) => {
+                self.automations.retain(|automation| automation.id != *id)
+            }
+            AutomationOp::PutRun(run) => {
+                self.next_run_id = self.next_run_id.max(run.id);
+                match self.runs.iter_mut().find(|r| r.id == run.id) {
+                    Some(existing) => *existing = run.clone(),
+                    None => self.runs.push(run.clone()),
+                }
+                if self.runs.len() > MAX_RUN_HISTORY {
+                    let excess = self.runs.len() - MAX_RUN_HISTORY;
+                    self.runs.drain(..excess);
+                }
+            }
+        }
+    }
+}
+
+/// Per-workspace store of automations and their run history
+pub struct AutomationStore {
+    inner: JournaledStore<AutomationFile>,
+}
+
+impl AutomationStore {
+    /// Open the automations of a workspace, kept in the local data directory
+    pub fn open_for_workspace(workspace_root: &Path) -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?
+            .join("automations");
+        Self::open(&dir.join(format!("{}.json", workspace_key(workspace_root))))
+    }
+
+    /// Open an automation store backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            inner: JournaledStore::open(path, JournalOptions::default())?,
+        })
+    }
+
+    /// Add an automation, assigning it a new id
+    pub fn add(&self, mut automation: Automation) -> Result<Automation> {
+        automation.trigger.validate()?;
+        self.inner.transact(|inner| {
+            automation.id = inner.next_id + 1;
+            tracing::info!(
+                "Added automation #{} '{}'",
+                automation.id,
+                automation.name
+            );
+            Ok((Some(AutomationOp::Put(automation.clone())), automation))
+        })
+    }
+
+    /// Replace an existing automation
+    pub fn update(&self, automation: Automation) -> Result<Automation> {
+        automation.trigger.validate()?;
+        self.inner.transact(|inner| {
+            if !inner.automations.iter().any(|a| a.id == automation.id) {
+                return Err(anyhow::anyhow!(
+                    "Automation #{} does not exist",
+                    automation.id
+                ));
+            }
+            Ok((Some(AutomationOp::Put(automation.clone())), automation))
+        })
+    }
+
+    pub fn set_enabled(&self, id: u64, enabled: bool) -> Result<Automation> {
+        let mut automation = self
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Automation #{} does not exist", id))?;
+        automation.enabled = enabled;
+        self.update(automation)
+    }
+
+    /// Delete an automation; its runs stay in the history
+    pub fn delete(&self, id: u64) -> Result<()> {
+        self.inner.transact(|inner| {
+            if !inner.automations.iter().any(|a| a.id == id) {
+                return Err(anyhow::anyhow!("Automation #{} does not exist", id));
+            }
+            Ok((Some(AutomationOp::Delete(id)), ()))
+        })
+    }
+
+    pub fn get(&self, id: u64) -> Option<Automation> {
+        self.inner
+            .read()
+            .automations
+            .iter()
+            .find(|automation| automation.id == id)
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<Automation> {
+        self.inner.read().automations.clone()
+    }
+
+    /// Get the most recent runs, newest first, optionally of one automation
+    pub fn history(
+        &self,
+        automation_id: Option<u64>,
+        limit: usize,
+    ) -> Vec<AutomationRun> {
+        self.inner
+            .read()
+            .runs
+            .iter()
+            .rev()
+            .filter(|run| automation_id.is_none_or(|id| run.automation_id == id))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    fn start_run(
+        &self,
+        automation_id: u64,
+        trigger: String,
+        status: RunStatus,
+    ) -> Result<AutomationRun> {
+        self.inner.transact(|inner| {
+            let now = current_time();
+            let run = AutomationRun {
+                id: inner.next_run_id + 1,
+                automation_id,
+                trigger,
+                finished_at: (status != RunStatus::Running).then_some(now),
+                status,
+                started_at: now,
+            };
+            Ok((Some(AutomationOp::PutRun(run.clone())), run))
+        })
+    }
+
+    fn finish_run(
+        &self,
+        mut run: AutomationRun,
+        status: RunStatus,
+    ) -> Result<AutomationRun> {
+        run.status = status;
+        run.finished_at = Some(current_time());
+        self.inner.apply(AutomationOp::PutRun(run.clone()))?;
+        Ok(run)
+    }
+}
+
+/// Stable file name for a workspace root
+fn workspace_key(workspace_root: &Path) -> String {
+    let root = workspace_root
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_root.to_path_buf());
+    format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()))
+}
+
+/// Carries out the action of a triggered automation
+///
+/// Implemented by the agent loop, which runs agent tasks in Agent mode with
+/// the automation's servers allowed. Returns a short summary of the outcome
+/// for the run history.
+pub trait AutomationExecutor: Send + Sync + 'static {
+    fn execute(
+        &self,
+        automation: &Automation,
+        event: &AutomationEvent,
+    ) -> Result<String>;
+}
+
+/// Replace the `{event}` placeholder in a prompt or argument string
+pub fn render_event(template: &str, event: &AutomationEvent) -> String {
+    template.replace(EVENT_PLACEHOLDER, &event.describe())
+}
+
+/// Run the steps of a tool pipeline against MCP servers
+///
+/// String arguments have their `{event}` placeholder replaced. The summary
+/// lists the steps that ran; the first failing step fails the pipeline.
+pub fn run_tool_pipeline(
+    steps: &[PipelineStep],
+    event: &AutomationEvent,
+    registry: &McpServerRegistry,
+    tracker: &DegradationTracker,
+) -> Result<String> {
+    let mut summary = Vec::new();
+    for step in steps {
+        let ToolTarget::Server {
+            server_id,
+            tool_name,
+        } = ToolTarget::parse(&step.tool)
+        else {
+            return Err(anyhow::anyhow!(
+                "Pipeline step '{}' is not a server tool",
+                step.tool
+            ));
+        };
+        let arguments = render_arguments(&step.arguments, event);
+        let result =
+            registry.call_tool_degraded(&server_id, &tool_name, arguments, tracker);
+        if result.is_error {
+            return Err(anyhow::anyhow!(
+                "Pipeline step '{}' failed: {}",
+                step.tool,
+                result_text(&result)
+            ));
+        }
+        summary.push(format!("{} succeeded", step.tool));
+    }
+    Ok(summary.join("\n"))
+}
+
+fn render_arguments(
+    arguments: &serde_json::Value,
+    event: &AutomationEvent,
+) -> serde_json::Value {
+    match arguments {
+        serde_json::Value::String(text) => {
+            serde_json::Value::String(render_event(text, event))
+        }
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| render_arguments(item, event))
+            .collect(),
+        serde_json::Value::Object(fields) => serde_json::Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), render_arguments(value, event)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn result_text(result: &McpToolResult) -> String {
+    result
+        .content
+        .iter()
+        .map(|content| match &content.data {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Default)]
+struct RunningCounts {
+    total: usize,
+    per_automation: HashMap<u64, usize>,
+}
+
+/// Starts automations for editor events within the concurrency limits
+pub struct AutomationEngine {
+    store: Arc<AutomationStore>,
+    executor: Arc<dyn AutomationExecutor>,
+    /// Automation runs allowed at once across all automations
+    max_concurrent_runs: usize,
+    running: Arc<Mutex<RunningCounts>>,
+    /// Minute of the last tick, so a schedule fires once per minute
+    last_tick: Mutex<Option<NaiveDateTime>>,
+    /// Skips triggered runs while on
+    focus: FocusMode,
+}
+
+impl AutomationEngine {
+    pub fn new(
+        store: Arc<AutomationStore>,
+        executor: Arc<dyn AutomationExecutor>,
+        max_concurrent_runs: usize,
+    ) -> Self {
+        Self {
+            store,
+            executor,
+            max_concurrent_runs: max_concurrent_runs.max(1),
+            running: Arc::new(Mutex::new(RunningCounts::default())),
+            last_tick: Mutex::new(None),
+            focus: FocusMode::global().clone(),
+        }
+    }
+
+    /// Skip triggered runs while `focus` is on instead of while the
+    /// application's focus mode is
+    pub fn with_focus_mode(mut self, focus: FocusMode) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    pub fn store(&self) -> &Arc<AutomationStore> {
+        &self.store
+    }
+
+    /// Get the number of runs in progress
+    pub fn running_count(&self) -> usize {
+        self.running.lock().total
+    }
+
+    /// Start the enabled automations triggered by an event
+    ///
+    /// Returns a handle per started run; triggers over a concurrency limit
+    /// are recorded as skipped runs.
+    pub fn handle_event(
+        &self,
+        event: &AutomationEvent,
+    ) -> Result<Vec<JobHandle<AutomationRun>>> {
+        let mut handles = Vec::new();
+        for automation in self.store.list() {
+            if automation.enabled && automation.trigger.matches(event) {
+                if let Some(handle) = self.start(automation, event.clone())? {
+                    handles.push(handle);
+                }
+            }
+        }
+        Ok(handles)
+    }
+
+    /// Fire the schedules due at `time`; ticks within the same minute as the
+    /// previous one are ignored
+    pub fn tick(
+        &self,
+        time: NaiveDateTime,
+    ) -> Result<Vec<JobHandle<AutomationRun>>> {
+        let minute = time
+            .with_second(0)
+            .and_then(|time| time.with_nanosecond(0))
+            .unwrap_or(time);
+        {
+            let mut last_tick = self.last_tick.lock();
+            if last_tick.is_some_and(|last| last >= minute) {
+                return Ok(Vec::new());
+            }
+            *last_tick = Some(minute);
+        }
+        self.handle_event(&AutomationEvent::Tick { time: minute })
+    }
+
+    /// Run an automation now, even if it is disabled
+    pub fn run_now(&self, id: u64) -> Result<Option<JobHandle<AutomationRun>>> {
+        let automation = self
+            .store
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Automation #{} does not exist", id))?;
+        self.start(automation, AutomationEvent::Manual)
+    }
+
+    fn start(
+        &self,
+        automation: Automation,
+        event: AutomationEvent,
+    ) -> Result<Option<JobHandle<AutomationRun>>> {
+        let trigger = event.describe();
+        let skipped = if event != AutomationEvent::Manual && self.focus.is_active() {
+            Some("Focus mode is on".to_string())
+        } else {
+            let mut running = self.running.lock();
+            let count = running.per_automation.get(&automation.id).copied();
+            if running.total >= self.max_concurrent_runs {
+                Some("Too many automations are running".to_string())
+            } else if count.unwrap_or(0) >= automation.max_concurrent_runs.max(1) {
+                Some("The previous run has not finished".to_string())
+            } else {
+                running.total += 1;
+                *running.per_automation.entry(automation.id).or_default() += 1;
+                None
+            }
+        };
+        if let Some(reason) = skipped {
+            tracing::info!(
+                "Skipped automation #{} '{}': {}",
+                automation.id,
+                automation.name,
+                reason
+            );
+            self.store.start_run(
+                automation.id,
+                trigger,
+                RunStatus::Skipped(reason),
+            )?;
+            return Ok(None);
+        }
+
+        let run =
+            match self
+                .store
+                .start_run(automation.id, trigger, RunStatus::Running)
+            {
+                Ok(run) => run,
+                Err(err) => {
+                    release(&self.running, automation.id);
+                    return Err(err);
+                }
+            };
+        let store = self.store.clone();
+        let executor = self.executor.clone();
+        let running = self.running.clone();
+        Ok(Some(JobScheduler::global().spawn_blocking(move || {
+            let status = match executor.execute(&automation, &event) {
+                Ok(summary) => RunStatus::Succeeded(summary),
+                Err(err) => {
+                    tracing::warn!(
+                        "Automation #{} '{}' failed: {}",
+                        automation.id,
+                        automation.name,
+                        err
+                    );
+                    RunStatus::Failed(err.to_string())
+                }
+            };
+            release(&running, automation.id);
+            store.finish_run(run, status)
+        })))
+    }
+}
+
+fn release(running: &Mutex<RunningCounts>, automation_id: u64) {
+    let mut running = running.lock();
+    running.total = running.total.saturating_sub(1);
+    if let Some(count) = running.per_automation.get_mut(&automation_id) {
+        *count -= 1;
+        if *count == 0 {
+            running.per_automation.remove(&automation_id);
+        }
+    }
+}
+
+/// Finds newly opened pull requests by polling the github MCP server
+///
+/// The first poll of a repository only records the open pull requests, so
+/// existing ones don't trigger automations when the editor starts.
+#[derive(Default)]
+pub struct PullRequestPoller {
+    seen: HashMap<String, HashSet<u64>>,
+}
+
+impl PullRequestPoller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Poll the repositories watched by pull request triggers, unless
+    /// focus mode is on
+    pub fn poll_automations(
+        &mut self,
+        store: &AutomationStore,
+        registry: &McpServerRegistry,
+        tracker: &DegradationTracker,
+    ) -> Vec<AutomationEvent> {
+        if FocusMode::global().is_active() {
+            return Vec::new();
+        }
+        let repositories: BTreeSet<String> = store
+            .list()
+            .into_iter()
+            .filter(|automation| automation.enabled)
+            .filter_map(|automation| match automation.trigger {
+                AutomationTrigger::PullRequestOpened { repository } => {
+                    Some(repository.to_lowercase())
+                }
+                _ => None,
+            })
+            .collect();
+        repositories
+            .iter()
+            .flat_map(|repository| {
+                self.poll(repository, registry, tracker)
+                    .unwrap_or_else(|err| {
+                        tracing::warn!(
+                            "Failed to poll pull requests of {}: {}",
+                            repository,
+                            err
+                        );
+                        Vec::new()
+                    })
+            })
+            .collect()
+    }
+
+    /// Get the pull requests opened in a repository since the last poll
+    pub fn poll(
+        &mut self,
+        repository: &str,
+        registry: &McpServerRegistry,
+        tracker: &DegradationTracker,
+    ) -> Result<Vec<AutomationEvent>> {
+        let (owner, name) = repository.split_once('/').ok_or_else(|| {
+            anyhow::anyhow!(
+                "Repository '{}' must be given as owner/name",
+                repository
+            )
+        })?;
+        let result = registry.call_tool_degraded(
+            GITHUB_SERVER_ID,
+            "list_pull_requests",
+            serde_json::json!({ "owner": owner, "repo": name, "state": "open" }),
+            tracker,
+        );
+        if result.is_error {
+            return Err(anyhow::anyhow!("{}", result_text(&result)));
+        }
+        Ok(self.record(repository, parse_pull_requests(&result)))
+    }
+
+    fn record(
+        &mut self,
+        repository: &str,
+        open: Vec<(u64, String, Option<String>)>,
+    ) -> Vec<AutomationEvent> {
+        let first_poll = !self.seen.contains_key(repository);
+        let seen = self.seen.entry(repository.to_string()).or_default();
+        open.into_iter()
+            .filter(|(number, _, _)| seen.insert(*number) && !first_poll)
+            .map(|(number, title, url)| AutomationEvent::PullRequestOpened {
+                repository: repository.to_string(),
+                number,
+                title,
+                url,
+            })
+            .collect()
+    }
+}
+
+/// Read `(number, title, url)` of the pull requests listed in a tool result,
+/// given either as JSON content or as JSON text
+fn parse_pull_requests(
+    result: &McpToolResult,
+) -> Vec<(u64, String, Option<String>)> {
+    result
+        .content
+        .iter()
+        .filter_map(|content| match &content.data {
+            serde_json::Value::String(text) => serde_json::from_str(text).ok(),
+            other => Some(other.clone()),
+        })
+        .filter_map(|value| match value {
+            serde_json::Value::Array(items) => Some(items),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|item| {
+            let number = item.get("number")?.as_u64()?;
+            let title = item.get("title")?.as_str()?.to_string();
+            let url = item
+                .get("html_url")
+                .and_then(|url| url.as_str())
+                .map(|url| url.to_string());
+            Some((number, title, url))
+        })
+        .collect()
+}
+
+/// Sidebar panel listing automations with their recent runs
+pub struct AutomationPanel {
+    engine: Arc<AutomationEngine>,
+}
+
+impl AutomationPanel {
+    pub const ID: &'static str = "catalyst.automations";
+
+    pub fn new(engine: Arc<AutomationEngine>) -> Self {
+        Self { engine }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        let params = &command.parameters;
+        let store = self.engine.store();
+        let id = || {
+            params
+                .get("id")
+                .and_then(|id| id.as_u64())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'id' parameter"))
+        };
+
+        match command.command_id.as_str() {
+            "list" => Ok(serde_json::to_value(store.list())?),
+            "history" => {
+                let limit = params
+                    .get("limit")
+                    .and_then(|limit| limit.as_u64())
+                    .unwrap_or(50) as usize;
+                let automation_id = params.get("id").and_then(|id| id.as_u64());
+                Ok(serde_json::to_value(store.history(automation_id, limit))?)
+            }
+            "add" => {
+                let automation =
+                    params.get("automation").cloned().ok_or_else(|| {
+                        anyhow::anyhow!("Missing 'automation' parameter")
+                    })?;
+                Ok(serde_json::to_value(
+                    store.add(serde_json::from_value(automation)?)?,
+                )?)
+            }
+            "enable" => {
+                let enabled = params
+                    .get("enabled")
+                    .and_then(|enabled| enabled.as_bool())
+                    .unwrap_or(true);
+                Ok(serde_json::to_value(store.set_enabled(id()?, enabled)?)?)
+            }
+            "delete" => {
+                store.delete(id()?)?;
+                Ok(serde_json::Value::Null)
+            }
+            "run" => {
+                let started = self.engine.run_now(id()?)?.is_some();
+                Ok(serde_json::json!({ "started": started }))
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown automation panel command '{}'",
+                other
+            )),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for AutomationPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Automations".to_string(),
+            description: "Agent tasks and tool pipelines run on schedules and \
+                          editor events"
+                .to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn floem::View> {
+        let store = self.engine.store().clone();
+        text_panel_view(move || {
+            let automations = store.list();
+            if automations.is_empty() {
+                return "No automations defined for this workspace yet.".to_string();
+            }
+            automations
+                .iter()
+                .map(|automation| {
+                    let last_run = store
+                        .history(Some(automation.id), 1)
+                        .into_iter()
+                        .next()
+                        .map(|run| match run.status {
+                            RunStatus::Running => "running".to_string(),
+                            RunStatus::Succeeded(_) => "succeeded".to_string(),
+                            RunStatus::Failed(err) => format!("failed: {}", err),
+                            RunStatus::Skipped(reason) => {
+                                format!("skipped: {}", reason)
+                            }
+                        })
+                        .unwrap_or_else(|| "never run".to_string());
+                    format!(
+                        "#{} [{}] {} ({})",
+                        automation.id,
+                        if automation.enabled { "on" } else { "off" },
+                        automation.name,
+                        last_run
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.engine.store().list()).unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::Receiver;
+
+    struct BlockingExecutor {
+        release: Receiver<()>,
+    }
+
+    impl AutomationExecutor for BlockingExecutor {
+        fn execute(
+            &self,
+            automation: &Automation,
+            event: &AutomationEvent,
+        ) -> Result<String> {
+            self.release.recv()?;
+            match &automation.action {
+                AutomationAction::AgentTask { prompt, .. } => {
+                    Ok(render_event(prompt, event))
+                }
+                AutomationAction::ToolPipeline { .. } => {
+                    Err(anyhow::anyhow!("no servers"))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_triggers_runs_within_limits() {
+        let schedule = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        let monday = NaiveDateTime::parse_from_str(
+            "2024-06-03 09:30:00",
+            "%Y-%m-%d %H:%M:%S",
+        )
+        .unwrap();
+        assert!(schedule.matches(&monday));
+        assert!(!schedule.matches(&(monday + chrono::Duration::days(5))));
+        assert!(CronSchedule::parse("61 * * * *").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(
+            AutomationStore::open(&dir.path().join("automations.json")).unwrap(),
+        );
+        let lint = store
+            .add(Automation {
+                id: 0,
+                name: "Explain errors".to_string(),
+                trigger: AutomationTrigger::DiagnosticsReceived {
+                    min_errors: 1,
+                    globs: vec!["src/**/*.rs".to_string()],
+                },
+                action: AutomationAction::AgentTask {
+                    prompt: "Fix: {event}".to_string(),
+                    servers: BTreeSet::new(),
+                },
+                enabled: true,
+                max_concurrent_runs: 1,
+            })
+            .unwrap();
+
+        let (release, receiver) = crossbeam_channel::unbounded();
+        let focus = FocusMode::new();
+        let engine = AutomationEngine::new(
+            store.clone(),
+            Arc::new(BlockingExecutor { release: receiver }),
+            DEFAULT_MAX_CONCURRENT_RUNS,
+        )
+        .with_focus_mode(focus.clone());
+        let event = AutomationEvent::DiagnosticsReceived {
+            path: PathBuf::from("src/main.rs"),
+            errors: 2,
+        };
+        let handles = engine.handle_event(&event).unwrap();
+        assert_eq!(handles.len(), 1);
+        // The first run is still going, so the second trigger is skipped
+        assert!(engine.handle_event(&event).unwrap().is_empty());
+        let other_file = AutomationEvent::DiagnosticsReceived {
+            path: PathBuf::from("docs/index.md"),
+            errors: 2,
+        };
+        assert!(engine.handle_event(&other_file).unwrap().is_empty());
+
+        release.send(()).unwrap();
+        let run = handles.into_iter().next().unwrap().join().unwrap();
+        assert_eq!(
+            run.status,
+            RunStatus::Succeeded(
+                "Fix: 2 error(s) reported in src/main.rs".to_string()
+            )
+        );
+        assert_eq!(engine.running_count(), 0);
+
+        focus.start(std::time::Duration::from_secs(3600));
+        assert!(engine.handle_event(&event).unwrap().is_empty());
+        focus.stop();
+
+        store.set_enabled(lint.id, false).unwrap();
+        assert!(engine.handle_event(&event).unwrap().is_empty());
+
+        let history = store.history(Some(lint.id), 10);
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history[0].status,
+            RunStatus::Skipped("Focus mode is on".to_string())
+        );
+
+        let mut poller = PullRequestPoller::new();
+        assert!(
+            poller
+                .record("acme/app", vec![(1, "Old".into(), None)])
+                .is_empty()
+        );
+        let opened = poller.record(
+            "acme/app",
+            vec![(1, "Old".into(), None), (2, "New".into(), None)],
+        );
+        assert_eq!(opened.len(), 1);
+    }
+}
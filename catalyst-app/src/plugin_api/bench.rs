@@ -0,0 +1,445 @@
+//! Benchmark Baselines
+//!
+//! This module backs `catalyst bench`, which times in-process benchmarks of
+//! the indexing and retrieval code and compares their 95th percentile with a
+//! baseline recorded earlier on the same kind of machine. Absolute time
+//! limits fail on every slow CI runner; a baseline per machine fingerprint
+//! (CPU model, core count, OS and architecture) only fails when the code
+//! itself got slower than the allowed percentage.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::plugin_api::{
+    ContextChunk, IndexKind, Report, ReportSection, TrigramIndex, chunk_text,
+    current_time, read_index, write_atomic, write_index,
+};
+
+/// Allowed p95 slowdown against the baseline unless configured otherwise
+pub const DEFAULT_MAX_REGRESSION_PERCENT: f64 = 10.0;
+/// Timed runs of each benchmark unless configured otherwise
+pub const DEFAULT_BENCH_SAMPLES: usize = 30;
+/// Slowdowns smaller than this are timer noise, whatever their percentage
+const NOISE_FLOOR: Duration = Duration::from_micros(50);
+/// Lines in the generated source file the benchmarks work on
+const BENCH_SOURCE_LINES: usize = 20_000;
+
+/// Kind of machine a baseline was recorded on
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MachineFingerprint {
+    pub cpu: String,
+    pub cores: usize,
+    pub os: String,
+    pub arch: String,
+}
+
+impl MachineFingerprint {
+    /// Get the fingerprint of this machine
+    pub fn current() -> Self {
+        Self {
+            cpu: cpu_model(),
+            cores: std::thread::available_parallelism()
+                .map(|cores| cores.get())
+                .unwrap_or(1),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// Get a name for the fingerprint that is safe as a file name
+    pub fn key(&self) -> String {
+        let raw = format!("{}-{}c-{}-{}", self.cpu, self.cores, self.os, self.arch);
+        let mut key = String::new();
+        for c in raw.to_lowercase().chars() {
+            if c.is_ascii_alphanumeric() {
+                key.push(c);
+            } else if !key.ends_with('-') {
+                key.push('-');
+            }
+        }
+        key.trim_matches('-').to_string()
+    }
+}
+
+fn cpu_model() -> String {
+    #[cfg(target_os = "linux")]
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        let model = cpuinfo.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == "model name").then(|| value.trim().to_string())
+        });
+        if let Some(model) = model {
+            return model;
+        }
+    }
+    #[cfg(target_os = "macos")]
+    if let Ok(output) = std::process::Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+    {
+        let model = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !model.is_empty() {
+            return model;
+        }
+    }
+    "unknown cpu".to_string()
+}
+
+/// Timings of one benchmark
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub name: String,
+    pub samples: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+}
+
+impl BenchResult {
+    pub fn from_samples(name: &str, mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let percentile = |p: f64| {
+            samples
+                .get(((samples.len().saturating_sub(1)) as f64 * p).round() as usize)
+                .copied()
+                .unwrap_or_default()
+        };
+        Self {
+            name: name.to_string(),
+            samples: samples.len(),
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+        }
+    }
+}
+
+/// A piece of work timed by `catalyst bench`
+pub struct Benchmark {
+    pub name: &'static str,
+    run: Box<dyn FnMut() -> Result<()>>,
+}
+
+impl Benchmark {
+    pub fn new(
+        name: &'static str,
+        run: impl FnMut() -> Result<()> + 'static,
+    ) -> Self {
+        Self {
+            name,
+            run: Box::new(run),
+        }
+    }
+
+    /// Time the benchmark after one warm-up run
+    pub fn measure(&mut self, samples: usize) -> Result<BenchResult> {
+        (self.run)()?;
+        let mut timings = Vec::with_capacity(samples);
+        for _ in 0..samples.max(1) {
+            let start = Instant::now();
+            (self.run)()?;
+            timings.push(start.elapsed());
+        }
+        Ok(BenchResult::from_samples(self.name, timings))
+    }
+}
+
+/// Get the benchmarks run by `catalyst bench`
+///
+/// `scratch` is a directory the benchmarks may write files to.
+pub fn builtin_benchmarks(scratch: &Path) -> Vec<Benchmark> {
+    let source: String = (0..BENCH_SOURCE_LINES)
+        .map(|i| {
+            format!("fn handler_{i}(request: &Request) -> Response {{ todo!() }}\n")
+        })
+        .collect();
+    let path = PathBuf::from("src/handlers.rs");
+    let chunks = chunk_text(&path, &source, 40, None);
+    let index = TrigramIndex::new();
+    index.insert_chunks(chunks.clone());
+    let index_path = scratch.join(IndexKind::FileIndex.file_name());
+
+    let chunk_source = source.clone();
+    let insert_chunks = chunks.clone();
+    vec![
+        Benchmark::new("chunk_text", move || {
+            chunk_text(&path, &chunk_source, 40, None);
+            Ok(())
+        }),
+        Benchmark::new("trigram_index_build", move || {
+            TrigramIndex::new().insert_chunks(insert_chunks.clone());
+            Ok(())
+        }),
+        Benchmark::new("trigram_search", move || {
+            index.search("handler_1234 request", 20);
+            Ok(())
+        }),
+        Benchmark::new("index_store_round_trip", move || {
+            write_index(&index_path, IndexKind::FileIndex, &chunks)?;
+            read_index::<Vec<ContextChunk>>(&index_path, IndexKind::FileIndex)
+                .loaded()
+                .ok_or_else(|| anyhow::anyhow!("Benchmark index did not load"))?;
+            Ok(())
+        }),
+    ]
+}
+
+/// Benchmark results recorded on one machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchBaseline {
+    pub machine: MachineFingerprint,
+    pub recorded_at: SystemTime,
+    pub results: BTreeMap<String, BenchResult>,
+}
+
+impl BenchBaseline {
+    pub fn new(machine: MachineFingerprint, results: &[BenchResult]) -> Self {
+        Self {
+            machine,
+            recorded_at: current_time(),
+            results: results
+                .iter()
+                .map(|result| (result.name.clone(), result.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Directory of baselines, one file per machine fingerprint
+pub struct BaselineStore {
+    dir: PathBuf,
+}
+
+impl BaselineStore {
+    /// Open the baselines kept in the local data directory
+    pub fn open_default() -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?;
+        Ok(Self::new(dir.join("bench-baselines")))
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, machine: &MachineFingerprint) -> PathBuf {
+        self.dir.join(format!("{}.json", machine.key()))
+    }
+
+    /// Get the baseline of a machine, if one was recorded
+    pub fn load(
+        &self,
+        machine: &MachineFingerprint,
+    ) -> Result<Option<BenchBaseline>> {
+        match std::fs::read(self.path(machine)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Record a baseline, replacing the previous one of its machine
+    pub fn save(&self, baseline: &BenchBaseline) -> Result<PathBuf> {
+        let path = self.path(&baseline.machine);
+        write_atomic(&path, &serde_json::to_vec_pretty(baseline)?)?;
+        Ok(path)
+    }
+}
+
+/// Change of one benchmark against the baseline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchDelta {
+    pub name: String,
+    pub current_p95: Duration,
+    /// None for benchmarks added since the baseline was recorded
+    pub baseline_p95: Option<Duration>,
+    pub change_percent: Option<f64>,
+    pub regressed: bool,
+}
+
+/// Benchmark results compared with a baseline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchComparison {
+    pub machine: MachineFingerprint,
+    pub max_regression_percent: f64,
+    pub deltas: Vec<BenchDelta>,
+}
+
+impl BenchComparison {
+    /// Compare results with a baseline, flagging p95 slowdowns beyond
+    /// `max_regression_percent`
+    pub fn new(
+        machine: MachineFingerprint,
+        baseline: Option<&BenchBaseline>,
+        current: &[BenchResult],
+        max_regression_percent: f64,
+    ) -> Self {
+        let deltas = current
+            .iter()
+            .map(|result| {
+                let baseline_p95 = baseline
+                    .and_then(|baseline| baseline.results.get(&result.name))
+                    .map(|baseline| baseline.p95);
+                let change_percent = baseline_p95
+                    .filter(|baseline| !baseline.is_zero())
+                    .map(|baseline| {
+                        (result.p95.as_secs_f64() / baseline.as_secs_f64() - 1.0)
+                            * 100.0
+                    });
+                let regressed = match (baseline_p95, change_percent) {
+                    (Some(baseline), Some(change)) => {
+                        change > max_regression_percent
+                            && result.p95.saturating_sub(baseline) > NOISE_FLOOR
+                    }
+                    _ => false,
+                };
+                BenchDelta {
+                    name: result.name.clone(),
+                    current_p95: result.p95,
+                    baseline_p95,
+                    change_percent,
+                    regressed,
+                }
+            })
+            .collect();
+        Self {
+            machine,
+            max_regression_percent,
+            deltas,
+        }
+    }
+
+    pub fn regressions(&self) -> impl Iterator<Item = &BenchDelta> {
+        self.deltas.iter().filter(|delta| delta.regressed)
+    }
+
+    /// Fail when a benchmark regressed, or when none had a baseline to
+    /// compare with, as on a machine whose baseline was never recorded
+    pub fn check(&self) -> Result<()> {
+        if self.deltas.iter().all(|delta| delta.baseline_p95.is_none()) {
+            return Err(anyhow::anyhow!(
+                "No baseline for this machine to check against, record one \
+                 with --save"
+            ));
+        }
+        let regressions: Vec<&str> = self
+            .regressions()
+            .map(|delta| delta.name.as_str())
+            .collect();
+        if !regressions.is_empty() {
+            return Err(anyhow::anyhow!(
+                "p95 regressed by more than {}% in {}",
+                self.max_regression_percent,
+                regressions.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Report for BenchComparison {
+    fn title(&self) -> String {
+        "Benchmark report".to_string()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        Some(format!(
+            "{}, {} cores; p95 may regress by {}%",
+            self.machine.cpu, self.machine.cores, self.max_regression_percent
+        ))
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        vec![self.deltas.iter().fold(
+            ReportSection::new("p95 against baseline"),
+            |section, delta| {
+                let current =
+                    format!("{:.3}ms", delta.current_p95.as_secs_f64() * 1e3);
+                let value = match (delta.baseline_p95, delta.change_percent) {
+                    (Some(baseline), Some(change)) => format!(
+                        "{} vs {:.3}ms ({:+.1}%){}",
+                        current,
+                        baseline.as_secs_f64() * 1e3,
+                        change,
+                        if delta.regressed { " REGRESSED" } else { "" }
+                    ),
+                    _ => format!("{} (no baseline)", current),
+                };
+                section.row(&delta.name, value)
+            },
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, p95_ms: u64) -> BenchResult {
+        BenchResult {
+            name: name.to_string(),
+            samples: 10,
+            p50: Duration::from_millis(p95_ms / 2),
+            p95: Duration::from_millis(p95_ms),
+        }
+    }
+
+    #[test]
+    fn test_bench_baselines() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let measured = BenchResult::from_samples("sorted", samples);
+        assert_eq!(measured.p50, Duration::from_millis(51));
+        assert_eq!(measured.p95, Duration::from_millis(95));
+
+        let machine = MachineFingerprint {
+            cpu: "Intel(R) Xeon(R) CPU @ 2.20GHz".to_string(),
+            cores: 4,
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+        };
+        assert_eq!(machine.key(), "intel-r-xeon-r-cpu-2-20ghz-4c-linux-x86-64");
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = BaselineStore::new(dir.path().to_path_buf());
+        assert!(store.load(&machine).unwrap().is_none());
+        let baseline = BenchBaseline::new(
+            machine.clone(),
+            &[result("a", 100), result("b", 10)],
+        );
+        store.save(&baseline).unwrap();
+        let baseline = store.load(&machine).unwrap().unwrap();
+
+        let current = [result("a", 109), result("b", 20), result("c", 5)];
+        let comparison =
+            BenchComparison::new(machine.clone(), Some(&baseline), &current, 10.0);
+        let regressed: Vec<&str> = comparison
+            .regressions()
+            .map(|delta| delta.name.as_str())
+            .collect();
+        assert_eq!(regressed, ["b"]);
+        assert_eq!(comparison.deltas[2].baseline_p95, None);
+        assert!(comparison.check().is_err());
+
+        // The built-in suite runs
+        let mut benchmarks = builtin_benchmarks(dir.path());
+        assert!(!benchmarks.is_empty());
+        for benchmark in &mut benchmarks {
+            assert_eq!(benchmark.measure(1).unwrap().samples, 1);
+        }
+    }
+
+    #[test]
+    fn test_check_requires_a_baseline() {
+        let machine = MachineFingerprint::current();
+        let current = [result("a", 100)];
+        let unrecorded = BenchComparison::new(machine.clone(), None, &current, 10.0);
+        assert!(unrecorded.check().is_err());
+
+        let baseline = BenchBaseline::new(machine.clone(), &[result("a", 95)]);
+        let comparison =
+            BenchComparison::new(machine, Some(&baseline), &current, 10.0);
+        comparison.check().unwrap();
+    }
+}
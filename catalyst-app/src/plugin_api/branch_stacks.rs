@@ -0,0 +1,739 @@
+//! Stacked Branches
+//!
+//! Agent-generated work reviews best as a stack of small branches, each
+//! building on the one below it. This module keeps track of such stacks:
+//! the parent of every stacked branch, and the commit of the parent it was
+//! last stacked on, are recorded in the repository's git config under
+//! `branch.<name>.catalyst-stack-parent` and `catalyst-stack-base`, so they
+//! survive restarts and stay with the clone.
+//!
+//! When a lower layer changes, e.g. after review feedback or after its
+//! parent moved on, the layers above it are restacked: their own commits
+//! are rebased in memory onto the new head of their parent, without
+//! touching the working tree unless the checked-out branch moves. A layer
+//! that doesn't apply cleanly stops the restack, leaving it and the layers
+//! above it as they were.
+//!
+//! Each layer can be opened as a pull request against its parent through
+//! the github MCP server, once its branch is pushed.
+
+use anyhow::{Result, anyhow};
+use floem::View;
+use git2::{
+    BranchType, ErrorCode, Oid, RebaseOptions, Repository, Status, StatusOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::plugin_api::ci_status::{result_json, result_text};
+use crate::plugin_api::{
+    DegradationTracker, GITHUB_SERVER_ID, McpServerRegistry, PanelCommand,
+    PanelCommandResult, SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition,
+    text_panel_view,
+};
+
+const PARENT_KEY: &str = "catalyst-stack-parent";
+const BASE_KEY: &str = "catalyst-stack-base";
+const PULL_REQUEST_KEY: &str = "catalyst-stack-pr";
+
+/// A branch of a stack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackLayer {
+    pub branch: String,
+    /// Branch the layer builds on, the layer below or the trunk
+    pub parent: String,
+    pub head: String,
+    /// Commits of the layer on top of its parent
+    pub commits: usize,
+    /// Whether the parent moved on since the layer was stacked on it
+    pub needs_restack: bool,
+    /// Number of the layer's pull request, once opened
+    pub pull_request: Option<u64>,
+}
+
+/// Stacked branches building on a trunk branch, such as `main`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchStack {
+    pub trunk: String,
+    /// From the bottom up; a layer always comes after its parent
+    pub layers: Vec<StackLayer>,
+}
+
+impl BranchStack {
+    pub fn contains(&self, branch: &str) -> bool {
+        self.layers.iter().any(|layer| layer.branch == branch)
+    }
+}
+
+/// Outcome of restacking a stack
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestackReport {
+    /// Layers rebased onto their parent
+    pub restacked: Vec<String>,
+    /// Layers that were already on top of their parent
+    pub up_to_date: Vec<String>,
+    /// Layer that didn't apply cleanly, with the layers above it left alone
+    pub conflict: Option<String>,
+}
+
+fn config_key(branch: &str, key: &str) -> String {
+    format!("branch.{}.{}", branch, key)
+}
+
+fn branch_head(repo: &Repository, branch: &str) -> Option<Oid> {
+    repo.find_branch(branch, BranchType::Local)
+        .ok()?
+        .get()
+        .target()
+}
+
+/// Get the branch checked out, if any
+pub fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    head.is_branch()
+        .then(|| head.shorthand().map(|name| name.to_string()))
+        .flatten()
+}
+
+/// Create a branch on top of `parent`, by default the checked-out branch,
+/// stack it on the parent and check it out
+pub fn create_stack_branch(
+    repo: &Repository,
+    name: &str,
+    parent: Option<&str>,
+) -> Result<StackLayer> {
+    let parent = match parent {
+        Some(parent) => parent.to_string(),
+        None => current_branch(repo)
+            .ok_or_else(|| anyhow!("Check out the branch to stack on first"))?,
+    };
+    let parent_head = branch_head(repo, &parent)
+        .ok_or_else(|| anyhow!("There is no branch '{}'", parent))?;
+    let branch = repo.branch(name, &repo.find_commit(parent_head)?, false)?;
+    let mut config = repo.config()?;
+    config.set_str(&config_key(name, PARENT_KEY), &parent)?;
+    config.set_str(&config_key(name, BASE_KEY), &parent_head.to_string())?;
+
+    let reference = branch
+        .get()
+        .name()
+        .ok_or_else(|| anyhow!("'{}' is not a valid branch name", name))?;
+    repo.set_head(reference)?;
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().safe()))?;
+    Ok(StackLayer {
+        branch: name.to_string(),
+        parent,
+        head: parent_head.to_string(),
+        commits: 0,
+        needs_restack: false,
+        pull_request: None,
+    })
+}
+
+/// Find the stacks of the repository, ordered by their bottom branch
+pub fn branch_stacks(repo: &Repository) -> Result<Vec<BranchStack>> {
+    let config = repo.config()?.snapshot()?;
+    let mut parents: BTreeMap<String, String> = BTreeMap::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let Some(name) = branch.name()? else {
+            continue;
+        };
+        if let Ok(parent) = config.get_string(&config_key(name, PARENT_KEY)) {
+            parents.insert(name.to_string(), parent);
+        }
+    }
+
+    let mut stacks = Vec::new();
+    for (bottom, trunk) in &parents {
+        if parents.contains_key(trunk) {
+            continue;
+        }
+        // Depth first, so every layer comes after its parent
+        let mut layers = Vec::new();
+        let mut pending = vec![bottom.clone()];
+        while let Some(branch) = pending.pop() {
+            let parent = &parents[&branch];
+            layers.push(stack_layer(repo, &config, &branch, parent)?);
+            pending.extend(
+                parents
+                    .iter()
+                    .rev()
+                    .filter(|(_, parent)| **parent == branch)
+                    .map(|(child, _)| child.clone()),
+            );
+        }
+        stacks.push(BranchStack {
+            trunk: trunk.clone(),
+            layers,
+        });
+    }
+    Ok(stacks)
+}
+
+fn stack_layer(
+    repo: &Repository,
+    config: &git2::Config,
+    branch: &str,
+    parent: &str,
+) -> Result<StackLayer> {
+    let head = branch_head(repo, branch)
+        .ok_or_else(|| anyhow!("There is no branch '{}'", branch))?;
+    let parent_head = branch_head(repo, parent);
+    let base = stack_base(repo, config, branch, head, parent_head);
+    let mut commits = 0;
+    if let Some(base) = base {
+        let mut walk = repo.revwalk()?;
+        walk.push(head)?;
+        walk.hide(base)?;
+        commits = walk.count();
+    }
+    Ok(StackLayer {
+        branch: branch.to_string(),
+        parent: parent.to_string(),
+        head: head.to_string(),
+        commits,
+        needs_restack: parent_head
+            .is_some_and(|parent_head| needs_restack(repo, head, parent_head)),
+        pull_request: config
+            .get_i64(&config_key(branch, PULL_REQUEST_KEY))
+            .ok()
+            .and_then(|number| u64::try_from(number).ok()),
+    })
+}
+
+/// Check if the parent of a layer moved on since the layer was stacked on
+/// it
+fn needs_restack(repo: &Repository, head: Oid, parent_head: Oid) -> bool {
+    parent_head != head
+        && !repo.graph_descendant_of(head, parent_head).unwrap_or(false)
+}
+
+/// Get the commit of the parent a layer was stacked on, falling back to
+/// where the two branches meet
+fn stack_base(
+    repo: &Repository,
+    config: &git2::Config,
+    branch: &str,
+    head: Oid,
+    parent_head: Option<Oid>,
+) -> Option<Oid> {
+    config
+        .get_string(&config_key(branch, BASE_KEY))
+        .ok()
+        .and_then(|base| Oid::from_str(&base).ok())
+        .filter(|base| {
+            *base == head || repo.graph_descendant_of(head, *base).unwrap_or(false)
+        })
+        .or_else(|| repo.merge_base(head, parent_head?).ok())
+}
+
+/// Rebase every layer of the stack containing `branch` onto the current
+/// head of its parent
+///
+/// The working tree must not have uncommitted changes, since the
+/// checked-out branch may move.
+pub fn restack(repo: &Repository, branch: &str) -> Result<RestackReport> {
+    let stack = branch_stacks(repo)?
+        .into_iter()
+        .find(|stack| stack.contains(branch))
+        .ok_or_else(|| anyhow!("'{}' is not part of a stack", branch))?;
+    if has_uncommitted_changes(repo)? {
+        return Err(anyhow!(
+            "Commit or stash the uncommitted changes before restacking"
+        ));
+    }
+    let signature = repo.signature().map_err(|_| {
+        anyhow!("Set user.name and user.email in the git config to restack")
+    })?;
+    let checked_out = current_branch(repo);
+    let mut config = repo.config()?;
+    let mut report = RestackReport::default();
+    for layer in &stack.layers {
+        let head = Oid::from_str(&layer.head)?;
+        let Some(parent_head) = branch_head(repo, &layer.parent) else {
+            report.up_to_date.push(layer.branch.clone());
+            continue;
+        };
+        let base_key = config_key(&layer.branch, BASE_KEY);
+        // Checked against the parent as it is now, since restacking the
+        // layer below moves it
+        if !needs_restack(repo, head, parent_head) {
+            config.set_str(&base_key, &parent_head.to_string())?;
+            report.up_to_date.push(layer.branch.clone());
+            continue;
+        }
+        let snapshot = config.snapshot()?;
+        let base =
+            stack_base(repo, &snapshot, &layer.branch, head, Some(parent_head))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "'{}' has no commit in common with '{}'",
+                        layer.branch,
+                        layer.parent
+                    )
+                })?;
+
+        let Some(new_head) = rebase_onto(repo, head, base, parent_head, &signature)?
+        else {
+            report.conflict = Some(layer.branch.clone());
+            break;
+        };
+        repo.reference(
+            &format!("refs/heads/{}", layer.branch),
+            new_head,
+            true,
+            &format!("restack: {} onto {}", layer.branch, layer.parent),
+        )?;
+        config.set_str(&base_key, &parent_head.to_string())?;
+        report.restacked.push(layer.branch.clone());
+    }
+
+    if checked_out
+        .as_ref()
+        .is_some_and(|branch| report.restacked.contains(branch))
+    {
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    }
+    Ok(report)
+}
+
+/// Replay the commits between `base` and `head` onto `onto` in memory,
+/// returning the new head, or `None` if a commit conflicts
+fn rebase_onto(
+    repo: &Repository,
+    head: Oid,
+    base: Oid,
+    onto: Oid,
+    signature: &git2::Signature,
+) -> Result<Option<Oid>> {
+    let head = repo.find_annotated_commit(head)?;
+    let base = repo.find_annotated_commit(base)?;
+    let onto_commit = repo.find_annotated_commit(onto)?;
+    let mut options = RebaseOptions::new();
+    options.inmemory(true);
+    let mut rebase = repo.rebase(
+        Some(&head),
+        Some(&base),
+        Some(&onto_commit),
+        Some(&mut options),
+    )?;
+    let mut new_head = onto;
+    while let Some(operation) = rebase.next() {
+        operation?;
+        if rebase.inmemory_index()?.has_conflicts() {
+            rebase.abort()?;
+            return Ok(None);
+        }
+        match rebase.commit(None, signature, None) {
+            Ok(oid) => new_head = oid,
+            // Already in the parent, as after a squash merge of it
+            Err(err) if err.code() == ErrorCode::Applied => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    rebase.finish(Some(signature))?;
+    Ok(Some(new_head))
+}
+
+fn has_uncommitted_changes(repo: &Repository) -> Result<bool> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+    Ok(repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .any(|entry| entry.status() != Status::CURRENT))
+}
+
+/// Pull request opened for a layer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackPullRequest {
+    pub branch: String,
+    pub number: u64,
+    pub url: Option<String>,
+}
+
+/// Manages the stacks of a workspace repository and their pull requests
+pub struct BranchStackService {
+    workdir: PathBuf,
+    registry: McpServerRegistry,
+    tracker: Arc<DegradationTracker>,
+    /// `owner/name` of the GitHub repository, if it is on GitHub
+    repository: Option<String>,
+}
+
+impl BranchStackService {
+    pub fn new(
+        workdir: PathBuf,
+        registry: McpServerRegistry,
+        tracker: Arc<DegradationTracker>,
+        repository: Option<String>,
+    ) -> Self {
+        Self {
+            workdir,
+            registry,
+            tracker,
+            repository,
+        }
+    }
+
+    fn open(&self) -> Result<Repository> {
+        Ok(Repository::discover(&self.workdir)?)
+    }
+
+    pub fn stacks(&self) -> Result<Vec<BranchStack>> {
+        branch_stacks(&self.open()?)
+    }
+
+    pub fn create(&self, name: &str, parent: Option<&str>) -> Result<StackLayer> {
+        create_stack_branch(&self.open()?, name, parent)
+    }
+
+    pub fn restack(&self, branch: &str) -> Result<RestackReport> {
+        restack(&self.open()?, branch)
+    }
+
+    /// Open a pull request against its parent for every layer of the stack
+    /// containing `branch` that has none yet
+    ///
+    /// The layers must be pushed first. Each description lists the layers
+    /// of the stack, so reviewers know what to review first.
+    pub fn open_pull_requests(&self, branch: &str) -> Result<Vec<StackPullRequest>> {
+        let (owner, name) = self
+            .repository
+            .as_deref()
+            .and_then(|repository| repository.split_once('/'))
+            .ok_or_else(|| anyhow!("The repository is not on GitHub"))?;
+        let repo = self.open()?;
+        let stack = branch_stacks(&repo)?
+            .into_iter()
+            .find(|stack| stack.contains(branch))
+            .ok_or_else(|| anyhow!("'{}' is not part of a stack", branch))?;
+        let mut config = repo.config()?;
+        let mut opened = Vec::new();
+        for (index, layer) in stack.layers.iter().enumerate() {
+            if layer.pull_request.is_some() {
+                continue;
+            }
+            let head = repo.find_commit(Oid::from_str(&layer.head)?)?;
+            let title = head.summary().unwrap_or(&layer.branch).to_string();
+            let result = self.registry.call_tool_degraded(
+                GITHUB_SERVER_ID,
+                "create_pull_request",
+                serde_json::json!({
+                    "owner": owner,
+                    "repo": name,
+                    "title": title,
+                    "body": stack_description(&stack, index),
+                    "head": layer.branch,
+                    "base": layer.parent,
+                }),
+                &self.tracker,
+            );
+            if result.is_error {
+                return Err(anyhow!(
+                    "Failed to open a pull request for '{}': {}",
+                    layer.branch,
+                    result_text(&result)
+                ));
+            }
+            let json = result_json(&result);
+            let number = json["number"].as_u64().ok_or_else(|| {
+                anyhow!("The pull request of '{}' has no number", layer.branch)
+            })?;
+            config.set_i64(
+                &config_key(&layer.branch, PULL_REQUEST_KEY),
+                number as i64,
+            )?;
+            opened.push(StackPullRequest {
+                branch: layer.branch.clone(),
+                number,
+                url: json["html_url"].as_str().map(|url| url.to_string()),
+            });
+        }
+        Ok(opened)
+    }
+}
+
+/// Describe a layer's place in its stack for its pull request
+fn stack_description(stack: &BranchStack, index: usize) -> String {
+    let mut description = format!(
+        "Part {} of {} of a stack on `{}`:\n\n",
+        index + 1,
+        stack.layers.len(),
+        stack.trunk
+    );
+    for (position, layer) in stack.layers.iter().enumerate() {
+        let marker = if position == index { " (this one)" } else { "" };
+        let pull_request = layer
+            .pull_request
+            .map(|number| format!(" #{}", number))
+            .unwrap_or_default();
+        description.push_str(&format!(
+            "{}. `{}`{}{}\n",
+            position + 1,
+            layer.branch,
+            pull_request,
+            marker
+        ));
+    }
+    description
+}
+
+/// Sidebar panel listing the stacked branches of the workspace
+pub struct BranchStackPanel {
+    service: Arc<BranchStackService>,
+}
+
+impl BranchStackPanel {
+    pub const ID: &'static str = "catalyst.branch_stacks";
+
+    pub fn new(service: Arc<BranchStackService>) -> Self {
+        Self { service }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        let text = |name: &str| {
+            command
+                .parameters
+                .get(name)
+                .and_then(|value| value.as_str())
+        };
+        let branch =
+            || text("branch").ok_or_else(|| anyhow!("Missing 'branch' parameter"));
+
+        match command.command_id.as_str() {
+            "stacks" => Ok(serde_json::to_value(self.service.stacks()?)?),
+            "create" => Ok(serde_json::to_value(
+                self.service.create(branch()?, text("parent"))?,
+            )?),
+            "restack" => Ok(serde_json::to_value(self.service.restack(branch()?)?)?),
+            "open_pull_requests" => Ok(serde_json::to_value(
+                self.service.open_pull_requests(branch()?)?,
+            )?),
+            other => Err(anyhow!("Unknown stack panel command '{}'", other)),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for BranchStackPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Stacks".to_string(),
+            description: "Stacked branches and their pull requests".to_string(),
+            icon: None,
+            position: SidebarPosition::Left,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let service = self.service.clone();
+        text_panel_view(move || match service.stacks() {
+            Ok(stacks) if stacks.is_empty() => "No stacked branches.".to_string(),
+            Ok(stacks) => stacks
+                .iter()
+                .map(|stack| {
+                    let mut lines = vec![stack.trunk.clone()];
+                    for layer in &stack.layers {
+                        let mut line = format!(
+                            "  {} ({} commits)",
+                            layer.branch, layer.commits
+                        );
+                        if let Some(number) = layer.pull_request {
+                            line.push_str(&format!("  #{}", number));
+                        }
+                        if layer.needs_restack {
+                            line.push_str("  needs restack");
+                        }
+                        lines.push(line);
+                    }
+                    lines.join("\n")
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Err(err) => format!("Can't read the stacks: {err:#}"),
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        self.service
+            .stacks()
+            .ok()
+            .and_then(|stacks| serde_json::to_value(stacks).ok())
+            .unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::path::Path;
+
+    fn commit_file(repo: &Repository, file: &str, content: &str, message: &str) {
+        let root = repo.workdir().unwrap();
+        std::fs::write(root.join(file), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    fn checkout(repo: &Repository, branch: &str) {
+        repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_restacks_branches() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        commit_file(&repo, "README.md", "readme\n", "Initial commit");
+        let trunk = current_branch(&repo).unwrap();
+
+        create_stack_branch(&repo, "api", None).unwrap();
+        commit_file(&repo, "api.rs", "fn api() {}\n", "Add the API");
+        create_stack_branch(&repo, "ui", None).unwrap();
+        commit_file(&repo, "ui.rs", "fn ui() {}\n", "Add the UI");
+        assert_eq!(current_branch(&repo).as_deref(), Some("ui"));
+
+        let stacks = branch_stacks(&repo).unwrap();
+        assert_eq!(stacks.len(), 1);
+        assert_eq!(stacks[0].trunk, trunk);
+        let layers: Vec<_> = stacks[0]
+            .layers
+            .iter()
+            .map(|layer| (layer.branch.as_str(), layer.commits, layer.needs_restack))
+            .collect();
+        assert_eq!(layers, [("api", 1, false), ("ui", 1, false)]);
+
+        // Review feedback lands on the lower layer
+        checkout(&repo, "api");
+        commit_file(&repo, "api.rs", "fn api() -> u8 { 1 }\n", "Fix the API");
+        let stacks = branch_stacks(&repo).unwrap();
+        assert!(stacks[0].layers[1].needs_restack);
+
+        checkout(&repo, "ui");
+        let report = restack(&repo, "api").unwrap();
+        assert_eq!(report.up_to_date, ["api"]);
+        assert_eq!(report.restacked, ["ui"]);
+        assert!(report.conflict.is_none());
+        let stacks = branch_stacks(&repo).unwrap();
+        assert!(!stacks[0].layers[1].needs_restack);
+        assert_eq!(stacks[0].layers[1].commits, 1);
+        // The checked-out layer was moved along with the working tree
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("api.rs")).unwrap(),
+            "fn api() -> u8 { 1 }\n"
+        );
+
+        // A conflicting layer is left as it was
+        checkout(&repo, "api");
+        commit_file(&repo, "ui.rs", "fn other() {}\n", "Clash with the UI");
+        let ui_head = branch_head(&repo, "ui").unwrap();
+        let report = restack(&repo, "ui").unwrap();
+        assert_eq!(report.conflict.as_deref(), Some("ui"));
+        assert_eq!(branch_head(&repo, "ui"), Some(ui_head));
+
+        let description = stack_description(&stacks[0], 1);
+        assert!(description.starts_with("Part 2 of 2"));
+        assert!(description.contains("2. `ui` (this one)"));
+    }
+
+    #[test]
+    fn test_restacks_when_trunk_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        commit_file(&repo, "README.md", "readme\n", "Initial commit");
+        let trunk = current_branch(&repo).unwrap();
+        create_stack_branch(&repo, "api", None).unwrap();
+        commit_file(&repo, "api.rs", "fn api() {}\n", "Add the API");
+        create_stack_branch(&repo, "ui", None).unwrap();
+        commit_file(&repo, "ui.rs", "fn ui() {}\n", "Add the UI");
+
+        // Only the bottom layer is behind its parent until it is restacked
+        checkout(&repo, &trunk);
+        commit_file(&repo, "README.md", "updated readme\n", "Update the readme");
+        let stacks = branch_stacks(&repo).unwrap();
+        assert!(stacks[0].layers[0].needs_restack);
+        assert!(!stacks[0].layers[1].needs_restack);
+
+        let report = restack(&repo, "ui").unwrap();
+        assert_eq!(report.restacked, ["api", "ui"]);
+        assert!(report.up_to_date.is_empty());
+        let trunk_head = branch_head(&repo, &trunk).unwrap();
+        let ui_head = branch_head(&repo, "ui").unwrap();
+        assert!(repo.graph_descendant_of(ui_head, trunk_head).unwrap());
+        let stacks = branch_stacks(&repo).unwrap();
+        let layers: Vec<_> = stacks[0]
+            .layers
+            .iter()
+            .map(|layer| (layer.branch.as_str(), layer.commits, layer.needs_restack))
+            .collect();
+        assert_eq!(layers, [("api", 1, false), ("ui", 1, false)]);
+    }
+}
@@ -0,0 +1,334 @@
+//! MCP Call Limits
+//!
+//! This module enforces the concurrency and request rate limits of an MCP
+//! server, for servers fronting APIs with upstream rate limits such as
+//! GitHub. Calls over the concurrency limit wait in a queue for a running
+//! call to finish; calls over the requests-per-minute limit are refused
+//! right away with the time until a slot frees up, which the agent reads as
+//! a tool error and can wait out.
+//!
+//! A queued call gives up at its deadline, or after
+//! [`DEFAULT_QUEUE_TIMEOUT`] without one. Limits of 0, which would block
+//! every call, are refused when the limits are loaded.
+//!
+//! The queue is reported in the `mcp.<id>.queued_calls` gauge and the
+//! `mcp.<id>.queue_wait_ms` samples, refused calls in the
+//! `mcp.<id>.rate_limited` and `mcp.<id>.queue_timeouts` counters.
+
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{Clock, MetricsRegistry, SystemClock};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Time a call waits in the queue when it has no deadline of its own
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Concurrency and rate limits of the tool calls to a server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct McpCallLimits {
+    /// Calls running at the same time; more wait in a queue
+    #[serde(deserialize_with = "non_zero")]
+    pub max_concurrency: Option<usize>,
+    /// Calls started in any minute; more are refused
+    #[serde(deserialize_with = "non_zero")]
+    pub requests_per_minute: Option<u32>,
+}
+
+impl McpCallLimits {
+    /// Check if no limit is set
+    pub fn is_empty(&self) -> bool {
+        self.max_concurrency.is_none() && self.requests_per_minute.is_none()
+    }
+
+    /// Check that no limit is 0
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_concurrency == Some(0) {
+            return Err(anyhow::anyhow!("max_concurrency must be at least 1"));
+        }
+        if self.requests_per_minute == Some(0) {
+            return Err(anyhow::anyhow!("requests_per_minute must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+fn non_zero<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default + PartialEq,
+{
+    let limit = Option::<T>::deserialize(deserializer)?;
+    if limit == Some(T::default()) {
+        return Err(serde::de::Error::custom("limit must be at least 1"));
+    }
+    Ok(limit)
+}
+
+/// Why a call was not started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallRefused {
+    /// The server had as many calls as it may in the last minute; holds
+    /// the time after which to retry
+    RateLimited(Duration),
+    /// The call waited in the queue until its deadline; holds the time it
+    /// waited
+    QueueTimeout(Duration),
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    running: usize,
+    queued: usize,
+    /// Start times of the calls of the last minute, oldest first
+    started: VecDeque<Instant>,
+}
+
+/// Applies the call limits of one server
+pub struct CallLimiter {
+    server_id: String,
+    limits: McpCallLimits,
+    state: Mutex<LimiterState>,
+    slot_freed: Condvar,
+    /// Times the rate window and deadlines
+    clock: Arc<dyn Clock>,
+}
+
+/// A running call; dropping it lets a queued call start
+pub struct CallPermit<'a> {
+    limiter: &'a CallLimiter,
+}
+
+impl Drop for CallPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().running -= 1;
+        self.limiter.slot_freed.notify_one();
+    }
+}
+
+impl CallLimiter {
+    pub fn new(server_id: &str, limits: McpCallLimits) -> Self {
+        Self {
+            server_id: server_id.to_string(),
+            limits,
+            state: Mutex::new(LimiterState::default()),
+            slot_freed: Condvar::new(),
+            clock: SystemClock::shared(),
+        }
+    }
+
+    /// Time the rate window and the deadlines of queued calls with `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn limits(&self) -> McpCallLimits {
+        self.limits
+    }
+
+    /// Get the number of calls waiting for a running call to finish
+    pub fn queued(&self) -> usize {
+        self.state.lock().queued
+    }
+
+    /// Start a call, waiting while the server runs as many calls as it may,
+    /// but not past `deadline`
+    pub fn acquire(&self, deadline: Instant) -> Result<CallPermit<'_>, CallRefused> {
+        let metrics = MetricsRegistry::global();
+        let queued_at = self.clock.instant();
+        let mut state = self.state.lock();
+        let mut waited = false;
+        loop {
+            let now = self.clock.instant();
+            while state
+                .started
+                .front()
+                .is_some_and(|started| now.duration_since(*started) >= RATE_WINDOW)
+            {
+                state.started.pop_front();
+            }
+            let rate_exceeded = self
+                .limits
+                .requests_per_minute
+                .is_some_and(|rpm| state.started.len() >= rpm as usize);
+            if rate_exceeded {
+                let retry_after = state
+                    .started
+                    .front()
+                    .map(|oldest| RATE_WINDOW - now.duration_since(*oldest))
+                    .unwrap_or(RATE_WINDOW);
+                if waited {
+                    self.leave_queue(&mut state);
+                }
+                metrics.increment(&self.metric("rate_limited"), 1);
+                return Err(CallRefused::RateLimited(retry_after));
+            }
+            let has_slot = self
+                .limits
+                .max_concurrency
+                .is_none_or(|max| state.running < max);
+            if has_slot {
+                break;
+            }
+            if !waited {
+                waited = true;
+                state.queued += 1;
+            }
+            metrics.set_gauge(&self.metric("queued_calls"), state.queued as f64);
+            let timeout = deadline.saturating_duration_since(self.clock.instant());
+            if self.slot_freed.wait_for(&mut state, timeout).timed_out()
+                && self
+                    .limits
+                    .max_concurrency
+                    .is_some_and(|max| state.running >= max)
+            {
+                self.leave_queue(&mut state);
+                metrics.increment(&self.metric("queue_timeouts"), 1);
+                return Err(CallRefused::QueueTimeout(self.waited_since(queued_at)));
+            }
+        }
+        if waited {
+            state.queued -= 1;
+            metrics.set_gauge(&self.metric("queued_calls"), state.queued as f64);
+            metrics.record_sample(
+                &self.metric("queue_wait_ms"),
+                self.waited_since(queued_at).as_secs_f64() * 1000.0,
+            );
+        }
+        state.running += 1;
+        state.started.push_back(self.clock.instant());
+        Ok(CallPermit { limiter: self })
+    }
+
+    fn waited_since(&self, queued_at: Instant) -> Duration {
+        self.clock.instant().duration_since(queued_at)
+    }
+
+    /// Take a call that gives up off the queue
+    fn leave_queue(&self, state: &mut LimiterState) {
+        state.queued -= 1;
+        MetricsRegistry::global()
+            .set_gauge(&self.metric("queued_calls"), state.queued as f64);
+        // Pass on a wakeup this call won't use
+        self.slot_freed.notify_one();
+    }
+
+    fn metric(&self, name: &str) -> String {
+        format!("mcp.{}.{}", self.server_id, name)
+    }
+}
+
+/// Format the wait before retrying a refused call, rounded up to seconds
+pub fn rate_limit_message(retry_after: Duration) -> String {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    format!("rate limited, retry after {secs}s")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::ManualClock;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_call_limits() {
+        let limiter = CallLimiter::new(
+            "limits-test",
+            McpCallLimits {
+                max_concurrency: None,
+                requests_per_minute: Some(2),
+            },
+        );
+        let deadline = Instant::now() + DEFAULT_QUEUE_TIMEOUT;
+        drop(limiter.acquire(deadline).unwrap());
+        drop(limiter.acquire(deadline).unwrap());
+        let Err(CallRefused::RateLimited(retry_after)) = limiter.acquire(deadline)
+        else {
+            panic!("the call wasn't rate limited");
+        };
+        assert!(retry_after > Duration::from_secs(59));
+        assert_eq!(
+            rate_limit_message(retry_after),
+            "rate limited, retry after 60s"
+        );
+        assert_eq!(
+            MetricsRegistry::global().get("mcp.limits-test.rate_limited"),
+            Some(1.0)
+        );
+
+        let limiter = Arc::new(CallLimiter::new(
+            "queue-test",
+            McpCallLimits {
+                max_concurrency: Some(1),
+                requests_per_minute: None,
+            },
+        ));
+        let permit = limiter.acquire(deadline).unwrap();
+        let waiting = std::thread::spawn({
+            let limiter = limiter.clone();
+            move || {
+                let _permit = limiter.acquire(deadline).unwrap();
+            }
+        });
+        while limiter.queued() == 0 {
+            std::thread::yield_now();
+        }
+        drop(permit);
+        waiting.join().unwrap();
+        assert_eq!(limiter.queued(), 0);
+        assert_eq!(
+            MetricsRegistry::global()
+                .history("mcp.queue-test.queue_wait_ms")
+                .len(),
+            1
+        );
+
+        // Queued calls give up at their deadline
+        let _permit = limiter.acquire(deadline).unwrap();
+        let refused = limiter.acquire(Instant::now() + Duration::from_millis(10));
+        assert!(matches!(refused, Err(CallRefused::QueueTimeout(_))));
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[test]
+    fn test_rate_window_follows_the_clock() {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let limiter = CallLimiter::new(
+            "clock-test",
+            McpCallLimits {
+                max_concurrency: None,
+                requests_per_minute: Some(1),
+            },
+        )
+        .with_clock(clock.clone());
+        let deadline = clock.instant() + DEFAULT_QUEUE_TIMEOUT;
+        drop(limiter.acquire(deadline).unwrap());
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(
+            limiter.acquire(deadline).err(),
+            Some(CallRefused::RateLimited(Duration::from_secs(15)))
+        );
+        clock.advance(Duration::from_secs(15));
+        assert!(limiter.acquire(deadline).is_ok());
+    }
+
+    #[test]
+    fn test_zero_limits_are_refused() {
+        let limits: McpCallLimits =
+            toml::from_str("max_concurrency = 2\nrequests_per_minute = 60").unwrap();
+        assert!(limits.validate().is_ok());
+        assert!(toml::from_str::<McpCallLimits>("max_concurrency = 0").is_err());
+        assert!(toml::from_str::<McpCallLimits>("requests_per_minute = 0").is_err());
+        let limits = McpCallLimits {
+            max_concurrency: Some(0),
+            requests_per_minute: None,
+        };
+        assert!(limits.validate().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! MCP Capability Cache
+//!
+//! The agent assembles its toolset on every turn, and asking each server
+//! for `tools/list` would put a round trip per server on that path. The
+//! registry instead keeps what every server offers once it is started: its
+//! capabilities, its tools and its resources. Entries are dropped when the
+//! server sends `notifications/tools/list_changed` or
+//! `notifications/resources/list_changed`, and when it stops, so the next
+//! request lists them again.
+
+use anyhow::Result;
+use dashmap::DashMap;
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    McpResource, McpServerCapabilities, McpServerNotification, McpServerPlugin,
+    McpTool,
+};
+
+/// What one server offers, as far as it has been listed
+#[derive(Debug, Clone, Default)]
+pub struct CachedCapabilities {
+    pub capabilities: Option<McpServerCapabilities>,
+    pub tools: Option<Arc<[McpTool]>>,
+    pub resources: Option<Arc<[McpResource]>>,
+}
+
+/// Capabilities, tools and resources of the registered servers
+///
+/// Cloning the cache gives another view of the same entries.
+#[derive(Clone, Default)]
+pub struct McpCapabilityCache {
+    servers: Arc<DashMap<String, CachedCapabilities>>,
+}
+
+impl McpCapabilityCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List everything a server offers again, as after it was initialized
+    ///
+    /// Tools and resources are only listed from servers declaring them.
+    pub fn refresh(
+        &self,
+        server_id: &str,
+        server: &dyn McpServerPlugin,
+    ) -> Result<()> {
+        let capabilities = server.server_info().capabilities;
+        let tools: Arc<[McpTool]> = if capabilities.tools {
+            server.get_tools()?.into()
+        } else {
+            Arc::from([])
+        };
+        let resources: Arc<[McpResource]> = if capabilities.resources {
+            server.get_resources()?.into()
+        } else {
+            Arc::from([])
+        };
+        self.servers.insert(
+            server_id.to_string(),
+            CachedCapabilities {
+                capabilities: Some(capabilities),
+                tools: Some(tools),
+                resources: Some(resources),
+            },
+        );
+        Ok(())
+    }
+
+    /// Get what has been cached for a server
+    pub fn get(&self, server_id: &str) -> Option<CachedCapabilities> {
+        self.servers.get(server_id).map(|entry| entry.clone())
+    }
+
+    /// Get the cached tools of a server
+    pub fn cached_tools(&self, server_id: &str) -> Option<Arc<[McpTool]>> {
+        self.servers.get(server_id)?.tools.clone()
+    }
+
+    /// Get the tools of a server, listing them if they aren't cached
+    pub fn tools(
+        &self,
+        server_id: &str,
+        server: &dyn McpServerPlugin,
+    ) -> Result<Arc<[McpTool]>> {
+        if let Some(tools) = self.cached_tools(server_id) {
+            return Ok(tools);
+        }
+        let tools: Arc<[McpTool]> = Arc::from(server.get_tools()?);
+        self.servers.entry(server_id.to_string()).or_default().tools =
+            Some(tools.clone());
+        Ok(tools)
+    }
+
+    /// Get the resources of a server, listing them if they aren't cached
+    pub fn resources(
+        &self,
+        server_id: &str,
+        server: &dyn McpServerPlugin,
+    ) -> Result<Arc<[McpResource]>> {
+        let cached = self
+            .servers
+            .get(server_id)
+            .and_then(|entry| entry.resources.clone());
+        if let Some(resources) = cached {
+            return Ok(resources);
+        }
+        let resources: Arc<[McpResource]> = Arc::from(server.get_resources()?);
+        self.servers
+            .entry(server_id.to_string())
+            .or_default()
+            .resources = Some(resources.clone());
+        Ok(resources)
+    }
+
+    /// Drop the lists a server said have changed
+    pub fn invalidate(&self, server_id: &str, notification: &McpServerNotification) {
+        let Some(mut entry) = self.servers.get_mut(server_id) else {
+            return;
+        };
+        match notification {
+            McpServerNotification::ToolsListChanged => entry.tools = None,
+            McpServerNotification::ResourcesListChanged => entry.resources = None,
+            _ => {}
+        }
+    }
+
+    /// Forget a server, as when it stops
+    pub fn remove(&self, server_id: &str) {
+        self.servers.remove(server_id);
+    }
+}
@@ -0,0 +1,248 @@
+//! Catalyst Ignore Rules
+//!
+//! This module implements `.catalystignore` files. They use gitignore syntax
+//! and exclude paths from indexing, AI context and filesystem tool access.
+//! Rules are layered: built-in defaults first, then every `.catalystignore`
+//! from the workspace root down to the file, with deeper files taking
+//! precedence (including `!` re-includes).
+//!
+//! The configuration also holds the [`SymlinkPolicy`], so the crawler and
+//! the sandbox treat symbolic links the same way.
+
+use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the per-directory ignore file
+pub const CATALYST_IGNORE_FILE_NAME: &str = ".catalystignore";
+
+/// Patterns excluded from AI context and indexing unless re-included
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    ".git/",
+    ".hg/",
+    ".svn/",
+    "node_modules/",
+    "target/",
+    "build/",
+    "dist/",
+    "out/",
+    ".next/",
+    ".venv/",
+    "venv/",
+    "__pycache__/",
+    "*.pyc",
+    "*.o",
+    "*.obj",
+    "*.class",
+    "*.so",
+    "*.dylib",
+    "*.dll",
+    "*.exe",
+    "*.min.js",
+    "*.map",
+];
+
+/// Configuration for ignore rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalystIgnoreConfig {
+    /// Apply `DEFAULT_IGNORE_PATTERNS`
+    pub use_default_patterns: bool,
+    /// Additional global patterns, e.g. from user settings
+    pub extra_patterns: Vec<String>,
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+}
+
+impl Default for CatalystIgnoreConfig {
+    fn default() -> Self {
+        Self {
+            use_default_patterns: true,
+            extra_patterns: Vec::new(),
+            symlinks: SymlinkPolicy::default(),
+        }
+    }
+}
+
+/// How symbolic links inside a workspace root are treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Follow links whose target lies inside a workspace root, under the
+    /// rules of that root; links leading out of the workspace are rejected
+    #[default]
+    Resolve,
+    /// Reject every path through a link
+    Reject,
+}
+
+/// Layered `.catalystignore` matcher for a workspace root
+pub struct CatalystIgnore {
+    root: PathBuf,
+    symlinks: SymlinkPolicy,
+    global: Gitignore,
+    /// Parsed ignore files keyed by directory, `None` when a directory has none
+    directories: RwLock<HashMap<PathBuf, Option<Arc<Gitignore>>>>,
+}
+
+impl CatalystIgnore {
+    /// Create the ignore rules for a workspace root
+    pub fn new(root: &Path, config: &CatalystIgnoreConfig) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        if config.use_default_patterns {
+            for pattern in DEFAULT_IGNORE_PATTERNS {
+                builder.add_line(None, pattern)?;
+            }
+        }
+        for pattern in &config.extra_patterns {
+            builder.add_line(None, pattern)?;
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            symlinks: config.symlinks,
+            global: builder.build()?,
+            directories: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Get the workspace root these rules apply to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get how links inside the root are treated
+    pub fn symlink_policy(&self) -> SymlinkPolicy {
+        self.symlinks
+    }
+
+    /// Check if a path is excluded by the global default patterns only
+    pub fn is_ignored_by_defaults(&self, path: &Path, is_dir: bool) -> bool {
+        path.starts_with(&self.root)
+            && self
+                .global
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+    }
+
+    /// Check if a path is excluded from indexing, AI context and tools
+    ///
+    /// Paths outside of the workspace root are never considered ignored;
+    /// rejecting them is the job of the sandbox.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = if path.is_relative() {
+            self.root.join(path)
+        } else {
+            path.to_path_buf()
+        };
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+
+        let mut ignored = self
+            .global
+            .matched_path_or_any_parents(&path, is_dir)
+            .is_ignore();
+
+        let Some(parent) = path.parent() else {
+            return ignored;
+        };
+        let Ok(relative_parent) = parent.strip_prefix(&self.root) else {
+            return ignored;
+        };
+
+        let mut dir = self.root.clone();
+        let mut apply = |dir: &Path| {
+            if let Some(matcher) = self.matcher_for_directory(dir) {
+                let matched = matcher.matched_path_or_any_parents(&path, is_dir);
+                if matched.is_ignore() {
+                    ignored = true;
+                } else if matched.is_whitelist() {
+                    ignored = false;
+                }
+            }
+        };
+        apply(&dir);
+        for component in relative_parent.components() {
+            dir.push(component);
+            apply(&dir);
+        }
+
+        ignored
+    }
+
+    /// Drop the cached rules of a directory, e.g. after its
+    /// `.catalystignore` changed on disk
+    pub fn invalidate(&self, dir: &Path) {
+        self.directories.write().remove(dir);
+    }
+
+    /// Drop all cached per-directory rules
+    pub fn invalidate_all(&self) {
+        self.directories.write().clear();
+    }
+
+    fn matcher_for_directory(&self, dir: &Path) -> Option<Arc<Gitignore>> {
+        if let Some(matcher) = self.directories.read().get(dir) {
+            return matcher.clone();
+        }
+
+        let file = dir.join(CATALYST_IGNORE_FILE_NAME);
+        let matcher = if file.is_file() {
+            let (matcher, err) = Gitignore::new(&file);
+            if let Some(err) = err {
+                tracing::warn!("Failed to parse '{}': {}", file.display(), err);
+            }
+            Some(Arc::new(matcher))
+        } else {
+            None
+        };
+
+        self.directories
+            .write()
+            .insert(dir.to_path_buf(), matcher.clone());
+        matcher
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore_for(root: &Path) -> CatalystIgnore {
+        CatalystIgnore::new(root, &CatalystIgnoreConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_default_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let ignore = ignore_for(dir.path());
+
+        assert!(ignore.is_ignored(Path::new("node_modules/react/index.js"), false));
+        assert!(ignore.is_ignored(Path::new("crates/a/target"), true));
+        assert!(!ignore.is_ignored(Path::new("src/main.rs"), false));
+    }
+
+    #[test]
+    fn test_nested_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("secrets/public")).unwrap();
+        std::fs::write(root.join(CATALYST_IGNORE_FILE_NAME), "secrets/\n*.env\n")
+            .unwrap();
+        std::fs::write(
+            root.join("secrets/public").join(CATALYST_IGNORE_FILE_NAME),
+            "!*.md\n",
+        )
+        .unwrap();
+
+        let ignore = ignore_for(root);
+        assert!(ignore.is_ignored(&root.join("secrets/key.pem"), false));
+        assert!(ignore.is_ignored(&root.join("app/.env"), false));
+        assert!(!ignore.is_ignored(&root.join("secrets/public/README.md"), false));
+        assert!(!ignore.is_ignored(Path::new("/elsewhere/secrets/key.pem"), false));
+    }
+}
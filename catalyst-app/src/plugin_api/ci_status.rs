@@ -0,0 +1,737 @@
+//! CI Status
+//!
+//! This module tracks the GitHub Actions workflow runs of the current branch
+//! through the github MCP server. The service polls the runs in the
+//! background and publishes them to the status bar item and the CI panel.
+//! Job logs are only fetched when asked for, either to show them in the
+//! panel or to attach the logs of a failed run to the conversation when the
+//! user asks the agent why it failed.
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+use floem::{
+    View,
+    ext_event::create_signal_from_channel,
+    reactive::SignalGet,
+    views::{Decorators, label},
+};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::plugin_api::{
+    DegradationTracker, FocusMode, GITHUB_SERVER_ID, McpServerRegistry,
+    McpToolResult, PanelCommand, PanelCommandResult, SidebarPanelInfo,
+    SidebarPanelPlugin, SidebarPanelRegistry, SidebarPosition, SourceTracker,
+    stack_detection::origin_remote_url, text_panel_view,
+};
+
+/// Time between polls of the workflow runs
+pub const DEFAULT_CI_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Runs fetched per poll
+const RUNS_PER_POLL: usize = 20;
+/// Lines kept from the end of each job log
+const MAX_LOG_LINES: usize = 300;
+
+/// State of a workflow run or job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CiStatus {
+    Queued,
+    InProgress,
+    Success,
+    Failure,
+    Cancelled,
+    Skipped,
+    Unknown,
+}
+
+impl CiStatus {
+    /// Combine GitHub's `status` and `conclusion` fields
+    pub fn from_github(status: Option<&str>, conclusion: Option<&str>) -> Self {
+        match (status, conclusion) {
+            (Some("completed"), Some("success")) => CiStatus::Success,
+            (
+                Some("completed"),
+                Some("failure" | "timed_out" | "startup_failure"),
+            ) => CiStatus::Failure,
+            (Some("completed"), Some("cancelled")) => CiStatus::Cancelled,
+            (Some("completed"), Some("skipped" | "neutral")) => CiStatus::Skipped,
+            (Some("in_progress"), _) => CiStatus::InProgress,
+            (Some("queued" | "waiting" | "pending" | "requested"), _) => {
+                CiStatus::Queued
+            }
+            _ => CiStatus::Unknown,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, CiStatus::Queued | CiStatus::InProgress)
+    }
+}
+
+/// A run of a workflow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub id: u64,
+    /// Name of the workflow
+    pub name: String,
+    pub branch: String,
+    pub head_sha: String,
+    pub status: CiStatus,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// A job of a workflow run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowJob {
+    pub id: u64,
+    pub name: String,
+    pub status: CiStatus,
+}
+
+/// Log of a job, trimmed to its last lines
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobLog {
+    pub job: WorkflowJob,
+    pub log: String,
+}
+
+/// Status of the latest commit of the branch, shown in the status bar
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CiSummary {
+    pub branch: String,
+    /// Latest run of each workflow for the newest commit
+    pub runs: Vec<WorkflowRun>,
+}
+
+impl CiSummary {
+    /// Get the combined status: any failure fails, any unfinished run is
+    /// pending, otherwise the runs succeeded
+    pub fn status(&self) -> Option<CiStatus> {
+        if self.runs.is_empty() {
+            return None;
+        }
+        let any = |status| self.runs.iter().any(|run| run.status == status);
+        Some(if any(CiStatus::Failure) {
+            CiStatus::Failure
+        } else if any(CiStatus::InProgress) {
+            CiStatus::InProgress
+        } else if any(CiStatus::Queued) {
+            CiStatus::Queued
+        } else if any(CiStatus::Cancelled) {
+            CiStatus::Cancelled
+        } else {
+            CiStatus::Success
+        })
+    }
+
+    /// Get the status bar text, empty when the branch has no runs
+    pub fn label(&self) -> String {
+        let failed = self
+            .runs
+            .iter()
+            .filter(|run| run.status == CiStatus::Failure)
+            .count();
+        match self.status() {
+            None => String::new(),
+            Some(CiStatus::Failure) => format!("CI: {} failed", failed),
+            Some(CiStatus::InProgress | CiStatus::Queued) => {
+                "CI: running".to_string()
+            }
+            Some(CiStatus::Cancelled) => "CI: cancelled".to_string(),
+            Some(_) => "CI: passed".to_string(),
+        }
+    }
+}
+
+/// Get the `owner/name` of a GitHub remote URL
+pub fn github_repository_from_remote(url: &str) -> Option<String> {
+    let url = url.trim();
+    let path = url
+        .strip_prefix("git@github.com:")
+        .or_else(|| url.strip_prefix("ssh://git@github.com/"))
+        .or_else(|| url.strip_prefix("https://github.com/"))
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (owner, name) = path.split_once('/')?;
+    if owner.is_empty() || name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(format!("{}/{}", owner, name))
+}
+
+/// Polls the workflow runs of a repository's current branch
+pub struct CiStatusService {
+    registry: McpServerRegistry,
+    tracker: Arc<DegradationTracker>,
+    owner: String,
+    repo: String,
+    branch: RwLock<String>,
+    summary: RwLock<CiSummary>,
+    listeners: Mutex<Vec<Sender<CiSummary>>>,
+}
+
+impl CiStatusService {
+    /// Create a service for an `owner/name` repository
+    pub fn new(
+        registry: McpServerRegistry,
+        tracker: Arc<DegradationTracker>,
+        repository: &str,
+        branch: &str,
+    ) -> Result<Arc<Self>> {
+        let (owner, repo) = repository.split_once('/').ok_or_else(|| {
+            anyhow::anyhow!(
+                "Repository '{}' must be given as owner/name",
+                repository
+            )
+        })?;
+        Ok(Arc::new(Self {
+            registry,
+            tracker,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            branch: RwLock::new(branch.to_string()),
+            summary: RwLock::new(CiSummary::default()),
+            listeners: Mutex::new(Vec::new()),
+        }))
+    }
+
+    pub fn branch(&self) -> String {
+        self.branch.read().clone()
+    }
+
+    /// Follow a branch switch; the runs of the old branch are dropped until
+    /// the next poll
+    pub fn set_branch(&self, branch: &str) {
+        {
+            let mut current = self.branch.write();
+            if *current == branch {
+                return;
+            }
+            *current = branch.to_string();
+        }
+        self.publish(CiSummary {
+            branch: branch.to_string(),
+            runs: Vec::new(),
+        });
+    }
+
+    pub fn summary(&self) -> CiSummary {
+        self.summary.read().clone()
+    }
+
+    /// Get the summary after every change
+    pub fn subscribe(&self) -> Receiver<CiSummary> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.listeners.lock().push(tx);
+        rx
+    }
+
+    /// Poll every `interval` on a background thread until the service is
+    /// dropped, skipping polls while focus mode is on
+    pub fn spawn_polling(self: &Arc<Self>, interval: Duration) {
+        let service: Weak<Self> = Arc::downgrade(self);
+        std::thread::spawn(move || {
+            while let Some(service) = service.upgrade() {
+                if FocusMode::global().is_active() {
+                    tracing::debug!("Focus mode is on; not polling CI status");
+                } else if service.branch().is_empty() {
+                    // The branch isn't known until the source control loads
+                } else if let Err(err) = service.poll() {
+                    tracing::warn!("Failed to poll CI status: {err:#}");
+                }
+                drop(service);
+                std::thread::sleep(interval);
+            }
+        });
+    }
+
+    /// Fetch the runs of the current branch
+    pub fn poll(&self) -> Result<CiSummary> {
+        let branch = self.branch();
+        let result = self.call(
+            "list_workflow_runs",
+            serde_json::json!({
+                "branch": branch,
+                "perPage": RUNS_PER_POLL,
+            }),
+        )?;
+        let summary = CiSummary {
+            runs: latest_runs(parse_runs(&result_json(&result))),
+            branch,
+        };
+        // Drop the result if the branch changed while it was fetched
+        if summary.branch == self.branch() {
+            self.publish(summary.clone());
+        }
+        Ok(summary)
+    }
+
+    /// Get the jobs of a run
+    pub fn jobs(&self, run_id: u64) -> Result<Vec<WorkflowJob>> {
+        let result = self.call(
+            "list_workflow_jobs",
+            serde_json::json!({ "run_id": run_id }),
+        )?;
+        Ok(parse_jobs(&result_json(&result)))
+    }
+
+    /// Fetch the logs of the jobs of a run, only of the failed jobs if
+    /// `failed_only`
+    pub fn job_logs(&self, run_id: u64, failed_only: bool) -> Result<Vec<JobLog>> {
+        self.jobs(run_id)?
+            .into_iter()
+            .filter(|job| !failed_only || job.status == CiStatus::Failure)
+            .map(|job| {
+                let result = self.call(
+                    "get_job_logs",
+                    serde_json::json!({
+                        "job_id": job.id,
+                        "return_content": true,
+                        "tail_lines": MAX_LOG_LINES,
+                    }),
+                )?;
+                Ok(JobLog {
+                    log: tail_lines(&result_text(&result), MAX_LOG_LINES),
+                    job,
+                })
+            })
+            .collect()
+    }
+
+    /// Attach the logs of a run's failed jobs to the sources of a request,
+    /// returning the question to ask the agent
+    pub fn explain_failure(
+        &self,
+        run_id: u64,
+        sources: &mut SourceTracker,
+    ) -> Result<String> {
+        let run = self.summary().runs.into_iter().find(|run| run.id == run_id);
+        let logs = self.job_logs(run_id, true)?;
+        if logs.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Run {} has no failed jobs to explain",
+                run_id
+            ));
+        }
+        let ids: Vec<String> = logs
+            .into_iter()
+            .map(|log| {
+                let id = sources.add_tool_result(
+                    GITHUB_SERVER_ID,
+                    "get_job_logs",
+                    &log.job.id.to_string(),
+                    format!("Log of job '{}':\n{}", log.job.name, log.log),
+                );
+                format!("[{}]", id)
+            })
+            .collect();
+        let name = run
+            .map(|run| format!("'{}' on {}", run.name, run.branch))
+            .unwrap_or_else(|| format!("{}", run_id));
+        Ok(format!(
+            "Why did the workflow run {} fail? The logs of the failed jobs are \
+             in sources {}. Identify the root cause and suggest a fix.",
+            name,
+            ids.join(", ")
+        ))
+    }
+
+    fn call(
+        &self,
+        tool: &str,
+        mut arguments: serde_json::Value,
+    ) -> Result<McpToolResult> {
+        arguments["owner"] = self.owner.clone().into();
+        arguments["repo"] = self.repo.clone().into();
+        let result = self.registry.call_tool_degraded(
+            GITHUB_SERVER_ID,
+            tool,
+            arguments,
+            &self.tracker,
+        );
+        if result.is_error {
+            return Err(anyhow::anyhow!(
+                "{} failed: {}",
+                tool,
+                result_text(&result)
+            ));
+        }
+        Ok(result)
+    }
+
+    fn publish(&self, summary: CiSummary) {
+        *self.summary.write() = summary.clone();
+        self.listeners
+            .lock()
+            .retain(|listener| listener.send(summary.clone()).is_ok());
+    }
+}
+
+pub(crate) fn result_text(result: &McpToolResult) -> String {
+    result
+        .content
+        .iter()
+        .map(|content| match &content.data {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Get the JSON of a tool result, given either as JSON content or as JSON
+/// text
+pub(crate) fn result_json(result: &McpToolResult) -> serde_json::Value {
+    result
+        .content
+        .iter()
+        .find_map(|content| match &content.data {
+            serde_json::Value::String(text) => serde_json::from_str(text).ok(),
+            other => Some(other.clone()),
+        })
+        .unwrap_or_default()
+}
+
+/// Read the items of a GitHub list response, which wraps them in an object
+/// such as `{"total_count": 2, "workflow_runs": [...]}`
+fn list_items<'a>(
+    value: &'a serde_json::Value,
+    field: &str,
+) -> &'a [serde_json::Value] {
+    value
+        .get(field)
+        .unwrap_or(value)
+        .as_array()
+        .map(|items| items.as_slice())
+        .unwrap_or_default()
+}
+
+fn item_status(item: &serde_json::Value) -> CiStatus {
+    CiStatus::from_github(
+        item.get("status").and_then(|status| status.as_str()),
+        item.get("conclusion")
+            .and_then(|conclusion| conclusion.as_str()),
+    )
+}
+
+fn parse_runs(value: &serde_json::Value) -> Vec<WorkflowRun> {
+    list_items(value, "workflow_runs")
+        .iter()
+        .filter_map(|item| {
+            let text = |field: &str| {
+                item.get(field)
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.to_string())
+            };
+            Some(WorkflowRun {
+                id: item.get("id")?.as_u64()?,
+                name: text("name").unwrap_or_default(),
+                branch: text("head_branch").unwrap_or_default(),
+                head_sha: text("head_sha").unwrap_or_default(),
+                status: item_status(item),
+                url: text("html_url"),
+            })
+        })
+        .collect()
+}
+
+fn parse_jobs(value: &serde_json::Value) -> Vec<WorkflowJob> {
+    list_items(value, "jobs")
+        .iter()
+        .filter_map(|item| {
+            Some(WorkflowJob {
+                id: item.get("id")?.as_u64()?,
+                name: item.get("name")?.as_str()?.to_string(),
+                status: item_status(item),
+            })
+        })
+        .collect()
+}
+
+/// Keep the latest run of each workflow for the newest commit; GitHub lists
+/// runs newest first
+fn latest_runs(runs: Vec<WorkflowRun>) -> Vec<WorkflowRun> {
+    let Some(head_sha) = runs.first().map(|run| run.head_sha.clone()) else {
+        return Vec::new();
+    };
+    let mut latest: Vec<WorkflowRun> = Vec::new();
+    for run in runs {
+        if run.head_sha == head_sha && !latest.iter().any(|r| r.name == run.name) {
+            latest.push(run);
+        }
+    }
+    latest
+}
+
+fn tail_lines(text: &str, limit: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(limit)..].join("\n")
+}
+
+/// Status bar item showing the CI status of the current branch
+pub fn ci_status_view(service: Arc<CiStatusService>) -> Box<dyn View> {
+    let initial = service.summary();
+    let summary = create_signal_from_channel(service.subscribe());
+    Box::new(
+        label(move || summary.get().unwrap_or_else(|| initial.clone()).label())
+            .style(|s| s.padding_horiz(10.0).selectable(false)),
+    )
+}
+
+/// Sidebar panel listing the workflow runs of the current branch
+pub struct CiStatusPanel {
+    service: Arc<CiStatusService>,
+}
+
+impl CiStatusPanel {
+    pub const ID: &'static str = "catalyst.ci_status";
+
+    pub fn new(service: Arc<CiStatusService>) -> Self {
+        Self { service }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        let run_id = || {
+            command
+                .parameters
+                .get("run_id")
+                .and_then(|id| id.as_u64())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'run_id' parameter"))
+        };
+
+        match command.command_id.as_str() {
+            "runs" => Ok(serde_json::to_value(self.service.summary())?),
+            "refresh" => Ok(serde_json::to_value(self.service.poll()?)?),
+            "logs" => {
+                let failed_only = command
+                    .parameters
+                    .get("failed_only")
+                    .and_then(|failed_only| failed_only.as_bool())
+                    .unwrap_or(false);
+                Ok(serde_json::to_value(
+                    self.service.job_logs(run_id()?, failed_only)?,
+                )?)
+            }
+            "explain" => {
+                let mut sources = SourceTracker::new();
+                let prompt =
+                    self.service.explain_failure(run_id()?, &mut sources)?;
+                Ok(serde_json::json!({
+                    "prompt": prompt,
+                    "sources": sources.into_sources(),
+                }))
+            }
+            other => Err(anyhow::anyhow!("Unknown CI panel command '{}'", other)),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for CiStatusPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "CI".to_string(),
+            description: "Workflow runs of the current branch".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let service = self.service.clone();
+        text_panel_view(move || {
+            let summary = service.summary();
+            if summary.runs.is_empty() {
+                return format!("No workflow runs for {} yet.", summary.branch);
+            }
+            summary
+                .runs
+                .iter()
+                .map(|run| {
+                    format!("{:?}  {}  (run {})", run.status, run.name, run.id)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.service.summary()).unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+/// CI status of an open workspace, shown in its status bar
+pub struct WorkspaceCiStatus {
+    pub service: Arc<CiStatusService>,
+    /// Whether this workspace registered the CI panel, which shows the first
+    /// open workspace with CI
+    owns_panel: bool,
+}
+
+impl WorkspaceCiStatus {
+    /// Start polling the CI of a workspace whose `origin` remote is on
+    /// GitHub, once the github server is registered, and register the CI
+    /// panel unless another workspace already did
+    pub fn start(
+        registry: &McpServerRegistry,
+        panels: &SidebarPanelRegistry,
+        workspace_root: &Path,
+        branch: &str,
+    ) -> Option<Self> {
+        registry.get_server(GITHUB_SERVER_ID)?;
+        let repository =
+            github_repository_from_remote(&origin_remote_url(workspace_root)?)?;
+        let service = CiStatusService::new(
+            registry.clone(),
+            Arc::new(DegradationTracker::new()),
+            &repository,
+            branch,
+        )
+        .ok()?;
+        service.spawn_polling(DEFAULT_CI_POLL_INTERVAL);
+        let owns_panel = panels
+            .register_panel(
+                CiStatusPanel::ID.to_string(),
+                Box::new(CiStatusPanel::new(service.clone())),
+            )
+            .is_ok();
+        Some(Self {
+            service,
+            owns_panel,
+        })
+    }
+
+    /// Unregister the CI panel if this workspace registered it
+    pub fn stop(&self, panels: &SidebarPanelRegistry) {
+        if self.owns_panel {
+            if let Err(err) = panels.unregister_panel(CiStatusPanel::ID) {
+                tracing::warn!("Failed to remove the CI panel: {err:#}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnoreConfig, GitMcpServer};
+
+    #[test]
+    fn test_summarizes_latest_runs() {
+        assert_eq!(
+            github_repository_from_remote("git@github.com:acme/app.git").as_deref(),
+            Some("acme/app")
+        );
+        assert_eq!(
+            github_repository_from_remote("https://github.com/acme/app").as_deref(),
+            Some("acme/app")
+        );
+        assert!(
+            github_repository_from_remote("https://gitlab.com/acme/app").is_none()
+        );
+
+        let response = serde_json::json!({
+            "total_count": 3,
+            "workflow_runs": [
+                { "id": 3, "name": "CI", "head_branch": "main", "head_sha": "b",
+                  "status": "completed", "conclusion": "failure" },
+                { "id": 2, "name": "Lint", "head_branch": "main", "head_sha": "b",
+                  "status": "in_progress", "conclusion": null },
+                { "id": 1, "name": "CI", "head_branch": "main", "head_sha": "a",
+                  "status": "completed", "conclusion": "success" }
+            ]
+        });
+        let summary = CiSummary {
+            branch: "main".to_string(),
+            runs: latest_runs(parse_runs(&response)),
+        };
+        assert_eq!(summary.runs.len(), 2);
+        assert_eq!(summary.status(), Some(CiStatus::Failure));
+        assert_eq!(summary.label(), "CI: 1 failed");
+        assert_eq!(CiSummary::default().label(), "");
+
+        assert_eq!(tail_lines("a\nb\nc", 2), "b\nc");
+    }
+    #[test]
+    fn test_workspace_ci_status_registers_the_panel_once() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(
+            dir.path().join(".git/config"),
+            "[remote \"origin\"]\n\turl = git@github.com:acme/app.git\n",
+        )
+        .unwrap();
+        let registry = McpServerRegistry::new();
+        let panels = SidebarPanelRegistry::new();
+
+        // Nothing to poll through until the github server is registered
+        assert!(
+            WorkspaceCiStatus::start(&registry, &panels, dir.path(), "").is_none()
+        );
+
+        registry
+            .register_server(
+                GITHUB_SERVER_ID.to_string(),
+                Box::new(GitMcpServer::new(CatalystIgnoreConfig::default())),
+            )
+            .unwrap();
+        let first =
+            WorkspaceCiStatus::start(&registry, &panels, dir.path(), "").unwrap();
+        let second =
+            WorkspaceCiStatus::start(&registry, &panels, dir.path(), "").unwrap();
+        assert!(panels.get_panel(CiStatusPanel::ID).is_some());
+
+        second.stop(&panels);
+        assert!(panels.get_panel(CiStatusPanel::ID).is_some());
+        first.stop(&panels);
+        assert!(panels.get_panel(CiStatusPanel::ID).is_none());
+    }
+}
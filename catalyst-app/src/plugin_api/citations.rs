@@ -0,0 +1,337 @@
+//! Context Citations
+//!
+//! This module tracks where the context of a request came from so answers
+//! can cite it. Every file range and tool result added to a request gets a
+//! numbered source, presented to the model as `[S1]`, `[S2]`, ... with an
+//! instruction to cite them. The markers in the answer are then resolved
+//! back to their sources and shown as clickable citations under the answer
+//! that jump to the file and range.
+
+use floem::{
+    View,
+    views::{Decorators, label},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::plugin_api::{ContextChunk, virtual_list_view};
+
+const ROW_HEIGHT: f64 = 20.0;
+
+/// Where a piece of context came from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceLocation {
+    /// Lines of a file, 1-based and inclusive
+    File {
+        path: PathBuf,
+        start_line: usize,
+        end_line: usize,
+    },
+    /// Result of a tool call
+    ToolResult {
+        server_id: String,
+        tool_name: String,
+        call_id: String,
+    },
+    /// A workspace memory
+    Memory { id: u64 },
+    /// Output the user shared from a terminal
+    Terminal { title: String },
+    /// Lines of a virtual document, 1-based and inclusive
+    Document {
+        uri: String,
+        start_line: usize,
+        end_line: usize,
+    },
+}
+
+impl SourceLocation {
+    /// Get the text shown for the source
+    pub fn label(&self) -> String {
+        match self {
+            Self::File {
+                path,
+                start_line,
+                end_line,
+            } if start_line == end_line => {
+                format!("{}:{}", path.display(), start_line)
+            }
+            Self::File {
+                path,
+                start_line,
+                end_line,
+            } => format!("{}:{}-{}", path.display(), start_line, end_line),
+            Self::ToolResult {
+                server_id,
+                tool_name,
+                ..
+            } => format!("{} ({})", tool_name, server_id),
+            Self::Memory { id } => format!("memory #{}", id),
+            Self::Terminal { title } => format!("terminal {}", title),
+            Self::Document {
+                uri,
+                start_line,
+                end_line,
+            } => format!("{}:{}-{}", uri, start_line, end_line),
+        }
+    }
+}
+
+/// A numbered piece of context included in a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextSource {
+    /// Number the model cites the source by, as `[S<id>]`
+    pub id: usize,
+    pub location: SourceLocation,
+    pub content: String,
+}
+
+impl ContextSource {
+    /// Get the marker citing the source
+    pub fn marker(&self) -> String {
+        format!("[S{}]", self.id)
+    }
+}
+
+/// Collects the sources of a request's context
+///
+/// Adding the same location twice returns the existing source, so a chunk
+/// found by several retrievers is cited once.
+#[derive(Debug, Clone, Default)]
+pub struct SourceTracker {
+    sources: Vec<ContextSource>,
+    ids: HashMap<SourceLocation, usize>,
+}
+
+impl SourceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add context from a location, returning its source id
+    pub fn add(
+        &mut self,
+        location: SourceLocation,
+        content: impl Into<String>,
+    ) -> usize {
+        if let Some(id) = self.ids.get(&location) {
+            return *id;
+        }
+        let id = self.sources.len() + 1;
+        self.ids.insert(location.clone(), id);
+        self.sources.push(ContextSource {
+            id,
+            location,
+            content: content.into(),
+        });
+        id
+    }
+
+    /// Add a retrieved chunk
+    pub fn add_chunk(&mut self, chunk: &ContextChunk) -> usize {
+        self.add(
+            SourceLocation::File {
+                path: chunk.path.to_path_buf(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+            },
+            chunk.content.clone(),
+        )
+    }
+
+    /// Add the text of a tool result
+    pub fn add_tool_result(
+        &mut self,
+        server_id: &str,
+        tool_name: &str,
+        call_id: &str,
+        content: impl Into<String>,
+    ) -> usize {
+        self.add(
+            SourceLocation::ToolResult {
+                server_id: server_id.to_string(),
+                tool_name: tool_name.to_string(),
+                call_id: call_id.to_string(),
+            },
+            content,
+        )
+    }
+
+    pub fn sources(&self) -> &[ContextSource] {
+        &self.sources
+    }
+
+    pub fn into_sources(self) -> Vec<ContextSource> {
+        self.sources
+    }
+}
+
+/// Format sources for the prompt, with the instruction to cite them
+pub fn sources_prompt(sources: &[ContextSource]) -> String {
+    if sources.is_empty() {
+        return String::new();
+    }
+    let mut prompt = String::from(
+        "Context sources follow. When a statement relies on a source, cite it \
+         with its marker, e.g. [S1]. Don't cite sources you didn't use.\n",
+    );
+    for source in sources {
+        prompt.push_str(&format!(
+            "\n{} {}\n{}\n",
+            source.marker(),
+            source.location.label(),
+            source.content.trim_end()
+        ));
+    }
+    prompt
+}
+
+/// A source cited in an answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    /// Number shown in the answer, in order of first citation
+    pub number: usize,
+    pub source: ContextSource,
+}
+
+/// Find the sources an answer cites, in order of first citation
+///
+/// Markers for unknown sources are ignored; `[S1, S3]` cites both.
+pub fn cited_sources(answer: &str, sources: &[ContextSource]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for id in citation_markers(answer)
+        .into_iter()
+        .flat_map(|(_, ids)| ids)
+    {
+        if citations.iter().any(|citation| citation.source.id == id) {
+            continue;
+        }
+        if let Some(source) = sources.iter().find(|source| source.id == id) {
+            citations.push(Citation {
+                number: citations.len() + 1,
+                source: source.clone(),
+            });
+        }
+    }
+    citations
+}
+
+/// Replace `[S<id>]` markers with the citation numbers shown under the
+/// answer, dropping markers of unknown sources
+pub fn render_citation_markers(answer: &str, citations: &[Citation]) -> String {
+    let mut rendered = String::with_capacity(answer.len());
+    let mut last = 0;
+    for (range, ids) in citation_markers(answer) {
+        rendered.push_str(&answer[last..range.start]);
+        let numbers: Vec<String> = ids
+            .iter()
+            .filter_map(|id| {
+                citations
+                    .iter()
+                    .find(|citation| citation.source.id == *id)
+                    .map(|citation| citation.number.to_string())
+            })
+            .collect();
+        if numbers.is_empty() {
+            // Don't leave the space before a dropped marker behind
+            if rendered.ends_with(' ') {
+                rendered.pop();
+            }
+        } else {
+            rendered.push_str(&format!("[{}]", numbers.join(", ")));
+        }
+        last = range.end;
+    }
+    rendered.push_str(&answer[last..]);
+    rendered
+}
+
+/// Find the `[S1]` and `[S1, S2]` markers of an answer
+fn citation_markers(answer: &str) -> Vec<(std::ops::Range<usize>, Vec<usize>)> {
+    let mut markers = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = answer[offset..].find("[S").map(|i| i + offset) {
+        let Some(end) = answer[start..].find(']').map(|i| i + start) else {
+            break;
+        };
+        let ids: Option<Vec<usize>> = answer[start + 1..end]
+            .split(',')
+            .map(|part| part.trim().strip_prefix('S')?.parse().ok())
+            .collect();
+        match ids {
+            Some(ids) => {
+                markers.push((start..end + 1, ids));
+                offset = end + 1;
+            }
+            None => offset = start + 2,
+        }
+    }
+    markers
+}
+
+/// Create the list of citations shown under an answer
+///
+/// `open` is called with the location of a clicked citation, e.g. to jump
+/// to the file range.
+pub fn citations_view(
+    citations: Vec<Citation>,
+    open: impl Fn(&SourceLocation) + Clone + 'static,
+) -> Box<dyn View> {
+    let citations: im::Vector<Citation> = citations.into_iter().collect();
+    virtual_list_view(
+        move || citations.clone(),
+        |(_, citation): &(usize, Citation)| citation.number,
+        move |(_, citation)| {
+            let text = format!(
+                "[{}] {}",
+                citation.number,
+                citation.source.location.label()
+            );
+            let open = open.clone();
+            label(move || text.clone()).on_click_stop(move |_| {
+                open(&citation.source.location);
+            })
+        },
+        ROW_HEIGHT,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::SharedPath;
+
+    #[test]
+    fn test_resolves_citations_in_order_of_use() {
+        let mut tracker = SourceTracker::new();
+        let chunk = ContextChunk {
+            path: SharedPath::from("src/lib.rs"),
+            start_line: 10,
+            end_line: 20,
+            content: "fn parse() {}".to_string(),
+            modified: None,
+        };
+        assert_eq!(tracker.add_chunk(&chunk), 1);
+        assert_eq!(tracker.add_tool_result("git", "log", "call-1", "abc"), 2);
+        assert_eq!(tracker.add_chunk(&chunk), 1);
+        let sources = tracker.into_sources();
+        assert!(
+            sources_prompt(&sources)
+                .contains("[S1] src/lib.rs:10-20\nfn parse() {}")
+        );
+
+        let answer = "Parsing happens in `parse` [S2, S1]. See [S9] and [Sx].";
+        let citations = cited_sources(answer, &sources);
+        let cited: Vec<_> = citations
+            .iter()
+            .map(|citation| (citation.number, citation.source.id))
+            .collect();
+        assert_eq!(cited, vec![(1, 2), (2, 1)]);
+        assert_eq!(
+            render_citation_markers(answer, &citations),
+            "Parsing happens in `parse` [1, 2]. See and [Sx]."
+        );
+    }
+}
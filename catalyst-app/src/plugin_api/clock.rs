@@ -0,0 +1,132 @@
+//! Clock
+//!
+//! This module lets code that stamps times, waits or measures deadlines take
+//! its clock as a parameter. [`SystemClock`] is the real one, except in
+//! [deterministic mode](crate::plugin_api::Determinism), where it reads the
+//! run's [`ManualClock`]; [`current_time`] reads it for code without a clock
+//! of its own. A [`ManualClock`] only moves when told to, and sleeping on it
+//! advances it instead of blocking, so tests of timeouts and health
+//! timestamps run instantly.
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::plugin_api::deterministic_clock;
+
+/// Source of time
+pub trait Clock: Send + Sync {
+    /// Get the wall-clock time
+    fn now(&self) -> SystemTime;
+
+    /// Get the monotonic time, for deadlines
+    fn instant(&self) -> Instant;
+
+    /// Wait for `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock, or the frozen one in deterministic mode
+///
+/// Sleeping always takes real time, so background loops don't spin in
+/// deterministic mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    /// Get the real clock as a shared clock
+    pub fn shared() -> Arc<dyn Clock> {
+        Arc::new(SystemClock)
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        deterministic_clock()
+            .map(|clock| clock.now())
+            .unwrap_or_else(SystemTime::now)
+    }
+
+    fn instant(&self) -> Instant {
+        deterministic_clock()
+            .map(|clock| clock.instant())
+            .unwrap_or_else(Instant::now)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Get the time of the [`SystemClock`]
+pub fn current_time() -> SystemTime {
+    SystemClock.now()
+}
+
+/// Clock moved by hand
+pub struct ManualClock {
+    start: SystemTime,
+    base: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            start,
+            base: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock() += duration;
+    }
+
+    /// Get the time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{DegradationTracker, Subsystem};
+
+    #[test]
+    fn test_manual_clock() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Arc::new(ManualClock::new(start));
+        let deadline = clock.instant() + Duration::from_secs(30);
+        clock.sleep(Duration::from_secs(31));
+        assert!(clock.instant() > deadline);
+        assert_eq!(clock.now(), start + Duration::from_secs(31));
+
+        // Degradations are ordered by the injected clock, without sleeping
+        let tracker = DegradationTracker::with_clock(clock.clone());
+        tracker.report(Subsystem::Provider("b".to_string()), "timeout", "a");
+        clock.advance(Duration::from_secs(60));
+        tracker.report(Subsystem::Provider("a".to_string()), "timeout", "b");
+        let status = tracker.status();
+        assert_eq!(status[0].subsystem, Subsystem::Provider("b".to_string()));
+        assert_eq!(
+            status[1].since.duration_since(status[0].since).unwrap(),
+            Duration::from_secs(60)
+        );
+    }
+}
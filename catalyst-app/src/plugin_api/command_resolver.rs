@@ -0,0 +1,433 @@
+//! Command Resolver
+//!
+//! This module turns the command of a server definition into something that
+//! can be spawned on the current platform. On Windows, tools installed by
+//! npm are `.cmd` shims that `CreateProcess` cannot run directly: they are
+//! looked up through `PATHEXT` and run through `cmd.exe` with its own quoting
+//! rules, or through PowerShell when only a `.ps1` shim exists or `cmd.exe`
+//! is unavailable.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::plugin_api::{McpServerInfo, ProjectTask};
+
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// Platform whose rules the resolver follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPlatform {
+    Unix,
+    Windows,
+}
+
+impl CommandPlatform {
+    /// Get the platform Catalyst is running on
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Unix
+        }
+    }
+}
+
+/// A command ready to be spawned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Pre-quoted argument string that must be passed verbatim, used for
+    /// `cmd.exe /c` whose parsing differs from other programs
+    pub raw_args: Option<String>,
+}
+
+impl ResolvedCommand {
+    /// Build a `Command` for the resolved program and arguments
+    pub fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(_raw) = &self.raw_args {
+            #[cfg(windows)]
+            {
+                use std::os::windows::process::CommandExt;
+                command.raw_arg(_raw);
+            }
+        }
+        command
+    }
+}
+
+/// Platform-aware lookup of server executables
+#[derive(Debug, Clone)]
+pub struct CommandResolver {
+    platform: CommandPlatform,
+    search_path: Vec<PathBuf>,
+    path_extensions: Vec<String>,
+    /// `cmd.exe`, absent in PowerShell-only environments
+    comspec: Option<PathBuf>,
+    powershell: Option<PathBuf>,
+}
+
+impl CommandResolver {
+    /// Create a resolver from the environment of the current process
+    pub fn from_env() -> Self {
+        Self::new(
+            CommandPlatform::current(),
+            std::env::var_os("PATH"),
+            std::env::var_os("PATHEXT"),
+            std::env::var_os("ComSpec").map(PathBuf::from),
+        )
+    }
+
+    /// Create a resolver for a platform with the given `PATH`, `PATHEXT` and
+    /// `ComSpec` values
+    pub fn new(
+        platform: CommandPlatform,
+        path: Option<OsString>,
+        path_ext: Option<OsString>,
+        comspec: Option<PathBuf>,
+    ) -> Self {
+        let search_path: Vec<PathBuf> = match (&path, platform) {
+            (Some(path), CommandPlatform::Windows) => path
+                .to_string_lossy()
+                .split(';')
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| PathBuf::from(dir.trim_matches('"')))
+                .collect(),
+            (Some(path), CommandPlatform::Unix) => {
+                std::env::split_paths(path).collect()
+            }
+            (None, _) => Vec::new(),
+        };
+        let path_extensions = path_ext
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_PATHEXT.to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_lowercase())
+            .collect();
+        let comspec = comspec.filter(|path| path.is_file());
+
+        let mut resolver = Self {
+            platform,
+            search_path,
+            path_extensions,
+            comspec,
+            powershell: None,
+        };
+        if platform == CommandPlatform::Windows {
+            resolver.powershell = ["pwsh", "powershell"]
+                .iter()
+                .find_map(|name| resolver.find_executable(name));
+        }
+        resolver
+    }
+
+    /// Resolve a program and its arguments
+    pub fn resolve(
+        &self,
+        program: &str,
+        args: &[String],
+    ) -> Result<ResolvedCommand> {
+        let path = self.find_executable(program).ok_or_else(|| {
+            anyhow::anyhow!("Could not find '{}' on the PATH", program)
+        })?;
+
+        if self.platform == CommandPlatform::Unix {
+            return Ok(ResolvedCommand {
+                program: path,
+                args: args.to_vec(),
+                raw_args: None,
+            });
+        }
+
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "cmd" | "bat" => {
+                if let Some(comspec) = &self.comspec {
+                    return Ok(ResolvedCommand {
+                        program: comspec.clone(),
+                        args: Vec::new(),
+                        raw_args: Some(cmd_command_line(&path, args)),
+                    });
+                }
+                // Without cmd.exe, a PowerShell shim next to the batch file
+                // is the only way to run the tool
+                let ps1 = path.with_extension("ps1");
+                if ps1.is_file() {
+                    return self.powershell_command(&ps1, args);
+                }
+                Err(anyhow::anyhow!(
+                    "'{}' needs cmd.exe, which is not available",
+                    path.display()
+                ))
+            }
+            "ps1" => self.powershell_command(&path, args),
+            _ => Ok(ResolvedCommand {
+                program: path,
+                args: args.to_vec(),
+                raw_args: None,
+            }),
+        }
+    }
+
+    /// Build the command for an MCP server definition
+    pub fn server_command(&self, info: &McpServerInfo) -> Result<Command> {
+        let (program, command_args) =
+            info.command.split_first().ok_or_else(|| {
+                anyhow::anyhow!("MCP server '{}' has no command", info.id)
+            })?;
+        let args: Vec<String> = command_args
+            .iter()
+            .chain(info.args.iter())
+            .cloned()
+            .collect();
+
+        let mut command = self.resolve(program, &args)?.to_command();
+        command.envs(&info.env);
+        if let Some(dir) = &info.working_directory {
+            command.current_dir(dir);
+        }
+        Ok(command)
+    }
+
+    /// Build the command for a task, with the workspace environment added
+    pub fn task_command(
+        &self,
+        task: &ProjectTask,
+        workspace_env: &HashMap<String, String>,
+    ) -> Result<Command> {
+        let (program, args) = task.command.split_first().ok_or_else(|| {
+            anyhow::anyhow!("Task '{}' has no command", task.label)
+        })?;
+        let mut command = self.resolve(program, args)?.to_command();
+        command.envs(workspace_env);
+        command.current_dir(&task.working_directory);
+        Ok(command)
+    }
+
+    fn powershell_command(
+        &self,
+        script: &Path,
+        args: &[String],
+    ) -> Result<ResolvedCommand> {
+        let powershell = self.powershell.clone().ok_or_else(|| {
+            anyhow::anyhow!(
+                "'{}' needs PowerShell, which is not available",
+                script.display()
+            )
+        })?;
+        let mut ps_args = vec![
+            "-NoLogo".to_string(),
+            "-NoProfile".to_string(),
+            "-ExecutionPolicy".to_string(),
+            "Bypass".to_string(),
+            "-File".to_string(),
+            script.to_string_lossy().to_string(),
+        ];
+        ps_args.extend(args.iter().cloned());
+        Ok(ResolvedCommand {
+            program: powershell,
+            args: ps_args,
+            raw_args: None,
+        })
+    }
+
+    /// Find an executable by name or path, trying `PATHEXT` extensions and
+    /// `.ps1` on Windows
+    pub fn find_executable(&self, program: &str) -> Option<PathBuf> {
+        let program_path = Path::new(program);
+        let has_dir = program.contains('/')
+            || (self.platform == CommandPlatform::Windows && program.contains('\\'));
+        let candidates_in = |dir: &Path| -> Vec<PathBuf> {
+            let base = dir.join(program_path);
+            if self.platform == CommandPlatform::Unix {
+                return vec![base];
+            }
+            let mut candidates = Vec::new();
+            if base.extension().is_some() {
+                candidates.push(base.clone());
+            }
+            for ext in self
+                .path_extensions
+                .iter()
+                .map(String::as_str)
+                .chain([".ps1"])
+            {
+                let mut name = base.as_os_str().to_owned();
+                name.push(ext);
+                candidates.push(PathBuf::from(name));
+            }
+            candidates
+        };
+
+        if has_dir || program_path.is_absolute() {
+            return candidates_in(Path::new(""))
+                .into_iter()
+                .find(|path| self.is_executable(path));
+        }
+        self.search_path
+            .iter()
+            .flat_map(|dir| candidates_in(dir))
+            .find(|path| self.is_executable(path))
+    }
+
+    fn is_executable(&self, path: &Path) -> bool {
+        let Ok(metadata) = path.metadata() else {
+            return false;
+        };
+        if !metadata.is_file() {
+            return false;
+        }
+        #[cfg(unix)]
+        if self.platform == CommandPlatform::Unix {
+            use std::os::unix::fs::PermissionsExt;
+            return metadata.permissions().mode() & 0o111 != 0;
+        }
+        true
+    }
+}
+
+/// Quote an argument following the `CommandLineToArgvW` rules
+pub fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quote an argument for a batch file run through `cmd.exe`, escaping the
+/// characters cmd would otherwise interpret
+fn quote_cmd_arg(arg: &str) -> String {
+    let quoted = quote_windows_arg(arg);
+    let mut escaped = String::with_capacity(quoted.len());
+    for c in quoted.chars() {
+        if matches!(c, '^' | '&' | '|' | '<' | '>' | '(' | ')' | '%' | '!' | '"') {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Build the arguments of `cmd.exe` that run a batch file
+///
+/// `/s` makes cmd strip exactly the outer quotes, so the script path and
+/// arguments keep their own quoting.
+fn cmd_command_line(script: &Path, args: &[String]) -> String {
+    let mut line = quote_cmd_arg(&script.to_string_lossy());
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote_cmd_arg(arg));
+    }
+    format!("/d /s /c \"{}\"", line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn test_windows_cmd_shim_with_spaces() {
+        let root = tempfile::tempdir().unwrap();
+        let node_dir = root.path().join("Program Files").join("nodejs");
+        let comspec = root.path().join("System32").join("cmd.exe");
+        touch(&node_dir.join("npx.cmd"));
+        touch(&node_dir.join("npx.ps1"));
+        touch(&comspec);
+
+        let resolver = CommandResolver::new(
+            CommandPlatform::Windows,
+            Some(format!("\"{}\"", node_dir.display()).into()),
+            Some(".EXE;.CMD".into()),
+            Some(comspec.clone()),
+        );
+        let args = vec![
+            "-y".to_string(),
+            "@modelcontextprotocol/server-github".to_string(),
+            "C:\\My Repos\\a&b".to_string(),
+        ];
+        let resolved = resolver.resolve("npx", &args).unwrap();
+        assert_eq!(resolved.program, comspec);
+        assert_eq!(
+            resolved.raw_args.unwrap(),
+            format!(
+                "/d /s /c \"^\"{}^\" -y @modelcontextprotocol/server-github \
+                 ^\"C:\\My Repos\\a^&b^\"\"",
+                node_dir.join("npx.cmd").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_windows_powershell_only() {
+        let root = tempfile::tempdir().unwrap();
+        let bin = root.path().join("bin");
+        let pwsh = root.path().join("PowerShell").join("pwsh.exe");
+        touch(&bin.join("npx.cmd"));
+        touch(&bin.join("npx.ps1"));
+        touch(&pwsh);
+
+        let path = format!("{};{}", bin.display(), pwsh.parent().unwrap().display());
+        let resolver = CommandResolver::new(
+            CommandPlatform::Windows,
+            Some(path.into()),
+            None,
+            None,
+        );
+        let resolved = resolver.resolve("npx", &["server".to_string()]).unwrap();
+        assert_eq!(resolved.program, pwsh);
+        assert_eq!(
+            resolved.args[4..],
+            [
+                "-File".to_string(),
+                bin.join("npx.ps1").to_string_lossy().to_string(),
+                "server".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote_windows_arg() {
+        assert_eq!(quote_windows_arg("plain"), "plain");
+        assert_eq!(quote_windows_arg(""), "\"\"");
+        assert_eq!(quote_windows_arg("a b"), "\"a b\"");
+        assert_eq!(
+            quote_windows_arg("C:\\dir with space\\"),
+            "\"C:\\dir with space\\\\\""
+        );
+        assert_eq!(quote_windows_arg("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -0,0 +1,1007 @@
+//! Control Socket
+//!
+//! This module implements the headless control protocol that external
+//! clients (the CLI, a web frontend) use to drive the assistant over a local
+//! socket. Messages are newline-delimited JSON. Streamed responses arrive as
+//! numbered chunk and tool progress events; the server keeps at most
+//! `window` unacknowledged events in flight per stream and coalesces text
+//! chunks while a slow client catches up, so a stalled client never blocks
+//! the provider or grows an unbounded queue of tiny events.
+//!
+//! A server given a [`ToolRunner`] also runs the tools the assistant calls,
+//! streaming their progress and results before the end of the response.
+//! A server created for a registry also adds the changes of the paths the
+//! assistant watches to its next request.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::log::LogConfig;
+use crate::plugin_api::{
+    AiAssistantPlugin, AiMessageRequest, AiMessageResponse, AiStreamEvent,
+    DEFAULT_AI_STREAM_CAPACITY, DegradationTracker, LogController, McpContent,
+    McpServerRegistry, McpToolResult, PathChangeStream, ToolCall, ToolTarget,
+    UsageInfo, correlation_span, new_correlation_id, spawn_ai_stream,
+};
+
+/// Version of the control protocol spoken by this build
+pub const CONTROL_PROTOCOL_VERSION: u32 = 1;
+/// Unacknowledged events allowed per stream unless the client asks otherwise
+pub const DEFAULT_STREAM_WINDOW: u32 = 32;
+
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Message from a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Negotiate the protocol version and flow control window
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        window: Option<u32>,
+    },
+    /// Send a message to an assistant; the server answers with the id of the
+    /// stream carrying the response
+    Send {
+        request_id: u64,
+        #[serde(default)]
+        assistant: Option<String>,
+        request: Box<AiMessageRequest>,
+        #[serde(default = "default_stream")]
+        stream: bool,
+    },
+    /// The client has processed every event of a stream up to `seq`
+    Ack { stream_id: u64, seq: u64 },
+    /// Stop delivering events for a stream
+    Cancel { stream_id: u64 },
+    /// Change the log level of a module, or the default level if `module` is
+    /// missing, until the log settings change
+    SetLogLevel {
+        #[serde(default)]
+        module: Option<String>,
+        level: String,
+    },
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+/// Message from the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlEvent {
+    Welcome {
+        protocol_version: u32,
+        window: u32,
+    },
+    /// A stream was opened for a `Send` request
+    Accepted {
+        request_id: u64,
+        stream_id: u64,
+        /// Id on the log lines of the request
+        #[serde(default)]
+        correlation_id: Option<String>,
+    },
+    /// Streamed assistant output; text of several provider chunks may be
+    /// combined
+    Chunk {
+        stream_id: u64,
+        seq: u64,
+        #[serde(default)]
+        content: Option<String>,
+        #[serde(default)]
+        tool_call: Option<ToolCall>,
+    },
+    /// Progress of a tool the assistant called
+    ToolProgress {
+        stream_id: u64,
+        seq: u64,
+        tool_call_id: String,
+        message: String,
+        #[serde(default)]
+        fraction: Option<f32>,
+    },
+    /// Result of a tool the assistant called
+    ToolResult {
+        stream_id: u64,
+        seq: u64,
+        tool_call_id: String,
+        result: McpToolResult,
+    },
+    /// Full response of a non-streamed request
+    Completed {
+        stream_id: u64,
+        seq: u64,
+        response: AiMessageResponse,
+    },
+    Finished {
+        stream_id: u64,
+        seq: u64,
+        cancelled: bool,
+        #[serde(default)]
+        usage: Option<UsageInfo>,
+    },
+    /// Log levels in effect after a `SetLogLevel` request
+    LogLevels {
+        config: LogConfig,
+    },
+    Error {
+        #[serde(default)]
+        stream_id: Option<u64>,
+        #[serde(default)]
+        seq: Option<u64>,
+        message: String,
+    },
+}
+
+impl ControlEvent {
+    /// Get the stream and sequence number of a stream event
+    pub fn stream_position(&self) -> Option<(u64, u64)> {
+        match self {
+            ControlEvent::Chunk { stream_id, seq, .. }
+            | ControlEvent::ToolProgress { stream_id, seq, .. }
+            | ControlEvent::ToolResult { stream_id, seq, .. }
+            | ControlEvent::Completed { stream_id, seq, .. }
+            | ControlEvent::Finished { stream_id, seq, .. } => {
+                Some((*stream_id, *seq))
+            }
+            ControlEvent::Error {
+                stream_id: Some(stream_id),
+                seq: Some(seq),
+                ..
+            } => Some((*stream_id, *seq)),
+            _ => None,
+        }
+    }
+
+    /// Check if this is the last event of its stream
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ControlEvent::Completed { .. }
+                | ControlEvent::Finished { .. }
+                | ControlEvent::Error {
+                    stream_id: Some(_),
+                    ..
+                }
+        )
+    }
+
+    fn set_seq(&mut self, value: u64) {
+        match self {
+            ControlEvent::Chunk { seq, .. }
+            | ControlEvent::ToolProgress { seq, .. }
+            | ControlEvent::ToolResult { seq, .. }
+            | ControlEvent::Completed { seq, .. }
+            | ControlEvent::Finished { seq, .. } => *seq = value,
+            ControlEvent::Error { seq, .. } => *seq = Some(value),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct StreamState {
+    pending: VecDeque<ControlEvent>,
+    sent_seq: u64,
+    acked_seq: u64,
+    cancelled: bool,
+    terminated: bool,
+}
+
+/// Outgoing events of one stream
+struct StreamQueue {
+    stream_id: u64,
+    state: Mutex<StreamState>,
+}
+
+impl StreamQueue {
+    fn new(stream_id: u64) -> Self {
+        Self {
+            stream_id,
+            state: Mutex::new(StreamState::default()),
+        }
+    }
+
+    fn push(&self, event: ControlEvent) {
+        let mut state = self.state.lock();
+        if state.terminated || (state.cancelled && !event.is_terminal()) {
+            return;
+        }
+        state.terminated = event.is_terminal();
+
+        match (state.pending.back_mut(), &event) {
+            (
+                Some(ControlEvent::Chunk {
+                    content: Some(pending),
+                    tool_call: None,
+                    ..
+                }),
+                ControlEvent::Chunk {
+                    content: Some(content),
+                    tool_call: None,
+                    ..
+                },
+            ) => {
+                pending.push_str(content);
+                return;
+            }
+            (
+                Some(ControlEvent::ToolProgress {
+                    tool_call_id: pending_id,
+                    message: pending_message,
+                    fraction: pending_fraction,
+                    ..
+                }),
+                ControlEvent::ToolProgress {
+                    tool_call_id,
+                    message,
+                    fraction,
+                    ..
+                },
+            ) if pending_id == tool_call_id => {
+                // Only the latest progress of a tool matters
+                *pending_message = message.clone();
+                *pending_fraction = *fraction;
+                return;
+            }
+            _ => {}
+        }
+        state.pending.push_back(event);
+    }
+
+    fn next_sendable(&self, window: u32) -> Option<ControlEvent> {
+        let mut state = self.state.lock();
+        if state.sent_seq - state.acked_seq >= window as u64 {
+            return None;
+        }
+        let mut event = state.pending.pop_front()?;
+        state.sent_seq += 1;
+        event.set_seq(state.sent_seq);
+        Some(event)
+    }
+
+    fn ack(&self, seq: u64) {
+        let mut state = self.state.lock();
+        state.acked_seq = state.acked_seq.max(seq.min(state.sent_seq));
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock();
+        if state.terminated {
+            return;
+        }
+        state.cancelled = true;
+        state.pending.clear();
+        drop(state);
+        self.push(ControlEvent::Finished {
+            stream_id: self.stream_id,
+            seq: 0,
+            cancelled: true,
+            usage: None,
+        });
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.lock().cancelled
+    }
+
+    fn is_drained(&self) -> bool {
+        let state = self.state.lock();
+        state.terminated && state.pending.is_empty()
+    }
+}
+
+/// Looks up an assistant by id, or the default one for `None`
+pub type AssistantLookup =
+    Arc<dyn Fn(Option<&str>) -> Option<Arc<dyn AiAssistantPlugin>> + Send + Sync>;
+
+/// Runs a tool the assistant called, reporting its progress on the way
+pub type ToolRunner =
+    Arc<dyn Fn(&ToolCall, &ToolProgressReporter) -> McpToolResult + Send + Sync>;
+
+/// Reports the progress of one tool call to the client of its stream
+pub struct ToolProgressReporter {
+    tool_call_id: String,
+    queue: Arc<StreamQueue>,
+}
+
+impl ToolProgressReporter {
+    /// Report progress of the tool, with the fraction done if it is known
+    pub fn report(&self, message: &str, fraction: Option<f32>) {
+        self.queue.push(ControlEvent::ToolProgress {
+            stream_id: self.queue.stream_id,
+            seq: 0,
+            tool_call_id: self.tool_call_id.clone(),
+            message: message.to_string(),
+            fraction,
+        });
+    }
+}
+
+/// Create a tool runner calling the tools of the servers in `registry`
+///
+/// Tools are called by the qualified names they are offered to the model
+/// under, such as `git__log`.
+pub fn registry_tool_runner(registry: McpServerRegistry) -> ToolRunner {
+    let tracker = DegradationTracker::new();
+    Arc::new(move |call, progress| match ToolTarget::parse(&call.name) {
+        ToolTarget::Server {
+            server_id,
+            tool_name,
+        } => {
+            progress.report(
+                &format!("Calling '{}' on '{}'", tool_name, server_id),
+                None,
+            );
+            registry.call_tool_degraded(
+                &server_id,
+                &tool_name,
+                call.arguments.clone(),
+                &tracker,
+            )
+        }
+        ToolTarget::Builtin(name) => McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                data: serde_json::Value::String(format!(
+                    "Tool '{}' is not provided by an MCP server",
+                    name
+                )),
+                hint: None,
+            }],
+            is_error: true,
+        },
+    })
+}
+
+/// Server side of the control protocol
+pub struct ControlServer {
+    assistants: AssistantLookup,
+    /// Runs the tools the assistants call, if the server runs them
+    tools: Option<ToolRunner>,
+    /// Changes of watched paths not yet sent to an assistant
+    path_changes: Option<PathChangeStream>,
+    next_stream_id: AtomicU64,
+    streams: Mutex<HashMap<u64, Arc<StreamQueue>>>,
+}
+
+impl ControlServer {
+    /// Create a control server for the assistants returned by `assistants`,
+    /// leaving the tools they call to the client
+    pub fn new(assistants: AssistantLookup) -> Arc<Self> {
+        Self::build(assistants, None, None)
+    }
+
+    /// Create a control server that runs the tools the assistants call with
+    /// `tools`
+    pub fn with_tools(assistants: AssistantLookup, tools: ToolRunner) -> Arc<Self> {
+        Self::build(assistants, Some(tools), None)
+    }
+
+    /// Create a control server that runs the tools the assistants call on
+    /// the servers of `registry`, and tells them how the paths they watch
+    /// changed
+    pub fn with_registry(
+        assistants: AssistantLookup,
+        registry: McpServerRegistry,
+    ) -> Arc<Self> {
+        let path_changes = registry.path_changes();
+        Self::build(
+            assistants,
+            Some(registry_tool_runner(registry)),
+            Some(path_changes),
+        )
+    }
+
+    fn build(
+        assistants: AssistantLookup,
+        tools: Option<ToolRunner>,
+        path_changes: Option<PathChangeStream>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            assistants,
+            tools,
+            path_changes,
+            next_stream_id: AtomicU64::new(1),
+            streams: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Listen on the default control socket, serving each client on its own
+    /// thread
+    pub fn listen(self: &Arc<Self>) -> Result<()> {
+        let path = Directory::control_socket()
+            .ok_or_else(|| anyhow::anyhow!("Can't get the control socket path"))?;
+        self.listen_on(&path)
+    }
+
+    /// Listen on a socket path, serving each client on its own thread
+    pub fn listen_on(self: &Arc<Self>, path: &Path) -> Result<()> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = interprocess::local_socket::LocalSocketListener::bind(path)?;
+        tracing::info!("Control socket listening on {}", path.display());
+
+        for stream in listener.incoming().flatten() {
+            let server = self.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = stream.set_nonblocking(true) {
+                    tracing::error!("{:?}", err);
+                    return;
+                }
+                if let Err(err) = server.serve_connection(stream) {
+                    tracing::warn!("Control client disconnected: {err:#}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve one client until it disconnects
+    ///
+    /// The stream must be non-blocking: reads and writes that would block
+    /// return `WouldBlock`, which lets one thread interleave both directions.
+    pub fn serve_connection<S: Read + Write>(&self, mut stream: S) -> Result<()> {
+        let mut window = DEFAULT_STREAM_WINDOW;
+        let mut own_streams: Vec<u64> = Vec::new();
+        let mut incoming = Vec::new();
+        let mut outgoing: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 8192];
+
+        let result = loop {
+            let mut progressed = false;
+
+            match stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    progressed = true;
+                    incoming.extend_from_slice(&buf[..n]);
+                    while let Some(pos) = incoming.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = incoming.drain(..=pos).collect();
+                        if line.iter().all(|b| b.is_ascii_whitespace()) {
+                            continue;
+                        }
+                        let reply = match serde_json::from_slice(&line) {
+                            Ok(request) => self.handle_request(
+                                request,
+                                &mut window,
+                                &mut own_streams,
+                            ),
+                            Err(err) => Some(ControlEvent::Error {
+                                stream_id: None,
+                                seq: None,
+                                message: format!("Invalid request: {}", err),
+                            }),
+                        };
+                        if let Some(reply) = reply {
+                            write_event(&mut outgoing, &reply)?;
+                        }
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => break Err(err.into()),
+            }
+
+            // Only pull more events once the previous ones are written, so
+            // events wait in their queues where they can still be coalesced
+            if outgoing.is_empty() {
+                let streams = self.streams.lock();
+                for stream_id in &own_streams {
+                    if let Some(queue) = streams.get(stream_id) {
+                        while let Some(event) = queue.next_sendable(window) {
+                            write_event(&mut outgoing, &event)?;
+                        }
+                    }
+                }
+            }
+
+            if !outgoing.is_empty() {
+                match stream.write(&outgoing) {
+                    Ok(n) => {
+                        outgoing.drain(..n);
+                        progressed |= n > 0;
+                    }
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            ErrorKind::WouldBlock | ErrorKind::Interrupted
+                        ) => {}
+                    Err(err) => break Err(err.into()),
+                }
+                if outgoing.is_empty() {
+                    let _ = stream.flush();
+                }
+            }
+
+            if outgoing.is_empty() {
+                let mut streams = self.streams.lock();
+                own_streams.retain(|stream_id| {
+                    let drained = streams
+                        .get(stream_id)
+                        .map(|queue| queue.is_drained())
+                        .unwrap_or(true);
+                    if drained {
+                        streams.remove(stream_id);
+                    }
+                    !drained
+                });
+            }
+
+            if !progressed {
+                std::thread::sleep(IDLE_POLL_INTERVAL);
+            }
+        };
+
+        let mut streams = self.streams.lock();
+        for stream_id in own_streams {
+            if let Some(queue) = streams.remove(&stream_id) {
+                queue.cancel();
+            }
+        }
+        result
+    }
+
+    fn handle_request(
+        &self,
+        request: ControlRequest,
+        window: &mut u32,
+        own_streams: &mut Vec<u64>,
+    ) -> Option<ControlEvent> {
+        match request {
+            ControlRequest::Hello {
+                protocol_version,
+                window: requested,
+            } => {
+                if protocol_version != CONTROL_PROTOCOL_VERSION {
+                    return Some(ControlEvent::Error {
+                        stream_id: None,
+                        seq: None,
+                        message: format!(
+                            "Unsupported protocol version {}, expected {}",
+                            protocol_version, CONTROL_PROTOCOL_VERSION
+                        ),
+                    });
+                }
+                if let Some(requested) = requested {
+                    *window = requested.max(1);
+                }
+                Some(ControlEvent::Welcome {
+                    protocol_version: CONTROL_PROTOCOL_VERSION,
+                    window: *window,
+                })
+            }
+            ControlRequest::Send {
+                request_id,
+                assistant,
+                mut request,
+                stream,
+            } => {
+                let Some(plugin) = (self.assistants)(assistant.as_deref()) else {
+                    return Some(ControlEvent::Error {
+                        stream_id: None,
+                        seq: None,
+                        message: format!(
+                            "Unknown assistant '{}'",
+                            assistant.unwrap_or_default()
+                        ),
+                    });
+                };
+
+                if let Some(message) = self
+                    .path_changes
+                    .as_ref()
+                    .and_then(PathChangeStream::take_message)
+                {
+                    request.messages.push(message);
+                }
+
+                let stream_id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+                let queue = Arc::new(StreamQueue::new(stream_id));
+                self.streams.lock().insert(stream_id, queue.clone());
+                own_streams.push(stream_id);
+                let correlation_id = new_correlation_id();
+                let span = correlation_span("control", &correlation_id);
+                let tools = self.tools.clone();
+                std::thread::spawn(move || {
+                    let _span = span.entered();
+                    tracing::debug!(
+                        request_id,
+                        stream_id,
+                        "Control request accepted"
+                    );
+                    run_request(plugin, tools, *request, stream, stream_id, queue)
+                });
+
+                Some(ControlEvent::Accepted {
+                    request_id,
+                    stream_id,
+                    correlation_id: Some(correlation_id),
+                })
+            }
+            ControlRequest::Ack { stream_id, seq } => {
+                if let Some(queue) = self.streams.lock().get(&stream_id) {
+                    queue.ack(seq);
+                }
+                None
+            }
+            ControlRequest::Cancel { stream_id } => {
+                if let Some(queue) = self.streams.lock().get(&stream_id) {
+                    queue.cancel();
+                }
+                None
+            }
+            ControlRequest::SetLogLevel { module, level } => {
+                let result = LogController::global()
+                    .ok_or_else(|| anyhow::anyhow!("Logging is not configured"))
+                    .and_then(|controller| {
+                        controller.set_level(module.as_deref(), &level)
+                    });
+                Some(match result {
+                    Ok(config) => ControlEvent::LogLevels { config },
+                    Err(err) => ControlEvent::Error {
+                        stream_id: None,
+                        seq: None,
+                        message: err.to_string(),
+                    },
+                })
+            }
+        }
+    }
+}
+
+fn run_request(
+    plugin: Arc<dyn AiAssistantPlugin>,
+    tools: Option<ToolRunner>,
+    request: AiMessageRequest,
+    stream: bool,
+    stream_id: u64,
+    queue: Arc<StreamQueue>,
+) {
+    let mut tool_calls = Vec::new();
+    let result = if stream {
+        let mut result = Err(anyhow::anyhow!("The stream ended without completing"));
+        // The queue never blocks, so the channel only needs to absorb bursts
+        for event in spawn_ai_stream(plugin, request, DEFAULT_AI_STREAM_CAPACITY) {
+            if queue.is_cancelled() {
+                // Dropping the stream makes the provider's next send fail
+                return;
+            }
+            match event {
+                AiStreamEvent::Chunk(chunk) => {
+                    tool_calls.extend(chunk.tool_call.clone());
+                    if chunk.content.is_some() || chunk.tool_call.is_some() {
+                        queue.push(ControlEvent::Chunk {
+                            stream_id,
+                            seq: 0,
+                            content: chunk.content,
+                            tool_call: chunk.tool_call,
+                        });
+                    }
+                }
+                AiStreamEvent::Completed(completion) => {
+                    result = Ok(ControlEvent::Finished {
+                        stream_id,
+                        seq: 0,
+                        cancelled: false,
+                        usage: completion.usage,
+                    });
+                }
+                AiStreamEvent::Error(err) => result = Err(anyhow::anyhow!(err)),
+            }
+        }
+        result
+    } else {
+        plugin.send_message(request).map(|response| {
+            tool_calls.extend(response.tool_calls.clone().unwrap_or_default());
+            ControlEvent::Completed {
+                stream_id,
+                seq: 0,
+                response,
+            }
+        })
+    };
+
+    // The response ends the stream, so the tools run before it is sent
+    if let (Ok(_), Some(tools)) = (&result, &tools) {
+        for call in &tool_calls {
+            if queue.is_cancelled() {
+                return;
+            }
+            let progress = ToolProgressReporter {
+                tool_call_id: call.id.clone(),
+                queue: queue.clone(),
+            };
+            queue.push(ControlEvent::ToolResult {
+                stream_id,
+                seq: 0,
+                tool_call_id: call.id.clone(),
+                result: tools(call, &progress),
+            });
+        }
+    }
+
+    queue.push(result.unwrap_or_else(|err| ControlEvent::Error {
+        stream_id: Some(stream_id),
+        seq: Some(0),
+        message: format!("{err:#}"),
+    }));
+}
+
+fn write_event(outgoing: &mut Vec<u8>, event: &ControlEvent) -> Result<()> {
+    serde_json::to_writer(&mut *outgoing, event)?;
+    outgoing.push(b'\n');
+    Ok(())
+}
+
+/// Client side of the control protocol, acknowledging events as they are
+/// read
+pub struct ControlClient<S: Read + Write> {
+    stream: S,
+    buffer: Vec<u8>,
+}
+
+impl ControlClient<interprocess::local_socket::LocalSocketStream> {
+    /// Connect to the control socket of a running Catalyst
+    pub fn connect() -> Result<Self> {
+        let path = Directory::control_socket()
+            .ok_or_else(|| anyhow::anyhow!("Can't get the control socket path"))?;
+        Ok(Self::new(
+            interprocess::local_socket::LocalSocketStream::connect(path)?,
+        ))
+    }
+}
+
+impl<S: Read + Write> ControlClient<S> {
+    /// Create a client over a blocking stream
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Send a request
+    pub fn send(&mut self, request: &ControlRequest) -> Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.stream.write_all(&line)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Read the next event, acknowledging it, or `None` when the server
+    /// closed the connection
+    pub fn next_event(&mut self) -> Result<Option<ControlEvent>> {
+        let mut buf = [0u8; 8192];
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let event: ControlEvent = serde_json::from_slice(&line)?;
+                if let Some((stream_id, seq)) = event.stream_position() {
+                    self.send(&ControlRequest::Ack { stream_id, seq })?;
+                }
+                return Ok(Some(event));
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(n) => self.buffer.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        AiAuthData, AiAuthResult, AiCapability, AiPluginInfo, AiStreamChunk,
+        AiStreamCompletion, AiStreamSender, AiUsageInfo,
+    };
+    use std::os::unix::net::UnixStream;
+
+    /// Streams 200 numbers, then calls `git__log` if `calls_tool` is set
+    struct CountingAssistant {
+        calls_tool: bool,
+    }
+
+    impl AiAssistantPlugin for CountingAssistant {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn plugin_info(&self) -> AiPluginInfo {
+            AiPluginInfo {
+                name: "counting".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                provider: "test".to_string(),
+                supports_streaming: true,
+                supports_tools: false,
+                supports_vision: false,
+            }
+        }
+
+        fn is_authenticated(&self) -> bool {
+            true
+        }
+
+        fn send_message(
+            &self,
+            _request: AiMessageRequest,
+        ) -> Result<AiMessageResponse> {
+            Err(anyhow::anyhow!("Only streaming is supported"))
+        }
+
+        fn stream_message(
+            &self,
+            _request: AiMessageRequest,
+            sender: &AiStreamSender,
+        ) -> Result<AiStreamCompletion> {
+            for i in 0..200 {
+                sender.send_text(format!("{} ", i))?;
+            }
+            if self.calls_tool {
+                sender.send(AiStreamChunk {
+                    content: None,
+                    tool_call: Some(ToolCall {
+                        id: "call-1".to_string(),
+                        name: "git__log".to_string(),
+                        arguments: serde_json::json!({}),
+                    }),
+                    finished: false,
+                })?;
+            }
+            Ok(AiStreamCompletion::default())
+        }
+
+        fn get_capabilities(&self) -> Vec<AiCapability> {
+            Vec::new()
+        }
+
+        fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+            Err(anyhow::anyhow!("Not supported"))
+        }
+
+        fn get_usage_info(&self) -> Option<AiUsageInfo> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_streamed_chunks_respect_window() {
+        let assistant: Arc<dyn AiAssistantPlugin> =
+            Arc::new(CountingAssistant { calls_tool: false });
+        let server = ControlServer::new(Arc::new(move |_| Some(assistant.clone())));
+        let (server_side, client_side) = UnixStream::pair().unwrap();
+        server_side.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || server.serve_connection(server_side));
+
+        let mut client = ControlClient::new(client_side);
+        client
+            .send(&ControlRequest::Hello {
+                protocol_version: CONTROL_PROTOCOL_VERSION,
+                window: Some(2),
+            })
+            .unwrap();
+        client
+            .send(&ControlRequest::Send {
+                request_id: 7,
+                assistant: None,
+                request: Box::new(AiMessageRequest {
+                    messages: Vec::new(),
+                    context: None,
+                    tools: None,
+                    model: None,
+                    max_tokens: None,
+                    temperature: None,
+                }),
+                stream: true,
+            })
+            .unwrap();
+
+        let mut text = String::new();
+        let mut last_seq = 0;
+        loop {
+            match client.next_event().unwrap().unwrap() {
+                ControlEvent::Welcome { window, .. } => assert_eq!(window, 2),
+                ControlEvent::Accepted { request_id, .. } => {
+                    assert_eq!(request_id, 7)
+                }
+                ControlEvent::Chunk { seq, content, .. } => {
+                    assert_eq!(seq, last_seq + 1);
+                    last_seq = seq;
+                    text.push_str(&content.unwrap());
+                }
+                ControlEvent::Finished { cancelled, .. } => {
+                    assert!(!cancelled);
+                    break;
+                }
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+
+        let expected: String = (0..200).map(|i| format!("{} ", i)).collect();
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn test_runs_tool_calls_with_progress() {
+        let assistant: Arc<dyn AiAssistantPlugin> =
+            Arc::new(CountingAssistant { calls_tool: true });
+        let tools: ToolRunner = Arc::new(|call, progress| {
+            progress.report(&format!("Running {}", call.name), Some(0.5));
+            McpToolResult {
+                content: Vec::new(),
+                is_error: false,
+            }
+        });
+        let server = ControlServer::with_tools(
+            Arc::new(move |_| Some(assistant.clone())),
+            tools,
+        );
+        let (server_side, client_side) = UnixStream::pair().unwrap();
+        server_side.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || server.serve_connection(server_side));
+
+        let mut client = ControlClient::new(client_side);
+        client
+            .send(&ControlRequest::Send {
+                request_id: 1,
+                assistant: None,
+                request: Box::new(AiMessageRequest {
+                    messages: Vec::new(),
+                    context: None,
+                    tools: None,
+                    model: None,
+                    max_tokens: None,
+                    temperature: None,
+                }),
+                stream: true,
+            })
+            .unwrap();
+
+        let mut tool_events = Vec::new();
+        loop {
+            match client.next_event().unwrap().unwrap() {
+                ControlEvent::ToolProgress {
+                    tool_call_id,
+                    message,
+                    fraction,
+                    ..
+                } => {
+                    assert_eq!(tool_call_id, "call-1");
+                    assert_eq!(fraction, Some(0.5));
+                    tool_events.push(message);
+                }
+                ControlEvent::ToolResult {
+                    tool_call_id,
+                    result,
+                    ..
+                } => {
+                    assert_eq!(tool_call_id, "call-1");
+                    assert!(!result.is_error);
+                    tool_events.push("result".to_string());
+                }
+                ControlEvent::Finished { .. } => break,
+                ControlEvent::Accepted { .. } | ControlEvent::Chunk { .. } => {}
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(tool_events, ["Running git__log", "result"]);
+    }
+}
@@ -0,0 +1,424 @@
+//! Conversation Mode
+//!
+//! This module decides which tools the model may use in a conversation. In
+//! Ask mode the model only answers from the context it is given and no
+//! tools are offered. In Agent mode it gets the tools of the MCP servers on
+//! the conversation's allowlist, plus the built-in tools such as
+//! `remember`. The mode and allowlist can change in the middle of a
+//! conversation; every tool call is checked against them when it is made,
+//! so a call the model planned under a wider policy is refused.
+//!
+//! Server tool results are cut to pages by the conversation's
+//! [`ToolOutputPager`], which also serves the `read_more_output` tool.
+//! Other built-in tools are registered on a workspace's [`BuiltinTools`] by
+//! the parts of Catalyst implementing them.
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    AiMessageRequest, DegradationTracker, McpContent, McpServerRegistry,
+    McpToolResult, READ_MORE_OUTPUT_TOOL_NAME, ToolCall, ToolDefinition,
+    ToolOutputLimits, ToolOutputPager,
+};
+
+/// Separates the server id from the tool name in the names given to the
+/// model, e.g. `git__log`
+pub const TOOL_NAME_SEPARATOR: &str = "__";
+
+/// Get the name a server's tool is offered to the model under
+pub fn qualified_tool_name(server_id: &str, tool_name: &str) -> String {
+    format!("{server_id}{TOOL_NAME_SEPARATOR}{tool_name}")
+}
+
+/// A part of Catalyst implementing tools for the model, such as the sparse
+/// index with `expand_index`
+pub trait BuiltinToolProvider: Send + Sync {
+    fn tool_definitions(&self) -> Vec<ToolDefinition>;
+
+    /// Run a call of one of the provider's tools, returning the text for the
+    /// model
+    fn handle_tool_call(&self, call: &ToolCall) -> Result<String>;
+}
+
+/// Built-in tools of a workspace
+///
+/// Clones share the same providers, so tools registered after a
+/// conversation started are offered from its next request on.
+#[derive(Clone, Default)]
+pub struct BuiltinTools {
+    providers: Arc<RwLock<Vec<Arc<dyn BuiltinToolProvider>>>>,
+}
+
+impl BuiltinTools {
+    pub fn register(&self, provider: Arc<dyn BuiltinToolProvider>) {
+        self.providers.write().push(provider);
+    }
+
+    /// Get the definitions of all registered tools, to offer with
+    /// [`ConversationPolicy::toolset`]
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.providers
+            .read()
+            .iter()
+            .flat_map(|provider| provider.tool_definitions())
+            .collect()
+    }
+
+    /// Run a built-in tool call allowed by
+    /// [`ConversationPolicy::check_tool_call`]
+    ///
+    /// Failures become error results the model can read.
+    pub fn call(&self, call: &ToolCall) -> McpToolResult {
+        let provider = self
+            .providers
+            .read()
+            .iter()
+            .find(|provider| {
+                provider
+                    .tool_definitions()
+                    .iter()
+                    .any(|tool| tool.name == call.name)
+            })
+            .cloned();
+        let Some(provider) = provider else {
+            return refused_result(format!("Unknown tool '{}'", call.name));
+        };
+        match provider.handle_tool_call(call) {
+            Ok(text) => McpToolResult {
+                content: vec![McpContent {
+                    content_type: "text".to_string(),
+                    data: serde_json::Value::String(text),
+                    hint: None,
+                }],
+                is_error: false,
+            },
+            Err(err) => refused_result(format!("{err:#}")),
+        }
+    }
+}
+
+/// What a tool name called by the model refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolTarget {
+    /// A tool implemented by Catalyst itself
+    Builtin(String),
+    Server {
+        server_id: String,
+        tool_name: String,
+    },
+}
+
+impl ToolTarget {
+    pub fn parse(name: &str) -> Self {
+        match name.split_once(TOOL_NAME_SEPARATOR) {
+            Some((server_id, tool_name))
+                if !server_id.is_empty() && !tool_name.is_empty() =>
+            {
+                Self::Server {
+                    server_id: server_id.to_string(),
+                    tool_name: tool_name.to_string(),
+                }
+            }
+            _ => Self::Builtin(name.to_string()),
+        }
+    }
+}
+
+/// What the model may do in a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationMode {
+    /// Answers only, from read-only context
+    #[default]
+    Ask,
+    /// Tools of the allowed servers may be called
+    Agent,
+}
+
+/// Mode and tool allowlist of a conversation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversationToolSettings {
+    pub mode: ConversationMode,
+    /// Servers whose tools are offered in Agent mode; kept while in Ask
+    /// mode so switching back restores them
+    #[serde(default)]
+    pub allowed_servers: BTreeSet<String>,
+    /// Whether mutating tool calls are planned instead of run; see
+    /// [`DryRunSession`](crate::plugin_api::DryRunSession)
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Shared tool policy of a conversation
+///
+/// The chat panel changes it while the agent loop reads it, so clones
+/// share the same settings.
+#[derive(Clone, Default)]
+pub struct ConversationPolicy {
+    settings: Arc<RwLock<ConversationToolSettings>>,
+    output: Arc<ToolOutputPager>,
+}
+
+impl ConversationPolicy {
+    pub fn new(settings: ConversationToolSettings) -> Self {
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+            output: Arc::default(),
+        }
+    }
+
+    pub fn settings(&self) -> ConversationToolSettings {
+        self.settings.read().clone()
+    }
+
+    pub fn mode(&self) -> ConversationMode {
+        self.settings.read().mode
+    }
+
+    pub fn set_mode(&self, mode: ConversationMode) {
+        self.settings.write().mode = mode;
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.settings.read().dry_run
+    }
+
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.settings.write().dry_run = dry_run;
+    }
+
+    pub fn output_limits(&self) -> ToolOutputLimits {
+        self.output.limits()
+    }
+
+    pub fn set_output_limits(&self, limits: ToolOutputLimits) {
+        self.output.set_limits(limits);
+    }
+
+    /// Add or remove a server from the allowlist
+    pub fn set_server_allowed(&self, server_id: &str, allowed: bool) {
+        let mut settings = self.settings.write();
+        if allowed {
+            settings.allowed_servers.insert(server_id.to_string());
+        } else {
+            settings.allowed_servers.remove(server_id);
+        }
+    }
+
+    /// Collect the tools to offer the model, or `None` in Ask mode
+    ///
+    /// `builtin` are the tools implemented by Catalyst itself; the
+    /// `read_more_output` tool is always added.
+    pub fn toolset(
+        &self,
+        registry: &McpServerRegistry,
+        tracker: &DegradationTracker,
+        builtin: Vec<ToolDefinition>,
+    ) -> Option<Vec<ToolDefinition>> {
+        let settings = self.settings();
+        if settings.mode == ConversationMode::Ask {
+            return None;
+        }
+        let mut tools = builtin;
+        tools.push(ToolOutputPager::tool_definition());
+        tools.extend(
+            registry
+                .available_tools(tracker)
+                .into_iter()
+                .filter(|(server_id, _)| {
+                    settings.allowed_servers.contains(server_id)
+                })
+                .map(|(server_id, tool)| ToolDefinition {
+                    name: qualified_tool_name(&server_id, &tool.name),
+                    description: tool.description.unwrap_or_default(),
+                    parameters: tool.input_schema,
+                    effect: tool.effect,
+                }),
+        );
+        Some(tools)
+    }
+
+    /// Set the tools of a request for the current mode
+    pub fn prepare_request(
+        &self,
+        request: &mut AiMessageRequest,
+        registry: &McpServerRegistry,
+        tracker: &DegradationTracker,
+        builtin: Vec<ToolDefinition>,
+    ) {
+        request.tools = self
+            .toolset(registry, tracker, builtin)
+            .filter(|tools| !tools.is_empty());
+    }
+
+    /// Check that a tool may be called now
+    pub fn check_tool_call(&self, name: &str) -> Result<ToolTarget> {
+        let settings = self.settings.read();
+        if settings.mode == ConversationMode::Ask {
+            return Err(anyhow::anyhow!(
+                "Tool '{}' can't be used: the conversation is in Ask mode",
+                name
+            ));
+        }
+        let target = ToolTarget::parse(name);
+        if let ToolTarget::Server { server_id, .. } = &target {
+            if !settings.allowed_servers.contains(server_id) {
+                return Err(anyhow::anyhow!(
+                    "Tool '{}' can't be used: server '{}' is not allowed in \
+                     this conversation",
+                    name,
+                    server_id
+                ));
+            }
+        }
+        Ok(target)
+    }
+
+    /// Call a server tool requested by the model
+    ///
+    /// A refused call becomes an error result the model can read, so the
+    /// turn continues. Long results are cut to their first page. Built-in
+    /// tools other than `read_more_output` are run by the caller after
+    /// [`Self::check_tool_call`], with [`BuiltinTools::call`].
+    pub fn call_server_tool(
+        &self,
+        call: &ToolCall,
+        registry: &McpServerRegistry,
+        tracker: &DegradationTracker,
+    ) -> McpToolResult {
+        match self.check_tool_call(&call.name) {
+            Ok(ToolTarget::Server {
+                server_id,
+                tool_name,
+            }) => self.output.limit(registry.call_tool_degraded(
+                &server_id,
+                &tool_name,
+                call.arguments.clone(),
+                tracker,
+            )),
+            Ok(ToolTarget::Builtin(name)) if name == READ_MORE_OUTPUT_TOOL_NAME => {
+                self.output
+                    .handle_tool_call(call)
+                    .unwrap_or_else(|err| refused_result(format!("{err:#}")))
+            }
+            Ok(ToolTarget::Builtin(name)) => refused_result(format!(
+                "Tool '{}' is not provided by an MCP server",
+                name
+            )),
+            Err(err) => refused_result(err.to_string()),
+        }
+    }
+}
+
+fn refused_result(message: String) -> McpToolResult {
+    McpToolResult {
+        content: vec![McpContent {
+            content_type: "text".to_string(),
+            data: serde_json::Value::String(message),
+            hint: None,
+        }],
+        is_error: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::WorkspaceMemoryStore;
+
+    #[test]
+    fn test_enforces_mode_and_allowlist() {
+        let policy = ConversationPolicy::default();
+        let registry = McpServerRegistry::new();
+        let tracker = DegradationTracker::new();
+        let builtin = vec![WorkspaceMemoryStore::remember_tool_definition()];
+        assert!(
+            policy
+                .toolset(&registry, &tracker, builtin.clone())
+                .is_none()
+        );
+        assert!(policy.check_tool_call("remember").is_err());
+
+        let shared = policy.clone();
+        shared.set_mode(ConversationMode::Agent);
+        shared.set_server_allowed("git", true);
+        assert_eq!(
+            policy.toolset(&registry, &tracker, builtin).unwrap().len(),
+            2
+        );
+        assert_eq!(
+            policy.check_tool_call("remember").unwrap(),
+            ToolTarget::Builtin("remember".to_string())
+        );
+        assert_eq!(
+            policy.check_tool_call("git__log").unwrap(),
+            ToolTarget::Server {
+                server_id: "git".to_string(),
+                tool_name: "log".to_string(),
+            }
+        );
+        assert!(policy.check_tool_call("shell__run").is_err());
+
+        let call = ToolCall {
+            id: "1".to_string(),
+            name: "shell__run".to_string(),
+            arguments: serde_json::json!({}),
+        };
+        let result = policy.call_server_tool(&call, &registry, &tracker);
+        assert!(result.is_error);
+        assert!(
+            result.content[0]
+                .data
+                .as_str()
+                .unwrap()
+                .contains("not allowed")
+        );
+
+        // Switching back to Ask mode refuses calls planned in Agent mode
+        shared.set_mode(ConversationMode::Ask);
+        assert!(policy.check_tool_call("git__log").is_err());
+        assert!(policy.settings().allowed_servers.contains("git"));
+    }
+
+    struct Echo;
+
+    impl BuiltinToolProvider for Echo {
+        fn tool_definitions(&self) -> Vec<ToolDefinition> {
+            let mut tool = WorkspaceMemoryStore::remember_tool_definition();
+            tool.name = "echo".to_string();
+            vec![tool]
+        }
+
+        fn handle_tool_call(&self, call: &ToolCall) -> Result<String> {
+            call.arguments
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Nothing to echo"))
+        }
+    }
+
+    #[test]
+    fn test_builtin_tools() {
+        let tools = BuiltinTools::default();
+        tools.clone().register(Arc::new(Echo));
+        assert_eq!(tools.definitions()[0].name, "echo");
+
+        let mut call = ToolCall {
+            id: "1".to_string(),
+            name: "echo".to_string(),
+            arguments: serde_json::json!("hi"),
+        };
+        let result = tools.call(&call);
+        assert!(!result.is_error);
+        assert_eq!(result.content[0].data, "hi");
+
+        call.arguments = serde_json::json!({});
+        assert!(tools.call(&call).is_error);
+        call.name = "missing".to_string();
+        assert!(tools.call(&call).is_error);
+    }
+}
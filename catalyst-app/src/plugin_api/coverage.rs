@@ -0,0 +1,440 @@
+//! Code Coverage
+//!
+//! This module reads the coverage reports written by test tasks, in LCOV
+//! (`lcov.info`) or Cobertura XML format, and makes the per-line hit counts
+//! available to the editor gutter and the context layer. When the assistant
+//! is asked to write tests, retrieved chunks with uncovered lines are ranked
+//! higher and the prompt lists which lines of the files in context are not
+//! covered yet.
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
+
+use crate::plugin_api::RankedChunk;
+
+/// Report files looked for in the workspace, relative to its root
+pub const COVERAGE_REPORT_PATHS: &[&str] = &[
+    "lcov.info",
+    "coverage/lcov.info",
+    "target/coverage/lcov.info",
+    "target/llvm-cov/lcov.info",
+    "coverage.xml",
+    "cobertura.xml",
+    "coverage/cobertura-coverage.xml",
+    "target/tarpaulin/cobertura.xml",
+];
+
+/// Check if a changed file is one of the reports looked for in a workspace
+pub fn is_coverage_report(workspace_root: &Path, path: &Path) -> bool {
+    path.strip_prefix(workspace_root).is_ok_and(|relative| {
+        COVERAGE_REPORT_PATHS
+            .iter()
+            .any(|report| relative == Path::new(report))
+    })
+}
+
+/// Relative boost given to a chunk whose instrumented lines are all
+/// uncovered, when prioritizing untested code
+pub const DEFAULT_UNTESTED_BOOST: f32 = 0.5;
+
+static COBERTURA_SOURCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<source>([^<]*)</source>").unwrap());
+static COBERTURA_CLASS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<class\b[^>]*?\bfilename="([^"]*)"[^>]*>(.*?)</class>"#)
+        .unwrap()
+});
+static COBERTURA_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<line\b[^>]*?\bnumber="(\d+)"[^>]*?\bhits="(\d+)""#).unwrap()
+});
+
+/// Hit counts of the instrumented lines of a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileCoverage {
+    /// Hits by 1-based line number
+    pub lines: BTreeMap<u32, u64>,
+}
+
+impl FileCoverage {
+    fn record(&mut self, line: u32, hits: u64) {
+        let entry = self.lines.entry(line).or_default();
+        *entry = (*entry).max(hits);
+    }
+
+    /// Get the hits of a 1-based line, or `None` if it isn't instrumented
+    pub fn hits(&self, line: u32) -> Option<u64> {
+        self.lines.get(&line).copied()
+    }
+
+    pub fn covered_count(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+
+    /// Get the share of instrumented lines that were run, from 0 to 1
+    pub fn ratio(&self) -> f32 {
+        if self.lines.is_empty() {
+            return 1.0;
+        }
+        self.covered_count() as f32 / self.lines.len() as f32
+    }
+
+    /// Get the runs of uncovered lines as inclusive, 1-based ranges;
+    /// lines that aren't instrumented don't break a run
+    pub fn uncovered_ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        let mut current: Option<(u32, u32)> = None;
+        for (line, hits) in &self.lines {
+            match (*hits == 0, current) {
+                (true, Some((start, _))) => current = Some((start, *line)),
+                (true, None) => current = Some((*line, *line)),
+                (false, Some(range)) => {
+                    ranges.push(range);
+                    current = None;
+                }
+                (false, None) => {}
+            }
+        }
+        ranges.extend(current);
+        ranges
+    }
+
+    /// Get the share of the instrumented lines in a range that are
+    /// uncovered, or `None` if none are instrumented
+    pub fn uncovered_share(&self, start_line: u32, end_line: u32) -> Option<f32> {
+        let (total, uncovered) = self.lines.range(start_line..=end_line).fold(
+            (0, 0),
+            |(total, uncovered), (_, hits)| {
+                (total + 1, uncovered + usize::from(*hits == 0))
+            },
+        );
+        (total > 0).then(|| uncovered as f32 / total as f32)
+    }
+}
+
+/// Coverage of a workspace, keyed by absolute file path
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    files: HashMap<PathBuf, Arc<FileCoverage>>,
+    /// When the report file was written
+    pub generated_at: Option<SystemTime>,
+}
+
+impl CoverageReport {
+    /// Load a report file, detecting its format; relative paths in the
+    /// report are resolved against `workspace_root`
+    pub fn load(path: &Path, workspace_root: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut report = if content.trim_start().starts_with('<') {
+            Self::parse_cobertura(&content, workspace_root)
+        } else {
+            Self::parse_lcov(&content, workspace_root)
+        };
+        if report.files.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No coverage data found in {}",
+                path.display()
+            ));
+        }
+        report.generated_at = std::fs::metadata(path)?.modified().ok();
+        Ok(report)
+    }
+
+    /// Load the newest report found at the usual output locations of
+    /// coverage tools
+    pub fn find_in_workspace(workspace_root: &Path) -> Option<Self> {
+        COVERAGE_REPORT_PATHS
+            .iter()
+            .map(|path| workspace_root.join(path))
+            .filter_map(|path| {
+                let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+                Some((modified, path))
+            })
+            .max_by_key(|(modified, _)| *modified)
+            .and_then(|(_, path)| match Self::load(&path, workspace_root) {
+                Ok(report) => Some(report),
+                Err(err) => {
+                    tracing::warn!("Skipping coverage report: {err:#}");
+                    None
+                }
+            })
+    }
+
+    /// Parse an LCOV trace file
+    pub fn parse_lcov(content: &str, workspace_root: &Path) -> Self {
+        let mut files: HashMap<PathBuf, FileCoverage> = HashMap::new();
+        let mut current: Option<PathBuf> = None;
+        for line in content.lines() {
+            let line = line.trim();
+            if let Some(path) = line.strip_prefix("SF:") {
+                current = Some(resolve(workspace_root, Path::new(path)));
+            } else if let Some(data) = line.strip_prefix("DA:") {
+                let mut fields = data.split(',');
+                let (Some(number), Some(hits)) = (fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                if let (Some(path), Ok(number), Ok(hits)) =
+                    (&current, number.parse(), hits.parse())
+                {
+                    files.entry(path.clone()).or_default().record(number, hits);
+                }
+            } else if line == "end_of_record" {
+                current = None;
+            }
+        }
+        Self::from_files(files)
+    }
+
+    /// Parse a Cobertura XML report
+    pub fn parse_cobertura(content: &str, workspace_root: &Path) -> Self {
+        let sources: Vec<PathBuf> = COBERTURA_SOURCE
+            .captures_iter(content)
+            .map(|source| resolve(workspace_root, Path::new(source[1].trim())))
+            .collect();
+        let mut files: HashMap<PathBuf, FileCoverage> = HashMap::new();
+        for class in COBERTURA_CLASS.captures_iter(content) {
+            let filename = Path::new(&class[1]);
+            // Class file names are relative to one of the sources
+            let path = sources
+                .iter()
+                .map(|source| source.join(filename))
+                .find(|path| path.is_file())
+                .unwrap_or_else(|| resolve(workspace_root, filename));
+            let coverage = files.entry(path).or_default();
+            for line in COBERTURA_LINE.captures_iter(&class[2]) {
+                if let (Ok(number), Ok(hits)) = (line[1].parse(), line[2].parse()) {
+                    coverage.record(number, hits);
+                }
+            }
+        }
+        Self::from_files(files)
+    }
+
+    fn from_files(files: HashMap<PathBuf, FileCoverage>) -> Self {
+        Self {
+            files: files
+                .into_iter()
+                .filter(|(_, coverage)| !coverage.lines.is_empty())
+                .map(|(path, coverage)| (path, Arc::new(coverage)))
+                .collect(),
+            generated_at: None,
+        }
+    }
+
+    /// Get the coverage of a file by absolute path
+    pub fn file(&self, path: &Path) -> Option<&Arc<FileCoverage>> {
+        self.files.get(path)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&PathBuf, &Arc<FileCoverage>)> {
+        self.files.iter()
+    }
+
+    /// Get the share of instrumented lines run across the workspace
+    pub fn total_ratio(&self) -> f32 {
+        let (covered, total) = self.files.values().fold((0, 0), |acc, file| {
+            (acc.0 + file.covered_count(), acc.1 + file.lines.len())
+        });
+        if total == 0 {
+            1.0
+        } else {
+            covered as f32 / total as f32
+        }
+    }
+
+    /// Get the files with the most uncovered lines
+    pub fn least_covered_files(&self, limit: usize) -> Vec<(PathBuf, usize)> {
+        let mut files: Vec<(PathBuf, usize)> = self
+            .files
+            .iter()
+            .map(|(path, file)| {
+                (path.clone(), file.lines.len() - file.covered_count())
+            })
+            .filter(|(_, uncovered)| *uncovered > 0)
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files.truncate(limit);
+        files
+    }
+
+    /// Rank chunks with uncovered lines higher, for requests to write tests
+    ///
+    /// A chunk's score grows by up to `boost` times, in proportion to the
+    /// share of its instrumented lines that are uncovered.
+    pub fn prioritize_untested(&self, ranked: &mut [RankedChunk], boost: f32) {
+        for chunk in ranked.iter_mut() {
+            let share = self
+                .file(&chunk.chunk.path)
+                .and_then(|file| {
+                    file.uncovered_share(
+                        chunk.chunk.start_line as u32,
+                        chunk.chunk.end_line as u32,
+                    )
+                })
+                .unwrap_or(0.0);
+            chunk.score *= 1.0 + boost * share;
+        }
+        ranked.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.chunk.key().cmp(&b.chunk.key()))
+        });
+    }
+
+    /// Describe the uncovered lines of files for the prompt
+    pub fn coverage_prompt<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Path>,
+        workspace_root: &Path,
+    ) -> String {
+        let mut lines = Vec::new();
+        for path in paths {
+            let Some(file) = self.file(path) else {
+                continue;
+            };
+            let ranges = file
+                .uncovered_ranges()
+                .iter()
+                .map(|(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{}-{}", start, end)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!(
+                "- {}: {:.0}% of lines covered{}",
+                path.strip_prefix(workspace_root).unwrap_or(path).display(),
+                file.ratio() * 100.0,
+                if ranges.is_empty() {
+                    String::new()
+                } else {
+                    format!("; uncovered lines {}", ranges)
+                }
+            ));
+        }
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!(
+            "Test coverage of the files in context (prioritize the uncovered \
+             code):\n{}",
+            lines.join("\n")
+        )
+    }
+}
+
+/// Make a report path absolute and drop `.` and `..` components, so it
+/// matches the paths of open documents
+fn resolve(workspace_root: &Path, path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in workspace_root.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// The latest coverage report, shared by the editor and the context layer
+#[derive(Clone, Default)]
+pub struct CoverageStore {
+    report: Arc<RwLock<Option<Arc<CoverageReport>>>>,
+}
+
+impl CoverageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn report(&self) -> Option<Arc<CoverageReport>> {
+        self.report.read().clone()
+    }
+
+    pub fn set(&self, report: Option<CoverageReport>) {
+        *self.report.write() = report.map(Arc::new);
+    }
+
+    /// Reload the report after a test task finished, returning whether one
+    /// was found
+    pub fn reload(&self, workspace_root: &Path) -> bool {
+        let report = CoverageReport::find_in_workspace(workspace_root);
+        let found = report.is_some();
+        self.set(report);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{ContextChunk, RetrievalSource};
+
+    #[test]
+    fn test_parses_reports_and_prioritizes_untested_code() {
+        let root = Path::new("/work");
+        let lcov = "TN:\nSF:src/lib.rs\nDA:1,3\nDA:2,0\nDA:3,0\nDA:5,1\n\
+                    DA:6,0\nend_of_record\n";
+        let report = CoverageReport::parse_lcov(lcov, root);
+        let file = report.file(Path::new("/work/src/lib.rs")).unwrap();
+        assert_eq!(file.hits(2), Some(0));
+        assert_eq!(file.uncovered_ranges(), vec![(2, 3), (6, 6)]);
+        assert_eq!(file.uncovered_share(1, 3), Some(2.0 / 3.0));
+        assert!(
+            report
+                .coverage_prompt([Path::new("/work/src/lib.rs")], root)
+                .contains(
+                    "src/lib.rs: 40% of lines covered; uncovered lines 2-3, 6"
+                )
+        );
+
+        let cobertura = r#"<?xml version="1.0"?>
+            <coverage><sources><source>/missing</source></sources><packages>
+            <package><classes><class name="app" filename="./app/main.py">
+            <lines><line number="4" hits="0"/><line number="5" hits="2"/></lines>
+            </class></classes></package></packages></coverage>"#;
+        let report = CoverageReport::parse_cobertura(cobertura, root);
+        let file = report.file(Path::new("/work/app/main.py")).unwrap();
+        assert_eq!(file.ratio(), 0.5);
+
+        let chunk = |path: &str, start_line, score| RankedChunk {
+            chunk: ContextChunk {
+                path: path.into(),
+                start_line,
+                end_line: start_line + 1,
+                content: String::new(),
+                modified: None,
+            },
+            score,
+            sources: vec![RetrievalSource::Vector],
+        };
+        let report = CoverageReport::parse_lcov(lcov, root);
+        let mut ranked = vec![
+            chunk("/work/src/lib.rs", 5, 1.1),
+            chunk("/work/src/lib.rs", 2, 1.0),
+        ];
+        report.prioritize_untested(&mut ranked, DEFAULT_UNTESTED_BOOST);
+        assert_eq!(ranked[0].chunk.start_line, 2);
+    }
+
+    #[test]
+    fn test_is_coverage_report() {
+        let root = Path::new("/work");
+        assert!(is_coverage_report(
+            root,
+            Path::new("/work/coverage/lcov.info")
+        ));
+        assert!(!is_coverage_report(root, Path::new("/work/src/lcov.info")));
+        assert!(!is_coverage_report(root, Path::new("/other/lcov.info")));
+    }
+}
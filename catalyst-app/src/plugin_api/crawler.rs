@@ -0,0 +1,319 @@
+//! Workspace Crawler
+//!
+//! This module walks a workspace to collect the files that the context
+//! indexer and the AI assistant are allowed to see. It is the single place
+//! where `.gitignore`, `.catalystignore` and the global default patterns are
+//! applied to directory traversal. Each root of a multi-root workspace is
+//! walked with its own rules.
+//!
+//! Symbolic links are followed under [`SymlinkPolicy::Resolve`] as long as
+//! their target lies inside a workspace root, and skipped under
+//! [`SymlinkPolicy::Reject`]. As in the sandbox, a followed link and
+//! everything found through it are also checked against the
+//! `.catalystignore` rules of the root its target lies in.
+
+use ignore::WalkBuilder;
+use parking_lot::Mutex;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    CATALYST_IGNORE_FILE_NAME, CatalystIgnore, ContextScope, SymlinkPolicy,
+};
+
+/// Walks workspace files while honoring ignore rules
+pub struct WorkspaceCrawler {
+    ignore: Arc<CatalystIgnore>,
+    /// Other roots of a multi-root workspace
+    other_roots: Vec<Arc<CatalystIgnore>>,
+    scope: Option<ContextScope>,
+    max_file_size: Option<u64>,
+}
+
+impl WorkspaceCrawler {
+    /// Create a new crawler for the workspace the ignore rules belong to
+    pub fn new(ignore: Arc<CatalystIgnore>) -> Self {
+        Self {
+            ignore,
+            other_roots: Vec::new(),
+            scope: None,
+            max_file_size: None,
+        }
+    }
+
+    /// Also crawl another root of the workspace
+    pub fn with_root(mut self, ignore: Arc<CatalystIgnore>) -> Self {
+        self.other_roots.push(ignore);
+        self
+    }
+
+    /// Restrict crawling to a sub-project scope
+    pub fn with_scope(mut self, scope: ContextScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Skip files larger than the given number of bytes
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// Get the ignore rules of the primary root
+    pub fn ignore(&self) -> &Arc<CatalystIgnore> {
+        &self.ignore
+    }
+
+    /// Visit every file that is not excluded
+    pub fn for_each_file(&self, mut visit: impl FnMut(&Path)) {
+        let _ = self.try_for_each_file(|path| {
+            visit(path);
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// Visit the files that are not excluded until `visit` breaks
+    pub fn try_for_each_file(
+        &self,
+        mut visit: impl FnMut(&Path) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let canonical_roots: Arc<Vec<(PathBuf, Arc<CatalystIgnore>)>> = Arc::new(
+            std::iter::once(&self.ignore)
+                .chain(&self.other_roots)
+                .filter_map(|ignore| {
+                    let root = std::fs::canonicalize(ignore.root()).ok()?;
+                    Some((root, ignore.clone()))
+                })
+                .collect(),
+        );
+        for (root, ignore) in self.roots() {
+            let policy = ignore.symlink_policy();
+            let canonical_roots = canonical_roots.clone();
+            // Followed links to directories, with where they lead
+            let links: Mutex<Vec<(PathBuf, LinkTarget)>> = Mutex::new(Vec::new());
+            let walker = WalkBuilder::new(&root)
+                .hidden(false)
+                .follow_links(policy == SymlinkPolicy::Resolve)
+                .max_filesize(self.max_file_size)
+                .add_custom_ignore_filename(CATALYST_IGNORE_FILE_NAME)
+                .filter_entry(move |entry| {
+                    let path = entry.path();
+                    let is_dir =
+                        entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                    if entry.path_is_symlink() {
+                        let Some(target) =
+                            link_target(path, policy, &canonical_roots)
+                        else {
+                            return false;
+                        };
+                        if target.is_ignored(is_dir) {
+                            return false;
+                        }
+                        if is_dir {
+                            links.lock().push((path.to_path_buf(), target));
+                        }
+                    } else if linked_target(&links.lock(), path)
+                        .is_some_and(|target| target.is_ignored(is_dir))
+                    {
+                        return false;
+                    }
+                    !ignore.is_ignored_by_defaults(path, is_dir)
+                })
+                .build();
+
+            for entry in walker {
+                match entry {
+                    Ok(entry) => {
+                        if entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                            visit(entry.path())?;
+                        }
+                    }
+                    Err(err) => {
+                        tracing::debug!("Skipping entry while crawling: {}", err);
+                    }
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Collect every file that is not excluded
+    pub fn collect_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        self.for_each_file(|path| files.push(path.to_path_buf()));
+        files
+    }
+
+    /// Get the directories to walk with the rules applying to them
+    fn roots(&self) -> Vec<(PathBuf, Arc<CatalystIgnore>)> {
+        let ignores = || std::iter::once(&self.ignore).chain(&self.other_roots);
+        match &self.scope {
+            Some(scope) => scope
+                .roots
+                .iter()
+                .map(|root| {
+                    let ignore = ignores()
+                        .filter(|ignore| root.starts_with(ignore.root()))
+                        .max_by_key(|ignore| ignore.root().components().count())
+                        .unwrap_or(&self.ignore);
+                    (root.clone(), ignore.clone())
+                })
+                .collect(),
+            None => ignores()
+                .map(|ignore| (ignore.root().to_path_buf(), ignore.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Place a followed link leads to, under the root it lies in
+struct LinkTarget {
+    /// Target of the link, inside the root of `ignore`
+    path: PathBuf,
+    ignore: Arc<CatalystIgnore>,
+}
+
+impl LinkTarget {
+    fn is_ignored(&self, is_dir: bool) -> bool {
+        self.ignore.is_ignored(&self.path, is_dir)
+    }
+}
+
+/// Get where a link found while crawling leads, if it is followed, which
+/// it is only if the policy resolves links and its target lies inside a
+/// root
+fn link_target(
+    link: &Path,
+    policy: SymlinkPolicy,
+    canonical_roots: &[(PathBuf, Arc<CatalystIgnore>)],
+) -> Option<LinkTarget> {
+    if policy != SymlinkPolicy::Resolve {
+        return None;
+    }
+    let target = std::fs::canonicalize(link).ok()?;
+    let (root, ignore) = canonical_roots
+        .iter()
+        .filter(|(root, _)| target.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())?;
+    let relative = target.strip_prefix(root).unwrap_or(Path::new(""));
+    Some(LinkTarget {
+        path: ignore.root().join(relative),
+        ignore: ignore.clone(),
+    })
+}
+
+/// Get where a path found through followed links leads, from the
+/// innermost link it was found through
+fn linked_target(
+    links: &[(PathBuf, LinkTarget)],
+    path: &Path,
+) -> Option<LinkTarget> {
+    let (link, target) = links
+        .iter()
+        .filter(|(link, _)| path.starts_with(link))
+        .max_by_key(|(link, _)| link.components().count())?;
+    let relative = path.strip_prefix(link).unwrap_or(Path::new(""));
+    Some(LinkTarget {
+        path: target.path.join(relative),
+        ignore: target.ignore.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::CatalystIgnoreConfig;
+
+    fn ignore(root: &Path) -> Arc<CatalystIgnore> {
+        Arc::new(
+            CatalystIgnore::new(root, &CatalystIgnoreConfig::default()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ignore_rules_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        // `.gitignore` is only honored inside a repository
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("generated")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/left-pad")).unwrap();
+        std::fs::write(root.join(".gitignore"), "generated/\n").unwrap();
+        std::fs::write(root.join(".catalystignore"), "*.secret\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/api.secret"), "TOKEN=1").unwrap();
+        std::fs::write(root.join("src/data.json"), "x".repeat(2048)).unwrap();
+        std::fs::write(root.join("generated/schema.rs"), "").unwrap();
+        std::fs::write(root.join("node_modules/left-pad/index.js"), "").unwrap();
+
+        let files = WorkspaceCrawler::new(ignore(root))
+            .with_max_file_size(1024)
+            .collect_files();
+        assert!(files.contains(&root.join("src/main.rs")));
+        assert!(files.contains(&root.join(".gitignore")));
+        assert!(!files.contains(&root.join("src/api.secret")));
+        assert!(!files.contains(&root.join("src/data.json")));
+        assert!(!files.contains(&root.join("generated/schema.rs")));
+        assert!(!files.iter().any(|file| file.starts_with(root.join(".git"))));
+        assert!(
+            !files
+                .iter()
+                .any(|file| file.starts_with(root.join("node_modules")))
+        );
+    }
+
+    #[test]
+    fn test_scope_restricts_crawl_to_its_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("packages/api/src")).unwrap();
+        std::fs::create_dir_all(root.join("packages/web/src")).unwrap();
+        std::fs::write(root.join(".catalystignore"), "*.snap\n").unwrap();
+        std::fs::write(root.join("README.md"), "").unwrap();
+        std::fs::write(root.join("packages/api/src/lib.rs"), "").unwrap();
+        std::fs::write(root.join("packages/api/src/lib.snap"), "").unwrap();
+        std::fs::write(root.join("packages/web/src/index.ts"), "").unwrap();
+
+        let scope = ContextScope {
+            roots: vec![root.join("packages/api")],
+            max_tokens: 8_000,
+            latency_budget: std::time::Duration::from_millis(100),
+        };
+        let files = WorkspaceCrawler::new(ignore(root))
+            .with_scope(scope)
+            .collect_files();
+        // The rules of the workspace root still apply inside the scope
+        assert_eq!(files, vec![root.join("packages/api/src/lib.rs")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_follow_rules_of_their_target() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        let other = dir.path().join("shared");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(other.join("config")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(other.join("config/app.toml"), "").unwrap();
+        std::fs::write(other.join("config/secrets.env"), "TOKEN=1").unwrap();
+        std::fs::write(other.join("private.key"), "key").unwrap();
+        std::fs::write(other.join(".catalystignore"), "*.env\nprivate.key\n")
+            .unwrap();
+        symlink(other.join("config"), root.join("config")).unwrap();
+        symlink(other.join("private.key"), root.join("key")).unwrap();
+
+        let files = WorkspaceCrawler::new(ignore(&root))
+            .with_root(ignore(&other))
+            .collect_files();
+        assert!(files.contains(&root.join("src/main.rs")));
+        assert!(files.contains(&root.join("config/app.toml")));
+        assert!(!files.contains(&root.join("config/secrets.env")));
+        assert!(!files.contains(&root.join("key")));
+        assert!(!files.contains(&other.join("private.key")));
+    }
+}
@@ -0,0 +1,200 @@
+//! Credential Storage
+//!
+//! This module keeps secrets, such as API keys and secret workspace
+//! variables, out of settings or workspace files that may be shared or
+//! committed. The global store keeps them in the system keychain, moving
+//! over any `credentials.toml` left in the config directory by earlier
+//! versions. Where no keychain is available it falls back to that file,
+//! which is only readable by the current user. Secrets are included in
+//! settings bundles only when they are encrypted.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{CREDENTIALS_FILE, write_atomic};
+
+/// Keychain service the secrets are stored under
+const KEYCHAIN_SERVICE: &str = "catalyst";
+/// Keychain account holding all secrets as one TOML table
+const KEYCHAIN_ACCOUNT: &str = "credentials";
+
+static GLOBAL: Lazy<CredentialStore> = Lazy::new(|| {
+    let path = Directory::config_directory()
+        .unwrap_or_default()
+        .join(CREDENTIALS_FILE);
+    CredentialStore::open_keychain(&path)
+        .or_else(|err| {
+            tracing::warn!(
+                "System keychain is unavailable, keeping secrets in {}: {err:#}",
+                path.display()
+            );
+            CredentialStore::open(&path)
+        })
+        .unwrap_or_else(|err| {
+            tracing::error!("Failed to read {}: {err:#}", path.display());
+            CredentialStore {
+                backend: Backend::File(path),
+                entries: RwLock::new(BTreeMap::new()),
+            }
+        })
+});
+
+/// Where a store persists its secrets
+enum Backend {
+    Keychain(keyring::Entry),
+    File(PathBuf),
+}
+
+/// Store of secrets keyed by name
+pub struct CredentialStore {
+    backend: Backend,
+    entries: RwLock<BTreeMap<String, String>>,
+}
+
+impl CredentialStore {
+    /// Get the store in the config directory
+    pub fn global() -> &'static CredentialStore {
+        &GLOBAL
+    }
+
+    /// Open a store backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            backend: Backend::File(path.to_path_buf()),
+            entries: RwLock::new(read_file(path)?),
+        })
+    }
+
+    /// Open the store in the system keychain, moving the secrets of the
+    /// plaintext file `legacy` into it
+    fn open_keychain(legacy: &Path) -> Result<Self> {
+        let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)?;
+        let entries: BTreeMap<String, String> = match entry.get_password() {
+            Ok(table) => toml::from_str(&table).map_err(|e| {
+                anyhow::anyhow!("Invalid keychain credentials: {}", e)
+            })?,
+            Err(keyring::Error::NoEntry) => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let store = Self {
+            backend: Backend::Keychain(entry),
+            entries: RwLock::new(entries),
+        };
+        if legacy.is_file() {
+            {
+                let mut entries = store.entries.write();
+                for (key, value) in read_file(legacy)? {
+                    entries.entry(key).or_insert(value);
+                }
+                store.save(&entries)?;
+            }
+            std::fs::remove_file(legacy)?;
+            tracing::info!(
+                "Moved secrets from {} to the system keychain",
+                legacy.display()
+            );
+        }
+        Ok(store)
+    }
+
+    /// Get the file the secrets are stored in, or `None` if they are in the
+    /// system keychain
+    pub fn path(&self) -> Option<&Path> {
+        match &self.backend {
+            Backend::Keychain(_) => None,
+            Backend::File(path) => Some(path),
+        }
+    }
+
+    /// Get a secret
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.read().get(key).cloned()
+    }
+
+    /// Get all stored secrets, to redact them from text shared with the
+    /// assistant
+    pub fn values(&self) -> Vec<String> {
+        self.entries.read().values().cloned().collect()
+    }
+
+    /// Check if a secret is stored
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.read().contains_key(key)
+    }
+
+    /// Get all stored secrets by name
+    pub fn entries(&self) -> BTreeMap<String, String> {
+        self.entries.read().clone()
+    }
+
+    /// Store several secrets at once
+    pub fn extend(&self, secrets: BTreeMap<String, String>) -> Result<()> {
+        let mut entries = self.entries.write();
+        entries.extend(secrets);
+        self.save(&entries)
+    }
+
+    /// Store a secret
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut entries = self.entries.write();
+        entries.insert(key.to_string(), value.to_string());
+        self.save(&entries)
+    }
+
+    /// Get the secret stored for an environment variable
+    pub fn get_env(&self, name: &str) -> Option<String> {
+        self.get(&env_key(name))
+    }
+
+    /// Store a secret for an environment variable, used by `${NAME}`
+    /// references when the variable is not set
+    pub fn set_env(&self, name: &str, value: &str) -> Result<()> {
+        self.set(&env_key(name), value)
+    }
+
+    /// Remove a secret, returning whether it was stored
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        let mut entries = self.entries.write();
+        if entries.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.save(&entries)?;
+        Ok(true)
+    }
+
+    fn save(&self, entries: &BTreeMap<String, String>) -> Result<()> {
+        let table = toml::to_string(entries)?;
+        let path = match &self.backend {
+            Backend::Keychain(entry) => {
+                entry.set_password(&table)?;
+                return Ok(());
+            }
+            Backend::File(path) => path,
+        };
+        write_atomic(path, table.as_bytes())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    toml::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+}
+
+/// Get the key the secret of an environment variable is stored under
+pub fn env_key(name: &str) -> String {
+    format!("env:{}", name)
+}
@@ -0,0 +1,469 @@
+//! Graceful Degradation
+//!
+//! This module defines what happens when a subsystem fails so the assistant
+//! keeps working with reduced quality instead of erroring mid-turn:
+//!
+//! | Failure                  | Fallback                                     |
+//! |--------------------------|----------------------------------------------|
+//! | Index corrupt or failing | Plain scan of the workspace files            |
+//! | Provider unavailable     | Next provider in the failover order          |
+//! | MCP server failing       | Its tools are removed from the agent toolset |
+//!
+//! Every active fallback is recorded in a [`DegradationTracker`], whose
+//! status is shown to the user and cleared once the subsystem recovers.
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    AiAssistantPlugin, AiAuthData, AiAuthResult, AiCapability, AiMessageRequest,
+    AiMessageResponse, AiPluginInfo, AiStreamCompletion, AiStreamSender,
+    AiUsageInfo, Clock, ContextChunk, RetrievalSource, Retriever, SystemClock,
+    WorkspaceCrawler, chunk_text,
+};
+
+/// Lines per chunk produced by the workspace scan fallback
+const SCAN_CHUNK_LINES: usize = 40;
+
+/// A part of the system that can fail independently
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Subsystem {
+    Index(RetrievalSource),
+    Provider(String),
+    McpServer(String),
+}
+
+/// An active fallback for a failing subsystem
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Degradation {
+    pub subsystem: Subsystem,
+    /// Why the subsystem is considered failing
+    pub reason: String,
+    /// What is used instead
+    pub fallback: String,
+    pub since: SystemTime,
+}
+
+/// Records the subsystems currently running in a degraded mode
+pub struct DegradationTracker {
+    active: RwLock<HashMap<Subsystem, Degradation>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for DegradationTracker {
+    fn default() -> Self {
+        Self::with_clock(SystemClock::shared())
+    }
+}
+
+impl DegradationTracker {
+    /// Create a tracker with no degradations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a tracker stamping degradations with the time of `clock`
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            active: RwLock::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    /// Record that a subsystem failed and a fallback is in use
+    ///
+    /// Reporting an already degraded subsystem updates the reason but keeps
+    /// the time the degradation started.
+    pub fn report(&self, subsystem: Subsystem, reason: &str, fallback: &str) {
+        let mut active = self.active.write();
+        match active.get_mut(&subsystem) {
+            Some(degradation) => {
+                degradation.reason = reason.to_string();
+                degradation.fallback = fallback.to_string();
+            }
+            None => {
+                tracing::warn!(
+                    "{:?} degraded ({}), falling back to {}",
+                    subsystem,
+                    reason,
+                    fallback
+                );
+                active.insert(
+                    subsystem.clone(),
+                    Degradation {
+                        subsystem,
+                        reason: reason.to_string(),
+                        fallback: fallback.to_string(),
+                        since: self.clock.now(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Record that a subsystem works again
+    pub fn recover(&self, subsystem: &Subsystem) {
+        if self.active.write().remove(subsystem).is_some() {
+            tracing::info!("{:?} recovered", subsystem);
+        }
+    }
+
+    /// Check if a subsystem is currently degraded
+    pub fn is_degraded(&self, subsystem: &Subsystem) -> bool {
+        self.active.read().contains_key(subsystem)
+    }
+
+    /// Get all current degradations, oldest first
+    pub fn status(&self) -> Vec<Degradation> {
+        let mut status: Vec<Degradation> =
+            self.active.read().values().cloned().collect();
+        status.sort_by_key(|degradation| degradation.since);
+        status
+    }
+}
+
+/// Retrieval that falls back to a second retriever while the primary index
+/// fails
+pub struct FallbackRetriever {
+    primary: Arc<dyn Retriever>,
+    fallback: Arc<dyn Retriever>,
+    tracker: Arc<DegradationTracker>,
+}
+
+impl FallbackRetriever {
+    /// Create a retriever using `fallback` whenever `primary` errors
+    pub fn new(
+        primary: Arc<dyn Retriever>,
+        fallback: Arc<dyn Retriever>,
+        tracker: Arc<DegradationTracker>,
+    ) -> Self {
+        Self {
+            primary,
+            fallback,
+            tracker,
+        }
+    }
+}
+
+impl Retriever for FallbackRetriever {
+    fn source(&self) -> RetrievalSource {
+        self.primary.source()
+    }
+
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>> {
+        let subsystem = Subsystem::Index(self.primary.source());
+        match self.primary.retrieve(query, limit) {
+            Ok(chunks) => {
+                self.tracker.recover(&subsystem);
+                Ok(chunks)
+            }
+            Err(err) => {
+                self.tracker.report(
+                    subsystem,
+                    &format!("{err:#}"),
+                    "workspace scan",
+                );
+                self.fallback.retrieve(query, limit)
+            }
+        }
+    }
+}
+
+/// Index-free content search that reads the workspace files on every query
+///
+/// Slow, but it has no state that can be corrupted, which makes it the last
+/// resort when an index is unusable.
+pub struct ScanRetriever {
+    crawler: Arc<WorkspaceCrawler>,
+}
+
+impl ScanRetriever {
+    /// Create a scan over the files the crawler visits
+    pub fn new(crawler: Arc<WorkspaceCrawler>) -> Self {
+        Self { crawler }
+    }
+}
+
+impl Retriever for ScanRetriever {
+    fn source(&self) -> RetrievalSource {
+        RetrievalSource::Trigram
+    }
+
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>> {
+        let terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|term| term.len() > 1)
+            .map(|term| term.to_lowercase())
+            .collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut scored: Vec<(usize, ContextChunk)> = Vec::new();
+        self.crawler.for_each_file(|path: &Path| {
+            // Binary and non UTF-8 files are not searchable as text
+            let Ok(content) = std::fs::read_to_string(path) else {
+                return;
+            };
+            let modified = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            for chunk in chunk_text(path, &content, SCAN_CHUNK_LINES, modified) {
+                let lower = chunk.content.to_lowercase();
+                let score: usize = terms
+                    .iter()
+                    .map(|term| lower.matches(term.as_str()).count())
+                    .sum();
+                if score > 0 {
+                    scored.push((score, chunk));
+                }
+            }
+        });
+
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        Ok(scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, chunk)| chunk)
+            .collect())
+    }
+}
+
+/// Assistant that routes requests to the first provider that works,
+/// switching to the next one when a provider fails
+pub struct FailoverAssistant {
+    /// Providers with their ids, in order of preference
+    providers: Vec<(String, Arc<dyn AiAssistantPlugin>)>,
+    tracker: Arc<DegradationTracker>,
+}
+
+impl FailoverAssistant {
+    /// Create a router over providers in order of preference
+    pub fn new(
+        providers: Vec<(String, Arc<dyn AiAssistantPlugin>)>,
+        tracker: Arc<DegradationTracker>,
+    ) -> Result<Self> {
+        if providers.is_empty() {
+            return Err(anyhow::anyhow!("At least one provider is required"));
+        }
+        Ok(Self { providers, tracker })
+    }
+
+    /// Get the id of the provider requests currently go to
+    pub fn active_provider(&self) -> &str {
+        self.candidates()
+            .next()
+            .map(|(id, _)| id.as_str())
+            .unwrap_or(&self.providers[0].0)
+    }
+
+    /// Providers to try in order: healthy ones first, then degraded ones so
+    /// a recovered provider is picked up again
+    fn candidates(
+        &self,
+    ) -> impl Iterator<Item = &(String, Arc<dyn AiAssistantPlugin>)> + '_ {
+        let degraded =
+            |id: &String| self.tracker.is_degraded(&Subsystem::Provider(id.clone()));
+        self.providers
+            .iter()
+            .filter(move |(id, _)| !degraded(id))
+            .chain(self.providers.iter().filter(move |(id, _)| degraded(id)))
+    }
+
+    /// Get the provider a request is pinned to by a provider-qualified model,
+    /// e.g. `fast/small-model`, and the request for the unqualified model
+    ///
+    /// A message overriding its model must not be answered by another
+    /// provider's model, so pinned requests don't fail over.
+    fn pinned(
+        &self,
+        request: &AiMessageRequest,
+    ) -> Option<(&Arc<dyn AiAssistantPlugin>, AiMessageRequest)> {
+        let (provider_id, model) = request.model.as_deref()?.split_once('/')?;
+        let (_, provider) =
+            self.providers.iter().find(|(id, _)| id == provider_id)?;
+        let mut request = request.clone();
+        request.model = Some(model.to_string());
+        Some((provider, request))
+    }
+
+    fn next_provider_name(&self, after: &str) -> String {
+        self.candidates()
+            .map(|(id, _)| id.as_str())
+            .skip_while(|id| *id != after)
+            .nth(1)
+            .unwrap_or("none")
+            .to_string()
+    }
+}
+
+impl AiAssistantPlugin for FailoverAssistant {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn plugin_info(&self) -> AiPluginInfo {
+        let primary = &self.providers[0].1.plugin_info();
+        AiPluginInfo {
+            name: "Provider failover".to_string(),
+            version: primary.version.clone(),
+            description: "Routes requests to the first available provider"
+                .to_string(),
+            provider: self.active_provider().to_string(),
+            supports_streaming: primary.supports_streaming,
+            supports_tools: primary.supports_tools,
+            supports_vision: primary.supports_vision,
+        }
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.providers
+            .iter()
+            .any(|(_, provider)| provider.is_authenticated())
+    }
+
+    fn send_message(&self, request: AiMessageRequest) -> Result<AiMessageResponse> {
+        if let Some((provider, request)) = self.pinned(&request) {
+            return provider.send_message(request);
+        }
+        let mut last_error = None;
+        for (id, provider) in self.candidates() {
+            let subsystem = Subsystem::Provider(id.clone());
+            match provider.send_message(request.clone()) {
+                Ok(response) => {
+                    self.tracker.recover(&subsystem);
+                    return Ok(response);
+                }
+                Err(err) => {
+                    self.tracker.report(
+                        subsystem,
+                        &format!("{err:#}"),
+                        &self.next_provider_name(id),
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No provider available")))
+    }
+
+    fn stream_message(
+        &self,
+        request: AiMessageRequest,
+        sender: &AiStreamSender,
+    ) -> Result<AiStreamCompletion> {
+        if let Some((provider, request)) = self.pinned(&request) {
+            return provider.stream_message(request, sender);
+        }
+        let mut last_error = None;
+        for (id, provider) in self.candidates() {
+            let subsystem = Subsystem::Provider(id.clone());
+            let sent_before = sender.sent_count();
+            match provider.stream_message(request.clone(), sender) {
+                Ok(completion) => {
+                    self.tracker.recover(&subsystem);
+                    return Ok(completion);
+                }
+                Err(err) => {
+                    self.tracker.report(
+                        subsystem,
+                        &format!("{err:#}"),
+                        &self.next_provider_name(id),
+                    );
+                    // Output already shown can't be taken back, so switching
+                    // providers mid-stream would produce a mixed answer
+                    if sender.sent_count() > sent_before {
+                        return Err(err);
+                    }
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No provider available")))
+    }
+
+    fn get_capabilities(&self) -> Vec<AiCapability> {
+        self.candidates()
+            .next()
+            .map(|(_, provider)| provider.get_capabilities())
+            .unwrap_or_default()
+    }
+
+    fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+        Err(anyhow::anyhow!(
+            "Authenticate the individual providers instead of the failover router"
+        ))
+    }
+
+    fn get_usage_info(&self) -> Option<AiUsageInfo> {
+        self.candidates()
+            .next()
+            .and_then(|(_, provider)| provider.get_usage_info())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct CorruptIndex {
+        corrupt: AtomicBool,
+    }
+
+    impl Retriever for CorruptIndex {
+        fn source(&self) -> RetrievalSource {
+            RetrievalSource::Vector
+        }
+
+        fn retrieve(
+            &self,
+            _query: &str,
+            _limit: usize,
+        ) -> Result<Vec<ContextChunk>> {
+            if self.corrupt.load(Ordering::Relaxed) {
+                Err(anyhow::anyhow!("checksum mismatch"))
+            } else {
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupt_index_falls_back_to_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn parse_config() {}\n").unwrap();
+        let ignore =
+            CatalystIgnore::new(dir.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let scan =
+            ScanRetriever::new(Arc::new(WorkspaceCrawler::new(Arc::new(ignore))));
+
+        let index = Arc::new(CorruptIndex {
+            corrupt: AtomicBool::new(true),
+        });
+        let tracker = Arc::new(DegradationTracker::new());
+        let retriever =
+            FallbackRetriever::new(index.clone(), Arc::new(scan), tracker.clone());
+
+        let chunks = retriever.retrieve("parse_config", 10).unwrap();
+        assert_eq!(chunks.len(), 1);
+        let status = tracker.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(
+            status[0].subsystem,
+            Subsystem::Index(RetrievalSource::Vector)
+        );
+
+        index.corrupt.store(false, Ordering::Relaxed);
+        retriever.retrieve("parse_config", 10).unwrap();
+        assert!(tracker.status().is_empty());
+    }
+}
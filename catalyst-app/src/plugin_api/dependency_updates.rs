@@ -0,0 +1,1131 @@
+//! Dependency Updates
+//!
+//! This module helps keep a project's dependencies current. It reads the
+//! packages pinned in `Cargo.lock`, `package-lock.json` and `poetry.lock`,
+//! asks crates.io, npm and PyPI for their latest versions and, when the
+//! `socket` MCP server is enabled, asks it for packages with security or
+//! supply chain problems. The resulting [`UpdatePlan`] lists the direct
+//! dependencies that are behind, and any package with an advisory, the
+//! advisories first.
+//!
+//! Updates are applied one at a time: each one is handed to the agent as an
+//! agent task through the [`AutomationExecutor`], then the project's build
+//! and test tasks are run. The first update the agent can't make or that
+//! breaks the build stops the run, so every applied update is known to
+//! build on its own.
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin_api::{
+    Automation, AutomationAction, AutomationEvent, AutomationExecutor,
+    AutomationTrigger, CommandResolver, DegradationTracker, McpServerRegistry,
+    McpToolResult, ProjectTask,
+};
+
+/// Id of the catalog server scoring packages for known problems
+pub const SOCKET_SERVER_ID: &str = "socket";
+
+/// Packages sent to the `socket` server in one call
+const SOCKET_BATCH_SIZE: usize = 50;
+
+/// Bytes of a failed verification's output kept in the report
+const MAX_VERIFICATION_OUTPUT: usize = 4000;
+
+/// Package registry a lockfile pins packages from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackageEcosystem {
+    Cargo,
+    Npm,
+    #[serde(rename = "pypi")]
+    PyPI,
+}
+
+impl PackageEcosystem {
+    pub const ALL: [PackageEcosystem; 3] = [Self::Cargo, Self::Npm, Self::PyPI];
+
+    /// Get the lockfile at the root of a project
+    pub fn lockfile(&self) -> &'static str {
+        match self {
+            Self::Cargo => "Cargo.lock",
+            Self::Npm => "package-lock.json",
+            Self::PyPI => "poetry.lock",
+        }
+    }
+
+    /// Get the manifest naming the direct dependencies, if the lockfile
+    /// doesn't
+    pub fn manifest(&self) -> Option<&'static str> {
+        match self {
+            Self::Cargo => None,
+            Self::Npm => Some("package.json"),
+            Self::PyPI => Some("pyproject.toml"),
+        }
+    }
+
+    /// Get the package URL type, as used by Socket
+    pub fn purl_type(&self) -> &'static str {
+        match self {
+            Self::Cargo => "cargo",
+            Self::Npm => "npm",
+            Self::PyPI => "pypi",
+        }
+    }
+
+    /// Get how to move a package to another version, for the agent
+    fn update_hint(&self, name: &str, version: &str) -> String {
+        match self {
+            Self::Cargo => format!(
+                "Run `cargo update -p {name} --precise {version}`, raising the \
+                 version requirement in Cargo.toml first if it doesn't allow \
+                 {version}."
+            ),
+            Self::Npm => format!(
+                "Run `npm install {name}@{version}`, keeping it in the same \
+                 dependency section of package.json."
+            ),
+            Self::PyPI => format!(
+                "Raise the constraint in pyproject.toml to allow {version} and \
+                 run `poetry update {name}`."
+            ),
+        }
+    }
+}
+
+/// A package pinned by a lockfile
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct LockedPackage {
+    pub ecosystem: PackageEcosystem,
+    pub name: String,
+    pub version: String,
+    /// Whether the project depends on the package itself rather than
+    /// through another package
+    pub direct: bool,
+}
+
+/// Read the packages pinned by the lockfiles at the root of a workspace
+pub fn read_workspace_packages(workspace_root: &Path) -> Result<Vec<LockedPackage>> {
+    let mut packages = Vec::new();
+    for ecosystem in PackageEcosystem::ALL {
+        let path = workspace_root.join(ecosystem.lockfile());
+        if !path.is_file() {
+            continue;
+        }
+        let lockfile = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let manifest = ecosystem.manifest().and_then(|name| {
+            std::fs::read_to_string(workspace_root.join(name)).ok()
+        });
+        packages.extend(
+            parse_lockfile(ecosystem, &lockfile, manifest.as_deref())
+                .with_context(|| format!("Failed to parse '{}'", path.display()))?,
+        );
+    }
+    Ok(packages)
+}
+
+/// Parse the registry packages pinned by a lockfile
+///
+/// `Cargo.lock` names the dependencies of the workspace members itself; the
+/// other lockfiles take them from the `package.json` or `pyproject.toml`
+/// given as `manifest`. Without one, every top-level package counts as
+/// direct. Packages from git or local paths are left out, since no registry
+/// knows their versions.
+pub fn parse_lockfile(
+    ecosystem: PackageEcosystem,
+    lockfile: &str,
+    manifest: Option<&str>,
+) -> Result<Vec<LockedPackage>> {
+    let mut packages = match ecosystem {
+        PackageEcosystem::Cargo => parse_cargo_lock(lockfile)?,
+        PackageEcosystem::Npm => parse_npm_lock(lockfile, manifest)?,
+        PackageEcosystem::PyPI => parse_poetry_lock(lockfile, manifest)?,
+    };
+    packages.sort();
+    packages.dedup();
+    Ok(packages)
+}
+
+fn parse_cargo_lock(lockfile: &str) -> Result<Vec<LockedPackage>> {
+    let lockfile: toml::Value = toml::from_str(lockfile)?;
+    let entries = lockfile
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    // Workspace members have no source; their dependencies are listed as
+    // `name`, `name version` or `name version (source)`
+    let direct: BTreeSet<&str> = entries
+        .iter()
+        .filter(|package| package.get("source").is_none())
+        .filter_map(|package| package.get("dependencies")?.as_array())
+        .flatten()
+        .filter_map(|dependency| dependency.as_str()?.split_whitespace().next())
+        .collect();
+    Ok(entries
+        .iter()
+        .filter(|package| {
+            package
+                .get("source")
+                .and_then(toml::Value::as_str)
+                .is_some_and(|source| {
+                    source.starts_with("registry+") || source.starts_with("sparse+")
+                })
+        })
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?;
+            Some(LockedPackage {
+                ecosystem: PackageEcosystem::Cargo,
+                name: name.to_string(),
+                version: package.get("version")?.as_str()?.to_string(),
+                direct: direct.contains(name),
+            })
+        })
+        .collect())
+}
+
+fn parse_npm_lock(
+    lockfile: &str,
+    manifest: Option<&str>,
+) -> Result<Vec<LockedPackage>> {
+    const DEPENDENCY_KEYS: [&str; 4] = [
+        "dependencies",
+        "devDependencies",
+        "optionalDependencies",
+        "peerDependencies",
+    ];
+    let lockfile: serde_json::Value = serde_json::from_str(lockfile)?;
+    let manifest: Option<serde_json::Value> =
+        manifest.map(serde_json::from_str).transpose()?;
+    // Version 2 and 3 list the root package under the empty path
+    let root = lockfile
+        .get("packages")
+        .and_then(|packages| packages.get(""))
+        .or(manifest.as_ref());
+    let direct: Option<BTreeSet<&str>> = root.map(|root| {
+        DEPENDENCY_KEYS
+            .iter()
+            .filter_map(|key| root.get(key)?.as_object())
+            .flat_map(|dependencies| dependencies.keys().map(String::as_str))
+            .collect()
+    });
+    let is_direct =
+        |name: &str| direct.as_ref().is_none_or(|direct| direct.contains(name));
+
+    let mut packages = Vec::new();
+    if let Some(entries) = lockfile.get("packages").and_then(|p| p.as_object()) {
+        for (path, package) in entries {
+            let Some((parent, name)) = path.rsplit_once("node_modules/") else {
+                continue;
+            };
+            let linked = package.get("link").and_then(|link| link.as_bool());
+            let Some(version) = package.get("version").and_then(|v| v.as_str())
+            else {
+                continue;
+            };
+            if linked == Some(true) {
+                continue;
+            }
+            packages.push(LockedPackage {
+                ecosystem: PackageEcosystem::Npm,
+                name: name.to_string(),
+                version: version.to_string(),
+                direct: parent.is_empty() && is_direct(name),
+            });
+        }
+    } else if let Some(entries) =
+        lockfile.get("dependencies").and_then(|d| d.as_object())
+    {
+        // Version 1 nests packages; only the top level is checked
+        for (name, package) in entries {
+            if let Some(version) = package.get("version").and_then(|v| v.as_str()) {
+                packages.push(LockedPackage {
+                    ecosystem: PackageEcosystem::Npm,
+                    name: name.clone(),
+                    version: version.to_string(),
+                    direct: is_direct(name),
+                });
+            }
+        }
+    }
+    Ok(packages)
+}
+
+fn parse_poetry_lock(
+    lockfile: &str,
+    manifest: Option<&str>,
+) -> Result<Vec<LockedPackage>> {
+    let lockfile: toml::Value = toml::from_str(lockfile)?;
+    let direct = manifest
+        .map(|manifest| {
+            toml::from_str::<toml::Value>(manifest)
+                .map(|manifest| pyproject_dependencies(&manifest))
+        })
+        .transpose()?;
+    Ok(lockfile
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+        // Packages with a source come from git, a path or another index
+        .filter(|package| package.get("source").is_none())
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?;
+            Some(LockedPackage {
+                ecosystem: PackageEcosystem::PyPI,
+                name: name.to_string(),
+                version: package.get("version")?.as_str()?.to_string(),
+                direct: direct.as_ref().is_none_or(|direct| {
+                    direct.contains(&normalize_python_name(name))
+                }),
+            })
+        })
+        .collect())
+}
+
+/// Get the normalized names of the dependencies declared in a
+/// `pyproject.toml`, both Poetry's tables and PEP 621 requirement strings
+fn pyproject_dependencies(manifest: &toml::Value) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let poetry = manifest.get("tool").and_then(|tool| tool.get("poetry"));
+    let groups = poetry
+        .and_then(|poetry| poetry.get("group"))
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|groups| groups.values())
+        .filter_map(|group| group.get("dependencies"));
+    let tables = ["dependencies", "dev-dependencies"]
+        .iter()
+        .filter_map(|key| poetry?.get(key))
+        .chain(groups);
+    for table in tables.filter_map(toml::Value::as_table) {
+        names.extend(
+            table
+                .keys()
+                .filter(|name| *name != "python")
+                .map(|name| normalize_python_name(name)),
+        );
+    }
+
+    let project = manifest.get("project");
+    let optional = project
+        .and_then(|project| project.get("optional-dependencies"))
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|extras| extras.values());
+    let requirements = project
+        .and_then(|project| project.get("dependencies"))
+        .into_iter()
+        .chain(optional)
+        .filter_map(toml::Value::as_array)
+        .flatten()
+        .filter_map(toml::Value::as_str);
+    for requirement in requirements {
+        let end = requirement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-_.".contains(c)))
+            .unwrap_or(requirement.len());
+        if end > 0 {
+            names.insert(normalize_python_name(&requirement[..end]));
+        }
+    }
+    names
+}
+
+/// Normalize a Python package name the way PyPI compares them
+fn normalize_python_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if "-_.".contains(c) {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// Source of the latest published version of packages
+pub trait PackageRegistry: Send + Sync {
+    /// Get the latest stable version of a package, or `None` if the
+    /// registry doesn't know it
+    fn latest_version(
+        &self,
+        ecosystem: PackageEcosystem,
+        name: &str,
+    ) -> Result<Option<String>>;
+}
+
+/// Looks up versions on crates.io, the npm registry and PyPI
+pub struct HttpPackageRegistry {
+    client: reqwest::blocking::Client,
+}
+
+impl HttpPackageRegistry {
+    pub fn new() -> Result<Self> {
+        // crates.io refuses requests without a user agent
+        let client = catalyst_proxy::http::client_builder()?
+            .user_agent(format!("Catalyst/{}", catalyst_core::meta::VERSION))
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self { client })
+    }
+}
+
+impl PackageRegistry for HttpPackageRegistry {
+    fn latest_version(
+        &self,
+        ecosystem: PackageEcosystem,
+        name: &str,
+    ) -> Result<Option<String>> {
+        let url = match ecosystem {
+            PackageEcosystem::Cargo => {
+                format!("https://crates.io/api/v1/crates/{name}")
+            }
+            PackageEcosystem::Npm => format!(
+                "https://registry.npmjs.org/-/package/{}/dist-tags",
+                name.replace('/', "%2f")
+            ),
+            PackageEcosystem::PyPI => format!("https://pypi.org/pypi/{name}/json"),
+        };
+        let response = self.client.get(&url).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body: serde_json::Value = response.error_for_status()?.json()?;
+        let version = match ecosystem {
+            PackageEcosystem::Cargo => body
+                .get("crate")
+                .and_then(|krate| {
+                    krate
+                        .get("max_stable_version")
+                        .filter(|version| !version.is_null())
+                        .or_else(|| krate.get("max_version"))
+                })
+                .and_then(|version| version.as_str()),
+            PackageEcosystem::Npm => body.get("latest").and_then(|v| v.as_str()),
+            PackageEcosystem::PyPI => body
+                .get("info")
+                .and_then(|info| info.get("version"))
+                .and_then(|version| version.as_str()),
+        };
+        Ok(version.map(str::to_string))
+    }
+}
+
+/// A known problem with a pinned package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Advisory {
+    pub ecosystem: PackageEcosystem,
+    pub name: String,
+    pub version: String,
+    pub summary: String,
+}
+
+/// Source of advisories for pinned packages
+pub trait AdvisorySource: Send + Sync {
+    fn advisories(&self, packages: &[LockedPackage]) -> Result<Vec<Advisory>>;
+}
+
+/// Scores packages with the `depscore` tool of the `socket` server
+///
+/// The tool answers with a line per package, such as
+/// `pkg:npm/express@4.18.2: supply_chain: 1.0, quality: 0.9, maintenance:
+/// 1.0, vulnerability: 0.4, license: 1.0`. A vulnerability or supply chain
+/// score under [`SocketAdvisories::min_score`] makes an advisory.
+pub struct SocketAdvisories {
+    registry: McpServerRegistry,
+    tracker: Arc<DegradationTracker>,
+    min_score: f64,
+}
+
+impl SocketAdvisories {
+    pub const SCORES: [&'static str; 2] = ["vulnerability", "supply_chain"];
+
+    pub fn new(
+        registry: McpServerRegistry,
+        tracker: Arc<DegradationTracker>,
+    ) -> Self {
+        Self {
+            registry,
+            tracker,
+            min_score: 0.5,
+        }
+    }
+
+    pub fn min_score(&self) -> f64 {
+        self.min_score
+    }
+
+    pub fn with_min_score(mut self, min_score: f64) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Find the advisories in the text of a `depscore` result
+    pub fn parse_scores(
+        &self,
+        packages: &[LockedPackage],
+        text: &str,
+    ) -> Vec<Advisory> {
+        let mut advisories = Vec::new();
+        for package in packages {
+            let pinned = format!("{}@{}", package.name, package.version);
+            let encoded =
+                pinned.replacen('@', "%40", usize::from(pinned.starts_with('@')));
+            let Some(line) = text.lines().find(|line| {
+                line.contains(&format!("{}/", package.ecosystem.purl_type()))
+                    && (line.contains(&format!("/{pinned}:"))
+                        || line.contains(&format!("/{encoded}:")))
+            }) else {
+                continue;
+            };
+            let low: Vec<String> = line
+                .split([',', ' '])
+                .collect::<Vec<_>>()
+                .windows(2)
+                .filter_map(|pair| {
+                    let key = pair[0].strip_suffix(':')?;
+                    let score: f64 = pair[1].parse().ok()?;
+                    (Self::SCORES.contains(&key) && score < self.min_score)
+                        .then(|| format!("{} score {score}", key.replace('_', " ")))
+                })
+                .collect();
+            if !low.is_empty() {
+                advisories.push(Advisory {
+                    ecosystem: package.ecosystem,
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    summary: format!("Socket reports a low {}", low.join(" and ")),
+                });
+            }
+        }
+        advisories
+    }
+}
+
+impl AdvisorySource for SocketAdvisories {
+    fn advisories(&self, packages: &[LockedPackage]) -> Result<Vec<Advisory>> {
+        let mut advisories = Vec::new();
+        for batch in packages.chunks(SOCKET_BATCH_SIZE) {
+            let arguments = serde_json::json!({
+                "packages": batch
+                    .iter()
+                    .map(|package| serde_json::json!({
+                        "ecosystem": package.ecosystem.purl_type(),
+                        "depname": package.name,
+                        "version": package.version,
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+            let result = self.registry.call_tool_degraded(
+                SOCKET_SERVER_ID,
+                "depscore",
+                arguments,
+                &self.tracker,
+            );
+            let text = result_text(&result);
+            if result.is_error {
+                return Err(anyhow!("depscore failed: {}", text));
+            }
+            advisories.extend(self.parse_scores(batch, &text));
+        }
+        Ok(advisories)
+    }
+}
+
+fn result_text(result: &McpToolResult) -> String {
+    result
+        .content
+        .iter()
+        .map(|content| match &content.data {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Check if `latest` is a higher version than `current`
+///
+/// Versions that aren't semver, as on PyPI, are compared by their numeric
+/// release segments.
+pub fn is_newer_version(latest: &str, current: &str) -> bool {
+    let parse =
+        |version: &str| semver::Version::parse(version.trim_start_matches('v'));
+    if let (Ok(latest), Ok(current)) = (parse(latest), parse(current)) {
+        return latest > current;
+    }
+    let release = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map_while(|segment| {
+                let digits: String =
+                    segment.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().ok()
+            })
+            .collect()
+    };
+    let (latest, current) = (release(latest), release(current));
+    !latest.is_empty() && !current.is_empty() && latest > current
+}
+
+/// An update of one package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DependencyUpdate {
+    pub ecosystem: PackageEcosystem,
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// Problems with the pinned version
+    pub advisories: Vec<String>,
+}
+
+impl DependencyUpdate {
+    pub fn describe(&self) -> String {
+        format!("{} {} → {}", self.name, self.from, self.to)
+    }
+
+    /// Get the agent task applying the update
+    pub fn prompt(&self, verification: &[ProjectTask]) -> String {
+        let mut prompt = format!(
+            "Update the {} dependency `{}` from {} to {} and no other \
+             dependency. {}",
+            self.ecosystem.purl_type(),
+            self.name,
+            self.from,
+            self.to,
+            self.ecosystem.update_hint(&self.name, &self.to)
+        );
+        if !self.advisories.is_empty() {
+            prompt.push_str(&format!(
+                "\n\nThe pinned version has known problems: {}.",
+                self.advisories.join("; ")
+            ));
+        }
+        prompt.push_str(
+            "\n\nFix the code that no longer builds or passes its tests with the \
+             new version.",
+        );
+        if !verification.is_empty() {
+            let commands: Vec<String> = verification
+                .iter()
+                .map(|task| format!("`{}`", task.command.join(" ")))
+                .collect();
+            prompt.push_str(&format!(
+                " These commands must pass: {}.",
+                commands.join(", ")
+            ));
+        }
+        prompt.push_str(
+            " If the update can't be made to work, revert your changes and \
+             explain what breaks.",
+        );
+        prompt
+    }
+}
+
+/// Updates to apply, the ones fixing advisories first
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UpdatePlan {
+    pub updates: Vec<DependencyUpdate>,
+    /// Lookups that failed, so the plan may be incomplete
+    pub warnings: Vec<String>,
+}
+
+/// Find the updates of the packages that are behind their registry
+///
+/// Direct dependencies are checked, and transitive ones with an advisory.
+/// A package pinned at several versions is checked at its highest.
+pub fn plan_updates(
+    packages: &[LockedPackage],
+    registry: &dyn PackageRegistry,
+    advisories: Option<&dyn AdvisorySource>,
+) -> UpdatePlan {
+    let mut plan = UpdatePlan::default();
+    let mut pinned: BTreeMap<(PackageEcosystem, &str), (&str, bool)> =
+        BTreeMap::new();
+    for package in packages {
+        let entry = pinned
+            .entry((package.ecosystem, package.name.as_str()))
+            .or_insert((&package.version, package.direct));
+        if is_newer_version(&package.version, entry.0) {
+            entry.0 = &package.version;
+        }
+        entry.1 |= package.direct;
+    }
+
+    let mut found: HashMap<(PackageEcosystem, String), Vec<String>> = HashMap::new();
+    if let Some(source) = advisories {
+        match source.advisories(packages) {
+            Ok(advisories) => {
+                for advisory in advisories {
+                    found
+                        .entry((advisory.ecosystem, advisory.name))
+                        .or_default()
+                        .push(format!(
+                            "{} ({})",
+                            advisory.summary, advisory.version
+                        ));
+                }
+            }
+            Err(err) => plan
+                .warnings
+                .push(format!("Couldn't check for advisories: {err:#}")),
+        }
+    }
+
+    for ((ecosystem, name), (version, direct)) in pinned {
+        let advisories = found
+            .remove(&(ecosystem, name.to_string()))
+            .unwrap_or_default();
+        if !direct && advisories.is_empty() {
+            continue;
+        }
+        match registry.latest_version(ecosystem, name) {
+            Ok(Some(latest)) if is_newer_version(&latest, version) => {
+                plan.updates.push(DependencyUpdate {
+                    ecosystem,
+                    name: name.to_string(),
+                    from: version.to_string(),
+                    to: latest,
+                    advisories,
+                });
+            }
+            Ok(_) => {
+                if !advisories.is_empty() {
+                    plan.warnings.push(format!(
+                        "{name} {version} has advisories but no newer version: {}",
+                        advisories.join("; ")
+                    ));
+                }
+            }
+            Err(err) => plan
+                .warnings
+                .push(format!("Couldn't check {name} for updates: {err:#}")),
+        }
+    }
+    // Stable sort keeps the ecosystem and name order within each group
+    plan.updates
+        .sort_by_key(|update| update.advisories.is_empty());
+    plan
+}
+
+/// How applying an update went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UpdateOutcome {
+    /// The agent applied the update and the build and tests pass
+    Applied { summary: String },
+    /// The agent couldn't apply the update
+    AgentFailed { error: String },
+    /// The build or tests fail after the update
+    VerificationFailed { task: String, output: String },
+}
+
+/// An update that was attempted
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpdateStep {
+    pub update: DependencyUpdate,
+    pub outcome: UpdateOutcome,
+}
+
+/// Outcome of applying a plan
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UpdateReport {
+    pub steps: Vec<UpdateStep>,
+    /// Updates not attempted because an earlier one failed
+    pub remaining: Vec<DependencyUpdate>,
+}
+
+impl UpdateReport {
+    pub fn summary(&self) -> String {
+        let mut lines: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| match &step.outcome {
+                UpdateOutcome::Applied { .. } => {
+                    format!("Updated {}", step.update.describe())
+                }
+                UpdateOutcome::AgentFailed { error } => {
+                    format!("Failed to update {}: {}", step.update.describe(), error)
+                }
+                UpdateOutcome::VerificationFailed { task, .. } => format!(
+                    "Updating {} breaks the {} task",
+                    step.update.describe(),
+                    task
+                ),
+            })
+            .collect();
+        if !self.remaining.is_empty() {
+            let count = self.remaining.len();
+            lines.push(format!(
+                "{} update{} not attempted",
+                count,
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Plans dependency updates for a workspace and applies them one by one
+pub struct DependencyUpdateAssistant {
+    workspace_root: PathBuf,
+    registry: Box<dyn PackageRegistry>,
+    advisories: Option<Box<dyn AdvisorySource>>,
+    /// Tasks run after every update, usually the build and test tasks
+    verification: Vec<ProjectTask>,
+    resolver: CommandResolver,
+}
+
+impl DependencyUpdateAssistant {
+    pub fn new(
+        workspace_root: &Path,
+        registry: Box<dyn PackageRegistry>,
+        verification: Vec<ProjectTask>,
+    ) -> Self {
+        Self {
+            workspace_root: workspace_root.to_path_buf(),
+            registry,
+            advisories: None,
+            verification,
+            resolver: CommandResolver::from_env(),
+        }
+    }
+
+    pub fn with_advisories(mut self, advisories: Box<dyn AdvisorySource>) -> Self {
+        self.advisories = Some(advisories);
+        self
+    }
+
+    pub fn verification(&self) -> &[ProjectTask] {
+        &self.verification
+    }
+
+    /// Find the updates of the workspace's pinned packages
+    pub fn plan(&self) -> Result<UpdatePlan> {
+        let packages = read_workspace_packages(&self.workspace_root)?;
+        if packages.is_empty() {
+            return Err(anyhow!(
+                "No Cargo.lock, package-lock.json or poetry.lock in '{}'",
+                self.workspace_root.display()
+            ));
+        }
+        Ok(plan_updates(
+            &packages,
+            self.registry.as_ref(),
+            self.advisories.as_deref(),
+        ))
+    }
+
+    /// Apply the updates of a plan in order through agent tasks, running the
+    /// verification tasks after each and stopping at the first failure
+    pub fn apply(
+        &self,
+        plan: &UpdatePlan,
+        executor: &dyn AutomationExecutor,
+    ) -> UpdateReport {
+        let mut report = UpdateReport::default();
+        let mut updates = plan.updates.iter();
+        for update in updates.by_ref() {
+            let outcome = self.apply_update(update, executor);
+            let failed = !matches!(outcome, UpdateOutcome::Applied { .. });
+            report.steps.push(UpdateStep {
+                update: update.clone(),
+                outcome,
+            });
+            if failed {
+                break;
+            }
+        }
+        report.remaining = updates.cloned().collect();
+        report
+    }
+
+    fn apply_update(
+        &self,
+        update: &DependencyUpdate,
+        executor: &dyn AutomationExecutor,
+    ) -> UpdateOutcome {
+        // The task is never stored; the trigger only records which file the
+        // update is about
+        let automation = Automation {
+            id: 0,
+            name: format!("Update {}", update.describe()),
+            trigger: AutomationTrigger::FileSaved {
+                globs: vec![update.ecosystem.lockfile().to_string()],
+            },
+            action: AutomationAction::AgentTask {
+                prompt: update.prompt(&self.verification),
+                servers: BTreeSet::new(),
+            },
+            enabled: true,
+            max_concurrent_runs: 1,
+        };
+        let summary = match executor.execute(&automation, &AutomationEvent::Manual) {
+            Ok(summary) => summary,
+            Err(err) => {
+                return UpdateOutcome::AgentFailed {
+                    error: format!("{err:#}"),
+                };
+            }
+        };
+        for task in &self.verification {
+            if let Err(output) = self.run_task(task) {
+                return UpdateOutcome::VerificationFailed {
+                    task: task.label.clone(),
+                    output,
+                };
+            }
+        }
+        UpdateOutcome::Applied { summary }
+    }
+
+    /// Run a verification task, returning the end of its output on failure
+    fn run_task(&self, task: &ProjectTask) -> Result<(), String> {
+        let output = self
+            .resolver
+            .task_command(task, &HashMap::new())
+            .and_then(|mut command| Ok(command.output()?))
+            .map_err(|err| format!("{err:#}"))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let mut start = text.len().saturating_sub(MAX_VERIFICATION_OUTPUT);
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        Err(text[start..].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    const CARGO_LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "regex 1.9.0", "local"]
+
+[[package]]
+name = "local"
+version = "0.1.0"
+
+[[package]]
+name = "regex"
+version = "1.9.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["memchr"]
+
+[[package]]
+name = "memchr"
+version = "2.5.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    struct FakeRegistry;
+
+    impl PackageRegistry for FakeRegistry {
+        fn latest_version(
+            &self,
+            _ecosystem: PackageEcosystem,
+            name: &str,
+        ) -> Result<Option<String>> {
+            match name {
+                "serde" => Ok(Some("1.0.200".to_string())),
+                "regex" => Ok(Some("1.9.0".to_string())),
+                "memchr" => Ok(Some("2.7.1".to_string())),
+                "left-pad" => Err(anyhow!("registry is down")),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    struct FakeAdvisories;
+
+    impl AdvisorySource for FakeAdvisories {
+        fn advisories(&self, _packages: &[LockedPackage]) -> Result<Vec<Advisory>> {
+            Ok(vec![Advisory {
+                ecosystem: PackageEcosystem::Cargo,
+                name: "memchr".to_string(),
+                version: "2.5.0".to_string(),
+                summary: "Socket reports a low vulnerability score 0.2".to_string(),
+            }])
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeAgent {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl AutomationExecutor for FakeAgent {
+        fn execute(
+            &self,
+            automation: &Automation,
+            _event: &AutomationEvent,
+        ) -> Result<String> {
+            let AutomationAction::AgentTask { prompt, .. } = &automation.action
+            else {
+                unreachable!()
+            };
+            self.prompts.lock().push(prompt.clone());
+            if prompt.contains("`serde`") {
+                return Err(anyhow!("serde 1.0.200 removed a derive we use"));
+            }
+            Ok(format!("Applied {}", automation.name))
+        }
+    }
+
+    fn task(label: &str, command: &[&str]) -> ProjectTask {
+        ProjectTask {
+            label: label.to_string(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            working_directory: std::env::temp_dir(),
+        }
+    }
+
+    #[test]
+    fn test_dependency_updates() {
+        let packages =
+            parse_lockfile(PackageEcosystem::Cargo, CARGO_LOCK, None).unwrap();
+        let names: Vec<_> = packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.direct))
+            .collect();
+        assert_eq!(names, [("memchr", false), ("regex", true), ("serde", true)]);
+
+        let npm_lock = r#"{
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "dependencies": { "left-pad": "^1.0.0" } },
+                "node_modules/left-pad": { "version": "1.0.0" },
+                "node_modules/left-pad/node_modules/tiny": { "version": "0.1.0" },
+                "node_modules/workspace-a": { "link": true },
+                "node_modules/tiny": { "version": "0.2.0" }
+            }
+        }"#;
+        let npm = parse_lockfile(PackageEcosystem::Npm, npm_lock, None).unwrap();
+        let direct: Vec<_> = npm
+            .iter()
+            .filter(|package| package.direct)
+            .map(|package| package.name.as_str())
+            .collect();
+        assert_eq!(npm.len(), 3);
+        assert_eq!(direct, ["left-pad"]);
+
+        let poetry_lock = r#"
+[[package]]
+name = "Django"
+version = "4.2.0"
+
+[[package]]
+name = "asgiref"
+version = "3.7.0"
+
+[[package]]
+name = "internal-lib"
+version = "0.1.0"
+[package.source]
+type = "git"
+url = "https://example.com/internal-lib.git"
+"#;
+        let pyproject = r#"
+[tool.poetry.dependencies]
+python = "^3.11"
+django = "^4.2"
+"#;
+        let python =
+            parse_lockfile(PackageEcosystem::PyPI, poetry_lock, Some(pyproject))
+                .unwrap();
+        assert_eq!(python.len(), 2);
+        assert!(python[0].direct && python[0].name == "Django");
+        assert!(!python[1].direct);
+
+        assert!(is_newer_version("1.0.200", "1.0.100"));
+        assert!(!is_newer_version("1.0.0-beta.1", "1.0.0"));
+        assert!(is_newer_version("2024.1", "2023.12.post1"));
+
+        // The advisory brings in a transitive package, and goes first
+        let mut all = packages.clone();
+        all.extend(npm);
+        let plan = plan_updates(&all, &FakeRegistry, Some(&FakeAdvisories));
+        let updates: Vec<_> = plan.updates.iter().map(|u| u.describe()).collect();
+        assert_eq!(updates, ["memchr 2.5.0 → 2.7.1", "serde 1.0.100 → 1.0.200"]);
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.warnings[0].contains("left-pad"));
+
+        let socket = SocketAdvisories::new(
+            McpServerRegistry::default(),
+            Arc::new(DegradationTracker::new()),
+        );
+        let advisories = socket.parse_scores(
+            &packages,
+            "pkg:cargo/memchr@2.5.0: supply_chain: 0.3, quality: 0.9, \
+             vulnerability: 0.2\npkg:cargo/serde@1.0.100: supply_chain: 1.0, \
+             vulnerability: 1.0",
+        );
+        assert_eq!(advisories.len(), 1);
+        assert_eq!(
+            advisories[0].summary,
+            "Socket reports a low supply chain score 0.3 and vulnerability score 0.2"
+        );
+
+        // The failing second update stops the run
+        let dir = tempfile::tempdir().unwrap();
+        let agent = FakeAgent::default();
+        let assistant = DependencyUpdateAssistant::new(
+            dir.path(),
+            Box::new(FakeRegistry),
+            vec![task("test", &["true"])],
+        );
+        assert!(assistant.plan().is_err());
+        let mut three = plan.clone();
+        three.updates.push(three.updates[1].clone());
+        let report = assistant.apply(&three, &agent);
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.remaining.len(), 1);
+        assert!(agent.prompts.lock()[0].contains("known problems"));
+        assert!(agent.prompts.lock()[0].contains("`cargo update -p memchr"));
+        assert_eq!(
+            report.summary(),
+            "Updated memchr 2.5.0 → 2.7.1\nFailed to update serde 1.0.100 → \
+             1.0.200: serde 1.0.200 removed a derive we use\n1 update not \
+             attempted"
+        );
+
+        let assistant = DependencyUpdateAssistant::new(
+            dir.path(),
+            Box::new(FakeRegistry),
+            vec![task("build", &["sh", "-c", "echo broken >&2; exit 1"])],
+        );
+        let report = assistant.apply(&plan, &agent);
+        assert_eq!(
+            report.steps[0].outcome,
+            UpdateOutcome::VerificationFailed {
+                task: "build".to_string(),
+                output: "broken\n".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! Deterministic Mode
+//!
+//! This module makes agent runs reproducible in tests. While a
+//! [`Determinism`] guard is held, the
+//! [`SystemClock`](crate::plugin_api::SystemClock) of every thread of the
+//! process reads a [`ManualClock`] that only moves when advanced, random
+//! numbers come from a seeded generator, and jobs given to a
+//! [`JobScheduler`](crate::plugin_api::JobScheduler) run on the calling
+//! thread in the order they were spawned. Code that stamps times or draws
+//! random numbers goes through the clock, or
+//! [`current_time`](crate::plugin_api::current_time), and [`random_u64`] so
+//! it picks the mode up. Runs on different threads take turns, so parallel
+//! tests don't see each other's clock.
+//!
+//! The app enters the mode for its whole lifetime when started with
+//! `CATALYST_DETERMINISTIC_SEED`, which the integration tests set, and
+//! [agent recordings](crate::plugin_api::AgentRecording) replay with the
+//! seed they were recorded with.
+
+use parking_lot::{
+    Mutex, ReentrantMutex, ReentrantMutexGuard, const_mutex, const_reentrant_mutex,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::ManualClock;
+
+/// Environment variable holding the seed of a deterministic run
+pub const DETERMINISTIC_SEED_ENV: &str = "CATALYST_DETERMINISTIC_SEED";
+/// Time the frozen clock starts at unless told otherwise, 2024-01-01 UTC
+const DEFAULT_START_SECS: u64 = 1_704_067_200;
+
+/// State of the current deterministic run, shared by every thread
+static ACTIVE: Mutex<Option<DeterministicState>> = const_mutex(None);
+/// Held by a run's guard so runs started on other threads wait for it
+static RUN: ReentrantMutex<()> = const_reentrant_mutex(());
+
+struct DeterministicState {
+    clock: Arc<ManualClock>,
+    rng: SeededRng,
+}
+
+/// Settings of a deterministic run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Determinism {
+    pub seed: u64,
+    /// Time the frozen clock starts at
+    pub start: SystemTime,
+}
+
+impl Determinism {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            start: SystemTime::UNIX_EPOCH + Duration::from_secs(DEFAULT_START_SECS),
+        }
+    }
+
+    /// Read the seed from `CATALYST_DETERMINISTIC_SEED`, if set
+    pub fn from_env() -> Option<Self> {
+        let seed = std::env::var(DETERMINISTIC_SEED_ENV).ok()?;
+        match seed.trim().parse() {
+            Ok(seed) => Some(Self::new(seed)),
+            Err(_) => {
+                tracing::warn!(
+                    "Ignoring {}: '{}' is not a number",
+                    DETERMINISTIC_SEED_ENV,
+                    seed
+                );
+                None
+            }
+        }
+    }
+
+    pub fn starting_at(mut self, start: SystemTime) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Make the process deterministic until the guard is dropped
+    ///
+    /// Waits for a run entered on another thread to end first; entering
+    /// again on the same thread nests.
+    pub fn enter(self) -> DeterminismGuard {
+        let run = RUN.lock();
+        let previous = ACTIVE.lock().replace(self.state());
+        DeterminismGuard {
+            previous,
+            _run: run,
+        }
+    }
+
+    /// Make the process deterministic for the rest of its lifetime, as the
+    /// app does when started with `CATALYST_DETERMINISTIC_SEED`
+    pub fn install(self) {
+        *ACTIVE.lock() = Some(self.state());
+    }
+
+    fn state(&self) -> DeterministicState {
+        DeterministicState {
+            clock: Arc::new(ManualClock::new(self.start)),
+            rng: SeededRng::new(self.seed),
+        }
+    }
+}
+
+/// Keeps the process deterministic; the previous mode is restored when
+/// dropped
+#[must_use]
+pub struct DeterminismGuard {
+    previous: Option<DeterministicState>,
+    _run: ReentrantMutexGuard<'static, ()>,
+}
+
+impl Drop for DeterminismGuard {
+    fn drop(&mut self) {
+        *ACTIVE.lock() = self.previous.take();
+    }
+}
+
+/// Check whether the process runs in deterministic mode
+pub fn is_deterministic() -> bool {
+    ACTIVE.lock().is_some()
+}
+
+/// Get the clock of the deterministic run, if any
+pub fn deterministic_clock() -> Option<Arc<ManualClock>> {
+    ACTIVE.lock().as_ref().map(|state| state.clock.clone())
+}
+
+/// Move the frozen clock forward, returning false outside deterministic mode
+pub fn advance_clock(duration: Duration) -> bool {
+    deterministic_clock()
+        .map(|clock| clock.advance(duration))
+        .is_some()
+}
+
+/// Get a random number, drawn from the seeded generator in deterministic
+/// mode
+pub fn random_u64() -> u64 {
+    ACTIVE
+        .lock()
+        .as_mut()
+        .map(|state| state.rng.next_u64())
+        .unwrap_or_else(|| RandomState::new().build_hasher().finish())
+}
+
+/// Small seeded random number generator (SplitMix64)
+///
+/// Not suitable for anything security related.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a number below `bound`, which must not be zero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{ConcurrencySettings, JobScheduler, current_time};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_deterministic_mode() {
+        let run = || {
+            let _guard = Determinism::new(42).enter();
+            let start = current_time();
+            assert!(advance_clock(Duration::from_secs(5)));
+            assert_eq!(current_time(), start + Duration::from_secs(5));
+
+            // Jobs run in spawn order on this thread
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let scheduler = JobScheduler::new(ConcurrencySettings::default());
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let order = order.clone();
+                    scheduler.spawn(move || {
+                        order.lock().push(i);
+                        Ok(random_u64())
+                    })
+                })
+                .collect();
+            assert_eq!(*order.lock(), [0, 1, 2, 3]);
+            let numbers: Vec<u64> = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect();
+            (start, numbers)
+        };
+        assert_eq!(run(), run());
+        {
+            // Keep runs of other tests out while checking the mode is off
+            let _run = RUN.lock();
+            assert!(!is_deterministic());
+            assert!(!advance_clock(Duration::from_secs(1)));
+        }
+
+        let mut items = [1, 2, 3, 4, 5];
+        SeededRng::new(7).shuffle(&mut items);
+        let mut again = [1, 2, 3, 4, 5];
+        SeededRng::new(7).shuffle(&mut again);
+        assert_eq!(items, again);
+    }
+
+    #[test]
+    fn test_deterministic_mode_is_process_wide() {
+        let guard = Determinism::new(3)
+            .starting_at(SystemTime::UNIX_EPOCH)
+            .enter();
+        let seen = std::thread::spawn(|| (is_deterministic(), current_time()))
+            .join()
+            .unwrap();
+        assert_eq!(seen, (true, SystemTime::UNIX_EPOCH));
+
+        // Nested runs restore the outer one
+        {
+            let _inner = Determinism::new(4).enter();
+            assert_ne!(current_time(), SystemTime::UNIX_EPOCH);
+        }
+        assert_eq!(current_time(), SystemTime::UNIX_EPOCH);
+
+        // A run on another thread waits for this one to end
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let other = std::thread::spawn(move || {
+            let _guard = Determinism::new(5).enter();
+            let _ = tx.send(current_time());
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(guard);
+        assert_eq!(
+            rx.recv().unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(DEFAULT_START_SECS)
+        );
+        other.join().unwrap();
+    }
+}
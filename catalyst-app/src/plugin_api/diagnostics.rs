@@ -0,0 +1,488 @@
+//! Performance Diagnostics
+//!
+//! This module backs the diagnostics panel, a user-facing view of the
+//! [`MetricsRegistry`]: startup phase timings, memory held by each
+//! subsystem, recent MCP tool call latencies, the background job queue and
+//! how long ago each retrieval index last changed, followed by every raw
+//! metric. The panel refreshes itself while it is visible, on a thread
+//! that stops when the panel is deactivated, dropped or given a new view.
+
+use anyhow::Result;
+use floem::{View, ext_event::create_signal_from_channel, reactive::SignalGet};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    AllocationCounters, MetricsRegistry, PanelCommand, PanelCommandResult,
+    PhaseTiming, Report, ReportFormat, ReportSection, RetrievalSource,
+    SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition, SubsystemAllocation,
+    alloc_tracking_enabled, current_time, format_bytes, startup_phases,
+    text_panel_view,
+};
+
+/// Time between two refreshes of an open diagnostics panel
+pub const DEFAULT_DIAGNOSTICS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Completed startup phase
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StartupPhaseEntry {
+    pub name: String,
+    pub thread: String,
+    pub duration: Duration,
+}
+
+/// Recent tool call latencies of an MCP server, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct McpLatencyEntry {
+    pub server_id: String,
+    pub latencies_ms: Vec<f64>,
+}
+
+/// When a retrieval index last changed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexFreshness {
+    pub source: RetrievalSource,
+    /// None if the index was not built in this session
+    pub updated_at: Option<SystemTime>,
+}
+
+/// Performance state of the IDE at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagnosticsReport {
+    pub generated_at: SystemTime,
+    pub startup_phases: Vec<StartupPhaseEntry>,
+    /// None if allocation tracking is not built in
+    pub memory: Option<Vec<SubsystemAllocation>>,
+    pub mcp_latency: Vec<McpLatencyEntry>,
+    /// Restart latencies of servers resumed after being suspended as idle
+    pub mcp_cold_starts: Vec<McpLatencyEntry>,
+    pub queued_jobs: usize,
+    pub running_jobs: usize,
+    pub index_freshness: Vec<IndexFreshness>,
+    pub metrics: BTreeMap<String, f64>,
+}
+
+impl DiagnosticsReport {
+    /// Collect the current state of the whole application
+    pub fn current() -> Self {
+        Self::collect(
+            MetricsRegistry::global(),
+            startup_phases(),
+            alloc_tracking_enabled()
+                .then(|| AllocationCounters::global().snapshot()),
+        )
+    }
+
+    pub fn collect(
+        metrics: &MetricsRegistry,
+        mut phases: Vec<PhaseTiming>,
+        memory: Option<Vec<SubsystemAllocation>>,
+    ) -> Self {
+        phases.sort_by_key(|phase| phase.started_at);
+        let snapshot = metrics.snapshot();
+        let mcp_samples = |suffix: &str| {
+            snapshot
+                .keys()
+                .filter_map(|name| {
+                    let server_id =
+                        name.strip_prefix("mcp.")?.strip_suffix(suffix)?;
+                    Some(McpLatencyEntry {
+                        server_id: server_id.to_string(),
+                        latencies_ms: metrics.history(name),
+                    })
+                })
+                .collect()
+        };
+        let mcp_latency = mcp_samples(".latency_ms");
+        let mcp_cold_starts = mcp_samples(".cold_start_ms");
+        let index_freshness = RetrievalSource::ALL
+            .iter()
+            .map(|source| IndexFreshness {
+                source: *source,
+                updated_at: metrics.get(&source.updated_metric()).map(|secs| {
+                    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(secs)
+                }),
+            })
+            .collect();
+        let gauge = |name: &str| metrics.get(name).unwrap_or(0.0) as usize;
+
+        Self {
+            generated_at: current_time(),
+            startup_phases: phases
+                .into_iter()
+                .map(|phase| StartupPhaseEntry {
+                    name: phase.name.to_string(),
+                    thread: phase.thread,
+                    duration: phase.duration,
+                })
+                .collect(),
+            memory,
+            mcp_latency,
+            mcp_cold_starts,
+            queued_jobs: gauge("scheduler.queued_jobs"),
+            running_jobs: gauge("scheduler.running_jobs"),
+            index_freshness,
+            metrics: snapshot
+                .into_iter()
+                .map(|(name, metric)| (name, metric.value))
+                .collect(),
+        }
+    }
+}
+
+/// Draw values as a line of bars scaled between their minimum and maximum
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARKLINE_BARS.len() - 1) as f64;
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * top).round()
+            } else {
+                top / 2.0
+            };
+            SPARKLINE_BARS[level as usize]
+        })
+        .collect()
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+impl Report for DiagnosticsReport {
+    fn title(&self) -> String {
+        "Diagnostics".to_string()
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        let startup = self.startup_phases.iter().fold(
+            ReportSection::new("Startup phases"),
+            |section, phase| {
+                section.row(
+                    &phase.name,
+                    format!(
+                        "{:.1}ms on {}",
+                        phase.duration.as_secs_f64() * 1000.0,
+                        phase.thread
+                    ),
+                )
+            },
+        );
+
+        let memory = match &self.memory {
+            Some(memory) => memory.iter().fold(
+                ReportSection::new("Memory by subsystem"),
+                |section, allocation| {
+                    section.row(
+                        allocation.tag.name(),
+                        format!(
+                            "{} ({} allocations)",
+                            format_bytes(allocation.live_bytes),
+                            allocation.live_allocations
+                        ),
+                    )
+                },
+            ),
+            None => ReportSection::new("Memory by subsystem")
+                .row("tracking", "off, build with --features alloc-tracking"),
+        };
+
+        let latency_section = |title: &str, entries: &[McpLatencyEntry]| {
+            entries
+                .iter()
+                .fold(ReportSection::new(title), |section, entry| {
+                    let last =
+                        entry.latencies_ms.last().copied().unwrap_or_default();
+                    section.row(
+                        &entry.server_id,
+                        format!("{} {:.0}ms", sparkline(&entry.latencies_ms), last),
+                    )
+                })
+        };
+        let mcp = latency_section("MCP tool call latency", &self.mcp_latency);
+        let cold_starts =
+            latency_section("MCP cold restarts", &self.mcp_cold_starts);
+
+        let jobs = ReportSection::new("Background jobs")
+            .row("queued", self.queued_jobs.to_string())
+            .row("running", self.running_jobs.to_string());
+
+        let freshness = self.index_freshness.iter().fold(
+            ReportSection::new("Index freshness"),
+            |section, index| {
+                let value = match index.updated_at {
+                    Some(updated_at) => format!(
+                        "updated {}",
+                        format_age(
+                            self.generated_at
+                                .duration_since(updated_at)
+                                .unwrap_or_default()
+                        )
+                    ),
+                    None => "not built".to_string(),
+                };
+                section.row(index.source.name(), value)
+            },
+        );
+
+        let metrics = self
+            .metrics
+            .iter()
+            .fold(ReportSection::new("Metrics"), |section, (name, value)| {
+                section.row(name, value.to_string())
+            });
+
+        vec![startup, memory, mcp, cold_starts, jobs, freshness, metrics]
+    }
+}
+
+/// Sidebar panel showing the performance diagnostics
+#[derive(Default)]
+pub struct DiagnosticsPanel {
+    refresh: Arc<RefreshState>,
+}
+
+/// State the panel shares with the thread refreshing its view
+#[derive(Default)]
+struct RefreshState {
+    visible: AtomicBool,
+    /// Number of the current view; the threads of older views stop
+    view: AtomicU64,
+}
+
+impl DiagnosticsPanel {
+    pub const ID: &'static str = "catalyst.diagnostics";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start sending a new report every `interval` while the panel is
+    /// visible, stopping the thread of the previous view
+    ///
+    /// The thread only holds a weak handle to the panel, so it also stops
+    /// once the panel is dropped.
+    fn spawn_refresh(
+        &self,
+        interval: Duration,
+    ) -> Result<(
+        crossbeam_channel::Receiver<DiagnosticsReport>,
+        JoinHandle<()>,
+    )> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let view = self.refresh.view.fetch_add(1, Ordering::SeqCst) + 1;
+        self.refresh.visible.store(true, Ordering::SeqCst);
+        let refresh = Arc::downgrade(&self.refresh);
+        let thread = std::thread::Builder::new()
+            .name("DiagnosticsPanel".to_string())
+            .spawn(move || {
+                while is_current(&refresh, view) {
+                    std::thread::sleep(interval);
+                    let visible = refresh.upgrade().is_some_and(|refresh| {
+                        refresh.visible.load(Ordering::SeqCst)
+                    });
+                    // Skipped while the view hasn't taken the last report
+                    if visible
+                        && is_current(&refresh, view)
+                        && tx
+                            .try_send(DiagnosticsReport::current())
+                            .is_err_and(|err| err.is_disconnected())
+                    {
+                        break;
+                    }
+                }
+            })?;
+        Ok((rx, thread))
+    }
+}
+
+/// Check if the panel is alive and `view` is still its current view
+fn is_current(refresh: &Weak<RefreshState>, view: u64) -> bool {
+    refresh
+        .upgrade()
+        .is_some_and(|refresh| refresh.view.load(Ordering::SeqCst) == view)
+}
+
+impl SidebarPanelPlugin for DiagnosticsPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Diagnostics".to_string(),
+            description: "Startup, memory, MCP latency, jobs and index freshness"
+                .to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(250),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let (_, rx) = crossbeam_channel::bounded(1);
+        let rx = match self.spawn_refresh(DEFAULT_DIAGNOSTICS_REFRESH_INTERVAL) {
+            Ok((rx, _)) => rx,
+            Err(err) => {
+                tracing::error!("Failed to start diagnostics refresh: {err:#}");
+                rx
+            }
+        };
+        let initial = DiagnosticsReport::current();
+        let report = create_signal_from_channel(rx);
+        text_panel_view(move || {
+            report
+                .get()
+                .unwrap_or_else(|| initial.clone())
+                .render(ReportFormat::Text)
+                .unwrap_or_default()
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        self.refresh.visible.store(false, Ordering::SeqCst);
+        self.refresh.view.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, visible: bool) -> Result<()> {
+        self.refresh.visible.store(visible, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(DiagnosticsReport::current()).unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match command.command_id.as_str() {
+            "snapshot" => PanelCommandResult {
+                success: true,
+                result: Some(self.get_state()),
+                error: None,
+            },
+            other => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!(
+                    "Unknown diagnostics panel command '{}'",
+                    other
+                )),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{AllocTag, mcp_cold_start_metric, mcp_latency_metric};
+
+    #[test]
+    fn test_diagnostics_report() {
+        assert_eq!(sparkline(&[1.0, 8.0, 4.5]), "▁█▅");
+        assert_eq!(sparkline(&[3.0, 3.0]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+
+        let metrics = MetricsRegistry::default();
+        for latency in [10.0, 40.0, 20.0] {
+            metrics.record_sample(&mcp_latency_metric("github"), latency);
+        }
+        metrics.record_sample(&mcp_cold_start_metric("github"), 850.0);
+        metrics.set_gauge("scheduler.queued_jobs", 3.0);
+        let updated_at = current_time() - Duration::from_secs(90);
+        metrics.set_gauge(
+            &RetrievalSource::Trigram.updated_metric(),
+            updated_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs_f64(),
+        );
+        let phases = vec![PhaseTiming {
+            name: "load_config",
+            thread: "main".to_string(),
+            started_at: Duration::from_millis(5),
+            duration: Duration::from_millis(12),
+        }];
+        let memory = vec![SubsystemAllocation {
+            tag: AllocTag::Index,
+            live_bytes: 3 * 1024 * 1024,
+            live_allocations: 12,
+        }];
+
+        let report = DiagnosticsReport::collect(&metrics, phases, Some(memory));
+        assert_eq!(report.mcp_latency[0].latencies_ms, [10.0, 40.0, 20.0]);
+        assert_eq!(report.mcp_cold_starts[0].latencies_ms, [850.0]);
+        assert_eq!(report.queued_jobs, 3);
+        let text = report.render(ReportFormat::Text).unwrap();
+        for line in [
+            "  load_config  12.0ms on main",
+            "  index  3.0 MB (12 allocations)",
+            "  github  ▁█▃ 20ms",
+            "  github  ▄ 850ms",
+            "  queued   3",
+            "  trigram  updated 1m ago",
+            "  vector   not built",
+        ] {
+            assert!(text.contains(line), "missing {line:?} in\n{text}");
+        }
+    }
+
+    #[test]
+    fn test_refresh_stops() {
+        let interval = Duration::from_millis(1);
+        let mut panel = DiagnosticsPanel::new();
+        let (reports, thread) = panel.spawn_refresh(interval).unwrap();
+        reports.recv().unwrap();
+
+        // Hidden panels aren't refreshed
+        panel.on_visibility_changed(false).unwrap();
+        std::thread::sleep(interval * 20);
+        while reports.try_recv().is_ok() {}
+        std::thread::sleep(interval * 20);
+        assert!(reports.try_recv().is_err());
+        panel.on_visibility_changed(true).unwrap();
+        reports.recv().unwrap();
+
+        // A new view stops the thread of the old one
+        let (reports, next) = panel.spawn_refresh(interval).unwrap();
+        thread.join().unwrap();
+        reports.recv().unwrap();
+
+        panel.on_deactivate().unwrap();
+        next.join().unwrap();
+
+        let (_reports, thread) = panel.spawn_refresh(interval).unwrap();
+        drop(panel);
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,587 @@
+//! Diff Context
+//!
+//! This module provides the context for questions about changes rather than
+//! code, such as "summarize my changes" or "write a changelog entry for this
+//! branch". Instead of file snapshots it assembles the diff of the working
+//! tree, the staged changes, a commit range or a branch, split into hunks.
+//! Each hunk is a chunk that can be cited like any other source, and the
+//! prompt groups the files by directory so related changes read together.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::plugin_api::{ContextChunk, SharedPath, SourceTracker, WorkspaceRoots};
+
+/// Lines of a hunk put in one chunk; longer hunks are split
+const MAX_CHUNK_LINES: usize = 80;
+/// Untracked files larger than this are listed without their content
+const MAX_UNTRACKED_BYTES: u64 = 64 * 1024;
+/// Default size of the diff in a prompt
+pub const DEFAULT_DIFF_PROMPT_CHARS: usize = 24_000;
+
+/// Which changes to look at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffRange {
+    /// Staged and unstaged changes against `HEAD`, and untracked files
+    WorkingTree,
+    /// Staged changes only
+    Staged,
+    /// Changes between two commits, or from a commit to the working tree
+    Commits { from: String, to: Option<String> },
+    /// Changes on the current branch since it forked from `base`
+    Branch { base: String },
+}
+
+impl DiffRange {
+    /// Parse the range typed by the user
+    ///
+    /// Nothing means the working tree, `staged` the staged changes,
+    /// `a..b` a commit range and `a...` or a single ref the changes since
+    /// the current branch forked from it.
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let check = |reference: &str| -> Result<String> {
+            if reference.starts_with('-') || reference.contains(char::is_whitespace)
+            {
+                return Err(anyhow::anyhow!(
+                    "'{}' is not a valid git ref",
+                    reference
+                ));
+            }
+            Ok(reference.to_string())
+        };
+        Ok(match input {
+            "" | "working" => Self::WorkingTree,
+            "staged" => Self::Staged,
+            _ => {
+                if let Some((base, head)) = input.split_once("...") {
+                    if !head.is_empty() && head != "HEAD" {
+                        return Err(anyhow::anyhow!(
+                            "Only the current branch can be compared with a base, \
+                             as in '{}...'",
+                            base
+                        ));
+                    }
+                    Self::Branch { base: check(base)? }
+                } else if let Some((from, to)) = input.split_once("..") {
+                    Self::Commits {
+                        from: check(from)?,
+                        to: (!to.is_empty()).then(|| check(to)).transpose()?,
+                    }
+                } else {
+                    Self::Branch {
+                        base: check(input)?,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Get a description of the range
+    pub fn label(&self) -> String {
+        match self {
+            Self::WorkingTree => "uncommitted changes".to_string(),
+            Self::Staged => "staged changes".to_string(),
+            Self::Commits { from, to } => {
+                format!("{}..{}", from, to.as_deref().unwrap_or("working tree"))
+            }
+            Self::Branch { base } => format!("changes since {}", base),
+        }
+    }
+
+    fn git_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "diff".to_string(),
+            "--no-color".to_string(),
+            "--no-ext-diff".to_string(),
+            "--find-renames".to_string(),
+        ];
+        match self {
+            Self::WorkingTree => args.push("HEAD".to_string()),
+            Self::Staged => args.push("--cached".to_string()),
+            Self::Commits { from, to } => {
+                args.push(from.clone());
+                args.extend(to.clone());
+            }
+            Self::Branch { base } => args.push(format!("{}...HEAD", base)),
+        }
+        args.push("--".to_string());
+        args
+    }
+}
+
+/// How a file changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFileStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+}
+
+/// A hunk of a file's diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffHunk {
+    /// 1-based, as in the hunk header
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    /// The enclosing function or section git shows after the header
+    pub section: String,
+    /// The diff lines, starting with ` `, `+` or `-`
+    pub lines: Vec<String>,
+}
+
+impl DiffHunk {
+    fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@ {}",
+            self.old_start,
+            self.old_lines,
+            self.new_start,
+            self.new_lines,
+            self.section
+        )
+        .trim_end()
+        .to_string()
+    }
+
+    pub fn additions(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| line.starts_with('+'))
+            .count()
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| line.starts_with('-'))
+            .count()
+    }
+}
+
+/// The diff of one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDiff {
+    /// Relative to the workspace root; the new path of a renamed file
+    pub path: PathBuf,
+    pub old_path: Option<PathBuf>,
+    pub status: DiffFileStatus,
+    pub binary: bool,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileDiff {
+    pub fn additions(&self) -> usize {
+        self.hunks.iter().map(DiffHunk::additions).sum()
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.hunks.iter().map(DiffHunk::deletions).sum()
+    }
+
+    fn describe(&self) -> String {
+        let status = match (&self.status, &self.old_path) {
+            (DiffFileStatus::Renamed, Some(old_path)) => {
+                format!("renamed from {}", old_path.display())
+            }
+            (status, _) => format!("{:?}", status).to_lowercase(),
+        };
+        if self.binary {
+            format!("{}, {}, binary", self.path.display(), status)
+        } else {
+            format!(
+                "{}, {}, +{} -{}",
+                self.path.display(),
+                status,
+                self.additions(),
+                self.deletions()
+            )
+        }
+    }
+}
+
+/// Parse the output of `git diff`
+pub fn parse_unified_diff(text: &str) -> Vec<FileDiff> {
+    let unquote = |path: &str| -> PathBuf {
+        let path = path.trim().trim_matches('"');
+        PathBuf::from(
+            path.strip_prefix("a/")
+                .or_else(|| path.strip_prefix("b/"))
+                .unwrap_or(path),
+        )
+    };
+
+    let mut files: Vec<FileDiff> = Vec::new();
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            // `a/path b/path`; the paths are corrected by the lines below
+            let path = header
+                .rsplit_once(" b/")
+                .map(|(_, path)| PathBuf::from(path))
+                .unwrap_or_else(|| unquote(header));
+            files.push(FileDiff {
+                path,
+                old_path: None,
+                status: DiffFileStatus::Modified,
+                binary: false,
+                hunks: Vec::new(),
+            });
+            continue;
+        }
+        let Some(file) = files.last_mut() else {
+            continue;
+        };
+        if let Some(hunk) = file.hunks.last_mut() {
+            if line.starts_with([' ', '+', '-']) && !line.starts_with("--- ")
+                || line.starts_with('\\')
+            {
+                if !line.starts_with('\\') {
+                    hunk.lines.push(line.to_string());
+                }
+                continue;
+            }
+        }
+        if let Some(header) = line.strip_prefix("@@ ") {
+            let Some((ranges, section)) = header.split_once(" @@") else {
+                continue;
+            };
+            let range = |range: Option<&str>| -> (usize, usize) {
+                let range = range.unwrap_or_default();
+                let (start, count) = range[1.min(range.len())..]
+                    .split_once(',')
+                    .unwrap_or((&range[1.min(range.len())..], "1"));
+                (start.parse().unwrap_or(0), count.parse().unwrap_or(1))
+            };
+            let mut parts = ranges.split_whitespace();
+            let (old_start, old_lines) = range(parts.next());
+            let (new_start, new_lines) = range(parts.next());
+            file.hunks.push(DiffHunk {
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                section: section.trim().to_string(),
+                lines: Vec::new(),
+            });
+        } else if line.starts_with("new file mode") {
+            file.status = DiffFileStatus::Added;
+        } else if line.starts_with("deleted file mode") {
+            file.status = DiffFileStatus::Deleted;
+        } else if let Some(from) = line.strip_prefix("rename from ") {
+            file.status = DiffFileStatus::Renamed;
+            file.old_path = Some(PathBuf::from(from));
+        } else if let Some(to) = line.strip_prefix("rename to ") {
+            file.path = PathBuf::from(to);
+        } else if line.starts_with("Binary files ") {
+            file.binary = true;
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            if path != "/dev/null" {
+                file.path = unquote(path);
+            }
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            if path == "/dev/null" {
+                file.status = DiffFileStatus::Added;
+            }
+        }
+    }
+    files
+}
+
+/// The changes of a range, ready to be given to the assistant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffContext {
+    pub root: PathBuf,
+    pub range: DiffRange,
+    pub files: Vec<FileDiff>,
+}
+
+impl DiffContext {
+    /// Read the changes of a range in a git workspace
+    pub fn load(root: &Path, range: DiffRange) -> Result<Self> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(range.git_args())
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "git diff failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let mut files = parse_unified_diff(&String::from_utf8_lossy(&output.stdout));
+        if range == DiffRange::WorkingTree {
+            files.extend(untracked_files(root)?);
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self {
+            root: root.to_path_buf(),
+            range,
+            files,
+        })
+    }
+
+    /// Read the changes of a range in every root of a multi-root workspace
+    /// that is a git repository, skipping the roots without changes
+    pub fn load_roots(
+        roots: &WorkspaceRoots,
+        range: DiffRange,
+    ) -> Result<Vec<Self>> {
+        let mut diffs = Vec::new();
+        for root in roots.roots() {
+            if !root.path.join(".git").exists() {
+                continue;
+            }
+            let diff = Self::load(&root.path, range.clone())?;
+            if !diff.is_empty() {
+                diffs.push(diff);
+            }
+        }
+        Ok(diffs)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Split the diff into chunks of at most one hunk
+    ///
+    /// A chunk covers the hunk's lines in the new file, or in the old one
+    /// for a deleted file.
+    pub fn chunks(&self) -> Vec<ContextChunk> {
+        let mut chunks = Vec::new();
+        for file in &self.files {
+            let deleted = file.status == DiffFileStatus::Deleted;
+            let path =
+                SharedPath::from(self.root.join(match (&file.old_path, deleted) {
+                    (Some(old_path), true) => old_path,
+                    _ => &file.path,
+                }));
+            for hunk in &file.hunks {
+                let (start, count) = if deleted {
+                    (hunk.old_start, hunk.old_lines)
+                } else {
+                    (hunk.new_start, hunk.new_lines)
+                };
+                let pieces = hunk.lines.chunks(MAX_CHUNK_LINES).collect::<Vec<_>>();
+                let last = pieces.len().saturating_sub(1);
+                for (index, lines) in pieces.into_iter().enumerate() {
+                    // Every piece repeats the header so it reads on its own
+                    let content = format!("{}\n{}", hunk.header(), lines.join("\n"));
+                    let piece_start = start + index * MAX_CHUNK_LINES;
+                    let piece_end = if index == last {
+                        (start + count).saturating_sub(1).max(piece_start)
+                    } else {
+                        piece_start + MAX_CHUNK_LINES - 1
+                    };
+                    chunks.push(ContextChunk {
+                        path: path.clone(),
+                        start_line: piece_start.max(1),
+                        end_line: piece_end.max(1),
+                        content,
+                        modified: None,
+                    });
+                }
+            }
+        }
+        chunks
+    }
+
+    /// Add every hunk as a citable source
+    pub fn add_sources(&self, sources: &mut SourceTracker) {
+        for chunk in self.chunks() {
+            sources.add_chunk(&chunk);
+        }
+    }
+
+    /// Describe the changes for the prompt, within about `max_chars`
+    ///
+    /// Files are grouped by directory. Once the budget is spent, the
+    /// remaining files are only listed with their line counts.
+    pub fn prompt(&self, max_chars: usize) -> String {
+        let additions: usize = self.files.iter().map(FileDiff::additions).sum();
+        let deletions: usize = self.files.iter().map(FileDiff::deletions).sum();
+        let mut prompt = format!(
+            "Diff of the {}: {} files changed, +{} -{}\n",
+            self.range.label(),
+            self.files.len(),
+            additions,
+            deletions
+        );
+        let mut omitted = Vec::new();
+        let mut directory: Option<&Path> = None;
+        for file in &self.files {
+            let mut section = String::new();
+            let parent = file.path.parent().unwrap_or(Path::new(""));
+            if directory != Some(parent) {
+                section.push_str(&format!(
+                    "\n## {}/\n",
+                    if parent.as_os_str().is_empty() {
+                        Path::new(".")
+                    } else {
+                        parent
+                    }
+                    .display()
+                ));
+            }
+            section.push_str(&format!("\n### {}\n", file.describe()));
+            if !file.hunks.is_empty() {
+                section.push_str("```diff\n");
+                for hunk in &file.hunks {
+                    section.push_str(&hunk.header());
+                    section.push('\n');
+                    for line in &hunk.lines {
+                        section.push_str(line);
+                        section.push('\n');
+                    }
+                }
+                section.push_str("```\n");
+            }
+            if prompt.len() + section.len() > max_chars {
+                omitted.push(file.describe());
+                continue;
+            }
+            directory = Some(parent);
+            prompt.push_str(&section);
+        }
+        if !omitted.is_empty() {
+            prompt.push_str("\nOther changed files, diff left out for length:\n");
+            for file in omitted {
+                prompt.push_str(&format!("- {}\n", file));
+            }
+        }
+        prompt
+    }
+}
+
+/// Get the untracked files of a working tree as added files
+fn untracked_files(root: &Path) -> Result<Vec<FileDiff>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-files", "--others", "--exclude-standard", "-z"])
+        .output()?;
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for path in String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+    {
+        let full = root.join(path);
+        let content = std::fs::metadata(&full)
+            .ok()
+            .filter(|metadata| metadata.len() <= MAX_UNTRACKED_BYTES)
+            .and_then(|_| std::fs::read(&full).ok())
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+        let hunks = content
+            .map(|content| {
+                let lines: Vec<String> =
+                    content.lines().map(|line| format!("+{}", line)).collect();
+                vec![DiffHunk {
+                    old_start: 0,
+                    old_lines: 0,
+                    new_start: 1,
+                    new_lines: lines.len(),
+                    section: String::new(),
+                    lines,
+                }]
+            })
+            .unwrap_or_default();
+        files.push(FileDiff {
+            path: PathBuf::from(path),
+            old_path: None,
+            status: DiffFileStatus::Added,
+            binary: hunks.is_empty(),
+            hunks,
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@ mod parser;
+ fn a() {}
+-fn b() {}
++fn b() -> u8 { 1 }
++fn c() {}
+ fn d() {}
+diff --git a/old.txt b/docs/new.txt
+similarity index 90%
+rename from old.txt
+rename to docs/new.txt
+diff --git a/gone.rs b/gone.rs
+deleted file mode 100644
+--- a/gone.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+-fn gone() {}
+-// bye
+diff --git a/logo.png b/logo.png
+new file mode 100644
+Binary files /dev/null and b/logo.png differ
+";
+
+    #[test]
+    fn test_parses_and_chunks_diffs() {
+        let files = parse_unified_diff(DIFF);
+        assert_eq!(files.len(), 4);
+        assert_eq!(files[0].path, Path::new("src/lib.rs"));
+        assert_eq!((files[0].additions(), files[0].deletions()), (2, 1));
+        assert_eq!(files[0].hunks[0].section, "mod parser;");
+        assert_eq!(files[1].status, DiffFileStatus::Renamed);
+        assert_eq!(files[1].path, Path::new("docs/new.txt"));
+        assert_eq!(files[2].status, DiffFileStatus::Deleted);
+        assert_eq!(files[2].path, Path::new("gone.rs"));
+        assert!(files[3].binary && files[3].status == DiffFileStatus::Added);
+
+        let context = DiffContext {
+            root: PathBuf::from("/w"),
+            range: DiffRange::WorkingTree,
+            files,
+        };
+        let chunks = context.chunks();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 4));
+        assert_eq!(chunks[1].path, Path::new("/w/gone.rs"));
+        assert!(chunks[1].content.starts_with("@@ -1,2 +0,0 @@\n-fn gone"));
+
+        let prompt = context.prompt(DEFAULT_DIFF_PROMPT_CHARS);
+        assert!(prompt.starts_with("Diff of the uncommitted changes: 4 files"));
+        assert!(prompt.contains("## src/\n\n### src/lib.rs, modified, +2 -1"));
+        let short = context.prompt(100);
+        assert!(short.contains("diff left out for length:\n- src/lib.rs"));
+
+        assert_eq!(DiffRange::parse("").unwrap(), DiffRange::WorkingTree);
+        assert_eq!(
+            DiffRange::parse("main").unwrap(),
+            DiffRange::Branch {
+                base: "main".to_string()
+            }
+        );
+        assert_eq!(
+            DiffRange::parse("v1.0..v1.1").unwrap(),
+            DiffRange::Commits {
+                from: "v1.0".to_string(),
+                to: Some("v1.1".to_string())
+            }
+        );
+        assert!(DiffRange::parse("--output=x").is_err());
+    }
+}
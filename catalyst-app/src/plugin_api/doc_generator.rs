@@ -0,0 +1,574 @@
+//! Documentation Generator
+//!
+//! This module finds the public items of a module that have no doc
+//! comment and has the assistant write them in batches. Items are found in
+//! the tree-sitter tree of each file when its grammar is loaded, and with a
+//! line scan otherwise. Each language has a style template that tells the
+//! assistant how its docs are written and how they are laid out as
+//! comments. The comments are proposed as an [`EditReview`], where every
+//! item is its own hunk, so the reviewer accepts them one by one before
+//! they are applied as a `WorkspaceEdit`.
+
+use anyhow::Result;
+use catalyst_core::{language::LapceLanguage, syntax::Syntax};
+use ignore::WalkBuilder;
+use lapce_xi_rope::Rope;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{EditReview, ProposedEdit, WorkspaceSandbox};
+
+/// Items documented per request to the assistant
+pub const DEFAULT_DOC_BATCH_SIZE: usize = 10;
+/// Files visited when documenting a directory
+const MAX_FILES: usize = 200;
+/// Lines of an item's source shown to the assistant
+const MAX_SOURCE_LINES: usize = 40;
+
+static RUST_ITEM: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^\s*pub\s+(?:(?:async|const|unsafe|extern\s+"[^"]*")\s+)*(fn|struct|enum|union|trait|type|const|static|mod)\s+([A-Za-z_]\w*)"#,
+    )
+    .unwrap()
+});
+static PYTHON_ITEM: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*(?:async\s+)?(def|class)\s+([A-Za-z]\w*)").unwrap()
+});
+static GO_ITEM: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(func|type)\s+(?:\([^)]*\)\s*)?([A-Z]\w*)").unwrap());
+static JS_ITEM: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\s*export\s+(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(function|class|const|let|interface|type|enum)\s+([A-Za-z_$][\w$]*)",
+    )
+    .unwrap()
+});
+
+/// How the docs of a language are written and laid out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocTemplate {
+    /// Line above the doc, e.g. `/**`
+    #[serde(default)]
+    pub open: Option<String>,
+    /// Prefix of every doc line, e.g. `/// `
+    #[serde(default)]
+    pub line_prefix: String,
+    /// Line below the doc, e.g. ` */`
+    #[serde(default)]
+    pub close: Option<String>,
+    /// The doc is the first statement of the item's body, like a Python
+    /// docstring, instead of a comment above the item
+    #[serde(default)]
+    pub in_body: bool,
+    /// Style guidance for the assistant
+    pub guidance: String,
+}
+
+impl DocTemplate {
+    /// Get the default template of a language
+    pub fn for_language(language: LapceLanguage) -> Option<Self> {
+        let template = match language {
+            LapceLanguage::Rust => Self {
+                open: None,
+                line_prefix: "/// ".to_string(),
+                close: None,
+                in_body: false,
+                guidance: "Start with a one-line summary in the third person. Add \
+                           `# Errors` or `# Panics` sections only when the item \
+                           returns errors or can panic."
+                    .to_string(),
+            },
+            LapceLanguage::Python => Self {
+                open: Some("\"\"\"".to_string()),
+                line_prefix: String::new(),
+                close: Some("\"\"\"".to_string()),
+                in_body: true,
+                guidance: "Use Google style: a one-line summary, then `Args:` and \
+                           `Returns:` sections for functions that take arguments \
+                           or return a value."
+                    .to_string(),
+            },
+            LapceLanguage::Go => Self {
+                open: None,
+                line_prefix: "// ".to_string(),
+                close: None,
+                in_body: false,
+                guidance: "Start with the item's name, as in `Name does ...`, \
+                           following Go doc conventions."
+                    .to_string(),
+            },
+            LapceLanguage::Javascript
+            | LapceLanguage::Jsx
+            | LapceLanguage::Typescript
+            | LapceLanguage::Tsx => Self {
+                open: Some("/**".to_string()),
+                line_prefix: " * ".to_string(),
+                close: Some(" */".to_string()),
+                in_body: false,
+                guidance: "Use JSDoc: a one-line summary, then `@param` and \
+                           `@returns` tags for functions. Leave out types that \
+                           TypeScript already declares."
+                    .to_string(),
+            },
+            _ => return None,
+        };
+        Some(template)
+    }
+
+    /// Lay out a doc as comment lines with the given indentation
+    pub fn render(&self, doc: &str, indent: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(open) = &self.open {
+            lines.push(format!("{indent}{open}"));
+        }
+        for line in doc.trim().lines() {
+            lines.push(
+                format!("{indent}{}{}", self.line_prefix, line.trim_end())
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+        if let Some(close) = &self.close {
+            lines.push(format!("{indent}{close}"));
+        }
+        lines
+    }
+
+    /// Build the prompt asking for the docs of a batch of items
+    pub fn prompt(&self, items: &[UndocumentedItem]) -> String {
+        let mut prompt = format!(
+            "Write documentation for each of the following {} items. {}\n\n\
+             Reply with only a JSON array of strings, one per item in the same \
+             order, holding the documentation text without comment markers.\n",
+            items.len(),
+            self.guidance
+        );
+        for (index, item) in items.iter().enumerate() {
+            prompt.push_str(&format!(
+                "\n{}. {} `{}` in {}:\n```\n{}\n```\n",
+                index + 1,
+                item.kind,
+                item.name,
+                item.path.display(),
+                item.source
+            ));
+        }
+        prompt
+    }
+}
+
+/// Parse the assistant's reply to [`DocTemplate::prompt`]
+pub fn parse_doc_response(response: &str) -> Result<Vec<String>> {
+    let start = response
+        .find('[')
+        .ok_or_else(|| anyhow::anyhow!("The reply has no JSON array"))?;
+    let end = response
+        .rfind(']')
+        .filter(|end| *end > start)
+        .ok_or_else(|| anyhow::anyhow!("The reply has no JSON array"))?;
+    Ok(serde_json::from_str(&response[start..=end])?)
+}
+
+/// A public item without documentation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndocumentedItem {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    pub name: String,
+    pub kind: String,
+    /// Line the doc is inserted at, 0-based
+    pub insert_line: usize,
+    pub indent: String,
+    /// Source of the item, truncated
+    pub source: String,
+}
+
+/// Writes the docs of items with the assistant
+pub trait DocWriter: Send + Sync {
+    /// Write the documentation text of each item, in order and without
+    /// comment markers
+    fn write_docs(
+        &self,
+        template: &DocTemplate,
+        items: &[UndocumentedItem],
+    ) -> Result<Vec<String>>;
+}
+
+/// Generated docs, ready for review
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocGeneration {
+    /// Items with their generated docs
+    pub items: Vec<(UndocumentedItem, String)>,
+    pub review: EditReview,
+}
+
+/// Generates doc comments for the undocumented public items of a module
+pub struct DocGenerator {
+    sandbox: Arc<WorkspaceSandbox>,
+    writer: Arc<dyn DocWriter>,
+    templates: HashMap<LapceLanguage, DocTemplate>,
+    batch_size: usize,
+}
+
+impl DocGenerator {
+    pub fn new(sandbox: Arc<WorkspaceSandbox>, writer: Arc<dyn DocWriter>) -> Self {
+        Self {
+            sandbox,
+            writer,
+            templates: HashMap::new(),
+            batch_size: DEFAULT_DOC_BATCH_SIZE,
+        }
+    }
+
+    /// Replace the default template of a language
+    pub fn with_template(
+        mut self,
+        language: LapceLanguage,
+        template: DocTemplate,
+    ) -> Self {
+        self.templates.insert(language, template);
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn template(&self, language: LapceLanguage) -> Option<DocTemplate> {
+        self.templates
+            .get(&language)
+            .cloned()
+            .or_else(|| DocTemplate::for_language(language))
+    }
+
+    /// Find the undocumented public items of a file or directory
+    pub fn find(&self, module: &Path) -> Result<Vec<UndocumentedItem>> {
+        let module = self.sandbox.check_access(module)?;
+        let mut paths = Vec::new();
+        if module.is_dir() {
+            let sandbox = self.sandbox.clone();
+            let walker = WalkBuilder::new(&module)
+                .filter_entry(move |entry| sandbox.is_allowed(entry.path()))
+                .build();
+            for entry in walker.flatten() {
+                if paths.len() >= MAX_FILES {
+                    break;
+                }
+                let path = entry.path();
+                if entry.file_type().is_some_and(|t| t.is_file())
+                    && LapceLanguage::from_path_raw(path)
+                        .is_some_and(|language| self.template(language).is_some())
+                {
+                    paths.push(path.to_path_buf());
+                }
+            }
+            paths.sort();
+        } else {
+            paths.push(module);
+        }
+
+        let mut items = Vec::new();
+        for path in paths {
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let relative = path
+                .strip_prefix(self.sandbox.root())
+                .unwrap_or(&path)
+                .to_path_buf();
+            let Some(template) = LapceLanguage::from_path_raw(&path)
+                .and_then(|language| self.template(language))
+            else {
+                continue;
+            };
+            items.extend(find_undocumented(&relative, &text, &template));
+        }
+        Ok(items)
+    }
+
+    /// Generate the docs of a file or directory
+    pub fn generate(&self, module: &Path) -> Result<DocGeneration> {
+        let items = self.find(module)?;
+        let mut by_language: Vec<(LapceLanguage, Vec<UndocumentedItem>)> =
+            Vec::new();
+        for item in items {
+            let language = LapceLanguage::from_path(&item.path);
+            match by_language.iter_mut().find(|(l, _)| *l == language) {
+                Some((_, items)) => items.push(item),
+                None => by_language.push((language, vec![item])),
+            }
+        }
+
+        let mut documented = Vec::new();
+        let mut by_file: HashMap<PathBuf, Vec<(usize, Vec<String>)>> =
+            HashMap::new();
+        for (language, items) in by_language {
+            let Some(template) = self.template(language) else {
+                continue;
+            };
+            for batch in items.chunks(self.batch_size) {
+                let docs = self.writer.write_docs(&template, batch)?;
+                if docs.len() != batch.len() {
+                    return Err(anyhow::anyhow!(
+                        "Expected docs for {} items but got {}",
+                        batch.len(),
+                        docs.len()
+                    ));
+                }
+                for (item, doc) in batch.iter().zip(docs) {
+                    if doc.trim().is_empty() {
+                        continue;
+                    }
+                    by_file.entry(item.path.clone()).or_default().push((
+                        item.insert_line,
+                        template.render(&doc, &item.indent),
+                    ));
+                    documented.push((item.clone(), doc));
+                }
+            }
+        }
+
+        let mut edits = Vec::new();
+        let mut paths: Vec<PathBuf> = by_file.keys().cloned().collect();
+        paths.sort();
+        for path in paths {
+            let mut insertions = by_file.remove(&path).unwrap_or_default();
+            let original =
+                std::fs::read_to_string(self.sandbox.check_access(&path)?)?;
+            let line_ending = if original.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            };
+            let mut lines: Vec<String> =
+                original.lines().map(|line| line.to_string()).collect();
+            // Insert from the bottom so earlier lines keep their numbers
+            insertions.sort_by(|a, b| b.0.cmp(&a.0));
+            for (line, comment) in insertions {
+                let line = line.min(lines.len());
+                lines.splice(line..line, comment);
+            }
+            let mut proposed = lines.join(line_ending);
+            if original.ends_with('\n') {
+                proposed.push_str(line_ending);
+            }
+            edits.push(ProposedEdit {
+                path,
+                original: Some(original),
+                proposed: Some(proposed),
+            });
+        }
+
+        Ok(DocGeneration {
+            items: documented,
+            review: EditReview::new(edits),
+        })
+    }
+}
+
+/// Find the undocumented public items of a file
+///
+/// The syntax tree is used when the language's grammar is loaded; otherwise
+/// the file is scanned line by line.
+pub fn find_undocumented(
+    path: &Path,
+    text: &str,
+    template: &DocTemplate,
+) -> Vec<UndocumentedItem> {
+    let lines: Vec<&str> = text.lines().collect();
+    let indent_of = |line: usize| -> String {
+        lines
+            .get(line)
+            .map(|l| l[..l.len() - l.trim_start().len()].to_string())
+            .unwrap_or_default()
+    };
+    let source_of = |start: usize, end: usize| -> String {
+        lines
+            .get(start..=end.min(start + MAX_SOURCE_LINES - 1).min(lines.len() - 1))
+            .map(|lines| lines.join("\n"))
+            .unwrap_or_default()
+    };
+
+    let mut syntax = Syntax::init(path);
+    syntax.parse(0, Rope::from(text), None);
+    if let Some(items) = syntax.public_items() {
+        return items
+            .into_iter()
+            .filter(|item| !item.documented)
+            .map(|item| {
+                let insert_line = if template.in_body {
+                    item.body_line.unwrap_or(item.start_line + 1)
+                } else {
+                    item.start_line
+                };
+                UndocumentedItem {
+                    path: path.to_path_buf(),
+                    indent: indent_of(insert_line),
+                    source: source_of(item.start_line, item.end_line),
+                    name: item.name,
+                    kind: item.kind,
+                    insert_line,
+                }
+            })
+            .collect();
+    }
+
+    scan_undocumented(path, &lines, template)
+        .into_iter()
+        .map(
+            |(name, kind, start_line, insert_line, indent)| UndocumentedItem {
+                path: path.to_path_buf(),
+                name,
+                kind,
+                insert_line,
+                indent,
+                source: source_of(start_line, start_line + MAX_SOURCE_LINES),
+            },
+        )
+        .collect()
+}
+
+/// Scan lines for undocumented public items when there is no syntax tree,
+/// giving each item's name, kind, line, doc line and indentation
+fn scan_undocumented(
+    path: &Path,
+    lines: &[&str],
+    template: &DocTemplate,
+) -> Vec<(String, String, usize, usize, String)> {
+    let language = LapceLanguage::from_path(path);
+    let pattern: &Regex = match language {
+        LapceLanguage::Rust => &RUST_ITEM,
+        LapceLanguage::Python => &PYTHON_ITEM,
+        LapceLanguage::Go => &GO_ITEM,
+        LapceLanguage::Javascript
+        | LapceLanguage::Jsx
+        | LapceLanguage::Typescript
+        | LapceLanguage::Tsx => &JS_ITEM,
+        _ => return Vec::new(),
+    };
+    let indent_of =
+        |line: &str| line[..line.len() - line.trim_start().len()].to_string();
+
+    let mut items = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some(captures) = pattern.captures(line) else {
+            continue;
+        };
+        let kind = captures[1].to_string();
+        let name = captures[2].to_string();
+        if template.in_body {
+            let body = lines[index + 1..]
+                .iter()
+                .position(|line| !line.trim().is_empty())
+                .map(|offset| index + 1 + offset);
+            let documented = body.is_some_and(|body| {
+                let first = lines[body].trim_start();
+                first.starts_with("\"\"\"") || first.starts_with("'''")
+            });
+            if documented || name.starts_with('_') {
+                continue;
+            }
+            let insert_line = body.unwrap_or(index + 1);
+            let indent = body
+                .map(|body| indent_of(lines[body]))
+                .unwrap_or_else(|| format!("{}    ", indent_of(line)));
+            items.push((name, kind, index, insert_line, indent));
+            continue;
+        }
+
+        // Attributes and decorators sit between the doc and the item
+        let mut start_line = index;
+        while start_line > 0 {
+            let previous = lines[start_line - 1].trim_start();
+            if previous.starts_with("#[") || previous.starts_with('@') {
+                start_line -= 1;
+            } else {
+                break;
+            }
+        }
+        let documented = start_line > 0 && {
+            let previous = lines[start_line - 1].trim();
+            match language {
+                LapceLanguage::Rust => {
+                    previous.starts_with("///") || previous.ends_with("*/")
+                }
+                LapceLanguage::Go => previous.starts_with("//"),
+                _ => previous.ends_with("*/"),
+            }
+        };
+        if !documented {
+            items.push((
+                name,
+                kind,
+                index,
+                start_line,
+                indent_of(lines[start_line]),
+            ));
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+
+    struct NamingWriter;
+
+    impl DocWriter for NamingWriter {
+        fn write_docs(
+            &self,
+            _template: &DocTemplate,
+            items: &[UndocumentedItem],
+        ) -> Result<Vec<String>> {
+            Ok(items
+                .iter()
+                .map(|item| format!("Documents `{}`.", item.name))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_generates_docs_for_undocumented_items() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("lib.rs"),
+            "/// Already documented\npub fn a() {}\n\nfn private() {}\n\n\
+             #[derive(Debug)]\npub struct B;\n\nimpl B {\n    pub fn c(&self) {}\n}\n",
+        )
+        .unwrap();
+        let ignore =
+            CatalystIgnore::new(dir.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = Arc::new(WorkspaceSandbox::new(Arc::new(ignore)));
+        let generator = DocGenerator::new(sandbox, Arc::new(NamingWriter));
+
+        let items = generator.find(Path::new("lib.rs")).unwrap();
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["B", "c"]);
+        assert_eq!(items[0].insert_line, 5);
+        assert_eq!(items[1].indent, "    ");
+
+        let generation = generator.generate(Path::new("lib.rs")).unwrap();
+        assert_eq!(generation.items.len(), 2);
+        let file = &generation.review.files[0];
+        assert_eq!(file.hunks.len(), 2);
+        assert_eq!(file.hunks[0].new_lines, vec!["/// Documents `B`."]);
+        assert_eq!(file.hunks[1].new_lines, vec!["    /// Documents `c`."]);
+
+        assert_eq!(
+            parse_doc_response("Sure:\n[\"One.\", \"Two.\"]").unwrap(),
+            vec!["One.", "Two."]
+        );
+        let python = DocTemplate::for_language(LapceLanguage::Python).unwrap();
+        assert_eq!(
+            python.render("Adds.", "    "),
+            vec!["    \"\"\"", "    Adds.", "    \"\"\""]
+        );
+    }
+}
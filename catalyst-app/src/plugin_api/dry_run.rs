@@ -0,0 +1,224 @@
+//! Dry Run
+//!
+//! This module lets the agent plan a turn without changing anything.
+//! While a conversation is in dry-run mode, read-only tools run for real so
+//! the plan rests on real data, and mutating tools are recorded instead of
+//! called: the model gets back a note saying what would have happened and
+//! continues as if the call succeeded. The recorded calls form a plan the
+//! user reviews step by step and approves to run for real.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::plugin_api::{
+    McpContent, McpToolResult, ToolCall, ToolDefinition, ToolEffect,
+};
+
+/// Longest argument text shown in a step summary
+const MAX_SUMMARY_ARGUMENTS: usize = 200;
+
+/// A mutating call recorded during a dry run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedStep {
+    pub call: ToolCall,
+    /// What the call would do, for review
+    pub summary: String,
+    pub approved: bool,
+}
+
+/// Mutating calls planned by a dry run, in order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DryRunPlan {
+    pub steps: Vec<PlannedStep>,
+}
+
+/// Outcome of running a step of an approved plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutedStep {
+    pub step: PlannedStep,
+    pub result: McpToolResult,
+}
+
+impl DryRunPlan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn set_approved(&mut self, step: usize, approved: bool) {
+        if let Some(step) = self.steps.get_mut(step) {
+            step.approved = approved;
+        }
+    }
+
+    pub fn approve_all(&mut self) {
+        for step in &mut self.steps {
+            step.approved = true;
+        }
+    }
+
+    /// Run the approved steps in order with `run`, which dispatches a call
+    /// to its server or built-in tool
+    ///
+    /// Running stops at the first failing step, since later steps were
+    /// planned assuming it succeeded.
+    pub fn execute(
+        &self,
+        mut run: impl FnMut(&ToolCall) -> McpToolResult,
+    ) -> Vec<ExecutedStep> {
+        let mut executed = Vec::new();
+        for step in self.steps.iter().filter(|step| step.approved) {
+            let result = run(&step.call);
+            let failed = result.is_error;
+            executed.push(ExecutedStep {
+                step: step.clone(),
+                result,
+            });
+            if failed {
+                break;
+            }
+        }
+        executed
+    }
+}
+
+/// Tool calls of a turn made in dry-run mode
+pub struct DryRunSession {
+    /// Effects of the offered tools by name
+    effects: HashMap<String, ToolEffect>,
+    plan: DryRunPlan,
+}
+
+impl DryRunSession {
+    /// Start a dry run with the tools offered to the model
+    pub fn new(tools: &[ToolDefinition]) -> Self {
+        Self {
+            effects: tools
+                .iter()
+                .map(|tool| (tool.name.clone(), tool.effect))
+                .collect(),
+            plan: DryRunPlan::default(),
+        }
+    }
+
+    /// Get the effect of a tool; unknown tools are treated as mutating
+    pub fn effect(&self, name: &str) -> ToolEffect {
+        self.effects.get(name).copied().unwrap_or_default()
+    }
+
+    /// Handle a call from the model: read-only calls are run with `run`,
+    /// mutating ones are added to the plan and answered with a simulated
+    /// result
+    pub fn call(
+        &mut self,
+        call: &ToolCall,
+        run: impl FnOnce(&ToolCall) -> McpToolResult,
+    ) -> McpToolResult {
+        if self.effect(&call.name) == ToolEffect::ReadOnly {
+            return run(call);
+        }
+        let summary = step_summary(call);
+        let note = format!(
+            "Dry run: step {} was not executed. {} \
+             Continue planning as if it succeeded.",
+            self.plan.steps.len() + 1,
+            summary
+        );
+        self.plan.steps.push(PlannedStep {
+            call: call.clone(),
+            summary,
+            approved: false,
+        });
+        McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                data: serde_json::Value::String(note),
+                hint: None,
+            }],
+            is_error: false,
+        }
+    }
+
+    pub fn plan(&self) -> &DryRunPlan {
+        &self.plan
+    }
+
+    /// Finish the dry run, returning the plan to review
+    pub fn into_plan(self) -> DryRunPlan {
+        self.plan
+    }
+}
+
+fn step_summary(call: &ToolCall) -> String {
+    let mut arguments = call.arguments.to_string();
+    if arguments.len() > MAX_SUMMARY_ARGUMENTS {
+        let mut end = MAX_SUMMARY_ARGUMENTS;
+        while !arguments.is_char_boundary(end) {
+            end -= 1;
+        }
+        arguments.truncate(end);
+        arguments.push('…');
+    }
+    format!("It would call '{}' with {}.", call.name, arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, effect: ToolEffect) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            parameters: serde_json::json!({}),
+            effect,
+        }
+    }
+
+    fn call(name: &str) -> ToolCall {
+        ToolCall {
+            id: name.to_string(),
+            name: name.to_string(),
+            arguments: serde_json::json!({ "path": "src/lib.rs" }),
+        }
+    }
+
+    fn text_result(text: &str, is_error: bool) -> McpToolResult {
+        McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                data: serde_json::Value::String(text.to_string()),
+                hint: None,
+            }],
+            is_error,
+        }
+    }
+
+    #[test]
+    fn test_plans_mutating_calls_and_executes_approved_steps() {
+        let mut session = DryRunSession::new(&[
+            tool("fs__read", ToolEffect::ReadOnly),
+            tool("fs__write", ToolEffect::Mutating),
+        ]);
+        let mut ran = Vec::new();
+        for name in ["fs__read", "fs__write", "git__commit", "fs__write"] {
+            let result = session.call(&call(name), |call| {
+                ran.push(call.name.clone());
+                text_result("ok", false)
+            });
+            assert!(!result.is_error);
+        }
+        assert_eq!(ran, vec!["fs__read"]);
+
+        let mut plan = session.into_plan();
+        assert_eq!(plan.steps.len(), 3);
+        assert!(plan.steps[0].summary.contains("'fs__write'"));
+
+        plan.approve_all();
+        plan.set_approved(1, false);
+        let executed =
+            plan.execute(|call| text_result("failed", call.name == "fs__write"));
+        // The first approved step fails, so the later one isn't run
+        assert_eq!(executed.len(), 1);
+        assert_eq!(executed[0].step.call.name, "fs__write");
+    }
+}
@@ -0,0 +1,910 @@
+//! Edit Review
+//!
+//! This module is the review surface for edits the agent proposes across
+//! several files. Each proposed file is diffed against its current content
+//! into hunks that can be accepted, rejected or replaced with the
+//! reviewer's own version. Accepted changes to existing files become a
+//! `WorkspaceEdit` for the editor to apply, so they land in open buffers
+//! with undo; created and deleted files are applied on disk. After applying,
+//! the changed files are re-validated with a syntax check and optionally a
+//! build.
+
+use anyhow::Result;
+use floem::{
+    View,
+    reactive::{RwSignal, SignalUpdate, SignalWith},
+    views::{Decorators, h_stack, label, v_stack},
+};
+use lsp_types::{Position, Range, TextEdit, Uri, WorkspaceEdit};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    CommandResolver, ProjectTask, WorkspaceSandbox, virtual_list_view, write_atomic,
+};
+
+const ROW_HEIGHT: f64 = 20.0;
+/// Lines of build output kept in a validation report
+const BUILD_OUTPUT_LINES: usize = 50;
+
+/// An edit proposed for one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposedEdit {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    /// `None` for a file the edit creates
+    pub original: Option<String>,
+    /// `None` for a file the edit deletes
+    pub proposed: Option<String>,
+}
+
+/// Reviewer's decision on a hunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HunkDecision {
+    Pending,
+    Accepted,
+    Rejected,
+    /// Accepted with the reviewer's replacement lines
+    Modified(Vec<String>),
+}
+
+/// A contiguous change in a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewHunk {
+    /// First replaced line of the original, 0-based
+    pub old_start: usize,
+    pub old_lines: Vec<String>,
+    /// First line of the change in the proposed file, 0-based
+    pub new_start: usize,
+    pub new_lines: Vec<String>,
+    pub decision: HunkDecision,
+}
+
+impl ReviewHunk {
+    /// Get the lines that replace the original ones with the current
+    /// decision; pending hunks are left out like rejected ones
+    pub fn result_lines(&self) -> &[String] {
+        match &self.decision {
+            HunkDecision::Accepted => &self.new_lines,
+            HunkDecision::Modified(lines) => lines,
+            HunkDecision::Pending | HunkDecision::Rejected => &self.old_lines,
+        }
+    }
+
+    fn is_applied(&self) -> bool {
+        matches!(
+            self.decision,
+            HunkDecision::Accepted | HunkDecision::Modified(_)
+        )
+    }
+}
+
+/// What an edit does to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileChangeKind {
+    Modified,
+    Created,
+    Deleted,
+}
+
+/// A file under review
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewFile {
+    pub path: PathBuf,
+    pub kind: FileChangeKind,
+    pub original: String,
+    pub hunks: Vec<ReviewHunk>,
+}
+
+impl ReviewFile {
+    /// Get the content of the file with the current decisions, or `None`
+    /// if the file won't exist
+    ///
+    /// The original's trailing newline is kept; created files keep the
+    /// proposed one.
+    pub fn result_text(&self) -> Option<String> {
+        match self.kind {
+            FileChangeKind::Deleted
+                if self.hunks.iter().all(ReviewHunk::is_applied) =>
+            {
+                return None;
+            }
+            FileChangeKind::Created
+                if !self.hunks.iter().any(ReviewHunk::is_applied) =>
+            {
+                return None;
+            }
+            _ => {}
+        }
+        let original: Vec<&str> = self.original.lines().collect();
+        let mut lines: Vec<&str> = Vec::new();
+        let mut next = 0;
+        for hunk in &self.hunks {
+            lines.extend(&original[next..hunk.old_start]);
+            lines.extend(hunk.result_lines().iter().map(String::as_str));
+            next = hunk.old_start + hunk.old_lines.len();
+        }
+        lines.extend(&original[next..]);
+
+        let mut text = lines.join("\n");
+        let trailing_newline = match self.kind {
+            FileChangeKind::Created => self
+                .hunks
+                .first()
+                .is_some_and(|hunk| !hunk.new_lines.is_empty()),
+            _ => self.original.ends_with('\n'),
+        };
+        if trailing_newline && !lines.is_empty() {
+            text.push('\n');
+        }
+        Some(text)
+    }
+
+    /// Get the text edits of the applied hunks, in original positions
+    pub fn text_edits(&self) -> Vec<TextEdit> {
+        let original: Vec<&str> = self.original.lines().collect();
+        let trailing_newline = self.original.ends_with('\n');
+        let end_of_last_line = || {
+            let last = original.len().saturating_sub(1);
+            let len = original
+                .last()
+                .map_or(0, |line| line.encode_utf16().count());
+            Position::new(last as u32, len as u32)
+        };
+
+        let mut edits = Vec::new();
+        for hunk in self.hunks.iter().filter(|hunk| hunk.is_applied()) {
+            let start = hunk.old_start;
+            let end = start + hunk.old_lines.len();
+            let lines = hunk.result_lines();
+            let edit = if end < original.len()
+                || trailing_newline
+                || original.is_empty()
+            {
+                let mut text: String =
+                    lines.iter().map(|line| format!("{line}\n")).collect();
+                if original.is_empty() && !trailing_newline {
+                    text.pop();
+                }
+                TextEdit::new(
+                    Range::new(
+                        Position::new(start as u32, 0),
+                        Position::new(end as u32, 0),
+                    ),
+                    text,
+                )
+            } else if start < original.len() {
+                // The change reaches the last line, which has no newline
+                TextEdit::new(
+                    Range::new(Position::new(start as u32, 0), end_of_last_line()),
+                    lines.join("\n"),
+                )
+            } else {
+                // Lines added after a last line without a newline
+                TextEdit::new(
+                    Range::new(end_of_last_line(), end_of_last_line()),
+                    format!("\n{}", lines.join("\n")),
+                )
+            };
+            edits.push(edit);
+        }
+        edits
+    }
+}
+
+/// Counts of a review
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewSummary {
+    pub files: usize,
+    pub hunks: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub pending: usize,
+}
+
+/// Proposed multi-file edit under review
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditReview {
+    pub files: Vec<ReviewFile>,
+}
+
+impl EditReview {
+    /// Diff the proposed edits into hunks; unchanged files are left out
+    pub fn new(edits: Vec<ProposedEdit>) -> Self {
+        let files = edits
+            .into_iter()
+            .filter_map(|edit| {
+                let kind = match (&edit.original, &edit.proposed) {
+                    (Some(_), Some(_)) => FileChangeKind::Modified,
+                    (None, Some(_)) => FileChangeKind::Created,
+                    (Some(_), None) => FileChangeKind::Deleted,
+                    (None, None) => return None,
+                };
+                let original = edit.original.unwrap_or_default();
+                let proposed = edit.proposed.unwrap_or_default();
+                let old: Vec<&str> = original.lines().collect();
+                let new: Vec<&str> = proposed.lines().collect();
+                let mut hunks: Vec<ReviewHunk> = diff_lines(&old, &new)
+                    .into_iter()
+                    .map(|(old_range, new_range)| ReviewHunk {
+                        old_start: old_range.start,
+                        old_lines: to_strings(&old[old_range]),
+                        new_start: new_range.start,
+                        new_lines: to_strings(&new[new_range]),
+                        decision: HunkDecision::Pending,
+                    })
+                    .collect();
+                // Creating or deleting an empty file is still a change
+                if hunks.is_empty() && kind != FileChangeKind::Modified {
+                    hunks.push(ReviewHunk {
+                        old_start: 0,
+                        old_lines: Vec::new(),
+                        new_start: 0,
+                        new_lines: Vec::new(),
+                        decision: HunkDecision::Pending,
+                    });
+                }
+                (!hunks.is_empty()).then(|| ReviewFile {
+                    path: edit.path,
+                    kind,
+                    original,
+                    hunks,
+                })
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// Decide on one hunk
+    pub fn set_decision(
+        &mut self,
+        file: usize,
+        hunk: usize,
+        decision: HunkDecision,
+    ) {
+        if let Some(hunk) = self
+            .files
+            .get_mut(file)
+            .and_then(|file| file.hunks.get_mut(hunk))
+        {
+            hunk.decision = decision;
+        }
+    }
+
+    /// Decide on every hunk of a file
+    pub fn set_file_decision(&mut self, file: usize, decision: HunkDecision) {
+        if let Some(file) = self.files.get_mut(file) {
+            for hunk in &mut file.hunks {
+                hunk.decision = decision.clone();
+            }
+        }
+    }
+
+    /// Decide on every hunk
+    pub fn set_all(&mut self, decision: HunkDecision) {
+        for file in 0..self.files.len() {
+            self.set_file_decision(file, decision.clone());
+        }
+    }
+
+    pub fn summary(&self) -> ReviewSummary {
+        let mut summary = ReviewSummary {
+            files: self.files.len(),
+            ..Default::default()
+        };
+        for hunk in self.files.iter().flat_map(|file| &file.hunks) {
+            summary.hunks += 1;
+            match hunk.decision {
+                HunkDecision::Pending => summary.pending += 1,
+                HunkDecision::Rejected => summary.rejected += 1,
+                HunkDecision::Accepted | HunkDecision::Modified(_) => {
+                    summary.accepted += 1
+                }
+            }
+        }
+        summary
+    }
+
+    /// Get the accepted changes to existing files as a workspace edit
+    pub fn workspace_edit(&self, workspace_root: &Path) -> WorkspaceEdit {
+        let mut changes: HashMap<Uri, Vec<TextEdit>> = HashMap::new();
+        for file in &self.files {
+            if file.kind != FileChangeKind::Modified {
+                continue;
+            }
+            let edits = file.text_edits();
+            if edits.is_empty() {
+                continue;
+            }
+            if let Some(uri) = file_uri(&workspace_root.join(&file.path)) {
+                changes.insert(uri, edits);
+            }
+        }
+        WorkspaceEdit::new(changes)
+    }
+
+    /// Create and delete accepted files on disk, returning the paths
+    ///
+    /// Changes to existing files go through [`Self::workspace_edit`].
+    pub fn apply_file_operations(
+        &self,
+        sandbox: &WorkspaceSandbox,
+    ) -> Result<Vec<PathBuf>> {
+        self.apply_on_disk(sandbox, |kind| kind != FileChangeKind::Modified)
+    }
+
+    /// Write every accepted change to disk, returning the changed paths,
+    /// e.g. when no editor is open on the workspace
+    pub fn apply_to_disk(&self, sandbox: &WorkspaceSandbox) -> Result<Vec<PathBuf>> {
+        self.apply_on_disk(sandbox, |_| true)
+    }
+
+    fn apply_on_disk(
+        &self,
+        sandbox: &WorkspaceSandbox,
+        include: impl Fn(FileChangeKind) -> bool,
+    ) -> Result<Vec<PathBuf>> {
+        // Check every path before touching anything
+        let mut changes = Vec::new();
+        for file in self.files.iter().filter(|file| include(file.kind)) {
+            if !file.hunks.iter().any(ReviewHunk::is_applied) {
+                continue;
+            }
+            changes.push((sandbox.check_access(&file.path)?, file.result_text()));
+        }
+        for (path, text) in &changes {
+            match text {
+                Some(text) => write_atomic(path, text.as_bytes())?,
+                None => std::fs::remove_file(path)?,
+            }
+        }
+        Ok(changes.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+/// Problem found in a changed file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyntaxProblem {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Result of the build run after applying
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildOutcome {
+    pub success: bool,
+    pub timed_out: bool,
+    /// Last lines of the combined output
+    pub output: String,
+}
+
+/// Result of re-validating applied edits
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub syntax_problems: Vec<SyntaxProblem>,
+    pub build: Option<BuildOutcome>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.syntax_problems.is_empty()
+            && self.build.as_ref().is_none_or(|build| build.success)
+    }
+}
+
+/// Re-validate files after an edit: parse each one that still exists and,
+/// when a build task is given, run it with a timeout
+pub fn validate_applied(
+    paths: &[PathBuf],
+    build: Option<(&ProjectTask, Duration)>,
+    workspace_env: &HashMap<String, String>,
+) -> Result<ValidationReport> {
+    let mut report = ValidationReport::default();
+    for path in paths {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        if let Err(message) = check_syntax(path, &text) {
+            report.syntax_problems.push(SyntaxProblem {
+                path: path.clone(),
+                message,
+            });
+        }
+    }
+    if let Some((task, timeout)) = build {
+        report.build = Some(run_build(task, timeout, workspace_env)?);
+    }
+    Ok(report)
+}
+
+/// Check that a file parses
+///
+/// JSON and TOML are parsed fully; for C-like languages, brackets must
+/// balance outside of strings and comments, which catches the truncated
+/// and half-applied edits that break a file.
+pub fn check_syntax(path: &Path, text: &str) -> std::result::Result<(), String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match extension {
+        "json" => serde_json::from_str::<serde_json::Value>(text)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        "toml" => toml::from_str::<toml::Value>(text)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        "rs" | "js" | "jsx" | "ts" | "tsx" | "java" | "kt" | "go" | "c" | "h"
+        | "cc" | "cpp" | "hpp" | "cs" | "swift" | "dart" | "scala" => {
+            check_brackets(text)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_brackets(text: &str) -> std::result::Result<(), String> {
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' | '`' => {
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        line += 1;
+                    }
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' {
+                        escaped = true;
+                    } else if next == c {
+                        break;
+                    }
+                }
+            }
+            // Character literals such as '{' and '\n'; a lone quote, as in
+            // Rust lifetimes, is skipped
+            '\'' => {
+                let mut lookahead = chars.clone();
+                let literal_len = match lookahead.next() {
+                    Some('\\') => lookahead.nth(1).map(|_| 3),
+                    Some(_) => Some(2),
+                    None => None,
+                };
+                if let Some(len) = literal_len {
+                    if lookahead.next() == Some('\'') {
+                        for _ in 0..len {
+                            chars.next();
+                        }
+                    }
+                }
+            }
+            '(' | '[' | '{' => stack.push((c, line)),
+            ')' | ']' | '}' => {
+                let expected = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match stack.pop() {
+                    Some((open, _)) if open == expected => {}
+                    Some((open, open_line)) => {
+                        return Err(format!(
+                            "Line {}: '{}' closes '{}' opened on line {}",
+                            line, c, open, open_line
+                        ));
+                    }
+                    None => return Err(format!("Line {}: unmatched '{}'", line, c)),
+                }
+            }
+            _ => {}
+        }
+    }
+    match stack.pop() {
+        Some((open, open_line)) => {
+            Err(format!("Line {}: '{}' is never closed", open_line, open))
+        }
+        None => Ok(()),
+    }
+}
+
+fn run_build(
+    task: &ProjectTask,
+    timeout: Duration,
+    workspace_env: &HashMap<String, String>,
+) -> Result<BuildOutcome> {
+    let mut output = tempfile::tempfile()?;
+    let mut command =
+        CommandResolver::from_env().task_command(task, workspace_env)?;
+    command
+        .stdin(Stdio::null())
+        .stdout(output.try_clone()?)
+        .stderr(output.try_clone()?);
+    let mut child = command.spawn()?;
+    let deadline = Instant::now() + timeout;
+    let (success, timed_out) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (status.success(), false);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break (false, true);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    let mut text = String::new();
+    output.rewind()?;
+    output.read_to_string(&mut text)?;
+    let lines: Vec<&str> = text.lines().collect();
+    let tail = lines[lines.len().saturating_sub(BUILD_OUTPUT_LINES)..].join("\n");
+    Ok(BuildOutcome {
+        success,
+        timed_out,
+        output: tail,
+    })
+}
+
+/// Find the changed line ranges between two versions with Myers' diff,
+/// as pairs of old and new ranges
+fn diff_lines(
+    old: &[&str],
+    new: &[&str],
+) -> Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    if n == 0 && m == 0 {
+        return Vec::new();
+    }
+
+    // Furthest x reached on each diagonal k = x - y, for every edit count
+    let max = n + m;
+    let index = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back through the trace collecting matched lines
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)])
+        {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = previous_x;
+            y = previous_y;
+        }
+    }
+    matches.reverse();
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in matches.into_iter().chain([(a.len(), b.len())]) {
+        if i < x || j < y {
+            changes.push((prefix + i..prefix + x, prefix + j..prefix + y));
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+    changes
+}
+
+fn to_strings(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+fn file_uri(path: &Path) -> Option<Uri> {
+    let url = url::Url::from_file_path(path).ok()?;
+    Uri::from_str(url.as_str()).ok()
+}
+
+#[derive(Clone)]
+enum ReviewRow {
+    File {
+        index: usize,
+        label: String,
+    },
+    Hunk {
+        file: usize,
+        hunk: usize,
+        label: String,
+    },
+    Line(String),
+}
+
+/// Create the review view
+///
+/// `on_modify` is called with the file and hunk index when the reviewer
+/// wants to edit a hunk, e.g. to open it in an editor and then record the
+/// result with [`HunkDecision::Modified`]. `on_apply` is called with the
+/// review when the reviewer applies it.
+pub fn edit_review_view(
+    review: RwSignal<EditReview>,
+    on_modify: impl Fn(usize, usize) + Clone + 'static,
+    on_apply: impl Fn(&EditReview) + 'static,
+) -> Box<dyn View> {
+    let summary = label(move || {
+        let summary = review.with(EditReview::summary);
+        format!(
+            "{} files, {} hunks: {} accepted, {} rejected, {} pending",
+            summary.files,
+            summary.hunks,
+            summary.accepted,
+            summary.rejected,
+            summary.pending
+        )
+    });
+    let toolbar = h_stack((
+        summary,
+        label(|| "Accept all".to_string()).on_click_stop(move |_| {
+            review.update(|review| review.set_all(HunkDecision::Accepted))
+        }),
+        label(|| "Reject all".to_string()).on_click_stop(move |_| {
+            review.update(|review| review.set_all(HunkDecision::Rejected))
+        }),
+        label(|| "Apply".to_string())
+            .on_click_stop(move |_| review.with(|review| on_apply(review))),
+    ))
+    .style(|s| s.gap(10.0));
+
+    let rows = virtual_list_view(
+        move || review.with(review_rows),
+        |(index, _): &(usize, ReviewRow)| *index,
+        move |(_, row)| -> Box<dyn View> {
+            match row {
+                ReviewRow::File { index, label: text } => Box::new(
+                    h_stack((
+                        label(move || text.clone()).style(|s| s.font_bold()),
+                        label(|| "Accept file".to_string()).on_click_stop(
+                            move |_| {
+                                review.update(|review| {
+                                    review.set_file_decision(
+                                        index,
+                                        HunkDecision::Accepted,
+                                    )
+                                })
+                            },
+                        ),
+                        label(|| "Reject file".to_string()).on_click_stop(
+                            move |_| {
+                                review.update(|review| {
+                                    review.set_file_decision(
+                                        index,
+                                        HunkDecision::Rejected,
+                                    )
+                                })
+                            },
+                        ),
+                    ))
+                    .style(|s| s.gap(10.0)),
+                ),
+                ReviewRow::Hunk {
+                    file,
+                    hunk,
+                    label: text,
+                } => {
+                    let decide = move |decision: HunkDecision| {
+                        move |_: &floem::event::Event| {
+                            let decision = decision.clone();
+                            review.update(|review| {
+                                review.set_decision(file, hunk, decision)
+                            })
+                        }
+                    };
+                    let on_modify = on_modify.clone();
+                    Box::new(
+                        h_stack((
+                            label(move || text.clone()),
+                            label(|| "Accept".to_string())
+                                .on_click_stop(decide(HunkDecision::Accepted)),
+                            label(|| "Reject".to_string())
+                                .on_click_stop(decide(HunkDecision::Rejected)),
+                            label(|| "Modify".to_string())
+                                .on_click_stop(move |_| on_modify(file, hunk)),
+                        ))
+                        .style(|s| s.gap(10.0)),
+                    )
+                }
+                ReviewRow::Line(text) => Box::new(
+                    label(move || text.clone()).style(|s| s.padding_left(20.0)),
+                ),
+            }
+        },
+        ROW_HEIGHT,
+    );
+    Box::new(v_stack((toolbar, rows)).style(|s| s.size_full()))
+}
+
+fn review_rows(review: &EditReview) -> im::Vector<ReviewRow> {
+    let mut rows = im::Vector::new();
+    for (index, file) in review.files.iter().enumerate() {
+        let kind = match file.kind {
+            FileChangeKind::Modified => "",
+            FileChangeKind::Created => " (new)",
+            FileChangeKind::Deleted => " (deleted)",
+        };
+        rows.push_back(ReviewRow::File {
+            index,
+            label: format!("{}{}", file.path.display(), kind),
+        });
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            let decision = match hunk.decision {
+                HunkDecision::Pending => "pending",
+                HunkDecision::Accepted => "accepted",
+                HunkDecision::Rejected => "rejected",
+                HunkDecision::Modified(_) => "modified",
+            };
+            rows.push_back(ReviewRow::Hunk {
+                file: index,
+                hunk: hunk_index,
+                label: format!(
+                    "@@ -{},{} +{},{} @@ {}",
+                    hunk.old_start + 1,
+                    hunk.old_lines.len(),
+                    hunk.new_start + 1,
+                    hunk.new_lines.len(),
+                    decision
+                ),
+            });
+            for line in &hunk.old_lines {
+                rows.push_back(ReviewRow::Line(format!("-{line}")));
+            }
+            let new_lines = match &hunk.decision {
+                HunkDecision::Modified(lines) => lines,
+                _ => &hunk.new_lines,
+            };
+            for line in new_lines {
+                rows.push_back(ReviewRow::Line(format!("+{line}")));
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_applies_reviewed_hunks_and_revalidates() {
+        let root = tempfile::tempdir().unwrap();
+        let original = "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n";
+        std::fs::write(root.path().join("lib.rs"), original).unwrap();
+        std::fs::write(root.path().join("old.rs"), "fn old() {}\n").unwrap();
+
+        let mut review = EditReview::new(vec![
+            ProposedEdit {
+                path: PathBuf::from("lib.rs"),
+                original: Some(original.to_string()),
+                proposed: Some(
+                    "fn a() {}\nfn b() -> u8 { 1 }\nfn c() {}\nfn d() {\n"
+                        .to_string(),
+                ),
+            },
+            ProposedEdit {
+                path: PathBuf::from("new.rs"),
+                original: None,
+                proposed: Some("fn new() {}\n".to_string()),
+            },
+            ProposedEdit {
+                path: PathBuf::from("old.rs"),
+                original: Some("fn old() {}\n".to_string()),
+                proposed: None,
+            },
+            ProposedEdit {
+                path: PathBuf::from("same.rs"),
+                original: Some("x\n".to_string()),
+                proposed: Some("x\n".to_string()),
+            },
+        ]);
+        assert_eq!(review.files.len(), 3);
+        assert_eq!(review.files[0].hunks.len(), 2);
+
+        review.set_decision(0, 0, HunkDecision::Accepted);
+        review.set_decision(
+            0,
+            1,
+            HunkDecision::Modified(vec!["fn d() { todo!() ".to_string()]),
+        );
+        review.set_file_decision(1, HunkDecision::Accepted);
+        review.set_file_decision(2, HunkDecision::Rejected);
+        assert_eq!(review.summary().pending, 0);
+
+        let edit = review.workspace_edit(root.path());
+        let edits = edit.changes.unwrap().into_values().next().unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].range.start, Position::new(1, 0));
+        assert_eq!(edits[0].new_text, "fn b() -> u8 { 1 }\n");
+
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = WorkspaceSandbox::new(Arc::new(ignore));
+        let paths = review.apply_to_disk(&sandbox).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(root.path().join("lib.rs")).unwrap(),
+            "fn a() {}\nfn b() -> u8 { 1 }\nfn c() {}\nfn d() { todo!() \n"
+        );
+        assert!(root.path().join("new.rs").exists());
+        assert!(root.path().join("old.rs").exists());
+
+        let report = validate_applied(&paths, None, &HashMap::new()).unwrap();
+        assert_eq!(report.syntax_problems.len(), 1);
+        assert!(report.syntax_problems[0].message.contains("never closed"));
+        assert!(
+            check_syntax(Path::new("a.rs"), "fn f<'a>(c: char) { '{' == c; }")
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,540 @@
+//! Embedding Provider API
+//!
+//! This module defines the interface the context indexer uses to turn text
+//! chunks into vectors, with an implementation for OpenAI-compatible
+//! embedding APIs and, behind the `local-embeddings` feature, a local ONNX
+//! sentence-transformer model. Which one is used is decided by the
+//! `NetworkPolicy`.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin_api::NetworkPolicy;
+
+/// A single embedding vector
+pub type Embedding = Vec<f32>;
+
+/// Trait that embedding backends must implement
+pub trait EmbeddingProvider: Send + Sync {
+    /// Get provider information
+    fn provider_info(&self) -> EmbeddingProviderInfo;
+
+    /// Embed a batch of inputs, returning one vector per input in order
+    fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>>;
+
+    /// Embed a single input
+    fn embed(&self, input: &str) -> Result<Embedding> {
+        self.embed_batch(&[input.to_string()])?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding provider returned no vector"))
+    }
+}
+
+/// Information about an embedding provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingProviderInfo {
+    pub id: String,
+    pub model: String,
+    /// Dimensions of the vectors the model produces
+    pub dimensions: usize,
+    /// Whether vectors may be truncated to fewer dimensions (Matryoshka
+    /// style models)
+    pub supports_truncation: bool,
+    pub max_batch_size: usize,
+    pub requires_network: bool,
+}
+
+/// Pick the number of dimensions to store in the index
+///
+/// `requested` is the dimension of an existing index, if any. The provider
+/// must either produce it natively or be able to truncate to it.
+pub fn negotiate_dimensions(
+    info: &EmbeddingProviderInfo,
+    requested: Option<usize>,
+) -> Result<usize> {
+    match requested {
+        None => Ok(info.dimensions),
+        Some(requested) if requested == info.dimensions => Ok(requested),
+        Some(requested)
+            if requested > 0
+                && requested < info.dimensions
+                && info.supports_truncation =>
+        {
+            Ok(requested)
+        }
+        Some(requested) => Err(anyhow::anyhow!(
+            "Embedding model '{}' produces {} dimensions and cannot provide {}",
+            info.model,
+            info.dimensions,
+            requested
+        )),
+    }
+}
+
+/// Embed any number of inputs, splitting them into provider-sized batches
+/// and reducing every vector to the negotiated dimensions
+pub fn embed_all(
+    provider: &dyn EmbeddingProvider,
+    inputs: &[String],
+    dimensions: usize,
+) -> Result<Vec<Embedding>> {
+    let info = provider.provider_info();
+    let mut embeddings = Vec::with_capacity(inputs.len());
+
+    for batch in inputs.chunks(info.max_batch_size.max(1)) {
+        let vectors = provider.embed_batch(batch)?;
+        if vectors.len() != batch.len() {
+            return Err(anyhow::anyhow!(
+                "Embedding provider '{}' returned {} vectors for {} inputs",
+                info.id,
+                vectors.len(),
+                batch.len()
+            ));
+        }
+
+        for vector in vectors {
+            embeddings.push(fit_dimensions(vector, dimensions)?);
+        }
+    }
+
+    Ok(embeddings)
+}
+
+fn fit_dimensions(mut vector: Embedding, dimensions: usize) -> Result<Embedding> {
+    if vector.len() < dimensions {
+        return Err(anyhow::anyhow!(
+            "Expected an embedding with {} dimensions, got {}",
+            dimensions,
+            vector.len()
+        ));
+    }
+    if vector.len() > dimensions {
+        vector.truncate(dimensions);
+        normalize(&mut vector);
+    }
+    Ok(vector)
+}
+
+/// Scale a vector to unit length
+pub fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// Configuration for an OpenAI-compatible embeddings endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiEmbeddingConfig {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub dimensions: usize,
+    pub supports_truncation: bool,
+    pub max_batch_size: usize,
+    pub timeout_seconds: u64,
+}
+
+impl Default for ApiEmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://api.openai.com/v1/embeddings".to_string(),
+            api_key: None,
+            model: "text-embedding-3-small".to_string(),
+            dimensions: 1536,
+            supports_truncation: true,
+            max_batch_size: 256,
+            timeout_seconds: 30,
+        }
+    }
+}
+
+/// Embedding provider backed by a remote API
+pub struct ApiEmbeddingProvider {
+    config: ApiEmbeddingConfig,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Deserialize)]
+struct ApiEmbeddingResponse {
+    data: Vec<ApiEmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct ApiEmbeddingData {
+    index: usize,
+    embedding: Embedding,
+}
+
+impl ApiEmbeddingProvider {
+    /// Create a new API embedding provider
+    pub fn new(config: ApiEmbeddingConfig) -> Result<Self> {
+        let client = catalyst_proxy::http::client_builder()?
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()?;
+        Ok(Self { config, client })
+    }
+}
+
+impl EmbeddingProvider for ApiEmbeddingProvider {
+    fn provider_info(&self) -> EmbeddingProviderInfo {
+        EmbeddingProviderInfo {
+            id: "api".to_string(),
+            model: self.config.model.clone(),
+            dimensions: self.config.dimensions,
+            supports_truncation: self.config.supports_truncation,
+            max_batch_size: self.config.max_batch_size,
+            requires_network: true,
+        }
+    }
+
+    fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>> {
+        let mut request =
+            self.client
+                .post(&self.config.endpoint)
+                .json(&serde_json::json!({
+                    "model": self.config.model,
+                    "input": inputs,
+                }));
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send()?.error_for_status()?;
+        let mut response: ApiEmbeddingResponse = response.json()?;
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+}
+
+/// A sentence-transformer model that can run locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalEmbeddingModel {
+    pub name: String,
+    pub model_url: String,
+    pub tokenizer_url: String,
+    pub dimensions: usize,
+    pub max_sequence_length: usize,
+}
+
+impl Default for LocalEmbeddingModel {
+    fn default() -> Self {
+        let base = "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main";
+        Self {
+            name: "all-MiniLM-L6-v2".to_string(),
+            model_url: format!("{base}/onnx/model.onnx"),
+            tokenizer_url: format!("{base}/tokenizer.json"),
+            dimensions: 384,
+            max_sequence_length: 256,
+        }
+    }
+}
+
+impl LocalEmbeddingModel {
+    const MODEL_FILE: &'static str = "model.onnx";
+    const TOKENIZER_FILE: &'static str = "tokenizer.json";
+
+    /// Directory holding a model shipped next to the executable
+    pub fn bundled_directory(&self) -> Option<PathBuf> {
+        let exe = std::env::current_exe().ok()?;
+        Some(exe.parent()?.join("models").join(&self.name))
+    }
+
+    /// Directory a downloaded model is cached in
+    pub fn cache_directory(&self) -> Option<PathBuf> {
+        Directory::cache_directory().map(|dir| dir.join("models").join(&self.name))
+    }
+
+    /// Find the model on disk, downloading it into the cache directory if
+    /// needed and allowed by the network policy
+    pub fn locate_or_download(&self, policy: NetworkPolicy) -> Result<PathBuf> {
+        for dir in [self.bundled_directory(), self.cache_directory()]
+            .into_iter()
+            .flatten()
+        {
+            if Self::is_complete(&dir) {
+                return Ok(dir);
+            }
+        }
+
+        if !policy.allows_downloads() {
+            return Err(anyhow::anyhow!(
+                "Local embedding model '{}' is not installed and downloads are disabled",
+                self.name
+            ));
+        }
+
+        let dir = self
+            .cache_directory()
+            .ok_or_else(|| anyhow::anyhow!("Cache directory is unavailable"))?;
+        std::fs::create_dir_all(&dir)?;
+        tracing::info!("Downloading local embedding model '{}'", self.name);
+        download(&self.model_url, &dir.join(Self::MODEL_FILE))?;
+        download(&self.tokenizer_url, &dir.join(Self::TOKENIZER_FILE))?;
+        Ok(dir)
+    }
+
+    fn is_complete(dir: &Path) -> bool {
+        dir.join(Self::MODEL_FILE).is_file()
+            && dir.join(Self::TOKENIZER_FILE).is_file()
+    }
+}
+
+/// Download a file, writing to a temporary file first so an interrupted
+/// download never leaves a truncated model behind
+fn download(url: &str, destination: &Path) -> Result<()> {
+    let mut response = catalyst_proxy::http::client_builder()?
+        .timeout(None)
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?;
+    let dir = destination
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid download destination"))?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    response.copy_to(&mut file)?;
+    file.persist(destination)?;
+    Ok(())
+}
+
+#[cfg(feature = "local-embeddings")]
+pub use local::LocalOnnxEmbeddingProvider;
+
+#[cfg(feature = "local-embeddings")]
+mod local {
+    use anyhow::Result;
+    use ort::{session::Session, value::Tensor};
+    use parking_lot::Mutex;
+    use std::path::Path;
+    use tokenizers::Tokenizer;
+
+    use super::{
+        Embedding, EmbeddingProvider, EmbeddingProviderInfo, LocalEmbeddingModel,
+    };
+
+    /// Embedding provider running a sentence-transformer ONNX model in
+    /// process with mean pooling
+    pub struct LocalOnnxEmbeddingProvider {
+        model: LocalEmbeddingModel,
+        session: Mutex<Session>,
+        tokenizer: Tokenizer,
+        uses_token_type_ids: bool,
+    }
+
+    impl LocalOnnxEmbeddingProvider {
+        /// Load the model from a directory containing `model.onnx` and
+        /// `tokenizer.json`
+        pub fn load(model: LocalEmbeddingModel, dir: &Path) -> Result<Self> {
+            let session = Session::builder()?
+                .commit_from_file(dir.join(LocalEmbeddingModel::MODEL_FILE))?;
+            let tokenizer =
+                Tokenizer::from_file(dir.join(LocalEmbeddingModel::TOKENIZER_FILE))
+                    .map_err(|err| {
+                        anyhow::anyhow!("Failed to load tokenizer: {err}")
+                    })?;
+            let uses_token_type_ids = session
+                .inputs
+                .iter()
+                .any(|input| input.name == "token_type_ids");
+
+            Ok(Self {
+                model,
+                session: Mutex::new(session),
+                tokenizer,
+                uses_token_type_ids,
+            })
+        }
+    }
+
+    impl EmbeddingProvider for LocalOnnxEmbeddingProvider {
+        fn provider_info(&self) -> EmbeddingProviderInfo {
+            EmbeddingProviderInfo {
+                id: "local-onnx".to_string(),
+                model: self.model.name.clone(),
+                dimensions: self.model.dimensions,
+                supports_truncation: false,
+                max_batch_size: 32,
+                requires_network: false,
+            }
+        }
+
+        fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>> {
+            if inputs.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let encodings = self
+                .tokenizer
+                .encode_batch(inputs.to_vec(), true)
+                .map_err(|err| anyhow::anyhow!("Failed to tokenize input: {err}"))?;
+            let max_length = self.model.max_sequence_length;
+            let sequence_length = encodings
+                .iter()
+                .map(|encoding| encoding.get_ids().len().min(max_length))
+                .max()
+                .unwrap_or(0)
+                .max(1);
+
+            // Pad every sequence to the same length
+            let batch_size = inputs.len();
+            let mut ids = vec![0i64; batch_size * sequence_length];
+            let mut mask = vec![0i64; batch_size * sequence_length];
+            let mut type_ids = vec![0i64; batch_size * sequence_length];
+            for (row, encoding) in encodings.iter().enumerate() {
+                let length = encoding.get_ids().len().min(max_length);
+                for column in 0..length {
+                    let index = row * sequence_length + column;
+                    ids[index] = encoding.get_ids()[column] as i64;
+                    mask[index] = encoding.get_attention_mask()[column] as i64;
+                    type_ids[index] = encoding.get_type_ids()[column] as i64;
+                }
+            }
+
+            let shape = [batch_size, sequence_length];
+            let mut session = self.session.lock();
+            let outputs = if self.uses_token_type_ids {
+                session.run(ort::inputs![
+                    "input_ids" => Tensor::from_array((shape, ids))?,
+                    "attention_mask" => Tensor::from_array((shape, mask.clone()))?,
+                    "token_type_ids" => Tensor::from_array((shape, type_ids))?,
+                ])?
+            } else {
+                session.run(ort::inputs![
+                    "input_ids" => Tensor::from_array((shape, ids))?,
+                    "attention_mask" => Tensor::from_array((shape, mask.clone()))?,
+                ])?
+            };
+
+            // Mean pooling of the token embeddings over the attention mask
+            let (output_shape, hidden) = outputs[0].try_extract_tensor::<f32>()?;
+            let hidden_size = output_shape.last().copied().ok_or_else(|| {
+                anyhow::anyhow!("Model returned an empty output shape")
+            })? as usize;
+
+            let mut embeddings = Vec::with_capacity(batch_size);
+            for row in 0..batch_size {
+                let mut embedding = vec![0f32; hidden_size];
+                let mut count = 0f32;
+                for column in 0..sequence_length {
+                    if mask[row * sequence_length + column] == 0 {
+                        continue;
+                    }
+                    let offset = (row * sequence_length + column) * hidden_size;
+                    for (value, hidden) in embedding
+                        .iter_mut()
+                        .zip(&hidden[offset..offset + hidden_size])
+                    {
+                        *value += hidden;
+                    }
+                    count += 1.0;
+                }
+                if count > 0.0 {
+                    embedding.iter_mut().for_each(|value| *value /= count);
+                }
+                super::normalize(&mut embedding);
+                embeddings.push(embedding);
+            }
+
+            Ok(embeddings)
+        }
+    }
+}
+
+/// Choose the embedding provider allowed by the network policy
+pub fn select_embedding_provider(
+    policy: NetworkPolicy,
+    remote: Option<Arc<dyn EmbeddingProvider>>,
+    local: Option<Arc<dyn EmbeddingProvider>>,
+) -> Result<Arc<dyn EmbeddingProvider>> {
+    let remote = remote.filter(|_| policy.allows_remote_processing());
+    let selected = if policy.prefers_local() {
+        local.or(remote)
+    } else {
+        remote.or(local)
+    };
+
+    selected.ok_or_else(|| {
+        anyhow::anyhow!(
+            "No embedding provider is available under the {:?} network policy",
+            policy
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedProvider {
+        dimensions: usize,
+    }
+
+    impl EmbeddingProvider for FixedProvider {
+        fn provider_info(&self) -> EmbeddingProviderInfo {
+            EmbeddingProviderInfo {
+                id: "fixed".to_string(),
+                model: "fixed".to_string(),
+                dimensions: self.dimensions,
+                supports_truncation: true,
+                max_batch_size: 2,
+                requires_network: false,
+            }
+        }
+
+        fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>> {
+            Ok(inputs.iter().map(|_| vec![1.0; self.dimensions]).collect())
+        }
+    }
+
+    #[test]
+    fn test_negotiate_dimensions() {
+        let info = FixedProvider { dimensions: 8 }.provider_info();
+        assert_eq!(negotiate_dimensions(&info, None).unwrap(), 8);
+        assert_eq!(negotiate_dimensions(&info, Some(4)).unwrap(), 4);
+        assert!(negotiate_dimensions(&info, Some(16)).is_err());
+    }
+
+    #[test]
+    fn test_embed_all_batches_and_truncates() {
+        let provider = FixedProvider { dimensions: 8 };
+        let inputs: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        let embeddings = embed_all(&provider, &inputs, 4).unwrap();
+
+        assert_eq!(embeddings.len(), 5);
+        let norm: f32 = embeddings[0].iter().map(|v| v * v).sum();
+        assert_eq!(embeddings[0].len(), 4);
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_select_embedding_provider() {
+        let remote: Arc<dyn EmbeddingProvider> =
+            Arc::new(FixedProvider { dimensions: 2 });
+        let local: Arc<dyn EmbeddingProvider> =
+            Arc::new(FixedProvider { dimensions: 3 });
+
+        let selected = select_embedding_provider(
+            NetworkPolicy::Online,
+            Some(remote.clone()),
+            Some(local.clone()),
+        )
+        .unwrap();
+        assert_eq!(selected.provider_info().dimensions, 2);
+
+        let selected = select_embedding_provider(
+            NetworkPolicy::LocalOnly,
+            Some(remote.clone()),
+            None,
+        );
+        assert!(selected.is_err());
+    }
+}
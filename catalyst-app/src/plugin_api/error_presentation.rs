@@ -0,0 +1,394 @@
+//! Error Presentation
+//!
+//! This module turns the errors that reach the UI into messages a user can
+//! act on. An error is classified into an [`ErrorKind`], either from a
+//! [`CatalystError`] somewhere in its chain or from the I/O and HTTP errors
+//! it wraps, and each kind has a localized title, an explanation and the
+//! actions that usually fix it, such as opening the settings or updating
+//! the credentials. The raw error is kept as the detail of the message.
+//!
+//! Messages are looked up in a [`MessageCatalog`]. The English messages are
+//! built in; a translation is a flat TOML file named after the locale in
+//! the `locales` folder of the config directory, e.g. `locales/de.toml`,
+//! and only needs the keys it translates.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+static GLOBAL_CATALOG: Lazy<MessageCatalog> = Lazy::new(|| {
+    let locale = system_locale();
+    match Directory::config_directory() {
+        Some(dir) => MessageCatalog::load(&dir.join("locales"), &locale)
+            .unwrap_or_else(|err| {
+                tracing::error!("Failed to load messages for {locale}: {err:#}");
+                MessageCatalog::default()
+            }),
+        None => MessageCatalog::default(),
+    }
+});
+
+const ENGLISH_MESSAGES: &[(&str, &str)] = &[
+    ("error.authentication.title", "Authentication failed"),
+    (
+        "error.authentication.message",
+        "The service rejected the credentials. Update them and try again.",
+    ),
+    ("error.network.title", "Connection failed"),
+    (
+        "error.network.message",
+        "The service could not be reached. Check your network connection and \
+         proxy settings.",
+    ),
+    ("error.timeout.title", "Request timed out"),
+    (
+        "error.timeout.message",
+        "The service took too long to answer. Try again later.",
+    ),
+    ("error.rate-limited.title", "Too many requests"),
+    (
+        "error.rate-limited.message",
+        "The service is limiting requests. Wait a moment and try again.",
+    ),
+    ("error.unavailable.title", "Service unavailable"),
+    (
+        "error.unavailable.message",
+        "The service reported an error on its side. Try again later.",
+    ),
+    ("error.configuration.title", "Invalid settings"),
+    (
+        "error.configuration.message",
+        "A setting has a value that cannot be used. Correct it in the settings.",
+    ),
+    ("error.not-found.title", "Not found"),
+    (
+        "error.not-found.message",
+        "A file or resource that was needed does not exist.",
+    ),
+    ("error.permission-denied.title", "Permission denied"),
+    (
+        "error.permission-denied.message",
+        "Catalyst is not allowed to access a file or resource it needs.",
+    ),
+    ("error.internal.title", "Something went wrong"),
+    (
+        "error.internal.message",
+        "An unexpected error occurred. The log file has more details.",
+    ),
+    ("action.open-settings", "Open Settings"),
+    ("action.reauthenticate", "Update Credentials"),
+    ("action.view-logs", "View Logs"),
+];
+
+/// Kind of failure, deciding the message and actions shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    Authentication,
+    Network,
+    Timeout,
+    RateLimited,
+    Unavailable,
+    Configuration,
+    NotFound,
+    PermissionDenied,
+    Internal,
+}
+
+impl ErrorKind {
+    /// Classify an error from the first cause that tells its kind
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain()
+            .find_map(|cause| {
+                if let Some(err) = cause.downcast_ref::<CatalystError>() {
+                    Some(err.kind)
+                } else if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+                    Self::of_io(err)
+                } else if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+                    Self::of_http(err)
+                } else if cause.is::<toml::de::Error>() {
+                    Some(Self::Configuration)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self::Internal)
+    }
+
+    fn of_io(err: &std::io::Error) -> Option<Self> {
+        use std::io::ErrorKind::*;
+        Some(match err.kind() {
+            NotFound => Self::NotFound,
+            PermissionDenied => Self::PermissionDenied,
+            TimedOut => Self::Timeout,
+            ConnectionRefused | ConnectionReset | ConnectionAborted
+            | NotConnected | AddrNotAvailable => Self::Network,
+            _ => return None,
+        })
+    }
+
+    fn of_http(err: &reqwest::Error) -> Option<Self> {
+        if err.is_timeout() {
+            return Some(Self::Timeout);
+        }
+        if err.is_connect() {
+            return Some(Self::Network);
+        }
+        Self::of_status(err.status()?.as_u16())
+    }
+
+    /// Classify the HTTP status of a failed response
+    pub fn of_status(status: u16) -> Option<Self> {
+        Some(match status {
+            401 | 403 => Self::Authentication,
+            404 => Self::NotFound,
+            429 => Self::RateLimited,
+            500..600 => Self::Unavailable,
+            _ => return None,
+        })
+    }
+
+    /// Get the name used in message keys
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Authentication => "authentication",
+            Self::Network => "network",
+            Self::Timeout => "timeout",
+            Self::RateLimited => "rate-limited",
+            Self::Unavailable => "unavailable",
+            Self::Configuration => "configuration",
+            Self::NotFound => "not-found",
+            Self::PermissionDenied => "permission-denied",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Get the actions offered for this kind of error
+    pub fn actions(&self) -> Vec<ErrorAction> {
+        match self {
+            Self::Authentication => {
+                vec![ErrorAction::Reauthenticate, ErrorAction::ViewLogs]
+            }
+            Self::Network | Self::Configuration => {
+                vec![ErrorAction::OpenSettings, ErrorAction::ViewLogs]
+            }
+            _ => vec![ErrorAction::ViewLogs],
+        }
+    }
+}
+
+/// Error of a known kind, to be wrapped in an [`anyhow::Error`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalystError {
+    pub kind: ErrorKind,
+    pub detail: String,
+}
+
+impl CatalystError {
+    pub fn new(kind: ErrorKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            detail: detail.into(),
+        }
+    }
+
+    /// Error for a response that failed with an HTTP status
+    pub fn from_status(status: u16, detail: impl Into<String>) -> Self {
+        Self::new(
+            ErrorKind::of_status(status).unwrap_or(ErrorKind::Internal),
+            detail,
+        )
+    }
+}
+
+impl fmt::Display for CatalystError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+impl std::error::Error for CatalystError {}
+
+/// Something the user can do about an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorAction {
+    OpenSettings,
+    Reauthenticate,
+    ViewLogs,
+}
+
+impl ErrorAction {
+    /// Get the key of the button label
+    pub fn message_key(&self) -> &'static str {
+        match self {
+            Self::OpenSettings => "action.open-settings",
+            Self::Reauthenticate => "action.reauthenticate",
+            Self::ViewLogs => "action.view-logs",
+        }
+    }
+}
+
+/// Localized messages, falling back to English for missing keys
+#[derive(Debug, Clone, Default)]
+pub struct MessageCatalog {
+    locale: Option<String>,
+    messages: HashMap<String, String>,
+}
+
+impl MessageCatalog {
+    /// Get the catalog of the system locale
+    pub fn global() -> &'static MessageCatalog {
+        &GLOBAL_CATALOG
+    }
+
+    /// Load the translation of `locale` from `dir`
+    ///
+    /// `de_DE.UTF-8` is looked up as `de-DE.toml`, then as `de.toml`. A
+    /// locale without a translation uses the English messages.
+    pub fn load(dir: &Path, locale: &str) -> Result<Self> {
+        let locale = locale
+            .split(['.', '@'])
+            .next()
+            .unwrap_or_default()
+            .replace('_', "-");
+        let language = locale.split('-').next().unwrap_or_default();
+        for name in [locale.as_str(), language] {
+            let path = dir.join(format!("{name}.toml"));
+            if !name.is_empty() && path.is_file() {
+                let messages = toml::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|e| {
+                        anyhow::anyhow!("Invalid {}: {}", path.display(), e)
+                    })?;
+                return Ok(Self {
+                    locale: Some(name.to_string()),
+                    messages,
+                });
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Get the locale of the translation, None for English
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Get the message of a key
+    pub fn get(&self, key: &str) -> String {
+        self.messages
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| {
+                ENGLISH_MESSAGES
+                    .iter()
+                    .find(|(english_key, _)| *english_key == key)
+                    .map(|(_, message)| *message)
+            })
+            .unwrap_or(key)
+            .to_string()
+    }
+}
+
+/// Get the locale the user's messages should be in
+pub fn system_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_else(|| "en".to_string())
+}
+
+/// Error as shown in a dialog
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentedError {
+    pub kind: ErrorKind,
+    pub title: String,
+    pub message: String,
+    /// The error chain, for the user to report or search for
+    pub detail: String,
+    pub actions: Vec<(ErrorAction, String)>,
+}
+
+impl PresentedError {
+    /// Present an error in the system locale
+    pub fn new(err: &anyhow::Error) -> Self {
+        Self::with_catalog(err, MessageCatalog::global())
+    }
+
+    pub fn with_catalog(err: &anyhow::Error, catalog: &MessageCatalog) -> Self {
+        let kind = ErrorKind::of(err);
+        Self {
+            kind,
+            title: catalog.get(&format!("error.{}.title", kind.name())),
+            message: catalog.get(&format!("error.{}.message", kind.name())),
+            detail: format!("{err:#}"),
+            actions: kind
+                .actions()
+                .into_iter()
+                .map(|action| (action, catalog.get(action.message_key())))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_present_error() {
+        let err = anyhow::Error::new(CatalystError::new(
+            ErrorKind::Authentication,
+            "401 from api.example.com",
+        ))
+        .context("Failed to complete the chat request");
+        let english = PresentedError::with_catalog(&err, &MessageCatalog::default());
+        assert_eq!(english.kind, ErrorKind::Authentication);
+        assert_eq!(english.title, "Authentication failed");
+        assert_eq!(
+            english.detail,
+            "Failed to complete the chat request: 401 from api.example.com"
+        );
+        assert_eq!(
+            english.actions,
+            [
+                (
+                    ErrorAction::Reauthenticate,
+                    "Update Credentials".to_string()
+                ),
+                (ErrorAction::ViewLogs, "View Logs".to_string()),
+            ]
+        );
+
+        let io = anyhow::Error::new(std::io::Error::from(
+            std::io::ErrorKind::PermissionDenied,
+        ))
+        .context("Failed to save");
+        assert_eq!(ErrorKind::of(&io), ErrorKind::PermissionDenied);
+        let status =
+            anyhow::Error::new(CatalystError::from_status(429, "slow down"));
+        assert_eq!(ErrorKind::of(&status), ErrorKind::RateLimited);
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("boom")), ErrorKind::Internal);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("de.toml"),
+            "\"error.authentication.title\" = \"Anmeldung fehlgeschlagen\"\n",
+        )
+        .unwrap();
+        let german = MessageCatalog::load(dir.path(), "de_DE.UTF-8").unwrap();
+        assert_eq!(german.locale(), Some("de"));
+        let presented = PresentedError::with_catalog(&err, &german);
+        assert_eq!(presented.title, "Anmeldung fehlgeschlagen");
+        // Untranslated keys fall back to English
+        assert_eq!(presented.actions[1].1, "View Logs");
+
+        let french = MessageCatalog::load(dir.path(), "fr_FR").unwrap();
+        assert_eq!(french.locale(), None);
+    }
+}
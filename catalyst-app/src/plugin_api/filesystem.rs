@@ -0,0 +1,168 @@
+//! Filesystem Access
+//!
+//! This module puts the file operations of stores and indexes behind the
+//! [`Fs`] trait so tests can make them fail. [`RealFs`] uses the disk;
+//! [`FaultyFs`] wraps it and fails chosen operations on chosen paths with an
+//! I/O error, which is how a full disk, a permission problem or a file
+//! vanishing mid-read is simulated.
+
+use parking_lot::Mutex;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// File operations used by stores and indexes
+pub trait Fs: Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Replace a file atomically, creating its directory if needed
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl RealFs {
+    /// Get the real filesystem as a shared filesystem
+    pub fn shared() -> Arc<dyn Fs> {
+        Arc::new(RealFs)
+    }
+}
+
+impl Fs for RealFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let dir = path.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid path {}", path.display()),
+            )
+        })?;
+        std::fs::create_dir_all(dir)?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(bytes)?;
+        file.as_file().sync_all()?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+}
+
+/// File operation that can be made to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsOperation {
+    Read,
+    Write,
+    Rename,
+    Remove,
+}
+
+/// Filesystem failing chosen operations
+#[derive(Default)]
+pub struct FaultyFs {
+    inner: RealFs,
+    failures: Mutex<Vec<(FsOperation, PathBuf)>>,
+}
+
+impl FaultyFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail `operation` on `path` and everything below it until healed
+    pub fn fail(&self, operation: FsOperation, path: &Path) {
+        self.failures.lock().push((operation, path.to_path_buf()));
+    }
+
+    /// Stop failing operations
+    pub fn heal(&self) {
+        self.failures.lock().clear();
+    }
+
+    fn check(&self, operation: FsOperation, path: &Path) -> io::Result<()> {
+        let failing = self.failures.lock().iter().any(|(failing, prefix)| {
+            *failing == operation && path.starts_with(prefix)
+        });
+        if failing {
+            return Err(io::Error::other(format!(
+                "injected {:?} failure for {}",
+                operation,
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Fs for FaultyFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.check(FsOperation::Read, path)?;
+        self.inner.read(path)
+    }
+
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        self.check(FsOperation::Write, path)?;
+        self.inner.write_atomic(path, bytes)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(FsOperation::Rename, from)?;
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check(FsOperation::Remove, path)?;
+        self.inner.remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        IndexKind, IndexLoad, read_index_with, write_index_with,
+    };
+
+    #[test]
+    fn test_faulty_fs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("nested")
+            .join(IndexKind::FileIndex.file_name());
+        let fs = FaultyFs::new();
+        fs.fail(FsOperation::Write, dir.path());
+        assert!(
+            write_index_with(&fs, &path, IndexKind::FileIndex, &vec![1]).is_err()
+        );
+        assert!(!path.exists());
+
+        fs.heal();
+        write_index_with(&fs, &path, IndexKind::FileIndex, &vec![1]).unwrap();
+        fs.fail(FsOperation::Read, &path);
+        assert!(matches!(
+            read_index_with::<Vec<u32>>(&fs, &path, IndexKind::FileIndex),
+            IndexLoad::Corrupt(reason) if reason.starts_with("unreadable")
+        ));
+        assert!(fs.rename(&path, &dir.path().join("moved")).is_ok());
+        assert!(matches!(
+            read_index_with::<Vec<u32>>(&RealFs, &path, IndexKind::FileIndex),
+            IndexLoad::Missing
+        ));
+    }
+}
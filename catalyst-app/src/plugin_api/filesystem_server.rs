@@ -0,0 +1,666 @@
+//! Built-in Filesystem MCP Server
+//!
+//! The filesystem server is the one nearly every workspace uses, so instead
+//! of spawning `@modelcontextprotocol/server-filesystem` with `npx` it runs
+//! inside Catalyst. It offers the `read_file`, `write_file`,
+//! `list_directory` and `search_files` tools over the roots of the
+//! workspace, and every path goes through the [`WorkspaceSandbox`] first:
+//! paths outside of the roots, paths excluded by `.catalystignore` and
+//! symbolic links escaping the workspace are refused.
+//!
+//! The `watch_path` and `unwatch_path` tools watch paths with a
+//! [`PathWatcher`], whose changes the server sends as
+//! [`PATHS_CHANGED`](crate::plugin_api::PATHS_CHANGED) notifications.
+//!
+//! The server learns the roots from the registry it is registered with,
+//! when it starts and whenever they change, like a spawned server asking
+//! `roots/list`. It starts without roots too, since it is started before
+//! the first window opens a workspace, and its tools refuse every path
+//! until roots are added.
+
+use anyhow::{Result, anyhow};
+use parking_lot::RwLock;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    CatalystIgnoreConfig, ContextScope, DEFAULT_PATH_CHANGE_DEBOUNCE, Fs,
+    INVALID_PARAMS, McpClientError, McpContent, McpError, McpNotification,
+    McpNotificationSink, McpRequest, McpRequestHandler, McpResource,
+    McpResourceContent, McpResourceLimits, McpResponse, McpServerCapabilities,
+    McpServerHealth, McpServerInfo, McpServerPlugin, McpServerStatus, McpTool,
+    McpToolResult, PathWatchLimits, PathWatcher, ROOTS_LIST_CHANGED, RealFs,
+    ToolEffect, UNWATCH_PATH_TOOL_NAME, WATCH_PATH_TOOL_NAME, WorkspaceRoots,
+    WorkspaceSandbox, serve_in_process,
+};
+
+/// Id the built-in filesystem server is registered under
+pub const FILESYSTEM_SERVER_ID: &str = "filesystem";
+/// Largest file `read_file` returns
+pub const MAX_READ_FILE_SIZE: u64 = 1024 * 1024;
+/// Most paths `search_files` returns
+pub const MAX_SEARCH_RESULTS: usize = 200;
+
+/// Filesystem MCP server running in-process
+pub struct FilesystemMcpServer {
+    config: CatalystIgnoreConfig,
+    request_handler: Option<McpRequestHandler>,
+    notification_sink: Option<McpNotificationSink>,
+    /// Roots and sandbox of the workspace while running with roots
+    workspace: RwLock<Option<(WorkspaceRoots, WorkspaceSandbox)>>,
+    /// Watcher of the paths the assistant watches, replaced with the roots
+    watcher: RwLock<Option<Arc<PathWatcher>>>,
+    started_at: Option<Instant>,
+    last_error: RwLock<Option<String>>,
+    request_count: AtomicU64,
+    error_count: AtomicU64,
+}
+
+impl FilesystemMcpServer {
+    /// Create the server, applying `config` to every root of the workspace
+    pub fn new(config: CatalystIgnoreConfig) -> Self {
+        Self {
+            config,
+            request_handler: None,
+            notification_sink: None,
+            workspace: RwLock::new(None),
+            watcher: RwLock::new(None),
+            started_at: None,
+            last_error: RwLock::new(None),
+            request_count: AtomicU64::new(0),
+            error_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Load the roots the server may see from the registry
+    ///
+    /// The previous roots are dropped first, so they aren't accessible
+    /// anymore once they are closed, even if the new ones fail to load.
+    fn load_workspace(&self) -> Result<()> {
+        *self.workspace.write() = None;
+        *self.watcher.write() = None;
+        let roots = self
+            .request_handler
+            .as_ref()
+            .map(McpRequestHandler::workspace_roots)
+            .unwrap_or_default();
+        if roots.is_empty() {
+            return Ok(());
+        }
+        let sandbox = roots.sandbox(&self.config)?;
+        let watcher = Arc::new(PathWatcher::new(
+            sandbox.clone(),
+            PathWatchLimits::default(),
+        ));
+        if let Some(sink) = self.notification_sink.clone() {
+            watcher.forward_notifications(
+                DEFAULT_PATH_CHANGE_DEBOUNCE,
+                move |notification| sink.deliver(notification),
+            )?;
+        }
+        *self.workspace.write() = Some((roots, sandbox));
+        *self.watcher.write() = Some(watcher);
+        Ok(())
+    }
+
+    fn watcher(&self) -> Result<Arc<PathWatcher>> {
+        self.watcher.read().clone().ok_or_else(no_workspace)
+    }
+
+    fn sandbox(&self) -> Result<WorkspaceSandbox> {
+        self.workspace
+            .read()
+            .as_ref()
+            .map(|(_, sandbox)| sandbox.clone())
+            .ok_or_else(no_workspace)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<String> {
+        let resolved = self.sandbox()?.check_access(path)?;
+        let size = std::fs::metadata(&resolved)?.len();
+        if size > MAX_READ_FILE_SIZE {
+            return Err(anyhow!(
+                "'{}' is {} bytes, more than the {} bytes read_file returns",
+                path.display(),
+                size,
+                MAX_READ_FILE_SIZE
+            ));
+        }
+        String::from_utf8(std::fs::read(&resolved)?)
+            .map_err(|_| anyhow!("'{}' is not a text file", path.display()))
+    }
+
+    fn write_file(&self, path: &Path, content: &str) -> Result<String> {
+        let resolved = self.sandbox()?.check_access(path)?;
+        if resolved.is_dir() {
+            return Err(anyhow!("'{}' is a directory", path.display()));
+        }
+        RealFs.write_atomic(&resolved, content.as_bytes())?;
+        Ok(format!(
+            "Wrote {} bytes to {}",
+            content.len(),
+            path.display()
+        ))
+    }
+
+    fn list_directory(&self, path: &Path) -> Result<String> {
+        let sandbox = self.sandbox()?;
+        let resolved = sandbox.check_access(path)?;
+        let mut entries = std::fs::read_dir(&resolved)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| sandbox.is_allowed(&entry.path()))
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                (name, entry.path().is_dir())
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(format!("{} is empty", path.display()));
+        }
+        entries.sort();
+        let entries = entries
+            .into_iter()
+            .map(|(name, is_dir)| {
+                format!("{} {}", if is_dir { "[DIR]" } else { "[FILE]" }, name)
+            })
+            .collect::<Vec<_>>();
+        Ok(entries.join("\n"))
+    }
+
+    fn search_files(&self, path: &Path, pattern: &str) -> Result<String> {
+        let (roots, sandbox) =
+            self.workspace.read().clone().ok_or_else(no_workspace)?;
+        let resolved = sandbox.check_access(path)?;
+        let pattern = pattern.to_lowercase();
+        let mut matches = Vec::new();
+        let mut total = 0;
+        roots
+            .crawler(&self.config)?
+            .with_scope(ContextScope {
+                roots: vec![resolved],
+                max_tokens: usize::MAX,
+                latency_budget: Duration::MAX,
+            })
+            .for_each_file(|file| {
+                let name = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if !name.contains(&pattern) {
+                    return;
+                }
+                total += 1;
+                if matches.len() < MAX_SEARCH_RESULTS {
+                    matches.push(display_path(file, &roots));
+                }
+            });
+        if matches.is_empty() {
+            return Ok(format!("No files matching '{}'", pattern));
+        }
+        matches.sort();
+        let mut output = matches.join("\n");
+        if total > matches.len() {
+            output.push_str(&format!(
+                "\n... and {} more, narrow the search to see them",
+                total - matches.len()
+            ));
+        }
+        Ok(output)
+    }
+}
+
+/// Show a path relative to its root, prefixed with the root's name in a
+/// multi-root workspace, so it can be passed back to the tools
+fn display_path(path: &Path, roots: &WorkspaceRoots) -> String {
+    let relative = roots.root_for_path(path).and_then(|root| {
+        let relative = path.strip_prefix(&root.path).ok()?;
+        Some(if roots.primary() == Some(root) {
+            relative.to_path_buf()
+        } else {
+            Path::new(&root.name).join(relative)
+        })
+    });
+    relative
+        .unwrap_or_else(|| path.to_path_buf())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+impl McpServerPlugin for FilesystemMcpServer {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn server_info(&self) -> McpServerInfo {
+        McpServerInfo {
+            id: FILESYSTEM_SERVER_ID.to_string(),
+            name: "Filesystem".to_string(),
+            description: "Read, write and watch files in the workspace".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command: Vec::new(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            working_directory: None,
+            auto_start: true,
+            keep_warm: false,
+            capabilities: McpServerCapabilities {
+                tools: true,
+                resources: false,
+                prompts: false,
+                logging: false,
+                experimental: HashMap::new(),
+            },
+            resource_limits: McpResourceLimits::default(),
+            call_limits: Default::default(),
+            request_timeout_secs: None,
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        self.load_workspace()?;
+        self.started_at = Some(Instant::now());
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        *self.workspace.write() = None;
+        *self.watcher.write() = None;
+        self.started_at = None;
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    fn health_check(&self) -> McpServerHealth {
+        McpServerHealth {
+            status: if self.is_running() {
+                McpServerStatus::Running
+            } else {
+                McpServerStatus::Stopped
+            },
+            last_error: self.last_error.read().clone(),
+            uptime: self.started_at.map(|started_at| started_at.elapsed()),
+            request_count: self.request_count.load(Ordering::Relaxed),
+            error_count: self.error_count.load(Ordering::Relaxed),
+        }
+    }
+
+    fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+        Ok(serve_in_process(self, request))
+    }
+
+    fn get_tools(&self) -> Result<Vec<McpTool>> {
+        let path = json!({
+            "type": "string",
+            "description": "Path relative to the workspace root"
+        });
+        let mut tools = vec![
+            McpTool {
+                name: "read_file".to_string(),
+                description: Some("Read a text file of the workspace".to_string()),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": { "path": path },
+                    "required": ["path"],
+                }),
+                effect: ToolEffect::ReadOnly,
+            },
+            McpTool {
+                name: "write_file".to_string(),
+                description: Some(
+                    "Create a file of the workspace or replace its content"
+                        .to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": path,
+                        "content": { "type": "string" },
+                    },
+                    "required": ["path", "content"],
+                }),
+                effect: ToolEffect::Mutating,
+            },
+            McpTool {
+                name: "list_directory".to_string(),
+                description: Some(
+                    "List the files and directories in a directory".to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": { "path": path },
+                    "required": ["path"],
+                }),
+                effect: ToolEffect::ReadOnly,
+            },
+            McpTool {
+                name: "search_files".to_string(),
+                description: Some(
+                    "Find files under a directory whose name contains a pattern, \
+                     ignoring case"
+                        .to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": path,
+                        "pattern": { "type": "string" },
+                    },
+                    "required": ["path", "pattern"],
+                }),
+                effect: ToolEffect::ReadOnly,
+            },
+        ];
+        tools.extend(PathWatcher::tools());
+        Ok(tools)
+    }
+
+    fn get_resources(&self) -> Result<Vec<McpResource>> {
+        Ok(Vec::new())
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<McpToolResult> {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        let path = string_argument(&arguments, "path")?;
+        let path = Path::new(path);
+        let output = match tool_name {
+            "read_file" => self.read_file(path),
+            "write_file" => {
+                let content = string_argument(&arguments, "content")?;
+                self.write_file(path, content)
+            }
+            "list_directory" => self.list_directory(path),
+            "search_files" => {
+                let pattern = string_argument(&arguments, "pattern")?;
+                self.search_files(path, pattern)
+            }
+            WATCH_PATH_TOOL_NAME | UNWATCH_PATH_TOOL_NAME => self
+                .watcher()
+                .and_then(|watcher| watcher.call_tool(tool_name, &arguments)),
+            _ => {
+                return Err(invalid_params(format!("Unknown tool '{}'", tool_name)));
+            }
+        };
+        let (text, is_error) = match output {
+            Ok(text) => (text, false),
+            Err(err) => {
+                self.error_count.fetch_add(1, Ordering::Relaxed);
+                let message = format!("{:#}", err);
+                *self.last_error.write() = Some(message.clone());
+                (message, true)
+            }
+        };
+        Ok(McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                data: Value::String(text),
+                hint: None,
+            }],
+            is_error,
+        })
+    }
+
+    fn read_resource(&self, resource_uri: &str) -> Result<McpResourceContent> {
+        Err(anyhow!(
+            "The filesystem server has no resource '{}'",
+            resource_uri
+        ))
+    }
+
+    fn subscribe_to_resource(&self, resource_uri: &str) -> Result<()> {
+        Err(anyhow!(
+            "The filesystem server has no resource '{}'",
+            resource_uri
+        ))
+    }
+
+    fn unsubscribe_from_resource(&self, _resource_uri: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_notification(&self, notification: McpNotification) -> Result<()> {
+        if notification.method == ROOTS_LIST_CHANGED && self.is_running() {
+            self.load_workspace()?;
+        }
+        Ok(())
+    }
+
+    fn set_notification_sink(&mut self, sink: McpNotificationSink) {
+        self.notification_sink = Some(sink);
+    }
+
+    fn set_request_handler(&mut self, handler: McpRequestHandler) {
+        self.request_handler = Some(handler);
+    }
+}
+
+fn no_workspace() -> anyhow::Error {
+    anyhow!("No workspace folder is open")
+}
+
+fn string_argument<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    arguments[name]
+        .as_str()
+        .ok_or_else(|| invalid_params(format!("'{}' must be a string", name)))
+}
+
+fn invalid_params(message: String) -> anyhow::Error {
+    McpClientError::ServerError(McpError {
+        code: INVALID_PARAMS,
+        message,
+        data: None,
+    })
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::McpServerRegistry;
+
+    fn text(result: &McpToolResult) -> &str {
+        result.content[0].data.as_str().unwrap()
+    }
+
+    #[test]
+    fn test_filesystem_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join(".catalystignore"), ".env\n").unwrap();
+        std::fs::write(root.join(".env"), "TOKEN=1\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "outside\n").unwrap();
+
+        let registry = McpServerRegistry::new();
+        registry.set_workspace_roots(WorkspaceRoots::new([root.clone()]));
+        let handle = registry
+            .register_server(
+                FILESYSTEM_SERVER_ID.to_string(),
+                Box::new(FilesystemMcpServer::new(CatalystIgnoreConfig::default())),
+            )
+            .unwrap();
+        handle.write().start().unwrap();
+        let server = handle.read();
+        let call = |tool: &str, arguments: Value| {
+            server.call_tool(tool, arguments).unwrap()
+        };
+
+        let read = call("read_file", json!({"path": "src/main.rs"}));
+        assert!(!read.is_error);
+        assert_eq!(text(&read), "fn main() {}\n");
+        assert!(call("read_file", json!({"path": ".env"})).is_error);
+        assert!(call("read_file", json!({"path": "../secret.txt"})).is_error);
+
+        let written = call(
+            "write_file",
+            json!({"path": "src/lib/mod.rs", "content": "pub fn lib() {}\n"}),
+        );
+        assert!(!written.is_error);
+        assert_eq!(
+            std::fs::read_to_string(root.join("src/lib/mod.rs")).unwrap(),
+            "pub fn lib() {}\n"
+        );
+        let escaping = call(
+            "write_file",
+            json!({"path": "../escape.txt", "content": "x"}),
+        );
+        assert!(escaping.is_error);
+        assert!(!dir.path().join("escape.txt").exists());
+
+        let listing = call("list_directory", json!({"path": "."}));
+        assert_eq!(text(&listing), "[FILE] .catalystignore\n[DIR] src");
+        let listing = call("list_directory", json!({"path": "src"}));
+        assert_eq!(text(&listing), "[DIR] lib\n[FILE] main.rs");
+
+        let found = call("search_files", json!({"path": ".", "pattern": "MOD"}));
+        assert_eq!(text(&found), "src/lib/mod.rs");
+        let found = call("search_files", json!({"path": ".", "pattern": "env"}));
+        assert!(text(&found).starts_with("No files"));
+
+        let err = server
+            .call_tool("delete_file", json!({"path": "a"}))
+            .unwrap_err();
+        assert!(matches!(
+            McpClientError::of(&err),
+            Some(McpClientError::ServerError(error)) if error.code == INVALID_PARAMS
+        ));
+        assert_eq!(server.health_check().error_count, 3);
+    }
+
+    #[test]
+    fn test_filesystem_server_follows_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        // The server starts before a workspace is open
+        let registry = McpServerRegistry::new();
+        let handle = registry
+            .register_server(
+                FILESYSTEM_SERVER_ID.to_string(),
+                Box::new(FilesystemMcpServer::new(CatalystIgnoreConfig::default())),
+            )
+            .unwrap();
+        handle.write().start().unwrap();
+        let read = || {
+            handle
+                .read()
+                .call_tool("read_file", json!({"path": "main.rs"}))
+                .unwrap()
+        };
+        assert!(handle.read().is_running());
+        assert_eq!(text(&read()), "No workspace folder is open");
+
+        registry
+            .set_workspace_roots(WorkspaceRoots::new([dir.path().to_path_buf()]));
+        assert_eq!(text(&read()), "fn main() {}\n");
+
+        // Closing the last folder drops its sandbox
+        registry.set_workspace_roots(WorkspaceRoots::default());
+        assert!(handle.read().is_running());
+        assert!(read().is_error);
+    }
+
+    #[test]
+    fn test_filesystem_server_streams_watched_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir_all(root.join("dist")).unwrap();
+        std::fs::write(root.join(".catalystignore"), "!dist/\n").unwrap();
+
+        let registry = McpServerRegistry::new();
+        registry.set_workspace_roots(WorkspaceRoots::new([root.clone()]));
+        let changes = registry.path_changes();
+        let handle = registry
+            .register_server(
+                FILESYSTEM_SERVER_ID.to_string(),
+                Box::new(FilesystemMcpServer::new(CatalystIgnoreConfig::default())),
+            )
+            .unwrap();
+        handle.write().start().unwrap();
+        let server = handle.read();
+        assert!(
+            server
+                .get_tools()
+                .unwrap()
+                .iter()
+                .any(|tool| tool.name == WATCH_PATH_TOOL_NAME)
+        );
+        let watched = server
+            .call_tool(WATCH_PATH_TOOL_NAME, json!({"path": "dist"}))
+            .unwrap();
+        assert!(!watched.is_error);
+        assert!(changes.take_message().is_none());
+
+        std::fs::write(root.join("dist/bundle.js"), "x".repeat(120)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let message = loop {
+            if let Some(message) = changes.take_message() {
+                break message;
+            }
+            assert!(Instant::now() < deadline, "no change was streamed");
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert!(
+            message
+                .content
+                .starts_with("[filesystem] Changes in watched")
+        );
+        assert!(message.content.contains("dist/bundle.js (120 bytes"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filesystem_server_symlink_escape() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "outside\n").unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("parent")).unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("secret.txt"),
+            root.join("secret.txt"),
+        )
+        .unwrap();
+
+        let registry = McpServerRegistry::new();
+        registry.set_workspace_roots(WorkspaceRoots::new([root.clone()]));
+        let handle = registry
+            .register_server(
+                FILESYSTEM_SERVER_ID.to_string(),
+                Box::new(FilesystemMcpServer::new(CatalystIgnoreConfig::default())),
+            )
+            .unwrap();
+        handle.write().start().unwrap();
+        let server = handle.read();
+
+        for arguments in [
+            json!({"path": "secret.txt"}),
+            json!({"path": "parent/secret.txt"}),
+        ] {
+            assert!(server.call_tool("read_file", arguments).unwrap().is_error);
+        }
+        let written = server
+            .call_tool(
+                "write_file",
+                json!({"path": "parent/escape.txt", "content": "x"}),
+            )
+            .unwrap();
+        assert!(written.is_error);
+        assert!(!dir.path().join("escape.txt").exists());
+        let listing = server
+            .call_tool("list_directory", json!({"path": "."}))
+            .unwrap();
+        assert_eq!(text(&listing), ". is empty");
+    }
+}
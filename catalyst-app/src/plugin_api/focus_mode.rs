@@ -0,0 +1,292 @@
+//! Focus Mode
+//!
+//! During a meeting or a demo, agents working in the background get in the
+//! way: automations fire, polls hit the network and MCP servers hold on to
+//! memory and CPU. Focus mode pauses all of that for a set time. While it
+//! is on, automations triggered by events and schedules are skipped (runs
+//! started by hand still go), CI and pull request polling and the warm-up
+//! of MCP servers are held back, and MCP servers that aren't kept warm are
+//! suspended, to be started again on their next tool call.
+//!
+//! A status bar item shows until when focus mode lasts; clicking it ends
+//! focus mode early. Otherwise it ends by itself once the time runs out.
+
+use crossbeam_channel::{Receiver, Sender};
+use floem::{
+    View,
+    ext_event::create_signal_from_channel,
+    reactive::SignalGet,
+    views::{Decorators, label},
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{Clock, SystemClock, current_time};
+
+static GLOBAL_FOCUS_MODE: Lazy<FocusMode> = Lazy::new(FocusMode::new);
+
+/// Minutes focus mode lasts unless the user picks another time
+pub const DEFAULT_FOCUS_MINUTES: u64 = 60;
+
+/// Longest time the timer sleeps before checking whether focus mode was
+/// ended or extended
+const TIMER_TICK: Duration = Duration::from_secs(1);
+
+/// How focus mode behaves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocusModeSettings {
+    /// Minutes focus mode lasts unless another time is picked
+    pub duration_minutes: u64,
+    /// Whether MCP servers that aren't kept warm are suspended
+    pub suspend_servers: bool,
+}
+
+impl Default for FocusModeSettings {
+    fn default() -> Self {
+        Self {
+            duration_minutes: DEFAULT_FOCUS_MINUTES,
+            suspend_servers: true,
+        }
+    }
+}
+
+impl FocusModeSettings {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_minutes.max(1) * 60)
+    }
+}
+
+/// Whether focus mode is on, and for how long
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusStatus {
+    pub active: bool,
+    /// Seconds until background activity resumes
+    pub remaining_secs: u64,
+}
+
+impl FocusStatus {
+    /// Describe the status for the status bar, e.g. `Focus until 14:30`;
+    /// empty when focus mode is off
+    pub fn label(&self) -> String {
+        if !self.active {
+            return String::new();
+        }
+        let until = chrono::DateTime::<chrono::Local>::from(
+            current_time() + Duration::from_secs(self.remaining_secs),
+        );
+        format!("Focus until {}", until.format("%H:%M"))
+    }
+}
+
+#[derive(Default)]
+struct FocusState {
+    until: Option<Instant>,
+    /// Whether the timer thread ending focus mode is running; it exits
+    /// once focus mode is off
+    timer_running: bool,
+}
+
+/// Switch pausing background agent activity for a while
+///
+/// Cloning gives another handle to the same switch.
+#[derive(Clone)]
+pub struct FocusMode {
+    clock: Arc<dyn Clock>,
+    state: Arc<Mutex<FocusState>>,
+    listeners: Arc<Mutex<Vec<Sender<FocusStatus>>>>,
+}
+
+impl Default for FocusMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FocusMode {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::shared())
+    }
+
+    /// Create a switch timed by `clock`
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            state: Arc::new(Mutex::new(FocusState::default())),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Get the switch shared by the whole application
+    pub fn global() -> &'static FocusMode {
+        &GLOBAL_FOCUS_MODE
+    }
+
+    /// Turn focus mode on for `duration`, or extend it to `duration` from
+    /// now if it is on already
+    pub fn start(&self, duration: Duration) -> FocusStatus {
+        let start_timer = {
+            let mut state = self.state.lock();
+            state.until = Some(self.clock.instant() + duration);
+            !std::mem::replace(&mut state.timer_running, true)
+        };
+        tracing::info!("Focus mode on for {} minutes", duration.as_secs() / 60);
+        let status = self.publish();
+        if start_timer {
+            let focus = self.clone();
+            let spawned = std::thread::Builder::new()
+                .name("FocusMode".to_string())
+                .spawn(move || focus.run_timer());
+            if let Err(err) = spawned {
+                tracing::error!("Failed to start the focus mode timer: {err}");
+                self.state.lock().timer_running = false;
+            }
+        }
+        status
+    }
+
+    /// Turn focus mode off, resuming background activity
+    pub fn stop(&self) -> FocusStatus {
+        if self.state.lock().until.take().is_some() {
+            tracing::info!("Focus mode ended");
+        }
+        self.publish()
+    }
+
+    /// End focus mode once its time runs out
+    ///
+    /// Sleeps at most [`TIMER_TICK`] at a time, so a start extending focus
+    /// mode or a stop ending it is seen without a thread of its own.
+    fn run_timer(&self) {
+        loop {
+            let remaining = {
+                let mut state = self.state.lock();
+                let now = self.clock.instant();
+                let remaining = state
+                    .until
+                    .map(|until| until.saturating_duration_since(now));
+                match remaining {
+                    Some(remaining) if !remaining.is_zero() => remaining,
+                    _ => {
+                        state.timer_running = false;
+                        if state.until.take().is_none() {
+                            // Ended by a stop
+                            return;
+                        }
+                        break;
+                    }
+                }
+            };
+            self.clock.sleep(remaining.min(TIMER_TICK));
+        }
+        tracing::info!("Focus mode ran out; background activity resumes");
+        self.publish();
+    }
+
+    /// Check if background activity is paused
+    pub fn is_active(&self) -> bool {
+        self.status().active
+    }
+
+    pub fn status(&self) -> FocusStatus {
+        let now = self.clock.instant();
+        let remaining = self
+            .state
+            .lock()
+            .until
+            .map(|until| until.saturating_duration_since(now))
+            .filter(|remaining| !remaining.is_zero());
+        FocusStatus {
+            active: remaining.is_some(),
+            remaining_secs: remaining.map_or(0, |remaining| remaining.as_secs()),
+        }
+    }
+
+    /// Get the status after every start and stop
+    pub fn subscribe(&self) -> Receiver<FocusStatus> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.listeners.lock().push(tx);
+        rx
+    }
+
+    fn publish(&self) -> FocusStatus {
+        let status = self.status();
+        self.listeners
+            .lock()
+            .retain(|listener| listener.send(status).is_ok());
+        status
+    }
+}
+
+/// Status bar item shown while focus mode is on; clicking it ends focus
+/// mode
+///
+/// The `toggle_focus_mode` command turns focus mode on and off.
+pub fn focus_mode_view(focus: FocusMode) -> Box<dyn View> {
+    let initial = focus.status();
+    let status = create_signal_from_channel(focus.subscribe());
+    let current = move || status.get().unwrap_or(initial);
+    Box::new(
+        label(move || current().label())
+            .on_click_stop(move |_| {
+                focus.stop();
+            })
+            .style(move |s| {
+                s.padding_horiz(10.0)
+                    .selectable(false)
+                    .apply_if(!current().active, |s| s.hide())
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::ManualClock;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_focus_mode_resumes() {
+        // Sleeping on the manual clock lets the time run out at once
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(SystemTime::now()));
+        let focus = FocusMode::with_clock(clock);
+        let statuses = focus.subscribe();
+        assert!(!focus.is_active());
+        assert_eq!(focus.status().label(), "");
+
+        focus.start(Duration::from_secs(30 * 60));
+        let started = statuses.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(started.active);
+        assert!(started.remaining_secs <= 30 * 60);
+        assert!(started.label().starts_with("Focus until "));
+        let resumed = statuses.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(resumed, FocusStatus::default());
+        assert!(!focus.is_active());
+
+        let focus = FocusMode::new();
+        focus.start(Duration::from_secs(3600));
+        assert!(focus.is_active());
+        assert!(!focus.stop().active);
+        assert!(!focus.is_active());
+    }
+
+    #[test]
+    fn test_focus_mode_timer_ends_with_focus_mode() {
+        let focus = FocusMode::new();
+        for _ in 0..3 {
+            focus.start(Duration::from_secs(3600));
+        }
+        assert!(focus.state.lock().timer_running);
+
+        focus.stop();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while focus.state.lock().timer_running {
+            assert!(Instant::now() < deadline, "the timer kept running");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!focus.is_active());
+    }
+}
@@ -0,0 +1,355 @@
+//! Index Store
+//!
+//! This module persists indexes to disk behind a small header holding a
+//! magic number, the format version, the kind of index and a SHA-256
+//! checksum of the payload. Loading verifies all of them, so a truncated
+//! write, bit rot or a file left by an older build is detected and the
+//! index rebuilt in the background, instead of crashing on a parse error or
+//! silently answering queries from garbage.
+
+use anyhow::Result;
+use serde::{Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    DegradationTracker, Fs, JobHandle, JobScheduler, RealFs, RetrievalSource,
+    Subsystem,
+};
+
+const INDEX_MAGIC: &[u8; 8] = b"CATINDEX";
+/// Version of the on-disk layout, bumped when the header or a payload
+/// changes incompatibly
+pub const INDEX_FORMAT_VERSION: u32 = 2;
+const CHECKSUM_LEN: usize = 32;
+const HEADER_LEN: usize = INDEX_MAGIC.len() + 4 + 1 + 8 + CHECKSUM_LEN;
+
+/// Kind of persisted index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    /// Trigram content index of the workspace files
+    FileIndex,
+    /// Embeddings of the workspace chunks
+    VectorStore,
+    /// State restored at startup to avoid a full re-index
+    WarmStartSnapshot,
+}
+
+impl IndexKind {
+    /// Get the file name of the index in an index directory
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            IndexKind::FileIndex => "files.idx",
+            IndexKind::VectorStore => "vectors.idx",
+            IndexKind::WarmStartSnapshot => "warm-start.idx",
+        }
+    }
+
+    /// Get the retrieval source served while this index is unavailable
+    pub fn retrieval_source(&self) -> Option<RetrievalSource> {
+        match self {
+            IndexKind::FileIndex => Some(RetrievalSource::Trigram),
+            IndexKind::VectorStore => Some(RetrievalSource::Vector),
+            IndexKind::WarmStartSnapshot => None,
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            IndexKind::FileIndex => 1,
+            IndexKind::VectorStore => 2,
+            IndexKind::WarmStartSnapshot => 3,
+        }
+    }
+}
+
+/// Result of loading a persisted index
+#[derive(Debug)]
+pub enum IndexLoad<T> {
+    Loaded(T),
+    /// No index has been written yet
+    Missing,
+    /// The file was written by a different format version
+    VersionMismatch {
+        found: u32,
+        expected: u32,
+    },
+    /// The file is intact but was built for another configuration, such as
+    /// a different embedding model
+    Incompatible(String),
+    /// The file is damaged or does not hold the expected index
+    Corrupt(String),
+}
+
+impl<T> IndexLoad<T> {
+    /// Get the loaded index, if the file was usable
+    pub fn loaded(self) -> Option<T> {
+        match self {
+            IndexLoad::Loaded(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Transform the loaded index
+    pub fn map<U>(self, f: impl FnOnce(T) -> IndexLoad<U>) -> IndexLoad<U> {
+        match self {
+            IndexLoad::Loaded(value) => f(value),
+            IndexLoad::Missing => IndexLoad::Missing,
+            IndexLoad::VersionMismatch { found, expected } => {
+                IndexLoad::VersionMismatch { found, expected }
+            }
+            IndexLoad::Incompatible(reason) => IndexLoad::Incompatible(reason),
+            IndexLoad::Corrupt(reason) => IndexLoad::Corrupt(reason),
+        }
+    }
+}
+
+/// Write an index atomically with its header and checksum
+pub fn write_index<T: Serialize>(
+    path: &Path,
+    kind: IndexKind,
+    value: &T,
+) -> Result<()> {
+    write_index_with(&RealFs, path, kind, value)
+}
+
+/// Write an index through `fs`
+pub fn write_index_with<T: Serialize>(
+    fs: &dyn Fs,
+    path: &Path,
+    kind: IndexKind,
+    value: &T,
+) -> Result<()> {
+    let payload = serde_json::to_vec(value)?;
+    let checksum = Sha256::digest(&payload);
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(INDEX_MAGIC);
+    bytes.extend_from_slice(&INDEX_FORMAT_VERSION.to_le_bytes());
+    bytes.push(kind.tag());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum);
+    bytes.extend_from_slice(&payload);
+
+    fs.write_atomic(path, &bytes).map_err(|err| {
+        anyhow::anyhow!("Failed to write index {}: {}", path.display(), err)
+    })
+}
+
+/// Read an index, verifying its header and checksum
+pub fn read_index<T: DeserializeOwned>(
+    path: &Path,
+    kind: IndexKind,
+) -> IndexLoad<T> {
+    read_index_with(&RealFs, path, kind)
+}
+
+/// Read an index through `fs`
+pub fn read_index_with<T: DeserializeOwned>(
+    fs: &dyn Fs,
+    path: &Path,
+    kind: IndexKind,
+) -> IndexLoad<T> {
+    let bytes = match fs.read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return IndexLoad::Missing;
+        }
+        Err(err) => return IndexLoad::Corrupt(format!("unreadable: {err}")),
+    };
+
+    if bytes.len() < HEADER_LEN || !bytes.starts_with(INDEX_MAGIC) {
+        return IndexLoad::Corrupt("missing index header".to_string());
+    }
+    let mut offset = INDEX_MAGIC.len();
+    let mut take = |len: usize| {
+        let field = &bytes[offset..offset + len];
+        offset += len;
+        field
+    };
+    let version = u32::from_le_bytes(take(4).try_into().unwrap());
+    let tag = take(1)[0];
+    let length = u64::from_le_bytes(take(8).try_into().unwrap());
+    let checksum = take(CHECKSUM_LEN).to_vec();
+
+    if version != INDEX_FORMAT_VERSION {
+        return IndexLoad::VersionMismatch {
+            found: version,
+            expected: INDEX_FORMAT_VERSION,
+        };
+    }
+    if tag != kind.tag() {
+        return IndexLoad::Corrupt(format!("not a {:?} index", kind));
+    }
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() as u64 != length {
+        return IndexLoad::Corrupt(format!(
+            "truncated, expected {} bytes but found {}",
+            length,
+            payload.len()
+        ));
+    }
+    if Sha256::digest(payload).as_slice() != checksum.as_slice() {
+        return IndexLoad::Corrupt("checksum mismatch".to_string());
+    }
+
+    match serde_json::from_slice(payload) {
+        Ok(value) => IndexLoad::Loaded(value),
+        Err(err) => IndexLoad::Corrupt(format!("invalid payload: {err}")),
+    }
+}
+
+/// Progress of a background index rebuild
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexRebuildProgress {
+    pub kind: IndexKind,
+    pub done: usize,
+    pub total: usize,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+/// Reports rebuild progress to the user
+pub type IndexProgressCallback = Arc<dyn Fn(IndexRebuildProgress) + Send + Sync>;
+
+/// Load an index, or start rebuilding it in the background when the file is
+/// missing, damaged or from another format version
+///
+/// A damaged file is kept next to the index with a `.corrupt` suffix for
+/// inspection. While the rebuild runs the index is reported as degraded so
+/// retrieval falls back to a workspace scan. `rebuild` receives a function
+/// to report `(done, total)` progress and must write the new index itself.
+/// It runs as a [`JobScheduler`] job, within the background concurrency
+/// settings.
+pub fn load_or_rebuild<T, F>(
+    path: &Path,
+    kind: IndexKind,
+    tracker: Arc<DegradationTracker>,
+    progress: IndexProgressCallback,
+    rebuild: F,
+) -> (Option<T>, Option<JobHandle<()>>)
+where
+    T: DeserializeOwned,
+    F: FnOnce(&dyn Fn(usize, usize)) -> Result<()> + Send + 'static,
+{
+    let reason = match read_index(path, kind) {
+        IndexLoad::Loaded(value) => return (Some(value), None),
+        IndexLoad::Missing => None,
+        IndexLoad::VersionMismatch { found, expected } => {
+            tracing::info!(
+                "{:?} index has format version {}, expected {}, rebuilding",
+                kind,
+                found,
+                expected
+            );
+            None
+        }
+        IndexLoad::Incompatible(reason) => {
+            tracing::info!("{:?} index is outdated ({}), rebuilding", kind, reason);
+            None
+        }
+        IndexLoad::Corrupt(reason) => {
+            tracing::warn!("{:?} index is corrupt ({}), rebuilding", kind, reason);
+            quarantine(path);
+            Some(reason)
+        }
+    };
+
+    let subsystem = kind.retrieval_source().map(Subsystem::Index);
+    if let Some(subsystem) = &subsystem {
+        tracker.report(
+            subsystem.clone(),
+            reason.as_deref().unwrap_or("index is being rebuilt"),
+            "workspace scan",
+        );
+    }
+
+    let handle = JobScheduler::global().spawn(move || {
+        let report = |done: usize, total: usize| {
+            progress(IndexRebuildProgress {
+                kind,
+                done,
+                total,
+                finished: false,
+                error: None,
+            })
+        };
+        let result = rebuild(&report);
+        if let Err(err) = &result {
+            tracing::error!("Failed to rebuild {:?} index: {err:#}", kind);
+        } else if let Some(subsystem) = &subsystem {
+            tracker.recover(subsystem);
+        }
+        progress(IndexRebuildProgress {
+            kind,
+            done: 0,
+            total: 0,
+            finished: true,
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        });
+        result
+    });
+    (None, Some(handle))
+}
+
+/// Move a damaged index out of the way
+fn quarantine(path: &Path) {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".corrupt");
+    if let Err(err) = std::fs::rename(path, PathBuf::from(name)) {
+        tracing::warn!("Failed to move corrupt index {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_corruption_and_rebuilds() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(IndexKind::FileIndex.file_name());
+        write_index(&path, IndexKind::FileIndex, &vec!["a".to_string()]).unwrap();
+        assert_eq!(
+            read_index::<Vec<String>>(&path, IndexKind::FileIndex).loaded(),
+            Some(vec!["a".to_string()])
+        );
+        assert!(matches!(
+            read_index::<Vec<String>>(&path, IndexKind::VectorStore),
+            IndexLoad::Corrupt(_)
+        ));
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let tracker = Arc::new(DegradationTracker::new());
+        let (updates, receiver) = std::sync::mpsc::channel();
+        let updates = parking_lot::Mutex::new(updates);
+        let rebuild_path = path.clone();
+        let (loaded, handle) = load_or_rebuild::<Vec<String>, _>(
+            &path,
+            IndexKind::FileIndex,
+            tracker.clone(),
+            Arc::new(move |progress| {
+                let _ = updates.lock().send(progress);
+            }),
+            move |report| {
+                report(1, 1);
+                write_index(&rebuild_path, IndexKind::FileIndex, &vec!["b"])
+            },
+        );
+        assert!(loaded.is_none());
+        handle.unwrap().join().unwrap();
+
+        let progress: Vec<IndexRebuildProgress> = receiver.try_iter().collect();
+        assert!(progress.last().unwrap().finished);
+        assert!(tracker.status().is_empty());
+        assert!(dir.path().join("files.idx.corrupt").exists());
+        assert_eq!(
+            read_index::<Vec<String>>(&path, IndexKind::FileIndex).loaded(),
+            Some(vec!["b".to_string()])
+        );
+    }
+}
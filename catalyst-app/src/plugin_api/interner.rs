@@ -0,0 +1,314 @@
+//! Path Interning
+//!
+//! This module deduplicates the paths held by the context indexes. Every
+//! chunk, symbol definition and reference of a file points at one shared
+//! allocation instead of its own `PathBuf`, and structures that only need
+//! identity store a 4-byte [`PathId`]. On workspaces with 100k+ files and
+//! several chunks per file this is most of the indexes' path memory. The
+//! path watcher and the source control statuses share the same paths.
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+static GLOBAL_PATHS: Lazy<PathInterner> = Lazy::new(PathInterner::default);
+
+/// A path shared by every structure referring to the same file
+///
+/// Cloning is a reference count increment. Compares, hashes and derefs like
+/// a [`Path`], so maps keyed by it can be queried with a `&Path`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SharedPath(Arc<Path>);
+
+impl SharedPath {
+    /// Get the shared allocation for a path
+    pub fn new(path: &Path) -> Self {
+        PathInterner::global().intern(path)
+    }
+
+    /// Get the interned id of the path
+    pub fn id(&self) -> PathId {
+        PathInterner::global().id(self)
+    }
+
+    /// Copy the path into an owned `PathBuf`
+    pub fn to_path_buf(&self) -> PathBuf {
+        self.0.to_path_buf()
+    }
+}
+
+impl Deref for SharedPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for SharedPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Borrow<Path> for SharedPath {
+    fn borrow(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SharedPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl PartialEq<Path> for SharedPath {
+    fn eq(&self, other: &Path) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<&Path> for SharedPath {
+    fn eq(&self, other: &&Path) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<PathBuf> for SharedPath {
+    fn eq(&self, other: &PathBuf) -> bool {
+        *self.0 == *other.as_path()
+    }
+}
+
+impl From<&Path> for SharedPath {
+    fn from(path: &Path) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<PathBuf> for SharedPath {
+    fn from(path: PathBuf) -> Self {
+        Self::new(&path)
+    }
+}
+
+impl From<&str> for SharedPath {
+    fn from(path: &str) -> Self {
+        Self::new(Path::new(path))
+    }
+}
+
+impl Serialize for SharedPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        PathBuf::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// Compact identifier of an interned path
+///
+/// An id is only valid while a [`SharedPath`] of its path is alive; once
+/// the path is released its id may be given to another path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PathId(u32);
+
+/// Interned paths before the first sweep for released ones
+const MIN_SWEEP_LEN: usize = 1024;
+
+struct PathInternerInner {
+    ids: HashMap<Arc<Path>, PathId>,
+    /// Path of each id, `None` for released ids
+    paths: Vec<Option<Arc<Path>>>,
+    /// Released ids to reuse
+    free: Vec<PathId>,
+    /// Number of interned paths at which the next sweep runs
+    sweep_at: usize,
+}
+
+impl Default for PathInternerInner {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            paths: Vec::new(),
+            free: Vec::new(),
+            sweep_at: MIN_SWEEP_LEN,
+        }
+    }
+}
+
+impl PathInternerInner {
+    /// Release the paths only the table refers to
+    fn release_unused(&mut self) -> usize {
+        let Self {
+            ids, paths, free, ..
+        } = self;
+        let before = ids.len();
+        // The table holds two references, one in `ids` and one in `paths`
+        ids.retain(|path, id| {
+            if Arc::strong_count(path) > 2 {
+                return true;
+            }
+            paths[id.0 as usize] = None;
+            free.push(*id);
+            false
+        });
+        before - ids.len()
+    }
+}
+
+/// Table of interned paths
+///
+/// A path stays interned while a [`SharedPath`] of it is alive. Paths
+/// nobody refers to anymore, such as those of deleted files or of closed
+/// workspaces, are released whenever the table has doubled in size since
+/// the last sweep, or by [`PathInterner::release_unused`].
+#[derive(Default)]
+pub struct PathInterner {
+    inner: RwLock<PathInternerInner>,
+}
+
+impl PathInterner {
+    /// Get the interner shared by all indexes
+    pub fn global() -> &'static PathInterner {
+        &GLOBAL_PATHS
+    }
+
+    /// Get the shared allocation for a path, interning it if needed
+    pub fn intern(&self, path: &Path) -> SharedPath {
+        if let Some((path, _)) = self.inner.read().ids.get_key_value(path) {
+            return SharedPath(path.clone());
+        }
+        let mut inner = self.inner.write();
+        let id = Self::insert(&mut inner, path);
+        SharedPath(
+            inner.paths[id.0 as usize]
+                .clone()
+                .expect("interned path is present"),
+        )
+    }
+
+    /// Get the id of a path, interning it if needed
+    ///
+    /// The id is released with the path, so callers keep a [`SharedPath`]
+    /// of it for as long as they hold the id.
+    pub fn id(&self, path: &Path) -> PathId {
+        if let Some(id) = self.inner.read().ids.get(path) {
+            return *id;
+        }
+        Self::insert(&mut self.inner.write(), path)
+    }
+
+    fn insert(inner: &mut PathInternerInner, path: &Path) -> PathId {
+        // Another thread may have interned it between the two locks
+        if let Some(id) = inner.ids.get(path) {
+            return *id;
+        }
+        if inner.ids.len() >= inner.sweep_at {
+            inner.release_unused();
+            inner.sweep_at = (inner.ids.len() * 2).max(MIN_SWEEP_LEN);
+        }
+        let path: Arc<Path> = Arc::from(path);
+        let id = match inner.free.pop() {
+            Some(id) => {
+                inner.paths[id.0 as usize] = Some(path.clone());
+                id
+            }
+            None => {
+                inner.paths.push(Some(path.clone()));
+                PathId(inner.paths.len() as u32 - 1)
+            }
+        };
+        inner.ids.insert(path, id);
+        id
+    }
+
+    /// Get the path of an id
+    ///
+    /// Panics if the id was released.
+    pub fn resolve(&self, id: PathId) -> SharedPath {
+        SharedPath(
+            self.inner.read().paths[id.0 as usize]
+                .clone()
+                .expect("path id was released"),
+        )
+    }
+
+    /// Release the paths no [`SharedPath`] refers to anymore, returning
+    /// how many were released
+    pub fn release_unused(&self) -> usize {
+        self.inner.write().release_unused()
+    }
+
+    /// Get the number of interned paths
+    pub fn len(&self) -> usize {
+        self.inner.read().ids.len()
+    }
+
+    /// Check if no path has been interned
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interned_paths_share_one_allocation() {
+        let interner = PathInterner::default();
+        let a = interner.intern(Path::new("src/main.rs"));
+        let b = interner.intern(&PathBuf::from("src/main.rs"));
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(interner.len(), 1);
+
+        let id = interner.id(Path::new("src/lib.rs"));
+        assert_eq!(interner.resolve(id), Path::new("src/lib.rs"));
+        assert_ne!(id, interner.id(&a));
+
+        let mut paths = HashMap::new();
+        paths.insert(a, 1);
+        assert_eq!(paths.get(Path::new("src/main.rs")), Some(&1));
+    }
+
+    #[test]
+    fn test_unused_paths_are_released() {
+        let interner = PathInterner::default();
+        let kept = interner.intern(Path::new("src/kept.rs"));
+        let removed = interner.intern(Path::new("src/removed.rs"));
+        let removed_id = interner.id(&removed);
+        assert_eq!(interner.release_unused(), 0);
+
+        drop(removed);
+        assert_eq!(interner.release_unused(), 1);
+        assert_eq!(interner.len(), 1);
+        assert_eq!(interner.intern(Path::new("src/kept.rs")), kept);
+
+        // The released id is reused for the next path
+        let added = interner.intern(Path::new("src/added.rs"));
+        assert_eq!(interner.id(&added), removed_id);
+        assert_eq!(interner.resolve(removed_id), Path::new("src/added.rs"));
+    }
+
+    #[test]
+    fn test_interning_sweeps_released_paths() {
+        let interner = PathInterner::default();
+        for index in 0..MIN_SWEEP_LEN * 3 {
+            interner.intern(&PathBuf::from(format!("src/{index}.rs")));
+        }
+        assert!(interner.len() <= MIN_SWEEP_LEN);
+        assert!(interner.inner.read().paths.len() <= MIN_SWEEP_LEN + 1);
+    }
+}
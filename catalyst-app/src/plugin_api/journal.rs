@@ -0,0 +1,592 @@
+//! Journaled Storage
+//!
+//! This module is the storage layer of small, write-heavy state stores such
+//! as memories, conversations and panel state. A store is a snapshot file
+//! plus a write-ahead log next to it (`<file>.wal`): every change is
+//! appended to the log as a checksummed, sequence-numbered record before it
+//! is applied in memory, and the log is periodically folded into a new
+//! snapshot. An abrupt kill can at worst lose the record being written,
+//! which is detected by its checksum and dropped on the next open; it can't
+//! leave a half-written snapshot behind.
+//!
+//! A store is open in one process at a time: opening it locks a
+//! `<file>.lock` next to it, and another process opening it meanwhile
+//! fails instead of interleaving its records with ours. Stores opened more
+//! than once in the same process share the lock.
+//!
+//! Snapshots record the format version of their state. Opening a store
+//! written in an older format upgrades the snapshot and any logged changes
+//! with the state's [`Migrations`], after backing both files up.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{Migrations, backup_before_migration};
+
+/// Length and checksum prefix of every log record
+const RECORD_HEADER_LEN: usize = 4 + 8;
+
+/// Locks of the stores open in this process, by lock file
+static STORE_LOCKS: Lazy<Mutex<HashMap<PathBuf, Weak<File>>>> =
+    Lazy::new(Default::default);
+
+/// State kept by a journaled store
+///
+/// Changes are expressed as operations so they can be logged and replayed.
+pub trait JournalState:
+    Default + Serialize + DeserializeOwned + Send + Sync
+{
+    type Op: Serialize + DeserializeOwned;
+
+    /// Apply a change; must be deterministic since it is replayed on open
+    fn apply(&mut self, op: &Self::Op);
+
+    /// Migrations from older formats of the state and its operations
+    fn migrations() -> Migrations {
+        Migrations::new(std::any::type_name::<Self>(), 0)
+    }
+}
+
+/// When log writes are flushed to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsyncPolicy {
+    /// After every change; nothing acknowledged is ever lost
+    Always,
+    /// At most once per interval; a crash loses at most the last interval
+    Interval { millis: u64 },
+    /// Left to the operating system
+    Never,
+}
+
+/// Tuning of a journaled store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalOptions {
+    pub fsync: FsyncPolicy,
+    /// Number of logged changes after which the log is compacted into a
+    /// new snapshot
+    pub compact_after: usize,
+}
+
+impl Default for JournalOptions {
+    fn default() -> Self {
+        Self {
+            fsync: FsyncPolicy::Always,
+            compact_after: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<S> {
+    #[serde(default)]
+    format_version: u32,
+    /// Sequence number of the last change included in the snapshot
+    seq: u64,
+    state: S,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record<Op> {
+    seq: u64,
+    op: Op,
+}
+
+struct Log {
+    file: File,
+    /// Length of the log up to the last complete record
+    len: u64,
+    seq: u64,
+    records: usize,
+    last_sync: Instant,
+}
+
+/// State store backed by a snapshot and a write-ahead log
+pub struct JournaledStore<S: JournalState> {
+    path: PathBuf,
+    options: JournalOptions,
+    format_version: u32,
+    state: RwLock<S>,
+    log: Mutex<Log>,
+    /// Held while the store is open, released when the last store of the
+    /// file in this process is dropped
+    _lock: Arc<File>,
+}
+
+impl<S: JournalState> JournaledStore<S> {
+    /// Open a store, replaying the log over the last snapshot
+    ///
+    /// A snapshot written by a plain JSON store of the same state, without
+    /// a journal, is read as the starting state. Stores in an older format
+    /// are migrated and compacted; stores in a newer format are refused, and
+    /// so are stores another process has open.
+    pub fn open(path: &Path, options: JournalOptions) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let lock = lock_store(path)?;
+        let migrations = S::migrations();
+        let snapshot = read_snapshot(path)?;
+        let is_new = snapshot.is_none();
+        let Snapshot {
+            format_version,
+            mut seq,
+            state,
+        } = match snapshot {
+            Some(snapshot) => snapshot,
+            None => Snapshot {
+                format_version: migrations.current(),
+                seq: 0,
+                state: serde_json::to_value(S::default())?,
+            },
+        };
+        migrations.check(format_version, path)?;
+
+        let wal_path = wal_path(path);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&wal_path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut offset = 0;
+        let mut pending = Vec::new();
+        while let Some((record, len)) =
+            decode_record::<serde_json::Value>(&bytes[offset..])
+        {
+            // Records already folded into the snapshot are left over from a
+            // compaction interrupted before the log was truncated
+            if record.seq > seq {
+                pending.push(record);
+            }
+            offset += len;
+        }
+        if offset < bytes.len() {
+            tracing::warn!(
+                "Dropping {} bytes of incomplete journal records in {}",
+                bytes.len() - offset,
+                wal_path.display()
+            );
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        let migrate = format_version < migrations.current();
+        let mut backup = None;
+        let (state, applied) = if migrate {
+            backup = backup_before_migration(path, format_version)?;
+            backup_before_migration(&wal_path, format_version)?;
+            migrations.migrate_document(format_version, state)?
+        } else {
+            (state, Vec::new())
+        };
+        let mut state: S = serde_json::from_value(state)?;
+        let records = pending.len();
+        for record in pending {
+            let op = if migrate {
+                migrations.migrate_op(format_version, record.op)?
+            } else {
+                record.op
+            };
+            state.apply(&serde_json::from_value(op)?);
+            seq = record.seq;
+        }
+
+        let store = Self {
+            path: path.to_path_buf(),
+            options,
+            format_version: migrations.current(),
+            state: RwLock::new(state),
+            log: Mutex::new(Log {
+                file,
+                len: offset as u64,
+                seq,
+                records,
+                last_sync: Instant::now(),
+            }),
+            _lock: lock,
+        };
+        // Migrated changes can only be replayed by folding them into a
+        // snapshot, and a new store gets one so its format is on disk
+        if migrate || is_new {
+            store.compact()?;
+        }
+        if migrate {
+            migrations.record(path, &applied, backup.as_deref());
+        }
+        Ok(store)
+    }
+
+    /// Lock the state for reading
+    pub fn read(&self) -> RwLockReadGuard<'_, S> {
+        self.state.read()
+    }
+
+    /// Log and apply a change
+    pub fn apply(&self, op: S::Op) -> Result<()> {
+        self.transact(|_| Ok((Some(op), ())))
+    }
+
+    /// Decide on a change from the current state, then log and apply it
+    ///
+    /// `f` runs with the store locked, so the change can depend on the
+    /// state, e.g. to allocate an id. Returning no operation changes
+    /// nothing.
+    pub fn transact<R>(
+        &self,
+        f: impl FnOnce(&S) -> Result<(Option<S::Op>, R)>,
+    ) -> Result<R> {
+        let mut state = self.state.write();
+        let (op, result) = f(&state)?;
+        let Some(op) = op else {
+            return Ok(result);
+        };
+
+        let mut log = self.log.lock();
+        let seq = log.seq + 1;
+        let payload = serde_json::to_vec(&Record { seq, op: &op })?;
+        let record = encode_record(&payload);
+        let sync = match self.options.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval { millis } => {
+                log.last_sync.elapsed() >= Duration::from_millis(millis)
+            }
+            FsyncPolicy::Never => false,
+        };
+        let written = log
+            .file
+            .write_all(&record)
+            .and_then(|()| if sync { log.file.sync_data() } else { Ok(()) });
+        if let Err(err) = written {
+            // The change isn't applied, so its record must not be replayed
+            // or followed by later records; if it can't be cut off, at least
+            // no later record reuses its sequence number
+            if log.file.set_len(log.len).is_err() {
+                log.seq = seq;
+            }
+            return Err(err.into());
+        }
+        if sync {
+            log.last_sync = Instant::now();
+        }
+        log.len += record.len() as u64;
+        log.seq = seq;
+        log.records += 1;
+
+        // Only applied once logged, so memory never runs ahead of disk
+        state.apply(&op);
+
+        if log.records >= self.options.compact_after {
+            if let Err(err) = self.compact_locked(&state, &mut log) {
+                tracing::error!(
+                    "Failed to compact journal of {}: {err:#}",
+                    self.path.display()
+                );
+            }
+        }
+        Ok(result)
+    }
+
+    /// Flush pending log writes to disk
+    pub fn sync(&self) -> Result<()> {
+        let mut log = self.log.lock();
+        log.file.sync_data()?;
+        log.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Fold the log into a new snapshot and empty it
+    pub fn compact(&self) -> Result<()> {
+        let state = self.state.read();
+        let mut log = self.log.lock();
+        self.compact_locked(&state, &mut log)
+    }
+
+    fn compact_locked(&self, state: &S, log: &mut Log) -> Result<()> {
+        let snapshot = Snapshot {
+            format_version: self.format_version,
+            seq: log.seq,
+            state,
+        };
+        write_atomic(&self.path, &serde_json::to_vec(&snapshot)?)?;
+        // A crash before this truncation only leaves records that the
+        // snapshot's sequence number makes replay skip
+        log.file.set_len(0)?;
+        log.file.sync_all()?;
+        log.len = 0;
+        log.records = 0;
+        Ok(())
+    }
+}
+
+/// Write a file atomically: the new content is fully written and synced to
+/// a temporary file that then replaces the target
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path {}", path.display()))?;
+    std::fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(bytes)?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+fn wal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".wal");
+    PathBuf::from(name)
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Lock a store for this process, sharing the lock of a store already
+/// open in it
+fn lock_store(path: &Path) -> Result<Arc<File>> {
+    let lock_path = lock_path(path);
+    let mut locks = STORE_LOCKS.lock();
+    if let Some(lock) = locks.get(&lock_path).and_then(Weak::upgrade) {
+        return Ok(lock);
+    }
+    let in_use = || anyhow::anyhow!("{} is open in another process", path.display());
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // Not shared, so no other process can open it until it is closed
+        options.share_mode(0);
+    }
+    let file = match options.open(&lock_path) {
+        Ok(file) => file,
+        #[cfg(windows)]
+        Err(err) if err.raw_os_error() == Some(32) => return Err(in_use()),
+        Err(err) => return Err(err.into()),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        // Released by the system when the file is closed, even on a crash
+        let locked =
+            unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if locked != 0 {
+            return Err(in_use());
+        }
+    }
+    let lock = Arc::new(file);
+    locks.retain(|_, lock| lock.strong_count() > 0);
+    locks.insert(lock_path, Arc::downgrade(&lock));
+    Ok(lock)
+}
+
+fn read_snapshot(path: &Path) -> Result<Option<Snapshot<serde_json::Value>>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let value: serde_json::Value = serde_json::from_slice(&bytes)?;
+    let is_snapshot = value.as_object().is_some_and(|object| {
+        object.contains_key("seq")
+            && object.contains_key("state")
+            && object.keys().all(|key| {
+                matches!(key.as_str(), "format_version" | "seq" | "state")
+            })
+    });
+    if is_snapshot {
+        Ok(Some(serde_json::from_value(value)?))
+    } else {
+        Ok(Some(Snapshot {
+            format_version: 0,
+            seq: 0,
+            state: value,
+        }))
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 8] {
+    Sha256::digest(payload)[..8].try_into().unwrap()
+}
+
+fn encode_record(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&checksum(payload));
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Decode the record at the start of `bytes`, with its encoded length
+///
+/// Returns `None` for a truncated or damaged record, which ends replay.
+fn decode_record<Op: DeserializeOwned>(bytes: &[u8]) -> Option<(Record<Op>, usize)> {
+    if bytes.len() < RECORD_HEADER_LEN {
+        return None;
+    }
+    let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    let payload = bytes.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + len)?;
+    if checksum(payload) != bytes[4..RECORD_HEADER_LEN] {
+        return None;
+    }
+    let record = serde_json::from_slice(payload).ok()?;
+    Some((record, RECORD_HEADER_LEN + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Serialize, Deserialize)]
+    struct Counter {
+        values: Vec<u32>,
+    }
+
+    impl JournalState for Counter {
+        type Op = u32;
+
+        fn apply(&mut self, op: &u32) {
+            self.values.push(*op);
+        }
+    }
+
+    #[test]
+    fn test_recovers_from_torn_writes_and_interrupted_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counter.json");
+        let options = JournalOptions {
+            fsync: FsyncPolicy::Always,
+            compact_after: 3,
+        };
+
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        for value in 1..=4 {
+            store.apply(value).unwrap();
+        }
+        // The third change compacted, the fourth is only in the log
+        assert!(path.exists());
+        let log = std::fs::read(wal_path(&path)).unwrap();
+        drop(store);
+
+        // Simulate a kill halfway through appending a record
+        let mut torn = log.clone();
+        torn.extend_from_slice(&encode_record(b"{\"seq\":5,\"op\":5}")[..10]);
+        std::fs::write(wal_path(&path), &torn).unwrap();
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        assert_eq!(store.read().values, vec![1, 2, 3, 4]);
+        assert_eq!(std::fs::read(wal_path(&path)).unwrap(), log);
+
+        // Simulate a kill between writing a snapshot and truncating the log
+        store.compact().unwrap();
+        std::fs::write(wal_path(&path), &log).unwrap();
+        store.apply(5).unwrap();
+        drop(store);
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        assert_eq!(store.read().values, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_store_is_locked_while_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counter.json");
+        let options = JournalOptions::default();
+
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        // Another open in this process shares the lock
+        let again = JournaledStore::<Counter>::open(&path, options).unwrap();
+        drop(again);
+        store.apply(1).unwrap();
+
+        // Another process can't lock the store while it is open
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let other = File::open(lock_path(&path)).unwrap();
+            let locked = unsafe {
+                libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB)
+            };
+            assert_ne!(locked, 0);
+        }
+        drop(store);
+        assert!(
+            STORE_LOCKS
+                .lock()
+                .get(&lock_path(&path))
+                .is_some_and(|lock| lock.upgrade().is_none())
+        );
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        assert_eq!(store.read().values, vec![1]);
+    }
+
+    #[derive(Default, Serialize, Deserialize)]
+    struct Labels {
+        labels: Vec<String>,
+    }
+
+    impl JournalState for Labels {
+        type Op = String;
+
+        fn apply(&mut self, op: &String) {
+            self.labels.push(op.clone());
+        }
+
+        fn migrations() -> Migrations {
+            Migrations::new("labels", 1).step_with_ops(
+                0,
+                "store values as labels",
+                |document| {
+                    let labels: Vec<_> = document["values"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|value| serde_json::Value::from(value.to_string()))
+                        .collect();
+                    Ok(serde_json::json!({ "labels": labels }))
+                },
+                |op| Ok(serde_json::Value::from(op.to_string())),
+            )
+        }
+    }
+
+    #[test]
+    fn test_migrates_snapshot_and_logged_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("labels.json");
+        let options = JournalOptions::default();
+
+        let store = JournaledStore::<Counter>::open(&path, options).unwrap();
+        store.apply(1).unwrap();
+        store.compact().unwrap();
+        store.apply(2).unwrap();
+        drop(store);
+
+        let store = JournaledStore::<Labels>::open(&path, options).unwrap();
+        assert_eq!(store.read().labels, vec!["1", "2"]);
+        assert!(dir.path().join("labels.json.v0.bak").exists());
+        assert!(dir.path().join("labels.json.wal.v0.bak").exists());
+        assert!(std::fs::read(wal_path(&path)).unwrap().is_empty());
+        drop(store);
+
+        // A build that only knows the old format refuses the new one
+        assert!(JournaledStore::<Counter>::open(&path, options).is_err());
+    }
+}
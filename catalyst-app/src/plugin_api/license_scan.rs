@@ -0,0 +1,919 @@
+//! License Scan
+//!
+//! This module reports the licenses of a workspace's dependencies. The
+//! packages come from the lockfiles read by
+//! [`read_workspace_packages`](crate::plugin_api::read_workspace_packages)
+//! and their licenses from the installed copies: the Cargo registry cache,
+//! `node_modules` and the `.venv` of the workspace. Nothing is fetched, so
+//! a package that isn't installed shows up as unknown.
+//!
+//! Licenses are read as SPDX expressions and judged against the workspace's
+//! `.catalyst/license-policy.toml`, which lists allowed and denied licenses
+//! and packages to leave out. Of the alternatives of an `OR` the most
+//! permissive counts, of the parts of an `AND` the most restrictive. The
+//! report is shown in the licenses panel and the agent consults it with the
+//! `check_license` tool.
+
+use anyhow::{Context, Result, anyhow};
+use catalyst_core::directory::Directory;
+use floem::View;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    LockedPackage, PackageEcosystem, PanelCommand, PanelCommandResult,
+    SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition, TEAM_CONFIG_DIR,
+    ToolCall, ToolDefinition, ToolEffect, read_workspace_packages, text_panel_view,
+};
+
+/// Name of the tool the assistant checks a library's license with
+pub const CHECK_LICENSE_TOOL_NAME: &str = "check_license";
+
+/// File of the license policy in the team configuration directory
+pub const LICENSE_POLICY_FILE: &str = "license-policy.toml";
+
+const PERMISSIVE: &[&str] = &[
+    "0BSD",
+    "Apache-2.0",
+    "BlueOak-1.0.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSL-1.0",
+    "CC-BY-4.0",
+    "CC0-1.0",
+    "ISC",
+    "MIT",
+    "MIT-0",
+    "NCSA",
+    "PSF-2.0",
+    "Python-2.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "WTFPL",
+    "X11",
+    "Zlib",
+];
+const WEAK_COPYLEFT: &[&str] = &[
+    "CDDL-1.0", "CDDL-1.1", "CPL-1.0", "EPL-1.0", "EPL-2.0", "LGPL-2.0", "LGPL-2.1",
+    "LGPL-3.0", "MPL-1.1", "MPL-2.0",
+];
+const STRONG_COPYLEFT: &[&str] = &[
+    "AGPL-1.0",
+    "AGPL-3.0",
+    "CC-BY-SA-4.0",
+    "EUPL-1.1",
+    "EUPL-1.2",
+    "GPL-1.0",
+    "GPL-2.0",
+    "GPL-3.0",
+    "OSL-3.0",
+    "SSPL-1.0",
+];
+
+/// Common license names that aren't SPDX identifiers
+const LICENSE_ALIASES: &[(&str, &str)] = &[
+    ("Apache 2.0", "Apache-2.0"),
+    ("Apache License 2.0", "Apache-2.0"),
+    ("Apache License, Version 2.0", "Apache-2.0"),
+    ("Apache Software License", "Apache-2.0"),
+    ("ISC License (ISCL)", "ISC"),
+    ("MIT License", "MIT"),
+];
+
+/// How much a license asks of the projects using it, least first
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseCategory {
+    Permissive,
+    /// Changes to the library itself must be shared
+    WeakCopyleft,
+    /// Programs using the library must be shared under the same license
+    StrongCopyleft,
+    Unknown,
+}
+
+impl LicenseCategory {
+    /// Get the category of an SPDX license identifier
+    pub fn of(identifier: &str) -> Self {
+        let base = identifier
+            .trim_end_matches('+')
+            .trim_end_matches("-only")
+            .trim_end_matches("-or-later");
+        let is = |list: &[&str]| list.iter().any(|id| id.eq_ignore_ascii_case(base));
+        if is(PERMISSIVE) {
+            Self::Permissive
+        } else if is(WEAK_COPYLEFT) {
+            Self::WeakCopyleft
+        } else if is(STRONG_COPYLEFT) {
+            Self::StrongCopyleft
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn is_copyleft(&self) -> bool {
+        matches!(self, Self::WeakCopyleft | Self::StrongCopyleft)
+    }
+}
+
+/// A parsed SPDX license expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseExpr {
+    /// A license identifier; exceptions given with `WITH` are dropped
+    License(String),
+    And(Vec<LicenseExpr>),
+    Or(Vec<LicenseExpr>),
+}
+
+impl LicenseExpr {
+    /// Parse an SPDX expression, also taking the `MIT/Apache-2.0` form of
+    /// older Cargo manifests
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = expression.trim();
+        if let Some((_, id)) = LICENSE_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(expression))
+        {
+            return Ok(Self::License(id.to_string()));
+        }
+        let spaced = expression
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .replace('/', " OR ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        let mut position = 0;
+        let expr = parse_or(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(anyhow!(
+                "Unexpected '{}' in license '{}'",
+                tokens[position],
+                expression
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Get the license identifiers, in order
+    pub fn identifiers(&self) -> Vec<&str> {
+        match self {
+            Self::License(id) => vec![id.as_str()],
+            Self::And(parts) | Self::Or(parts) => {
+                parts.iter().flat_map(LicenseExpr::identifiers).collect()
+            }
+        }
+    }
+
+    /// Judge the expression by judging its licenses: the least of the
+    /// alternatives of an `OR`, the greatest of the parts of an `AND`
+    pub fn evaluate<T: Ord>(&self, judge: &impl Fn(&str) -> T) -> T {
+        match self {
+            Self::License(id) => judge(id),
+            Self::And(parts) => parts
+                .iter()
+                .map(|part| part.evaluate(judge))
+                .max()
+                .expect("expressions have parts"),
+            Self::Or(parts) => parts
+                .iter()
+                .map(|part| part.evaluate(judge))
+                .min()
+                .expect("expressions have parts"),
+        }
+    }
+
+    pub fn category(&self) -> LicenseCategory {
+        self.evaluate(&LicenseCategory::of)
+    }
+}
+
+fn parse_or(tokens: &[&str], position: &mut usize) -> Result<LicenseExpr> {
+    let mut parts = vec![parse_and(tokens, position)?];
+    while tokens
+        .get(*position)
+        .is_some_and(|token| token.eq_ignore_ascii_case("OR"))
+    {
+        *position += 1;
+        parts.push(parse_and(tokens, position)?);
+    }
+    Ok(if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        LicenseExpr::Or(parts)
+    })
+}
+
+fn parse_and(tokens: &[&str], position: &mut usize) -> Result<LicenseExpr> {
+    let mut parts = vec![parse_license(tokens, position)?];
+    while tokens
+        .get(*position)
+        .is_some_and(|token| token.eq_ignore_ascii_case("AND"))
+    {
+        *position += 1;
+        parts.push(parse_license(tokens, position)?);
+    }
+    Ok(if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        LicenseExpr::And(parts)
+    })
+}
+
+fn parse_license(tokens: &[&str], position: &mut usize) -> Result<LicenseExpr> {
+    let token = tokens
+        .get(*position)
+        .ok_or_else(|| anyhow!("License expression ends early"))?;
+    *position += 1;
+    if *token == "(" {
+        let expr = parse_or(tokens, position)?;
+        if tokens.get(*position) != Some(&")") {
+            return Err(anyhow!("Missing ')' in license expression"));
+        }
+        *position += 1;
+        return Ok(expr);
+    }
+    let is_operator = ["AND", "OR", "WITH", ")"]
+        .iter()
+        .any(|operator| token.eq_ignore_ascii_case(operator));
+    if is_operator {
+        return Err(anyhow!("Expected a license before '{}'", token));
+    }
+    if tokens
+        .get(*position)
+        .is_some_and(|next| next.eq_ignore_ascii_case("WITH"))
+    {
+        // Skip the exception, which only loosens the license
+        *position += 2;
+    }
+    Ok(LicenseExpr::License(token.to_string()))
+}
+
+/// Licenses a workspace accepts, read from `.catalyst/license-policy.toml`
+///
+/// Entries match SPDX identifiers without regard to case; one ending in `*`
+/// matches every identifier starting with the rest, e.g. `GPL-*`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LicensePolicy {
+    /// Licenses accepted; when set, any other license needs a review
+    pub allow: Vec<String>,
+    /// Licenses never accepted
+    pub deny: Vec<String>,
+    /// Packages left out of the checks, e.g. ones with a separate agreement
+    pub ignore_packages: Vec<String>,
+    /// Whether strong copyleft licenses not allowed explicitly need a review
+    pub review_copyleft: bool,
+}
+
+impl Default for LicensePolicy {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            ignore_packages: Vec::new(),
+            review_copyleft: true,
+        }
+    }
+}
+
+impl LicensePolicy {
+    /// Load the policy of a workspace, the default one if it has none
+    pub fn load(workspace_root: &Path) -> Result<Self> {
+        let path = workspace_root
+            .join(TEAM_CONFIG_DIR)
+            .join(LICENSE_POLICY_FILE);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Invalid license policy '{}'", path.display()))
+    }
+
+    /// Judge a package's license expression, `None` if it is unknown
+    pub fn judge(&self, package: &str, license: Option<&str>) -> LicenseVerdict {
+        if self
+            .ignore_packages
+            .iter()
+            .any(|ignored| ignored == package)
+        {
+            return LicenseVerdict::Allowed;
+        }
+        match license.map(LicenseExpr::parse) {
+            Some(Ok(expr)) => expr.evaluate(&|id| self.judge_license(id)),
+            Some(Err(_)) | None => LicenseVerdict::NeedsReview,
+        }
+    }
+
+    fn judge_license(&self, identifier: &str) -> LicenseVerdict {
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => identifier
+                        .to_ascii_lowercase()
+                        .starts_with(&prefix.to_ascii_lowercase()),
+                    None => pattern.eq_ignore_ascii_case(identifier),
+                })
+        };
+        if matches(&self.deny) {
+            return LicenseVerdict::Denied;
+        }
+        if matches(&self.allow) {
+            return LicenseVerdict::Allowed;
+        }
+        match LicenseCategory::of(identifier) {
+            _ if !self.allow.is_empty() => LicenseVerdict::NeedsReview,
+            LicenseCategory::Unknown => LicenseVerdict::NeedsReview,
+            LicenseCategory::StrongCopyleft if self.review_copyleft => {
+                LicenseVerdict::NeedsReview
+            }
+            _ => LicenseVerdict::Allowed,
+        }
+    }
+}
+
+/// What the policy says of a license, most acceptable first
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseVerdict {
+    Allowed,
+    NeedsReview,
+    Denied,
+}
+
+impl LicenseVerdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Allowed => "allowed",
+            Self::NeedsReview => "needs review",
+            Self::Denied => "denied",
+        }
+    }
+}
+
+/// The license of one dependency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageLicense {
+    pub package: LockedPackage,
+    /// License expression as declared by the package
+    pub license: Option<String>,
+    pub category: LicenseCategory,
+    pub verdict: LicenseVerdict,
+}
+
+impl PackageLicense {
+    fn describe(&self) -> String {
+        format!(
+            "{} {} ({})",
+            self.package.name,
+            self.package.version,
+            self.license.as_deref().unwrap_or("unknown license")
+        )
+    }
+}
+
+/// Licenses of all the dependencies of a workspace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LicenseReport {
+    pub packages: Vec<PackageLicense>,
+}
+
+impl LicenseReport {
+    /// Find the license of a dependency by name
+    pub fn find(&self, name: &str) -> Option<&PackageLicense> {
+        self.packages
+            .iter()
+            .find(|license| license.package.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn with_verdict(&self, verdict: LicenseVerdict) -> Vec<&PackageLicense> {
+        self.packages
+            .iter()
+            .filter(|license| license.verdict == verdict)
+            .collect()
+    }
+
+    /// Count the packages under each license expression
+    pub fn license_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for license in &self.packages {
+            let name = license.license.as_deref().unwrap_or("unknown");
+            *counts.entry(name.to_string()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Render the report shown in the panel
+    pub fn render(&self) -> String {
+        if self.packages.is_empty() {
+            return "No dependencies found in the workspace lockfiles".to_string();
+        }
+        let denied = self.with_verdict(LicenseVerdict::Denied);
+        let review = self.with_verdict(LicenseVerdict::NeedsReview);
+        let copyleft: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|license| license.category.is_copyleft())
+            .collect();
+        let mut lines = vec![format!(
+            "{} dependencies: {} denied, {} to review, {} copyleft",
+            self.packages.len(),
+            denied.len(),
+            review.len(),
+            copyleft.len()
+        )];
+        for (title, licenses) in [
+            ("Denied", denied),
+            ("Needs review", review),
+            ("Copyleft", copyleft),
+        ] {
+            if !licenses.is_empty() {
+                lines.push(String::new());
+                lines.push(title.to_string());
+                lines.extend(
+                    licenses
+                        .iter()
+                        .map(|license| format!("  {}", license.describe())),
+                );
+            }
+        }
+        lines.push(String::new());
+        lines.push("Licenses".to_string());
+        for (license, count) in self.license_counts() {
+            lines.push(format!("  {license}: {count}"));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Reads the licenses of a workspace's dependencies and keeps the last report
+pub struct LicenseScanner {
+    workspace_root: PathBuf,
+    /// Cargo's home, holding the registry cache
+    cargo_home: Option<PathBuf>,
+    report: Mutex<Option<Arc<LicenseReport>>>,
+}
+
+impl LicenseScanner {
+    pub fn new(workspace_root: &Path) -> Self {
+        let cargo_home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Directory::home_dir().map(|home| home.join(".cargo")));
+        Self::with_cargo_home(workspace_root, cargo_home)
+    }
+
+    pub fn with_cargo_home(
+        workspace_root: &Path,
+        cargo_home: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            workspace_root: workspace_root.to_path_buf(),
+            cargo_home,
+            report: Mutex::new(None),
+        }
+    }
+
+    pub fn policy(&self) -> Result<LicensePolicy> {
+        LicensePolicy::load(&self.workspace_root)
+    }
+
+    /// Scan the dependencies again
+    pub fn scan(&self) -> Result<Arc<LicenseReport>> {
+        let policy = self.policy()?;
+        let packages = read_workspace_packages(&self.workspace_root)?
+            .into_iter()
+            .map(|package| {
+                let license = self.installed_license(&package);
+                let category = license
+                    .as_deref()
+                    .and_then(|license| LicenseExpr::parse(license).ok())
+                    .map_or(LicenseCategory::Unknown, |expr| expr.category());
+                PackageLicense {
+                    verdict: policy.judge(&package.name, license.as_deref()),
+                    package,
+                    license,
+                    category,
+                }
+            })
+            .collect();
+        let report = Arc::new(LicenseReport { packages });
+        *self.report.lock() = Some(report.clone());
+        Ok(report)
+    }
+
+    /// Get the last report, scanning if there is none
+    pub fn report(&self) -> Result<Arc<LicenseReport>> {
+        if let Some(report) = self.report.lock().clone() {
+            return Ok(report);
+        }
+        self.scan()
+    }
+
+    /// Read the license a package declares in its installed copy
+    pub fn installed_license(&self, package: &LockedPackage) -> Option<String> {
+        let license = match package.ecosystem {
+            PackageEcosystem::Cargo => self.cargo_license(package),
+            PackageEcosystem::Npm => self.npm_license(package),
+            PackageEcosystem::PyPI => self.python_license(package),
+        };
+        license.filter(|license| !license.trim().is_empty())
+    }
+
+    fn cargo_license(&self, package: &LockedPackage) -> Option<String> {
+        let sources = self.cargo_home.as_ref()?.join("registry").join("src");
+        let dir_name = format!("{}-{}", package.name, package.version);
+        std::fs::read_dir(sources)
+            .ok()?
+            .flatten()
+            .find_map(|index| {
+                let manifest = std::fs::read_to_string(
+                    index.path().join(&dir_name).join("Cargo.toml"),
+                )
+                .ok()?;
+                let manifest: toml::Value = toml::from_str(&manifest).ok()?;
+                let package = manifest.get("package")?;
+                match package.get("license").and_then(|license| license.as_str()) {
+                    Some(license) => Some(license.to_string()),
+                    None => package
+                        .get("license-file")
+                        .map(|_| "LicenseRef-license-file".to_string()),
+                }
+            })
+    }
+
+    fn npm_license(&self, package: &LockedPackage) -> Option<String> {
+        let manifest = self
+            .workspace_root
+            .join("node_modules")
+            .join(&package.name)
+            .join("package.json");
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(manifest).ok()?).ok()?;
+        let license_type = |license: &serde_json::Value| {
+            license
+                .as_str()
+                .or_else(|| license.get("type")?.as_str())
+                .map(str::to_string)
+        };
+        // Old packages list their licenses as objects in `licenses`
+        match manifest.get("license") {
+            Some(license) => license_type(license),
+            None => {
+                let licenses: Vec<String> = manifest
+                    .get("licenses")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(license_type)
+                    .collect();
+                (!licenses.is_empty()).then(|| licenses.join(" OR "))
+            }
+        }
+    }
+
+    fn python_license(&self, package: &LockedPackage) -> Option<String> {
+        let lib = self.workspace_root.join(".venv").join("lib");
+        let dist_info = format!(
+            "{}-{}.dist-info",
+            package.name.replace('-', "_").to_lowercase(),
+            package.version
+        );
+        let metadata =
+            std::fs::read_dir(lib).ok()?.flatten().find_map(|python| {
+                let site_packages = python.path().join("site-packages");
+                std::fs::read_dir(site_packages)
+                    .ok()?
+                    .flatten()
+                    .find(|entry| {
+                        entry.file_name().to_string_lossy().to_lowercase()
+                            == dist_info
+                    })
+                    .and_then(|entry| {
+                        std::fs::read_to_string(entry.path().join("METADATA")).ok()
+                    })
+            })?;
+        let field = |name: &str| {
+            metadata
+                .lines()
+                // The headers end at the first blank line
+                .take_while(|line| !line.is_empty())
+                .find_map(|line| line.strip_prefix(name))
+                .map(|value| value.trim().to_string())
+        };
+        field("License-Expression:").or_else(|| {
+            // A short `License` is a name; long ones hold the whole text
+            field("License:").filter(|license| license.len() <= 64)
+        })
+    }
+
+    /// Definition of the `check_license` tool offered to the assistant
+    pub fn tool_definition() -> ToolDefinition {
+        ToolDefinition {
+            name: CHECK_LICENSE_TOOL_NAME.to_string(),
+            description: "Check whether a library's license is acceptable \
+                          under the workspace's license policy. Dependencies \
+                          of the workspace are looked up by name; for other \
+                          libraries pass their SPDX license expression."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "package": {
+                        "type": "string",
+                        "description": "Name of the library"
+                    },
+                    "license": {
+                        "type": "string",
+                        "description": "SPDX license expression, e.g. \
+                                        \"MIT OR Apache-2.0\""
+                    }
+                },
+                "required": ["package"]
+            }),
+            effect: ToolEffect::ReadOnly,
+        }
+    }
+
+    /// Execute a `check_license` tool call from the assistant
+    pub fn handle_tool_call(&self, call: &ToolCall) -> Result<String> {
+        if call.name != CHECK_LICENSE_TOOL_NAME {
+            return Err(anyhow!("Unknown license tool '{}'", call.name));
+        }
+        let package = call
+            .arguments
+            .get("package")
+            .and_then(|package| package.as_str())
+            .ok_or_else(|| anyhow!("Missing 'package' argument"))?;
+        let license = match call.arguments.get("license").and_then(|l| l.as_str()) {
+            Some(license) => license.to_string(),
+            None => {
+                let report = self.report()?;
+                let Some(found) = report.find(package) else {
+                    return Ok(format!(
+                        "{package} is not a dependency of the workspace; pass its \
+                         license to check it against the policy"
+                    ));
+                };
+                match &found.license {
+                    Some(license) => license.clone(),
+                    None => {
+                        return Ok(format!(
+                            "{} is a dependency but its license is unknown; it \
+                             needs a review",
+                            found.describe()
+                        ));
+                    }
+                }
+            }
+        };
+        let expr = LicenseExpr::parse(&license)?;
+        let policy = self.policy()?;
+        let verdict = policy.judge(package, Some(&license));
+        let category = match expr.category() {
+            LicenseCategory::Permissive => "a permissive license",
+            LicenseCategory::WeakCopyleft => {
+                "a weak copyleft license: changes to the library itself must be \
+                 shared"
+            }
+            LicenseCategory::StrongCopyleft => {
+                "a strong copyleft license: programs using the library must be \
+                 shared under the same license"
+            }
+            LicenseCategory::Unknown => "a license not known to the scanner",
+        };
+        Ok(format!(
+            "{package} is licensed under {license}, {category}. The workspace \
+             policy says: {}.",
+            verdict.label()
+        ))
+    }
+}
+
+/// Panel showing the license report of the workspace
+pub struct LicensePanel {
+    scanner: Arc<LicenseScanner>,
+}
+
+impl LicensePanel {
+    pub const ID: &'static str = "catalyst.licenses";
+
+    pub fn new(scanner: Arc<LicenseScanner>) -> Self {
+        Self { scanner }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        match command.command_id.as_str() {
+            "report" => Ok(serde_json::to_value(&*self.scanner.report()?)?),
+            "scan" => Ok(serde_json::to_value(&*self.scanner.scan()?)?),
+            "policy" => Ok(serde_json::to_value(self.scanner.policy()?)?),
+            other => Err(anyhow!("Unknown license panel command '{}'", other)),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for LicensePanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Licenses".to_string(),
+            description: "Licenses of the workspace's dependencies".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(250),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let scanner = self.scanner.clone();
+        text_panel_view(move || match scanner.report() {
+            Ok(report) => report.render(),
+            Err(err) => format!("Failed to scan licenses: {err:#}"),
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_license_scan() {
+        let expr =
+            LicenseExpr::parse("(MIT OR Apache-2.0) AND GPL-3.0-only").unwrap();
+        assert_eq!(expr.identifiers(), ["MIT", "Apache-2.0", "GPL-3.0-only"]);
+        assert_eq!(expr.category(), LicenseCategory::StrongCopyleft);
+        let expr = LicenseExpr::parse("MIT/Apache-2.0").unwrap();
+        assert_eq!(expr.category(), LicenseCategory::Permissive);
+        assert_eq!(
+            LicenseExpr::parse(
+                "GPL-2.0-or-later WITH Classpath-exception-2.0 or MIT"
+            )
+            .unwrap()
+            .category(),
+            LicenseCategory::Permissive
+        );
+        assert!(LicenseExpr::parse("MIT AND").is_err());
+        assert!(LicenseExpr::parse("(MIT").is_err());
+
+        let root = tempfile::tempdir().unwrap();
+        let cargo_home = tempfile::tempdir().unwrap();
+        write(
+            &root.path().join("Cargo.lock"),
+            r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "readline", "mystery"]
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "readline"
+version = "2.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "mystery"
+version = "0.1.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        );
+        let index = cargo_home.path().join("registry/src/index.crates.io-1");
+        write(
+            &index.join("serde-1.0.0/Cargo.toml"),
+            "[package]\nname = \"serde\"\nlicense = \"MIT OR Apache-2.0\"\n",
+        );
+        write(
+            &index.join("readline-2.0.0/Cargo.toml"),
+            "[package]\nname = \"readline\"\nlicense = \"GPL-3.0-only\"\n",
+        );
+        write(
+            &root.path().join("package-lock.json"),
+            r#"{ "packages": { "node_modules/left-pad": { "version": "1.3.0" } } }"#,
+        );
+        write(
+            &root.path().join("node_modules/left-pad/package.json"),
+            r#"{ "licenses": [{ "type": "WTFPL" }] }"#,
+        );
+
+        let scanner = LicenseScanner::with_cargo_home(
+            root.path(),
+            Some(cargo_home.path().to_path_buf()),
+        );
+        let report = scanner.scan().unwrap();
+        let verdicts: Vec<_> = report
+            .packages
+            .iter()
+            .map(|license| (license.package.name.as_str(), license.verdict))
+            .collect();
+        assert_eq!(
+            verdicts,
+            [
+                ("mystery", LicenseVerdict::NeedsReview),
+                ("readline", LicenseVerdict::NeedsReview),
+                ("serde", LicenseVerdict::Allowed),
+                ("left-pad", LicenseVerdict::Allowed),
+            ]
+        );
+        assert!(report.render().starts_with(
+            "4 dependencies: 0 denied, 2 to review, 1 copyleft\n\nNeeds review\n  \
+             mystery 0.1.0 (unknown license)\n  readline 2.0.0 (GPL-3.0-only)"
+        ));
+
+        write(
+            &root.path().join(TEAM_CONFIG_DIR).join(LICENSE_POLICY_FILE),
+            "deny = [\"GPL-*\", \"AGPL-*\"]\nignore-packages = [\"mystery\"]\n",
+        );
+        let report = scanner.scan().unwrap();
+        assert_eq!(
+            report.find("readline").unwrap().verdict,
+            LicenseVerdict::Denied
+        );
+        assert_eq!(report.with_verdict(LicenseVerdict::NeedsReview).len(), 0);
+
+        let check = |arguments: serde_json::Value| {
+            scanner.handle_tool_call(&ToolCall {
+                id: "1".to_string(),
+                name: CHECK_LICENSE_TOOL_NAME.to_string(),
+                arguments,
+            })
+        };
+        assert_eq!(
+            check(serde_json::json!({ "package": "serde" })).unwrap(),
+            "serde is licensed under MIT OR Apache-2.0, a permissive license. \
+             The workspace policy says: allowed."
+        );
+        assert!(
+            check(serde_json::json!({ "package": "ghostscript", "license": "AGPL-3.0-only" }))
+                .unwrap()
+                .ends_with("The workspace policy says: denied.")
+        );
+        assert!(
+            check(serde_json::json!({ "package": "unknown-lib" }))
+                .unwrap()
+                .contains("not a dependency")
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Log Control
+//!
+//! This module changes what the log file records while the IDE runs. The
+//! levels come from the `[log]` section of the settings and are applied
+//! again whenever that section changes; the control socket can override
+//! them until it does, which is how a user turns on `trace` for one module
+//! to capture a bug without restarting.
+//!
+//! It also creates the correlation ids that tie together the log lines of
+//! one request. A [`correlation_span`] puts the id on every line logged
+//! inside it, on the threads the request hops to as well when they enter
+//! the span they were spawned from.
+
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing_subscriber::filter::Targets;
+
+use crate::config::log::LogConfig;
+
+static LOG_CONTROLLER: OnceCell<LogController> = OnceCell::new();
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(1);
+
+type ApplyFilter = Box<dyn Fn(Targets) -> Result<()> + Send + Sync>;
+
+/// Applies log levels to the running log file
+pub struct LogController {
+    config: Mutex<LogConfig>,
+    apply: ApplyFilter,
+}
+
+impl LogController {
+    /// Create a controller that hands every new filter to `apply`, usually
+    /// the reload handle of the log file layer
+    pub fn new(
+        config: LogConfig,
+        apply: impl Fn(Targets) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            config: Mutex::new(config),
+            apply: Box::new(apply),
+        }
+    }
+
+    /// Install the controller used by the whole application
+    pub fn install(controller: LogController) -> Result<()> {
+        LOG_CONTROLLER
+            .set(controller)
+            .map_err(|_| anyhow::anyhow!("The log controller is already installed"))
+    }
+
+    /// Get the controller installed at startup
+    pub fn global() -> Option<&'static LogController> {
+        LOG_CONTROLLER.get()
+    }
+
+    /// Get the levels currently applied
+    pub fn config(&self) -> LogConfig {
+        self.config.lock().clone()
+    }
+
+    /// Replace all levels, as when the settings changed
+    pub fn apply_config(&self, config: LogConfig) -> Result<()> {
+        let mut current = self.config.lock();
+        (self.apply)(config.targets()?)?;
+        *current = config;
+        Ok(())
+    }
+
+    /// Change the level of a module, or the default level for `None`
+    pub fn set_level(&self, module: Option<&str>, level: &str) -> Result<LogConfig> {
+        let mut current = self.config.lock();
+        let mut config = current.clone();
+        config.set_level(module, level)?;
+        (self.apply)(config.targets()?)?;
+        *current = config.clone();
+        tracing::info!(
+            "Log level of {} set to {}",
+            module.unwrap_or("all modules"),
+            level
+        );
+        Ok(config)
+    }
+}
+
+/// Create an id for the log lines of one request
+pub fn new_correlation_id() -> String {
+    format!(
+        "{:x}-{}",
+        std::process::id(),
+        NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Create a span that adds `correlation_id` to every line logged inside it
+pub fn correlation_span(kind: &'static str, correlation_id: &str) -> tracing::Span {
+    tracing::info_span!("request", kind, correlation_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::Level;
+
+    #[test]
+    fn test_log_controller() {
+        let applied: Arc<Mutex<Option<Targets>>> = Arc::default();
+        let controller = LogController::new(LogConfig::default(), {
+            let applied = applied.clone();
+            move |targets| {
+                *applied.lock() = Some(targets);
+                Ok(())
+            }
+        });
+
+        let config = controller
+            .set_level(Some("catalyst_proxy::plugin"), "trace")
+            .unwrap();
+        assert_eq!(config.modules["catalyst_proxy::plugin"], "trace");
+        let targets = applied.lock().take().unwrap();
+        assert!(targets.would_enable("catalyst_proxy::plugin::lsp", &Level::TRACE));
+        assert!(!targets.would_enable("catalyst_proxy::buffer", &Level::TRACE));
+        assert!(targets.would_enable("catalyst_proxy::buffer", &Level::DEBUG));
+        assert!(!targets.would_enable("alacritty_terminal", &Level::DEBUG));
+
+        // An invalid level changes nothing
+        assert!(controller.set_level(None, "loud").is_err());
+        assert!(applied.lock().is_none());
+        assert_eq!(controller.config().level, "info");
+
+        controller.set_level(None, "warn").unwrap();
+        let targets = applied.lock().take().unwrap();
+        assert!(!targets.would_enable("alacritty_terminal", &Level::INFO));
+
+        assert_ne!(new_correlation_id(), new_correlation_id());
+    }
+}
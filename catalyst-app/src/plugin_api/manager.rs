@@ -3,30 +3,89 @@
 //! This module manages the loading and lifecycle of all plugins in Catalyst IDE.
 
 use anyhow::Result;
+use catalyst_core::directory::Directory;
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::plugin_api::{
-    AiAssistantPlugin, McpServerRegistry, SidebarPanelRegistry,
+    AiAssistantPlugin, CatalystIgnoreConfig, CommandResolver, ConcurrencySettings,
+    FILESYSTEM_SERVER_ID, FilesystemMcpServer, FocusMode, FocusModeSettings,
+    FocusStatus, GIT_SERVER_ID, GitMcpServer, IdleSuspendSettings, JobScheduler,
+    McpBatchResult, McpOperation, McpSampler, McpServerPlugin, McpServerRegistry,
+    MemoryPressure, NetworkPolicy, ProcessRegistry, SamplingApprover,
+    SamplingPolicy, ShutdownCoordinator, ShutdownPhase, ShutdownSettings,
+    SidebarPanelRegistry, SlashCommandRegistry, SpeechProvider, SpeechSettings,
+    StdioMcpServer, TeamConfig, ToolUsageStore, WebhookSettings,
+    configured_speech_provider, user_mcp_settings_path,
 };
 
+static PLUGIN_MANAGER: OnceCell<RwLock<PluginManager>> = OnceCell::new();
+
+/// File in the config directory with the [`PluginConfig`]
+pub const PLUGIN_SETTINGS_FILE: &str = "plugins.toml";
+
 /// Main plugin manager for Catalyst IDE
 pub struct PluginManager {
     ai_assistants: HashMap<String, Arc<dyn AiAssistantPlugin>>,
+    /// Assistant answering requests that don't pick one, such as MCP
+    /// sampling
+    active_ai_assistant: Option<String>,
+    sampling_approver: Option<Arc<dyn SamplingApprover>>,
     sidebar_registry: SidebarPanelRegistry,
     mcp_registry: McpServerRegistry,
+    /// PID files of the server processes, if the data directory is
+    /// available
+    processes: Option<ProcessRegistry>,
+    slash_commands: SlashCommandRegistry,
     config: PluginConfig,
 }
 
 /// Configuration for the plugin system
+///
+/// Read from [`PLUGIN_SETTINGS_FILE`]; settings missing from the file keep
+/// their defaults.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PluginConfig {
     pub enabled_plugins: Vec<String>,
     pub plugin_directories: Vec<String>,
     pub auto_load_plugins: bool,
     pub max_plugins: usize,
     pub plugin_timeout_seconds: u64,
+    /// Thread pools and low-power behavior of background work
+    #[serde(default)]
+    pub concurrency: ConcurrencySettings,
+    /// Deadlines of the shutdown sequence
+    #[serde(default)]
+    pub shutdown: ShutdownSettings,
+    /// When MCP servers without tool calls are suspended
+    #[serde(default)]
+    pub idle_suspend: IdleSuspendSettings,
+    /// When MCP servers may ask the active AI assistant for completions
+    #[serde(default)]
+    pub sampling: SamplingPolicy,
+    /// How long focus mode pauses background activity, and what it pauses
+    #[serde(default)]
+    pub focus_mode: FocusModeSettings,
+    /// Ignore rules of the built-in MCP servers
+    #[serde(default)]
+    pub ignore: CatalystIgnoreConfig,
+    /// Local HTTP receiver for CI and other external systems, off unless
+    /// enabled
+    #[serde(default)]
+    pub webhook: WebhookSettings,
+    /// Network access of AI features, e.g. whether answers are read aloud
+    /// by a speech API or the platform's text-to-speech
+    #[serde(default)]
+    pub network_policy: NetworkPolicy,
+    /// Reading assistant answers aloud, off unless enabled
+    #[serde(default)]
+    pub speech: SpeechSettings,
 }
 
 impl Default for PluginConfig {
@@ -40,10 +99,57 @@ fn default() -> Self {
             auto_load_plugins: true,
             max_plugins: 50,
             plugin_timeout_seconds: 30,
+            concurrency: ConcurrencySettings::default(),
+            shutdown: ShutdownSettings::default(),
+            idle_suspend: IdleSuspendSettings::default(),
+            sampling: SamplingPolicy::default(),
+            focus_mode: FocusModeSettings::default(),
+            ignore: CatalystIgnoreConfig::default(),
+            webhook: WebhookSettings::default(),
+            network_policy: NetworkPolicy::default(),
+            speech: SpeechSettings::default(),
         }
     }
 }
 
+impl PluginConfig {
+    /// Get the settings file in the config directory
+    pub fn default_path() -> Option<PathBuf> {
+        Directory::config_directory().map(|dir| dir.join(PLUGIN_SETTINGS_FILE))
+    }
+
+    /// Load the settings in the config directory, see [`Self::load`]
+    pub fn load_default() -> Self {
+        Self::default_path()
+            .map(|path| Self::load(&path))
+            .unwrap_or_default()
+    }
+
+    /// Load the settings from a TOML file
+    ///
+    /// A missing file gives the defaults. So does an invalid one, after
+    /// logging why, so a typo doesn't keep the plugins from loading.
+    pub fn load(path: &Path) -> Self {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Self::default();
+            }
+            Err(err) => {
+                tracing::error!("Failed to read {}: {err}", path.display());
+                return Self::default();
+            }
+        };
+        toml::from_str(&content).unwrap_or_else(|err| {
+            tracing::error!(
+                "Invalid plugin settings in {}, using the defaults: {err}",
+                path.display()
+            );
+            Self::default()
+        })
+    }
+}
+
 /// Information about a loaded plugin
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginInfo {
@@ -68,27 +174,199 @@ pub enum PluginType {
 impl PluginManager {
     /// Create a new plugin manager
     pub fn new(config: PluginConfig) -> Self {
-        Self {
+        JobScheduler::global().configure(config.concurrency.clone());
+        let mcp_registry = match ToolUsageStore::open_default() {
+            Ok(usage) => McpServerRegistry::new().with_usage_store(Arc::new(usage)),
+            Err(err) => {
+                tracing::error!("Failed to open the MCP tool usage: {err:#}");
+                McpServerRegistry::new()
+            }
+        };
+        mcp_registry.set_idle_suspend(config.idle_suspend.clone());
+        let processes = match ProcessRegistry::open_default() {
+            Ok(processes) => Some(processes),
+            Err(err) => {
+                tracing::error!("Failed to open the MCP process registry: {err:#}");
+                None
+            }
+        };
+        let manager = Self {
             ai_assistants: HashMap::new(),
+            active_ai_assistant: None,
+            sampling_approver: None,
             sidebar_registry: SidebarPanelRegistry::new(),
-            mcp_registry: McpServerRegistry::new(),
+            mcp_registry,
+            processes,
+            slash_commands: SlashCommandRegistry::with_builtin_commands(),
             config,
+        };
+        manager.register_builtin_servers();
+        manager
+    }
+
+    /// Install the plugin manager used by the whole application
+    pub fn install(manager: PluginManager) -> Result<()> {
+        PLUGIN_MANAGER
+            .set(RwLock::new(manager))
+            .map_err(|_| anyhow::anyhow!("The plugin manager is already installed"))
+    }
+
+    /// Get the plugin manager installed at startup
+    pub fn global() -> Option<&'static RwLock<PluginManager>> {
+        PLUGIN_MANAGER.get()
+    }
+
+    /// Register the MCP servers built into Catalyst, which start with the
+    /// other auto-start servers
+    fn register_builtin_servers(&self) {
+        let ignore = &self.config.ignore;
+        let servers = [
+            (
+                FILESYSTEM_SERVER_ID,
+                Box::new(FilesystemMcpServer::new(ignore.clone()))
+                    as Box<dyn McpServerPlugin>,
+            ),
+            (GIT_SERVER_ID, Box::new(GitMcpServer::new(ignore.clone()))),
+        ];
+        for (id, server) in servers {
+            if let Err(err) =
+                self.mcp_registry.register_server(id.to_string(), server)
+            {
+                tracing::error!("Failed to register the {id} MCP server: {err:#}");
+            }
         }
     }
 
     /// Initialize the plugin manager
-    pub fn initialize(&mut self) -> Result<()> {
+    ///
+    /// Auto-start MCP servers are started in the background; the returned
+    /// operation completes once all of them are up or have failed.
+    pub fn initialize(&mut self) -> Result<McpOperation<McpBatchResult>> {
         tracing::info!("Initializing plugin manager");
 
         if self.config.auto_load_plugins {
             self.load_all_plugins()?;
         }
 
+        // Servers of a session that crashed may still be running; they are
+        // given the grace period to exit, so this doesn't hold up startup
+        if let Some(processes) = self.processes.clone() {
+            let spawned = std::thread::Builder::new()
+                .name("CleanupOrphans".to_string())
+                .spawn(move || {
+                    let cleaned = processes.cleanup_orphans();
+                    if !cleaned.is_empty() {
+                        tracing::info!(
+                            "Cleaned up {} orphaned MCP servers",
+                            cleaned.len()
+                        );
+                    }
+                });
+            if let Err(err) = spawned {
+                tracing::error!("Failed to clean up orphaned MCP servers: {err}");
+            }
+        }
+
         // Start auto-start MCP servers
-        self.mcp_registry.start_auto_start_servers()?;
+        let started = self.mcp_registry.start_auto_start_servers();
+        self.mcp_registry.start_idle_suspension();
 
         tracing::info!("Plugin manager initialized successfully");
-        Ok(())
+        Ok(started)
+    }
+
+    /// Register the MCP servers configured for a workspace, by the team and
+    /// in the user's `mcp_servers.toml`, and start the auto-start ones
+    ///
+    /// Servers already registered, e.g. by another workspace, are kept.
+    /// They run as child processes spawned with
+    /// [`ManagedChild::spawn_server`](crate::plugin_api::ManagedChild::spawn_server).
+    pub fn register_workspace_servers(
+        &self,
+        workspace_root: &Path,
+    ) -> Result<McpOperation<McpBatchResult>> {
+        let Some(processes) = &self.processes else {
+            return Err(anyhow::anyhow!(
+                "MCP servers can't be spawned without the process registry"
+            ));
+        };
+        let team = TeamConfig::load_with_user_settings(
+            workspace_root,
+            user_mcp_settings_path().as_deref(),
+        )?;
+        let resolver = CommandResolver::from_env();
+        let mut auto_start = Vec::new();
+        for info in team.mcp_servers() {
+            if self.mcp_registry.get_server(&info.id).is_some() {
+                continue;
+            }
+            let id = info.id.clone();
+            let starts = info.auto_start;
+            let server =
+                StdioMcpServer::new(info, resolver.clone(), processes.clone());
+            if let Err(err) = self
+                .mcp_registry
+                .register_server(id.clone(), Box::new(server))
+            {
+                tracing::error!("Failed to register MCP server '{id}': {err:#}");
+                continue;
+            }
+            if starts {
+                auto_start.push(id);
+            }
+        }
+        Ok(self.mcp_registry.start_servers(auto_start))
+    }
+
+    /// Start the keep-warm MCP servers in the background
+    ///
+    /// Call once the first frame is painted, so spawning and initializing
+    /// them doesn't delay the window. Nothing is warmed up while focus mode
+    /// is on.
+    pub fn warm_up_servers(&self) -> McpOperation<McpBatchResult> {
+        if FocusMode::global().is_active() {
+            tracing::info!("Focus mode is on; not warming up MCP servers");
+            return McpOperation::ready(McpBatchResult::default());
+        }
+        self.mcp_registry.warm_up_servers()
+    }
+
+    /// Pause background agent activity for `duration`, the configured
+    /// focus mode duration if none is given
+    ///
+    /// MCP servers that aren't kept warm are suspended in the background if
+    /// the settings say so; focus mode resumes everything by itself once
+    /// the time runs out.
+    pub fn start_focus_mode(&self, duration: Option<Duration>) -> FocusStatus {
+        let settings = &self.config.focus_mode;
+        let status = FocusMode::global()
+            .start(duration.unwrap_or_else(|| settings.duration()));
+        if settings.suspend_servers {
+            let _ = self.mcp_registry.suspend_non_essential_servers();
+        }
+        status
+    }
+
+    /// End focus mode early, resuming background activity
+    pub fn stop_focus_mode(&self) -> FocusStatus {
+        FocusMode::global().stop()
+    }
+
+    /// Start the servers of a batch again whose failure was transient, such
+    /// as a timeout or a dropped connection
+    ///
+    /// Servers that failed for good, like ones answering with invalid
+    /// messages, are left stopped. Completes at once if there is nothing to
+    /// retry.
+    pub fn retry_failed_servers(
+        &self,
+        batch: &McpBatchResult,
+    ) -> McpOperation<McpBatchResult> {
+        if batch.retryable.is_empty() {
+            return McpOperation::ready(McpBatchResult::default());
+        }
+        tracing::info!("Retrying MCP servers {:?}", batch.retryable);
+        self.mcp_registry.start_servers(batch.retryable.clone())
     }
 
     /// Load all plugins from configured directories
@@ -120,6 +398,8 @@ pub fn load_plugins_from_directory(&mut self, directory: &str) -> Result<()> {
     }
 
     /// Register an AI assistant plugin
+    ///
+    /// The first assistant registered becomes the active one.
     pub fn register_ai_assistant(
         &mut self,
         id: String,
@@ -133,10 +413,59 @@ pub fn register_ai_assistant(
         }
 
         tracing::info!("Registering AI assistant plugin: {}", id);
-        self.ai_assistants.insert(id, plugin);
+        self.ai_assistants.insert(id.clone(), plugin);
+        if self.active_ai_assistant.is_none() {
+            self.set_active_ai_assistant(&id)?;
+        }
         Ok(())
     }
 
+    /// Get the assistant answering requests that don't pick one
+    pub fn active_ai_assistant(&self) -> Option<Arc<dyn AiAssistantPlugin>> {
+        self.active_ai_assistant
+            .as_ref()
+            .and_then(|id| self.get_ai_assistant(id))
+    }
+
+    /// Make a registered assistant the active one
+    pub fn set_active_ai_assistant(&mut self, id: &str) -> Result<()> {
+        if !self.ai_assistants.contains_key(id) {
+            return Err(anyhow::anyhow!(
+                "AI assistant with id '{}' is not registered",
+                id
+            ));
+        }
+        self.active_ai_assistant = Some(id.to_string());
+        self.update_sampler();
+        Ok(())
+    }
+
+    /// Ask the user through `approver` before a server that isn't trusted
+    /// samples
+    pub fn set_sampling_approver(&mut self, approver: Arc<dyn SamplingApprover>) {
+        self.sampling_approver = Some(approver);
+        self.update_sampler();
+    }
+
+    /// Change when MCP servers may sample
+    pub fn set_sampling_policy(&mut self, policy: SamplingPolicy) {
+        self.config.sampling = policy;
+        self.update_sampler();
+    }
+
+    /// Point the sampling requests of the MCP servers at the active
+    /// assistant
+    fn update_sampler(&self) {
+        let sampler = self.active_ai_assistant().map(|assistant| {
+            let sampler = McpSampler::new(assistant, self.config.sampling.clone());
+            match &self.sampling_approver {
+                Some(approver) => Arc::new(sampler.with_approver(approver.clone())),
+                None => Arc::new(sampler),
+            }
+        });
+        self.mcp_registry.set_sampler(sampler);
+    }
+
     /// Get an AI assistant plugin by id
     pub fn get_ai_assistant(&self, id: &str) -> Option<Arc<dyn AiAssistantPlugin>> {
         self.ai_assistants.get(id).cloned()
@@ -147,24 +476,30 @@ pub fn get_ai_assistant_ids(&self) -> Vec<String> {
         self.ai_assistants.keys().cloned().collect()
     }
 
-    /// Get sidebar panel registry
+    /// Get sidebar panel registry; clone it to share with other threads
     pub fn get_sidebar_registry(&self) -> &SidebarPanelRegistry {
         &self.sidebar_registry
     }
 
-    /// Get mutable sidebar panel registry
-    pub fn get_sidebar_registry_mut(&mut self) -> &mut SidebarPanelRegistry {
-        &mut self.sidebar_registry
-    }
-
-    /// Get MCP server registry
+    /// Get MCP server registry; clone it to share with other threads
     pub fn get_mcp_registry(&self) -> &McpServerRegistry {
         &self.mcp_registry
     }
 
-    /// Get mutable MCP server registry
-    pub fn get_mcp_registry_mut(&mut self) -> &mut McpServerRegistry {
-        &mut self.mcp_registry
+    /// Get the provider reading answers aloud, `None` unless speech is
+    /// enabled and available
+    pub fn speech_provider(&self) -> Option<Arc<dyn SpeechProvider>> {
+        configured_speech_provider(&self.config.speech, self.config.network_policy)
+    }
+
+    pub fn speech_settings(&self) -> &SpeechSettings {
+        &self.config.speech
+    }
+
+    /// Get the chat slash-command registry; plugins register their
+    /// commands on it
+    pub fn get_slash_commands(&self) -> &SlashCommandRegistry {
+        &self.slash_commands
     }
 
     /// Get information about all loaded plugins
@@ -239,17 +574,79 @@ pub fn disable_plugin(&mut self, plugin_id: &str) -> Result<()> {
     }
 
     /// Shutdown all plugins
-    pub fn shutdown(&mut self) -> Result<()> {
+    ///
+    /// MCP servers are stopped in the background; the returned operation
+    /// completes once all of them have stopped.
+    pub fn shutdown(&mut self) -> McpOperation<McpBatchResult> {
         tracing::info!("Shutting down plugin manager");
 
         // Stop all MCP servers
-        self.mcp_registry.stop_all_servers()?;
+        let stopped = self.mcp_registry.stop_all_servers();
 
         // Clear all registries
         self.ai_assistants.clear();
+        self.active_ai_assistant = None;
+        self.update_sampler();
 
-        tracing::info!("Plugin manager shutdown complete");
-        Ok(())
+        stopped
+    }
+
+    /// Build the shutdown sequence of the plugin system
+    ///
+    /// Panel state is written to `panel_state_path` when given. Other
+    /// subsystems add their own steps to the returned coordinator before it
+    /// is run.
+    pub fn shutdown_sequence(
+        &self,
+        panel_state_path: Option<&Path>,
+    ) -> ShutdownCoordinator {
+        let settings = self.config.shutdown.clone();
+        let mut coordinator = ShutdownCoordinator::new(settings.clone());
+
+        // Tool calls are only flushed once a second while running
+        if let Some(usage) = self.mcp_registry.usage_store().cloned() {
+            coordinator
+                .add_step(ShutdownPhase::Flush, "tool usage", move || usage.sync());
+        }
+
+        if let Some(path) = panel_state_path {
+            let panels = self.sidebar_registry.clone();
+            let path: PathBuf = path.to_path_buf();
+            coordinator.add_step(
+                ShutdownPhase::PersistState,
+                "panel state",
+                move || panels.save_states(&path),
+            );
+        }
+
+        let servers = self.mcp_registry.clone();
+        // Servers stop in parallel, so the step needs one server deadline
+        // plus some slack, not one per server
+        let server_timeout = settings.server_stop_timeout();
+        coordinator.add_step_with_timeout(
+            ShutdownPhase::StopServers,
+            "MCP servers",
+            server_timeout + Duration::from_millis(500),
+            move || {
+                servers
+                    .stop_all_servers_within(server_timeout)
+                    .wait()?
+                    .into_result()
+            },
+        );
+
+        coordinator.add_step(ShutdownPhase::CancelJobs, "background jobs", || {
+            let cancelled = JobScheduler::global().cancel_pending();
+            tracing::info!("Cancelled {} queued background jobs", cancelled);
+            Ok(())
+        });
+        coordinator
+    }
+
+    /// Ask plugins to release memory they can rebuild on demand
+    pub fn on_low_memory(&self, pressure: MemoryPressure) {
+        tracing::debug!("Releasing plugin memory ({:?} pressure)", pressure);
+        self.sidebar_registry.notify_low_memory(pressure);
     }
 
     /// Get plugin configuration
@@ -259,6 +656,141 @@ pub fn get_config(&self) -> &PluginConfig {
 
     /// Update plugin configuration
     pub fn update_config(&mut self, config: PluginConfig) {
+        JobScheduler::global().configure(config.concurrency.clone());
+        self.mcp_registry
+            .set_idle_suspend(config.idle_suspend.clone());
         self.config = config;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        PanelCommand, PanelCommandResult, SidebarPanelInfo, SidebarPanelPlugin,
+        SidebarPosition, text_panel_view,
+    };
+    use floem::View;
+    use parking_lot::Mutex;
+
+    struct CachingPanel {
+        released: Arc<Mutex<Vec<MemoryPressure>>>,
+    }
+
+    impl SidebarPanelPlugin for CachingPanel {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn panel_info(&self) -> SidebarPanelInfo {
+            SidebarPanelInfo {
+                id: "caching".to_string(),
+                name: "Caching".to_string(),
+                description: String::new(),
+                icon: None,
+                position: SidebarPosition::Left,
+                default_visible: false,
+                resizable: true,
+                minimum_width: None,
+                maximum_width: None,
+            }
+        }
+
+        fn create_view(&self) -> Box<dyn View> {
+            text_panel_view(String::new)
+        }
+
+        fn on_activate(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_deactivate(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_state(&self) -> serde_json::Value {
+            serde_json::Value::Null
+        }
+
+        fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_command(
+            &mut self,
+            _command: PanelCommand,
+        ) -> Result<PanelCommandResult> {
+            Ok(PanelCommandResult {
+                success: true,
+                result: None,
+                error: None,
+            })
+        }
+
+        fn on_low_memory(&mut self, pressure: MemoryPressure) {
+            self.released.lock().push(pressure);
+        }
+    }
+
+    #[test]
+    fn test_low_memory_reaches_shown_panels() {
+        let manager = PluginManager::new(PluginConfig::default());
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let handle = manager
+            .get_sidebar_registry()
+            .register_panel(
+                "caching".to_string(),
+                Box::new(CachingPanel {
+                    released: released.clone(),
+                }),
+            )
+            .unwrap();
+
+        // Panels never shown hold nothing to release
+        manager.on_low_memory(MemoryPressure::Moderate);
+        assert!(released.lock().is_empty());
+
+        handle.set_visible(true).unwrap();
+        manager.on_low_memory(MemoryPressure::Moderate);
+        manager.on_low_memory(MemoryPressure::Critical);
+        assert_eq!(
+            *released.lock(),
+            vec![MemoryPressure::Moderate, MemoryPressure::Critical]
+        );
+    }
+
+    #[test]
+    fn test_plugin_config_keeps_defaults_for_missing_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PLUGIN_SETTINGS_FILE);
+        std::fs::write(
+            &path,
+            "max_plugins = 5\n\n[shutdown]\nglobal_timeout_ms = 2000\n",
+        )
+        .unwrap();
+
+        let config = PluginConfig::load(&path);
+        assert_eq!(config.max_plugins, 5);
+        assert_eq!(config.shutdown.global_timeout_ms, 2000);
+        assert_eq!(
+            config.shutdown.step_timeout_ms,
+            ShutdownSettings::default().step_timeout_ms
+        );
+        assert!(config.auto_load_plugins);
+    }
+
+    #[test]
+    fn test_missing_or_invalid_plugin_config_gives_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PLUGIN_SETTINGS_FILE);
+        let defaults = PluginConfig::default();
+        assert_eq!(PluginConfig::load(&path).max_plugins, defaults.max_plugins);
+
+        std::fs::write(&path, "max_plugins = \"many\"\n").unwrap();
+        assert_eq!(PluginConfig::load(&path).max_plugins, defaults.max_plugins);
+    }
+}
@@ -0,0 +1,327 @@
+//! MCP Server Catalog
+//!
+//! This module lists the MCP servers Catalyst knows how to set up without
+//! the user writing a server definition: how each one is started, which
+//! runtime it needs on the `PATH` and which environment variables hold its
+//! credentials. Enabling an entry adds it to the workspace's
+//! `.catalyst/local/settings.toml` with `${NAME}` references for the
+//! credentials, which resolve from the environment or from secrets stored
+//! with [`CredentialStore::set_env`](crate::plugin_api::CredentialStore::set_env).
+//!
+//! The filesystem and git servers aren't listed: they are built in, see
+//! [`FilesystemMcpServer`](crate::plugin_api::FilesystemMcpServer) and
+//! [`GitMcpServer`](crate::plugin_api::GitMcpServer), and registered by the
+//! [`PluginManager`](crate::plugin_api::PluginManager).
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::plugin_api::{
+    CommandResolver, McpCallLimits, McpResourceLimits, TeamMcpServer,
+};
+
+/// Program a catalog server is started with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum McpRuntime {
+    /// Node.js packages run with `npx`
+    Node,
+    /// Python packages run with `uvx`
+    Python,
+}
+
+impl McpRuntime {
+    pub const ALL: [McpRuntime; 2] = [McpRuntime::Node, McpRuntime::Python];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Node => "Node.js",
+            Self::Python => "uv",
+        }
+    }
+
+    /// Get the executable that starts the servers
+    pub fn executable(&self) -> &'static str {
+        match self {
+            Self::Node => "npx",
+            Self::Python => "uvx",
+        }
+    }
+}
+
+/// Problems keeping a picked server from starting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerHealthCheck {
+    pub server_id: String,
+    pub missing_programs: Vec<String>,
+    pub missing_credentials: Vec<String>,
+}
+
+impl ServerHealthCheck {
+    pub fn passed(&self) -> bool {
+        self.missing_programs.is_empty() && self.missing_credentials.is_empty()
+    }
+}
+
+/// A server of the catalog
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct McpCatalogEntry {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub runtime: McpRuntime,
+    pub package: &'static str,
+    pub args: &'static [&'static str],
+    /// Environment variables the server needs credentials in
+    pub credentials: &'static [&'static str],
+    /// Other programs the server calls
+    pub requires: &'static [&'static str],
+    /// Limits keeping the agent within the upstream API's rate limits
+    pub call_limits: McpCallLimits,
+}
+
+impl McpCatalogEntry {
+    /// Get the server definition added to the settings
+    pub fn team_server(&self) -> TeamMcpServer {
+        let (command, mut args) = match self.runtime {
+            McpRuntime::Node => ("npx", vec!["-y".to_string()]),
+            McpRuntime::Python => ("uvx", Vec::new()),
+        };
+        args.push(self.package.to_string());
+        args.extend(self.args.iter().map(|arg| arg.to_string()));
+        TeamMcpServer {
+            name: Some(self.name.to_string()),
+            description: self.description.to_string(),
+            command: vec![command.to_string()],
+            args,
+            env: self
+                .credentials
+                .iter()
+                .map(|name| (name.to_string(), format!("${{{name}}}")))
+                .collect::<BTreeMap<_, _>>(),
+            working_directory: None,
+            auto_start: true,
+            keep_warm: false,
+            enabled: true,
+            resource_limits: McpResourceLimits::default(),
+            call_limits: self.call_limits,
+            request_timeout_secs: None,
+        }
+    }
+
+    /// Get the programs missing to run the server
+    pub fn missing_programs(&self, resolver: &CommandResolver) -> Vec<&'static str> {
+        std::iter::once(self.runtime.executable())
+            .chain(self.requires.iter().copied())
+            .filter(|program| resolver.find_executable(program).is_none())
+            .collect()
+    }
+
+    /// Check that the server has its programs and credentials
+    pub fn health_check(
+        &self,
+        resolver: &CommandResolver,
+        has_credential: impl Fn(&str) -> bool,
+    ) -> ServerHealthCheck {
+        ServerHealthCheck {
+            server_id: self.id.to_string(),
+            missing_programs: self
+                .missing_programs(resolver)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            missing_credentials: self
+                .credentials
+                .iter()
+                .filter(|name| !has_credential(name))
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+const NO_CALL_LIMITS: McpCallLimits = McpCallLimits {
+    max_concurrency: None,
+    requests_per_minute: None,
+};
+
+/// Servers Catalyst can set up
+pub const MCP_CATALOG: &[McpCatalogEntry] = &[
+    McpCatalogEntry {
+        id: "github",
+        name: "GitHub",
+        description: "Issues, pull requests and workflow runs",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-github",
+        args: &[],
+        credentials: &["GITHUB_PERSONAL_ACCESS_TOKEN"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(4),
+            requests_per_minute: Some(80),
+        },
+    },
+    McpCatalogEntry {
+        id: "gitlab",
+        name: "GitLab",
+        description: "Issues, merge requests and pipelines",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-gitlab",
+        args: &[],
+        credentials: &["GITLAB_PERSONAL_ACCESS_TOKEN"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(4),
+            requests_per_minute: Some(300),
+        },
+    },
+    McpCatalogEntry {
+        id: "fetch",
+        name: "Fetch",
+        description: "Download web pages as markdown",
+        runtime: McpRuntime::Python,
+        package: "mcp-server-fetch",
+        args: &[],
+        credentials: &[],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "memory",
+        name: "Memory",
+        description: "Knowledge graph the agent keeps between sessions",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-memory",
+        args: &[],
+        credentials: &[],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "sequential-thinking",
+        name: "Sequential Thinking",
+        description: "Step-by-step reasoning for larger problems",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-sequential-thinking",
+        args: &[],
+        credentials: &[],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "time",
+        name: "Time",
+        description: "Current time and time zone conversions",
+        runtime: McpRuntime::Python,
+        package: "mcp-server-time",
+        args: &[],
+        credentials: &[],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "puppeteer",
+        name: "Puppeteer",
+        description: "Drive a headless browser to test web pages",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-puppeteer",
+        args: &[],
+        credentials: &[],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "brave-search",
+        name: "Brave Search",
+        description: "Search the web",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-brave-search",
+        args: &[],
+        credentials: &["BRAVE_API_KEY"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(1),
+            requests_per_minute: Some(60),
+        },
+    },
+    McpCatalogEntry {
+        id: "slack",
+        name: "Slack",
+        description: "Read and post messages in Slack channels",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-slack",
+        args: &[],
+        credentials: &["SLACK_BOT_TOKEN", "SLACK_TEAM_ID"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(2),
+            requests_per_minute: Some(50),
+        },
+    },
+    McpCatalogEntry {
+        id: "sentry",
+        name: "Sentry",
+        description: "Look up errors and their stack traces",
+        runtime: McpRuntime::Node,
+        package: "@sentry/mcp-server",
+        args: &[],
+        credentials: &["SENTRY_ACCESS_TOKEN"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(4),
+            requests_per_minute: Some(100),
+        },
+    },
+    McpCatalogEntry {
+        id: "google-maps",
+        name: "Google Maps",
+        description: "Geocoding, places and directions",
+        runtime: McpRuntime::Node,
+        package: "@modelcontextprotocol/server-google-maps",
+        args: &[],
+        credentials: &["GOOGLE_MAPS_API_KEY"],
+        requires: &[],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "docker",
+        name: "Docker",
+        description: "Manage containers, images and compose projects",
+        runtime: McpRuntime::Python,
+        package: "mcp-server-docker",
+        args: &[],
+        credentials: &[],
+        requires: &["docker"],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "kubernetes",
+        name: "Kubernetes",
+        description: "Inspect and manage cluster resources",
+        runtime: McpRuntime::Node,
+        package: "mcp-server-kubernetes",
+        args: &[],
+        credentials: &[],
+        requires: &["kubectl"],
+        call_limits: NO_CALL_LIMITS,
+    },
+    McpCatalogEntry {
+        id: "socket",
+        name: "Socket",
+        description: "Check dependencies for known vulnerabilities",
+        runtime: McpRuntime::Node,
+        package: "@socketsecurity/mcp",
+        args: &[],
+        credentials: &["SOCKET_API_KEY"],
+        requires: &[],
+        call_limits: McpCallLimits {
+            max_concurrency: Some(1),
+            requests_per_minute: None,
+        },
+    },
+];
+
+/// Find a server of the catalog
+pub fn mcp_catalog_entry(id: &str) -> Option<&'static McpCatalogEntry> {
+    MCP_CATALOG.iter().find(|entry| entry.id == id)
+}
@@ -0,0 +1,475 @@
+//! MCP Payloads
+//!
+//! This module reads MCP messages without materializing them as
+//! `serde_json::Value` trees. Small messages are parsed in place with the
+//! result kept as a borrowed [`RawValue`], so routing a response costs one
+//! scan of the bytes and no copies. Messages above the inline threshold are
+//! streamed to a temporary file while they are read and only their envelope
+//! is parsed, so a multi-megabyte file read or screenshot never sits in
+//! memory more than once, and messages above the hard cap are dropped.
+
+use anyhow::Result;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    McpError, McpResourceChunk, McpServerHandle, McpToolResult,
+};
+
+/// Bytes requested per range read when streaming a resource
+pub const RESOURCE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Size limits applied while reading MCP messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpPayloadLimits {
+    /// Messages up to this size are kept in memory
+    pub inline_max_bytes: usize,
+    /// Messages above this size are discarded
+    pub max_bytes: u64,
+    /// Directory for spilled messages, the system temp directory if unset
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for McpPayloadLimits {
+    fn default() -> Self {
+        Self {
+            inline_max_bytes: 1024 * 1024,
+            max_bytes: 256 * 1024 * 1024,
+            spill_dir: None,
+        }
+    }
+}
+
+impl McpPayloadLimits {
+    fn spill_file(&self) -> Result<tempfile::NamedTempFile> {
+        Ok(match &self.spill_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                tempfile::NamedTempFile::new_in(dir)?
+            }
+            None => tempfile::NamedTempFile::new()?,
+        })
+    }
+}
+
+/// A message spilled to a temporary file, deleted when dropped
+#[derive(Debug)]
+pub struct SpilledPayload {
+    path: tempfile::TempPath,
+    len: u64,
+}
+
+impl SpilledPayload {
+    /// Get the path of the spilled message
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the size of the message in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Check if the message is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Open the message for streaming reads
+    pub fn open(&self) -> Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(&self.path)?))
+    }
+
+    /// Copy a stream into a temporary file, failing once it exceeds
+    /// `max_bytes`
+    ///
+    /// This is the hand-off for consumers that need a path rather than a
+    /// reader, such as opening a large resource in an editor tab.
+    pub fn from_reader(
+        mut reader: impl Read,
+        limits: &McpPayloadLimits,
+    ) -> Result<Self> {
+        let mut file = limits.spill_file()?;
+        let len =
+            std::io::copy(&mut (&mut reader).take(limits.max_bytes + 1), &mut file)?;
+        if len > limits.max_bytes {
+            return Err(anyhow::anyhow!(
+                "Resource exceeds the limit of {} bytes",
+                limits.max_bytes
+            ));
+        }
+        file.flush()?;
+        Ok(Self {
+            path: file.into_temp_path(),
+            len,
+        })
+    }
+}
+
+/// Reads a resource through repeated range requests, holding at most one
+/// chunk in memory
+///
+/// Resources of servers without range reads are read whole once and kept
+/// in a temporary file the chunks are read from.
+pub struct McpResourceReader {
+    server: McpServerHandle,
+    uri: String,
+    chunk_size: usize,
+    offset: u64,
+    buffer: Vec<u8>,
+    position: usize,
+    eof: bool,
+    total_size: Option<u64>,
+    spilled: Option<tempfile::NamedTempFile>,
+}
+
+impl McpResourceReader {
+    /// Create a reader for a resource of a server
+    pub fn new(server: McpServerHandle, uri: &str) -> Self {
+        Self {
+            server,
+            uri: uri.to_string(),
+            chunk_size: RESOURCE_CHUNK_SIZE,
+            offset: 0,
+            buffer: Vec::new(),
+            position: 0,
+            eof: false,
+            total_size: None,
+            spilled: None,
+        }
+    }
+
+    /// Set the number of bytes requested per range read
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Start reading at an offset instead of the beginning
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Get the size of the resource once the first chunk has been read, if
+    /// the server reports it
+    pub fn total_size(&self) -> Option<u64> {
+        self.total_size
+    }
+
+    /// Copy the rest of the resource into a temporary file
+    pub fn spill(self, limits: &McpPayloadLimits) -> Result<SpilledPayload> {
+        SpilledPayload::from_reader(self, limits)
+    }
+
+    /// Read the chunk at the current offset, by range from servers that
+    /// read ranges, or else from the resource read whole once
+    fn next_chunk(&mut self) -> Result<McpResourceChunk> {
+        let server = self.server.read();
+        if server.reads_resource_ranges() {
+            return server.read_resource_range(
+                &self.uri,
+                self.offset,
+                self.chunk_size,
+            );
+        }
+        if self.spilled.is_none() {
+            let content = server.read_resource(&self.uri)?;
+            let mut file = McpPayloadLimits::default().spill_file()?;
+            match (&content.blob, &content.text) {
+                (Some(blob), _) => file.write_all(blob)?,
+                (None, Some(text)) => file.write_all(text.as_bytes())?,
+                (None, None) => {}
+            }
+            file.flush()?;
+            self.spilled = Some(file);
+        }
+        let file = self.spilled.as_mut().expect("resource was spilled");
+        let total_size = file.as_file().metadata()?.len();
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = Vec::new();
+        Read::by_ref(file)
+            .take(self.chunk_size as u64)
+            .read_to_end(&mut data)?;
+        Ok(McpResourceChunk {
+            uri: self.uri.clone(),
+            mime_type: None,
+            offset: self.offset,
+            eof: self.offset + data.len() as u64 >= total_size,
+            data,
+            total_size: Some(total_size),
+        })
+    }
+}
+
+impl Read for McpResourceReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            let chunk = self.next_chunk().map_err(std::io::Error::other)?;
+            self.offset += chunk.data.len() as u64;
+            // A server returning nothing without flagging the end would
+            // otherwise make us spin forever
+            self.eof = chunk.eof || chunk.data.is_empty();
+            self.total_size = chunk.total_size.or(self.total_size);
+            self.buffer = chunk.data;
+            self.position = 0;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// One newline-delimited message read from an MCP transport
+#[derive(Debug)]
+pub enum McpFrame {
+    Inline(Vec<u8>),
+    Spilled(SpilledPayload),
+}
+
+impl McpFrame {
+    /// Get the size of the message in bytes
+    pub fn len(&self) -> u64 {
+        match self {
+            McpFrame::Inline(bytes) => bytes.len() as u64,
+            McpFrame::Spilled(payload) => payload.len(),
+        }
+    }
+
+    /// Check if the message is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Parse the envelope of an inline message, borrowing the result
+    ///
+    /// Returns `None` for spilled messages, which must be read with
+    /// [`McpFrame::header`] instead.
+    pub fn borrowed(&self) -> Option<Result<RawMcpResponse<'_>>> {
+        match self {
+            McpFrame::Inline(bytes) => {
+                Some(serde_json::from_slice(bytes).map_err(Into::into))
+            }
+            McpFrame::Spilled(_) => None,
+        }
+    }
+
+    /// Parse only the id and error of the message, skipping over the result
+    /// without allocating it
+    pub fn header(&self) -> Result<McpResponseHeader> {
+        match self {
+            McpFrame::Inline(bytes) => Ok(serde_json::from_slice(bytes)?),
+            McpFrame::Spilled(payload) => {
+                Ok(serde_json::from_reader(payload.open()?)?)
+            }
+        }
+    }
+
+    /// Deserialize the result of the message into a concrete type, streaming
+    /// from disk for spilled messages
+    pub fn result<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        #[derive(Deserialize)]
+        struct ResultOnly<T> {
+            #[serde(default = "Option::default")]
+            result: Option<T>,
+        }
+
+        let message: ResultOnly<T> = match self {
+            McpFrame::Inline(bytes) => serde_json::from_slice(bytes)?,
+            McpFrame::Spilled(payload) => serde_json::from_reader(payload.open()?)?,
+        };
+        Ok(message.result)
+    }
+
+    /// Read the result of a `tools/call` response as MCP sends it
+    pub fn tool_result(&self) -> Result<Option<McpToolResult>> {
+        self.result::<serde_json::Value>()?
+            .map(|result| Ok(McpToolResult::from_wire(&result)?))
+            .transpose()
+    }
+}
+
+/// A response whose result is left as unparsed JSON borrowed from the
+/// message bytes
+#[derive(Debug, Deserialize)]
+pub struct RawMcpResponse<'a> {
+    #[serde(borrow)]
+    pub id: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    pub result: Option<&'a RawValue>,
+    #[serde(default)]
+    pub error: Option<McpError>,
+}
+
+impl RawMcpResponse<'_> {
+    /// Deserialize the result into a concrete type
+    pub fn parse_result<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        self.result
+            .map(|raw| serde_json::from_str(raw.get()))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Copy the result out of the message buffer without parsing it
+    pub fn to_owned_result(&self) -> Option<Box<RawValue>> {
+        self.result.map(|raw| raw.to_owned())
+    }
+}
+
+/// The routing fields of a response, read without the result
+#[derive(Debug, Deserialize)]
+pub struct McpResponseHeader {
+    #[serde(default)]
+    pub id: Option<serde_json::Value>,
+    #[serde(default)]
+    result: Option<IgnoredAny>,
+    #[serde(default)]
+    pub error: Option<McpError>,
+}
+
+impl McpResponseHeader {
+    /// Check if the message carries a result
+    pub fn has_result(&self) -> bool {
+        self.result.is_some()
+    }
+}
+
+/// Read the next newline-delimited message, spilling it to disk once it
+/// grows past the inline threshold
+///
+/// Returns `None` at the end of the stream. A message larger than
+/// `max_bytes` is consumed and reported as an error, so the stream stays
+/// usable for the messages after it.
+pub fn read_frame(
+    reader: &mut impl BufRead,
+    limits: &McpPayloadLimits,
+) -> Result<Option<McpFrame>> {
+    let mut inline = Vec::new();
+    let mut spill: Option<tempfile::NamedTempFile> = None;
+    let mut len: u64 = 0;
+    let mut oversized = false;
+
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            if len == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+        let (chunk, done) = match available.iter().position(|b| *b == b'\n') {
+            Some(pos) => (&available[..pos], true),
+            None => (available, false),
+        };
+        let consumed = chunk.len() + usize::from(done);
+
+        len += chunk.len() as u64;
+        if len > limits.max_bytes {
+            oversized = true;
+            spill = None;
+            inline = Vec::new();
+        } else if let Some(file) = spill.as_mut() {
+            file.write_all(chunk)?;
+        } else if inline.len() + chunk.len() > limits.inline_max_bytes {
+            let mut file = limits.spill_file()?;
+            file.write_all(&inline)?;
+            file.write_all(chunk)?;
+            inline = Vec::new();
+            spill = Some(file);
+        } else {
+            inline.extend_from_slice(chunk);
+        }
+
+        reader.consume(consumed);
+        if done {
+            break;
+        }
+    }
+
+    if oversized {
+        return Err(anyhow::anyhow!(
+            "MCP message of {} bytes exceeds the limit of {} bytes",
+            len,
+            limits.max_bytes
+        ));
+    }
+    match spill {
+        Some(mut file) => {
+            file.flush()?;
+            tracing::debug!("Spilled MCP message of {} bytes to disk", len);
+            Ok(Some(McpFrame::Spilled(SpilledPayload {
+                path: file.into_temp_path(),
+                len,
+            })))
+        }
+        None => Ok(Some(McpFrame::Inline(inline))),
+    }
+}
+
+/// Read frames from a transport until the end of the stream
+pub fn read_frames<R: Read>(
+    reader: R,
+    limits: McpPayloadLimits,
+) -> impl Iterator<Item = Result<McpFrame>> {
+    let mut reader = BufReader::new(reader);
+    std::iter::from_fn(move || read_frame(&mut reader, &limits).transpose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_results_spill_and_small_ones_borrow() {
+        let small =
+            r#"{"jsonrpc":"2.0","id":1,"result":{"content":[],"isError":false}}"#;
+        let text = "x".repeat(4096);
+        let large = format!(
+            r#"{{"jsonrpc":"2.0","id":2,"result":{{"content":[{{"type":"text","text":"{text}"}}],"isError":false}}}}"#
+        );
+        let huge = format!(r#"{{"id":3,"result":"{}"}}"#, "y".repeat(20_000));
+        let input = format!("{small}\n{large}\n{huge}\n{small}\n");
+        let limits = McpPayloadLimits {
+            inline_max_bytes: 1024,
+            max_bytes: 16 * 1024,
+            spill_dir: None,
+        };
+        let frames: Vec<Result<McpFrame>> =
+            read_frames(input.as_bytes(), limits).collect();
+        assert_eq!(frames.len(), 4);
+
+        let first = frames[0].as_ref().unwrap();
+        let response = first.borrowed().unwrap().unwrap();
+        assert_eq!(response.id.unwrap().get(), "1");
+        let result = response
+            .parse_result::<serde_json::Value>()
+            .unwrap()
+            .unwrap();
+        assert!(
+            McpToolResult::from_wire(&result)
+                .unwrap()
+                .content
+                .is_empty()
+        );
+
+        let second = frames[1].as_ref().unwrap();
+        assert!(matches!(second, McpFrame::Spilled(_)));
+        assert!(second.borrowed().is_none());
+        let header = second.header().unwrap();
+        assert!(header.has_result() && header.error.is_none());
+        let result = second.tool_result().unwrap().unwrap();
+        assert_eq!(result.content[0].data.as_str().unwrap().len(), 4096);
+
+        assert!(frames[2].is_err());
+        assert!(matches!(frames[3], Ok(McpFrame::Inline(_))));
+    }
+}
@@ -0,0 +1,303 @@
+//! MCP Server Scaffolding
+//!
+//! This module creates a new MCP server project for `catalyst mcp new`. The
+//! project is a Rust crate serving the protocol over stdio with the
+//! `catalyst-mcp-protocol` crate Catalyst itself uses, an example tool to
+//! start from, a test running the
+//! [compliance suite](crate::plugin_api::check_mcp_compliance) against it,
+//! and a `catalyst-mcp.toml` snippet registering the server in a workspace's
+//! `.catalyst/local/settings.toml`.
+
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// Where the generated project gets the protocol crate from
+const CATALYST_GIT: &str = "https://github.com/codeyousef/catalyst";
+/// Name of the generated registration snippet
+pub const MCP_REGISTRATION_FILE: &str = "catalyst-mcp.toml";
+
+const CARGO_TOML: &str = r#"[package]
+name = "{{name}}"
+version = "0.1.0"
+edition = "2024"
+
+[dependencies]
+anyhow = "1"
+catalyst-mcp-protocol = { git = "{{catalyst_git}}" }
+serde_json = "1"
+"#;
+
+const LIB_RS: &str = r#"//! {{name}} MCP server
+//!
+//! `handle` answers one JSON-RPC request; `main.rs` feeds it the requests
+//! read from stdin. Add tools to `tools` and `call_tool`.
+
+use catalyst_mcp_protocol::{
+    INVALID_PARAMS, METHOD_NOT_FOUND, McpContent, McpError, McpRequest,
+    McpResponse, McpTool, McpToolResult, ToolEffect, negotiate_protocol_version,
+};
+use serde_json::{Value, json};
+
+/// Tools offered by the server
+pub fn tools() -> Vec<McpTool> {
+    vec![McpTool {
+        name: "word_count".to_string(),
+        description: Some("Count the words and lines of a text".to_string()),
+        input_schema: json!({
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"],
+        }),
+        effect: ToolEffect::ReadOnly,
+    }]
+}
+
+/// Run a tool
+pub fn call_tool(name: &str, arguments: &Value) -> Result<McpToolResult, McpError> {
+    match name {
+        "word_count" => {
+            let text = arguments["text"]
+                .as_str()
+                .ok_or_else(|| invalid_params("'text' must be a string"))?;
+            Ok(text_result(format!(
+                "{} words, {} lines",
+                text.split_whitespace().count(),
+                text.lines().count()
+            )))
+        }
+        _ => Err(invalid_params(&format!("Unknown tool '{}'", name))),
+    }
+}
+
+/// Answer one request
+pub fn handle(request: McpRequest) -> McpResponse {
+    let params = request.params.unwrap_or(Value::Null);
+    let result = match request.method.as_str() {
+        "initialize" => Ok(json!({
+            "protocolVersion": negotiate_protocol_version(Some(&params)),
+            "capabilities": {"tools": {}},
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })),
+        "tools/list" => Ok(json!({
+            "tools": tools().iter().map(McpTool::to_wire).collect::<Vec<_>>(),
+        })),
+        "tools/call" => match params["name"].as_str() {
+            Some(name) => {
+                call_tool(name, &params["arguments"]).map(|result| result.to_wire())
+            }
+            None => Err(invalid_params("'name' must be a string")),
+        },
+        method => Err(McpError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method '{}'", method),
+            data: None,
+        }),
+    };
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request.id,
+        result,
+        error,
+    }
+}
+
+fn text_result(text: String) -> McpToolResult {
+    McpToolResult {
+        content: vec![McpContent::text(text)],
+        is_error: false,
+    }
+}
+
+fn invalid_params(message: &str) -> McpError {
+    McpError {
+        code: INVALID_PARAMS,
+        message: message.to_string(),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_count() {
+        let result = call_tool("word_count", &json!({"text": "one two\nthree"})).unwrap();
+        assert_eq!(result.content[0].data, "3 words, 2 lines");
+        assert!(call_tool("word_count", &json!({})).is_err());
+    }
+}
+"#;
+
+const MAIN_RS: &str = r#"//! Serves the MCP protocol over stdio, one JSON-RPC message per line
+
+fn main() -> anyhow::Result<()> {
+    catalyst_mcp_protocol::serve_lines(
+        std::io::stdin().lock(),
+        std::io::stdout(),
+        mcp_server::handle,
+    )
+}
+"#;
+
+const COMPLIANCE_RS: &str = r#"//! Checks the server against Catalyst's MCP compliance suite
+
+use catalyst_mcp_protocol::check_mcp_compliance;
+
+#[test]
+fn test_mcp_compliance() {
+    let report = check_mcp_compliance(|request| Ok(mcp_server::handle(request)));
+    assert!(report.is_compliant(), "{}", report);
+}
+"#;
+
+const REGISTRATION_TOML: &str = r#"# Copy this table into .catalyst/local/settings.toml of a workspace, or
+# into .catalyst/settings.toml to share the server with the team.
+[mcp_servers.{{name}}]
+name = "{{name}}"
+description = "{{name}} MCP server"
+command = ["cargo", "run", "--quiet", "--release", "--manifest-path", "{{manifest}}"]
+auto_start = false
+"#;
+
+const README_MD: &str = r#"# {{name}}
+
+An MCP server built on Catalyst's `catalyst-mcp-protocol` crate.
+
+- `src/lib.rs` defines the tools and answers requests
+- `src/main.rs` serves the protocol over stdio
+- `tests/compliance.rs` runs Catalyst's MCP compliance suite
+
+Run `cargo test` to check the server, then copy the table in
+`catalyst-mcp.toml` into a workspace's `.catalyst/local/settings.toml` to use
+it from Catalyst.
+"#;
+
+const GITIGNORE: &str = "/target\n";
+
+/// A new MCP server project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpScaffold {
+    name: String,
+}
+
+impl McpScaffold {
+    /// Check the name can be used as the crate and server name
+    pub fn new(name: &str) -> Result<Self> {
+        let valid = name.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+        });
+        if name.is_empty()
+            || !valid
+            || !name.starts_with(|c: char| c.is_ascii_lowercase())
+        {
+            return Err(anyhow!(
+                "Invalid server name '{}': use lowercase letters, digits, '-' \
+                 and '_', starting with a letter",
+                name
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the files of the project, relative to its directory
+    ///
+    /// `dir` is where the project is going to be written, which the
+    /// registration snippet needs to run it.
+    pub fn files(&self, dir: &Path) -> Vec<(PathBuf, String)> {
+        let manifest = dir.join("Cargo.toml");
+        let fill = |template: &str| {
+            template
+                .replace("{{name}}", &self.name)
+                .replace("{{catalyst_git}}", CATALYST_GIT)
+                .replace("{{manifest}}", &toml_escape(&manifest.to_string_lossy()))
+                // The library is renamed so the tests don't depend on the name
+                .replace("mcp_server::", &format!("{}::", self.crate_name()))
+        };
+        [
+            ("Cargo.toml", CARGO_TOML),
+            ("src/lib.rs", LIB_RS),
+            ("src/main.rs", MAIN_RS),
+            ("tests/compliance.rs", COMPLIANCE_RS),
+            (MCP_REGISTRATION_FILE, REGISTRATION_TOML),
+            ("README.md", README_MD),
+            (".gitignore", GITIGNORE),
+        ]
+        .into_iter()
+        .map(|(path, template)| (PathBuf::from(path), fill(template)))
+        .collect()
+    }
+
+    /// Write the project into `dir`, which must not exist or be empty
+    pub fn write(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(anyhow!(
+                "Can't create the server in {}: the directory isn't empty",
+                dir.display()
+            ));
+        }
+        let dir = std::path::absolute(dir)?;
+        let mut written = Vec::new();
+        for (path, content) in self.files(&dir) {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, content)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    fn crate_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+fn toml_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaffold_mcp_server() {
+        assert!(McpScaffold::new("My Server").is_err());
+        assert!(McpScaffold::new("1server").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("word-tools");
+        let scaffold = McpScaffold::new("word-tools").unwrap();
+        let written = scaffold.write(&project).unwrap();
+        assert_eq!(written.len(), 7);
+
+        let compliance =
+            std::fs::read_to_string(project.join("tests/compliance.rs")).unwrap();
+        assert!(compliance.contains("word_tools::handle(request)"));
+
+        let registration: toml::Value = toml::from_str(
+            &std::fs::read_to_string(project.join(MCP_REGISTRATION_FILE)).unwrap(),
+        )
+        .unwrap();
+        let server = &registration["mcp_servers"]["word-tools"];
+        assert_eq!(server["command"][0].as_str(), Some("cargo"));
+
+        // An existing project is never overwritten
+        assert!(scaffold.write(&project).is_err());
+    }
+}
@@ -4,8 +4,69 @@
 //! that can be integrated into Catalyst IDE.
 
 use anyhow::Result;
+use crossbeam_channel::{Receiver, TryRecvError};
+use dashmap::{DashMap, mapref::entry::Entry};
+use once_cell::sync::Lazy;
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    AllocTag, CallLimiter, CallRefused, Clock, DEFAULT_QUEUE_TIMEOUT,
+    DegradationTracker, EditorContext, McpCallLimits, McpCapabilityCache,
+    McpResourceReader, McpSampler, MetricsRegistry, PathChangeStream, PluginHook,
+    ResourceUpdateStream, Subsystem, SystemClock, ToolUsageStore, UnusedServer,
+    WorkspaceRoots, alloc_scope, correlation_span, new_correlation_id,
+    rate_limit_message,
+};
+
+pub use catalyst_mcp_protocol::{
+    CANCELLED, INITIALIZED, INTERNAL_ERROR, INVALID_PARAMS, INVALID_REQUEST,
+    LOGGING_MESSAGE, LineConnection, LineTransport, MCP_PROTOCOL_VERSION,
+    METHOD_NOT_FOUND, McpClientError, McpComplianceCheck, McpComplianceReport, McpContent,
+    McpContentHint, McpEmbeddedResource, McpError, McpNotification, McpPrompt,
+    McpPromptArgument, McpPromptMessage, McpRequest, McpRequestId, McpResource,
+    McpResourceChunk, McpResourceContent, McpResponse, McpRoot, McpSamplingContent,
+    McpSamplingMessage, McpSamplingRequest, McpSamplingResult, McpTool,
+    McpToolResult, McpTransport, McpTypedContent, NotificationCallback,
+    RESOURCE_UPDATED, RESOURCES_LIST_CHANGED, ROOTS_LIST, ROOTS_LIST_CHANGED,
+    RequestCallback, SAMPLING_CREATE_MESSAGE, SUPPORTED_PROTOCOL_VERSIONS,
+    TOOLS_LIST_CHANGED, ToolEffect, check_mcp_compliance, client_capabilities,
+    demultiplex_batch, initialize_params, is_supported_protocol_version,
+    negotiate_protocol_version, serve_lines,
+};
+
+/// Get the name of the metric sampling the tool call latency of a server,
+/// in milliseconds
+pub fn mcp_latency_metric(server_id: &str) -> String {
+    format!("mcp.{server_id}.latency_ms")
+}
+
+/// Get the name of the metric sampling how long a suspended server took to
+/// restart for a tool call, in milliseconds
+pub fn mcp_cold_start_metric(server_id: &str) -> String {
+    format!("mcp.{server_id}.cold_start_ms")
+}
+
+/// Servers probed, started or stopped at the same time
+pub const MAX_PARALLEL_SERVER_OPERATIONS: usize = 15;
+
+/// Time a tool call or request may take unless its server sets its own
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Threads running server lifecycle operations, sized so one slow server
+/// can't hold up the others but a large config can't spawn unbounded threads
+static LIFECYCLE_POOL: Lazy<rayon::ThreadPool> = Lazy::new(|| {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(MAX_PARALLEL_SERVER_OPERATIONS)
+        .thread_name(|i| format!("mcp-lifecycle-{i}"))
+        .build()
+        .expect("failed to build the MCP lifecycle thread pool")
+});
 
 /// Trait that MCP server plugins must implement
 pub trait McpServerPlugin: Send + Sync + 'static {
@@ -30,6 +91,22 @@ pub trait McpServerPlugin: Send + Sync + 'static {
     /// Send a request to the MCP server
     fn send_request(&self, request: McpRequest) -> Result<McpResponse>;
 
+    /// Send several requests as one JSON-RPC batch, getting the outcome of
+    /// each in the order of `requests`
+    ///
+    /// The default sends them one by one; servers talking over an
+    /// [`McpTransport`] should override it with
+    /// [`McpTransport::request_batch`].
+    fn send_batch(
+        &self,
+        requests: Vec<McpRequest>,
+    ) -> Result<Vec<Result<McpResponse>>> {
+        Ok(requests
+            .into_iter()
+            .map(|request| self.send_request(request))
+            .collect())
+    }
+
     /// Get available tools from the server
     fn get_tools(&self) -> Result<Vec<McpTool>>;
 
@@ -43,14 +120,523 @@ fn call_tool(
         arguments: serde_json::Value,
     ) -> Result<McpToolResult>;
 
+    /// Get a caller sending requests to the running server without locking
+    /// it
+    ///
+    /// Tool calls and requests with a timeout go through it, so a server
+    /// that hangs on one can still be stopped or restarted. Servers talking
+    /// to a process return a [`TransportCaller`]; the default has none, for
+    /// servers running in-process, whose calls then hold the server's lock.
+    fn caller(&self) -> Option<Arc<dyn McpCaller>> {
+        None
+    }
+
     /// Read a resource from the server
     fn read_resource(&self, resource_uri: &str) -> Result<McpResourceContent>;
 
+    /// Read up to `length` bytes of a resource starting at `offset`
+    ///
+    /// The default reads the whole resource and slices it; servers exposing
+    /// large resources should override it to read only the requested range,
+    /// along with [`Self::reads_resource_ranges`].
+    fn read_resource_range(
+        &self,
+        resource_uri: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<McpResourceChunk> {
+        let content = self.read_resource(resource_uri)?;
+        let bytes = match (content.blob, content.text) {
+            (Some(blob), _) => blob,
+            (None, Some(text)) => text.into_bytes(),
+            (None, None) => Vec::new(),
+        };
+        let total_size = bytes.len() as u64;
+        let start = offset.min(total_size) as usize;
+        let end = start.saturating_add(length).min(bytes.len());
+        Ok(McpResourceChunk {
+            uri: content.uri,
+            mime_type: content.mime_type,
+            offset,
+            data: bytes[start..end].to_vec(),
+            total_size: Some(total_size),
+            eof: end == bytes.len(),
+        })
+    }
+
+    /// Check if [`Self::read_resource_range`] reads only the requested range
+    ///
+    /// A [`McpResourceReader`] reads resources of servers without range
+    /// reads whole, once, and serves its chunks from a temporary file.
+    fn reads_resource_ranges(&self) -> bool {
+        false
+    }
+
     /// Subscribe to resource changes
     fn subscribe_to_resource(&self, resource_uri: &str) -> Result<()>;
 
     /// Unsubscribe from resource changes
     fn unsubscribe_from_resource(&self, resource_uri: &str) -> Result<()>;
+
+    /// Get the prompt templates the server offers
+    ///
+    /// The default sends a `prompts/list` request, or returns no prompts if
+    /// the server doesn't declare the `prompts` capability.
+    fn get_prompts(&self) -> Result<Vec<McpPrompt>> {
+        if !self.server_info().capabilities.prompts {
+            return Ok(Vec::new());
+        }
+        let result = server_request(self, "prompts/list", None)?;
+        serde_json::from_value(result.get("prompts").cloned().unwrap_or_default())
+            .map_err(|err| McpClientError::from(err).into())
+    }
+
+    /// Fill in a prompt template with the given arguments
+    ///
+    /// The default sends a `prompts/get` request.
+    fn get_prompt(
+        &self,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<Vec<McpPromptMessage>> {
+        let params = serde_json::json!({ "name": name, "arguments": arguments });
+        let result = server_request(self, "prompts/get", Some(params))?;
+        result
+            .get("messages")
+            .and_then(|messages| messages.as_array())
+            .ok_or_else(|| {
+                McpClientError::ProtocolViolation(format!(
+                    "Prompt '{}' has no messages",
+                    name
+                ))
+            })?
+            .iter()
+            .map(prompt_message)
+            .collect()
+    }
+
+    /// Ask the server to abort a request still running, such as a long
+    /// tool call, without stopping the server
+    ///
+    /// The server sends [`CANCELLED`] and doesn't answer the request. The
+    /// default cancels on the server's [`caller`](Self::caller), and
+    /// refuses for servers without one.
+    fn cancel_request(&self, id: &str) -> Result<()> {
+        match self.caller() {
+            Some(caller) => caller.cancel_request(id, None),
+            None => Err(anyhow::anyhow!(
+                "MCP server '{}' can't cancel request '{}'",
+                self.server_info().id,
+                id
+            )),
+        }
+    }
+
+    /// Agree on a protocol version with the server
+    ///
+    /// Sends `initialize` asking for the newest version Catalyst speaks.
+    /// A server answering with an older supported version is talked to in
+    /// that version; any other version fails with
+    /// [`McpClientError::UnsupportedProtocolVersion`] and the server should
+    /// be stopped. On success the server is sent [`INITIALIZED`].
+    /// The registry calls this after every start of the server.
+    fn handshake(&self) -> Result<McpInitializeResult> {
+        let server_id = self.server_info().id;
+        let result = server_request(
+            self,
+            "initialize",
+            Some(initialize_params("catalyst", env!("CARGO_PKG_VERSION"))),
+        )?;
+        let initialized =
+            McpInitializeResult::from_result(&result).map_err(|err| {
+                anyhow::Error::new(err)
+                    .context(format!("MCP server '{}' was refused", server_id))
+            })?;
+        if initialized.is_downgraded() {
+            tracing::info!(
+                "MCP server '{}' speaks protocol version {}, not {}",
+                server_id,
+                initialized.protocol_version,
+                MCP_PROTOCOL_VERSION
+            );
+        }
+        self.send_notification(McpNotification::new(INITIALIZED, None))?;
+        Ok(initialized)
+    }
+
+    /// Send a notification to the server, such as [`ROOTS_LIST_CHANGED`]
+    ///
+    /// The default drops it, for servers that don't read notifications.
+    fn send_notification(&self, _notification: McpNotification) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keep the sink the server delivers its notifications to
+    ///
+    /// Called when the server is registered. The default drops it, for
+    /// servers that never push notifications.
+    fn set_notification_sink(&mut self, _sink: McpNotificationSink) {}
+
+    /// Keep the handler that answers the requests the server sends
+    ///
+    /// Called when the server is registered. The default drops it, for
+    /// servers that never send requests.
+    fn set_request_handler(&mut self, _handler: McpRequestHandler) {}
+}
+
+/// Connection to a running MCP server that requests are sent on without
+/// locking the server
+///
+/// A request sent on a caller can be cancelled from another thread by the
+/// JSON-RPC id it was sent with, while it is still waiting for its
+/// response.
+pub trait McpCaller: Send + Sync {
+    /// Send a request and wait for its response
+    fn send_request(&self, request: McpRequest) -> Result<McpResponse>;
+
+    /// Ask the server to abort the request sent with the given id, giving
+    /// a reason the server may log
+    fn cancel_request(&self, id: &str, reason: Option<&str>) -> Result<()>;
+
+    /// Call a tool, sending `tools/call` with `request_id` as its id
+    fn call_tool(
+        &self,
+        request_id: &str,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<McpToolResult> {
+        let method = "tools/call";
+        let response = self.send_request(McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: request_id.into(),
+            method: method.to_string(),
+            params: Some(
+                serde_json::json!({ "name": tool_name, "arguments": arguments }),
+            ),
+        })?;
+        let result = McpClientError::result_of(method, response)?;
+        Ok(McpToolResult::from_wire(&result)?)
+    }
+}
+
+/// Caller sending requests to a server process over a [`LineConnection`]
+///
+/// Requests from several threads share the connection, and a cancelled
+/// request stops waiting for its response at once.
+pub struct TransportCaller {
+    connection: LineConnection<Box<dyn std::io::Write + Send>>,
+}
+
+impl TransportCaller {
+    pub fn new(connection: LineConnection<Box<dyn std::io::Write + Send>>) -> Self {
+        Self { connection }
+    }
+}
+
+impl McpCaller for TransportCaller {
+    fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+        self.connection.request(&request)
+    }
+
+    fn cancel_request(&self, id: &str, reason: Option<&str>) -> Result<()> {
+        self.connection.cancel(&id.into(), reason)
+    }
+}
+
+/// Caller of a server without one of its own, holding the server's lock
+/// for as long as a request takes
+struct LockedCaller(McpServerHandle);
+
+impl McpCaller for LockedCaller {
+    fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+        self.0.read().send_request(request)
+    }
+
+    fn cancel_request(&self, id: &str, _reason: Option<&str>) -> Result<()> {
+        // A server waiting to be stopped can't be locked, and is stopped
+        // anyway
+        match self.0.server.try_read() {
+            Some(server) => server.cancel_request(id),
+            None => Ok(()),
+        }
+    }
+
+    fn call_tool(
+        &self,
+        _request_id: &str,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<McpToolResult> {
+        self.0.read().call_tool(tool_name, arguments)
+    }
+}
+
+/// Send a request and get its result
+fn server_request<S: McpServerPlugin + ?Sized>(
+    server: &S,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let response = server.send_request(McpRequest {
+        jsonrpc: "2.0".to_string(),
+        id: new_correlation_id().into(),
+        method: method.to_string(),
+        params,
+    })?;
+    McpClientError::result_of(method, response)
+        .map_err(|err| anyhow::Error::new(err).context(format!("{} failed", method)))
+}
+
+/// Answer a request to a server running in-process
+///
+/// Built-in servers have no process speaking JSON-RPC; this answers
+/// `initialize`, `tools/list` and `tools/call` with the server's own
+/// methods, so they can be driven like any other server.
+pub fn serve_in_process<S: McpServerPlugin + ?Sized>(
+    server: &S,
+    request: McpRequest,
+) -> McpResponse {
+    let params = request.params.unwrap_or_default();
+    let result = match request.method.as_str() {
+        "initialize" => {
+            let info = server.server_info();
+            Ok(serde_json::json!({
+                "protocolVersion": negotiate_protocol_version(Some(&params)),
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": info.id, "version": info.version },
+            }))
+        }
+        "tools/list" => server.get_tools().map(|tools| {
+            let tools: Vec<_> = tools.iter().map(McpTool::to_wire).collect();
+            serde_json::json!({ "tools": tools })
+        }),
+        "tools/call" => {
+            let name = params["name"].as_str().unwrap_or_default();
+            server
+                .call_tool(name, params["arguments"].clone())
+                .map(|result| result.to_wire())
+        }
+        method => Err(McpClientError::ServerError(McpError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method '{}'", method),
+            data: None,
+        })
+        .into()),
+    };
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(err) => {
+            let error = match McpClientError::of(&err) {
+                Some(McpClientError::ServerError(error)) => error.clone(),
+                _ => McpError {
+                    code: INTERNAL_ERROR,
+                    message: format!("{:#}", err),
+                    data: None,
+                },
+            };
+            (None, Some(error))
+        }
+    };
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request.id,
+        result,
+        error,
+    }
+}
+
+/// Read a prompt message as sent, e.g.
+/// `{"role": "user", "content": {"type": "text", "text": "..."}}`
+fn prompt_message(message: &serde_json::Value) -> Result<McpPromptMessage> {
+    let role = message
+        .get("role")
+        .and_then(|role| role.as_str())
+        .ok_or_else(|| {
+            McpClientError::ProtocolViolation("Prompt message has no role".into())
+        })?;
+    let content = message.get("content").ok_or_else(|| {
+        McpClientError::ProtocolViolation("Prompt message has no content".into())
+    })?;
+    Ok(McpPromptMessage {
+        role: role.to_string(),
+        content: McpContent::from_wire(content.clone()),
+    })
+}
+
+/// Stop a server, keeping its tools so they can still be offered
+///
+/// Servers that can't list their tools are left running.
+fn suspend_server(
+    server_id: &str,
+    server: &mut dyn McpServerPlugin,
+    capabilities: &McpCapabilityCache,
+    suspended: &DashMap<String, Vec<McpTool>>,
+) -> Option<Result<()>> {
+    let tools = capabilities.tools(server_id, server).ok()?;
+    Some(server.stop().map(|()| {
+        capabilities.remove(server_id);
+        suspended.insert(server_id.to_string(), tools.to_vec());
+    }))
+}
+
+/// Notification pushed by a server
+#[derive(Debug, Clone, PartialEq)]
+pub enum McpServerNotification {
+    ToolsListChanged,
+    ResourcesListChanged,
+    /// A subscribed resource changed
+    ResourceUpdated {
+        uri: String,
+    },
+    /// Log message, with the syslog level name the server gave it
+    Log {
+        level: String,
+        logger: Option<String>,
+        data: serde_json::Value,
+    },
+    /// Any other notification, as sent
+    Other(McpNotification),
+}
+
+impl From<McpNotification> for McpServerNotification {
+    fn from(notification: McpNotification) -> Self {
+        let params = notification.params.clone().unwrap_or_default();
+        let text = |key: &str| {
+            params
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string())
+        };
+        match notification.method.as_str() {
+            TOOLS_LIST_CHANGED => Self::ToolsListChanged,
+            RESOURCES_LIST_CHANGED => Self::ResourcesListChanged,
+            RESOURCE_UPDATED => match text("uri") {
+                Some(uri) => Self::ResourceUpdated { uri },
+                None => Self::Other(notification),
+            },
+            LOGGING_MESSAGE => Self::Log {
+                level: text("level").unwrap_or_else(|| "info".to_string()),
+                logger: text("logger"),
+                data: params.get("data").cloned().unwrap_or_default(),
+            },
+            _ => Self::Other(notification),
+        }
+    }
+}
+
+/// Receives the notifications of all registered servers
+pub trait McpNotificationHandler: Send + Sync + 'static {
+    fn handle_notification(
+        &self,
+        server_id: &str,
+        notification: &McpServerNotification,
+    );
+}
+
+/// Delivers the notifications of one server to the registry and its
+/// handlers
+///
+/// Servers reading messages with a [`LineTransport`] pass
+/// [`Self::callback`] to [`LineTransport::with_notification_handler`], or
+/// to [`LineConnection::spawn`] to receive notifications while idle.
+/// Handlers run on the thread delivering the notification, so they must
+/// not wait on a request to the same server.
+#[derive(Clone)]
+pub struct McpNotificationSink {
+    server_id: Arc<str>,
+    handlers: Arc<RwLock<Vec<Arc<dyn McpNotificationHandler>>>>,
+    capabilities: McpCapabilityCache,
+}
+
+impl McpNotificationSink {
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+
+    /// Deliver a notification received from the server
+    pub fn deliver(&self, notification: McpNotification) {
+        let notification = McpServerNotification::from(notification);
+        tracing::debug!(
+            server_id = &*self.server_id,
+            ?notification,
+            "MCP server notification"
+        );
+        // Listed again on the next request
+        self.capabilities.invalidate(&self.server_id, &notification);
+        let handlers = self.handlers.read().clone();
+        for handler in handlers {
+            handler.handle_notification(&self.server_id, &notification);
+        }
+    }
+
+    /// Get a transport callback delivering to this sink
+    pub fn callback(&self) -> NotificationCallback {
+        let sink = self.clone();
+        Box::new(move |notification| sink.deliver(notification))
+    }
+}
+
+/// Answers the requests one server sends, such as
+/// [`SAMPLING_CREATE_MESSAGE`]
+///
+/// Servers reading messages with a [`LineTransport`] pass
+/// [`Self::callback`] to [`LineTransport::with_request_handler`] or
+/// [`LineConnection::spawn`]. Sampling
+/// is answered by the registry's current [`McpSampler`], and refused while
+/// there is none. [`ROOTS_LIST`] is answered with the workspace roots the
+/// server is enabled for.
+#[derive(Clone)]
+pub struct McpRequestHandler {
+    server_id: Arc<str>,
+    sampler: Arc<RwLock<Option<Arc<McpSampler>>>>,
+    roots: Arc<RwLock<WorkspaceRoots>>,
+}
+
+impl McpRequestHandler {
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+
+    /// Answer a request received from the server
+    pub fn handle(&self, request: McpRequest) -> McpResponse {
+        let sampler = self.sampler.read().clone();
+        match (request.method.as_str(), sampler) {
+            (SAMPLING_CREATE_MESSAGE, Some(sampler)) => {
+                sampler.handle_request(&self.server_id, request)
+            }
+            (ROOTS_LIST, _) => {
+                let roots = self.roots.read().mcp_roots(&self.server_id);
+                McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(serde_json::json!({ "roots": roots })),
+                    error: None,
+                }
+            }
+            _ => McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(McpError {
+                    code: METHOD_NOT_FOUND,
+                    message: format!("Method '{}' not found", request.method),
+                    data: None,
+                }),
+            },
+        }
+    }
+
+    /// Get the workspace roots the server may see, for servers running
+    /// in-process that don't ask with [`ROOTS_LIST`]
+    pub fn workspace_roots(&self) -> WorkspaceRoots {
+        self.roots.read().for_server(&self.server_id)
+    }
+
+    /// Get a transport callback answering with this handler
+    pub fn callback(&self) -> RequestCallback {
+        let handler = self.clone();
+        Box::new(move |request| handler.handle(request))
+    }
 }
 
 /// Information about an MCP server plugin
@@ -65,7 +651,145 @@ pub struct McpServerInfo {
     pub env: HashMap<String, String>,
     pub working_directory: Option<String>,
     pub auto_start: bool,
+    /// Start the server once the window is up and keep it running, so the
+    /// first tool call doesn't wait for it to spawn and initialize
+    #[serde(default)]
+    pub keep_warm: bool,
     pub capabilities: McpServerCapabilities,
+    #[serde(default)]
+    pub resource_limits: McpResourceLimits,
+    #[serde(default)]
+    pub call_limits: McpCallLimits,
+    /// Seconds a tool call or request may take before it is cancelled,
+    /// [`DEFAULT_REQUEST_TIMEOUT`] if unset
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+}
+
+impl McpServerInfo {
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout_secs
+            .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_secs)
+    }
+}
+
+/// Limits applied to an MCP server process when it is spawned
+///
+/// On Unix memory and CPU time are watched for the server's process group
+/// and the open file limit becomes an rlimit; on Windows they become Job
+/// Object limits. Memory is resident size on Unix, and the CPU limit is
+/// total CPU time, not a rate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpResourceLimits {
+    pub max_memory_bytes: Option<u64>,
+    pub max_cpu_seconds: Option<u64>,
+    /// Not supported on Windows
+    pub max_open_files: Option<u64>,
+}
+
+impl McpResourceLimits {
+    /// Check if no limit is set
+    pub fn is_empty(&self) -> bool {
+        self.max_memory_bytes.is_none()
+            && self.max_cpu_seconds.is_none()
+            && self.max_open_files.is_none()
+    }
+}
+
+/// When servers without tool calls are suspended
+///
+/// A suspended server's process is stopped but it stays registered, with
+/// the tools it listed before, and it is started again by the next call of
+/// one of its tools. The restart latency is sampled in
+/// [`mcp_cold_start_metric`] to help pick the timeout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleSuspendSettings {
+    pub enabled: bool,
+    /// Time without tool calls after which a server is suspended
+    pub idle_timeout_secs: u64,
+    /// How often servers are checked for idleness, at most once a second
+    pub check_interval_secs: u64,
+}
+
+impl Default for IdleSuspendSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_timeout_secs: 600,
+            check_interval_secs: 60,
+        }
+    }
+}
+
+impl IdleSuspendSettings {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs.max(1))
+    }
+}
+
+/// Resource whose limit caused a server to be killed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceLimitKind {
+    Memory,
+    Cpu,
+    OpenFiles,
+}
+
+/// Outcome of the `initialize` handshake with a server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpInitializeResult {
+    /// Version the server and Catalyst agreed on
+    pub protocol_version: String,
+    pub server_name: Option<String>,
+    pub server_version: Option<String>,
+    /// Capabilities the server declared, as sent
+    pub capabilities: serde_json::Value,
+}
+
+impl McpInitializeResult {
+    /// Read a server's answer to `initialize`, refusing versions Catalyst
+    /// doesn't speak
+    pub fn from_result(
+        result: &serde_json::Value,
+    ) -> std::result::Result<Self, McpClientError> {
+        let version = result
+            .get("protocolVersion")
+            .and_then(|version| version.as_str())
+            .ok_or_else(|| {
+                McpClientError::ProtocolViolation(
+                    "initialize returned no protocolVersion".to_string(),
+                )
+            })?;
+        if !is_supported_protocol_version(version) {
+            return Err(McpClientError::UnsupportedProtocolVersion(
+                version.to_string(),
+            ));
+        }
+        let info = |field: &str| {
+            result["serverInfo"][field]
+                .as_str()
+                .map(|value| value.to_string())
+        };
+        Ok(Self {
+            protocol_version: version.to_string(),
+            server_name: info("name"),
+            server_version: info("version"),
+            capabilities: result
+                .get("capabilities")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({})),
+        })
+    }
+
+    /// Check if the server speaks an older version than Catalyst asked for
+    pub fn is_downgraded(&self) -> bool {
+        self.protocol_version != MCP_PROTOCOL_VERSION
+    }
 }
 
 /// Capabilities that an MCP server supports
@@ -89,173 +813,2261 @@ pub struct McpServerHealth {
 }
 
 /// Status of an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum McpServerStatus {
     Stopped,
     Starting,
     Running,
     Error,
     Restarting,
+    /// The server process exited unexpectedly
+    Crashed,
+    /// The server process was killed for exceeding a resource limit
+    LimitExceeded(ResourceLimitKind),
 }
 
-/// Request to send to an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpRequest {
-    pub jsonrpc: String,
-    pub id: String,
-    pub method: String,
-    pub params: Option<serde_json::Value>,
-}
-
-/// Response from an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpResponse {
-    pub jsonrpc: String,
-    pub id: String,
-    pub result: Option<serde_json::Value>,
-    pub error: Option<McpError>,
+/// Shared handle to a registered MCP server
+///
+/// Handles are cheap to clone and stay valid after the server is
+/// unregistered, so callers never hold the registry while talking to a
+/// server. Most server operations take `&self` and only need `read`.
+#[derive(Clone)]
+pub struct McpServerHandle {
+    id: Arc<str>,
+    server: Arc<RwLock<Box<dyn McpServerPlugin>>>,
 }
 
-/// Error from an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpError {
-    pub code: i32,
-    pub message: String,
-    pub data: Option<serde_json::Value>,
-}
+impl McpServerHandle {
+    /// Create a handle for a server
+    pub fn new(id: &str, server: Box<dyn McpServerPlugin>) -> Self {
+        Self {
+            id: Arc::from(id),
+            server: Arc::new(RwLock::new(server)),
+        }
+    }
 
-/// Tool available from an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpTool {
-    pub name: String,
-    pub description: Option<String>,
-    pub input_schema: serde_json::Value,
-}
+    /// Get the id the server is registered under
+    pub fn id(&self) -> &str {
+        &self.id
+    }
 
-/// Resource available from an MCP server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpResource {
-    pub uri: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub mime_type: Option<String>,
-}
+    /// Lock the server for a shared operation
+    pub fn read(&self) -> RwLockReadGuard<'_, Box<dyn McpServerPlugin>> {
+        self.server.read()
+    }
 
-/// Result of calling a tool
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpToolResult {
-    pub content: Vec<McpContent>,
-    pub is_error: bool,
-}
+    /// Get a caller sending requests to the server, which locks the server
+    /// only if it has no [`McpCaller`] of its own
+    pub fn caller(&self) -> Arc<dyn McpCaller> {
+        self.read()
+            .caller()
+            .unwrap_or_else(|| Arc::new(LockedCaller(self.clone())))
+    }
 
-/// Content returned by MCP operations
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpContent {
-    pub content_type: String,
-    pub data: serde_json::Value,
-}
+    /// Stream a resource in chunks instead of buffering it whole
+    pub fn resource_reader(&self, resource_uri: &str) -> McpResourceReader {
+        McpResourceReader::new(self.clone(), resource_uri)
+    }
 
-/// Content of a resource
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpResourceContent {
-    pub uri: String,
-    pub mime_type: Option<String>,
-    pub text: Option<String>,
-    pub blob: Option<Vec<u8>>,
+    /// Lock the server for a lifecycle operation such as start or stop
+    pub fn write(&self) -> RwLockWriteGuard<'_, Box<dyn McpServerPlugin>> {
+        self.server.write()
+    }
 }
 
 /// Registry for managing MCP servers
+///
+/// Cloning the registry gives another view of the same servers, so health
+/// checks, the agent loop and the UI can each keep one without a lock around
+/// the whole registry.
+#[derive(Clone)]
 pub struct McpServerRegistry {
-    servers: HashMap<String, Box<dyn McpServerPlugin>>,
+    servers: Arc<DashMap<String, McpServerHandle>>,
+    usage: Option<Arc<ToolUsageStore>>,
+    idle_suspend: Arc<RwLock<IdleSuspendSettings>>,
+    /// Time of the last tool call, or of the first idle check, per server
+    last_calls: Arc<DashMap<String, Instant>>,
+    /// Tools of the suspended servers, listed before they were stopped
+    suspended: Arc<DashMap<String, Vec<McpTool>>>,
+    /// What the running servers offer, listed once they are started
+    capabilities: McpCapabilityCache,
+    /// Resources subscribed to per server, subscribed again on restart
+    subscriptions: Arc<DashMap<String, BTreeSet<String>>>,
+    /// Call limits of the servers that have any
+    limiters: Arc<DashMap<String, Arc<CallLimiter>>>,
+    notification_handlers: Arc<RwLock<Vec<Arc<dyn McpNotificationHandler>>>>,
+    /// Answers the sampling requests of all servers, if sampling is set up
+    sampler: Arc<RwLock<Option<Arc<McpSampler>>>>,
+    /// Folders of the workspace, answered to `roots/list`
+    roots: Arc<RwLock<WorkspaceRoots>>,
+    /// Times idle checks, call deadlines and latencies
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for McpServerRegistry {
+    fn default() -> Self {
+        Self {
+            servers: Default::default(),
+            usage: None,
+            idle_suspend: Default::default(),
+            last_calls: Default::default(),
+            suspended: Default::default(),
+            capabilities: Default::default(),
+            subscriptions: Default::default(),
+            limiters: Default::default(),
+            notification_handlers: Default::default(),
+            sampler: Default::default(),
+            roots: Default::default(),
+            clock: SystemClock::shared(),
+        }
+    }
 }
 
 impl McpServerRegistry {
     /// Create a new MCP server registry
     pub fn new() -> Self {
-        Self {
-            servers: HashMap::new(),
-        }
+        Self::default()
     }
 
-    /// Register a new MCP server
-    pub fn register_server(
-        &mut self,
-        id: String,
-        server: Box<dyn McpServerPlugin>,
-    ) -> Result<()> {
-        if self.servers.contains_key(&id) {
-            return Err(anyhow::anyhow!(
-                "MCP server with id '{}' is already registered",
-                id
-            ));
+    /// Time idle checks, call deadlines and the call limits of servers
+    /// registered from now on with `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Record the tool calls made through the registry in `usage`
+    pub fn with_usage_store(mut self, usage: Arc<ToolUsageStore>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Get the store the tool calls are recorded in, if any
+    pub fn usage_store(&self) -> Option<&Arc<ToolUsageStore>> {
+        self.usage.as_ref()
+    }
+
+    /// Change when idle servers are suspended
+    pub fn set_idle_suspend(&self, settings: IdleSuspendSettings) {
+        *self.idle_suspend.write() = settings;
+    }
+
+    pub fn idle_suspend(&self) -> IdleSuspendSettings {
+        self.idle_suspend.read().clone()
+    }
+
+    /// Pass the notifications of all servers to `handler` as well
+    pub fn add_notification_handler(
+        &self,
+        handler: Arc<dyn McpNotificationHandler>,
+    ) {
+        self.notification_handlers.write().push(handler);
+    }
+
+    /// Stop passing notifications to a handler added before
+    pub fn remove_notification_handler(
+        &self,
+        handler: &Arc<dyn McpNotificationHandler>,
+    ) {
+        self.notification_handlers
+            .write()
+            .retain(|added| !Arc::ptr_eq(added, handler));
+    }
+
+    /// Create a stream delivering the updates of the resources it
+    /// subscribes to, debounced by `debounce` such as
+    /// [`DEFAULT_RESOURCE_DEBOUNCE`](crate::plugin_api::DEFAULT_RESOURCE_DEBOUNCE)
+    pub fn resource_updates(&self, debounce: Duration) -> ResourceUpdateStream {
+        ResourceUpdateStream::new(self.clone(), debounce)
+    }
+
+    /// Create a stream collecting the changes of the paths watched on the
+    /// servers, for the agent loop to pass to the model
+    pub fn path_changes(&self) -> PathChangeStream {
+        PathChangeStream::new(self.clone())
+    }
+
+    fn notification_sink(&self, server_id: &str) -> McpNotificationSink {
+        McpNotificationSink {
+            server_id: Arc::from(server_id),
+            handlers: self.notification_handlers.clone(),
+            capabilities: self.capabilities.clone(),
         }
+    }
 
-        self.servers.insert(id, server);
-        Ok(())
+    /// Answer the sampling requests of all servers with `sampler`, or
+    /// refuse them with `None`
+    pub fn set_sampler(&self, sampler: Option<Arc<McpSampler>>) {
+        *self.sampler.write() = sampler;
     }
 
-    /// Unregister an MCP server
-    pub fn unregister_server(&mut self, id: &str) -> Result<()> {
-        self.servers.remove(id).ok_or_else(|| {
-            anyhow::anyhow!("MCP server with id '{}' is not registered", id)
-        })?;
-        Ok(())
+    fn request_handler(&self, server_id: &str) -> McpRequestHandler {
+        McpRequestHandler {
+            server_id: Arc::from(server_id),
+            sampler: self.sampler.clone(),
+            roots: self.roots.clone(),
+        }
     }
 
-    /// Get an MCP server by id
-    pub fn get_server(&self, id: &str) -> Option<&dyn McpServerPlugin> {
-        self.servers.get(id).map(|server| server.as_ref())
+    /// Get the folders of the workspace the servers may work in
+    pub fn workspace_roots(&self) -> WorkspaceRoots {
+        self.roots.read().clone()
     }
 
-    /// Get a mutable MCP server by id
-    pub fn get_server_mut(&mut self, id: &str) -> Option<&mut dyn McpServerPlugin> {
-        self.servers.get_mut(id).map(|server| server.as_mut())
+    /// Change the folders of the workspace, telling the running servers
+    /// to list them again
+    pub fn set_workspace_roots(&self, roots: WorkspaceRoots) {
+        *self.roots.write() = roots;
+        self.notify_roots_changed();
     }
 
-    /// Get all registered server IDs
-    pub fn get_server_ids(&self) -> Vec<String> {
-        self.servers.keys().cloned().collect()
+    /// Add or remove the root of a project opened or closed, telling the
+    /// running servers if the roots changed
+    pub fn handle_hook(&self, hook: &PluginHook) {
+        let changed = self.roots.write().apply_hook(hook);
+        if changed {
+            self.notify_roots_changed();
+        }
     }
 
-    /// Get server info for all registered servers
-    pub fn get_all_server_info(&self) -> Vec<McpServerInfo> {
-        self.servers
-            .values()
-            .map(|server| server.server_info())
-            .collect()
+    /// Take the workspace roots from an editor context, telling the
+    /// running servers if they changed
+    pub fn sync_workspace_roots(&self, context: &EditorContext) {
+        let changed = self.roots.write().sync_with_context(context);
+        if changed {
+            self.notify_roots_changed();
+        }
     }
 
-    /// Start all auto-start servers
-    pub fn start_auto_start_servers(&mut self) -> Result<()> {
-        for server in self.servers.values_mut() {
-            if server.server_info().auto_start && !server.is_running() {
-                server.start()?;
+    fn notify_roots_changed(&self) {
+        for entry in self.servers.iter() {
+            let server = entry.value().read();
+            if !server.is_running() {
+                continue;
+            }
+            let notification = McpNotification::new(ROOTS_LIST_CHANGED, None);
+            if let Err(err) = server.send_notification(notification) {
+                tracing::warn!(
+                    "Failed to notify MCP server '{}' of changed roots: {:#}",
+                    entry.key(),
+                    err
+                );
             }
         }
-        Ok(())
     }
 
-    /// Stop all running servers
-    pub fn stop_all_servers(&mut self) -> Result<()> {
-        for server in self.servers.values_mut() {
-            if server.is_running() {
-                server.stop()?;
+    /// Check if a server may be used for a file, which it may unless it is
+    /// disabled for the workspace root of the file
+    pub fn is_server_enabled_for_path(&self, server_id: &str, path: &Path) -> bool {
+        self.roots
+            .read()
+            .is_server_enabled_for_path(server_id, path)
+    }
+
+    /// Register a new MCP server
+    pub fn register_server(
+        &self,
+        id: String,
+        mut server: Box<dyn McpServerPlugin>,
+    ) -> Result<McpServerHandle> {
+        match self.servers.entry(id) {
+            Entry::Occupied(entry) => Err(anyhow::anyhow!(
+                "MCP server with id '{}' is already registered",
+                entry.key()
+            )),
+            Entry::Vacant(entry) => {
+                let recorded = self
+                    .usage
+                    .as_ref()
+                    .map(|usage| usage.record_registered(entry.key()));
+                if let Some(Err(err)) = recorded {
+                    tracing::warn!("Failed to record MCP server usage: {err:#}");
+                }
+                let limits = server.server_info().call_limits;
+                limits.validate().map_err(|err| {
+                    anyhow::anyhow!(
+                        "Invalid call limits of MCP server '{}': {err}",
+                        entry.key()
+                    )
+                })?;
+                if !limits.is_empty() {
+                    self.limiters.insert(
+                        entry.key().clone(),
+                        Arc::new(
+                            CallLimiter::new(entry.key(), limits)
+                                .with_clock(self.clock.clone()),
+                        ),
+                    );
+                }
+                server.set_notification_sink(self.notification_sink(entry.key()));
+                server.set_request_handler(self.request_handler(entry.key()));
+                let handle = McpServerHandle::new(entry.key(), server);
+                entry.insert(handle.clone());
+                Ok(handle)
             }
         }
+    }
+
+    /// Stop a server and remove it from the registry, as when the user
+    /// disables a server they don't use
+    pub fn disable_server(&self, id: &str) -> Result<()> {
+        let handle = self.unregister_server(id)?;
+        let mut server = handle.write();
+        if server.is_running() {
+            server.stop()?;
+        }
+        tracing::info!("Disabled MCP server '{}'", id);
         Ok(())
     }
 
-    /// Get health status for all servers
-    pub fn get_all_health_status(&self) -> HashMap<String, McpServerHealth> {
+    /// Find the registered servers without a tool call for at least
+    /// `unused_after`
+    pub fn unused_servers(&self, unused_after: Duration) -> Vec<UnusedServer> {
+        match &self.usage {
+            Some(usage) => usage.unused_servers(
+                &self.get_server_ids(),
+                unused_after,
+                self.clock.now(),
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    /// Unregister an MCP server
+    pub fn unregister_server(&self, id: &str) -> Result<McpServerHandle> {
+        self.last_calls.remove(id);
+        self.suspended.remove(id);
+        self.capabilities.remove(id);
+        self.subscriptions.remove(id);
+        self.limiters.remove(id);
         self.servers
-            .iter()
-            .map(|(id, server)| (id.clone(), server.health_check()))
-            .collect()
+            .remove(id)
+            .map(|(_, handle)| handle)
+            .ok_or_else(|| {
+                anyhow::anyhow!("MCP server with id '{}' is not registered", id)
+            })
     }
-}
 
-impl Default for McpServerRegistry {
-    fn default() -> Self {
-        Self::new()
+    /// Get an MCP server by id
+    pub fn get_server(&self, id: &str) -> Option<McpServerHandle> {
+        self.servers.get(id).map(|handle| handle.clone())
+    }
+
+    /// Abort a request a server is still running, as when the user cancels
+    /// a long tool call
+    pub fn cancel_request(&self, server_id: &str, request_id: &str) -> Result<()> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        handle.caller().cancel_request(request_id, None)?;
+        tracing::info!(server_id, request_id, "Cancelled MCP request");
+        Ok(())
+    }
+
+    /// Get the prompt templates of a server
+    pub fn get_prompts(&self, server_id: &str) -> Result<Vec<McpPrompt>> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        handle.read().get_prompts()
+    }
+
+    /// Get the tools of a server, listed only when they aren't cached
+    pub fn get_tools(&self, server_id: &str) -> Result<Arc<[McpTool]>> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        self.capabilities.tools(server_id, &**handle.read())
+    }
+
+    /// Get the resources of a server, listed only when they aren't cached
+    pub fn get_resources(&self, server_id: &str) -> Result<Arc<[McpResource]>> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        self.capabilities.resources(server_id, &**handle.read())
+    }
+
+    /// Fill in a prompt template of a server, as when the user picks it in
+    /// the assistant
+    pub fn get_prompt(
+        &self,
+        server_id: &str,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<Vec<McpPromptMessage>> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        handle.read().get_prompt(name, arguments)
+    }
+
+    /// Collect the prompt templates of the running servers, with the id of
+    /// the server offering each
+    ///
+    /// Servers failing to list their prompts are logged and left out.
+    pub fn available_prompts(&self) -> Vec<(String, McpPrompt)> {
+        let mut prompts = Vec::new();
+        for handle in self.handles() {
+            let server = handle.read();
+            if !server.is_running() {
+                continue;
+            }
+            match server.get_prompts() {
+                Ok(server_prompts) => prompts.extend(
+                    server_prompts
+                        .into_iter()
+                        .map(|prompt| (handle.id().to_string(), prompt)),
+                ),
+                Err(err) => tracing::warn!(
+                    "Failed to list the prompts of MCP server '{}': {err:#}",
+                    handle.id()
+                ),
+            }
+        }
+        prompts
+    }
+
+    /// Check if a server is suspended for being idle
+    pub fn is_suspended(&self, id: &str) -> bool {
+        self.suspended.contains_key(id)
+    }
+
+    /// Read a resource, resuming its server if it is suspended
+    pub fn read_resource(
+        &self,
+        server_id: &str,
+        resource_uri: &str,
+    ) -> Result<McpResourceContent> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        handle.read().read_resource(resource_uri)
+    }
+
+    /// Subscribe to changes of a resource, again after every restart of the
+    /// server until unsubscribed
+    pub fn subscribe_resource(
+        &self,
+        server_id: &str,
+        resource_uri: &str,
+    ) -> Result<()> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        handle.read().subscribe_to_resource(resource_uri)?;
+        self.subscriptions
+            .entry(server_id.to_string())
+            .or_default()
+            .insert(resource_uri.to_string());
+        Ok(())
+    }
+
+    /// Unsubscribe from changes of a resource
+    pub fn unsubscribe_resource(
+        &self,
+        server_id: &str,
+        resource_uri: &str,
+    ) -> Result<()> {
+        if let Some(mut uris) = self.subscriptions.get_mut(server_id) {
+            uris.remove(resource_uri);
+        }
+        match self.get_server(server_id) {
+            Some(handle) => handle.read().unsubscribe_from_resource(resource_uri),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the ids of the servers suspended for being idle
+    pub fn suspended_server_ids(&self) -> Vec<String> {
+        self.suspended
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Get handles to all registered servers
+    ///
+    /// The handles are collected first so no map shard stays locked while
+    /// servers are called.
+    pub fn handles(&self) -> Vec<McpServerHandle> {
+        self.servers
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// Get all registered server IDs
+    pub fn get_server_ids(&self) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Get server info for all registered servers
+    pub fn get_all_server_info(&self) -> Vec<McpServerInfo> {
+        self.handles()
+            .iter()
+            .map(|handle| handle.read().server_info())
+            .collect()
+    }
+
+    /// Start all auto-start servers in the background
+    pub fn start_auto_start_servers(&self) -> McpOperation<McpBatchResult> {
+        let capabilities = self.capabilities.clone();
+        self.run_batch(move |handle| {
+            let mut server = handle.write();
+            if server.server_info().auto_start && !server.is_running() {
+                Some(start_server(handle.id(), &mut **server, &capabilities))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Start the given servers that aren't running in the background, such
+    /// as the [`retryable`](McpBatchResult::retryable) failures of a batch
+    pub fn start_servers(&self, ids: Vec<String>) -> McpOperation<McpBatchResult> {
+        let capabilities = self.capabilities.clone();
+        self.run_batch(move |handle| {
+            let mut server = handle.write();
+            if ids.iter().any(|id| id == handle.id()) && !server.is_running() {
+                Some(start_server(handle.id(), &mut **server, &capabilities))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Stop all running servers in the background
+    pub fn stop_all_servers(&self) -> McpOperation<McpBatchResult> {
+        let capabilities = self.capabilities.clone();
+        self.run_batch(move |handle| {
+            let mut server = handle.write();
+            if server.is_running() {
+                capabilities.remove(handle.id());
+                Some(server.stop())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Stop all running servers in the background, giving each one at most
+    /// `deadline` to stop
+    ///
+    /// A server that misses its deadline is reported as failed and left to
+    /// finish stopping on its own thread, so one hung server can't hold up
+    /// shutdown.
+    pub fn stop_all_servers_within(
+        &self,
+        deadline: Duration,
+    ) -> McpOperation<McpBatchResult> {
+        self.run_batch(move |handle| {
+            if !handle.read().is_running() {
+                return None;
+            }
+            let (tx, rx) = crossbeam_channel::bounded(1);
+            let stopping = handle.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("mcp-stop-{}", handle.id()))
+                .spawn(move || {
+                    let _ = tx.send(stopping.write().stop());
+                });
+            if let Err(err) = spawned {
+                return Some(Err(err.into()));
+            }
+            Some(match rx.recv_timeout(deadline) {
+                Ok(stopped) => stopped,
+                Err(_) => Err(McpClientError::Timeout(deadline).into()),
+            })
+        })
+    }
+
+    /// Start the keep-warm servers in the background and list their tools
+    /// ahead of the first call
+    ///
+    /// Meant to run once the first frame is painted, so warming servers up
+    /// doesn't delay the window; running it again restarts keep-warm servers
+    /// that stopped.
+    pub fn warm_up_servers(&self) -> McpOperation<McpBatchResult> {
+        let capabilities = self.capabilities.clone();
+        let subscriptions = self.subscriptions.clone();
+        self.run_batch(move |handle| {
+            let mut server = handle.write();
+            if !server.server_info().keep_warm {
+                return None;
+            }
+            Some(warm_up_server(
+                handle.id(),
+                &mut **server,
+                &capabilities,
+                &subscriptions,
+            ))
+        })
+    }
+
+    /// Suspend the running servers without a tool call for the idle timeout
+    /// in the background
+    ///
+    /// A server is only timed from the first check that sees it, so one
+    /// started without being called isn't suspended right away. Keep-warm
+    /// servers and servers that can't list their tools are left running,
+    /// since the tools of the latter couldn't be offered while they are
+    /// suspended.
+    pub fn suspend_idle_servers(&self) -> McpOperation<McpBatchResult> {
+        let settings = self.idle_suspend();
+        let last_calls = self.last_calls.clone();
+        let suspended = self.suspended.clone();
+        let capabilities = self.capabilities.clone();
+        let now = self.clock.instant();
+        self.run_batch(move |handle| {
+            let is_idle = || {
+                let last_call =
+                    *last_calls.entry(handle.id().to_string()).or_insert(now);
+                now.duration_since(last_call) >= settings.idle_timeout()
+            };
+            if !settings.enabled || !is_idle() {
+                return None;
+            }
+            let mut server = handle.write();
+            // A call may have finished while waiting for the lock
+            if !server.is_running() || !is_idle() || server.server_info().keep_warm {
+                return None;
+            }
+            let stopped = suspend_server(
+                handle.id(),
+                &mut **server,
+                &capabilities,
+                &suspended,
+            )?;
+            Some(stopped.map(|()| {
+                tracing::info!("Suspended idle MCP server '{}'", handle.id());
+            }))
+        })
+    }
+
+    /// Suspend the running servers that aren't kept warm in the
+    /// background, as when focus mode starts
+    ///
+    /// Like idle servers, they are started again on their next tool call.
+    pub fn suspend_non_essential_servers(&self) -> McpOperation<McpBatchResult> {
+        let suspended = self.suspended.clone();
+        let capabilities = self.capabilities.clone();
+        self.run_batch(move |handle| {
+            let mut server = handle.write();
+            if !server.is_running() || server.server_info().keep_warm {
+                return None;
+            }
+            suspend_server(handle.id(), &mut **server, &capabilities, &suspended)
+        })
+    }
+
+    /// Suspend idle servers in the background at the check interval of the
+    /// idle settings, for as long as the application runs
+    pub fn start_idle_suspension(&self) {
+        let registry = self.clone();
+        std::thread::Builder::new()
+            .name("McpIdleSuspension".to_string())
+            .spawn(move || {
+                loop {
+                    registry.clock.sleep(registry.idle_suspend().check_interval());
+                    let _ = registry.suspend_idle_servers().wait();
+                }
+            })
+            .ok();
+    }
+
+    /// Start a server again if it was suspended, sampling how long the cold
+    /// restart took
+    fn resume_if_suspended(&self, handle: &McpServerHandle) -> Result<()> {
+        self.resume(handle, false)
+    }
+
+    /// Record a tool call to a server, starting the server again if it was
+    /// suspended
+    ///
+    /// The call is recorded before the server is checked, and again before
+    /// a resumed server is unlocked, so the idle suspender, which checks
+    /// under the server's lock, either sees the call or has suspended the
+    /// server by then.
+    fn resume_for_call(&self, handle: &McpServerHandle) -> Result<()> {
+        self.last_calls
+            .insert(handle.id().to_string(), self.clock.instant());
+        // Wait for a suspension in progress to finish
+        drop(handle.read());
+        self.resume(handle, true)
+    }
+
+    fn resume(&self, handle: &McpServerHandle, record_call: bool) -> Result<()> {
+        if !self.suspended.contains_key(handle.id()) {
+            return Ok(());
+        }
+        let mut server = handle.write();
+        if !server.is_running() {
+            let started = self.clock.instant();
+            start_transport(&mut **server).map_err(|err| {
+                err.context(format!(
+                    "Failed to resume suspended MCP server '{}'",
+                    handle.id()
+                ))
+            })?;
+            let latency = self.clock.instant().duration_since(started);
+            MetricsRegistry::global().record_sample(
+                &mcp_cold_start_metric(handle.id()),
+                latency.as_secs_f64() * 1000.0,
+            );
+            tracing::info!("Resumed MCP server '{}' in {:?}", handle.id(), latency);
+            restore_subscriptions(handle.id(), &**server, &self.subscriptions);
+            refresh_capabilities(handle.id(), &**server, &self.capabilities);
+        }
+        if record_call {
+            self.last_calls
+                .insert(handle.id().to_string(), self.clock.instant());
+        }
+        self.suspended.remove(handle.id());
+        Ok(())
+    }
+
+    /// Run a lifecycle operation on every server, at most
+    /// [`MAX_PARALLEL_SERVER_OPERATIONS`] at a time, skipping servers for
+    /// which `operation` returns `None`
+    fn run_batch<F>(&self, operation: F) -> McpOperation<McpBatchResult>
+    where
+        F: Fn(&McpServerHandle) -> Option<Result<()>> + Send + Sync + 'static,
+    {
+        self.run_parallel(
+            move |handle| {
+                operation(handle).map(|result| {
+                    result.map_err(|err| {
+                        let retryable = McpClientError::of(&err)
+                            .is_some_and(McpClientError::is_retryable);
+                        (format!("{err:#}"), retryable)
+                    })
+                })
+            },
+            |results| {
+                let mut batch = McpBatchResult::default();
+                for (id, result) in results {
+                    match result {
+                        Ok(()) => batch.succeeded.push(id),
+                        Err((err, retryable)) => {
+                            tracing::error!("MCP server '{}' failed: {}", id, err);
+                            if retryable {
+                                batch.retryable.push(id.clone());
+                            }
+                            batch.failed.push((id, err));
+                        }
+                    }
+                }
+                batch
+            },
+        )
+    }
+
+    /// Run `f` for every server on the lifecycle pool and pass the results,
+    /// keyed by server id, to `finish`
+    fn run_parallel<T: Send, U: Send + 'static>(
+        &self,
+        f: impl Fn(&McpServerHandle) -> Option<T> + Send + Sync + 'static,
+        finish: impl FnOnce(Vec<(String, T)>) -> U + Send + 'static,
+    ) -> McpOperation<U> {
+        let handles = self.handles();
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        LIFECYCLE_POOL.spawn(move || {
+            let results = handles
+                .par_iter()
+                .filter_map(|handle| {
+                    f(handle).map(|result| (handle.id().to_string(), result))
+                })
+                .collect();
+            let _ = tx.send(finish(results));
+        });
+        McpOperation { receiver: rx }
+    }
+
+    /// Collect the tools of all healthy servers for the agent's toolset
+    ///
+    /// Servers that are not running or fail to list their tools are left out
+    /// and recorded as degraded, so a broken server shrinks the toolset
+    /// instead of failing the turn. Suspended servers offer the tools they
+    /// listed before they were stopped.
+    pub fn available_tools(
+        &self,
+        tracker: &DegradationTracker,
+    ) -> Vec<(String, McpTool)> {
+        let mut tools = Vec::new();
+        for handle in self.handles() {
+            let id = handle.id().to_string();
+            let subsystem = Subsystem::McpServer(id.clone());
+            if let Some(suspended) = self.suspended.get(&id) {
+                tracker.recover(&subsystem);
+                tools
+                    .extend(suspended.iter().map(|tool| (id.clone(), tool.clone())));
+                continue;
+            }
+            let server = handle.read();
+            let health = server.health_check();
+            if !server.is_running() || health.status != McpServerStatus::Running {
+                let reason = health
+                    .last_error
+                    .unwrap_or_else(|| format!("server is {:?}", health.status));
+                tracker.report(subsystem, &reason, "tools unavailable");
+                continue;
+            }
+            match self.capabilities.tools(&id, &**server) {
+                Ok(server_tools) => {
+                    tracker.recover(&subsystem);
+                    tools.extend(
+                        server_tools.iter().map(|tool| (id.clone(), tool.clone())),
+                    );
+                }
+                Err(err) => {
+                    tracker.report(
+                        subsystem,
+                        &format!("{err:#}"),
+                        "tools unavailable",
+                    );
+                }
+            }
+        }
+        tools
+    }
+
+    /// Call a tool, turning a failing or missing server into an error result
+    /// the agent can read instead of an error that aborts the turn
+    ///
+    /// Calls over the server's concurrency limit wait for a running call to
+    /// finish; calls over its rate limit get an error result telling the
+    /// agent when to retry, without marking the server degraded. Calls
+    /// taking longer than the server's
+    /// [`request_timeout`](McpServerInfo::request_timeout) are cancelled.
+    pub fn call_tool_degraded(
+        &self,
+        server_id: &str,
+        tool_name: &str,
+        arguments: serde_json::Value,
+        tracker: &DegradationTracker,
+    ) -> McpToolResult {
+        self.call_tool_until(server_id, tool_name, arguments, tracker, None)
+    }
+
+    /// Call a tool like [`Self::call_tool_degraded`], cancelling it at
+    /// `deadline` if that comes before the server's timeout
+    ///
+    /// Lets a caller with a deadline of its own, such as an agent turn,
+    /// pass on the time it has left.
+    pub fn call_tool_until(
+        &self,
+        server_id: &str,
+        tool_name: &str,
+        arguments: serde_json::Value,
+        tracker: &DegradationTracker,
+        deadline: Option<Instant>,
+    ) -> McpToolResult {
+        let _scope = alloc_scope(AllocTag::Mcp);
+        let request_id = new_correlation_id();
+        let _span = correlation_span("mcp_tool", &request_id).entered();
+        tracing::debug!(server_id, tool_name, "Calling MCP tool");
+        if let Some(root) = self
+            .roots
+            .read()
+            .disabled_root_in_call(server_id, &arguments)
+        {
+            return tool_error_result(format!(
+                "Tool '{}' can't be used on '{}': server '{}' is disabled for \
+                 this folder",
+                tool_name, root.name, server_id
+            ));
+        }
+        let subsystem = Subsystem::McpServer(server_id.to_string());
+        let limiter = self
+            .limiters
+            .get(server_id)
+            .map(|limiter| limiter.value().clone());
+        let queue_deadline = deadline
+            .unwrap_or_else(|| self.clock.instant() + DEFAULT_QUEUE_TIMEOUT);
+        let _permit = match limiter
+            .as_deref()
+            .map(|limiter| limiter.acquire(queue_deadline))
+        {
+            Some(Err(CallRefused::RateLimited(retry_after))) => {
+                return tool_error_result(format!(
+                    "Tool '{}' is unavailable: {}",
+                    tool_name,
+                    rate_limit_message(retry_after)
+                ));
+            }
+            Some(Err(CallRefused::QueueTimeout(waited))) => {
+                return tool_error_result(format!(
+                    "Tool '{}' is unavailable: waited {}s for other calls to \
+                     '{}' to finish",
+                    tool_name,
+                    waited.as_secs(),
+                    server_id
+                ));
+            }
+            permit => permit,
+        };
+        let result = match self.get_server(server_id) {
+            Some(handle) => self.resume_for_call(&handle).and_then(|()| {
+                let started = self.clock.instant();
+                let timeout = request_timeout(&handle, deadline, &*self.clock);
+                let tool = tool_name.to_string();
+                let id = request_id.clone();
+                let result = call_with_timeout(
+                    &handle,
+                    &request_id,
+                    timeout,
+                    move |caller| caller.call_tool(&id, &tool, arguments),
+                );
+                MetricsRegistry::global().record_sample(
+                    &mcp_latency_metric(server_id),
+                    self.clock.instant().duration_since(started).as_secs_f64()
+                        * 1000.0,
+                );
+                let recorded = self
+                    .usage
+                    .as_ref()
+                    .map(|usage| usage.record_call(server_id, tool_name));
+                if let Some(Err(err)) = recorded {
+                    tracing::warn!("Failed to record MCP tool usage: {err:#}");
+                }
+                result
+            }),
+            None => Err(anyhow::anyhow!(
+                "MCP server with id '{}' is not registered",
+                server_id
+            )),
+        };
+        let err = match result {
+            Ok(result) => return result,
+            Err(err) => err,
+        };
+        let message = format!("{err:#}");
+        match McpClientError::of(&err) {
+            // The server is fine, it refused this call
+            Some(failure) if !failure.is_server_failure() => {
+                let hint = if failure.is_retryable() {
+                    "; the call may be retried"
+                } else {
+                    ""
+                };
+                tool_error_result(format!(
+                    "Tool '{}' failed: {}{}",
+                    tool_name, message, hint
+                ))
+            }
+            failure => {
+                tracker.report(subsystem, &message, "tools unavailable");
+                let hint = if failure.is_some_and(McpClientError::is_retryable) {
+                    "; the failure is temporary, the call may be retried"
+                } else {
+                    ""
+                };
+                tool_error_result(format!(
+                    "Tool '{}' is unavailable: {}{}",
+                    tool_name, message, hint
+                ))
+            }
+        }
+    }
+
+    /// Send a request to a server, cancelling it once it takes longer than
+    /// the server's [`request_timeout`](McpServerInfo::request_timeout) or
+    /// `deadline`, whichever comes first
+    pub fn send_request(
+        &self,
+        server_id: &str,
+        request: McpRequest,
+        deadline: Option<Instant>,
+    ) -> Result<McpResponse> {
+        let handle = self.get_server(server_id).ok_or_else(|| {
+            anyhow::anyhow!("MCP server with id '{}' is not registered", server_id)
+        })?;
+        self.resume_if_suspended(&handle)?;
+        let timeout = request_timeout(&handle, deadline, &*self.clock);
+        let request_id = request.id.to_string();
+        call_with_timeout(&handle, &request_id, timeout, move |caller| {
+            caller.send_request(request)
+        })
+    }
+
+    /// Probe the health of all servers in the background
+    pub fn get_all_health_status(
+        &self,
+    ) -> McpOperation<HashMap<String, McpServerHealth>> {
+        self.run_parallel(
+            |handle| Some(handle.read().health_check()),
+            |results| results.into_iter().collect(),
+        )
+    }
+}
+
+/// Get the time a request to a server may take, cut short by `deadline`
+fn request_timeout(
+    handle: &McpServerHandle,
+    deadline: Option<Instant>,
+    clock: &dyn Clock,
+) -> Duration {
+    let timeout = handle.read().server_info().request_timeout();
+    match deadline {
+        Some(deadline) => {
+            timeout.min(deadline.saturating_duration_since(clock.instant()))
+        }
+        None => timeout,
+    }
+}
+
+/// Run `call` on a thread of its own and wait for it up to `timeout`
+///
+/// A call still running then is cancelled on the server by `request_id`,
+/// left to finish on its thread, and fails with
+/// [`McpClientError::Timeout`], so a hung server can't hold up the agent.
+/// The call runs on the server's [`McpCaller`], so it doesn't keep the
+/// server from being stopped or restarted meanwhile.
+fn call_with_timeout<T: Send + 'static>(
+    handle: &McpServerHandle,
+    request_id: &str,
+    timeout: Duration,
+    call: impl FnOnce(&dyn McpCaller) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    if timeout.is_zero() {
+        return Err(McpClientError::Timeout(timeout).into());
+    }
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    let caller = handle.caller();
+    let calling = caller.clone();
+    std::thread::Builder::new()
+        .name(format!("mcp-call-{}", handle.id()))
+        .spawn(move || {
+            let _ = tx.send(call(calling.as_ref()));
+        })?;
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+            tracing::warn!(
+                "MCP request '{}' to '{}' timed out after {:?}",
+                request_id,
+                handle.id(),
+                timeout
+            );
+            let reason = format!("Timed out after {:?}", timeout);
+            if let Err(err) = caller.cancel_request(request_id, Some(&reason)) {
+                tracing::debug!("Failed to cancel MCP request: {err:#}");
+            }
+            Err(McpClientError::Timeout(timeout).into())
+        }
+        Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+            Err(McpClientError::Transport(format!(
+                "request '{}' ended without a result",
+                request_id
+            ))
+            .into())
+        }
+    }
+}
+
+/// Tool result carrying an error message for the agent
+fn tool_error_result(message: String) -> McpToolResult {
+    McpToolResult {
+        content: vec![McpContent {
+            content_type: "text".to_string(),
+            data: serde_json::Value::String(message),
+            hint: None,
+        }],
+        is_error: true,
+    }
+}
+
+/// Start a server and agree on a protocol version with it
+///
+/// A server whose handshake fails is stopped again, so it isn't used in a
+/// version Catalyst doesn't speak.
+fn start_transport(server: &mut dyn McpServerPlugin) -> Result<()> {
+    server.start()?;
+    if let Err(err) = server.handshake() {
+        if let Err(stop_err) = server.stop() {
+            tracing::warn!(
+                "Failed to stop MCP server '{}' after its handshake failed: {:#}",
+                server.server_info().id,
+                stop_err
+            );
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Start a server and cache what it offers
+fn start_server(
+    server_id: &str,
+    server: &mut dyn McpServerPlugin,
+    capabilities: &McpCapabilityCache,
+) -> Result<()> {
+    start_transport(server)?;
+    refresh_capabilities(server_id, server, capabilities);
+    Ok(())
+}
+
+/// Cache what a started server offers
+///
+/// A server failing to list its tools is logged rather than failing the
+/// start; they are listed again on the next request.
+fn refresh_capabilities(
+    server_id: &str,
+    server: &dyn McpServerPlugin,
+    capabilities: &McpCapabilityCache,
+) {
+    if let Err(err) = capabilities.refresh(server_id, server) {
+        capabilities.remove(server_id);
+        tracing::warn!(
+            "Failed to list the capabilities of MCP server '{}': {:#}",
+            server_id,
+            err
+        );
+    }
+}
+
+/// Start a keep-warm server if it isn't running and cache what it offers
+fn warm_up_server(
+    server_id: &str,
+    server: &mut dyn McpServerPlugin,
+    capabilities: &McpCapabilityCache,
+    subscriptions: &DashMap<String, BTreeSet<String>>,
+) -> Result<()> {
+    if !server.is_running() {
+        start_transport(server)?;
+        restore_subscriptions(server_id, server, subscriptions);
+    }
+    capabilities.refresh(server_id, server)
+}
+
+/// Subscribe a restarted server to the resources it was subscribed to
+///
+/// A failed subscription is logged rather than failing the restart, since
+/// the server can still serve tool calls.
+fn restore_subscriptions(
+    server_id: &str,
+    server: &dyn McpServerPlugin,
+    subscriptions: &DashMap<String, BTreeSet<String>>,
+) {
+    let Some(uris) = subscriptions.get(server_id) else {
+        return;
+    };
+    for uri in uris.iter() {
+        if let Err(err) = server.subscribe_to_resource(uri) {
+            tracing::warn!(
+                "Failed to subscribe MCP server '{}' to {} again: {:#}",
+                server_id,
+                uri,
+                err
+            );
+        }
+    }
+}
+
+/// Outcome of a lifecycle operation run on several servers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct McpBatchResult {
+    pub succeeded: Vec<String>,
+    /// Server ids with their error messages
+    pub failed: Vec<(String, String)>,
+    /// Ids of the failed servers whose failure is transient, see
+    /// [`McpClientError::is_retryable`]
+    pub retryable: Vec<String>,
+}
+
+impl McpBatchResult {
+    /// Turn the failures into a single error
+    pub fn into_result(self) -> Result<()> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        let failures: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(id, err)| format!("{id}: {err}"))
+            .collect();
+        Err(anyhow::anyhow!(
+            "MCP server operation failed for {}",
+            failures.join(", ")
+        ))
+    }
+}
+
+/// Result of an operation running in the background
+///
+/// The UI polls [`McpOperation::try_take`] or selects on
+/// [`McpOperation::receiver`]; only code off the UI thread should
+/// [`wait`](McpOperation::wait).
+pub struct McpOperation<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T: Send + 'static> McpOperation<T> {
+    /// Create an operation that already completed
+    pub fn ready(value: T) -> Self {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = tx.send(value);
+        Self { receiver: rx }
+    }
+
+    /// Block until the operation completes
+    pub fn wait(self) -> Result<T> {
+        self.receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("MCP operation was abandoned"))
+    }
+
+    /// Get the result if the operation has completed
+    pub fn try_take(&self) -> Result<Option<T>> {
+        match self.receiver.try_recv() {
+            Ok(value) => Ok(Some(value)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("MCP operation was abandoned"))
+            }
+        }
+    }
+
+    /// Get the channel the result is delivered on
+    pub fn receiver(&self) -> &Receiver<T> {
+        &self.receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::ManualClock;
+    use std::io::Read;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::SystemTime;
+
+    #[derive(Default)]
+    struct FakeServer {
+        running: bool,
+        keep_warm: bool,
+        call_limits: McpCallLimits,
+        starts: Arc<AtomicUsize>,
+        subscriptions: Arc<parking_lot::Mutex<Vec<String>>>,
+        sink: Arc<parking_lot::Mutex<Option<McpNotificationSink>>>,
+        request_handler: Arc<parking_lot::Mutex<Option<McpRequestHandler>>>,
+        cancelled: Arc<parking_lot::Mutex<Vec<String>>>,
+        notifications: Arc<parking_lot::Mutex<Vec<String>>>,
+        prompts: bool,
+        /// Version answered to `initialize` instead of the one asked for
+        protocol_version: Option<&'static str>,
+        /// Times the tools were listed
+        listings: Arc<AtomicUsize>,
+        /// Times a resource was read
+        resource_reads: Arc<AtomicUsize>,
+        caller: Option<Arc<FakeCaller>>,
+        /// Ends a call of the `hang` tool when sent to or dropped; without
+        /// it the tool answers right away
+        hang_release: Option<Receiver<()>>,
+    }
+
+    /// Caller whose `hang` tool answers only once the test releases it,
+    /// whether cancelled or not
+    struct FakeCaller {
+        sent: parking_lot::Mutex<Vec<String>>,
+        cancelled: parking_lot::Mutex<Vec<String>>,
+        release: (crossbeam_channel::Sender<()>, Receiver<()>),
+    }
+
+    impl FakeCaller {
+        fn new() -> Self {
+            Self {
+                sent: Default::default(),
+                cancelled: Default::default(),
+                release: crossbeam_channel::unbounded(),
+            }
+        }
+    }
+
+    impl McpCaller for FakeCaller {
+        fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+            self.sent.lock().push(request.id.to_string());
+            let params = request.params.unwrap_or_default();
+            if params["name"] == "hang" {
+                let _ = self.release.1.recv();
+            }
+            Ok(McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(serde_json::json!({
+                    "content": [{ "type": "text", "text": "done" }],
+                })),
+                error: None,
+            })
+        }
+
+        fn cancel_request(&self, id: &str, _reason: Option<&str>) -> Result<()> {
+            self.cancelled.lock().push(id.to_string());
+            Ok(())
+        }
+    }
+
+    impl McpServerPlugin for FakeServer {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn server_info(&self) -> McpServerInfo {
+            McpServerInfo {
+                id: "fake".to_string(),
+                name: "Fake".to_string(),
+                description: String::new(),
+                version: "1.0.0".to_string(),
+                command: Vec::new(),
+                args: Vec::new(),
+                env: HashMap::new(),
+                working_directory: None,
+                auto_start: true,
+                keep_warm: self.keep_warm,
+                capabilities: McpServerCapabilities {
+                    tools: true,
+                    resources: false,
+                    prompts: self.prompts,
+                    logging: false,
+                    experimental: HashMap::new(),
+                },
+                resource_limits: McpResourceLimits::default(),
+                call_limits: self.call_limits,
+                request_timeout_secs: None,
+            }
+        }
+
+        fn start(&mut self) -> Result<()> {
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            self.running = true;
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            self.running = false;
+            Ok(())
+        }
+
+        fn is_running(&self) -> bool {
+            self.running
+        }
+
+        fn health_check(&self) -> McpServerHealth {
+            McpServerHealth {
+                status: if self.running {
+                    McpServerStatus::Running
+                } else {
+                    McpServerStatus::Stopped
+                },
+                last_error: None,
+                uptime: None,
+                request_count: 0,
+                error_count: 0,
+            }
+        }
+
+        fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+            let params = request.params.unwrap_or_default();
+            let result = match request.method.as_str() {
+                "initialize" => serde_json::json!({
+                    "protocolVersion": self
+                        .protocol_version
+                        .unwrap_or_else(|| negotiate_protocol_version(Some(&params))),
+                    "capabilities": { "tools": {} },
+                    "serverInfo": { "name": "fake", "version": "1.0.0" },
+                }),
+                "prompts/list" => serde_json::json!({
+                    "prompts": [{
+                        "name": "review",
+                        "description": "Review a file",
+                        "arguments": [{ "name": "path", "required": true }]
+                    }]
+                }),
+                "prompts/get" if params["name"] == "review" => serde_json::json!({
+                    "messages": [{
+                        "role": "user",
+                        "content": {
+                            "type": "text",
+                            "text": format!(
+                                "Review {}",
+                                params["arguments"]["path"].as_str().unwrap_or_default()
+                            )
+                        }
+                    }]
+                }),
+                "prompts/get" => {
+                    return Ok(McpResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id: request.id,
+                        result: None,
+                        error: Some(McpError {
+                            code: INVALID_PARAMS,
+                            message: "Unknown prompt".to_string(),
+                            data: None,
+                        }),
+                    });
+                }
+                _ => return Err(anyhow::anyhow!("not supported")),
+            };
+            Ok(McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(result),
+                error: None,
+            })
+        }
+
+        fn get_tools(&self) -> Result<Vec<McpTool>> {
+            self.listings.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![McpTool {
+                name: "echo".to_string(),
+                description: Some("Echo the arguments".to_string()),
+                input_schema: serde_json::json!({"type": "object"}),
+                effect: Default::default(),
+            }])
+        }
+
+        fn get_resources(&self) -> Result<Vec<McpResource>> {
+            Ok(Vec::new())
+        }
+
+        fn call_tool(
+            &self,
+            tool_name: &str,
+            arguments: serde_json::Value,
+        ) -> Result<McpToolResult> {
+            if !self.running {
+                return Err(anyhow::anyhow!("server is stopped"));
+            }
+            match tool_name {
+                "refuse" => {
+                    return Err(McpClientError::ServerError(McpError {
+                        code: INVALID_PARAMS,
+                        message: "missing argument 'path'".to_string(),
+                        data: None,
+                    })
+                    .into());
+                }
+                "hang" => {
+                    if let Some(release) = &self.hang_release {
+                        let _ = release.recv();
+                    }
+                }
+                _ => {}
+            }
+            Ok(McpToolResult {
+                content: vec![McpContent {
+                    content_type: "text".to_string(),
+                    data: arguments,
+                    hint: None,
+                }],
+                is_error: false,
+            })
+        }
+
+        fn read_resource(&self, resource_uri: &str) -> Result<McpResourceContent> {
+            if !self.running {
+                return Err(anyhow::anyhow!("server is stopped"));
+            }
+            self.resource_reads.fetch_add(1, Ordering::SeqCst);
+            Ok(McpResourceContent {
+                uri: resource_uri.to_string(),
+                mime_type: Some("text/plain".to_string()),
+                text: Some(format!("contents of {resource_uri}")),
+                blob: None,
+            })
+        }
+
+        fn subscribe_to_resource(&self, resource_uri: &str) -> Result<()> {
+            self.subscriptions.lock().push(resource_uri.to_string());
+            Ok(())
+        }
+
+        fn unsubscribe_from_resource(&self, _resource_uri: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn cancel_request(&self, id: &str) -> Result<()> {
+            self.cancelled.lock().push(id.to_string());
+            Ok(())
+        }
+
+        fn caller(&self) -> Option<Arc<dyn McpCaller>> {
+            self.caller
+                .clone()
+                .map(|caller| caller as Arc<dyn McpCaller>)
+        }
+
+        fn send_notification(&self, notification: McpNotification) -> Result<()> {
+            self.notifications.lock().push(notification.method);
+            Ok(())
+        }
+
+        fn set_notification_sink(&mut self, sink: McpNotificationSink) {
+            *self.sink.lock() = Some(sink);
+        }
+
+        fn set_request_handler(&mut self, handler: McpRequestHandler) {
+            *self.request_handler.lock() = Some(handler);
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        received: parking_lot::Mutex<Vec<(String, McpServerNotification)>>,
+    }
+
+    impl McpNotificationHandler for RecordingHandler {
+        fn handle_notification(
+            &self,
+            server_id: &str,
+            notification: &McpServerNotification,
+        ) {
+            self.received
+                .lock()
+                .push((server_id.to_string(), notification.clone()));
+        }
+    }
+
+    #[test]
+    fn test_idle_servers_are_suspended_and_resumed() {
+        let registry = McpServerRegistry::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    starts: starts.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        registry.start_auto_start_servers().wait().unwrap();
+
+        // Not idle long enough
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert!(batch.succeeded.is_empty());
+
+        let settings = IdleSuspendSettings {
+            idle_timeout_secs: 0,
+            check_interval_secs: 0,
+            ..IdleSuspendSettings::default()
+        };
+        assert_eq!(settings.check_interval(), Duration::from_secs(1));
+        registry.set_idle_suspend(settings);
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert_eq!(batch.succeeded, ["fake"]);
+        assert!(registry.is_suspended("fake"));
+        assert!(!registry.get_server("fake").unwrap().read().is_running());
+
+        // The suspended server keeps offering its tools
+        let tracker = DegradationTracker::default();
+        let tools = registry.available_tools(&tracker);
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].1.name, "echo");
+
+        let result = registry.call_tool_degraded(
+            "fake",
+            "echo",
+            serde_json::json!("hi"),
+            &tracker,
+        );
+        assert!(!result.is_error);
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        assert!(!registry.is_suspended("fake"));
+        assert_eq!(
+            MetricsRegistry::global()
+                .history(&mcp_cold_start_metric("fake"))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_idle_checks_and_deadlines_follow_the_clock() {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let registry = McpServerRegistry::new().with_clock(clock.clone());
+        registry
+            .register_server(
+                "clocked".to_string(),
+                Box::new(FakeServer::default()),
+            )
+            .unwrap();
+        registry.start_auto_start_servers().wait().unwrap();
+
+        let idle_timeout = registry.idle_suspend().idle_timeout();
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert!(batch.succeeded.is_empty());
+        clock.advance(idle_timeout - Duration::from_secs(1));
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert!(batch.succeeded.is_empty());
+        clock.advance(Duration::from_secs(1));
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert_eq!(batch.succeeded, ["clocked"]);
+
+        // A deadline passed on the clock fails the call without waiting
+        let deadline = clock.instant() + Duration::from_secs(5);
+        clock.advance(Duration::from_secs(6));
+        let request = McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "r1".into(),
+            method: "prompts/list".to_string(),
+            params: None,
+        };
+        let err = registry
+            .send_request("clocked", request, Some(deadline))
+            .unwrap_err();
+        assert!(matches!(
+            McpClientError::of(&err),
+            Some(McpClientError::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_keep_warm_servers() {
+        let registry = McpServerRegistry::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let subscriptions = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        registry
+            .register_server(
+                "warm".to_string(),
+                Box::new(FakeServer {
+                    keep_warm: true,
+                    starts: starts.clone(),
+                    subscriptions: subscriptions.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        registry
+            .register_server("cold".to_string(), Box::new(FakeServer::default()))
+            .unwrap();
+
+        let batch = registry.warm_up_servers().wait().unwrap();
+        assert_eq!(batch.succeeded, ["warm"]);
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        registry
+            .subscribe_resource("warm", "file:///notes.md")
+            .unwrap();
+
+        // Keep-warm servers are never suspended
+        let settings = IdleSuspendSettings {
+            idle_timeout_secs: 0,
+            check_interval_secs: 0,
+            ..IdleSuspendSettings::default()
+        };
+        assert_eq!(settings.check_interval(), Duration::from_secs(1));
+        registry.set_idle_suspend(settings);
+        let batch = registry.suspend_idle_servers().wait().unwrap();
+        assert!(batch.succeeded.is_empty());
+
+        let tools = registry.available_tools(&DegradationTracker::default());
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].0, "warm");
+
+        // A stopped server is started again with its subscriptions
+        registry.get_server("warm").unwrap().write().stop().unwrap();
+        registry.warm_up_servers().wait().unwrap();
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *subscriptions.lock(),
+            ["file:///notes.md", "file:///notes.md"]
+        );
+
+        // Focus mode only suspends the servers that aren't kept warm
+        registry
+            .start_servers(vec!["cold".to_string()])
+            .wait()
+            .unwrap();
+        let batch = registry.suspend_non_essential_servers().wait().unwrap();
+        assert_eq!(batch.succeeded, ["cold"]);
+        assert!(registry.is_suspended("cold"));
+        assert!(registry.get_server("warm").unwrap().read().is_running());
+    }
+
+    #[test]
+    fn test_rate_limited_calls() {
+        let registry = McpServerRegistry::new();
+        registry
+            .register_server(
+                "limited".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    call_limits: McpCallLimits {
+                        max_concurrency: Some(1),
+                        requests_per_minute: Some(1),
+                    },
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        let tracker = DegradationTracker::default();
+        let call = || {
+            registry.call_tool_degraded(
+                "limited",
+                "echo",
+                serde_json::json!("hi"),
+                &tracker,
+            )
+        };
+        assert!(!call().is_error);
+        let limited = call();
+        assert!(limited.is_error);
+        let message = limited.content[0].data.as_str().unwrap();
+        assert!(
+            message.starts_with(
+                "Tool 'echo' is unavailable: rate limited, retry after"
+            ),
+            "{message}"
+        );
+    }
+    #[test]
+    fn test_capability_cache() {
+        let registry = McpServerRegistry::new();
+        let listings = Arc::new(AtomicUsize::new(0));
+        let sink = Arc::new(parking_lot::Mutex::new(None));
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    listings: listings.clone(),
+                    sink: sink.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        registry.start_auto_start_servers().wait().unwrap();
+        assert_eq!(listings.load(Ordering::SeqCst), 1);
+        let cached = registry.capabilities.get("fake").unwrap();
+        assert!(cached.capabilities.unwrap().tools);
+        assert_eq!(cached.resources.unwrap().len(), 0);
+
+        // Repeated listings are answered from the cache
+        let tracker = DegradationTracker::default();
+        for _ in 0..3 {
+            assert_eq!(registry.available_tools(&tracker).len(), 1);
+        }
+        assert_eq!(registry.get_tools("fake").unwrap()[0].name, "echo");
+        assert_eq!(listings.load(Ordering::SeqCst), 1);
+
+        let mut callback = sink.lock().clone().unwrap().callback();
+        callback(McpNotification::new(RESOURCES_LIST_CHANGED, None));
+        assert!(registry.capabilities.cached_tools("fake").is_some());
+        callback(McpNotification::new(TOOLS_LIST_CHANGED, None));
+        assert_eq!(registry.available_tools(&tracker).len(), 1);
+        assert_eq!(registry.available_tools(&tracker).len(), 1);
+        assert_eq!(listings.load(Ordering::SeqCst), 2);
+
+        registry.stop_all_servers().wait().unwrap();
+        assert!(registry.capabilities.get("fake").is_none());
+    }
+
+    #[test]
+    fn test_server_notifications() {
+        let registry = McpServerRegistry::new();
+        let sink = Arc::new(parking_lot::Mutex::new(None));
+        registry
+            .register_server(
+                "warm".to_string(),
+                Box::new(FakeServer {
+                    keep_warm: true,
+                    sink: sink.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        registry.warm_up_servers().wait().unwrap();
+        assert!(registry.capabilities.cached_tools("warm").is_some());
+        let handler = Arc::new(RecordingHandler::default());
+        registry.add_notification_handler(handler.clone());
+
+        let sink = sink.lock().clone().unwrap();
+        assert_eq!(sink.server_id(), "warm");
+        let mut callback = sink.callback();
+        callback(McpNotification::new(TOOLS_LIST_CHANGED, None));
+        callback(McpNotification::new(
+            LOGGING_MESSAGE,
+            Some(serde_json::json!({"level": "warning", "data": "quota low"})),
+        ));
+        callback(McpNotification::new(
+            RESOURCE_UPDATED,
+            Some(serde_json::json!({"uri": "file:///notes.md"})),
+        ));
+
+        // The tools are listed again after they changed
+        assert!(registry.capabilities.cached_tools("warm").is_none());
+        let received = handler.received.lock();
+        assert_eq!(received.len(), 3);
+        assert_eq!(
+            received[0],
+            ("warm".to_string(), McpServerNotification::ToolsListChanged)
+        );
+        assert_eq!(
+            received[1].1,
+            McpServerNotification::Log {
+                level: "warning".to_string(),
+                logger: None,
+                data: serde_json::json!("quota low"),
+            }
+        );
+        assert_eq!(
+            received[2].1,
+            McpServerNotification::ResourceUpdated {
+                uri: "file:///notes.md".to_string(),
+            }
+        );
+    }
+    #[test]
+    fn test_resource_updates_are_debounced_and_read() {
+        let registry = McpServerRegistry::new();
+        let sink = Arc::new(parking_lot::Mutex::new(None));
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    sink: sink.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        let stream = registry.resource_updates(Duration::from_millis(50));
+        stream.subscribe("fake", "file:///notes.md").unwrap();
+        assert!(stream.subscribe("missing", "file:///notes.md").is_err());
+
+        let mut callback = sink.lock().clone().unwrap().callback();
+        for uri in ["file:///notes.md", "file:///notes.md", "file:///other.md"] {
+            callback(McpNotification::new(
+                RESOURCE_UPDATED,
+                Some(serde_json::json!({ "uri": uri })),
+            ));
+        }
+        // Not quiet for long enough yet
+        assert!(stream.try_recv().is_none());
+
+        let update = stream.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(update.uri, "file:///notes.md");
+        assert_eq!(update.notifications, 2);
+        assert_eq!(
+            update.content.unwrap().text.as_deref(),
+            Some("contents of file:///notes.md")
+        );
+        // Resources not subscribed to are ignored
+        assert!(stream.recv_timeout(Duration::from_millis(100)).is_none());
+
+        drop(stream);
+        assert!(registry.notification_handlers.read().is_empty());
+    }
+
+    #[test]
+    fn test_resources_without_range_reads_are_read_once() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let handle = McpServerHandle::new(
+            "fake",
+            Box::new(FakeServer {
+                running: true,
+                resource_reads: reads.clone(),
+                ..FakeServer::default()
+            }),
+        );
+        let mut reader = handle
+            .resource_reader("file:///notes.md")
+            .with_chunk_size(4);
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "contents of file:///notes.md");
+        assert_eq!(reader.total_size(), Some(28));
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        let mut reader = handle
+            .resource_reader("file:///notes.md")
+            .with_chunk_size(4)
+            .with_offset(12);
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "file:///notes.md");
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        let registry = McpServerRegistry::new();
+        let cancelled = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    cancelled: cancelled.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        registry.cancel_request("fake", "7").unwrap();
+        assert_eq!(*cancelled.lock(), ["7"]);
+        assert!(registry.cancel_request("missing", "7").is_err());
+    }
+
+    #[test]
+    fn test_prompts() {
+        let registry = McpServerRegistry::new();
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    prompts: true,
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        let prompts = registry.available_prompts();
+        assert_eq!(prompts.len(), 1);
+        assert_eq!(prompts[0].0, "fake");
+        assert_eq!(prompts[0].1.name, "review");
+        assert!(prompts[0].1.arguments[0].required);
+
+        let arguments =
+            HashMap::from([("path".to_string(), "src/main.rs".to_string())]);
+        let messages = registry.get_prompt("fake", "review", &arguments).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(messages[0].content.data, "Review src/main.rs");
+        let err = registry
+            .get_prompt("fake", "missing", &arguments)
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "prompts/get failed: MCP server error -32602: Unknown prompt"
+        );
+        assert!(matches!(
+            McpClientError::of(&err),
+            Some(McpClientError::ServerError(error)) if error.code == INVALID_PARAMS
+        ));
+
+        // Servers without the capability are not asked
+        let server = FakeServer::default();
+        assert!(server.get_prompts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sampling_refused_without_sampler() {
+        let registry = McpServerRegistry::new();
+        let server = FakeServer::default();
+        let request_handler = server.request_handler.clone();
+        registry
+            .register_server("fake".to_string(), Box::new(server))
+            .unwrap();
+        let handler = request_handler.lock().clone().unwrap();
+        assert_eq!(handler.server_id(), "fake");
+
+        let mut answer = handler.callback();
+        let response = answer(McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "s1".into(),
+            method: SAMPLING_CREATE_MESSAGE.to_string(),
+            params: None,
+        });
+        assert_eq!(response.id, "s1");
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_roots_are_listed_per_server() {
+        let registry = McpServerRegistry::new();
+        let server = FakeServer {
+            running: true,
+            ..FakeServer::default()
+        };
+        let request_handler = server.request_handler.clone();
+        let notifications = server.notifications.clone();
+        registry
+            .register_server("fake".to_string(), Box::new(server))
+            .unwrap();
+        let handler = request_handler.lock().clone().unwrap();
+        let list_roots = || {
+            let response = handler.handle(McpRequest {
+                jsonrpc: "2.0".to_string(),
+                id: "r1".into(),
+                method: ROOTS_LIST.to_string(),
+                params: None,
+            });
+            let roots = response.result.unwrap()["roots"].clone();
+            serde_json::from_value::<Vec<McpRoot>>(roots).unwrap()
+        };
+        assert!(list_roots().is_empty());
+
+        let mut roots = WorkspaceRoots::new([
+            PathBuf::from("/work/api"),
+            PathBuf::from("/work/web"),
+        ]);
+        roots
+            .set_server_enabled(Path::new("/work/web"), "fake", false)
+            .unwrap();
+        registry.set_workspace_roots(roots);
+        assert_eq!(*notifications.lock(), [ROOTS_LIST_CHANGED]);
+        assert_eq!(
+            list_roots(),
+            [McpRoot {
+                uri: "file:///work/api/".to_string(),
+                name: Some("api".to_string()),
+            }]
+        );
+        let (api_file, web_file) =
+            (Path::new("/work/api/a.rs"), Path::new("/work/web/a.rs"));
+        assert!(registry.is_server_enabled_for_path("fake", api_file));
+        assert!(!registry.is_server_enabled_for_path("fake", web_file));
+        assert!(registry.is_server_enabled_for_path("other", web_file));
+        let result = registry.call_tool_degraded(
+            "fake",
+            "read",
+            serde_json::json!({ "path": "/work/web/a.rs" }),
+            &DegradationTracker::default(),
+        );
+        assert!(result.is_error);
+        assert_eq!(
+            result.content[0].data.as_str().unwrap(),
+            "Tool 'read' can't be used on 'web': server 'fake' is disabled for \
+             this folder"
+        );
+
+        let docs = PathBuf::from("/work/docs");
+        registry.handle_hook(&PluginHook::ProjectOpened { root: docs.clone() });
+        registry.handle_hook(&PluginHook::ProjectOpened { root: docs.clone() });
+        assert_eq!(notifications.lock().len(), 2);
+        assert_eq!(list_roots().len(), 2);
+        registry.handle_hook(&PluginHook::ProjectClosed {
+            root: PathBuf::from("/work/api"),
+        });
+        assert_eq!(notifications.lock().len(), 3);
+        assert_eq!(
+            list_roots(),
+            [McpRoot {
+                uri: "file:///work/docs/".to_string(),
+                name: Some("docs".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_client_errors_are_classified() {
+        let registry = McpServerRegistry::new();
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        let tracker = DegradationTracker::default();
+        let subsystem = Subsystem::McpServer("fake".to_string());
+        let call = |tool_name| {
+            let result = registry.call_tool_degraded(
+                "fake",
+                tool_name,
+                serde_json::json!({}),
+                &tracker,
+            );
+            assert!(result.is_error);
+            result.content[0].data.as_str().unwrap().to_string()
+        };
+
+        // A refused call is the caller's problem, not the server's
+        assert_eq!(
+            call("refuse"),
+            "Tool 'refuse' failed: MCP server error -32602: missing argument 'path'"
+        );
+        assert!(!tracker.is_degraded(&subsystem));
+
+        let timeout = McpClientError::Timeout(Duration::from_secs(1));
+        assert!(timeout.is_retryable() && timeout.is_server_failure());
+        let violation = McpClientError::result_of(
+            "tools/list",
+            McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: "1".into(),
+                result: None,
+                error: None,
+            },
+        )
+        .unwrap_err();
+        assert!(!violation.is_retryable());
+        assert_eq!(
+            violation.to_string(),
+            "MCP protocol violation: tools/list returned no result"
+        );
+    }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let server = FakeServer::default();
+        let initialized = server.handshake().unwrap();
+        assert_eq!(initialized.protocol_version, MCP_PROTOCOL_VERSION);
+        assert_eq!(initialized.server_name.as_deref(), Some("fake"));
+        assert!(!initialized.is_downgraded());
+        assert_eq!(*server.notifications.lock(), vec![INITIALIZED.to_string()]);
+
+        let answer = |version: &str| {
+            McpInitializeResult::from_result(&serde_json::json!({
+                "protocolVersion": version,
+                "capabilities": {},
+            }))
+        };
+        assert!(!answer("2024-11-05").unwrap().is_downgraded());
+        assert!(answer("2025-03-26").is_err());
+        let err = answer("2099-01-01").unwrap_err();
+        assert!(!err.is_retryable() && err.is_server_failure());
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "MCP server speaks protocol version 2099-01-01, but Catalyst \
+                 supports only {}",
+                SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+            )
+        );
+        assert!(matches!(
+            McpInitializeResult::from_result(&serde_json::json!({})),
+            Err(McpClientError::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_start_handshakes() {
+        let registry = McpServerRegistry::new();
+        let server = FakeServer::default();
+        let notifications = server.notifications.clone();
+        registry
+            .register_server("fake".to_string(), Box::new(server))
+            .unwrap();
+        let newer = FakeServer {
+            protocol_version: Some("2025-03-26"),
+            ..FakeServer::default()
+        };
+        registry
+            .register_server("newer".to_string(), Box::new(newer))
+            .unwrap();
+
+        let batch = registry.start_auto_start_servers().wait().unwrap();
+        assert_eq!(batch.succeeded, ["fake"]);
+        assert_eq!(*notifications.lock(), [INITIALIZED]);
+        assert_eq!(batch.failed[0].0, "newer");
+        assert!(batch.failed[0].1.contains("protocol version 2025-03-26"));
+        assert!(batch.retryable.is_empty());
+        assert!(!registry.get_server("newer").unwrap().read().is_running());
+    }
+
+    #[test]
+    fn test_calls_time_out() {
+        let registry = McpServerRegistry::new();
+        let (_release, hang_release) = crossbeam_channel::unbounded();
+        let server = FakeServer {
+            running: true,
+            hang_release: Some(hang_release),
+            ..FakeServer::default()
+        };
+        let cancelled = server.cancelled.clone();
+        registry
+            .register_server("fake".to_string(), Box::new(server))
+            .unwrap();
+        let tracker = DegradationTracker::default();
+        let deadline = || Some(Instant::now() + Duration::from_millis(20));
+
+        let result = registry.call_tool_until(
+            "fake",
+            "hang",
+            serde_json::json!({}),
+            &tracker,
+            deadline(),
+        );
+        assert!(result.is_error);
+        let message = result.content[0].data.as_str().unwrap();
+        assert!(
+            message.starts_with("Tool 'hang' is unavailable: MCP server did not"),
+            "{message}"
+        );
+        assert!(message.ends_with("the call may be retried"), "{message}");
+        assert!(tracker.is_degraded(&Subsystem::McpServer("fake".to_string())));
+        assert_eq!(cancelled.lock().len(), 1);
+
+        // Calls within the deadline are not cut short
+        let result = registry.call_tool_until(
+            "fake",
+            "echo",
+            serde_json::json!("hi"),
+            &tracker,
+            deadline(),
+        );
+        assert!(!result.is_error);
+        let past = Some(Instant::now());
+        let request = McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "r1".into(),
+            method: "prompts/list".to_string(),
+            params: None,
+        };
+        let err = registry.send_request("fake", request, past).unwrap_err();
+        assert!(matches!(
+            McpClientError::of(&err),
+            Some(McpClientError::Timeout(_))
+        ));
+        assert_eq!(
+            FakeServer::default().server_info().request_timeout(),
+            DEFAULT_REQUEST_TIMEOUT
+        );
+    }
+
+    #[test]
+    fn test_hung_calls_leave_server_unlocked() {
+        let registry = McpServerRegistry::new();
+        let caller = Arc::new(FakeCaller::new());
+        let cancelled = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        registry
+            .register_server(
+                "fake".to_string(),
+                Box::new(FakeServer {
+                    running: true,
+                    caller: Some(caller.clone()),
+                    cancelled: cancelled.clone(),
+                    ..FakeServer::default()
+                }),
+            )
+            .unwrap();
+        let tracker = DegradationTracker::default();
+        let deadline = Some(Instant::now() + Duration::from_millis(20));
+        let result = registry.call_tool_until(
+            "fake",
+            "hang",
+            serde_json::json!({}),
+            &tracker,
+            deadline,
+        );
+        assert!(result.is_error);
+
+        // The call is cancelled by the JSON-RPC id it was sent with
+        let sent = caller.sent.lock().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(*caller.cancelled.lock(), sent);
+        assert!(cancelled.lock().is_empty());
+
+        // The server can be stopped while the call still hangs
+        let handle = registry.get_server("fake").unwrap();
+        handle.write().stop().unwrap();
+        assert!(!handle.read().is_running());
+        caller.release.0.send(()).unwrap();
+
+        registry.cancel_request("fake", "7").unwrap();
+        assert_eq!(caller.cancelled.lock().last().unwrap(), "7");
     }
 }
@@ -0,0 +1,488 @@
+//! Workspace Memory
+//!
+//! This module stores durable facts about a workspace ("tests run with make
+//! check", "deploy uses terraform in /infra") that the AI assistant records
+//! through the `remember` tool. Relevant memories are attached to the editor
+//! context of later requests, and users can review, edit and delete them in
+//! the memory panel.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    EditorContext, JournalOptions, JournalState, JournaledStore, PanelCommand,
+    PanelCommandResult, SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition,
+    ToolCall, ToolDefinition, ToolEffect, current_time, text_panel_view,
+};
+
+/// Name of the tool the assistant uses to record a memory
+pub const REMEMBER_TOOL_NAME: &str = "remember";
+
+/// Common words ignored when matching memories against a query
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "with", "how", "what", "this", "that", "use", "uses",
+    "are", "from", "into", "when", "does",
+];
+
+/// Who recorded a memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemorySource {
+    Agent,
+    User,
+}
+
+/// A durable fact about the workspace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub id: u64,
+    pub fact: String,
+    pub tags: Vec<String>,
+    pub source: MemorySource,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MemoryFile {
+    next_id: u64,
+    entries: Vec<MemoryEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum MemoryOp {
+    /// Add a new entry or replace the entry with the same id
+    Put(MemoryEntry),
+    Delete(u64),
+}
+
+impl JournalState for MemoryFile {
+    type Op = MemoryOp;
+
+    fn apply(&mut self, op: &MemoryOp) {
+        match op {
+            MemoryOp::Put(entry) => {
+                self.next_id = self.next_id.max(entry.id);
+                match self.entries.iter_mut().find(|e| e.id == entry.id) {
+                    Some(existing) => *existing = entry.clone(),
+                    None => self.entries.push(entry.clone()),
+                }
+            }
+            MemoryOp::Delete(id) => self.entries.retain(|entry| entry.id != *id),
+        }
+    }
+}
+
+/// Per-workspace store of agent memories
+pub struct WorkspaceMemoryStore {
+    inner: JournaledStore<MemoryFile>,
+}
+
+impl WorkspaceMemoryStore {
+    /// Open the memory store of a workspace, kept in the local data directory
+    pub fn open_for_workspace(workspace_root: &Path) -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?
+            .join("memories");
+        Self::open(&dir.join(format!("{}.json", workspace_key(workspace_root))))
+    }
+
+    /// Open a memory store backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            inner: JournaledStore::open(path, JournalOptions::default())?,
+        })
+    }
+
+    /// Record a fact, returning the existing entry if it is already known
+    pub fn remember(
+        &self,
+        fact: &str,
+        tags: Vec<String>,
+        source: MemorySource,
+    ) -> Result<MemoryEntry> {
+        let fact = fact.trim();
+        if fact.is_empty() {
+            return Err(anyhow::anyhow!("Cannot remember an empty fact"));
+        }
+
+        self.inner.transact(|inner| {
+            if let Some(existing) = inner
+                .entries
+                .iter()
+                .find(|entry| entry.fact.eq_ignore_ascii_case(fact))
+            {
+                return Ok((None, existing.clone()));
+            }
+
+            let now = current_time();
+            let entry = MemoryEntry {
+                id: inner.next_id + 1,
+                fact: fact.to_string(),
+                tags,
+                source,
+                created_at: now,
+                updated_at: now,
+            };
+            tracing::info!("Remembered workspace fact #{}", entry.id);
+            Ok((Some(MemoryOp::Put(entry.clone())), entry))
+        })
+    }
+
+    /// Edit a memory
+    pub fn update(
+        &self,
+        id: u64,
+        fact: &str,
+        tags: Option<Vec<String>>,
+    ) -> Result<MemoryEntry> {
+        let fact = fact.trim();
+        if fact.is_empty() {
+            return Err(anyhow::anyhow!("Cannot remember an empty fact"));
+        }
+
+        self.inner.transact(|inner| {
+            let mut entry = inner
+                .entries
+                .iter()
+                .find(|entry| entry.id == id)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Memory #{} does not exist", id))?;
+
+            entry.fact = fact.to_string();
+            if let Some(tags) = tags {
+                entry.tags = tags;
+            }
+            entry.updated_at = current_time();
+            Ok((Some(MemoryOp::Put(entry.clone())), entry))
+        })
+    }
+
+    /// Delete a memory
+    pub fn delete(&self, id: u64) -> Result<()> {
+        self.inner.transact(|inner| {
+            if !inner.entries.iter().any(|entry| entry.id == id) {
+                return Err(anyhow::anyhow!("Memory #{} does not exist", id));
+            }
+            Ok((Some(MemoryOp::Delete(id)), ()))
+        })
+    }
+
+    /// Get all memories, oldest first
+    pub fn list(&self) -> Vec<MemoryEntry> {
+        self.inner.read().entries.clone()
+    }
+
+    /// Get the memories most relevant to a query
+    ///
+    /// Memories are scored by shared words and tags; ties and an empty query
+    /// fall back to the most recently updated memories.
+    pub fn relevant(&self, query: &str, limit: usize) -> Vec<MemoryEntry> {
+        let query_words = words(query);
+        let inner = self.inner.read();
+
+        let mut scored: Vec<(usize, &MemoryEntry)> = inner
+            .entries
+            .iter()
+            .map(|entry| {
+                let fact_words = words(&entry.fact);
+                let mut score = query_words.intersection(&fact_words).count();
+                score += entry
+                    .tags
+                    .iter()
+                    .filter(|tag| query_words.contains(&tag.to_lowercase()))
+                    .count()
+                    * 2;
+                (score, entry)
+            })
+            .filter(|(score, _)| query_words.is_empty() || *score > 0)
+            .collect();
+        scored.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| b.1.updated_at.cmp(&a.1.updated_at))
+        });
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+
+    /// Attach the memories relevant to a query to an editor context
+    pub fn attach_to_context(
+        &self,
+        context: &mut EditorContext,
+        query: &str,
+        limit: usize,
+    ) {
+        context.memories = self
+            .relevant(query, limit)
+            .into_iter()
+            .map(|entry| entry.fact)
+            .collect();
+    }
+
+    /// Definition of the `remember` tool offered to the assistant
+    pub fn remember_tool_definition() -> ToolDefinition {
+        ToolDefinition {
+            name: REMEMBER_TOOL_NAME.to_string(),
+            description: "Record a durable fact about this workspace, such as how \
+                          to build, test or deploy it, so it is available in future \
+                          conversations."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "fact": {
+                        "type": "string",
+                        "description": "The fact to remember, as one sentence"
+                    },
+                    "tags": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Optional keywords, e.g. \"test\" or \"deploy\""
+                    }
+                },
+                "required": ["fact"]
+            }),
+            effect: ToolEffect::Mutating,
+        }
+    }
+
+    /// Execute a `remember` tool call from the assistant
+    pub fn handle_tool_call(&self, call: &ToolCall) -> Result<String> {
+        if call.name != REMEMBER_TOOL_NAME {
+            return Err(anyhow::anyhow!("Unknown memory tool '{}'", call.name));
+        }
+
+        let fact = call
+            .arguments
+            .get("fact")
+            .and_then(|fact| fact.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'fact' argument"))?;
+        let tags = call
+            .arguments
+            .get("tags")
+            .and_then(|tags| tags.as_array())
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().map(|tag| tag.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let entry = self.remember(fact, tags, MemorySource::Agent)?;
+        Ok(format!("Remembered as memory #{}", entry.id))
+    }
+}
+
+/// Stable file name for a workspace root
+fn workspace_key(workspace_root: &Path) -> String {
+    let root = workspace_root
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_root.to_path_buf());
+    format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()))
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() >= 3)
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Sidebar panel listing workspace memories for review and editing
+pub struct MemoryPanel {
+    store: Arc<WorkspaceMemoryStore>,
+}
+
+impl MemoryPanel {
+    pub const ID: &'static str = "catalyst.memory";
+
+    /// Create a panel for a memory store
+    pub fn new(store: Arc<WorkspaceMemoryStore>) -> Self {
+        Self { store }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        let params = &command.parameters;
+        let id = || {
+            params
+                .get("id")
+                .and_then(|id| id.as_u64())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'id' parameter"))
+        };
+        let fact = || {
+            params
+                .get("fact")
+                .and_then(|fact| fact.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'fact' parameter"))
+        };
+        let tags = || -> Option<Vec<String>> {
+            params
+                .get("tags")
+                .and_then(|tags| serde_json::from_value(tags.clone()).ok())
+        };
+
+        match command.command_id.as_str() {
+            "list" => Ok(serde_json::to_value(self.store.list())?),
+            "add" => Ok(serde_json::to_value(self.store.remember(
+                fact()?,
+                tags().unwrap_or_default(),
+                MemorySource::User,
+            )?)?),
+            "update" => Ok(serde_json::to_value(self.store.update(
+                id()?,
+                fact()?,
+                tags(),
+            )?)?),
+            "delete" => {
+                self.store.delete(id()?)?;
+                Ok(serde_json::Value::Null)
+            }
+            other => {
+                Err(anyhow::anyhow!("Unknown memory panel command '{}'", other))
+            }
+        }
+    }
+}
+
+impl SidebarPanelPlugin for MemoryPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Memory".to_string(),
+            description: "Facts the assistant remembers about this workspace"
+                .to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn floem::View> {
+        let store = self.store.clone();
+        text_panel_view(move || {
+            let entries = store.list();
+            if entries.is_empty() {
+                return "No memories recorded for this workspace yet.".to_string();
+            }
+            entries
+                .iter()
+                .map(|entry| format!("#{}  {}", entry.id, entry.fact))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.store.list()).unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remember_and_recall() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.json");
+        let store = WorkspaceMemoryStore::open(&path).unwrap();
+
+        store
+            .remember(
+                "Tests run with make check",
+                vec!["test".into()],
+                MemorySource::Agent,
+            )
+            .unwrap();
+        let deploy = store
+            .remember(
+                "Deploy uses terraform in /infra",
+                Vec::new(),
+                MemorySource::User,
+            )
+            .unwrap();
+        store
+            .remember("tests run with make check", Vec::new(), MemorySource::Agent)
+            .unwrap();
+        assert_eq!(store.list().len(), 2);
+
+        let relevant = store.relevant("how do I deploy with terraform?", 5);
+        assert_eq!(relevant.len(), 1);
+        assert_eq!(relevant[0].id, deploy.id);
+
+        store.delete(deploy.id).unwrap();
+        let reopened = WorkspaceMemoryStore::open(&path).unwrap();
+        assert_eq!(reopened.list().len(), 1);
+        assert_eq!(reopened.relevant("test", 5).len(), 1);
+    }
+
+    #[test]
+    fn test_update_rejects_empty_fact() {
+        let dir = tempfile::tempdir().unwrap();
+        let store =
+            WorkspaceMemoryStore::open(&dir.path().join("memory.json")).unwrap();
+        let entry = store
+            .remember("Lint with cargo clippy", Vec::new(), MemorySource::User)
+            .unwrap();
+
+        assert!(store.update(entry.id, "  ", None).is_err());
+        assert_eq!(store.list()[0].fact, "Lint with cargo clippy");
+
+        let updated = store
+            .update(entry.id, " Lint with make lint ", Some(vec!["lint".into()]))
+            .unwrap();
+        assert_eq!(updated.fact, "Lint with make lint");
+        assert_eq!(updated.tags, vec!["lint".to_string()]);
+    }
+}
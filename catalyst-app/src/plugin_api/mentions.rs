@@ -0,0 +1,595 @@
+//! Chat Mentions
+//!
+//! This module handles `@` mentions in the chat input. `@file:src/main.rs`
+//! (optionally with `:10-20` for a line range) and `@symbol:PluginManager`
+//! attach context to the request as citable sources, `@server:git` limits
+//! the tools offered to the model to the mentioned servers, and `@problems`
+//! asks for the workspace diagnostics to be attached. `@doc:` attaches a
+//! virtual document, like `@doc:untitled:Untitled-1` or
+//! `@doc:git-show:HEAD~1:src/main.rs`, with an optional line range too.
+//! Completions for a mention being typed come from the workspace files, the
+//! symbol graph and the untitled buffers.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    McpTool, SourceLocation, SourceTracker, SymbolGraph, VirtualDocumentUri,
+    VirtualDocuments, WorkspaceSandbox,
+};
+
+/// Largest file attached whole; larger files need a line range
+pub const MAX_MENTION_FILE_BYTES: u64 = 256 * 1024;
+
+const MENTION_KINDS: [&str; 5] = ["file:", "symbol:", "server:", "doc:", "problems"];
+
+/// A mention in the chat input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mention {
+    /// A file, or lines of it, 1-based and inclusive
+    File {
+        path: PathBuf,
+        lines: Option<(usize, usize)>,
+    },
+    Symbol {
+        name: String,
+    },
+    Server {
+        id: String,
+    },
+    /// A virtual document, or lines of it
+    Document {
+        uri: VirtualDocumentUri,
+        lines: Option<(usize, usize)>,
+    },
+    Problems,
+}
+
+/// A mention with its byte range in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedMention {
+    pub mention: Mention,
+    pub span: Range<usize>,
+}
+
+/// Find the mentions in a chat input
+///
+/// A mention starts with `@` at the start of the input or after
+/// whitespace, so e-mail addresses are not mentions; unknown kinds are
+/// left as text.
+pub fn parse_mentions(input: &str) -> Vec<ParsedMention> {
+    mention_tokens(input)
+        .filter_map(|(span, token)| {
+            parse_mention(token).map(|mention| ParsedMention { mention, span })
+        })
+        .collect()
+}
+
+/// Remove the mentions from a chat input, leaving the message text
+pub fn strip_mentions(input: &str, mentions: &[ParsedMention]) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut next = 0;
+    for mention in mentions {
+        text.push_str(&input[next..mention.span.start]);
+        next = mention.span.end;
+    }
+    text.push_str(&input[next..]);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Get the `@` tokens of an input with their byte ranges
+fn mention_tokens(input: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    input
+        .char_indices()
+        .filter(move |(i, c)| {
+            *c == '@'
+                && input[..*i]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map(move |(start, _)| {
+            let end = input[start..]
+                .find(char::is_whitespace)
+                .map_or(input.len(), |len| start + len);
+            (start..end, &input[start + 1..end])
+        })
+}
+
+fn parse_mention(token: &str) -> Option<Mention> {
+    // Trailing punctuation belongs to the sentence
+    let token = token.trim_end_matches([',', '.', ';', '!', '?', ')']);
+    if token == "problems" {
+        return Some(Mention::Problems);
+    }
+    let (kind, value) = token.split_once(':')?;
+    if value.is_empty() {
+        return None;
+    }
+    match kind {
+        "file" => {
+            let (path, lines) = split_line_range(value, |_| true);
+            Some(Mention::File {
+                path: PathBuf::from(path),
+                lines,
+            })
+        }
+        "doc" => {
+            let (uri, lines) = split_line_range(value, |uri| {
+                VirtualDocumentUri::parse(uri).is_some()
+            });
+            Some(Mention::Document {
+                uri: VirtualDocumentUri::parse(uri)?,
+                lines,
+            })
+        }
+        "symbol" => Some(Mention::Symbol {
+            name: value.to_string(),
+        }),
+        "server" => Some(Mention::Server {
+            id: value.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Split a trailing `:10-20` line range off a mention value, if what is
+/// left before it is `valid`
+fn split_line_range(
+    value: &str,
+    valid: impl Fn(&str) -> bool,
+) -> (&str, Option<(usize, usize)>) {
+    value
+        .rsplit_once(':')
+        .filter(|(rest, _)| valid(rest))
+        .and_then(|(rest, range)| Some((rest, Some(parse_line_range(range)?))))
+        .unwrap_or((value, None))
+}
+
+fn parse_line_range(range: &str) -> Option<(usize, usize)> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+        None => {
+            let line = range.parse().ok()?;
+            (line, line)
+        }
+    };
+    (start >= 1 && start <= end).then_some((start, end))
+}
+
+/// What the mentions of a message resolved to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MentionContext {
+    /// Ids of the sources added for file and symbol mentions
+    pub source_ids: Vec<usize>,
+    /// Servers the tools are limited to; all servers when empty
+    pub servers: Vec<String>,
+    /// Whether the workspace diagnostics should be attached
+    pub include_problems: bool,
+    /// Mentions that could not be resolved, with the reason
+    pub errors: Vec<String>,
+}
+
+impl MentionContext {
+    /// Limit tools to the mentioned servers, if any were mentioned
+    pub fn scope_tools(
+        &self,
+        tools: Vec<(String, McpTool)>,
+    ) -> Vec<(String, McpTool)> {
+        if self.servers.is_empty() {
+            return tools;
+        }
+        tools
+            .into_iter()
+            .filter(|(server_id, _)| self.servers.contains(server_id))
+            .collect()
+    }
+}
+
+/// Resolves mentions against the workspace
+pub struct MentionResolver<'a> {
+    pub sandbox: &'a WorkspaceSandbox,
+    pub symbols: &'a SymbolGraph,
+    /// Ids of the registered servers
+    pub servers: &'a [String],
+    pub documents: &'a VirtualDocuments,
+}
+
+impl MentionResolver<'_> {
+    /// Resolve mentions, adding attached context to the request's sources
+    ///
+    /// A mention that can't be resolved is reported in
+    /// [`MentionContext::errors`] and doesn't stop the others.
+    pub fn resolve(
+        &self,
+        mentions: &[ParsedMention],
+        tracker: &mut SourceTracker,
+    ) -> MentionContext {
+        let mut context = MentionContext::default();
+        for parsed in mentions {
+            let result = match &parsed.mention {
+                Mention::File { path, lines } => {
+                    self.attach_file(path, *lines, tracker).map(|id| {
+                        context.source_ids.push(id);
+                    })
+                }
+                Mention::Symbol { name } => {
+                    self.attach_symbol(name, tracker).map(|ids| {
+                        context.source_ids.extend(ids);
+                    })
+                }
+                Mention::Server { id } => {
+                    if self.servers.contains(id) {
+                        if !context.servers.contains(id) {
+                            context.servers.push(id.clone());
+                        }
+                        Ok(())
+                    } else {
+                        Err(anyhow::anyhow!("No server '{}' is registered", id))
+                    }
+                }
+                Mention::Document { uri, lines } => {
+                    self.attach_document(uri, *lines, tracker).map(|id| {
+                        context.source_ids.push(id);
+                    })
+                }
+                Mention::Problems => {
+                    context.include_problems = true;
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                context.errors.push(format!("{err:#}"));
+            }
+        }
+        let mut seen = HashSet::new();
+        context.source_ids.retain(|id| seen.insert(*id));
+        context
+    }
+
+    fn attach_file(
+        &self,
+        path: &Path,
+        lines: Option<(usize, usize)>,
+        tracker: &mut SourceTracker,
+    ) -> Result<usize> {
+        let resolved = self.sandbox.check_access(path)?;
+        let size = std::fs::metadata(&resolved)
+            .map_err(|err| {
+                anyhow::anyhow!("Can't read '{}': {err}", path.display())
+            })?
+            .len();
+        if lines.is_none() && size > MAX_MENTION_FILE_BYTES {
+            return Err(anyhow::anyhow!(
+                "'{}' is too large to attach whole; mention a line range, \
+                 e.g. @file:{}:1-100",
+                path.display(),
+                path.display()
+            ));
+        }
+        let text = std::fs::read_to_string(&resolved)?;
+        let (start_line, end_line, content) =
+            slice_lines(&path.display().to_string(), &text, lines)?;
+        Ok(tracker.add(
+            SourceLocation::File {
+                path: path.to_path_buf(),
+                start_line,
+                end_line,
+            },
+            content,
+        ))
+    }
+
+    fn attach_document(
+        &self,
+        uri: &VirtualDocumentUri,
+        lines: Option<(usize, usize)>,
+        tracker: &mut SourceTracker,
+    ) -> Result<usize> {
+        let text = self.documents.read(uri, self.sandbox)?;
+        let uri = uri.to_string();
+        let (start_line, end_line, content) = slice_lines(&uri, &text, lines)?;
+        Ok(tracker.add(
+            SourceLocation::Document {
+                uri,
+                start_line,
+                end_line,
+            },
+            content,
+        ))
+    }
+
+    fn attach_symbol(
+        &self,
+        name: &str,
+        tracker: &mut SourceTracker,
+    ) -> Result<Vec<usize>> {
+        let definitions = self.symbols.definitions(name);
+        if definitions.is_empty() {
+            return Err(anyhow::anyhow!("No definition of '{}' was found", name));
+        }
+        Ok(definitions
+            .iter()
+            .filter(|definition| self.sandbox.is_allowed(&definition.path))
+            .filter_map(|definition| self.symbols.definition_chunk(definition))
+            .map(|chunk| tracker.add_chunk(&chunk))
+            .collect())
+    }
+}
+
+/// Get lines of a text, all of them if no range is given, as the first and
+/// last line and the content
+fn slice_lines(
+    name: &str,
+    text: &str,
+    lines: Option<(usize, usize)>,
+) -> Result<(usize, usize, String)> {
+    let line_count = text.lines().count().max(1);
+    let (start_line, end_line) = lines.unwrap_or((1, line_count));
+    if start_line > line_count {
+        return Err(anyhow::anyhow!("'{}' has only {} lines", name, line_count));
+    }
+    let end_line = end_line.min(line_count);
+    let content = text
+        .lines()
+        .skip(start_line - 1)
+        .take(end_line + 1 - start_line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((start_line, end_line, content))
+}
+
+/// A completion for the mention being typed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionCompletion {
+    pub label: String,
+    /// Shown next to the label, e.g. the file of a symbol
+    pub detail: Option<String>,
+    /// Text replacing [`Self::replace`] in the input
+    pub insert_text: String,
+    pub replace: Range<usize>,
+}
+
+/// Where mention completions come from
+pub struct MentionCompletionSources<'a> {
+    /// Workspace files, relative to the root
+    pub files: &'a [PathBuf],
+    pub symbols: &'a SymbolGraph,
+    pub servers: &'a [String],
+    /// Names of the untitled buffers
+    pub untitled: &'a [String],
+}
+
+/// Complete the mention at the cursor, if the cursor is in one
+pub fn complete_mention(
+    input: &str,
+    cursor: usize,
+    sources: &MentionCompletionSources,
+    limit: usize,
+) -> Vec<MentionCompletion> {
+    let Some((span, token)) = mention_tokens(input)
+        .find(|(span, _)| span.start < cursor && cursor <= span.end)
+    else {
+        return Vec::new();
+    };
+    let typed = &token[..cursor - span.start - 1];
+    let replace = span.start..span.end;
+    let completion =
+        |label: String, detail: Option<String>, insert: String| MentionCompletion {
+            label,
+            detail,
+            insert_text: format!("@{insert} "),
+            replace: replace.clone(),
+        };
+
+    let Some((kind, query)) = typed.split_once(':') else {
+        return MENTION_KINDS
+            .iter()
+            .filter(|kind| kind.starts_with(typed))
+            .map(|kind| MentionCompletion {
+                label: format!("@{kind}"),
+                detail: None,
+                insert_text: if kind.ends_with(':') {
+                    format!("@{kind}")
+                } else {
+                    format!("@{kind} ")
+                },
+                replace: replace.clone(),
+            })
+            .collect();
+    };
+    match kind {
+        "file" => {
+            let mut scored: Vec<(u32, &PathBuf)> = sources
+                .files
+                .iter()
+                .filter_map(|path| Some((file_match_score(path, query)?, path)))
+                .collect();
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+            scored
+                .into_iter()
+                .take(limit)
+                .map(|(_, path)| {
+                    let path = path.to_string_lossy().replace('\\', "/");
+                    completion(path.clone(), None, format!("file:{path}"))
+                })
+                .collect()
+        }
+        "symbol" if !query.is_empty() => sources
+            .symbols
+            .complete(query, limit)
+            .into_iter()
+            .map(|definition| {
+                completion(
+                    definition.name.clone(),
+                    Some(format!(
+                        "{}:{}",
+                        definition.path.display(),
+                        definition.line
+                    )),
+                    format!("symbol:{}", definition.name),
+                )
+            })
+            .collect(),
+        "server" => sources
+            .servers
+            .iter()
+            .filter(|id| id.starts_with(query))
+            .take(limit)
+            .map(|id| completion(id.clone(), None, format!("server:{id}")))
+            .collect(),
+        "doc" => sources
+            .untitled
+            .iter()
+            .map(|name| format!("untitled:{name}"))
+            .filter(|uri| uri.starts_with(query))
+            .take(limit)
+            .map(|uri| completion(uri.clone(), None, format!("doc:{uri}")))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Score how well a path matches a query, or `None` if it doesn't
+///
+/// File names starting with the query rank first, then paths containing
+/// it, then paths containing its characters in order.
+fn file_match_score(path: &Path, query: &str) -> Option<u32> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let query = query.to_lowercase();
+    let full = path.to_string_lossy().to_lowercase().replace('\\', "/");
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if name.starts_with(&query) {
+        return Some(300);
+    }
+    if full.contains(&query) {
+        return Some(200);
+    }
+    let mut chars = full.chars();
+    query.chars().all(|q| chars.any(|c| c == q)).then_some(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        CatalystIgnore, CatalystIgnoreConfig, ContextChunk, SharedPath,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parses_resolves_and_completes_mentions() {
+        let input = "Why does @file:src/main.rs:2-3 call @symbol:PluginManager? \
+                     Use @server:git, see @problems and mail a@b.c @unknown:x \
+                     @doc:untitled:Notes:2";
+        let mentions = parse_mentions(input);
+        let kinds: Vec<_> = mentions.iter().map(|m| m.mention.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Mention::File {
+                    path: PathBuf::from("src/main.rs"),
+                    lines: Some((2, 3)),
+                },
+                Mention::Symbol {
+                    name: "PluginManager".to_string(),
+                },
+                Mention::Server {
+                    id: "git".to_string(),
+                },
+                Mention::Problems,
+                Mention::Document {
+                    uri: VirtualDocumentUri::Untitled {
+                        name: "Notes".to_string(),
+                    },
+                    lines: Some((2, 2)),
+                },
+            ]
+        );
+        assert_eq!(
+            parse_mentions("@doc:untitled:1")[0].mention,
+            Mention::Document {
+                uri: VirtualDocumentUri::Untitled {
+                    name: "1".to_string(),
+                },
+                lines: None,
+            }
+        );
+        assert_eq!(
+            strip_mentions(input, &mentions),
+            "Why does call Use see and mail a@b.c @unknown:x"
+        );
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("src")).unwrap();
+        std::fs::write(root.path().join("src/main.rs"), "a\nb\nc\nd\n").unwrap();
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = WorkspaceSandbox::new(Arc::new(ignore));
+        let symbols = SymbolGraph::new();
+        let source = "pub struct PluginManager {}\n";
+        symbols.index_file(
+            Path::new("src/manager.rs"),
+            source,
+            vec![ContextChunk {
+                path: SharedPath::from("src/manager.rs"),
+                start_line: 1,
+                end_line: 1,
+                content: source.to_string(),
+                modified: None,
+            }],
+        );
+        let servers = vec!["git".to_string(), "github".to_string()];
+        let documents = VirtualDocuments::new();
+        documents.set_untitled("Notes", "first\nsecond\n");
+        let resolver = MentionResolver {
+            sandbox: &sandbox,
+            symbols: &symbols,
+            servers: &servers,
+            documents: &documents,
+        };
+        let mut tracker = SourceTracker::new();
+        let context = resolver.resolve(&mentions, &mut tracker);
+        assert_eq!(context.source_ids, vec![1, 2, 3]);
+        assert_eq!(context.servers, vec!["git".to_string()]);
+        assert!(context.include_problems);
+        assert!(context.errors.is_empty());
+        assert_eq!(tracker.sources()[0].content, "b\nc");
+        assert_eq!(tracker.sources()[2].content, "second");
+        assert_eq!(tracker.sources()[2].location.label(), "untitled:Notes:2-2");
+
+        let files = vec![PathBuf::from("src/main.rs"), PathBuf::from("README.md")];
+        let untitled = documents.untitled_names();
+        let sources = MentionCompletionSources {
+            files: &files,
+            symbols: &symbols,
+            servers: &servers,
+            untitled: &untitled,
+        };
+        let labels = |input: &str| -> Vec<String> {
+            complete_mention(input, input.len(), &sources, 10)
+                .into_iter()
+                .map(|completion| completion.insert_text)
+                .collect()
+        };
+        assert_eq!(labels("see @s"), vec!["@symbol:", "@server:"]);
+        assert_eq!(labels("see @file:mai"), vec!["@file:src/main.rs "]);
+        assert_eq!(labels("@symbol:plug"), vec!["@symbol:PluginManager "]);
+        assert_eq!(
+            labels("@server:git"),
+            vec!["@server:git ", "@server:github "]
+        );
+        assert_eq!(labels("@doc:unt"), vec!["@doc:untitled:Notes "]);
+        assert!(labels("mail a@b").is_empty());
+    }
+}
@@ -0,0 +1,572 @@
+//! Merge Assistant
+//!
+//! This module helps resolve the conflicts of a merge, rebase or cherry-pick.
+//! The conflicted files are found in the git index, and each conflict in a
+//! file is read from its markers together with the lines around it. Files
+//! written without the common ancestor (the default `merge` conflict style)
+//! get it from the index, so every conflict is shown with its base, ours and
+//! theirs versions.
+//!
+//! The assistant proposes a resolution for each conflict, which becomes one
+//! hunk of an [`EditReview`], so conflicts are accepted one by one and the
+//! rejected ones keep their markers. Once the review is applied, files
+//! without markers are staged and the test task can be run on the result.
+
+use anyhow::{Result, anyhow};
+use git2::{IndexConflict, MergeFileOptions, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin_api::{
+    EditReview, FileChangeKind, HunkDecision, ProjectTask, ReviewFile, ReviewHunk,
+    ValidationReport, WorkspaceSandbox, validate_applied,
+};
+
+/// Lines shown before and after a conflict
+pub const CONFLICT_CONTEXT_LINES: usize = 3;
+/// Time the test task may run after resolving
+pub const DEFAULT_MERGE_TEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+const OURS_MARKER: &str = "<<<<<<<";
+const BASE_MARKER: &str = "|||||||";
+const SEPARATOR: &str = "=======";
+const THEIRS_MARKER: &str = ">>>>>>>";
+
+/// A conflict between two versions of some lines
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    /// Line of the opening marker, 0-based
+    pub start_line: usize,
+    /// Number of lines from the opening marker to the closing one
+    pub line_count: usize,
+    /// Label of our side, e.g. `HEAD`
+    pub ours_label: String,
+    pub theirs_label: String,
+    pub ours: Vec<String>,
+    /// The common ancestor, `None` if it isn't known, e.g. for a file both
+    /// sides added
+    pub base: Option<Vec<String>>,
+    pub theirs: Vec<String>,
+    /// Lines before the conflict, at most [`CONFLICT_CONTEXT_LINES`]
+    pub before: Vec<String>,
+    /// Lines after the conflict, at most [`CONFLICT_CONTEXT_LINES`]
+    pub after: Vec<String>,
+}
+
+/// A file with unresolved conflicts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictedFile {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    /// Content with the conflict markers
+    pub text: String,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl ConflictedFile {
+    /// Build the prompt asking for the resolution of every conflict
+    pub fn prompt(&self) -> String {
+        let mut prompt = format!(
+            "Resolve each of the following {} merge conflicts in {}. Keep the \
+             intent of both sides where they don't contradict each other.\n\n\
+             Reply with only a JSON array of strings, one per conflict in the \
+             same order, holding the lines that replace the conflict, without \
+             conflict markers.\n",
+            self.conflicts.len(),
+            self.path.display()
+        );
+        for (index, conflict) in self.conflicts.iter().enumerate() {
+            prompt.push_str(&format!(
+                "\n{}. Conflict at line {}:\n",
+                index + 1,
+                conflict.start_line + 1
+            ));
+            let mut section = |title: String, lines: &[String]| {
+                prompt.push_str(&format!("{}:\n```\n", title));
+                for line in lines {
+                    prompt.push_str(line);
+                    prompt.push('\n');
+                }
+                prompt.push_str("```\n");
+            };
+            section("Before".to_string(), &conflict.before);
+            section(format!("Ours ({})", conflict.ours_label), &conflict.ours);
+            if let Some(base) = &conflict.base {
+                section("Base".to_string(), base);
+            }
+            section(
+                format!("Theirs ({})", conflict.theirs_label),
+                &conflict.theirs,
+            );
+            section("After".to_string(), &conflict.after);
+        }
+        prompt
+    }
+}
+
+/// Parse the assistant's reply to [`ConflictedFile::prompt`]
+pub fn parse_resolution_response(response: &str) -> Result<Vec<String>> {
+    let start = response
+        .find('[')
+        .ok_or_else(|| anyhow!("The reply has no JSON array"))?;
+    let end = response
+        .rfind(']')
+        .filter(|end| *end > start)
+        .ok_or_else(|| anyhow!("The reply has no JSON array"))?;
+    Ok(serde_json::from_str(&response[start..=end])?)
+}
+
+/// Find the conflicts of a file from its markers
+pub fn parse_conflicts(text: &str) -> Result<Vec<MergeConflict>> {
+    enum Section {
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut conflicts = Vec::new();
+    let mut current: Option<(MergeConflict, Section)> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let Some((conflict, section)) = current.as_mut() else {
+            if let Some(label) = marker_label(line, OURS_MARKER) {
+                current = Some((
+                    MergeConflict {
+                        start_line: index,
+                        line_count: 0,
+                        ours_label: label.to_string(),
+                        theirs_label: String::new(),
+                        ours: Vec::new(),
+                        base: None,
+                        theirs: Vec::new(),
+                        before: to_strings(
+                            &lines[index.saturating_sub(CONFLICT_CONTEXT_LINES)
+                                ..index],
+                        ),
+                        after: Vec::new(),
+                    },
+                    Section::Ours,
+                ));
+            }
+            continue;
+        };
+        match section {
+            Section::Ours | Section::Base if *line == SEPARATOR => {
+                *section = Section::Theirs;
+            }
+            Section::Ours if marker_label(line, BASE_MARKER).is_some() => {
+                conflict.base = Some(Vec::new());
+                *section = Section::Base;
+            }
+            Section::Ours => conflict.ours.push(line.to_string()),
+            Section::Base => {
+                if let Some(base) = conflict.base.as_mut() {
+                    base.push(line.to_string());
+                }
+            }
+            Section::Theirs => match marker_label(line, THEIRS_MARKER) {
+                Some(label) => {
+                    let Some((mut conflict, _)) = current.take() else {
+                        continue;
+                    };
+                    conflict.theirs_label = label.to_string();
+                    conflict.line_count = index + 1 - conflict.start_line;
+                    let after = index + 1;
+                    conflict.after = to_strings(
+                        &lines[after
+                            ..(after + CONFLICT_CONTEXT_LINES).min(lines.len())],
+                    );
+                    conflicts.push(conflict);
+                }
+                None => conflict.theirs.push(line.to_string()),
+            },
+        }
+    }
+    match current {
+        Some((conflict, _)) => Err(anyhow!(
+            "The conflict at line {} is never closed",
+            conflict.start_line + 1
+        )),
+        None => Ok(conflicts),
+    }
+}
+
+/// Get the label of a conflict marker line, e.g. `HEAD` for
+/// `<<<<<<< HEAD`
+fn marker_label<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(marker)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix(' ')
+    }
+}
+
+/// Resolves conflicts with the assistant
+pub trait ConflictResolver: Send + Sync {
+    /// Resolve each conflict of a file, in order, into the lines replacing
+    /// it
+    fn resolve(&self, file: &ConflictedFile) -> Result<Vec<String>>;
+}
+
+/// Proposed resolutions, ready for review
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeProposal {
+    pub files: Vec<ConflictedFile>,
+    /// One hunk per conflict, in the order of `files`
+    pub review: EditReview,
+}
+
+/// Outcome of finishing a merge resolution
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeCompletion {
+    /// Files without markers, now staged
+    pub staged: Vec<PathBuf>,
+    /// Files that still have conflict markers
+    pub unresolved: Vec<PathBuf>,
+    /// Result of the test task, when it was run
+    pub validation: Option<ValidationReport>,
+}
+
+/// Walks through the conflicts of the workspace repository
+pub struct MergeAssistant {
+    sandbox: Arc<WorkspaceSandbox>,
+    resolver: Arc<dyn ConflictResolver>,
+    test_task: Option<(ProjectTask, Duration)>,
+}
+
+impl MergeAssistant {
+    pub fn new(
+        sandbox: Arc<WorkspaceSandbox>,
+        resolver: Arc<dyn ConflictResolver>,
+    ) -> Self {
+        Self {
+            sandbox,
+            resolver,
+            test_task: None,
+        }
+    }
+
+    /// Run a task, usually the project's tests, once every conflict is
+    /// resolved
+    pub fn with_test_task(mut self, task: ProjectTask, timeout: Duration) -> Self {
+        self.test_task = Some((task, timeout));
+        self
+    }
+
+    fn repository(&self) -> Result<Repository> {
+        Repository::discover(self.sandbox.root()).map_err(|err| {
+            anyhow!(
+                "{} is not in a git repository: {}",
+                self.sandbox.root().display(),
+                err.message()
+            )
+        })
+    }
+
+    /// Find the conflicted files of the workspace that still have markers
+    pub fn conflicts(&self) -> Result<Vec<ConflictedFile>> {
+        let repo = self.repository()?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| anyhow!("The repository has no working directory"))?
+            .canonicalize()?;
+        let root = self.sandbox.root().canonicalize()?;
+        let mut files = Vec::new();
+        for conflict in repo.index()?.conflicts()? {
+            let conflict = conflict?;
+            let Some(entry) = conflict.our.as_ref().or(conflict.their.as_ref())
+            else {
+                continue;
+            };
+            let path = workdir.join(String::from_utf8_lossy(&entry.path).as_ref());
+            let Ok(relative) = path.strip_prefix(&root) else {
+                continue;
+            };
+            let Ok(path) = self.sandbox.check_access(relative) else {
+                continue;
+            };
+            // Deleted on one side, or not text
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let mut conflicts = parse_conflicts(&text)?;
+            if conflicts.is_empty() {
+                continue;
+            }
+            fill_bases(&repo, &conflict, &mut conflicts);
+            files.push(ConflictedFile {
+                path: relative.to_path_buf(),
+                text,
+                conflicts,
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// Have the assistant resolve every conflict of the workspace
+    pub fn propose(&self) -> Result<MergeProposal> {
+        let files = self.conflicts()?;
+        let mut review = EditReview::default();
+        for file in &files {
+            let resolutions = self.resolver.resolve(file)?;
+            if resolutions.len() != file.conflicts.len() {
+                return Err(anyhow!(
+                    "Expected resolutions for {} conflicts in {} but got {}",
+                    file.conflicts.len(),
+                    file.path.display(),
+                    resolutions.len()
+                ));
+            }
+            review.files.push(review_file(file, &resolutions));
+        }
+        Ok(MergeProposal { files, review })
+    }
+
+    /// Stage the files of an applied review that no longer have markers,
+    /// then run the test task if every conflict is resolved
+    pub fn complete(
+        &self,
+        proposal: &MergeProposal,
+        workspace_env: &HashMap<String, String>,
+    ) -> Result<MergeCompletion> {
+        let repo = self.repository()?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| anyhow!("The repository has no working directory"))?
+            .canonicalize()?;
+        let mut index = repo.index()?;
+        let mut completion = MergeCompletion::default();
+        for file in &proposal.files {
+            let path = self.sandbox.check_access(&file.path)?;
+            let text = std::fs::read_to_string(&path)?;
+            if !parse_conflicts(&text)?.is_empty() {
+                completion.unresolved.push(file.path.clone());
+                continue;
+            }
+            let relative = path
+                .canonicalize()?
+                .strip_prefix(&workdir)
+                .map_err(|_| {
+                    anyhow!("{} is outside of the repository", file.path.display())
+                })?
+                .to_path_buf();
+            index.add_path(&relative)?;
+            completion.staged.push(path);
+        }
+        index.write()?;
+
+        // Tests only make sense once the whole merge is resolved
+        let resolved = completion.unresolved.is_empty() && !index.has_conflicts();
+        if let Some((task, timeout)) = self.test_task.as_ref().filter(|_| resolved) {
+            completion.validation = Some(validate_applied(
+                &completion.staged,
+                Some((task, *timeout)),
+                workspace_env,
+            )?);
+        }
+        Ok(completion)
+    }
+}
+
+/// Take the base of conflicts written without it from a three-way merge of
+/// the index entries
+fn fill_bases(
+    repo: &Repository,
+    conflict: &IndexConflict,
+    conflicts: &mut [MergeConflict],
+) {
+    if conflicts.iter().all(|conflict| conflict.base.is_some()) {
+        return;
+    }
+    let (Some(ancestor), Some(ours), Some(theirs)) =
+        (&conflict.ancestor, &conflict.our, &conflict.their)
+    else {
+        return;
+    };
+    let mut options = MergeFileOptions::new();
+    options.style_diff3(true);
+    let Ok(merged) =
+        repo.merge_file_from_index(ancestor, ours, theirs, Some(&mut options))
+    else {
+        return;
+    };
+    let Ok(merged) = parse_conflicts(&String::from_utf8_lossy(merged.content()))
+    else {
+        return;
+    };
+    for conflict in conflicts.iter_mut().filter(|c| c.base.is_none()) {
+        conflict.base = merged
+            .iter()
+            .find(|merged| {
+                merged.ours == conflict.ours && merged.theirs == conflict.theirs
+            })
+            .and_then(|merged| merged.base.clone());
+    }
+}
+
+/// Turn each conflict into a hunk replacing it with its resolution
+fn review_file(file: &ConflictedFile, resolutions: &[String]) -> ReviewFile {
+    let lines: Vec<&str> = file.text.lines().collect();
+    let mut offset = 0isize;
+    let hunks = file
+        .conflicts
+        .iter()
+        .zip(resolutions)
+        .map(|(conflict, resolution)| {
+            let old_lines = to_strings(
+                &lines
+                    [conflict.start_line..conflict.start_line + conflict.line_count],
+            );
+            let new_lines = to_strings(&resolution.lines().collect::<Vec<_>>());
+            let new_start = (conflict.start_line as isize + offset) as usize;
+            offset += new_lines.len() as isize - old_lines.len() as isize;
+            ReviewHunk {
+                old_start: conflict.start_line,
+                old_lines,
+                new_start,
+                new_lines,
+                decision: HunkDecision::Pending,
+            }
+        })
+        .collect();
+    ReviewFile {
+        path: file.path.clone(),
+        kind: FileChangeKind::Modified,
+        original: file.text.clone(),
+        hunks,
+    }
+}
+
+fn to_strings(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+    use git2::{BranchType, Signature};
+    use std::path::Path;
+
+    struct TakeTheirs;
+
+    impl ConflictResolver for TakeTheirs {
+        fn resolve(&self, file: &ConflictedFile) -> Result<Vec<String>> {
+            Ok(file
+                .conflicts
+                .iter()
+                .map(|conflict| conflict.theirs.join("\n"))
+                .collect())
+        }
+    }
+
+    fn commit(repo: &Repository, message: &str, parents: &[&git2::Commit]) {
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("lib.rs")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            parents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_conflicts() {
+        let text =
+            "a\n<<<<<<< HEAD\nb\n||||||| base\nc\n=======\nd\n>>>>>>> topic\ne\n";
+        let conflicts = parse_conflicts(text).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!((conflict.start_line, conflict.line_count), (1, 7));
+        assert_eq!(conflict.ours_label, "HEAD");
+        assert_eq!(conflict.theirs_label, "topic");
+        assert_eq!(conflict.ours, vec!["b"]);
+        assert_eq!(conflict.base, Some(vec!["c".to_string()]));
+        assert_eq!(conflict.theirs, vec!["d"]);
+        assert_eq!(
+            (conflict.before.clone(), conflict.after.clone()),
+            (vec!["a".to_string()], vec!["e".to_string()])
+        );
+        assert!(parse_conflicts("<<<<<<< HEAD\nb\n=======\n").is_err());
+        assert!(parse_conflicts("a\n=======\nb\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolves_merge_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let repo = Repository::init(root).unwrap();
+        std::fs::write(root.join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n")
+            .unwrap();
+        commit(&repo, "Base", &[]);
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("topic", &base, false).unwrap();
+
+        std::fs::write(root.join("lib.rs"), "fn a() { 1 }\nfn b() {}\nfn c() {}\n")
+            .unwrap();
+        commit(&repo, "Ours", &[&base]);
+        repo.set_head("refs/heads/topic").unwrap();
+        std::fs::write(root.join("lib.rs"), "fn a() { 2 }\nfn b() {}\nfn c() {}\n")
+            .unwrap();
+        commit(&repo, "Theirs", &[&base]);
+        let topic = repo.find_branch("topic", BranchType::Local).unwrap();
+        let theirs = repo.reference_to_annotated_commit(topic.get()).unwrap();
+        let main = repo
+            .branches(Some(BranchType::Local))
+            .unwrap()
+            .map(|branch| branch.unwrap().0)
+            .find(|branch| branch.name().unwrap() != Some("topic"))
+            .unwrap();
+        repo.set_head(main.get().name().unwrap()).unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        repo.merge(&[&theirs], None, None).unwrap();
+
+        let ignore =
+            CatalystIgnore::new(root, &CatalystIgnoreConfig::default()).unwrap();
+        let sandbox = Arc::new(WorkspaceSandbox::new(Arc::new(ignore)));
+        let assistant = MergeAssistant::new(sandbox.clone(), Arc::new(TakeTheirs));
+        let mut proposal = assistant.propose().unwrap();
+        assert_eq!(proposal.files.len(), 1);
+        let conflict = &proposal.files[0].conflicts[0];
+        assert_eq!(conflict.ours, vec!["fn a() { 1 }"]);
+        assert_eq!(conflict.base, Some(vec!["fn a() {}".to_string()]));
+        assert_eq!(conflict.theirs, vec!["fn a() { 2 }"]);
+        assert!(
+            proposal.files[0]
+                .prompt()
+                .contains("Base:\n```\nfn a() {}\n")
+        );
+
+        // Nothing is staged while markers are left
+        proposal.review.set_all(HunkDecision::Rejected);
+        proposal.review.apply_to_disk(&sandbox).unwrap();
+        let completion = assistant.complete(&proposal, &HashMap::new()).unwrap();
+        assert_eq!(completion.unresolved, vec![PathBuf::from("lib.rs")]);
+
+        proposal.review.set_all(HunkDecision::Accepted);
+        proposal.review.apply_to_disk(&sandbox).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("lib.rs")).unwrap(),
+            "fn a() { 2 }\nfn b() {}\nfn c() {}\n"
+        );
+        let completion = assistant.complete(&proposal, &HashMap::new()).unwrap();
+        assert_eq!(completion.staged.len(), 1);
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        assert!(!index.has_conflicts());
+        assert!(completion.validation.is_none());
+    }
+}
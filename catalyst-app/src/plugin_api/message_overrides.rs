@@ -0,0 +1,177 @@
+//! Per-Message Overrides
+//!
+//! This module lets a single message use another model, temperature or
+//! token limit than the conversation's defaults. The overrides are set with
+//! the chat's gear control or written in front of the message:
+//!
+//! ```text
+//! @model:fast/small-model @temperature:0.2 @max_tokens:500 summarize this
+//! ```
+//!
+//! A model can be qualified with a provider id, as in `fast/small-model`, to
+//! send the message to that provider; see
+//! [`FailoverAssistant`](crate::plugin_api::FailoverAssistant). Overrides are
+//! stored with the message so a regenerated answer uses the same
+//! parameters.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::plugin_api::AiMessageRequest;
+
+/// Highest temperature accepted by the providers
+const MAX_TEMPERATURE: f32 = 2.0;
+
+/// Parameters of a request overridden for one message
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl MessageOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.model.is_none()
+            && self.temperature.is_none()
+            && self.max_tokens.is_none()
+    }
+
+    /// Split the overrides written in front of a message from its text
+    ///
+    /// Only the leading `@key:value` words are read, so mentions later in the
+    /// message are left alone. `temp` and `max` are accepted as short keys.
+    pub fn parse_prefix(text: &str) -> Result<(Self, &str)> {
+        let mut overrides = Self::default();
+        let mut rest = text.trim_start();
+        while let Some(word) = rest.split_whitespace().next() {
+            let Some((key, value)) = word
+                .strip_prefix('@')
+                .and_then(|setting| setting.split_once(':'))
+            else {
+                break;
+            };
+            match key {
+                "model" => overrides.model = Some(value.to_string()),
+                "temperature" | "temp" => {
+                    overrides.temperature = Some(value.parse().map_err(|_| {
+                        anyhow!("Invalid temperature '{}': expected a number", value)
+                    })?)
+                }
+                "max_tokens" | "max" => {
+                    overrides.max_tokens = Some(value.parse().map_err(|_| {
+                        anyhow!(
+                            "Invalid max tokens '{}': expected a whole number",
+                            value
+                        )
+                    })?)
+                }
+                // Anything else starts the message, e.g. `@file:` mentions
+                _ => break,
+            }
+            rest = rest[word.len()..].trim_start();
+        }
+        overrides.validate()?;
+        Ok((overrides, rest))
+    }
+
+    /// Check the values are usable by a provider
+    pub fn validate(&self) -> Result<()> {
+        if let Some(model) = &self.model {
+            if model.is_empty() || model.chars().any(char::is_whitespace) {
+                return Err(anyhow!("Invalid model '{}'", model));
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+                return Err(anyhow!(
+                    "Temperature {} is out of range 0 to {}",
+                    temperature,
+                    MAX_TEMPERATURE
+                ));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err(anyhow!("Max tokens must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Combine with other overrides, whose values win
+    pub fn merge(&self, other: &MessageOverrides) -> MessageOverrides {
+        MessageOverrides {
+            model: other.model.clone().or_else(|| self.model.clone()),
+            temperature: other.temperature.or(self.temperature),
+            max_tokens: other.max_tokens.or(self.max_tokens),
+        }
+    }
+
+    /// Set the overridden parameters on a request
+    pub fn apply(&self, request: &mut AiMessageRequest) {
+        if let Some(model) = &self.model {
+            request.model = Some(model.clone());
+        }
+        if let Some(temperature) = self.temperature {
+            request.temperature = Some(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            request.max_tokens = Some(max_tokens);
+        }
+    }
+
+    /// Get the text shown next to the message
+    pub fn label(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(model) = &self.model {
+            parts.push(model.clone());
+        }
+        if let Some(temperature) = self.temperature {
+            parts.push(format!("temperature {}", temperature));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            parts.push(format!("max {} tokens", max_tokens));
+        }
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message_prefix() {
+        let (overrides, text) = MessageOverrides::parse_prefix(
+            "@model:fast/small @temp:0.2 @max:300 hi @x",
+        )
+        .unwrap();
+        assert_eq!(overrides.model.as_deref(), Some("fast/small"));
+        assert_eq!(overrides.temperature, Some(0.2));
+        assert_eq!(overrides.max_tokens, Some(300));
+        assert_eq!(text, "hi @x");
+
+        // Mentions are left to the mention parser
+        let (overrides, text) =
+            MessageOverrides::parse_prefix("@file:src/main.rs explain").unwrap();
+        assert!(overrides.is_empty());
+        assert_eq!(text, "@file:src/main.rs explain");
+
+        assert!(MessageOverrides::parse_prefix("@temp:5 hi").is_err());
+        assert!(MessageOverrides::parse_prefix("@max:many hi").is_err());
+
+        let defaults = MessageOverrides {
+            temperature: Some(1.0),
+            max_tokens: Some(100),
+            ..Default::default()
+        };
+        let merged = defaults.merge(&MessageOverrides {
+            temperature: Some(0.0),
+            ..Default::default()
+        });
+        assert_eq!(merged.temperature, Some(0.0));
+        assert_eq!(merged.max_tokens, Some(100));
+    }
+}
@@ -0,0 +1,118 @@
+//! Metrics Registry
+//!
+//! This module keeps named gauges and counters that subsystems update as
+//! they run, so the current state of the IDE (thread pool utilization,
+//! queue depths, ...) can be inspected from diagnostics without each
+//! subsystem exposing its own accessors. Gauges updated through
+//! [`MetricsRegistry::record_sample`] also keep their recent values so
+//! diagnostics can show how they changed.
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+static GLOBAL_METRICS: Lazy<MetricsRegistry> = Lazy::new(MetricsRegistry::default);
+
+/// Number of recent values kept for sampled gauges
+pub const METRIC_HISTORY_LEN: usize = 60;
+
+/// Kind of metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    /// Value that goes up and down, replaced on every update
+    Gauge,
+    /// Value that only increases
+    Counter,
+}
+
+/// Current value of a metric
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricValue {
+    pub kind: MetricKind,
+    pub value: f64,
+}
+
+/// Named metrics shared by all subsystems
+#[derive(Default)]
+pub struct MetricsRegistry {
+    metrics: RwLock<HashMap<String, MetricValue>>,
+    history: RwLock<HashMap<String, VecDeque<f64>>>,
+}
+
+impl MetricsRegistry {
+    /// Get the registry shared by the whole application
+    pub fn global() -> &'static MetricsRegistry {
+        &GLOBAL_METRICS
+    }
+
+    /// Set the value of a gauge
+    pub fn set_gauge(&self, name: &str, value: f64) {
+        let metric = MetricValue {
+            kind: MetricKind::Gauge,
+            value,
+        };
+        let mut metrics = self.metrics.write();
+        match metrics.get_mut(name) {
+            Some(existing) => *existing = metric,
+            None => {
+                metrics.insert(name.to_string(), metric);
+            }
+        }
+    }
+
+    /// Set the value of a gauge and keep it in the gauge's recent values
+    pub fn record_sample(&self, name: &str, value: f64) {
+        self.set_gauge(name, value);
+        let mut history = self.history.write();
+        let values = history.entry(name.to_string()).or_default();
+        if values.len() == METRIC_HISTORY_LEN {
+            values.pop_front();
+        }
+        values.push_back(value);
+    }
+
+    /// Get the recent values of a sampled gauge, oldest first
+    pub fn history(&self, name: &str) -> Vec<f64> {
+        self.history
+            .read()
+            .get(name)
+            .map(|values| values.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Add to a counter, creating it at zero if needed
+    pub fn increment(&self, name: &str, amount: u64) {
+        let mut metrics = self.metrics.write();
+        let metric = metrics.entry(name.to_string()).or_insert(MetricValue {
+            kind: MetricKind::Counter,
+            value: 0.0,
+        });
+        metric.value += amount as f64;
+    }
+
+    /// Get the current value of a metric
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.metrics.read().get(name).map(|metric| metric.value)
+    }
+
+    /// Get all metrics, sorted by name
+    pub fn snapshot(&self) -> BTreeMap<String, MetricValue> {
+        self.metrics
+            .read()
+            .iter()
+            .map(|(name, metric)| (name.clone(), *metric))
+            .collect()
+    }
+
+    /// Get the metrics whose name starts with a prefix, sorted by name
+    pub fn snapshot_prefix(&self, prefix: &str) -> BTreeMap<String, MetricValue> {
+        self.metrics
+            .read()
+            .iter()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, metric)| (name.clone(), *metric))
+            .collect()
+    }
+}
@@ -0,0 +1,307 @@
+//! Format Migrations
+//!
+//! This module upgrades on-disk formats when they are opened. A store
+//! declares its current format version and one migration per older
+//! version; opening an older file backs it up next to itself, applies the
+//! migrations in order and records them in a log shown by diagnostics.
+//! Files written by a newer build are refused instead of being read with
+//! the wrong schema and overwritten, so switching between releases never
+//! silently loses data.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::write_atomic;
+
+/// Field holding the format version of migrated JSON documents
+pub const FORMAT_VERSION_FIELD: &str = "format_version";
+
+static APPLIED: Lazy<Mutex<Vec<AppliedMigration>>> = Lazy::new(Default::default);
+
+type MigrateFn = fn(Value) -> Result<Value>;
+
+struct Migration {
+    from: u32,
+    description: &'static str,
+    document: MigrateFn,
+    /// Upgrade of a single logged change, for journaled stores
+    op: Option<MigrateFn>,
+}
+
+/// A migration that was applied to a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub store: String,
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    pub description: String,
+    /// Copy of the file as it was before the migration
+    pub backup: Option<PathBuf>,
+}
+
+/// Get the migrations applied since the application started
+pub fn applied_migrations() -> Vec<AppliedMigration> {
+    APPLIED.lock().clone()
+}
+
+/// Migrations of one store, from any older version to the current one
+pub struct Migrations {
+    store: &'static str,
+    current: u32,
+    steps: Vec<Migration>,
+}
+
+impl Migrations {
+    /// Create the migrations of a store whose current format is `current`
+    pub fn new(store: &'static str, current: u32) -> Self {
+        Self {
+            store,
+            current,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Add the migration from version `from` to `from + 1`
+    pub fn step(
+        self,
+        from: u32,
+        description: &'static str,
+        document: MigrateFn,
+    ) -> Self {
+        self.add(from, description, document, None)
+    }
+
+    /// Add the migration from version `from` to `from + 1` of a journaled
+    /// store, which also upgrades changes still in its log
+    pub fn step_with_ops(
+        self,
+        from: u32,
+        description: &'static str,
+        document: MigrateFn,
+        op: MigrateFn,
+    ) -> Self {
+        self.add(from, description, document, Some(op))
+    }
+
+    fn add(
+        mut self,
+        from: u32,
+        description: &'static str,
+        document: MigrateFn,
+        op: Option<MigrateFn>,
+    ) -> Self {
+        self.steps.push(Migration {
+            from,
+            description,
+            document,
+            op,
+        });
+        self
+    }
+
+    /// Get the store name
+    pub fn store(&self) -> &'static str {
+        self.store
+    }
+
+    /// Get the current format version
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Check that a file of version `found` can be opened by this build
+    pub fn check(&self, found: u32, path: &Path) -> Result<()> {
+        if found > self.current {
+            return Err(anyhow::anyhow!(
+                "{} was written by a newer version of Catalyst (format {}, this \
+                 version supports up to {}); refusing to open it so it isn't \
+                 overwritten",
+                path.display(),
+                found,
+                self.current
+            ));
+        }
+        Ok(())
+    }
+
+    /// Upgrade a document from version `found` to the current version
+    pub fn migrate_document(
+        &self,
+        found: u32,
+        mut document: Value,
+    ) -> Result<(Value, Vec<(u32, &'static str)>)> {
+        let mut applied = Vec::new();
+        for version in found..self.current {
+            let step = self.step_from(version)?;
+            document = (step.document)(document).map_err(|err| {
+                anyhow::anyhow!(
+                    "{} migration from format {} failed: {err:#}",
+                    self.store,
+                    version
+                )
+            })?;
+            applied.push((version, step.description));
+        }
+        Ok((document, applied))
+    }
+
+    /// Upgrade a logged change from version `found` to the current version
+    pub fn migrate_op(&self, found: u32, mut op: Value) -> Result<Value> {
+        for version in found..self.current {
+            if let Some(migrate) = self.step_from(version)?.op {
+                op = migrate(op)?;
+            }
+        }
+        Ok(op)
+    }
+
+    /// Record migrations applied to a file
+    pub fn record(
+        &self,
+        path: &Path,
+        applied: &[(u32, &'static str)],
+        backup: Option<&Path>,
+    ) {
+        let mut log = APPLIED.lock();
+        for (from, description) in applied {
+            tracing::info!(
+                "Migrated {} {} from format {} to {}: {}",
+                self.store,
+                path.display(),
+                from,
+                from + 1,
+                description
+            );
+            log.push(AppliedMigration {
+                store: self.store.to_string(),
+                path: path.to_path_buf(),
+                from: *from,
+                to: from + 1,
+                description: description.to_string(),
+                backup: backup.map(Path::to_path_buf),
+            });
+        }
+    }
+
+    fn step_from(&self, version: u32) -> Result<&Migration> {
+        self.steps
+            .iter()
+            .find(|step| step.from == version)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No {} migration from format {} to {}",
+                    self.store,
+                    version,
+                    version + 1
+                )
+            })
+    }
+}
+
+/// Copy a file to `<file>.v<version>.bak` before it is migrated, returning
+/// the backup path or `None` if the file doesn't exist
+pub fn backup_before_migration(
+    path: &Path,
+    version: u32,
+) -> Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{version}.bak"));
+    let backup = PathBuf::from(name);
+    std::fs::copy(path, &backup)?;
+    Ok(Some(backup))
+}
+
+/// Open a JSON document with a top-level `format_version` field, upgrading
+/// the file in place if it is older than the current format
+///
+/// Documents without the field are format 0.
+pub fn open_json_document(path: &Path, migrations: &Migrations) -> Result<Value> {
+    let mut document: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    let found = document
+        .get(FORMAT_VERSION_FIELD)
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+    migrations.check(found, path)?;
+    if found == migrations.current() {
+        return Ok(document);
+    }
+
+    let backup = backup_before_migration(path, found)?;
+    let applied;
+    (document, applied) = migrations.migrate_document(found, document)?;
+    if let Some(object) = document.as_object_mut() {
+        object.insert(
+            FORMAT_VERSION_FIELD.to_string(),
+            Value::from(migrations.current()),
+        );
+    }
+    write_atomic(path, &serde_json::to_vec_pretty(&document)?)?;
+    migrations.record(path, &applied, backup.as_deref());
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations() -> Migrations {
+        Migrations::new("session", 2)
+            .step(0, "rename tabs to editors", |mut document| {
+                let tabs = document["tabs"].take();
+                document["editors"] = tabs;
+                Ok(document)
+            })
+            .step(1, "add split layout", |mut document| {
+                document["layout"] = Value::from("single");
+                Ok(document)
+            })
+    }
+
+    #[test]
+    fn test_upgrades_with_backup_and_refuses_newer_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        std::fs::write(&path, r#"{"tabs":["a.rs"]}"#).unwrap();
+
+        let document = open_json_document(&path, &migrations()).unwrap();
+        assert_eq!(
+            document,
+            serde_json::json!({
+                "editors": ["a.rs"],
+                "tabs": null,
+                "layout": "single",
+                "format_version": 2,
+            })
+        );
+        let backup = dir.path().join("session.json.v0.bak");
+        assert_eq!(
+            std::fs::read_to_string(&backup).unwrap(),
+            r#"{"tabs":["a.rs"]}"#
+        );
+        let applied: Vec<_> = applied_migrations()
+            .into_iter()
+            .filter(|migration| migration.path == path)
+            .map(|migration| (migration.from, migration.to))
+            .collect();
+        assert_eq!(applied, vec![(0, 1), (1, 2)]);
+
+        // Reopening an up-to-date file changes nothing
+        assert_eq!(open_json_document(&path, &migrations()).unwrap(), document);
+
+        std::fs::write(&path, r#"{"format_version":3}"#).unwrap();
+        let err = open_json_document(&path, &migrations()).unwrap_err();
+        assert!(err.to_string().contains("newer version"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"format_version":3}"#
+        );
+    }
+}
@@ -3,12 +3,188 @@
 //! This module contains the plugin interfaces and extension points for Catalyst IDE.
 //! It allows for modular functionality to be added without modifying core editor code.
 
+pub mod agent_replay;
 pub mod ai_assistant;
+pub mod alloc_tracking;
+pub mod api_contract;
+pub mod attachments;
+pub mod attention;
+pub mod automations;
+pub mod bench;
+pub mod branch_stacks;
+pub mod call_limits;
+pub mod capability_cache;
+pub mod catalyst_ignore;
+pub mod ci_status;
+pub mod citations;
+pub mod clock;
+pub mod command_resolver;
+pub mod control_socket;
+pub mod conversation_mode;
+pub mod coverage;
+pub mod crawler;
+pub mod credentials;
+pub mod degradation;
+pub mod dependency_updates;
+pub mod determinism;
+pub mod diagnostics;
+pub mod diff_context;
+pub mod doc_generator;
+pub mod dry_run;
+pub mod edit_review;
+pub mod embedding;
+pub mod error_presentation;
+pub mod filesystem;
+pub mod filesystem_server;
+pub mod focus_mode;
+pub mod git_server;
+pub mod index_store;
+pub mod interner;
+pub mod journal;
+pub mod license_scan;
+pub mod log_control;
 pub mod manager;
+pub mod mcp_catalog;
+pub mod mcp_payload;
+pub mod mcp_scaffold;
 pub mod mcp_server;
+pub mod memory_store;
+pub mod mentions;
+pub mod merge_assistant;
+pub mod message_overrides;
+pub mod metrics;
+pub mod migration;
+pub mod network_policy;
+pub mod onboarding;
+pub mod path_watch;
+pub mod process_registry;
+pub mod project_chat;
+pub mod redaction;
+pub mod refactor;
+pub mod reports;
+pub mod rerank;
+pub mod resource_updates;
+pub mod retrieval;
+pub mod sampling;
+pub mod sandbox;
+pub mod scheduler;
+pub mod semantic_diff;
+pub mod settings_bundle;
+pub mod shutdown;
 pub mod sidebar;
+pub mod single_flight;
+pub mod slash_commands;
+pub mod sparse_index;
+pub mod speech;
+pub mod stack_detection;
+pub mod stdio_server;
+pub mod startup_profile;
+pub mod style_profile;
+pub mod symbol_graph;
+pub mod team_config;
+pub mod test_history;
+pub mod tool_output;
+pub mod tool_usage;
+pub mod tool_viewers;
+pub mod trigram_index;
+pub mod usage_simulation;
+pub mod usage_store;
+pub mod vector_index;
+pub mod virtual_documents;
+pub mod webhook;
+pub mod workspace_analyzer;
+pub mod workspace_env;
+pub mod workspace_roots;
 
+pub use agent_replay::*;
 pub use ai_assistant::*;
+pub use alloc_tracking::*;
+pub use api_contract::*;
+pub use attachments::*;
+pub use attention::*;
+pub use automations::*;
+pub use bench::*;
+pub use branch_stacks::*;
+pub use call_limits::*;
+pub use capability_cache::*;
+pub use catalyst_ignore::*;
+pub use ci_status::*;
+pub use citations::*;
+pub use clock::*;
+pub use command_resolver::*;
+pub use control_socket::*;
+pub use conversation_mode::*;
+pub use coverage::*;
+pub use crawler::*;
+pub use credentials::*;
+pub use degradation::*;
+pub use dependency_updates::*;
+pub use determinism::*;
+pub use diagnostics::*;
+pub use diff_context::*;
+pub use doc_generator::*;
+pub use dry_run::*;
+pub use edit_review::*;
+pub use embedding::*;
+pub use error_presentation::*;
+pub use filesystem::*;
+pub use filesystem_server::*;
+pub use focus_mode::*;
+pub use git_server::*;
+pub use index_store::*;
+pub use interner::*;
+pub use journal::*;
+pub use license_scan::*;
+pub use log_control::*;
 pub use manager::*;
+pub use mcp_catalog::*;
+pub use mcp_payload::*;
+pub use mcp_scaffold::*;
 pub use mcp_server::*;
+pub use memory_store::*;
+pub use mentions::*;
+pub use merge_assistant::*;
+pub use message_overrides::*;
+pub use metrics::*;
+pub use migration::*;
+pub use network_policy::*;
+pub use onboarding::*;
+pub use path_watch::*;
+pub use process_registry::*;
+pub use project_chat::*;
+pub use redaction::*;
+pub use refactor::*;
+pub use reports::*;
+pub use rerank::*;
+pub use resource_updates::*;
+pub use retrieval::*;
+pub use sampling::*;
+pub use sandbox::*;
+pub use scheduler::*;
+pub use semantic_diff::*;
+pub use settings_bundle::*;
+pub use shutdown::*;
 pub use sidebar::*;
+pub use single_flight::*;
+pub use slash_commands::*;
+pub use sparse_index::*;
+pub use speech::*;
+pub use stack_detection::*;
+pub use stdio_server::*;
+pub use startup_profile::*;
+pub use style_profile::*;
+pub use symbol_graph::*;
+pub use team_config::*;
+pub use test_history::*;
+pub use tool_output::*;
+pub use tool_usage::*;
+pub use tool_viewers::*;
+pub use trigram_index::*;
+pub use usage_simulation::*;
+pub use usage_store::*;
+pub use vector_index::*;
+pub use virtual_documents::*;
+pub use webhook::*;
+pub use workspace_analyzer::*;
+pub use workspace_env::*;
+pub use workspace_roots::*;
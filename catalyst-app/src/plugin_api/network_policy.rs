@@ -0,0 +1,40 @@
+//! Network Policy
+//!
+//! This module defines how much network access AI features may use. It is
+//! consulted whenever a feature can run either against a remote service or
+//! locally, e.g. when choosing an embedding provider.
+
+use serde::{Deserialize, Serialize};
+
+/// Network access allowed for AI features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkPolicy {
+    /// Remote services are used whenever they are configured
+    #[default]
+    Online,
+    /// Local implementations are preferred, remote services are a fallback
+    PreferLocal,
+    /// Source code never leaves the machine, but models and metadata may
+    /// still be downloaded
+    LocalOnly,
+    /// No network access at all
+    Offline,
+}
+
+impl NetworkPolicy {
+    /// Check if workspace content may be sent to remote services
+    pub fn allows_remote_processing(&self) -> bool {
+        matches!(self, NetworkPolicy::Online | NetworkPolicy::PreferLocal)
+    }
+
+    /// Check if models and other assets may be downloaded
+    pub fn allows_downloads(&self) -> bool {
+        !matches!(self, NetworkPolicy::Offline)
+    }
+
+    /// Check if local implementations should be tried first
+    pub fn prefers_local(&self) -> bool {
+        !matches!(self, NetworkPolicy::Online)
+    }
+}
@@ -0,0 +1,781 @@
+//! First-Run Onboarding
+//!
+//! This module walks a new user through the AI and MCP setup: it detects
+//! the runtimes the catalog servers need, stores the API key of an AI
+//! provider, lets the user pick servers from the [`MCP_CATALOG`], checks
+//! that each picked server has its programs and credentials, and writes
+//! the servers to the user's MCP settings, used in every workspace.
+//!
+//! The flow is an [`OnboardingWizard`] moving through the
+//! [`OnboardingStep`]s, which knows nothing about the UI so the whole flow
+//! can be driven headlessly; the onboarding panel forwards its commands to
+//! it, and is registered at startup until the onboarding completes once.
+//! Secrets go to the system keychain through the [`CredentialStore`], never
+//! to the settings; the panel says so when no keychain is available and
+//! they are kept in a file instead.
+
+use anyhow::{Result, anyhow};
+use catalyst_core::directory::Directory;
+use floem::View;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    CommandResolver, CredentialStore, MCP_CATALOG, McpCatalogEntry, McpRuntime,
+    PanelCommand, PanelCommandResult, ServerHealthCheck, SidebarPanelInfo,
+    SidebarPanelPlugin, SidebarPanelRegistry, SidebarPosition, add_mcp_servers,
+    current_time, mcp_catalog_entry, text_panel_view, user_mcp_settings_path,
+    write_atomic,
+};
+
+/// File in the config directory recording a completed onboarding
+pub const ONBOARDING_FILE: &str = "onboarding.json";
+
+/// Programs checked for on the first step, besides the catalog runtimes
+const EXTRA_PROGRAMS: &[&str] = &["git", "docker", "kubectl", "ollama"];
+
+/// An AI provider the onboarding can set up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AiProviderSetup {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Environment variable holding the API key, None for local providers
+    pub api_key_env: Option<&'static str>,
+    /// Program a local provider runs as
+    pub program: Option<&'static str>,
+}
+
+/// Providers offered by the onboarding
+pub const AI_PROVIDERS: &[AiProviderSetup] = &[
+    AiProviderSetup {
+        id: "anthropic",
+        name: "Anthropic",
+        api_key_env: Some("ANTHROPIC_API_KEY"),
+        program: None,
+    },
+    AiProviderSetup {
+        id: "openai",
+        name: "OpenAI",
+        api_key_env: Some("OPENAI_API_KEY"),
+        program: None,
+    },
+    AiProviderSetup {
+        id: "gemini",
+        name: "Google Gemini",
+        api_key_env: Some("GEMINI_API_KEY"),
+        program: None,
+    },
+    AiProviderSetup {
+        id: "ollama",
+        name: "Ollama",
+        api_key_env: None,
+        program: Some("ollama"),
+    },
+];
+
+/// Step of the onboarding, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnboardingStep {
+    Runtimes,
+    Provider,
+    McpServers,
+    HealthChecks,
+    Finish,
+    Done,
+}
+
+impl OnboardingStep {
+    fn next(self) -> Self {
+        match self {
+            Self::Runtimes => Self::Provider,
+            Self::Provider => Self::McpServers,
+            Self::McpServers => Self::HealthChecks,
+            Self::HealthChecks => Self::Finish,
+            Self::Finish | Self::Done => Self::Done,
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Self::Runtimes | Self::Provider => Self::Runtimes,
+            Self::McpServers => Self::Provider,
+            Self::HealthChecks => Self::McpServers,
+            Self::Finish => Self::HealthChecks,
+            Self::Done => Self::Done,
+        }
+    }
+}
+
+/// Whether a program was found on the `PATH`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuntimeStatus {
+    pub program: String,
+    pub path: Option<PathBuf>,
+}
+
+/// What a completed onboarding chose, saved to [`ONBOARDING_FILE`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OnboardingRecord {
+    pub completed_at: SystemTime,
+    pub provider: Option<String>,
+    pub mcp_servers: Vec<String>,
+}
+
+impl OnboardingRecord {
+    /// Get the record in the config directory
+    pub fn default_path() -> Option<PathBuf> {
+        Directory::config_directory().map(|dir| dir.join(ONBOARDING_FILE))
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Check if the onboarding should be offered, i.e. it never completed
+pub fn onboarding_needed() -> bool {
+    OnboardingRecord::default_path().is_some_and(|path| needs_onboarding(&path))
+}
+
+fn needs_onboarding(record_path: &Path) -> bool {
+    matches!(OnboardingRecord::load(record_path), Ok(None))
+}
+
+/// Register the onboarding panel on first run, i.e. when no onboarding was
+/// recorded at `record_path`, returning whether it was registered
+pub fn register_onboarding_panel(
+    registry: &SidebarPanelRegistry,
+    record_path: &Path,
+) -> Result<bool> {
+    if !needs_onboarding(record_path) {
+        return Ok(false);
+    }
+    registry.register_panel(
+        OnboardingPanel::ID.to_string(),
+        Box::new(OnboardingPanel::new()),
+    )?;
+    Ok(true)
+}
+
+/// State machine of the onboarding flow
+pub struct OnboardingWizard<'a> {
+    step: OnboardingStep,
+    /// User settings the picked servers are written to
+    user_settings: PathBuf,
+    resolver: CommandResolver,
+    credentials: &'a CredentialStore,
+    env: HashMap<String, String>,
+    runtimes: Vec<RuntimeStatus>,
+    provider: Option<&'static AiProviderSetup>,
+    provider_skipped: bool,
+    servers: BTreeSet<&'static str>,
+    health_checks: Vec<ServerHealthCheck>,
+}
+
+impl OnboardingWizard<'static> {
+    /// Start the onboarding with the environment and user settings of the
+    /// current process
+    pub fn from_env() -> Self {
+        Self::new(
+            &user_mcp_settings_path().unwrap_or_default(),
+            CommandResolver::from_env(),
+            CredentialStore::global(),
+            std::env::vars().collect(),
+        )
+    }
+}
+
+impl<'a> OnboardingWizard<'a> {
+    pub fn new(
+        user_settings: &Path,
+        resolver: CommandResolver,
+        credentials: &'a CredentialStore,
+        env: HashMap<String, String>,
+    ) -> Self {
+        let mut wizard = Self {
+            step: OnboardingStep::Runtimes,
+            user_settings: user_settings.to_path_buf(),
+            resolver,
+            credentials,
+            env,
+            runtimes: Vec::new(),
+            provider: None,
+            provider_skipped: false,
+            servers: BTreeSet::new(),
+            health_checks: Vec::new(),
+        };
+        wizard.detect_runtimes();
+        wizard
+    }
+
+    pub fn step(&self) -> OnboardingStep {
+        self.step
+    }
+
+    pub fn runtimes(&self) -> &[RuntimeStatus] {
+        &self.runtimes
+    }
+
+    pub fn provider(&self) -> Option<&'static AiProviderSetup> {
+        self.provider
+    }
+
+    pub fn selected_servers(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.servers.iter().copied()
+    }
+
+    pub fn health_checks(&self) -> &[ServerHealthCheck] {
+        &self.health_checks
+    }
+
+    /// Look for the runtimes again, e.g. after the user installed one
+    pub fn detect_runtimes(&mut self) {
+        let programs: BTreeSet<&str> = McpRuntime::ALL
+            .iter()
+            .map(McpRuntime::executable)
+            .chain(EXTRA_PROGRAMS.iter().copied())
+            .collect();
+        self.runtimes = programs
+            .into_iter()
+            .map(|program| RuntimeStatus {
+                program: program.to_string(),
+                path: self.resolver.find_executable(program),
+            })
+            .collect();
+    }
+
+    fn has_program(&self, program: &str) -> bool {
+        self.resolver.find_executable(program).is_some()
+    }
+
+    fn has_credential(&self, name: &str) -> bool {
+        self.env.get(name).is_some_and(|value| !value.is_empty())
+            || self.credentials.get_env(name).is_some()
+    }
+
+    fn expect_step(&self, steps: &[OnboardingStep], action: &str) -> Result<()> {
+        if steps.contains(&self.step) {
+            Ok(())
+        } else {
+            Err(anyhow!("Cannot {} on the {:?} step", action, self.step))
+        }
+    }
+
+    /// Choose the AI provider, storing its API key
+    ///
+    /// The key may be left out when its variable is already set or stored.
+    pub fn choose_provider(
+        &mut self,
+        id: &str,
+        api_key: Option<&str>,
+    ) -> Result<()> {
+        self.expect_step(&[OnboardingStep::Provider], "choose a provider")?;
+        let provider = AI_PROVIDERS
+            .iter()
+            .find(|provider| provider.id == id)
+            .ok_or_else(|| anyhow!("Unknown AI provider '{}'", id))?;
+        if let Some(program) = provider
+            .program
+            .filter(|program| !self.has_program(program))
+        {
+            return Err(anyhow!("{} needs {} on the PATH", provider.name, program));
+        }
+        if let Some(name) = provider.api_key_env {
+            match api_key.map(str::trim).filter(|key| !key.is_empty()) {
+                Some(key) => self.credentials.set_env(name, key)?,
+                None if self.has_credential(name) => {}
+                None => {
+                    return Err(anyhow!("{} needs an API key", provider.name));
+                }
+            }
+        }
+        self.provider = Some(provider);
+        self.provider_skipped = false;
+        Ok(())
+    }
+
+    /// Continue without an AI provider
+    pub fn skip_provider(&mut self) -> Result<()> {
+        self.expect_step(&[OnboardingStep::Provider], "skip the provider")?;
+        self.provider = None;
+        self.provider_skipped = true;
+        self.step = self.step.next();
+        Ok(())
+    }
+
+    /// Pick or drop a catalog server
+    pub fn set_server_enabled(&mut self, id: &str, enabled: bool) -> Result<()> {
+        self.expect_step(
+            &[OnboardingStep::McpServers, OnboardingStep::HealthChecks],
+            "change the servers",
+        )?;
+        let entry = mcp_catalog_entry(id)
+            .ok_or_else(|| anyhow!("Unknown MCP server '{}'", id))?;
+        if enabled {
+            self.servers.insert(entry.id);
+        } else {
+            self.servers.remove(entry.id);
+        }
+        if self.step == OnboardingStep::HealthChecks {
+            self.run_health_checks();
+        }
+        Ok(())
+    }
+
+    /// Store a credential a picked server needs
+    pub fn set_credential(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(anyhow!("The value of {} is empty", name));
+        }
+        self.credentials.set_env(name, value)?;
+        if self.step == OnboardingStep::HealthChecks {
+            self.run_health_checks();
+        }
+        Ok(())
+    }
+
+    /// Check the programs and credentials of the picked servers
+    pub fn run_health_checks(&mut self) {
+        self.health_checks = self
+            .servers
+            .iter()
+            .filter_map(|id| mcp_catalog_entry(id))
+            .map(|entry| {
+                entry.health_check(&self.resolver, |name| self.has_credential(name))
+            })
+            .collect();
+    }
+
+    /// Go to the next step if the current one is complete
+    pub fn advance(&mut self) -> Result<OnboardingStep> {
+        match self.step {
+            OnboardingStep::Provider
+                if self.provider.is_none() && !self.provider_skipped =>
+            {
+                return Err(anyhow!("Choose an AI provider or skip this step"));
+            }
+            OnboardingStep::McpServers => self.run_health_checks(),
+            OnboardingStep::HealthChecks => {
+                let failed: Vec<&str> = self
+                    .health_checks
+                    .iter()
+                    .filter(|check| !check.passed())
+                    .map(|check| check.server_id.as_str())
+                    .collect();
+                if !failed.is_empty() {
+                    return Err(anyhow!(
+                        "Fix or deselect the servers that failed their checks: {}",
+                        failed.join(", ")
+                    ));
+                }
+            }
+            OnboardingStep::Finish => {
+                return Err(anyhow!("Finish the onboarding to write the settings"));
+            }
+            _ => {}
+        }
+        self.step = self.step.next();
+        Ok(self.step)
+    }
+
+    /// Go back to the previous step
+    pub fn back(&mut self) -> OnboardingStep {
+        self.step = self.step.previous();
+        self.step
+    }
+
+    /// Write the picked servers to the user's MCP settings and record the
+    /// onboarding as completed
+    pub fn finish(
+        &mut self,
+        record_path: Option<&Path>,
+    ) -> Result<OnboardingRecord> {
+        self.expect_step(&[OnboardingStep::Finish], "finish")?;
+        let servers: BTreeMap<String, _> = self
+            .servers
+            .iter()
+            .filter_map(|id| mcp_catalog_entry(id))
+            .map(|entry| (entry.id.to_string(), entry.team_server()))
+            .collect();
+        if !servers.is_empty() {
+            add_mcp_servers(&self.user_settings, servers)?;
+        }
+        let record = OnboardingRecord {
+            completed_at: current_time(),
+            provider: self.provider.map(|provider| provider.id.to_string()),
+            mcp_servers: self.servers.iter().map(|id| id.to_string()).collect(),
+        };
+        if let Some(path) = record_path {
+            record.save(path)?;
+        }
+        self.step = OnboardingStep::Done;
+        Ok(record)
+    }
+
+    /// Describe the current step for the panel
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!("Step: {:?}", self.step), String::new()];
+        match self.step {
+            OnboardingStep::Runtimes => {
+                for runtime in &self.runtimes {
+                    lines.push(match &runtime.path {
+                        Some(path) => {
+                            format!("✓ {}  {}", runtime.program, path.display())
+                        }
+                        None => format!("✗ {}  not found", runtime.program),
+                    });
+                }
+            }
+            OnboardingStep::Provider => {
+                lines.push(match self.credentials.path() {
+                    None => "API keys are stored in the system keychain".to_string(),
+                    Some(path) => format!(
+                        "No system keychain is available; API keys are stored \
+                         in {}",
+                        path.display()
+                    ),
+                });
+                lines.push(String::new());
+                for provider in AI_PROVIDERS {
+                    let chosen = self.provider.is_some_and(|p| p.id == provider.id);
+                    lines.push(format!(
+                        "{} {} ({})",
+                        if chosen { "●" } else { "○" },
+                        provider.name,
+                        provider.id
+                    ));
+                }
+            }
+            OnboardingStep::McpServers => {
+                for entry in MCP_CATALOG {
+                    lines.push(self.server_line(entry));
+                }
+            }
+            OnboardingStep::HealthChecks => {
+                for check in &self.health_checks {
+                    let mut problems = check.missing_programs.clone();
+                    problems.extend(check.missing_credentials.clone());
+                    lines.push(if check.passed() {
+                        format!("✓ {}", check.server_id)
+                    } else {
+                        format!(
+                            "✗ {}  missing {}",
+                            check.server_id,
+                            problems.join(", ")
+                        )
+                    });
+                }
+            }
+            OnboardingStep::Finish | OnboardingStep::Done => {
+                lines.push(format!(
+                    "Provider: {}",
+                    self.provider.map_or("none", |provider| provider.name)
+                ));
+                lines.push(format!(
+                    "MCP servers: {}",
+                    self.servers.iter().copied().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn server_line(&self, entry: &McpCatalogEntry) -> String {
+        let mut line = format!(
+            "[{}] {} ({}): {}",
+            if self.servers.contains(entry.id) {
+                "x"
+            } else {
+                " "
+            },
+            entry.name,
+            entry.id,
+            entry.description
+        );
+        let missing = entry.missing_programs(&self.resolver);
+        if !missing.is_empty() {
+            line.push_str(&format!(", needs {}", missing.join(", ")));
+        }
+        line
+    }
+}
+
+/// Sidebar panel driving the onboarding
+///
+/// Commands: `next`, `back`, `skip_provider`, `detect_runtimes`,
+/// `choose_provider` with `id` and an optional `api_key`, `set_server` with
+/// `id` and `enabled`, `set_credential` with `name` and `value`, and
+/// `finish`.
+pub struct OnboardingPanel {
+    wizard: Arc<RwLock<OnboardingWizard<'static>>>,
+}
+
+impl OnboardingPanel {
+    pub const ID: &'static str = "catalyst.onboarding";
+
+    pub fn new() -> Self {
+        Self {
+            wizard: Arc::new(RwLock::new(OnboardingWizard::from_env())),
+        }
+    }
+}
+
+impl Default for OnboardingPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SidebarPanelPlugin for OnboardingPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Get Started".to_string(),
+            description: "Set up an AI provider and MCP servers".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: onboarding_needed(),
+            resizable: true,
+            minimum_width: Some(300),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let wizard = self.wizard.clone();
+        text_panel_view(move || wizard.read().summary())
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        let wizard = self.wizard.read();
+        serde_json::json!({
+            "step": wizard.step(),
+            "runtimes": wizard.runtimes(),
+            "provider": wizard.provider().map(|provider| provider.id),
+            "servers": wizard.selected_servers().collect::<Vec<_>>(),
+            "health_checks": wizard.health_checks(),
+        })
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        let param = |key: &str| {
+            command
+                .parameters
+                .get(key)
+                .and_then(|value| value.as_str())
+                .ok_or_else(|| anyhow!("Missing parameter '{}'", key))
+        };
+        let outcome = {
+            let mut wizard = self.wizard.write();
+            match command.command_id.as_str() {
+                "next" => wizard.advance().map(|_| ()),
+                "back" => {
+                    wizard.back();
+                    Ok(())
+                }
+                "skip_provider" => wizard.skip_provider(),
+                "detect_runtimes" => {
+                    wizard.detect_runtimes();
+                    Ok(())
+                }
+                "choose_provider" => param("id").and_then(|id| {
+                    wizard.choose_provider(id, param("api_key").ok())
+                }),
+                "set_server" => param("id").and_then(|id| {
+                    let enabled = command
+                        .parameters
+                        .get("enabled")
+                        .and_then(|value| value.as_bool())
+                        .unwrap_or(true);
+                    wizard.set_server_enabled(id, enabled)
+                }),
+                "set_credential" => param("name")
+                    .and_then(|name| wizard.set_credential(name, param("value")?)),
+                "finish" => wizard
+                    .finish(OnboardingRecord::default_path().as_deref())
+                    .map(|_| ()),
+                other => Err(anyhow!("Unknown command '{}'", other)),
+            }
+        };
+        Ok(match outcome {
+            Ok(()) => PanelCommandResult {
+                success: true,
+                result: Some(self.get_state()),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: Some(self.get_state()),
+                error: Some(format!("{err:#}")),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        CommandPlatform, TEAM_CONFIG_DIR, TeamConfig, USER_MCP_SETTINGS_FILE,
+    };
+
+    #[cfg(unix)]
+    #[test]
+    fn test_onboarding_flow() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = tempfile::tempdir().unwrap();
+        for program in ["npx", "git"] {
+            let path = bin.path().join(program);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+        let resolver = CommandResolver::new(
+            CommandPlatform::Unix,
+            Some(bin.path().as_os_str().to_owned()),
+            None,
+            None,
+        );
+        let config = tempfile::tempdir().unwrap();
+        let credentials =
+            CredentialStore::open(&config.path().join("credentials.toml")).unwrap();
+        let user_settings = config.path().join(USER_MCP_SETTINGS_FILE);
+        let mut wizard = OnboardingWizard::new(
+            &user_settings,
+            resolver,
+            &credentials,
+            HashMap::new(),
+        );
+
+        let npx = wizard
+            .runtimes()
+            .iter()
+            .find(|r| r.program == "npx")
+            .unwrap();
+        assert!(npx.path.is_some());
+        let uvx = wizard
+            .runtimes()
+            .iter()
+            .find(|r| r.program == "uvx")
+            .unwrap();
+        assert!(uvx.path.is_none());
+
+        assert!(wizard.choose_provider("anthropic", None).is_err());
+        assert_eq!(wizard.advance().unwrap(), OnboardingStep::Provider);
+        assert!(wizard.advance().is_err());
+        assert!(wizard.choose_provider("anthropic", None).is_err());
+        wizard
+            .choose_provider("anthropic", Some("sk-test"))
+            .unwrap();
+        assert_eq!(credentials.get_env("ANTHROPIC_API_KEY").unwrap(), "sk-test");
+        assert_eq!(wizard.advance().unwrap(), OnboardingStep::McpServers);
+
+        wizard.set_server_enabled("github", true).unwrap();
+        wizard.set_server_enabled("fetch", true).unwrap();
+        assert!(wizard.set_server_enabled("nonexistent", true).is_err());
+        assert_eq!(wizard.advance().unwrap(), OnboardingStep::HealthChecks);
+        let checks = wizard.health_checks();
+        assert_eq!(checks[0].server_id, "fetch");
+        assert_eq!(checks[0].missing_programs, ["uvx"]);
+        assert_eq!(
+            checks[1].missing_credentials,
+            ["GITHUB_PERSONAL_ACCESS_TOKEN"]
+        );
+        assert!(wizard.advance().is_err());
+
+        // Fixing the problems reruns the checks
+        wizard.set_server_enabled("fetch", false).unwrap();
+        wizard
+            .set_credential("GITHUB_PERSONAL_ACCESS_TOKEN", "ghp_test")
+            .unwrap();
+        assert!(wizard.health_checks().iter().all(ServerHealthCheck::passed));
+        assert_eq!(wizard.advance().unwrap(), OnboardingStep::Finish);
+        assert!(wizard.advance().is_err());
+
+        let record_path = config.path().join(ONBOARDING_FILE);
+        let record = wizard.finish(Some(&record_path)).unwrap();
+        assert_eq!(wizard.step(), OnboardingStep::Done);
+        assert_eq!(record.provider.as_deref(), Some("anthropic"));
+        assert_eq!(
+            OnboardingRecord::load(&record_path)
+                .unwrap()
+                .unwrap()
+                .mcp_servers,
+            ["github"]
+        );
+
+        let written = std::fs::read_to_string(&user_settings).unwrap();
+        assert!(written.contains("@modelcontextprotocol/server-github"));
+        assert!(!written.contains("ghp_test"));
+        // The servers are used in every workspace
+        let workspace = tempfile::tempdir().unwrap();
+        assert!(!workspace.path().join(TEAM_CONFIG_DIR).exists());
+        let team = TeamConfig::load_with_user_settings(
+            workspace.path(),
+            Some(&user_settings),
+        )
+        .unwrap();
+        let github = &team.settings.mcp_servers["github"];
+        assert_eq!(
+            github.env["GITHUB_PERSONAL_ACCESS_TOKEN"],
+            "${GITHUB_PERSONAL_ACCESS_TOKEN}"
+        );
+    }
+
+    #[test]
+    fn test_onboarding_panel_registered_on_first_run() {
+        let config = tempfile::tempdir().unwrap();
+        let record_path = config.path().join(ONBOARDING_FILE);
+        let registry = SidebarPanelRegistry::default();
+        assert!(register_onboarding_panel(&registry, &record_path).unwrap());
+        assert!(registry.get_panel(OnboardingPanel::ID).is_some());
+
+        OnboardingRecord {
+            completed_at: SystemTime::UNIX_EPOCH,
+            provider: None,
+            mcp_servers: Vec::new(),
+        }
+        .save(&record_path)
+        .unwrap();
+        let registry = SidebarPanelRegistry::default();
+        assert!(!register_onboarding_panel(&registry, &record_path).unwrap());
+        assert!(registry.get_panel(OnboardingPanel::ID).is_none());
+    }
+}
@@ -0,0 +1,597 @@
+//! Path Watching
+//!
+//! This module backs the `watch_path` and `unwatch_path` tools of the
+//! built-in filesystem server, for workflows like "watch the build output
+//! directory and tell me when the bundle size changes". Watched paths go
+//! through the [`WorkspaceSandbox`] like every other filesystem tool, so
+//! build directories ignored by default, such as `dist/`, need a `!dist/`
+//! line in `.catalystignore` first.
+//!
+//! The queue keeps one entry per path, so a file rewritten a hundred times
+//! during a build shows up once with its event count, and holds at most
+//! [`PathWatchLimits::max_pending_changes`] paths; changes to other paths
+//! past that are only counted. The server sends the queued changes as a
+//! [`PATHS_CHANGED`] notification once they settle, and the agent loop
+//! reads them from a [`PathChangeStream`] and hands them to the model with
+//! its next request.
+
+use anyhow::{Result, anyhow};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin_api::{
+    AiMessage, McpNotification, McpNotificationHandler, McpServerNotification,
+    McpServerRegistry, McpTool, MessageRole, SharedPath, ToolEffect,
+    WorkspaceSandbox, current_time,
+};
+
+/// Name of the tool the assistant uses to start watching a path
+pub const WATCH_PATH_TOOL_NAME: &str = "watch_path";
+
+/// Name of the tool the assistant uses to stop watching a path
+pub const UNWATCH_PATH_TOOL_NAME: &str = "unwatch_path";
+
+/// Notification a server sends with the changes of its watched paths, the
+/// message for the model in its `message` parameter
+pub const PATHS_CHANGED: &str = "notifications/catalyst/pathsChanged";
+
+/// Time changes are collected before they are sent, so a build writing
+/// many files is reported once
+pub const DEFAULT_PATH_CHANGE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Notifications a [`PathChangeStream`] keeps until they are taken
+const MAX_PENDING_NOTIFICATIONS: usize = 16;
+
+/// Time the forwarding thread waits before checking its watcher is still
+/// in use
+const FORWARD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits on what the assistant may watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathWatchLimits {
+    /// Paths watched at the same time
+    pub max_watches: usize,
+    /// Changed paths queued before further changes are only counted
+    pub max_pending_changes: usize,
+}
+
+impl Default for PathWatchLimits {
+    fn default() -> Self {
+        Self {
+            max_watches: 8,
+            max_pending_changes: 50,
+        }
+    }
+}
+
+/// How a watched path changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+impl PathChangeKind {
+    fn from_event(kind: &EventKind) -> Option<Self> {
+        match kind {
+            EventKind::Create(_) => Some(Self::Created),
+            EventKind::Modify(_) => Some(Self::Modified),
+            EventKind::Remove(_) => Some(Self::Removed),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Removed => "removed",
+        }
+    }
+}
+
+/// Change of one path since the changes were last taken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathChange {
+    /// Path relative to the workspace root
+    pub path: SharedPath,
+    pub kind: PathChangeKind,
+    /// Events seen for the path
+    pub events: u32,
+    /// Size of the file when the change was taken
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct ChangeQueue {
+    changes: BTreeMap<SharedPath, PathChange>,
+    /// Changes not queued because the queue was full
+    dropped: usize,
+}
+
+impl ChangeQueue {
+    fn push(&mut self, path: SharedPath, kind: PathChangeKind, limit: usize) {
+        if let Some(change) = self.changes.get_mut(&path) {
+            change.events += 1;
+            // A file created and then written is still new
+            if !(change.kind == PathChangeKind::Created
+                && kind == PathChangeKind::Modified)
+            {
+                change.kind = kind;
+            }
+            return;
+        }
+        if self.changes.len() >= limit {
+            self.dropped += 1;
+            return;
+        }
+        self.changes.insert(
+            path.clone(),
+            PathChange {
+                path,
+                kind,
+                events: 1,
+                size: None,
+            },
+        );
+    }
+}
+
+struct SharedQueue {
+    queue: Mutex<ChangeQueue>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct WatchState {
+    /// Created on the first watch
+    watcher: Option<RecommendedWatcher>,
+    /// Watched paths, relative to the workspace root
+    watched: BTreeSet<SharedPath>,
+}
+
+/// Watches paths for the assistant and queues their changes
+pub struct PathWatcher {
+    sandbox: WorkspaceSandbox,
+    limits: PathWatchLimits,
+    state: Mutex<WatchState>,
+    shared: Arc<SharedQueue>,
+}
+
+impl PathWatcher {
+    pub fn new(sandbox: WorkspaceSandbox, limits: PathWatchLimits) -> Self {
+        Self {
+            sandbox,
+            limits,
+            state: Mutex::new(WatchState::default()),
+            shared: Arc::new(SharedQueue {
+                queue: Mutex::new(ChangeQueue::default()),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Get the watched paths, relative to the workspace root
+    pub fn watched(&self) -> Vec<PathBuf> {
+        self.state
+            .lock()
+            .watched
+            .iter()
+            .map(SharedPath::to_path_buf)
+            .collect()
+    }
+
+    /// Start watching a workspace path
+    pub fn watch(&self, path: &Path, recursive: bool) -> Result<()> {
+        let resolved = self.sandbox.check_access(path)?;
+        if !resolved.exists() {
+            return Err(anyhow!("'{}' does not exist", path.display()));
+        }
+        let relative = self.relative(&resolved);
+        let mut state = self.state.lock();
+        if state.watched.contains(&relative) {
+            return Ok(());
+        }
+        if state.watched.len() >= self.limits.max_watches {
+            return Err(anyhow!(
+                "Can't watch more than {} paths; stop watching one first",
+                self.limits.max_watches
+            ));
+        }
+
+        if state.watcher.is_none() {
+            let shared = self.shared.clone();
+            let sandbox = self.sandbox.clone();
+            let limit = self.limits.max_pending_changes;
+            let watcher = notify::recommended_watcher(
+                move |event: notify::Result<notify::Event>| match event {
+                    Ok(event) => record_event(&shared, &sandbox, &event, limit),
+                    Err(err) => tracing::warn!("Path watch error: {err}"),
+                },
+            )?;
+            state.watcher = Some(watcher);
+        }
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        state
+            .watcher
+            .as_mut()
+            .expect("watcher was just created")
+            .watch(&resolved, mode)?;
+        state.watched.insert(relative);
+        Ok(())
+    }
+
+    /// Stop watching a path
+    pub fn unwatch(&self, path: &Path) -> Result<()> {
+        let resolved = self.sandbox.check_access(path)?;
+        let relative = self.relative(&resolved);
+        let mut state = self.state.lock();
+        if !state.watched.remove(&relative) {
+            return Err(anyhow!("'{}' is not being watched", path.display()));
+        }
+        if let Some(watcher) = state.watcher.as_mut() {
+            watcher.unwatch(&resolved)?;
+        }
+        if state.watched.is_empty() {
+            state.watcher = None;
+        }
+        Ok(())
+    }
+
+    /// Take the queued changes, with the current size of changed files, and
+    /// the number of changes dropped because the queue was full
+    pub fn take_changes(&self) -> (Vec<PathChange>, usize) {
+        let queue = std::mem::take(&mut *self.shared.queue.lock());
+        let changes = queue
+            .changes
+            .into_values()
+            .map(|mut change| {
+                if change.kind != PathChangeKind::Removed {
+                    change.size =
+                        std::fs::metadata(self.sandbox.root().join(&change.path))
+                            .ok()
+                            .filter(|metadata| metadata.is_file())
+                            .map(|metadata| metadata.len());
+                }
+                change
+            })
+            .collect();
+        (changes, queue.dropped)
+    }
+
+    /// Take the queued changes as a message for the model, or `None` if
+    /// nothing changed
+    pub fn take_notification(&self) -> Option<String> {
+        let (changes, dropped) = self.take_changes();
+        if changes.is_empty() && dropped == 0 {
+            return None;
+        }
+        let mut lines = vec!["Changes in watched paths:".to_string()];
+        for change in changes {
+            let mut details = Vec::new();
+            if let Some(size) = change.size {
+                details.push(format!("{size} bytes"));
+            }
+            if change.events > 1 {
+                details.push(format!("{} events", change.events));
+            }
+            let details = if details.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", details.join(", "))
+            };
+            lines.push(format!(
+                "- {} {}{}",
+                change.kind.label(),
+                change.path.display(),
+                details
+            ));
+        }
+        if dropped > 0 {
+            lines.push(format!("- {dropped} more changes not listed"));
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// Wait until a change is queued or `timeout` passes, returning whether
+    /// there are changes to take
+    pub fn wait_for_changes(&self, timeout: Duration) -> bool {
+        let mut queue = self.shared.queue.lock();
+        if queue.changes.is_empty() && queue.dropped == 0 {
+            self.shared.changed.wait_for(&mut queue, timeout);
+        }
+        !queue.changes.is_empty() || queue.dropped > 0
+    }
+
+    /// Send the queued changes to `deliver` as a [`PATHS_CHANGED`]
+    /// notification, `debounce` after the first of them
+    ///
+    /// The changes are sent from a thread that ends once the watcher is
+    /// dropped.
+    pub fn forward_notifications(
+        self: &Arc<Self>,
+        debounce: Duration,
+        deliver: impl Fn(McpNotification) + Send + 'static,
+    ) -> Result<()> {
+        let watcher = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("ForwardPathChanges".to_owned())
+            .spawn(move || {
+                while let Some(watcher) = watcher.upgrade() {
+                    if !watcher.wait_for_changes(FORWARD_POLL_INTERVAL) {
+                        continue;
+                    }
+                    // A build writing many files is sent as one notification
+                    std::thread::sleep(debounce);
+                    if let Some(message) = watcher.take_notification() {
+                        deliver(McpNotification::new(
+                            PATHS_CHANGED,
+                            Some(json!({ "message": message })),
+                        ));
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Tools a server offers to watch paths
+    pub fn tools() -> Vec<McpTool> {
+        vec![
+            McpTool {
+                name: WATCH_PATH_TOOL_NAME.to_string(),
+                description: Some(
+                    "Watch a file or directory in the workspace. Changes to it \
+                     are reported in later messages until it is unwatched."
+                        .to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path relative to the workspace root"
+                        },
+                        "recursive": {
+                            "type": "boolean",
+                            "description": "Also watch subdirectories; defaults to true"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                effect: ToolEffect::ReadOnly,
+            },
+            McpTool {
+                name: UNWATCH_PATH_TOOL_NAME.to_string(),
+                description: Some(
+                    "Stop watching a path started with `watch_path`.".to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path passed to `watch_path`"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                effect: ToolEffect::ReadOnly,
+            },
+        ]
+    }
+
+    /// Run a `watch_path` or `unwatch_path` tool call
+    pub fn call_tool(&self, tool_name: &str, arguments: &Value) -> Result<String> {
+        let path = arguments
+            .get("path")
+            .and_then(|path| path.as_str())
+            .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+        match tool_name {
+            WATCH_PATH_TOOL_NAME => {
+                let recursive = arguments
+                    .get("recursive")
+                    .and_then(|recursive| recursive.as_bool())
+                    .unwrap_or(true);
+                self.watch(Path::new(path), recursive)?;
+                Ok(format!(
+                    "Watching {path}; changes will be reported as they happen"
+                ))
+            }
+            UNWATCH_PATH_TOOL_NAME => {
+                self.unwatch(Path::new(path))?;
+                Ok(format!("Stopped watching {path}"))
+            }
+            other => Err(anyhow!("Unknown path watch tool '{}'", other)),
+        }
+    }
+
+    fn relative(&self, resolved: &Path) -> SharedPath {
+        SharedPath::new(
+            resolved
+                .strip_prefix(self.sandbox.root())
+                .unwrap_or(resolved),
+        )
+    }
+}
+
+/// Queue the paths of a watcher event the sandbox allows
+fn record_event(
+    shared: &SharedQueue,
+    sandbox: &WorkspaceSandbox,
+    event: &notify::Event,
+    limit: usize,
+) {
+    let Some(kind) = PathChangeKind::from_event(&event.kind) else {
+        return;
+    };
+    let mut queue = shared.queue.lock();
+    for path in &event.paths {
+        let Ok(resolved) = sandbox.check_access(path) else {
+            continue;
+        };
+        let relative = SharedPath::new(
+            resolved.strip_prefix(sandbox.root()).unwrap_or(&resolved),
+        );
+        queue.push(relative, kind, limit);
+    }
+    drop(queue);
+    shared.changed.notify_all();
+}
+
+/// Notifications received but not yet taken
+#[derive(Default)]
+struct PendingPathChanges {
+    /// `(server_id, message)` of each notification, the oldest first
+    messages: Mutex<VecDeque<(String, String)>>,
+}
+
+impl McpNotificationHandler for PendingPathChanges {
+    fn handle_notification(
+        &self,
+        server_id: &str,
+        notification: &McpServerNotification,
+    ) {
+        let McpServerNotification::Other(notification) = notification else {
+            return;
+        };
+        if notification.method != PATHS_CHANGED {
+            return;
+        }
+        let Some(message) = notification
+            .params
+            .as_ref()
+            .and_then(|params| params.get("message"))
+            .and_then(|message| message.as_str())
+        else {
+            return;
+        };
+        let mut messages = self.messages.lock();
+        if messages.len() >= MAX_PENDING_NOTIFICATIONS {
+            messages.pop_front();
+        }
+        messages.push_back((server_id.to_string(), message.to_string()));
+    }
+}
+
+/// Collects the changes of the paths watched on all servers for the agent
+/// loop
+///
+/// Create one with [`McpServerRegistry::path_changes`]. Only the latest
+/// notifications are kept while nobody takes them.
+pub struct PathChangeStream {
+    registry: McpServerRegistry,
+    pending: Arc<PendingPathChanges>,
+}
+
+impl PathChangeStream {
+    pub(crate) fn new(registry: McpServerRegistry) -> Self {
+        let pending = Arc::new(PendingPathChanges::default());
+        registry.add_notification_handler(pending.clone());
+        Self { registry, pending }
+    }
+
+    /// Take the changes received since the last call as a message for the
+    /// model, or `None` if nothing changed
+    pub fn take_message(&self) -> Option<AiMessage> {
+        let messages = std::mem::take(&mut *self.pending.messages.lock());
+        if messages.is_empty() {
+            return None;
+        }
+        let content = messages
+            .into_iter()
+            .map(|(server_id, message)| format!("[{server_id}] {message}"))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        Some(AiMessage {
+            role: MessageRole::User,
+            content,
+            timestamp: Some(current_time()),
+            images: Vec::new(),
+        })
+    }
+}
+
+impl Drop for PathChangeStream {
+    fn drop(&mut self) {
+        let handler: Arc<dyn McpNotificationHandler> = self.pending.clone();
+        self.registry.remove_notification_handler(&handler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+    use notify::event::{CreateKind, DataChange, ModifyKind};
+
+    #[test]
+    fn test_path_changes_are_deduplicated_and_bounded() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("dist")).unwrap();
+        std::fs::write(root.path().join("dist/bundle.js"), "x".repeat(120)).unwrap();
+        std::fs::write(root.path().join(".catalystignore"), "!dist/\n").unwrap();
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = WorkspaceSandbox::new(Arc::new(ignore));
+        let watcher = PathWatcher::new(
+            sandbox.clone(),
+            PathWatchLimits {
+                max_watches: 1,
+                max_pending_changes: 2,
+            },
+        );
+        watcher.watch(Path::new("dist"), true).unwrap();
+        assert_eq!(watcher.watched(), vec![PathBuf::from("dist")]);
+        assert!(watcher.watch(Path::new("."), true).is_err());
+        assert!(watcher.watch(Path::new("../outside"), true).is_err());
+
+        // Events are recorded straight away instead of waiting on the OS
+        let bundle = root.path().join("dist/bundle.js");
+        let created = notify::Event::new(EventKind::Create(CreateKind::File))
+            .add_path(bundle.clone());
+        let written = notify::Event::new(EventKind::Modify(ModifyKind::Data(
+            DataChange::Content,
+        )))
+        .add_path(bundle);
+        for event in [&created, &written, &written] {
+            record_event(&watcher.shared, &sandbox, event, 2);
+        }
+        let others =
+            notify::Event::new(EventKind::Remove(notify::event::RemoveKind::File))
+                .add_path(root.path().join("dist/a.js"))
+                .add_path(root.path().join("dist/b.js"));
+        record_event(&watcher.shared, &sandbox, &others, 2);
+        assert!(watcher.wait_for_changes(Duration::ZERO));
+
+        assert_eq!(
+            watcher.take_notification().unwrap(),
+            "Changes in watched paths:\n\
+             - removed dist/a.js\n\
+             - created dist/bundle.js (120 bytes, 3 events)\n\
+             - 1 more changes not listed"
+        );
+        assert!(watcher.take_notification().is_none());
+
+        assert_eq!(
+            watcher
+                .call_tool(UNWATCH_PATH_TOOL_NAME, &json!({ "path": "dist" }))
+                .unwrap(),
+            "Stopped watching dist"
+        );
+        assert!(watcher.watched().is_empty());
+    }
+}
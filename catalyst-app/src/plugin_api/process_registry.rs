@@ -0,0 +1,814 @@
+//! Process Registry
+//!
+//! This module keeps track of the server processes Catalyst spawns so none
+//! outlive the IDE. Each child runs in its own process group (a Job Object on
+//! Windows) and is recorded in a PID file under the runtime directory. On
+//! startup, records left behind by a session that crashed are used to clean
+//! up the orphaned children. Shutdown asks every child to exit (SIGTERM, or
+//! CTRL_BREAK on Windows) and only force-kills the ones still running after
+//! the grace period.
+//!
+//! Resource limits from [`McpResourceLimits`] are applied at spawn, and exits
+//! caused by them are reported separately from crashes, with the limit that
+//! was hit. On Unix, memory and CPU time are watched for the whole process
+//! group, which is killed once it goes over a limit. Memory is measured as
+//! resident set size rather than address space, since runtimes such as
+//! Node reserve far more address space than they use.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::plugin_api::{
+    CommandResolver, McpResourceLimits, McpServerInfo, McpServerStatus,
+    ResourceLimitKind,
+};
+
+/// Default time children get to exit before they are killed
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// PID file contents for a spawned server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    pub pid: u32,
+    pub server_id: String,
+    pub command: Vec<String>,
+    /// PID of the IDE process that spawned the server
+    pub owner_pid: u32,
+    pub started_at: SystemTime,
+}
+
+/// PID-file registry of spawned servers
+#[derive(Debug, Clone)]
+pub struct ProcessRegistry {
+    dir: PathBuf,
+}
+
+impl ProcessRegistry {
+    /// Open the registry in the default runtime directory
+    pub fn open_default() -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?
+            .join("run")
+            .join("servers");
+        Self::open(&dir)
+    }
+
+    /// Open a registry stored in a directory
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Record a spawned process
+    pub fn register(&self, record: &ProcessRecord) -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut file, record)?;
+        file.persist(self.record_path(record.pid))?;
+        Ok(())
+    }
+
+    /// Forget a process that has exited
+    pub fn unregister(&self, pid: u32) {
+        let _ = std::fs::remove_file(self.record_path(pid));
+    }
+
+    /// Get all recorded processes
+    pub fn records(&self) -> Vec<ProcessRecord> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry.path().extension().and_then(|e| e.to_str()) == Some("pid")
+            })
+            .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect()
+    }
+
+    /// Terminate children left behind by IDE sessions that are no longer
+    /// running, returning the records that were cleaned up
+    ///
+    /// Records owned by this process or another live IDE instance are left
+    /// alone.
+    pub fn cleanup_orphans(&self) -> Vec<ProcessRecord> {
+        let mut cleaned = Vec::new();
+        for record in self.records() {
+            if record.owner_pid == std::process::id()
+                || platform::is_alive(record.owner_pid)
+            {
+                continue;
+            }
+
+            if platform::is_alive(record.pid) && platform::matches_command(&record) {
+                tracing::warn!(
+                    "Terminating orphaned server '{}' (pid {})",
+                    record.server_id,
+                    record.pid
+                );
+                platform::terminate_group(record.pid);
+                let deadline = Instant::now() + DEFAULT_SHUTDOWN_GRACE;
+                while platform::is_alive(record.pid) && Instant::now() < deadline {
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                if platform::is_alive(record.pid) {
+                    platform::kill_group(record.pid);
+                }
+            }
+
+            self.unregister(record.pid);
+            cleaned.push(record);
+        }
+        cleaned
+    }
+
+    fn record_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.pid", pid))
+    }
+}
+
+/// Make a command start in its own process group, so signals reach the
+/// server and everything it spawns
+pub fn configure_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(
+            windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP,
+        );
+    }
+}
+
+/// A spawned server process that is registered until it exits
+pub struct ManagedChild {
+    child: Child,
+    server_id: String,
+    registry: ProcessRegistry,
+    #[cfg(windows)]
+    limits: McpResourceLimits,
+    shutdown_requested: bool,
+    #[cfg(unix)]
+    monitor: Option<platform::ResourceMonitor>,
+    #[cfg(windows)]
+    _job: platform::JobObject,
+}
+
+impl ManagedChild {
+    /// Spawn a command in its own process group with resource limits and
+    /// register it
+    pub fn spawn(
+        mut command: Command,
+        server_id: &str,
+        limits: &McpResourceLimits,
+        registry: &ProcessRegistry,
+    ) -> Result<Self> {
+        configure_process_group(&mut command);
+        #[cfg(unix)]
+        platform::apply_limits(&mut command, limits);
+        let args = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+
+        let child = command.spawn()?;
+        #[cfg(unix)]
+        let monitor = platform::ResourceMonitor::start(child.id(), limits);
+        #[cfg(windows)]
+        let job = platform::JobObject::assign(&child, limits)?;
+
+        registry.register(&ProcessRecord {
+            pid: child.id(),
+            server_id: server_id.to_string(),
+            command: args,
+            owner_pid: std::process::id(),
+            started_at: SystemTime::now(),
+        })?;
+
+        Ok(Self {
+            child,
+            server_id: server_id.to_string(),
+            registry: registry.clone(),
+            #[cfg(windows)]
+            limits: limits.clone(),
+            shutdown_requested: false,
+            #[cfg(unix)]
+            monitor,
+            #[cfg(windows)]
+            _job: job,
+        })
+    }
+
+    /// Resolve the command of an MCP server for this platform and spawn it
+    /// with its resource limits and its stdio piped
+    pub fn spawn_server(
+        info: &McpServerInfo,
+        resolver: &CommandResolver,
+        registry: &ProcessRegistry,
+    ) -> Result<Self> {
+        let mut command = resolver.server_command(info)?;
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        Self::spawn(command, &info.id, &info.resource_limits, registry)
+    }
+
+    /// Get the process id
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Get the underlying child, e.g. to take its stdio handles
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Check if the process has exited without blocking
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        let status = self.child.try_wait()?;
+        if status.is_some() {
+            self.registry.unregister(self.child.id());
+        }
+        Ok(status)
+    }
+
+    /// Classify how the process exited for health reporting
+    ///
+    /// Exits after a requested shutdown count as stopped. On Unix a limit is
+    /// reported only when the process group was killed for going over it;
+    /// on Windows memory limits are detected from the failed allocation, so
+    /// they are a best guess. Running out of file descriptors surfaces as a
+    /// crash.
+    pub fn exit_status(&self, status: &ExitStatus) -> McpServerStatus {
+        if self.shutdown_requested || status.success() {
+            return McpServerStatus::Stopped;
+        }
+        #[cfg(unix)]
+        let exceeded = self
+            .monitor
+            .as_ref()
+            .and_then(platform::ResourceMonitor::limit_hit);
+        #[cfg(windows)]
+        let exceeded = platform::limit_exceeded(status, &self.limits);
+        match exceeded {
+            Some(kind) => {
+                tracing::warn!(
+                    "Server '{}' exceeded its {:?} limit",
+                    self.server_id,
+                    kind
+                );
+                McpServerStatus::LimitExceeded(kind)
+            }
+            None => McpServerStatus::Crashed,
+        }
+    }
+
+    /// Ask the process group to exit, killing it after the grace period
+    pub fn shutdown(mut self, grace: Duration) -> Result<ExitStatus> {
+        self.request_exit();
+        self.wait_or_kill(Instant::now() + grace)
+    }
+
+    fn request_exit(&mut self) {
+        self.shutdown_requested = true;
+        if let Ok(None) = self.child.try_wait() {
+            platform::terminate_group(self.child.id());
+        }
+    }
+
+    fn wait_or_kill(&mut self, deadline: Instant) -> Result<ExitStatus> {
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                self.registry.unregister(self.child.id());
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    "Server '{}' did not exit in time, killing it",
+                    self.server_id
+                );
+                platform::kill_group(self.child.id());
+                let status = self.child.wait()?;
+                self.registry.unregister(self.child.id());
+                return Ok(status);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for ManagedChild {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            platform::kill_group(self.child.id());
+            let _ = self.child.wait();
+        }
+        self.registry.unregister(self.child.id());
+    }
+}
+
+/// Shut down several children together: all of them are asked to exit
+/// first, then they share one grace period before the rest are killed
+pub fn shutdown_all(
+    children: Vec<ManagedChild>,
+    grace: Duration,
+) -> Vec<(String, Result<ExitStatus>)> {
+    let mut children = children;
+    for child in children.iter_mut() {
+        child.request_exit();
+    }
+
+    let deadline = Instant::now() + grace;
+    children
+        .iter_mut()
+        .map(|child| (child.server_id.clone(), child.wait_or_kill(deadline)))
+        .collect()
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{McpResourceLimits, ProcessRecord, ResourceLimitKind};
+    use parking_lot::Mutex;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// Time between two measurements of a process group
+    const MONITOR_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn apply_limits(command: &mut Command, limits: &McpResourceLimits) {
+        let Some(files) = limits.max_open_files else {
+            return;
+        };
+        // Runs in the forked child before exec, so only async-signal-safe
+        // calls are allowed here
+        unsafe {
+            command.pre_exec(move || {
+                let limit = libc::rlimit {
+                    rlim_cur: files as libc::rlim_t,
+                    rlim_max: files as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Watches the memory and CPU time of a process group, killing the
+    /// group once it goes over a limit
+    pub struct ResourceMonitor {
+        stopped: Arc<AtomicBool>,
+        limit_hit: Arc<Mutex<Option<ResourceLimitKind>>>,
+    }
+
+    impl ResourceMonitor {
+        /// Start watching the group led by `pid`, if a memory or CPU limit
+        /// is set
+        pub fn start(pid: u32, limits: &McpResourceLimits) -> Option<Self> {
+            if limits.max_memory_bytes.is_none() && limits.max_cpu_seconds.is_none()
+            {
+                return None;
+            }
+            let monitor = Self {
+                stopped: Arc::new(AtomicBool::new(false)),
+                limit_hit: Arc::new(Mutex::new(None)),
+            };
+            let stopped = monitor.stopped.clone();
+            let limit_hit = monitor.limit_hit.clone();
+            let limits = limits.clone();
+            std::thread::Builder::new()
+                .name("ResourceMonitor".to_string())
+                .spawn(move || {
+                    while !stopped.load(Ordering::SeqCst) {
+                        std::thread::sleep(MONITOR_INTERVAL);
+                        let Some(usage) = group_usage(pid) else {
+                            break;
+                        };
+                        let exceeded = if limits
+                            .max_memory_bytes
+                            .is_some_and(|max| usage.resident_bytes > max)
+                        {
+                            Some(ResourceLimitKind::Memory)
+                        } else if limits
+                            .max_cpu_seconds
+                            .is_some_and(|max| usage.cpu >= Duration::from_secs(max))
+                        {
+                            Some(ResourceLimitKind::Cpu)
+                        } else {
+                            None
+                        };
+                        if exceeded.is_some() && !stopped.load(Ordering::SeqCst) {
+                            *limit_hit.lock() = exceeded;
+                            kill_group(pid);
+                            break;
+                        }
+                    }
+                })
+                .ok()?;
+            Some(monitor)
+        }
+
+        /// Get the limit the group was killed for going over
+        pub fn limit_hit(&self) -> Option<ResourceLimitKind> {
+            *self.limit_hit.lock()
+        }
+    }
+
+    impl Drop for ResourceMonitor {
+        fn drop(&mut self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Resources used by the live processes of a group
+    #[derive(Debug, Default, PartialEq)]
+    pub struct GroupUsage {
+        pub resident_bytes: u64,
+        pub cpu: Duration,
+    }
+
+    /// Measure the processes of the group `pgid`, or `None` if it has none
+    #[cfg(target_os = "linux")]
+    pub fn group_usage(pgid: u32) -> Option<GroupUsage> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+        let mut usage = None::<GroupUsage>;
+        for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+            let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            // Fields after the command name, which may contain spaces
+            let Some((_, fields)) = stat.rsplit_once(')') else {
+                continue;
+            };
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            let field = |index: usize| {
+                fields
+                    .get(index)
+                    .and_then(|field| field.parse::<u64>().ok())
+            };
+            if field(2) != Some(u64::from(pgid)) {
+                continue;
+            }
+            let usage = usage.get_or_insert_with(GroupUsage::default);
+            usage.resident_bytes += field(21).unwrap_or(0) * page_size;
+            let cpu_ticks = field(11).unwrap_or(0) + field(12).unwrap_or(0);
+            usage.cpu += Duration::from_secs_f64(cpu_ticks as f64 / ticks);
+        }
+        usage
+    }
+
+    /// Measure the processes of the group `pgid`, or `None` if it has none
+    #[cfg(not(target_os = "linux"))]
+    pub fn group_usage(pgid: u32) -> Option<GroupUsage> {
+        let output = Command::new("ps")
+            .args(["-A", "-o", "pgid=,rss=,time="])
+            .output()
+            .ok()?;
+        let mut usage = None::<GroupUsage>;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(group), Some(rss), Some(time)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if group.parse::<u32>().ok() != Some(pgid) {
+                continue;
+            }
+            let usage = usage.get_or_insert_with(GroupUsage::default);
+            usage.resident_bytes += rss.parse::<u64>().unwrap_or(0) * 1024;
+            usage.cpu += parse_cpu_time(time).unwrap_or_default();
+        }
+        usage
+    }
+
+    /// Parse a CPU time as `ps` prints it, `[[dd-]hh:]mm:ss[.cc]`
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    pub fn parse_cpu_time(time: &str) -> Option<Duration> {
+        let (days, time) = match time.split_once('-') {
+            Some((days, time)) => (days.parse::<u64>().ok()?, time),
+            None => (0, time),
+        };
+        let mut seconds = days as f64 * 86_400.0;
+        for (part, unit) in time.rsplit(':').zip([1.0, 60.0, 3600.0]) {
+            seconds += part.parse::<f64>().ok()? * unit;
+        }
+        Some(Duration::from_secs_f64(seconds))
+    }
+
+    pub fn is_alive(pid: u32) -> bool {
+        // Signal 0 only checks that the process exists
+        let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        result == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    /// Guard against the pid having been reused by an unrelated process
+    pub fn matches_command(record: &ProcessRecord) -> bool {
+        let Ok(cmdline) = std::fs::read(format!("/proc/{}/cmdline", record.pid))
+        else {
+            // No procfs on this platform, trust the record
+            return !cfg!(target_os = "linux");
+        };
+        let program = record
+            .command
+            .first()
+            .and_then(|program| std::path::Path::new(program).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        String::from_utf8_lossy(&cmdline).contains(&program)
+    }
+
+    pub fn terminate_group(pid: u32) {
+        signal_group(pid, libc::SIGTERM);
+    }
+
+    pub fn kill_group(pid: u32) {
+        signal_group(pid, libc::SIGKILL);
+    }
+
+    fn signal_group(pid: u32, signal: libc::c_int) {
+        // The child leads its own process group, so its pgid equals its pid
+        unsafe {
+            if libc::kill(-(pid as libc::pid_t), signal) != 0 {
+                libc::kill(pid as libc::pid_t, signal);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{McpResourceLimits, ProcessRecord, ResourceLimitKind};
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE, STILL_ACTIVE},
+        System::{
+            Console::{CTRL_BREAK_EVENT, GenerateConsoleCtrlEvent},
+            JobObjects::{
+                AssignProcessToJobObject, CreateJobObjectW,
+                JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+                JOB_OBJECT_LIMIT_PROCESS_TIME, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+                JobObjectExtendedLimitInformation, SetInformationJobObject,
+            },
+            Threading::{
+                GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+                PROCESS_TERMINATE, TerminateProcess,
+            },
+        },
+    };
+
+    /// Job Object that kills its processes when the IDE exits, even if it
+    /// crashes
+    pub struct JobObject(HANDLE);
+
+    impl JobObject {
+        pub fn assign(
+            child: &std::process::Child,
+            limits: &McpResourceLimits,
+        ) -> anyhow::Result<Self> {
+            if limits.max_open_files.is_some() {
+                tracing::warn!("Open file limits are not supported on Windows");
+            }
+
+            unsafe {
+                let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if job as usize == 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                let job = JobObject(job);
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION =
+                    std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags =
+                    JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if let Some(bytes) = limits.max_memory_bytes {
+                    info.BasicLimitInformation.LimitFlags |=
+                        JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                    info.ProcessMemoryLimit = bytes as usize;
+                }
+                if let Some(seconds) = limits.max_cpu_seconds {
+                    info.BasicLimitInformation.LimitFlags |=
+                        JOB_OBJECT_LIMIT_PROCESS_TIME;
+                    // In 100 nanosecond ticks
+                    info.BasicLimitInformation.PerProcessUserTimeLimit =
+                        (seconds * 10_000_000) as i64;
+                }
+                if SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>()
+                        as u32,
+                ) == 0
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                if AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE)
+                    == 0
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(job)
+            }
+        }
+    }
+
+    impl Drop for JobObject {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    pub fn is_alive(pid: u32) -> bool {
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if handle as usize == 0 {
+                return false;
+            }
+            let mut code = 0u32;
+            let ok = GetExitCodeProcess(handle, &mut code);
+            CloseHandle(handle);
+            ok != 0 && code == STILL_ACTIVE as u32
+        }
+    }
+
+    pub fn matches_command(_record: &ProcessRecord) -> bool {
+        true
+    }
+
+    /// ERROR_NOT_ENOUGH_QUOTA, used when the process time limit is hit
+    const EXIT_PROCESS_TIME_LIMIT: u32 = 1816;
+    const STATUS_NO_MEMORY: u32 = 0xC000_0017;
+    const STATUS_COMMITMENT_LIMIT: u32 = 0xC000_012D;
+
+    pub fn limit_exceeded(
+        status: &std::process::ExitStatus,
+        limits: &McpResourceLimits,
+    ) -> Option<ResourceLimitKind> {
+        match status.code()? as u32 {
+            EXIT_PROCESS_TIME_LIMIT if limits.max_cpu_seconds.is_some() => {
+                Some(ResourceLimitKind::Cpu)
+            }
+            STATUS_NO_MEMORY | STATUS_COMMITMENT_LIMIT
+                if limits.max_memory_bytes.is_some() =>
+            {
+                Some(ResourceLimitKind::Memory)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn terminate_group(pid: u32) {
+        // Delivered to the process group created with CREATE_NEW_PROCESS_GROUP
+        unsafe {
+            GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid);
+        }
+    }
+
+    pub fn kill_group(pid: u32) {
+        unsafe {
+            let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+            if handle as usize != 0 {
+                TerminateProcess(handle, 1);
+                CloseHandle(handle);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shutdown_and_orphan_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProcessRegistry::open(dir.path()).unwrap();
+
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let child = ManagedChild::spawn(
+            command,
+            "sleeper",
+            &McpResourceLimits::default(),
+            &registry,
+        )
+        .unwrap();
+        assert_eq!(registry.records().len(), 1);
+        assert!(registry.cleanup_orphans().is_empty());
+
+        let status = child.shutdown(Duration::from_secs(2)).unwrap();
+        assert!(!status.success());
+        assert!(registry.records().is_empty());
+
+        registry
+            .register(&ProcessRecord {
+                pid: u32::MAX / 2,
+                server_id: "gone".to_string(),
+                command: vec!["gone".to_string()],
+                owner_pid: u32::MAX / 2,
+                started_at: SystemTime::now(),
+            })
+            .unwrap();
+        assert_eq!(registry.cleanup_orphans().len(), 1);
+        assert!(registry.records().is_empty());
+    }
+
+    #[test]
+    fn test_cpu_limit_reported_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProcessRegistry::open(dir.path()).unwrap();
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "while :; do :; done"]);
+        let limits = McpResourceLimits {
+            max_cpu_seconds: Some(1),
+            ..Default::default()
+        };
+        let mut child =
+            ManagedChild::spawn(command, "spinner", &limits, &registry).unwrap();
+
+        let status = child.child_mut().wait().unwrap();
+        assert_eq!(
+            child.exit_status(&status),
+            McpServerStatus::LimitExceeded(ResourceLimitKind::Cpu)
+        );
+    }
+
+    #[test]
+    fn test_memory_limit_uses_resident_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProcessRegistry::open(dir.path()).unwrap();
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "x=a; while :; do x=\"$x$x\"; sleep 0.05; done"]);
+        let limits = McpResourceLimits {
+            max_memory_bytes: Some(32 * 1024 * 1024),
+            max_cpu_seconds: Some(60),
+            ..Default::default()
+        };
+        let mut child =
+            ManagedChild::spawn(command, "grower", &limits, &registry).unwrap();
+
+        let status = child.child_mut().wait().unwrap();
+        assert_eq!(
+            child.exit_status(&status),
+            McpServerStatus::LimitExceeded(ResourceLimitKind::Memory)
+        );
+    }
+
+    #[test]
+    fn test_external_kill_is_a_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ProcessRegistry::open(dir.path()).unwrap();
+
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let limits = McpResourceLimits {
+            max_memory_bytes: Some(1024 * 1024 * 1024),
+            max_cpu_seconds: Some(60),
+            ..Default::default()
+        };
+        let mut child =
+            ManagedChild::spawn(command, "sleeper", &limits, &registry).unwrap();
+
+        platform::kill_group(child.id());
+        let status = child.child_mut().wait().unwrap();
+        assert_eq!(child.exit_status(&status), McpServerStatus::Crashed);
+    }
+
+    #[test]
+    fn test_parse_cpu_time() {
+        assert_eq!(
+            platform::parse_cpu_time("0:01.50"),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            platform::parse_cpu_time("1-02:03:04"),
+            Some(Duration::from_secs(86_400 + 2 * 3600 + 3 * 60 + 4))
+        );
+        assert_eq!(platform::parse_cpu_time("soon"), None);
+    }
+}
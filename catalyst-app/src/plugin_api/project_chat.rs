@@ -0,0 +1,1368 @@
+//! Project Chat
+//!
+//! This module is a chat about the codebase as a whole, meant for
+//! onboarding questions such as "where is auth handled?". Unlike the editor
+//! assistant it does not depend on the open file: every question is
+//! answered from the workspace summary, the workspace memories and the
+//! chunks the retrieval pipeline finds for it, all numbered as sources so
+//! the answer cites where its claims come from. The conversation is kept
+//! per workspace and survives restarts. Files dropped onto the panel and
+//! pasted images are attached to the next question. A question can
+//! override the model, temperature or token limit, which is kept with it so
+//! regenerating the answer uses the same parameters. Regenerated answers
+//! and alternative drafts are kept next to each other as answers to the same
+//! question, with what each one cost, and the user picks the one the
+//! conversation continues from. When a tool policy is given, the
+//! assistant may call the tools it allows in Agent mode; the results are
+//! shown under the answer and given to the assistant with the next
+//! question.
+
+use anyhow::Result;
+use base64::{Engine as _, engine::general_purpose};
+use catalyst_core::directory::Directory;
+use floem::{
+    event::{Event, EventListener},
+    reactive::{SignalGet, SignalUpdate, create_rw_signal},
+    views::{Decorators, empty, stack_from_iter, v_stack},
+};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    AiAssistantPlugin, AiMessage, AiMessageRequest, AiMessageResponse,
+    AttachmentLimits, ChatAttachment, Citation, ContextSource, ConversationMode,
+    ConversationPolicy, DegradationTracker, EditorContext, HybridRetriever,
+    JournalOptions, JournalState, JournaledStore, McpServerRegistry, McpToolResult,
+    MessageOverrides, MessageRole, PanelCommand, PanelCommandResult, SharedPath,
+    SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition, SourceLocation,
+    SourceTracker, SpeechProvider, SpeechReadout, SpeechSettings, StyleProfile,
+    SubProjectKind, ToolCall, UsageStore, UsageTotals, WorkspaceLayout,
+    WorkspaceMemoryStore, WorkspaceRoots, WorkspaceSandbox, attachment_images,
+    cited_sources, content_text, current_time, render_citation_markers,
+    speech_controls_view, text_panel_view, tool_call_view,
+};
+
+/// Retrieved chunks attached to a question
+const RETRIEVED_CHUNKS: usize = 10;
+/// Memories attached to a question
+const MEMORY_LIMIT: usize = 5;
+/// Earlier messages sent along with a question
+const HISTORY_MESSAGES: usize = 6;
+/// Messages kept in the stored conversation
+const MAX_MESSAGES: usize = 500;
+/// Alternative answers generated at once
+const MAX_ALTERNATIVES: usize = 5;
+/// Questions this short are taken as follow-ups and retrieved together
+/// with the previous question
+const FOLLOW_UP_WORDS: usize = 5;
+
+const INSTRUCTIONS: &str = "You answer questions about this codebase for \
+    someone who is new to it. Explain where things live: name the files, \
+    modules and entry points involved and how they connect, and suggest what \
+    to read next. Answer only from the workspace summary and the sources \
+    given; if they don't cover the question, say so and name where you \
+    would look. Cite every claim about the code with its source marker.";
+
+/// Who wrote a project chat message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectChatRole {
+    User,
+    Assistant,
+}
+
+/// A message of the project chat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectChatMessage {
+    pub id: u64,
+    pub role: ProjectChatRole,
+    /// For answers, with citation markers replaced by citation numbers
+    pub content: String,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    /// For questions, the labels of the files and images attached to them
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    /// For questions, the request parameters they override
+    #[serde(default, skip_serializing_if = "MessageOverrides::is_empty")]
+    pub overrides: MessageOverrides,
+    /// For answers, the id of their question; answers to the same question
+    /// are alternatives of each other
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u64>,
+    pub timestamp: SystemTime,
+}
+
+/// A tool the assistant called for an answer, with its result
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatToolResult {
+    pub call: ToolCall,
+    pub result: McpToolResult,
+}
+
+/// Tools offered to the assistant while the policy is in Agent mode
+struct ChatTools {
+    policy: ConversationPolicy,
+    registry: McpServerRegistry,
+    tracker: DegradationTracker,
+}
+
+/// An answer to a question, among the other answers to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectChatAlternative {
+    pub message: ProjectChatMessage,
+    /// Whether the conversation continues from this answer
+    pub selected: bool,
+    pub usage: UsageTotals,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProjectChatFile {
+    next_id: u64,
+    messages: Vec<ProjectChatMessage>,
+    /// Answer picked for a question, by question id; otherwise the latest
+    /// answer is shown
+    #[serde(default)]
+    selected: HashMap<u64, u64>,
+}
+
+impl ProjectChatFile {
+    fn selected_answer(&self, question: u64) -> Option<u64> {
+        self.selected
+            .get(&question)
+            .copied()
+            .filter(|answer| {
+                self.messages.iter().any(|message| message.id == *answer)
+            })
+            .or_else(|| {
+                self.messages
+                    .iter()
+                    .rev()
+                    .find(|message| message.parent == Some(question))
+                    .map(|message| message.id)
+            })
+    }
+
+    /// Get the conversation with the selected answer of every question
+    fn thread(&self) -> Vec<ProjectChatMessage> {
+        self.messages
+            .iter()
+            .filter(|message| match message.parent {
+                Some(question) => self.selected_answer(question) == Some(message.id),
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ProjectChatOp {
+    Push(ProjectChatMessage),
+    Select { question: u64, answer: u64 },
+    Clear,
+}
+
+impl JournalState for ProjectChatFile {
+    type Op = ProjectChatOp;
+
+    fn apply(&mut self, op: &ProjectChatOp) {
+        match op {
+            ProjectChatOp::Push(message) => {
+                self.next_id = self.next_id.max(message.id);
+                // A new answer is shown until another one is picked
+                if let Some(question) = message.parent {
+                    self.selected.insert(question, message.id);
+                }
+                self.messages.push(message.clone());
+                if self.messages.len() > MAX_MESSAGES {
+                    let excess = self.messages.len() - MAX_MESSAGES;
+                    self.messages.drain(..excess);
+                    let oldest = self.messages.first().map(|message| message.id);
+                    self.selected
+                        .retain(|question, _| Some(*question) >= oldest);
+                }
+            }
+            ProjectChatOp::Select { question, answer } => {
+                self.selected.insert(*question, *answer);
+            }
+            ProjectChatOp::Clear => {
+                self.messages.clear();
+                self.selected.clear();
+            }
+        }
+    }
+}
+
+/// Describe the layout of a workspace for the assistant
+pub fn workspace_summary(layout: &WorkspaceLayout) -> String {
+    let name = layout
+        .root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut summary = if layout.is_monorepo() {
+        format!(
+            "Workspace '{}' is a {:?} monorepo with {} projects:\n",
+            name,
+            layout.kind,
+            layout.sub_projects.len()
+        )
+    } else {
+        format!("Workspace '{}':\n", name)
+    };
+    for project in &layout.sub_projects {
+        let path = project
+            .root
+            .strip_prefix(&layout.root)
+            .unwrap_or(&project.root);
+        let kind = match project.kind {
+            SubProjectKind::Cargo => "Rust crate",
+            SubProjectKind::Node => "Node package",
+            SubProjectKind::Bazel => "Bazel package",
+            SubProjectKind::Unknown => "project",
+        };
+        summary.push_str(&format!(
+            "- {} ({}) at {}",
+            project.name,
+            kind,
+            if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            }
+            .display()
+        ));
+        for task in [&project.build_task, &project.test_task]
+            .into_iter()
+            .flatten()
+        {
+            summary.push_str(&format!(
+                "; {}: `{}`",
+                task.label,
+                task.command.join(" ")
+            ));
+        }
+        summary.push('\n');
+    }
+    summary
+}
+
+/// Per-workspace chat answering questions about the codebase
+pub struct ProjectChat {
+    store: JournaledStore<ProjectChatFile>,
+    root: PathBuf,
+    /// Folders of the workspace, the primary root first
+    roots: RwLock<WorkspaceRoots>,
+    summary: RwLock<String>,
+    style: RwLock<Option<StyleProfile>>,
+    memory: Arc<WorkspaceMemoryStore>,
+    retriever: Arc<HybridRetriever>,
+    /// Attachments of the next question
+    attachments: Mutex<Vec<ChatAttachment>>,
+    limits: AttachmentLimits,
+    usage: Option<Arc<UsageStore>>,
+    tools: Option<ChatTools>,
+    /// Tools called for each answer, by answer id, kept while the chat is
+    /// open
+    tool_results: Mutex<HashMap<u64, Vec<ChatToolResult>>>,
+}
+
+impl ProjectChat {
+    /// Open the project chat of a workspace, kept in the local data directory
+    pub fn open_for_workspace(
+        layout: &WorkspaceLayout,
+        memory: Arc<WorkspaceMemoryStore>,
+        retriever: Arc<HybridRetriever>,
+    ) -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?
+            .join("project_chat");
+        Self::open(
+            &dir.join(format!("{}.json", workspace_key(&layout.root))),
+            layout,
+            memory,
+            retriever,
+        )
+    }
+
+    /// Open a project chat backed by the given file
+    pub fn open(
+        path: &Path,
+        layout: &WorkspaceLayout,
+        memory: Arc<WorkspaceMemoryStore>,
+        retriever: Arc<HybridRetriever>,
+    ) -> Result<Self> {
+        Ok(Self {
+            store: JournaledStore::open(path, JournalOptions::default())?,
+            root: layout.root.clone(),
+            roots: RwLock::new(WorkspaceRoots::new([layout.root.clone()])),
+            summary: RwLock::new(workspace_summary(layout)),
+            style: RwLock::new(None),
+            memory,
+            retriever,
+            attachments: Mutex::new(Vec::new()),
+            limits: AttachmentLimits::default(),
+            usage: None,
+            tools: None,
+            tool_results: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Record the usage of every answer in a usage store
+    pub fn with_usage_store(mut self, usage: Arc<UsageStore>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Offer the assistant the tools of the servers `policy` allows, which
+    /// it may call while the policy is in Agent mode
+    pub fn with_tools(
+        mut self,
+        policy: ConversationPolicy,
+        registry: McpServerRegistry,
+    ) -> Self {
+        self.tools = Some(ChatTools {
+            policy,
+            registry,
+            tracker: DegradationTracker::new(),
+        });
+        self
+    }
+
+    /// Get the tool policy, `None` when no tools are offered
+    pub fn tool_policy(&self) -> Option<&ConversationPolicy> {
+        self.tools.as_ref().map(|tools| &tools.policy)
+    }
+
+    /// Get the tools called for an answer, with their results
+    pub fn tool_results(&self, answer: u64) -> Vec<ChatToolResult> {
+        self.tool_results
+            .lock()
+            .get(&answer)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Update the workspace summary after the workspace is analyzed again
+    pub fn set_layout(&self, layout: &WorkspaceLayout) {
+        *self.summary.write() = workspace_summary(layout);
+    }
+
+    /// Set the folders of a multi-root workspace the questions are about
+    pub fn set_workspace_roots(&self, roots: WorkspaceRoots) {
+        *self.roots.write() = roots;
+    }
+
+    /// Set the code style answers follow, once it is extracted
+    pub fn set_style_profile(&self, profile: StyleProfile) {
+        *self.style.write() = Some(profile);
+    }
+
+    /// Get the conversation, with the selected answer of every question
+    pub fn messages(&self) -> Vec<ProjectChatMessage> {
+        self.store.read().thread()
+    }
+
+    /// Get the last answer of the conversation
+    pub fn latest_answer(&self) -> Option<String> {
+        self.messages()
+            .into_iter()
+            .rev()
+            .find(|message| message.role == ProjectChatRole::Assistant)
+            .map(|message| message.content)
+    }
+
+    /// Get the answers to a question, oldest first
+    pub fn alternatives(&self, question: u64) -> Vec<ProjectChatAlternative> {
+        let file = self.store.read();
+        let selected = file.selected_answer(question);
+        file.messages
+            .iter()
+            .filter(|message| message.parent == Some(question))
+            .map(|message| ProjectChatAlternative {
+                message: message.clone(),
+                selected: selected == Some(message.id),
+                usage: self
+                    .usage
+                    .as_ref()
+                    .map(|usage| usage.usage(&usage_key(message.id)))
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Continue the conversation from another answer to its question
+    pub fn select_alternative(&self, answer: u64) -> Result<()> {
+        let question = self
+            .store
+            .read()
+            .messages
+            .iter()
+            .find(|message| message.id == answer)
+            .and_then(|message| message.parent)
+            .ok_or_else(|| anyhow::anyhow!("No answer {}", answer))?;
+        self.store.apply(ProjectChatOp::Select { question, answer })
+    }
+
+    /// Attach a dropped file to the next question
+    pub fn attach_file(&self, path: &Path) -> Result<ChatAttachment> {
+        let attachment = ChatAttachment::from_file(path, &self.limits)?;
+        self.attachments.lock().push(attachment.clone());
+        Ok(attachment)
+    }
+
+    /// Attach a pasted image to the next question
+    pub fn attach_image(&self, bytes: &[u8]) -> Result<ChatAttachment> {
+        let attachment = ChatAttachment::from_clipboard_image(bytes, &self.limits)?;
+        self.attachments.lock().push(attachment.clone());
+        Ok(attachment)
+    }
+
+    /// Attach terminal output the user reviewed, built with
+    /// [`ChatAttachment::from_terminal`]
+    pub fn attach_terminal_output(&self, attachment: ChatAttachment) -> Result<()> {
+        if !matches!(attachment, ChatAttachment::Terminal { .. }) {
+            return Err(anyhow::anyhow!(
+                "{} is not terminal output",
+                attachment.label()
+            ));
+        }
+        self.attachments.lock().push(attachment);
+        Ok(())
+    }
+
+    pub fn attachments(&self) -> Vec<ChatAttachment> {
+        self.attachments.lock().clone()
+    }
+
+    pub fn remove_attachment(&self, index: usize) -> Result<ChatAttachment> {
+        let mut attachments = self.attachments.lock();
+        if index >= attachments.len() {
+            return Err(anyhow::anyhow!("No attachment {}", index));
+        }
+        Ok(attachments.remove(index))
+    }
+
+    /// Forget the conversation
+    pub fn clear(&self) -> Result<()> {
+        self.store.apply(ProjectChatOp::Clear)
+    }
+
+    /// Build the request answering a question, with the sources it may cite
+    pub fn prepare(
+        &self,
+        question: &str,
+        overrides: &MessageOverrides,
+    ) -> (AiMessageRequest, Vec<ContextSource>) {
+        self.prepare_with_history(
+            question,
+            overrides,
+            &self.attachments(),
+            &self.messages(),
+        )
+    }
+
+    fn prepare_with_history(
+        &self,
+        question: &str,
+        overrides: &MessageOverrides,
+        attachments: &[ChatAttachment],
+        history: &[ProjectChatMessage],
+    ) -> (AiMessageRequest, Vec<ContextSource>) {
+        let query = match history
+            .iter()
+            .rev()
+            .find(|message| message.role == ProjectChatRole::User)
+        {
+            Some(previous)
+                if question.split_whitespace().count() <= FOLLOW_UP_WORDS =>
+            {
+                format!("{} {}", previous.content, question)
+            }
+            _ => question.to_string(),
+        };
+
+        let mut sources = SourceTracker::new();
+        for attachment in attachments {
+            let mut attachment = attachment.clone();
+            if let ChatAttachment::Text { path, .. } = &mut attachment {
+                if let Ok(relative) =
+                    path.strip_prefix(&self.root).map(Path::to_path_buf)
+                {
+                    *path = relative;
+                }
+            }
+            attachment.add_source(&mut sources);
+        }
+        for entry in self.memory.relevant(&query, MEMORY_LIMIT) {
+            sources.add(SourceLocation::Memory { id: entry.id }, entry.fact);
+        }
+        for ranked in self.retriever.retrieve(&query, RETRIEVED_CHUNKS) {
+            let mut chunk = ranked.chunk;
+            // Cite paths relative to the workspace, as the user knows them
+            if let Ok(relative) =
+                chunk.path.strip_prefix(&self.root).map(SharedPath::from)
+            {
+                chunk.path = relative;
+            }
+            sources.add_chunk(&chunk);
+        }
+        let sources = sources.into_sources();
+
+        let mut messages = vec![AiMessage {
+            role: MessageRole::System,
+            content: format!("{}\n\n{}", INSTRUCTIONS, self.summary.read()),
+            timestamp: None,
+            images: Vec::new(),
+        }];
+        let start = history.len().saturating_sub(HISTORY_MESSAGES);
+        messages.extend(history[start..].iter().map(|message| AiMessage {
+            role: match message.role {
+                ProjectChatRole::User => MessageRole::User,
+                ProjectChatRole::Assistant => MessageRole::Assistant,
+            },
+            content: self.message_text(message),
+            timestamp: Some(message.timestamp),
+            images: Vec::new(),
+        }));
+        messages.push(AiMessage {
+            role: MessageRole::User,
+            content: question.to_string(),
+            timestamp: Some(current_time()),
+            // Checked against the assistant's vision support by `ask`
+            images: attachment_images(attachments, true).unwrap_or_default(),
+        });
+
+        let mut request = AiMessageRequest {
+            messages,
+            context: Some(EditorContext {
+                current_file: None,
+                selection: None,
+                project: None,
+                workspace_roots: self.roots.read().project_contexts(),
+                open_files: Vec::new(),
+                memories: Vec::new(),
+                sources: sources.clone(),
+            }),
+            tools: None,
+            model: None,
+            max_tokens: None,
+            temperature: None,
+        };
+        if let Some(tools) = &self.tools {
+            tools.policy.prepare_request(
+                &mut request,
+                &tools.registry,
+                &tools.tracker,
+                Vec::new(),
+            );
+        }
+        if let Some(style) = &*self.style.read() {
+            style.apply(&mut request);
+        }
+        overrides.apply(&mut request);
+        (request, sources)
+    }
+
+    /// Get the text of a message for the assistant, followed by the results
+    /// of the tools it called for it
+    fn message_text(&self, message: &ProjectChatMessage) -> String {
+        let mut text = message.content.clone();
+        for tool in self.tool_results(message.id) {
+            let result = tool
+                .result
+                .content
+                .iter()
+                .map(content_text)
+                .collect::<Vec<_>>()
+                .join("\n");
+            text.push_str(&format!("\n\nResult of {}:\n{}", tool.call.name, result));
+        }
+        text
+    }
+
+    /// Run the tool calls of a response, keeping the results with its answer
+    ///
+    /// Calls the policy doesn't allow get an error result.
+    fn run_tool_calls(
+        &self,
+        answer: &ProjectChatMessage,
+        response: &AiMessageResponse,
+    ) {
+        let (Some(tools), Some(calls)) = (&self.tools, &response.tool_calls) else {
+            return;
+        };
+        let results: Vec<ChatToolResult> = calls
+            .iter()
+            .map(|call| ChatToolResult {
+                result: tools.policy.call_server_tool(
+                    call,
+                    &tools.registry,
+                    &tools.tracker,
+                ),
+                call: call.clone(),
+            })
+            .collect();
+        if !results.is_empty() {
+            self.tool_results.lock().insert(answer.id, results);
+        }
+    }
+
+    /// Record a question and its answer, resolving the answer's citations
+    ///
+    /// The attachments sent with the question are cleared.
+    pub fn record(
+        &self,
+        question: &str,
+        overrides: &MessageOverrides,
+        answer: &str,
+        sources: &[ContextSource],
+    ) -> Result<ProjectChatMessage> {
+        let attachments = std::mem::take(&mut *self.attachments.lock())
+            .iter()
+            .map(|attachment| attachment.label())
+            .collect();
+        let question = self.store.transact(|inner| {
+            let question = ProjectChatMessage {
+                id: inner.next_id + 1,
+                role: ProjectChatRole::User,
+                content: question.to_string(),
+                citations: Vec::new(),
+                attachments,
+                overrides: overrides.clone(),
+                parent: None,
+                timestamp: current_time(),
+            };
+            Ok((Some(ProjectChatOp::Push(question.clone())), question))
+        })?;
+        self.record_answer(question.id, answer, sources)
+    }
+
+    fn record_answer(
+        &self,
+        question: u64,
+        answer: &str,
+        sources: &[ContextSource],
+    ) -> Result<ProjectChatMessage> {
+        let citations = cited_sources(answer, sources);
+        let content = render_citation_markers(answer, &citations);
+        self.store.transact(|inner| {
+            let answer = ProjectChatMessage {
+                id: inner.next_id + 1,
+                role: ProjectChatRole::Assistant,
+                content,
+                citations,
+                attachments: Vec::new(),
+                overrides: MessageOverrides::default(),
+                parent: Some(question),
+                timestamp: current_time(),
+            };
+            Ok((Some(ProjectChatOp::Push(answer.clone())), answer))
+        })
+    }
+
+    fn record_usage(
+        &self,
+        answer: &ProjectChatMessage,
+        response: &AiMessageResponse,
+    ) {
+        if let Some(usage) = &self.usage {
+            if let Err(err) = usage.record(&usage_key(answer.id), response) {
+                tracing::warn!("Failed to record project chat usage: {err:#}");
+            }
+        }
+    }
+
+    /// Ask the assistant a question and record the answer
+    ///
+    /// Overrides written in front of the question, such as
+    /// `@model:name`, take precedence over `overrides`.
+    pub fn ask(
+        &self,
+        assistant: &dyn AiAssistantPlugin,
+        question: &str,
+        overrides: &MessageOverrides,
+    ) -> Result<ProjectChatMessage> {
+        let (prefix, question) = MessageOverrides::parse_prefix(question)?;
+        let overrides = overrides.merge(&prefix);
+        overrides.validate()?;
+        let question = question.trim();
+        if question.is_empty() {
+            return Err(anyhow::anyhow!("The question is empty"));
+        }
+        attachment_images(
+            &self.attachments.lock(),
+            assistant.plugin_info().supports_vision,
+        )?;
+        let (request, sources) = self.prepare(question, &overrides);
+        let response = assistant.send_message(request)?;
+        let answer =
+            self.record(question, &overrides, &response.content, &sources)?;
+        self.record_usage(&answer, &response);
+        self.run_tool_calls(&answer, &response);
+        Ok(answer)
+    }
+
+    /// Answer the last question again, with the parameters it was asked with
+    ///
+    /// The new answer is shown, and the earlier ones are kept as
+    /// alternatives.
+    pub fn regenerate(
+        &self,
+        assistant: &dyn AiAssistantPlugin,
+    ) -> Result<ProjectChatMessage> {
+        self.generate_alternatives(assistant, 1)?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No answer was generated"))
+    }
+
+    /// Generate other answers to the last question, showing the last one
+    ///
+    /// The files and images attached to the question aren't kept, so the
+    /// new answers are given without them.
+    pub fn generate_alternatives(
+        &self,
+        assistant: &dyn AiAssistantPlugin,
+        count: usize,
+    ) -> Result<Vec<ProjectChatMessage>> {
+        if !(1..=MAX_ALTERNATIVES).contains(&count) {
+            return Err(anyhow::anyhow!(
+                "Between 1 and {} alternatives can be generated at once",
+                MAX_ALTERNATIVES
+            ));
+        }
+        let thread = self.messages();
+        let question = thread
+            .iter()
+            .rev()
+            .find(|message| message.role == ProjectChatRole::User)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!("There is no question to answer again")
+            })?;
+        let history: Vec<ProjectChatMessage> = thread
+            .into_iter()
+            .filter(|message| message.id < question.id)
+            .collect();
+        let (request, sources) = self.prepare_with_history(
+            &question.content,
+            &question.overrides,
+            &[],
+            &history,
+        );
+        let mut answers = Vec::new();
+        for _ in 0..count {
+            let response = assistant.send_message(request.clone())?;
+            let answer =
+                self.record_answer(question.id, &response.content, &sources)?;
+            self.record_usage(&answer, &response);
+            self.run_tool_calls(&answer, &response);
+            answers.push(answer);
+        }
+        Ok(answers)
+    }
+}
+
+/// Key of an answer in the usage store
+fn usage_key(answer: u64) -> String {
+    format!("project_chat/{}", answer)
+}
+
+/// Stable file name for a workspace root
+fn workspace_key(workspace_root: &Path) -> String {
+    let root = workspace_root
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_root.to_path_buf());
+    format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()))
+}
+
+/// Sidebar panel with the project chat
+pub struct ProjectChatPanel {
+    chat: Arc<ProjectChat>,
+    assistant: Option<Arc<dyn AiAssistantPlugin>>,
+    /// Reads the latest answer aloud when speech is enabled
+    readout: Option<Arc<SpeechReadout>>,
+    /// Workspace the diffs returned by tools are applied to
+    sandbox: Option<WorkspaceSandbox>,
+}
+
+impl ProjectChatPanel {
+    pub const ID: &'static str = "catalyst.project_chat";
+
+    pub fn new(
+        chat: Arc<ProjectChat>,
+        assistant: Option<Arc<dyn AiAssistantPlugin>>,
+    ) -> Self {
+        Self {
+            chat,
+            assistant,
+            readout: None,
+            sandbox: None,
+        }
+    }
+
+    /// Let the diffs returned by tools be applied to the workspace
+    pub fn with_sandbox(mut self, sandbox: WorkspaceSandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Offer to read the answers aloud with `provider`
+    pub fn with_speech(
+        mut self,
+        provider: Arc<dyn SpeechProvider>,
+        settings: SpeechSettings,
+    ) -> Self {
+        self.readout = Some(Arc::new(SpeechReadout::new(provider, settings, "")));
+        self
+    }
+
+    fn assistant(&self) -> Result<&dyn AiAssistantPlugin> {
+        self.assistant
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No AI assistant is configured"))
+    }
+
+    fn tool_policy(&self) -> Result<&ConversationPolicy> {
+        self.chat
+            .tool_policy()
+            .ok_or_else(|| anyhow::anyhow!("No tools are offered in this chat"))
+    }
+
+    fn readout(&self) -> Result<&Arc<SpeechReadout>> {
+        self.readout
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Reading answers aloud is not enabled"))
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        match command.command_id.as_str() {
+            "history" => Ok(serde_json::to_value(self.chat.messages())?),
+            "ask" => {
+                let question = command
+                    .parameters
+                    .get("question")
+                    .and_then(|question| question.as_str())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Missing 'question' parameter")
+                    })?;
+                // Set with the gear control
+                let overrides: MessageOverrides = match command
+                    .parameters
+                    .get("overrides")
+                {
+                    Some(overrides) => serde_json::from_value(overrides.clone())?,
+                    None => MessageOverrides::default(),
+                };
+                Ok(serde_json::to_value(self.chat.ask(
+                    self.assistant()?,
+                    question,
+                    &overrides,
+                )?)?)
+            }
+            "regenerate" => Ok(serde_json::to_value(
+                self.chat.regenerate(self.assistant()?)?,
+            )?),
+            "generate_alternatives" => {
+                let count = command
+                    .parameters
+                    .get("count")
+                    .and_then(|count| count.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'count' parameter"))?;
+                Ok(serde_json::to_value(self.chat.generate_alternatives(
+                    self.assistant()?,
+                    count as usize,
+                )?)?)
+            }
+            "alternatives" => {
+                let question = command
+                    .parameters
+                    .get("question")
+                    .and_then(|question| question.as_u64())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Missing 'question' parameter")
+                    })?;
+                Ok(serde_json::to_value(self.chat.alternatives(question))?)
+            }
+            "select_alternative" => {
+                let answer = command
+                    .parameters
+                    .get("answer")
+                    .and_then(|answer| answer.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'answer' parameter"))?;
+                self.chat.select_alternative(answer)?;
+                Ok(serde_json::Value::Null)
+            }
+            "clear" => {
+                self.chat.clear()?;
+                Ok(serde_json::Value::Null)
+            }
+            "read_aloud" => {
+                // Pauses the answer being read, or reads the latest one
+                let readout = self.readout()?;
+                if let Some(answer) = self.chat.latest_answer() {
+                    readout.load(&answer);
+                }
+                readout.toggle();
+                Ok(serde_json::to_value(readout.state())?)
+            }
+            "stop_reading" => {
+                self.readout()?.stop();
+                Ok(serde_json::Value::Null)
+            }
+            "tool_results" => {
+                let answer = command
+                    .parameters
+                    .get("answer")
+                    .and_then(|answer| answer.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'answer' parameter"))?;
+                Ok(serde_json::to_value(self.chat.tool_results(answer))?)
+            }
+            "set_mode" => {
+                let mode: ConversationMode = serde_json::from_value(
+                    command.parameters.get("mode").cloned().ok_or_else(|| {
+                        anyhow::anyhow!("Missing 'mode' parameter")
+                    })?,
+                )?;
+                self.tool_policy()?.set_mode(mode);
+                Ok(serde_json::Value::Null)
+            }
+            "allow_server" => {
+                let server_id = command
+                    .parameters
+                    .get("server_id")
+                    .and_then(|server_id| server_id.as_str())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Missing 'server_id' parameter")
+                    })?;
+                let allowed = command
+                    .parameters
+                    .get("allowed")
+                    .and_then(|allowed| allowed.as_bool())
+                    .unwrap_or(true);
+                self.tool_policy()?.set_server_allowed(server_id, allowed);
+                Ok(serde_json::Value::Null)
+            }
+            "attachments" => Ok(serde_json::to_value(self.chat.attachments())?),
+            "attach" => {
+                let path = command
+                    .parameters
+                    .get("path")
+                    .and_then(|path| path.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'path' parameter"))?;
+                Ok(serde_json::to_value(
+                    self.chat.attach_file(Path::new(path))?,
+                )?)
+            }
+            "paste_image" => {
+                let data = command
+                    .parameters
+                    .get("data")
+                    .and_then(|data| data.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'data' parameter"))?;
+                let bytes = general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|err| anyhow::anyhow!("Invalid image data: {}", err))?;
+                Ok(serde_json::to_value(self.chat.attach_image(&bytes)?)?)
+            }
+            "detach" => {
+                let index = command
+                    .parameters
+                    .get("index")
+                    .and_then(|index| index.as_u64())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'index' parameter"))?;
+                Ok(serde_json::to_value(
+                    self.chat.remove_attachment(index as usize)?,
+                )?)
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown project chat panel command '{}'",
+                other
+            )),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for ProjectChatPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Project Chat".to_string(),
+            description: "Ask questions about the codebase, answered with sources"
+                .to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn floem::View> {
+        let chat = self.chat.clone();
+        // Outcome of the last drop, shown until the next one
+        let notice = create_rw_signal(None::<String>);
+        let view = text_panel_view(move || {
+            let messages = chat.messages();
+            let mut sections = Vec::new();
+            if messages.is_empty() {
+                sections.push(
+                    "Ask anything about this codebase, e.g. \"where is auth \
+                     handled?\""
+                        .to_string(),
+                );
+            }
+            sections.extend(messages.iter().map(|message| {
+                let mut text = match message.role {
+                    ProjectChatRole::User => format!("> {}", message.content),
+                    ProjectChatRole::Assistant => message.content.clone(),
+                };
+                if let Some(question) = message.parent {
+                    let alternatives = chat.alternatives(question);
+                    if alternatives.len() > 1 {
+                        let position = alternatives
+                            .iter()
+                            .position(|alternative| alternative.selected)
+                            .unwrap_or(0);
+                        text = format!(
+                            "[answer {} of {}]\n{}",
+                            position + 1,
+                            alternatives.len(),
+                            text
+                        );
+                    }
+                }
+                if !message.overrides.is_empty() {
+                    text.push_str(&format!("\n  ({})", message.overrides.label()));
+                }
+                for attachment in &message.attachments {
+                    text.push_str(&format!("\n  + {}", attachment));
+                }
+                for citation in &message.citations {
+                    text.push_str(&format!(
+                        "\n  [{}] {}",
+                        citation.number,
+                        citation.source.location.label()
+                    ));
+                }
+                text
+            }));
+            for attachment in chat.attachments() {
+                let mut text = format!("Attached: {}", attachment.label());
+                if let Some(preview) = attachment.preview() {
+                    text.push_str(&format!("\n{}", preview));
+                }
+                sections.push(text);
+            }
+            if let Some(notice) = notice.get() {
+                sections.push(notice);
+            }
+            sections.join("\n\n")
+        });
+        let chat = self.chat.clone();
+        let view = view.on_event_stop(EventListener::DroppedFile, move |event| {
+            if let Event::DroppedFile(file) = event {
+                notice.set(
+                    chat.attach_file(&file.path)
+                        .err()
+                        .map(|err| err.to_string()),
+                );
+            }
+        });
+        let controls = match self.readout.clone() {
+            Some(readout) => {
+                let chat = self.chat.clone();
+                speech_controls_view(readout, move || chat.latest_answer())
+            }
+            None => Box::new(empty()),
+        };
+        // Each in the viewer its content hint selects
+        let tool_calls = self
+            .chat
+            .messages()
+            .into_iter()
+            .rev()
+            .find(|message| message.role == ProjectChatRole::Assistant)
+            .map(|answer| self.chat.tool_results(answer.id))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tool| {
+                tool_call_view(&tool.call.name, &tool.result, self.sandbox.clone())
+            })
+            .collect::<Vec<_>>();
+        Box::new(
+            v_stack((
+                controls.style(|s| s.padding_horiz(10.0).padding_top(10.0)),
+                view,
+                stack_from_iter(tool_calls)
+                    .style(|s| s.flex_col().gap(10.0).padding(10.0)),
+            ))
+            .style(|s| s.size_full()),
+        )
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.chat.messages()).unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        AiAuthData, AiAuthResult, AiCapability, AiPluginInfo, AiStreamCompletion,
+        AiStreamSender, AiUsageInfo, MemorySource, MonorepoKind,
+        READ_MORE_OUTPUT_TOOL_NAME, RetrievalWeights,
+    };
+
+    /// Answers every question by checking the git status
+    #[derive(Default)]
+    struct ToolCallingAssistant {
+        requests: Mutex<Vec<AiMessageRequest>>,
+    }
+
+    impl AiAssistantPlugin for ToolCallingAssistant {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn plugin_info(&self) -> AiPluginInfo {
+            AiPluginInfo {
+                name: "tools".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                provider: "test".to_string(),
+                supports_streaming: false,
+                supports_tools: true,
+                supports_vision: false,
+            }
+        }
+
+        fn is_authenticated(&self) -> bool {
+            true
+        }
+
+        fn send_message(
+            &self,
+            request: AiMessageRequest,
+        ) -> Result<AiMessageResponse> {
+            self.requests.lock().push(request);
+            Ok(AiMessageResponse {
+                content: "Let me check.".to_string(),
+                tool_calls: Some(vec![ToolCall {
+                    id: "call-1".to_string(),
+                    name: "git__git_status".to_string(),
+                    arguments: serde_json::json!({}),
+                }]),
+                usage: None,
+                model: "tools-1".to_string(),
+                finish_reason: Some("tool_calls".to_string()),
+            })
+        }
+
+        fn stream_message(
+            &self,
+            _request: AiMessageRequest,
+            _sender: &AiStreamSender,
+        ) -> Result<AiStreamCompletion> {
+            Err(anyhow::anyhow!("Streaming is not supported"))
+        }
+
+        fn get_capabilities(&self) -> Vec<AiCapability> {
+            Vec::new()
+        }
+
+        fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+            Err(anyhow::anyhow!("Not supported"))
+        }
+
+        fn get_usage_info(&self) -> Option<AiUsageInfo> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_answers_cite_workspace_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Arc::new(
+            WorkspaceMemoryStore::open(&dir.path().join("memory.json")).unwrap(),
+        );
+        memory
+            .remember(
+                "Auth tokens are checked in the gateway middleware",
+                Vec::new(),
+                MemorySource::User,
+            )
+            .unwrap();
+        let layout = WorkspaceLayout {
+            root: dir.path().to_path_buf(),
+            kind: MonorepoKind::Single,
+            sub_projects: Vec::new(),
+        };
+        let path = dir.path().join("chat.json");
+        let chat = ProjectChat::open(
+            &path,
+            &layout,
+            memory.clone(),
+            Arc::new(HybridRetriever::new(RetrievalWeights::default())),
+        )
+        .unwrap();
+
+        let overrides = MessageOverrides {
+            temperature: Some(0.1),
+            ..Default::default()
+        };
+        let (request, sources) = chat.prepare("Where is auth handled?", &overrides);
+        assert_eq!(request.temperature, Some(0.1));
+        assert_eq!(sources.len(), 1);
+        let context = request.context.unwrap();
+        assert!(context.current_file.is_none());
+        assert_eq!(context.workspace_roots.len(), 1);
+        assert_eq!(
+            context.workspace_roots[0].root_path,
+            dir.path().to_string_lossy()
+        );
+        assert_eq!(request.messages.len(), 2);
+
+        let answer = chat
+            .record(
+                "Where is auth handled?",
+                &overrides,
+                "In the gateway middleware [S1].",
+                &sources,
+            )
+            .unwrap();
+        assert_eq!(answer.content, "In the gateway middleware [1].");
+        assert_eq!(answer.citations.len(), 1);
+
+        // A short follow-up is retrieved together with the previous question
+        let (request, sources) =
+            chat.prepare("and tokens?", &MessageOverrides::default());
+        assert_eq!(sources.len(), 1);
+        assert_eq!(request.messages.len(), 4);
+
+        let reopened = ProjectChat::open(
+            &path,
+            &layout,
+            memory,
+            Arc::new(HybridRetriever::new(RetrievalWeights::default())),
+        )
+        .unwrap();
+        assert_eq!(reopened.messages().len(), 2);
+        // Kept for regenerating the answer
+        assert_eq!(reopened.messages()[0].overrides, overrides);
+
+        // Another answer is kept next to the first and shown instead
+        let question = reopened.messages()[0].id;
+        let draft = reopened
+            .record_answer(question, "Maybe auth.rs", &[])
+            .unwrap();
+        assert_eq!(reopened.messages().len(), 2);
+        assert_eq!(reopened.messages()[1].id, draft.id);
+        assert_eq!(reopened.alternatives(question).len(), 2);
+        assert_eq!(reopened.latest_answer().as_deref(), Some("Maybe auth.rs"));
+        reopened.select_alternative(answer.id).unwrap();
+        assert_eq!(reopened.messages()[1].id, answer.id);
+        // Read aloud instead of the draft
+        assert_eq!(
+            reopened.latest_answer().as_deref(),
+            Some("In the gateway middleware [1].")
+        );
+    }
+    #[test]
+    fn test_runs_tool_calls_in_agent_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Arc::new(
+            WorkspaceMemoryStore::open(&dir.path().join("memory.json")).unwrap(),
+        );
+        let layout = WorkspaceLayout {
+            root: dir.path().to_path_buf(),
+            kind: MonorepoKind::Single,
+            sub_projects: Vec::new(),
+        };
+        let policy = ConversationPolicy::default();
+        let chat = ProjectChat::open(
+            &dir.path().join("chat.json"),
+            &layout,
+            memory,
+            Arc::new(HybridRetriever::new(RetrievalWeights::default())),
+        )
+        .unwrap()
+        .with_tools(policy.clone(), McpServerRegistry::new());
+        let overrides = MessageOverrides::default();
+
+        // No tools are offered in Ask mode
+        let (request, _) = chat.prepare("Is the tree clean?", &overrides);
+        assert!(request.tools.is_none());
+
+        policy.set_mode(ConversationMode::Agent);
+        let assistant = ToolCallingAssistant::default();
+        let answer = chat
+            .ask(&assistant, "Is the tree clean?", &overrides)
+            .unwrap();
+        let tools = assistant.requests.lock()[0].tools.clone().unwrap();
+        assert!(
+            tools
+                .iter()
+                .any(|tool| tool.name == READ_MORE_OUTPUT_TOOL_NAME)
+        );
+        let results = chat.tool_results(answer.id);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].call.name, "git__git_status");
+        // The git server is not on the allowlist
+        assert!(results[0].result.is_error);
+
+        // The results are given to the assistant with the next question
+        let (request, _) = chat.prepare("And now?", &overrides);
+        assert!(request.messages.iter().any(|message| {
+            message
+                .content
+                .starts_with("Let me check.\n\nResult of git__git_status:")
+        }));
+    }
+}
@@ -0,0 +1,551 @@
+//! Refactor Planner
+//!
+//! This module plans renames of symbols and moves of files across the
+//! workspace. The language server is asked first, since its edits follow
+//! the language's scoping rules. When no server supports the refactoring,
+//! the assistant proposes the edits instead, and they are only accepted if
+//! every changed file still parses and no reference to the old name is left
+//! in the files that use it. Either way the result is an
+//! [`EditReview`] for the user to go through before anything is applied.
+
+use anyhow::Result;
+use lsp_types::{
+    DocumentChangeOperation, DocumentChanges, OneOf, Position, ResourceOp, TextEdit,
+    WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::lsp::path_from_url;
+use crate::plugin_api::{
+    EditReview, ProposedEdit, SymbolGraph, WorkspaceSandbox, check_syntax,
+};
+
+/// Files given to the assistant when it plans a refactoring
+const MAX_CANDIDATE_FILES: usize = 50;
+
+/// A refactoring to plan; paths are relative to the workspace root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RefactorRequest {
+    RenameSymbol {
+        path: PathBuf,
+        /// Position of the symbol, as an LSP position
+        position: Position,
+        old_name: String,
+        new_name: String,
+    },
+    MoveFile {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+/// Who planned the edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefactorSource {
+    LanguageServer,
+    Assistant,
+}
+
+/// Planned edits of a refactoring, ready for review
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefactorPlan {
+    pub request: RefactorRequest,
+    pub source: RefactorSource,
+    pub review: EditReview,
+    /// Problems the reviewer should look at, e.g. references the assistant
+    /// may have missed
+    pub warnings: Vec<String>,
+}
+
+/// Refactorings of the language server of a file
+///
+/// Implemented over the proxy's LSP connection. `Ok(None)` means no server
+/// for the file supports the refactoring, and the planner falls back to the
+/// assistant.
+pub trait RefactorLanguageServer: Send + Sync {
+    /// `textDocument/rename`; `path` is absolute
+    fn rename(
+        &self,
+        path: &Path,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>>;
+
+    /// `workspace/willRenameFiles`; paths are absolute
+    fn will_rename_file(
+        &self,
+        from: &Path,
+        to: &Path,
+    ) -> Result<Option<WorkspaceEdit>>;
+}
+
+/// Proposes the edits of a refactoring with the AI assistant
+pub trait RefactorAssistant: Send + Sync {
+    /// Get the new content of the files that need to change, given the
+    /// files that may be affected with their current content
+    ///
+    /// Paths are relative to the workspace root. A move is expressed by
+    /// the planner itself; the assistant only updates contents.
+    fn propose(
+        &self,
+        request: &RefactorRequest,
+        files: &[(PathBuf, String)],
+    ) -> Result<Vec<ProposedEdit>>;
+}
+
+/// Plans refactorings with the language server, or the assistant where the
+/// server can't help
+pub struct RefactorPlanner {
+    sandbox: Arc<WorkspaceSandbox>,
+    symbols: Option<Arc<SymbolGraph>>,
+    language_server: Option<Arc<dyn RefactorLanguageServer>>,
+    assistant: Option<Arc<dyn RefactorAssistant>>,
+}
+
+impl RefactorPlanner {
+    pub fn new(sandbox: Arc<WorkspaceSandbox>) -> Self {
+        Self {
+            sandbox,
+            symbols: None,
+            language_server: None,
+            assistant: None,
+        }
+    }
+
+    /// Use the symbol graph to find the files the assistant should look at
+    pub fn with_symbols(mut self, symbols: Arc<SymbolGraph>) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    pub fn with_language_server(
+        mut self,
+        language_server: Arc<dyn RefactorLanguageServer>,
+    ) -> Self {
+        self.language_server = Some(language_server);
+        self
+    }
+
+    pub fn with_assistant(mut self, assistant: Arc<dyn RefactorAssistant>) -> Self {
+        self.assistant = Some(assistant);
+        self
+    }
+
+    /// Plan a refactoring
+    pub fn plan(&self, request: RefactorRequest) -> Result<RefactorPlan> {
+        if let Some(edits) = self.plan_with_language_server(&request)? {
+            return Ok(RefactorPlan {
+                review: EditReview::new(edits),
+                request,
+                source: RefactorSource::LanguageServer,
+                warnings: Vec::new(),
+            });
+        }
+
+        let assistant = self.assistant.as_ref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "No language server supports this refactoring and the \
+                 assistant is not available"
+            )
+        })?;
+        let files = self.candidate_files(&request)?;
+        let mut edits = assistant.propose(&request, &files)?;
+        for edit in &mut edits {
+            self.sandbox.check_access(&self.absolute(&edit.path))?;
+            // Only the new content comes from the assistant
+            edit.original = files
+                .iter()
+                .find(|(path, _)| *path == edit.path)
+                .map(|(_, content)| content.clone())
+                .or_else(|| std::fs::read_to_string(self.absolute(&edit.path)).ok());
+        }
+        if let RefactorRequest::MoveFile { from, to } = &request {
+            edits = self.move_edits(from, to, edits)?;
+        }
+        let warnings = validate_assistant_edits(&request, &files, &edits)?;
+        Ok(RefactorPlan {
+            review: EditReview::new(edits),
+            request,
+            source: RefactorSource::Assistant,
+            warnings,
+        })
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        self.sandbox.root().join(path)
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.sandbox.root())
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    fn plan_with_language_server(
+        &self,
+        request: &RefactorRequest,
+    ) -> Result<Option<Vec<ProposedEdit>>> {
+        let Some(server) = &self.language_server else {
+            return Ok(None);
+        };
+        match request {
+            RefactorRequest::RenameSymbol {
+                path,
+                position,
+                new_name,
+                ..
+            } => {
+                let path = self.sandbox.check_access(&self.absolute(path))?;
+                match server.rename(&path, *position, new_name)? {
+                    Some(edit) => Ok(Some(self.workspace_edit_to_proposed(edit)?)),
+                    None => Ok(None),
+                }
+            }
+            RefactorRequest::MoveFile { from, to } => {
+                let from_abs = self.sandbox.check_access(&self.absolute(from))?;
+                let to_abs = self.sandbox.check_access(&self.absolute(to))?;
+                let Some(edit) = server.will_rename_file(&from_abs, &to_abs)? else {
+                    return Ok(None);
+                };
+                let edits = self.workspace_edit_to_proposed(edit)?;
+                Ok(Some(self.move_edits(from, to, edits)?))
+            }
+        }
+    }
+
+    /// Turn the edits of a language server into whole-file proposals
+    fn workspace_edit_to_proposed(
+        &self,
+        edit: WorkspaceEdit,
+    ) -> Result<Vec<ProposedEdit>> {
+        let mut text_edits: BTreeMap<PathBuf, Vec<TextEdit>> = BTreeMap::new();
+        for (uri, edits) in edit.changes.unwrap_or_default() {
+            text_edits
+                .entry(path_from_url(&uri))
+                .or_default()
+                .extend(edits);
+        }
+        let mut document_edits = Vec::new();
+        match edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => document_edits.extend(edits),
+            Some(DocumentChanges::Operations(operations)) => {
+                for operation in operations {
+                    match operation {
+                        DocumentChangeOperation::Edit(edit) => {
+                            document_edits.push(edit)
+                        }
+                        DocumentChangeOperation::Op(ResourceOp::Rename(_)) => {
+                            // The move itself is added by the planner
+                        }
+                        DocumentChangeOperation::Op(_) => {
+                            return Err(anyhow::anyhow!(
+                                "The language server's edit creates or deletes \
+                                 files, which is not supported"
+                            ));
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+        for edit in document_edits {
+            text_edits
+                .entry(path_from_url(&edit.text_document.uri))
+                .or_default()
+                .extend(edit.edits.into_iter().map(|edit| match edit {
+                    OneOf::Left(edit) => edit,
+                    OneOf::Right(annotated) => annotated.text_edit,
+                }));
+        }
+
+        text_edits
+            .into_iter()
+            .map(|(path, edits)| {
+                let path = self.sandbox.check_access(&path)?;
+                let original = std::fs::read_to_string(&path)?;
+                let proposed = apply_text_edits(&original, edits)?;
+                Ok(ProposedEdit {
+                    path: self.relative(&path),
+                    original: Some(original),
+                    proposed: Some(proposed),
+                })
+            })
+            .collect()
+    }
+
+    /// Add the move of `from` to `to` to the edits of other files; edits to
+    /// the moved file itself end up in the created file
+    fn move_edits(
+        &self,
+        from: &Path,
+        to: &Path,
+        mut edits: Vec<ProposedEdit>,
+    ) -> Result<Vec<ProposedEdit>> {
+        if self.absolute(to).exists() {
+            return Err(anyhow::anyhow!("{} already exists", to.display()));
+        }
+        let original = std::fs::read_to_string(self.absolute(from))?;
+        let content = match edits.iter().position(|edit| edit.path == from) {
+            Some(index) => edits.remove(index).proposed.unwrap_or_default(),
+            None => original.clone(),
+        };
+        edits.push(ProposedEdit {
+            path: from.to_path_buf(),
+            original: Some(original),
+            proposed: None,
+        });
+        edits.push(ProposedEdit {
+            path: to.to_path_buf(),
+            original: None,
+            proposed: Some(content),
+        });
+        Ok(edits)
+    }
+
+    /// Find the files a refactoring may affect, with their content
+    fn candidate_files(
+        &self,
+        request: &RefactorRequest,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let (own, name) = match request {
+            RefactorRequest::RenameSymbol { path, old_name, .. } => {
+                (path, old_name.clone())
+            }
+            RefactorRequest::MoveFile { from, .. } => (
+                from,
+                from.file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            ),
+        };
+        let mut paths = vec![self.absolute(own)];
+        if let Some(symbols) = &self.symbols {
+            paths.extend(symbols.references(&name));
+        }
+        paths.dedup();
+
+        let mut files = Vec::new();
+        for path in paths {
+            if files.len() >= MAX_CANDIDATE_FILES {
+                break;
+            }
+            let path = if path.is_absolute() {
+                path
+            } else {
+                self.absolute(&path)
+            };
+            let relative = self.relative(&path);
+            if !self.sandbox.is_allowed(&path)
+                || files.iter().any(|(existing, _)| *existing == relative)
+            {
+                continue;
+            }
+            if let Ok(content) = std::fs::read_to_string(&path) {
+                files.push((relative, content));
+            }
+        }
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("{} can't be read", own.display()));
+        }
+        Ok(files)
+    }
+}
+
+/// Check the assistant's edits: every changed file must parse, and the old
+/// name should not be left in the affected files
+///
+/// Syntax errors fail the plan; leftover references become warnings, since
+/// some (in comments or strings) may be intended.
+fn validate_assistant_edits(
+    request: &RefactorRequest,
+    files: &[(PathBuf, String)],
+    edits: &[ProposedEdit],
+) -> Result<Vec<String>> {
+    for edit in edits {
+        if let Some(proposed) = &edit.proposed {
+            check_syntax(&edit.path, proposed).map_err(|message| {
+                anyhow::anyhow!(
+                    "The assistant's edit of {} doesn't parse: {}",
+                    edit.path.display(),
+                    message
+                )
+            })?;
+        }
+    }
+
+    let old_name = match request {
+        RefactorRequest::RenameSymbol { old_name, .. } => old_name.clone(),
+        RefactorRequest::MoveFile { from, .. } => {
+            from.with_extension("").to_string_lossy().replace('\\', "/")
+        }
+    };
+    let mut warnings = Vec::new();
+    for (path, content) in files {
+        let result = match edits.iter().find(|edit| edit.path == *path) {
+            Some(edit) => match &edit.proposed {
+                Some(proposed) => proposed,
+                None => continue,
+            },
+            None => content,
+        };
+        let left = count_references(result, &old_name);
+        if left > 0 {
+            warnings.push(format!(
+                "{} still mentions '{}' {} time(s)",
+                path.display(),
+                old_name,
+                left
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+/// Count the occurrences of a name that aren't part of a longer identifier
+fn count_references(text: &str, name: &str) -> usize {
+    if name.is_empty() {
+        return 0;
+    }
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(name)
+        .filter(|(start, _)| {
+            let before = text[..*start].chars().next_back();
+            let after = text[start + name.len()..].chars().next();
+            !before.is_some_and(is_identifier) && !after.is_some_and(is_identifier)
+        })
+        .count()
+}
+
+/// Apply LSP text edits, whose positions count UTF-16 code units
+pub fn apply_text_edits(text: &str, mut edits: Vec<TextEdit>) -> Result<String> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    let offset = |position: Position| -> Result<usize> {
+        let start = *line_starts.get(position.line as usize).ok_or_else(|| {
+            anyhow::anyhow!("Line {} is past the end of the file", position.line)
+        })?;
+        let line = &text[start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut units = 0;
+        for (index, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return Ok(start + index);
+            }
+            units += c.len_utf16();
+        }
+        Ok(start + line.len())
+    };
+
+    // Apply from the end so earlier offsets stay valid
+    edits.sort_by(|a, b| {
+        (b.range.start.line, b.range.start.character)
+            .cmp(&(a.range.start.line, a.range.start.character))
+    });
+    let mut result = text.to_string();
+    let mut previous_start = usize::MAX;
+    for edit in edits {
+        let start = offset(edit.range.start)?;
+        let end = offset(edit.range.end)?;
+        if start > end || end > previous_start {
+            return Err(anyhow::anyhow!(
+                "The language server sent overlapping edits"
+            ));
+        }
+        result.replace_range(start..end, &edit.new_text);
+        previous_start = start;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+    use lsp_types::Range;
+
+    struct RenamingAssistant;
+
+    impl RefactorAssistant for RenamingAssistant {
+        fn propose(
+            &self,
+            request: &RefactorRequest,
+            files: &[(PathBuf, String)],
+        ) -> Result<Vec<ProposedEdit>> {
+            let RefactorRequest::RenameSymbol {
+                old_name, new_name, ..
+            } = request
+            else {
+                return Ok(Vec::new());
+            };
+            // Misses the second file on purpose
+            Ok(files
+                .iter()
+                .take(1)
+                .map(|(path, content)| ProposedEdit {
+                    path: path.clone(),
+                    original: None,
+                    proposed: Some(content.replace(old_name.as_str(), new_name)),
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_plans_rename_with_assistant_fallback() {
+        let edit = |line, start, end, text: &str| TextEdit {
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: text.to_string(),
+        };
+        assert_eq!(
+            apply_text_edits(
+                "let é = old;\nold();\n",
+                vec![edit(1, 0, 3, "new"), edit(0, 8, 11, "new")]
+            )
+            .unwrap(),
+            "let é = new;\nnew();\n"
+        );
+        assert_eq!(count_references("old old_name fold old()", "old"), 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn old() {}\n").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() { old() }\n").unwrap();
+        let ignore =
+            CatalystIgnore::new(dir.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = Arc::new(WorkspaceSandbox::new(Arc::new(ignore)));
+        let symbols = Arc::new(SymbolGraph::new());
+        symbols.index_file(
+            &dir.path().join("main.rs"),
+            "fn main() { old() }\n",
+            Vec::new(),
+        );
+
+        let request = RefactorRequest::RenameSymbol {
+            path: PathBuf::from("lib.rs"),
+            position: Position::new(0, 3),
+            old_name: "old".to_string(),
+            new_name: "new".to_string(),
+        };
+        assert!(
+            RefactorPlanner::new(sandbox.clone())
+                .plan(request.clone())
+                .is_err()
+        );
+
+        let plan = RefactorPlanner::new(sandbox)
+            .with_symbols(symbols)
+            .with_assistant(Arc::new(RenamingAssistant))
+            .plan(request)
+            .unwrap();
+        assert_eq!(plan.source, RefactorSource::Assistant);
+        assert_eq!(plan.review.files.len(), 1);
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.warnings[0].contains("main.rs"));
+    }
+}
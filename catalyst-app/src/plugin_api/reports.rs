@@ -0,0 +1,550 @@
+//! Reports
+//!
+//! This module builds the health, usage and MCP compliance reports as
+//! serializable structs instead of assembling their text directly. Each
+//! report describes itself as titled sections of labeled rows through the
+//! [`Report`] trait, from which the same outline is rendered as text for the
+//! terminal or HTML for sharing, while JSON is the struct itself for tools.
+//! Reports of test results also list their [`ReportTestCase`]s through the
+//! [`TestReport`] trait, to be rendered as JUnit XML for CI systems.
+//! The rendered output of every format is covered by snapshot tests, so a
+//! change to a report shows up as a reviewable diff of `snapshots/`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    Degradation, DegradationTracker, McpComplianceReport, McpServerHealth,
+    McpServerRegistry, Subsystem, UsageRecord, UsageTotals, current_time,
+};
+
+/// Output format of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Html,
+}
+
+impl ReportFormat {
+    /// Parse a format name as used on the command line
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            other => Err(anyhow::anyhow!(
+                "Unknown report format '{}', expected one of text, json, html",
+                other
+            )),
+        }
+    }
+}
+
+/// Titled group of labeled rows in a report
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportSection {
+    pub heading: String,
+    pub rows: Vec<(String, String)>,
+}
+
+impl ReportSection {
+    pub fn new(heading: &str) -> Self {
+        Self {
+            heading: heading.to_string(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(
+        mut self,
+        label: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.rows.push((label.into(), value.into()));
+        self
+    }
+}
+
+/// Report that can be rendered in every [`ReportFormat`]
+pub trait Report: Serialize {
+    fn title(&self) -> String;
+
+    /// Line shown under the title, such as when the report was generated
+    fn subtitle(&self) -> Option<String> {
+        None
+    }
+
+    fn sections(&self) -> Vec<ReportSection>;
+
+    fn render(&self, format: ReportFormat) -> Result<String> {
+        match format {
+            ReportFormat::Text => Ok(render_text(self)),
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+            ReportFormat::Html => Ok(render_html(self)),
+        }
+    }
+}
+
+/// Outcome of one test in a [`TestReport`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportTestCase {
+    pub name: String,
+    pub time: Duration,
+    /// Failure message, None if the test passed
+    pub failure: Option<String>,
+    /// Output the test logged
+    pub output: String,
+}
+
+/// Report of test results, which can also be rendered as JUnit XML
+pub trait TestReport: Report {
+    fn test_cases(&self) -> Vec<ReportTestCase>;
+
+    /// Render the test cases as one JUnit test suite named after the title
+    fn render_junit(&self) -> String {
+        render_junit(self)
+    }
+}
+
+fn render_text<R: Report + ?Sized>(report: &R) -> String {
+    let mut text = format!("{}\n", report.title());
+    if let Some(subtitle) = report.subtitle() {
+        text.push_str(&format!("{}\n", subtitle));
+    }
+    for section in report.sections() {
+        text.push_str(&format!("\n{}\n", section.heading));
+        if section.rows.is_empty() {
+            text.push_str("  none\n");
+        }
+        let width = section
+            .rows
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (label, value) in &section.rows {
+            text.push_str(&format!("  {:<width$}  {}\n", label, value));
+        }
+    }
+    text
+}
+
+fn render_html<R: Report + ?Sized>(report: &R) -> String {
+    let title = escape_html(&report.title());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    if let Some(subtitle) = report.subtitle() {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(&subtitle)));
+    }
+    for section in report.sections() {
+        html.push_str(&format!("<h2>{}</h2>\n", escape_html(&section.heading)));
+        if section.rows.is_empty() {
+            html.push_str("<p>None</p>\n");
+            continue;
+        }
+        html.push_str("<table>\n");
+        for (label, value) in &section.rows {
+            html.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                escape_html(label),
+                escape_html(value)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_junit<R: TestReport + ?Sized>(report: &R) -> String {
+    let cases = report.test_cases();
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let time: Duration = cases.iter().map(|case| case.time).sum();
+    let suite = escape_html(&report.title());
+    let counts = format!(
+        "tests=\"{}\" failures=\"{}\" time=\"{:.3}\"",
+        cases.len(),
+        failures,
+        time.as_secs_f64()
+    );
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites {counts}>\n  \
+         <testsuite name=\"{suite}\" {counts}>\n"
+    );
+    for case in &cases {
+        xml.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\">\n",
+            suite,
+            escape_html(&case.name),
+            case.time.as_secs_f64()
+        ));
+        if let Some(failure) = &case.failure {
+            let failure = escape_html(failure);
+            xml.push_str(&format!(
+                "      <failure message=\"{failure}\">{failure}</failure>\n"
+            ));
+        }
+        if !case.output.is_empty() {
+            xml.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape_html(&case.output)
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Escape text for HTML, which is also valid in XML
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%Y-%m-%d %H:%M:%S UTC")
+        .to_string()
+}
+
+/// Health of the subsystems and MCP servers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub generated_at: SystemTime,
+    /// Subsystems running on a fallback, oldest first
+    pub degradations: Vec<Degradation>,
+    /// Registered MCP servers by id
+    pub mcp_servers: Vec<McpServerHealthEntry>,
+}
+
+/// Health of one registered MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpServerHealthEntry {
+    pub id: String,
+    pub health: McpServerHealth,
+}
+
+impl HealthReport {
+    /// Gather the current degradations and the health of every MCP server
+    pub fn collect(
+        tracker: &DegradationTracker,
+        registry: &McpServerRegistry,
+    ) -> Self {
+        let mut server_ids = registry.get_server_ids();
+        server_ids.sort();
+        let mcp_servers = server_ids
+            .into_iter()
+            .filter_map(|id| {
+                let server = registry.get_server(&id)?;
+                let health = server.read().health_check();
+                Some(McpServerHealthEntry { id, health })
+            })
+            .collect();
+        Self {
+            generated_at: current_time(),
+            degradations: tracker.status(),
+            mcp_servers,
+        }
+    }
+
+    /// Check that nothing is degraded
+    pub fn is_healthy(&self) -> bool {
+        self.degradations.is_empty()
+    }
+}
+
+impl Report for HealthReport {
+    fn title(&self) -> String {
+        "Health report".to_string()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        Some(format!("Generated {}", format_time(self.generated_at)))
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        let degradations = self.degradations.iter().fold(
+            ReportSection::new("Degraded subsystems"),
+            |section, degradation| {
+                section.row(
+                    subsystem_label(&degradation.subsystem),
+                    format!(
+                        "{}; using {} since {}",
+                        degradation.reason,
+                        degradation.fallback,
+                        format_time(degradation.since)
+                    ),
+                )
+            },
+        );
+        let servers = self.mcp_servers.iter().fold(
+            ReportSection::new("MCP servers"),
+            |section, server| {
+                let health = &server.health;
+                let mut value = format!(
+                    "{:?}, {} requests, {} errors",
+                    health.status, health.request_count, health.error_count
+                );
+                if let Some(uptime) = health.uptime {
+                    value.push_str(&format!(", up {}s", uptime.as_secs()));
+                }
+                if let Some(error) = &health.last_error {
+                    value.push_str(&format!("; last error: {}", error));
+                }
+                section.row(&server.id, value)
+            },
+        );
+        vec![degradations, servers]
+    }
+}
+
+fn subsystem_label(subsystem: &Subsystem) -> String {
+    match subsystem {
+        Subsystem::Index(source) => format!("{:?} index", source),
+        Subsystem::Provider(id) => format!("Provider {}", id),
+        Subsystem::McpServer(id) => format!("MCP server {}", id),
+    }
+}
+
+/// Tokens and cost of assistant requests, in total and broken down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub generated_at: SystemTime,
+    pub total: UsageTotals,
+    /// Usage per feature, the part of the usage key before the first `/`
+    pub features: BTreeMap<String, UsageTotals>,
+    pub models: BTreeMap<String, UsageTotals>,
+}
+
+impl UsageReport {
+    pub fn from_records(records: &[UsageRecord], generated_at: SystemTime) -> Self {
+        let mut report = Self {
+            generated_at,
+            total: UsageTotals::default(),
+            features: BTreeMap::new(),
+            models: BTreeMap::new(),
+        };
+        for record in records {
+            let feature = record.key.split('/').next().unwrap_or(&record.key);
+            report.total.add(record);
+            report
+                .features
+                .entry(feature.to_string())
+                .or_default()
+                .add(record);
+            report
+                .models
+                .entry(record.model.clone())
+                .or_default()
+                .add(record);
+        }
+        report
+    }
+}
+
+impl Report for UsageReport {
+    fn title(&self) -> String {
+        "Usage report".to_string()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        Some(format!("Generated {}", format_time(self.generated_at)))
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        let breakdown = |heading: &str, totals: &BTreeMap<String, UsageTotals>| {
+            totals
+                .iter()
+                .fold(ReportSection::new(heading), |section, (name, totals)| {
+                    section.row(name, describe_usage(totals))
+                })
+        };
+        vec![
+            ReportSection::new("Total")
+                .row("Requests", self.total.requests.to_string())
+                .row("Input tokens", self.total.input_tokens.to_string())
+                .row("Output tokens", self.total.output_tokens.to_string())
+                .row("Cost", format!("${:.4}", self.total.cost)),
+            breakdown("By feature", &self.features),
+            breakdown("By model", &self.models),
+        ]
+    }
+}
+
+fn describe_usage(totals: &UsageTotals) -> String {
+    format!(
+        "{} requests, {} input and {} output tokens, ${:.4}",
+        totals.requests, totals.input_tokens, totals.output_tokens, totals.cost
+    )
+}
+
+impl Report for McpComplianceReport {
+    fn title(&self) -> String {
+        "MCP compliance report".to_string()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        let passed = self.checks.iter().filter(|check| check.passed).count();
+        Some(format!("{} of {} checks passed", passed, self.checks.len()))
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        vec![self.checks.iter().fold(
+            ReportSection::new("Checks"),
+            |section, check| match &check.detail {
+                Some(detail) => {
+                    section.row(&check.name, format!("FAIL: {}", detail))
+                }
+                None => section.row(&check.name, "ok"),
+            },
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        Determinism, ManualClock, McpComplianceCheck, McpServerStatus,
+        RetrievalSource,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn assert_snapshots(name: &str, report: &impl Report) {
+        for (format, extension) in [
+            (ReportFormat::Text, "txt"),
+            (ReportFormat::Json, "json"),
+            (ReportFormat::Html, "html"),
+        ] {
+            insta::assert_snapshot!(
+                format!("{}_{}", name, extension),
+                report.render(format).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_snapshots() {
+        let _guard = Determinism::new(1).enter();
+        let start = current_time();
+
+        let clock = Arc::new(ManualClock::new(start));
+        let tracker = DegradationTracker::with_clock(clock.clone());
+        tracker.report(
+            Subsystem::Index(RetrievalSource::Vector),
+            "checksum mismatch",
+            "workspace scan",
+        );
+        clock.advance(Duration::from_secs(90));
+        tracker.report(
+            Subsystem::Provider("fast".to_string()),
+            "connection refused",
+            "<default>",
+        );
+        let mut health = HealthReport::collect(&tracker, &McpServerRegistry::new());
+        assert!(!health.is_healthy());
+        health.mcp_servers.push(McpServerHealthEntry {
+            id: "github".to_string(),
+            health: McpServerHealth {
+                status: McpServerStatus::Running,
+                last_error: Some("rate limited".to_string()),
+                uptime: Some(Duration::from_secs(300)),
+                request_count: 12,
+                error_count: 1,
+            },
+        });
+        assert_snapshots("health", &health);
+
+        let record =
+            |key: &str, model: &str, tokens: u32, cost: Option<f64>| UsageRecord {
+                key: key.to_string(),
+                model: model.to_string(),
+                input_tokens: tokens,
+                output_tokens: tokens / 4,
+                cost,
+                timestamp: start,
+            };
+        let usage = UsageReport::from_records(
+            &[
+                record("project_chat/1", "large", 1200, Some(0.012)),
+                record("project_chat/2", "small", 800, None),
+                record("commit_message", "small", 400, Some(0.0004)),
+            ],
+            start,
+        );
+        assert_eq!(usage.total.requests, 3);
+        assert_snapshots("usage", &usage);
+
+        let compliance = McpComplianceReport {
+            checks: vec![
+                McpComplianceCheck {
+                    name: "initialize".to_string(),
+                    passed: true,
+                    detail: None,
+                },
+                McpComplianceCheck {
+                    name: "tools/list".to_string(),
+                    passed: false,
+                    detail: Some("tool 'search' has no inputSchema".to_string()),
+                },
+            ],
+        };
+        assert_snapshots("compliance", &compliance);
+    }
+
+    #[derive(Serialize)]
+    struct Suite;
+
+    impl Report for Suite {
+        fn title(&self) -> String {
+            "Suite".to_string()
+        }
+
+        fn sections(&self) -> Vec<ReportSection> {
+            Vec::new()
+        }
+    }
+
+    impl TestReport for Suite {
+        fn test_cases(&self) -> Vec<ReportTestCase> {
+            vec![
+                ReportTestCase {
+                    name: "startup".to_string(),
+                    time: Duration::from_millis(125),
+                    failure: None,
+                    output: String::new(),
+                },
+                ReportTestCase {
+                    name: "mcp".to_string(),
+                    time: Duration::from_millis(40),
+                    failure: Some("server <github> exited".to_string()),
+                    output: "Starting & waiting".to_string(),
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn test_render_junit() {
+        let xml = Suite.render_junit();
+        assert!(xml.contains(
+            "<testsuite name=\"Suite\" tests=\"2\" failures=\"1\" time=\"0.165\">"
+        ));
+        assert!(xml.contains("name=\"startup\" time=\"0.125\">\n    </testcase>"));
+        assert!(xml.contains(
+            "<failure message=\"server &lt;github&gt; exited\">server \
+             &lt;github&gt; exited</failure>"
+        ));
+        assert!(xml.contains("<system-out>Starting &amp; waiting</system-out>"));
+    }
+}
@@ -0,0 +1,494 @@
+//! Context Re-ranking
+//!
+//! This module adds an optional stage after rank fusion that scores the top
+//! retrieved chunks directly against the user query, either with a local
+//! cross-encoder model or with a cheap LLM call. The number of chunks scored
+//! is limited by a latency budget, using a moving average of the latency
+//! observed on previous runs. The average starts from the reranker's own
+//! estimate and drifts back to it while the stage is skipped, so one slow
+//! run doesn't turn the stage off for good.
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    AiAssistantPlugin, AiMessage, AiMessageRequest, ContextChunk, MessageRole,
+    RankedChunk,
+};
+
+/// Maximum characters of a chunk included in an LLM scoring prompt
+const LLM_CHUNK_PREVIEW_CHARS: usize = 1500;
+
+/// Trait that re-ranking backends must implement
+pub trait ChunkReranker: Send + Sync {
+    /// Get reranker information
+    fn reranker_info(&self) -> RerankerInfo;
+
+    /// Score each chunk's relevance to the query, higher is better
+    fn score(&self, query: &str, chunks: &[ContextChunk]) -> Result<Vec<f32>>;
+}
+
+/// Information about a reranker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankerInfo {
+    pub id: String,
+    /// Latency per chunk assumed before any run has been observed
+    pub estimated_latency_per_chunk: Duration,
+    /// Fixed latency of a scoring call, e.g. a network round trip
+    pub estimated_base_latency: Duration,
+}
+
+/// Configuration for the re-ranking stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankConfig {
+    pub enabled: bool,
+    /// Maximum number of fused results to re-score
+    pub top_n: usize,
+    /// Time the stage may add to a retrieval
+    pub latency_budget: Duration,
+}
+
+impl Default for RerankConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            top_n: 20,
+            latency_budget: Duration::from_millis(800),
+        }
+    }
+}
+
+/// Re-ranking stage applied to fused retrieval results
+pub struct RerankStage {
+    reranker: Arc<dyn ChunkReranker>,
+    config: RerankConfig,
+    /// Exponential moving average of the observed latency per chunk
+    observed_latency_per_chunk: Mutex<Option<Duration>>,
+}
+
+impl RerankStage {
+    /// Create a new re-ranking stage
+    pub fn new(reranker: Arc<dyn ChunkReranker>, config: RerankConfig) -> Self {
+        Self {
+            reranker,
+            config,
+            observed_latency_per_chunk: Mutex::new(None),
+        }
+    }
+
+    /// Get the stage configuration
+    pub fn config(&self) -> &RerankConfig {
+        &self.config
+    }
+
+    /// Number of chunks that can be scored within the latency budget
+    pub fn affordable_chunks(&self) -> usize {
+        let info = self.reranker.reranker_info();
+        let per_chunk = self
+            .observed_latency_per_chunk
+            .lock()
+            .unwrap_or(info.estimated_latency_per_chunk);
+        let Some(remaining) = self
+            .config
+            .latency_budget
+            .checked_sub(info.estimated_base_latency)
+        else {
+            return 0;
+        };
+        if per_chunk.is_zero() {
+            return self.config.top_n;
+        }
+
+        let affordable =
+            (remaining.as_secs_f64() / per_chunk.as_secs_f64()) as usize;
+        affordable.min(self.config.top_n)
+    }
+
+    /// Re-order the head of the ranked list by reranker score
+    ///
+    /// Results beyond the scored head keep their fused order. If scoring
+    /// fails the input is returned unchanged.
+    pub fn apply(
+        &self,
+        query: &str,
+        mut ranked: Vec<RankedChunk>,
+    ) -> Vec<RankedChunk> {
+        if !self.config.enabled {
+            return ranked;
+        }
+        let count = self.affordable_chunks().min(ranked.len());
+        if count < 2 {
+            if ranked.len() >= 2 {
+                self.decay_latency();
+            }
+            return ranked;
+        }
+
+        let chunks: Vec<ContextChunk> = ranked[..count]
+            .iter()
+            .map(|ranked| ranked.chunk.clone())
+            .collect();
+        let start = Instant::now();
+        let scores = match self.reranker.score(query, &chunks) {
+            Ok(scores) if scores.len() == count => scores,
+            Ok(scores) => {
+                tracing::warn!(
+                    "Reranker returned {} scores for {} chunks",
+                    scores.len(),
+                    count
+                );
+                return ranked;
+            }
+            Err(err) => {
+                tracing::warn!("Re-ranking failed: {}", err);
+                return ranked;
+            }
+        };
+        self.record_latency(start.elapsed(), count);
+
+        let mut head: Vec<(RankedChunk, f32)> =
+            ranked.drain(..count).zip(scores).collect();
+        head.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| b.0.score.total_cmp(&a.0.score))
+        });
+        let mut reranked: Vec<RankedChunk> =
+            head.into_iter().map(|(ranked, _)| ranked).collect();
+        reranked.extend(ranked);
+        reranked
+    }
+
+    fn record_latency(&self, elapsed: Duration, count: usize) {
+        let base = self.reranker.reranker_info().estimated_base_latency;
+        let per_chunk = elapsed.saturating_sub(base) / count.max(1) as u32;
+
+        self.observe(per_chunk);
+    }
+
+    /// Move the observed latency back toward the reranker's estimate, as
+    /// when the stage is skipped for being too slow
+    fn decay_latency(&self) {
+        if self.observed_latency_per_chunk.lock().is_some() {
+            self.observe(self.reranker.reranker_info().estimated_latency_per_chunk);
+        }
+    }
+
+    /// Fold a latency per chunk into the moving average, which starts from
+    /// the reranker's estimate
+    fn observe(&self, per_chunk: Duration) {
+        let estimate = self.reranker.reranker_info().estimated_latency_per_chunk;
+        let mut observed = self.observed_latency_per_chunk.lock();
+        let previous = observed.unwrap_or(estimate);
+        *observed = Some((previous * 3 + per_chunk) / 4);
+    }
+}
+
+/// Reranker asking an AI assistant to grade chunk relevance
+pub struct LlmReranker {
+    assistant: Arc<dyn AiAssistantPlugin>,
+    model: Option<String>,
+}
+
+impl LlmReranker {
+    /// Create a reranker using the given assistant, preferably with a small
+    /// and cheap model
+    pub fn new(
+        assistant: Arc<dyn AiAssistantPlugin>,
+        model: Option<String>,
+    ) -> Self {
+        Self { assistant, model }
+    }
+
+    fn prompt(query: &str, chunks: &[ContextChunk]) -> String {
+        let mut prompt = format!(
+            "Rate how relevant each code excerpt is for answering the question, \
+             from 0 (irrelevant) to 10 (answers it directly). Reply with only a \
+             JSON array of {} numbers in excerpt order.\n\nQuestion: {}\n",
+            chunks.len(),
+            query
+        );
+        for (i, chunk) in chunks.iter().enumerate() {
+            let preview: String = chunk
+                .content
+                .chars()
+                .take(LLM_CHUNK_PREVIEW_CHARS)
+                .collect();
+            prompt.push_str(&format!(
+                "\nExcerpt {} ({}:{}-{}):\n{}\n",
+                i + 1,
+                chunk.path.display(),
+                chunk.start_line,
+                chunk.end_line,
+                preview
+            ));
+        }
+        prompt
+    }
+}
+
+impl ChunkReranker for LlmReranker {
+    fn reranker_info(&self) -> RerankerInfo {
+        RerankerInfo {
+            id: format!("llm:{}", self.assistant.plugin_info().name),
+            estimated_latency_per_chunk: Duration::from_millis(15),
+            estimated_base_latency: Duration::from_millis(400),
+        }
+    }
+
+    fn score(&self, query: &str, chunks: &[ContextChunk]) -> Result<Vec<f32>> {
+        let response = self.assistant.send_message(AiMessageRequest {
+            messages: vec![AiMessage {
+                role: MessageRole::User,
+                content: Self::prompt(query, chunks),
+                timestamp: None,
+                images: Vec::new(),
+            }],
+            context: None,
+            tools: None,
+            model: self.model.clone(),
+            max_tokens: Some(16 + chunks.len() as u32 * 4),
+            temperature: Some(0.0),
+        })?;
+
+        parse_scores(&response.content)
+    }
+}
+
+/// Extract the first JSON array of numbers from a model reply
+fn parse_scores(content: &str) -> Result<Vec<f32>> {
+    let start = content
+        .find('[')
+        .ok_or_else(|| anyhow::anyhow!("Reranker reply contains no score array"))?;
+    let end = content[start..]
+        .find(']')
+        .map(|end| start + end)
+        .ok_or_else(|| anyhow::anyhow!("Reranker reply contains no score array"))?;
+    Ok(serde_json::from_str(&content[start..=end])?)
+}
+
+#[cfg(feature = "local-embeddings")]
+pub use cross_encoder::CrossEncoderReranker;
+
+#[cfg(feature = "local-embeddings")]
+mod cross_encoder {
+    use anyhow::Result;
+    use ort::{session::Session, value::Tensor};
+    use parking_lot::Mutex;
+    use std::path::Path;
+    use std::time::Duration;
+    use tokenizers::Tokenizer;
+
+    use super::{ChunkReranker, RerankerInfo};
+    use crate::plugin_api::ContextChunk;
+
+    /// Reranker running a cross-encoder ONNX model (e.g.
+    /// ms-marco-MiniLM-L-6-v2) over query/chunk pairs
+    pub struct CrossEncoderReranker {
+        session: Mutex<Session>,
+        tokenizer: Tokenizer,
+        max_sequence_length: usize,
+        uses_token_type_ids: bool,
+    }
+
+    impl CrossEncoderReranker {
+        /// Load the model from a directory containing `model.onnx` and
+        /// `tokenizer.json`
+        pub fn load(dir: &Path, max_sequence_length: usize) -> Result<Self> {
+            let session =
+                Session::builder()?.commit_from_file(dir.join("model.onnx"))?;
+            let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+                .map_err(|err| anyhow::anyhow!("Failed to load tokenizer: {err}"))?;
+            let uses_token_type_ids = session
+                .inputs
+                .iter()
+                .any(|input| input.name == "token_type_ids");
+
+            Ok(Self {
+                session: Mutex::new(session),
+                tokenizer,
+                max_sequence_length,
+                uses_token_type_ids,
+            })
+        }
+    }
+
+    impl ChunkReranker for CrossEncoderReranker {
+        fn reranker_info(&self) -> RerankerInfo {
+            RerankerInfo {
+                id: "cross-encoder".to_string(),
+                estimated_latency_per_chunk: Duration::from_millis(8),
+                estimated_base_latency: Duration::ZERO,
+            }
+        }
+
+        fn score(&self, query: &str, chunks: &[ContextChunk]) -> Result<Vec<f32>> {
+            if chunks.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let pairs: Vec<(&str, &str)> = chunks
+                .iter()
+                .map(|chunk| (query, chunk.content.as_str()))
+                .collect();
+            let encodings = self
+                .tokenizer
+                .encode_batch(pairs, true)
+                .map_err(|err| anyhow::anyhow!("Failed to tokenize input: {err}"))?;
+            let sequence_length = encodings
+                .iter()
+                .map(|encoding| {
+                    encoding.get_ids().len().min(self.max_sequence_length)
+                })
+                .max()
+                .unwrap_or(0)
+                .max(1);
+
+            let batch_size = chunks.len();
+            let mut ids = vec![0i64; batch_size * sequence_length];
+            let mut mask = vec![0i64; batch_size * sequence_length];
+            let mut type_ids = vec![0i64; batch_size * sequence_length];
+            for (row, encoding) in encodings.iter().enumerate() {
+                let length = encoding.get_ids().len().min(sequence_length);
+                for column in 0..length {
+                    let index = row * sequence_length + column;
+                    ids[index] = encoding.get_ids()[column] as i64;
+                    mask[index] = encoding.get_attention_mask()[column] as i64;
+                    type_ids[index] = encoding.get_type_ids()[column] as i64;
+                }
+            }
+
+            let shape = [batch_size, sequence_length];
+            let mut session = self.session.lock();
+            let outputs = if self.uses_token_type_ids {
+                session.run(ort::inputs![
+                    "input_ids" => Tensor::from_array((shape, ids))?,
+                    "attention_mask" => Tensor::from_array((shape, mask))?,
+                    "token_type_ids" => Tensor::from_array((shape, type_ids))?,
+                ])?
+            } else {
+                session.run(ort::inputs![
+                    "input_ids" => Tensor::from_array((shape, ids))?,
+                    "attention_mask" => Tensor::from_array((shape, mask))?,
+                ])?
+            };
+
+            // One relevance logit per pair
+            let (_, logits) = outputs[0].try_extract_tensor::<f32>()?;
+            if logits.len() < batch_size {
+                return Err(anyhow::anyhow!(
+                    "Cross-encoder returned {} scores for {} pairs",
+                    logits.len(),
+                    batch_size
+                ));
+            }
+            let per_pair = logits.len() / batch_size;
+            Ok((0..batch_size)
+                .map(|row| logits[row * per_pair + per_pair - 1])
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::SharedPath;
+
+    struct LengthReranker;
+
+    impl ChunkReranker for LengthReranker {
+        fn reranker_info(&self) -> RerankerInfo {
+            RerankerInfo {
+                id: "length".to_string(),
+                estimated_latency_per_chunk: Duration::from_millis(10),
+                estimated_base_latency: Duration::ZERO,
+            }
+        }
+
+        fn score(&self, _query: &str, chunks: &[ContextChunk]) -> Result<Vec<f32>> {
+            Ok(chunks.iter().map(|c| c.content.len() as f32).collect())
+        }
+    }
+
+    fn ranked(content: &str, score: f32) -> RankedChunk {
+        RankedChunk {
+            chunk: ContextChunk {
+                path: SharedPath::from(content),
+                start_line: 1,
+                end_line: 1,
+                content: content.to_string(),
+                modified: None,
+            },
+            score,
+            sources: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rerank_within_budget() {
+        let stage = RerankStage::new(
+            Arc::new(LengthReranker),
+            RerankConfig {
+                enabled: true,
+                top_n: 10,
+                latency_budget: Duration::from_millis(20),
+            },
+        );
+        assert_eq!(stage.affordable_chunks(), 2);
+
+        let input =
+            vec![ranked("a", 3.0), ranked("bbb", 2.0), ranked("cccccc", 1.0)];
+        let output = stage.apply("query", input);
+        let order: Vec<_> =
+            output.iter().map(|r| r.chunk.content.as_str()).collect();
+        // Only the first two fit the budget, the third keeps its place
+        assert_eq!(order, vec!["bbb", "a", "cccccc"]);
+    }
+
+    #[test]
+    fn test_slow_runs_are_forgotten() {
+        let stage = RerankStage::new(
+            Arc::new(LengthReranker),
+            RerankConfig {
+                enabled: true,
+                top_n: 10,
+                latency_budget: Duration::from_millis(40),
+            },
+        );
+        stage.record_latency(Duration::from_secs(1), 2);
+        assert_eq!(stage.affordable_chunks(), 0);
+
+        // Skipped runs bring the estimate back within the budget
+        let input = || vec![ranked("a", 2.0), ranked("bbb", 1.0)];
+        let skipped = (0..20)
+            .take_while(|_| stage.apply("query", input())[0].chunk.content == "a")
+            .count();
+        assert!(skipped > 0 && skipped < 20);
+        assert!(stage.affordable_chunks() >= 2);
+
+        // The default budget fits the base latency of an LLM call
+        let llm_like = RerankerInfo {
+            id: "llm".to_string(),
+            estimated_latency_per_chunk: Duration::from_millis(15),
+            estimated_base_latency: Duration::from_millis(400),
+        };
+        let budget = RerankConfig::default().latency_budget;
+        assert!(
+            budget
+                > llm_like.estimated_base_latency
+                    + llm_like.estimated_latency_per_chunk * 10
+        );
+    }
+
+    #[test]
+    fn test_parse_scores() {
+        assert_eq!(
+            parse_scores("Scores: [1, 7.5, 0]").unwrap(),
+            vec![1.0, 7.5, 0.0]
+        );
+        assert!(parse_scores("no scores").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Resource Update Delivery
+//!
+//! Servers announce changes of subscribed resources with a
+//! `notifications/resources/updated` notification carrying only the uri.
+//! A [`ResourceUpdateStream`] subscribes to resources, waits for those
+//! notifications and reads the changed resource again, so its consumer gets
+//! the new content rather than a bare uri.
+//!
+//! Servers watching files often notify several times for one save, so
+//! notifications are debounced per resource: an update is delivered once
+//! the resource has been quiet for the debounce interval, with the number
+//! of notifications it stands for.
+
+use anyhow::Result;
+use parking_lot::{Condvar, Mutex};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    McpNotificationHandler, McpResourceContent, McpServerNotification,
+    McpServerRegistry,
+};
+
+/// Time a resource must be quiet before its update is delivered
+pub const DEFAULT_RESOURCE_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Resource of a server, as `(server_id, uri)`
+type ResourceKey = (String, String);
+
+/// Change of a subscribed resource
+#[derive(Debug)]
+pub struct ResourceUpdate {
+    pub server_id: String,
+    pub uri: String,
+    /// Notifications debounced into this update
+    pub notifications: usize,
+    /// Content read after the last notification
+    pub content: Result<McpResourceContent>,
+}
+
+/// Notifications received but not yet delivered
+#[derive(Default)]
+struct PendingUpdates {
+    subscribed: Mutex<BTreeSet<ResourceKey>>,
+    /// Time of the last notification and the number of notifications per
+    /// resource
+    pending: Mutex<BTreeMap<ResourceKey, (Instant, usize)>>,
+    changed: Condvar,
+}
+
+impl McpNotificationHandler for PendingUpdates {
+    fn handle_notification(
+        &self,
+        server_id: &str,
+        notification: &McpServerNotification,
+    ) {
+        let McpServerNotification::ResourceUpdated { uri } = notification else {
+            return;
+        };
+        let key = (server_id.to_string(), uri.clone());
+        if !self.subscribed.lock().contains(&key) {
+            return;
+        }
+        let mut pending = self.pending.lock();
+        let entry = pending.entry(key).or_insert((Instant::now(), 0));
+        *entry = (Instant::now(), entry.1 + 1);
+        self.changed.notify_all();
+    }
+}
+
+/// Delivers the updates of the resources it subscribed to
+///
+/// Create one with [`McpServerRegistry::resource_updates`]. Dropping the
+/// stream unsubscribes from its resources, so streams should not share a
+/// resource.
+pub struct ResourceUpdateStream {
+    registry: McpServerRegistry,
+    updates: Arc<PendingUpdates>,
+    debounce: Duration,
+}
+
+impl ResourceUpdateStream {
+    pub(crate) fn new(registry: McpServerRegistry, debounce: Duration) -> Self {
+        let updates = Arc::new(PendingUpdates::default());
+        registry.add_notification_handler(updates.clone());
+        Self {
+            registry,
+            updates,
+            debounce,
+        }
+    }
+
+    /// Subscribe to changes of a resource of `server_id`
+    pub fn subscribe(&self, server_id: &str, uri: &str) -> Result<()> {
+        self.registry.subscribe_resource(server_id, uri)?;
+        self.updates
+            .subscribed
+            .lock()
+            .insert((server_id.to_string(), uri.to_string()));
+        Ok(())
+    }
+
+    /// Unsubscribe from a resource, dropping its pending update
+    pub fn unsubscribe(&self, server_id: &str, uri: &str) -> Result<()> {
+        let key = (server_id.to_string(), uri.to_string());
+        self.updates.subscribed.lock().remove(&key);
+        self.updates.pending.lock().remove(&key);
+        self.registry.unsubscribe_resource(server_id, uri)
+    }
+
+    /// Get the resources subscribed to, as `(server_id, uri)`
+    pub fn subscriptions(&self) -> Vec<(String, String)> {
+        self.updates.subscribed.lock().iter().cloned().collect()
+    }
+
+    /// Take an update that is due, without waiting
+    pub fn try_recv(&self) -> Option<ResourceUpdate> {
+        self.recv_timeout(Duration::ZERO)
+    }
+
+    /// Wait up to `timeout` for an update to be due and read its resource
+    ///
+    /// Of several due updates, the one quiet the longest comes first.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ResourceUpdate> {
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.updates.pending.lock();
+        loop {
+            let now = Instant::now();
+            let oldest = pending
+                .iter()
+                .min_by_key(|(_, (last, _))| *last)
+                .map(|(key, (last, _))| (key.clone(), *last + self.debounce));
+            let wake = match oldest {
+                Some((key, due)) if due <= now => {
+                    let (_, notifications) = pending.remove(&key)?;
+                    drop(pending);
+                    return Some(self.read(key, notifications));
+                }
+                Some((_, due)) => due.min(deadline),
+                None => deadline,
+            };
+            if now >= deadline {
+                return None;
+            }
+            self.updates.changed.wait_for(&mut pending, wake - now);
+        }
+    }
+
+    fn read(
+        &self,
+        (server_id, uri): ResourceKey,
+        notifications: usize,
+    ) -> ResourceUpdate {
+        let content = self.registry.read_resource(&server_id, &uri);
+        if let Err(err) = &content {
+            tracing::warn!(
+                "Failed to read updated resource {} of MCP server '{}': {:#}",
+                uri,
+                server_id,
+                err
+            );
+        }
+        ResourceUpdate {
+            server_id,
+            uri,
+            notifications,
+            content,
+        }
+    }
+}
+
+impl Drop for ResourceUpdateStream {
+    fn drop(&mut self) {
+        let handler: Arc<dyn McpNotificationHandler> = self.updates.clone();
+        self.registry.remove_notification_handler(&handler);
+        for (server_id, uri) in self.subscriptions() {
+            if let Err(err) = self.registry.unsubscribe_resource(&server_id, &uri) {
+                tracing::debug!(
+                    "Failed to unsubscribe from {} of MCP server '{}': {:#}",
+                    uri,
+                    server_id,
+                    err
+                );
+            }
+        }
+    }
+}
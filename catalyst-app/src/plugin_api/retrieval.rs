@@ -0,0 +1,503 @@
+//! Context Retrieval
+//!
+//! This module fuses the results of the vector index, the trigram content
+//! index and the symbol graph into a single ranked set of context chunks
+//! using weighted reciprocal-rank fusion, with a boost for recently modified
+//! files. It also contains a small evaluation harness used to tune the
+//! fusion weights against labeled queries.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    AttentionTracker, MetricsRegistry, RerankStage, SharedPath, current_time,
+};
+
+/// Labeled queries against this repository used by the evaluation harness
+const DEFAULT_EVAL_QUERIES: &str = include_str!("retrieval_eval.json");
+
+/// A contiguous range of lines from a workspace file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextChunk {
+    pub path: SharedPath,
+    /// First line of the chunk, 1-based
+    pub start_line: usize,
+    /// Last line of the chunk, inclusive
+    pub end_line: usize,
+    pub content: String,
+    pub modified: Option<SystemTime>,
+}
+
+impl ContextChunk {
+    /// Key identifying the chunk across indexes
+    pub fn key(&self) -> String {
+        format!(
+            "{}:{}-{}",
+            self.path.display(),
+            self.start_line,
+            self.end_line
+        )
+    }
+
+    /// Check if the chunk contains a 1-based line
+    pub fn contains_line(&self, line: usize) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// Split file content into chunks of at most `max_lines` lines
+///
+/// All indexes are built from the same chunking so their results can be
+/// matched up during fusion.
+pub fn chunk_text(
+    path: &Path,
+    content: &str,
+    max_lines: usize,
+    modified: Option<SystemTime>,
+) -> Vec<ContextChunk> {
+    let path = SharedPath::new(path);
+    let lines: Vec<&str> = content.lines().collect();
+    lines
+        .chunks(max_lines.max(1))
+        .enumerate()
+        .map(|(i, lines)| {
+            let start_line = i * max_lines.max(1) + 1;
+            ContextChunk {
+                path: path.clone(),
+                start_line,
+                end_line: start_line + lines.len() - 1,
+                content: lines.join("\n"),
+                modified,
+            }
+        })
+        .collect()
+}
+
+/// Index a retrieval result came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RetrievalSource {
+    Vector,
+    Trigram,
+    Symbol,
+}
+
+impl RetrievalSource {
+    pub const ALL: [RetrievalSource; 3] = [
+        RetrievalSource::Vector,
+        RetrievalSource::Trigram,
+        RetrievalSource::Symbol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetrievalSource::Vector => "vector",
+            RetrievalSource::Trigram => "trigram",
+            RetrievalSource::Symbol => "symbol",
+        }
+    }
+
+    /// Get the name of the gauge holding when the index last changed, in
+    /// seconds since the Unix epoch
+    pub fn updated_metric(&self) -> String {
+        format!("index.{}.updated_at", self.name())
+    }
+
+    /// Record that the index changed now
+    pub fn mark_updated(&self) {
+        let now = current_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        MetricsRegistry::global()
+            .set_gauge(&self.updated_metric(), now.as_secs_f64());
+    }
+}
+
+/// Trait that retrieval indexes must implement
+pub trait Retriever: Send + Sync {
+    /// Get the kind of index
+    fn source(&self) -> RetrievalSource;
+
+    /// Return up to `limit` chunks for the query, best match first
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>>;
+}
+
+/// Weights used when fusing ranked lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalWeights {
+    pub vector: f32,
+    pub trigram: f32,
+    pub symbol: f32,
+    /// The `k` constant of reciprocal-rank fusion
+    pub rrf_k: f32,
+    /// Maximum relative boost given to a file modified just now
+    pub recency_boost: f32,
+    /// Age at which the recency boost is halved
+    pub recency_half_life: Duration,
+    /// Maximum relative boost given to the code the user is working on
+    #[serde(default = "default_attention_boost")]
+    pub attention_boost: f32,
+}
+
+fn default_attention_boost() -> f32 {
+    0.3
+}
+
+impl Default for RetrievalWeights {
+    fn default() -> Self {
+        Self {
+            vector: 1.0,
+            trigram: 0.8,
+            symbol: 1.2,
+            rrf_k: 60.0,
+            recency_boost: 0.2,
+            recency_half_life: Duration::from_secs(3 * 24 * 60 * 60),
+            attention_boost: default_attention_boost(),
+        }
+    }
+}
+
+impl RetrievalWeights {
+    fn weight(&self, source: RetrievalSource) -> f32 {
+        match source {
+            RetrievalSource::Vector => self.vector,
+            RetrievalSource::Trigram => self.trigram,
+            RetrievalSource::Symbol => self.symbol,
+        }
+    }
+}
+
+/// A chunk with its fused score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedChunk {
+    pub chunk: ContextChunk,
+    pub score: f32,
+    pub sources: Vec<RetrievalSource>,
+}
+
+/// Fuse ranked lists from several sources with weighted reciprocal-rank
+/// fusion and apply the recency boost
+pub fn reciprocal_rank_fusion(
+    results: &[(RetrievalSource, Vec<ContextChunk>)],
+    weights: &RetrievalWeights,
+    now: SystemTime,
+) -> Vec<RankedChunk> {
+    let mut fused: HashMap<String, RankedChunk> = HashMap::new();
+
+    for (source, chunks) in results {
+        let weight = weights.weight(*source);
+        for (rank, chunk) in chunks.iter().enumerate() {
+            let score = weight / (weights.rrf_k + rank as f32 + 1.0);
+            let entry = fused.entry(chunk.key()).or_insert_with(|| RankedChunk {
+                chunk: chunk.clone(),
+                score: 0.0,
+                sources: Vec::new(),
+            });
+            entry.score += score;
+            if !entry.sources.contains(source) {
+                entry.sources.push(*source);
+            }
+        }
+    }
+
+    let mut ranked: Vec<RankedChunk> = fused
+        .into_values()
+        .map(|mut ranked| {
+            ranked.score *= 1.0 + recency_factor(&ranked.chunk, weights, now);
+            ranked
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.chunk.key().cmp(&b.chunk.key()))
+    });
+    ranked
+}
+
+fn recency_factor(
+    chunk: &ContextChunk,
+    weights: &RetrievalWeights,
+    now: SystemTime,
+) -> f32 {
+    let Some(modified) = chunk.modified else {
+        return 0.0;
+    };
+    let age = now.duration_since(modified).unwrap_or_default();
+    let half_lives =
+        age.as_secs_f32() / weights.recency_half_life.as_secs_f32().max(1.0);
+    weights.recency_boost * 0.5f32.powf(half_lives)
+}
+
+/// Retrieval over several indexes with rank fusion
+pub struct HybridRetriever {
+    retrievers: Vec<Arc<dyn Retriever>>,
+    weights: RetrievalWeights,
+    candidates_per_source: usize,
+    rerank: Option<RerankStage>,
+    attention: Option<Arc<AttentionTracker>>,
+}
+
+impl HybridRetriever {
+    /// Create a new hybrid retriever
+    pub fn new(weights: RetrievalWeights) -> Self {
+        Self {
+            retrievers: Vec::new(),
+            weights,
+            candidates_per_source: 50,
+            rerank: None,
+            attention: None,
+        }
+    }
+
+    /// Add an index to retrieve from
+    pub fn add_retriever(&mut self, retriever: Arc<dyn Retriever>) {
+        self.retrievers.push(retriever);
+    }
+
+    /// Set the number of candidates requested from each index
+    pub fn set_candidates_per_source(&mut self, candidates: usize) {
+        self.candidates_per_source = candidates;
+    }
+
+    /// Set the optional re-ranking stage run after fusion
+    pub fn set_rerank_stage(&mut self, rerank: Option<RerankStage>) {
+        self.rerank = rerank;
+    }
+
+    /// Set the attention signal that boosts the code the user is working on
+    pub fn set_attention(&mut self, attention: Option<Arc<AttentionTracker>>) {
+        self.attention = attention;
+    }
+
+    /// Get the fusion weights
+    pub fn weights(&self) -> &RetrievalWeights {
+        &self.weights
+    }
+
+    /// Update the fusion weights
+    pub fn set_weights(&mut self, weights: RetrievalWeights) {
+        self.weights = weights;
+    }
+
+    /// Collect the ranked list of every index for a query
+    ///
+    /// An index that fails is skipped so the others can still answer.
+    pub fn retrieve_per_source(
+        &self,
+        query: &str,
+    ) -> Vec<(RetrievalSource, Vec<ContextChunk>)> {
+        self.retrievers
+            .iter()
+            .filter_map(|retriever| {
+                match retriever.retrieve(query, self.candidates_per_source) {
+                    Ok(chunks) => Some((retriever.source(), chunks)),
+                    Err(err) => {
+                        tracing::warn!(
+                            "{:?} retrieval failed: {}",
+                            retriever.source(),
+                            err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Retrieve the best `limit` chunks for a query
+    pub fn retrieve(&self, query: &str, limit: usize) -> Vec<RankedChunk> {
+        let results = self.retrieve_per_source(query);
+        let mut ranked =
+            reciprocal_rank_fusion(&results, &self.weights, SystemTime::now());
+        if let Some(attention) = &self.attention {
+            attention.boost(&mut ranked, self.weights.attention_boost);
+        }
+        if let Some(rerank) = &self.rerank {
+            ranked = rerank.apply(query, ranked);
+        }
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+/// A query with the files that are known to answer it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabeledQuery {
+    pub query: String,
+    /// Workspace-relative paths of relevant files
+    pub relevant_paths: Vec<String>,
+}
+
+/// Load the labeled queries shipped with Catalyst
+pub fn default_eval_queries() -> Vec<LabeledQuery> {
+    serde_json::from_str(DEFAULT_EVAL_QUERIES).unwrap_or_default()
+}
+
+/// Quality metrics of a retrieval configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalEvalReport {
+    pub query_count: usize,
+    pub k: usize,
+    /// Fraction of queries with a relevant file in the top `k`
+    pub recall_at_k: f32,
+    /// Mean reciprocal rank of the first relevant file
+    pub mean_reciprocal_rank: f32,
+}
+
+/// Evaluates retrieval quality against labeled queries
+pub struct RetrievalEvaluator {
+    queries: Vec<LabeledQuery>,
+    per_source: Vec<Vec<(RetrievalSource, Vec<ContextChunk>)>>,
+    k: usize,
+}
+
+impl RetrievalEvaluator {
+    /// Run every query against the retriever's indexes once; weights can then
+    /// be evaluated without querying the indexes again
+    pub fn new(
+        retriever: &HybridRetriever,
+        queries: Vec<LabeledQuery>,
+        k: usize,
+    ) -> Self {
+        let per_source = queries
+            .iter()
+            .map(|query| retriever.retrieve_per_source(&query.query))
+            .collect();
+        Self {
+            queries,
+            per_source,
+            k,
+        }
+    }
+
+    /// Evaluate a set of fusion weights
+    pub fn evaluate(&self, weights: &RetrievalWeights) -> RetrievalEvalReport {
+        let now = SystemTime::now();
+        let mut hits = 0;
+        let mut reciprocal_rank_sum = 0.0;
+
+        for (query, results) in self.queries.iter().zip(&self.per_source) {
+            let ranked = reciprocal_rank_fusion(results, weights, now);
+            let first_relevant = ranked.iter().take(self.k).position(|ranked| {
+                query
+                    .relevant_paths
+                    .iter()
+                    .any(|path| ranked.chunk.path.ends_with(path))
+            });
+            if let Some(position) = first_relevant {
+                hits += 1;
+                reciprocal_rank_sum += 1.0 / (position as f32 + 1.0);
+            }
+        }
+
+        let query_count = self.queries.len();
+        let denominator = query_count.max(1) as f32;
+        RetrievalEvalReport {
+            query_count,
+            k: self.k,
+            recall_at_k: hits as f32 / denominator,
+            mean_reciprocal_rank: reciprocal_rank_sum / denominator,
+        }
+    }
+
+    /// Grid-search the per-source weights, returning the best weights by
+    /// mean reciprocal rank together with their report
+    pub fn tune(
+        &self,
+        base: &RetrievalWeights,
+        candidates: &[f32],
+    ) -> (RetrievalWeights, RetrievalEvalReport) {
+        let mut best = (base.clone(), self.evaluate(base));
+
+        for &vector in candidates {
+            for &trigram in candidates {
+                for &symbol in candidates {
+                    let weights = RetrievalWeights {
+                        vector,
+                        trigram,
+                        symbol,
+                        ..base.clone()
+                    };
+                    let report = self.evaluate(&weights);
+                    if report.mean_reciprocal_rank > best.1.mean_reciprocal_rank {
+                        best = (weights, report);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(path: &str, modified: Option<SystemTime>) -> ContextChunk {
+        ContextChunk {
+            path: SharedPath::from(path),
+            start_line: 1,
+            end_line: 10,
+            content: String::new(),
+            modified,
+        }
+    }
+
+    #[test]
+    fn test_fusion_rewards_agreement() {
+        let results = vec![
+            (
+                RetrievalSource::Vector,
+                vec![chunk("a", None), chunk("b", None)],
+            ),
+            (
+                RetrievalSource::Trigram,
+                vec![chunk("b", None), chunk("c", None)],
+            ),
+        ];
+        let ranked = reciprocal_rank_fusion(
+            &results,
+            &RetrievalWeights::default(),
+            SystemTime::now(),
+        );
+
+        assert_eq!(ranked[0].chunk.path, Path::new("b"));
+        assert_eq!(ranked[0].sources.len(), 2);
+        assert_eq!(ranked.len(), 3);
+    }
+
+    #[test]
+    fn test_recency_boost() {
+        let now = SystemTime::now();
+        let old = now - Duration::from_secs(365 * 24 * 60 * 60);
+        let results = vec![
+            (RetrievalSource::Vector, vec![chunk("old", Some(old))]),
+            (RetrievalSource::Trigram, vec![chunk("new", Some(now))]),
+        ];
+        let weights = RetrievalWeights {
+            trigram: 1.0,
+            ..Default::default()
+        };
+        let ranked = reciprocal_rank_fusion(&results, &weights, now);
+
+        assert_eq!(ranked[0].chunk.path, Path::new("new"));
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        let chunks = chunk_text(Path::new("f"), "1\n2\n3\n4\n5", 2, None);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!((chunks[2].start_line, chunks[2].end_line), (5, 5));
+        assert!(chunks[1].contains_line(4));
+    }
+
+    #[test]
+    fn test_default_eval_queries_parse() {
+        assert!(!default_eval_queries().is_empty());
+    }
+}
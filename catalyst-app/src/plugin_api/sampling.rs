@@ -0,0 +1,406 @@
+//! MCP sampling
+//!
+//! Servers can ask the client for a model completion with a
+//! [`SAMPLING_CREATE_MESSAGE`] request, so a tool can think without holding
+//! an API key of its own. The request is answered by the active AI
+//! assistant, once the [`SamplingPolicy`] allows it: servers are trusted
+//! by the user, approved per request, or never allowed to sample.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    AiAssistantPlugin, AiImage, AiMessage, AiMessageRequest, AiMessageResponse,
+    INTERNAL_ERROR, INVALID_PARAMS, McpError, McpRequest, McpResponse,
+    McpSamplingContent, McpSamplingMessage, McpSamplingRequest, McpSamplingResult,
+    MessageRole, SAMPLING_CREATE_MESSAGE,
+};
+
+/// JSON-RPC error code of a sampling request the user or policy rejected
+pub const SAMPLING_REJECTED: i32 = -1;
+
+/// Whether servers may sample without asking the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingApproval {
+    /// Every server may sample
+    Always,
+    /// The user approves each request of a server that isn't trusted
+    #[default]
+    Ask,
+    /// No server may sample
+    Never,
+}
+
+/// When MCP servers may ask the AI assistant for completions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingPolicy {
+    pub approval: SamplingApproval,
+    /// Servers that sample without asking, unless sampling is disabled
+    pub trusted_servers: Vec<String>,
+    /// Upper bound of the tokens a server may ask for
+    pub max_tokens: u32,
+}
+
+impl Default for SamplingPolicy {
+    fn default() -> Self {
+        Self {
+            approval: SamplingApproval::Ask,
+            trusted_servers: Vec::new(),
+            max_tokens: 4096,
+        }
+    }
+}
+
+impl SamplingPolicy {
+    /// Check if the user has to approve a request of `server_id`, or
+    /// `None` if the server may not sample at all
+    pub fn needs_approval(&self, server_id: &str) -> Option<bool> {
+        match self.approval {
+            SamplingApproval::Never => None,
+            SamplingApproval::Always => Some(false),
+            SamplingApproval::Ask => {
+                Some(!self.trusted_servers.iter().any(|id| id == server_id))
+            }
+        }
+    }
+}
+
+/// Asks the user whether a server may sample
+pub trait SamplingApprover: Send + Sync {
+    /// Show the request of `server_id` and return whether the user allowed
+    /// it; blocks until they answer
+    fn approve(&self, server_id: &str, request: &McpSamplingRequest) -> bool;
+}
+
+/// Answers the sampling requests of MCP servers with an AI assistant
+pub struct McpSampler {
+    assistant: Arc<dyn AiAssistantPlugin>,
+    policy: SamplingPolicy,
+    approver: Option<Arc<dyn SamplingApprover>>,
+}
+
+impl McpSampler {
+    /// Without an approver, requests that need approval are rejected
+    pub fn new(
+        assistant: Arc<dyn AiAssistantPlugin>,
+        policy: SamplingPolicy,
+    ) -> Self {
+        Self {
+            assistant,
+            policy,
+            approver: None,
+        }
+    }
+
+    pub fn with_approver(mut self, approver: Arc<dyn SamplingApprover>) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    pub fn policy(&self) -> &SamplingPolicy {
+        &self.policy
+    }
+
+    /// Get a completion for a request of `server_id`
+    pub fn create_message(
+        &self,
+        server_id: &str,
+        request: McpSamplingRequest,
+    ) -> Result<McpSamplingResult> {
+        self.check_allowed(server_id, &request)
+            .map_err(|message| anyhow!(message))?;
+        let response = self.assistant.send_message(self.message_request(request))?;
+        Ok(sampling_result(response))
+    }
+
+    /// Answer a [`SAMPLING_CREATE_MESSAGE`] request of `server_id`
+    pub fn handle_request(
+        &self,
+        server_id: &str,
+        request: McpRequest,
+    ) -> McpResponse {
+        let result = match request.method.as_str() {
+            SAMPLING_CREATE_MESSAGE => self.answer(server_id, request.params),
+            method => Err(McpError {
+                code: INVALID_PARAMS,
+                message: format!("'{}' is not a sampling request", method),
+                data: None,
+            }),
+        };
+        match result {
+            Ok(result) => McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+
+    fn answer(
+        &self,
+        server_id: &str,
+        params: Option<serde_json::Value>,
+    ) -> std::result::Result<serde_json::Value, McpError> {
+        let error = |code, message: String| McpError {
+            code,
+            message,
+            data: None,
+        };
+        let request: McpSamplingRequest =
+            serde_json::from_value(params.unwrap_or_default()).map_err(|err| {
+                error(INVALID_PARAMS, format!("Invalid sampling request: {}", err))
+            })?;
+        self.check_allowed(server_id, &request)
+            .map_err(|message| error(SAMPLING_REJECTED, message))?;
+        let response = self
+            .assistant
+            .send_message(self.message_request(request))
+            .map_err(|err| error(INTERNAL_ERROR, format!("{err:#}")))?;
+        serde_json::to_value(sampling_result(response))
+            .map_err(|err| error(INTERNAL_ERROR, err.to_string()))
+    }
+
+    fn check_allowed(
+        &self,
+        server_id: &str,
+        request: &McpSamplingRequest,
+    ) -> std::result::Result<(), String> {
+        let approved = match self.policy.needs_approval(server_id) {
+            None => false,
+            Some(false) => true,
+            Some(true) => self
+                .approver
+                .as_ref()
+                .is_some_and(|approver| approver.approve(server_id, request)),
+        };
+        if approved {
+            Ok(())
+        } else {
+            tracing::info!(
+                "Rejected sampling request of MCP server '{}'",
+                server_id
+            );
+            Err(format!(
+                "Sampling request of MCP server '{}' was rejected",
+                server_id
+            ))
+        }
+    }
+
+    fn message_request(&self, request: McpSamplingRequest) -> AiMessageRequest {
+        let system = request.system_prompt.map(|prompt| AiMessage {
+            role: MessageRole::System,
+            content: prompt,
+            timestamp: None,
+            images: Vec::new(),
+        });
+        let messages = system
+            .into_iter()
+            .chain(request.messages.into_iter().map(ai_message))
+            .collect();
+        AiMessageRequest {
+            messages,
+            context: None,
+            tools: None,
+            model: None,
+            max_tokens: Some(request.max_tokens.min(self.policy.max_tokens)),
+            temperature: request.temperature,
+        }
+    }
+}
+
+fn ai_message(message: McpSamplingMessage) -> AiMessage {
+    let role = match message.role.as_str() {
+        "assistant" => MessageRole::Assistant,
+        _ => MessageRole::User,
+    };
+    let (content, images) = match message.content {
+        McpSamplingContent::Text { text } => (text, Vec::new()),
+        McpSamplingContent::Image { data, mime_type } => (
+            String::new(),
+            vec![AiImage {
+                media_type: mime_type,
+                data,
+            }],
+        ),
+    };
+    AiMessage {
+        role,
+        content,
+        timestamp: None,
+        images,
+    }
+}
+
+fn sampling_result(response: AiMessageResponse) -> McpSamplingResult {
+    let stop_reason = response.finish_reason.map(|reason| {
+        match reason.as_str() {
+            "stop" | "end_turn" => "endTurn",
+            "length" | "max_tokens" => "maxTokens",
+            "stop_sequence" => "stopSequence",
+            other => other,
+        }
+        .to_string()
+    });
+    McpSamplingResult {
+        role: "assistant".to_string(),
+        content: McpSamplingContent::Text {
+            text: response.content,
+        },
+        model: response.model,
+        stop_reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        AiAuthData, AiAuthResult, AiCapability, AiPluginInfo, AiStreamCompletion,
+        AiStreamSender, AiUsageInfo,
+    };
+    use parking_lot::Mutex;
+    use serde_json::json;
+
+    /// Echoes the last message and keeps the requests it got
+    #[derive(Default)]
+    struct EchoAssistant {
+        requests: Mutex<Vec<AiMessageRequest>>,
+    }
+
+    impl AiAssistantPlugin for EchoAssistant {
+        fn initialize(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn plugin_info(&self) -> AiPluginInfo {
+            AiPluginInfo {
+                name: "echo".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                provider: "test".to_string(),
+                supports_streaming: false,
+                supports_tools: false,
+                supports_vision: true,
+            }
+        }
+
+        fn is_authenticated(&self) -> bool {
+            true
+        }
+
+        fn send_message(
+            &self,
+            request: AiMessageRequest,
+        ) -> Result<AiMessageResponse> {
+            let content = request.messages.last().unwrap().content.clone();
+            self.requests.lock().push(request);
+            Ok(AiMessageResponse {
+                content,
+                tool_calls: None,
+                usage: None,
+                model: "echo-1".to_string(),
+                finish_reason: Some("length".to_string()),
+            })
+        }
+
+        fn stream_message(
+            &self,
+            _request: AiMessageRequest,
+            _sender: &AiStreamSender,
+        ) -> Result<AiStreamCompletion> {
+            Err(anyhow!("Streaming is not supported"))
+        }
+
+        fn get_capabilities(&self) -> Vec<AiCapability> {
+            Vec::new()
+        }
+
+        fn authenticate(&mut self, _auth_data: AiAuthData) -> Result<AiAuthResult> {
+            Err(anyhow!("Not supported"))
+        }
+
+        fn get_usage_info(&self) -> Option<AiUsageInfo> {
+            None
+        }
+    }
+
+    struct Deny;
+
+    impl SamplingApprover for Deny {
+        fn approve(&self, _server_id: &str, _request: &McpSamplingRequest) -> bool {
+            false
+        }
+    }
+
+    fn create_message(params: serde_json::Value) -> McpRequest {
+        McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "s1".into(),
+            method: SAMPLING_CREATE_MESSAGE.to_string(),
+            params: Some(params),
+        }
+    }
+
+    #[test]
+    fn test_sampling_policy() {
+        let assistant = Arc::new(EchoAssistant::default());
+        let policy = SamplingPolicy {
+            trusted_servers: vec!["search".to_string()],
+            max_tokens: 100,
+            ..SamplingPolicy::default()
+        };
+        let sampler =
+            McpSampler::new(assistant.clone(), policy).with_approver(Arc::new(Deny));
+        let params = json!({
+            "messages": [{
+                "role": "user",
+                "content": { "type": "text", "text": "Summarize" }
+            }],
+            "systemPrompt": "Be brief",
+            "maxTokens": 500
+        });
+
+        let response =
+            sampler.handle_request("search", create_message(params.clone()));
+        assert_eq!(response.id, "s1");
+        let result: McpSamplingResult =
+            serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(
+            result.content,
+            McpSamplingContent::Text {
+                text: "Summarize".to_string()
+            }
+        );
+        assert_eq!(result.model, "echo-1");
+        assert_eq!(result.stop_reason.as_deref(), Some("maxTokens"));
+        let requests = assistant.requests.lock();
+        assert_eq!(requests[0].messages.len(), 2);
+        assert_eq!(requests[0].messages[0].content, "Be brief");
+        assert_eq!(requests[0].max_tokens, Some(100));
+        drop(requests);
+
+        // Untrusted servers need approval, which is denied
+        let response = sampler.handle_request("other", create_message(params));
+        assert_eq!(response.error.unwrap().code, SAMPLING_REJECTED);
+        let response = sampler.handle_request("search", create_message(json!({})));
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+        let never = SamplingPolicy {
+            approval: SamplingApproval::Never,
+            ..sampler.policy().clone()
+        };
+        assert_eq!(never.needs_approval("search"), None);
+        assert_eq!(assistant.requests.lock().len(), 1);
+    }
+}
@@ -0,0 +1,297 @@
+//! Workspace Sandbox
+//!
+//! This module decides which paths the AI assistant's filesystem tools may
+//! touch. Paths must resolve inside a workspace root and must not be
+//! excluded by the `.catalystignore` rules of that root.
+//!
+//! In a multi-root workspace, relative paths resolve against the primary
+//! root, unless their first component is the name of another root and not
+//! of a folder in the primary root.
+//!
+//! Symbolic links follow the [`SymlinkPolicy`] of the ignore configuration.
+//! Under [`SymlinkPolicy::Resolve`] a path through a link is allowed when
+//! its target lies inside a workspace root and isn't excluded there; under
+//! [`SymlinkPolicy::Reject`] no path through a link is allowed.
+
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{CatalystIgnore, SymlinkPolicy};
+
+/// Access checks for filesystem tools
+#[derive(Clone)]
+pub struct WorkspaceSandbox {
+    ignore: Arc<CatalystIgnore>,
+    /// Other roots of a multi-root workspace, by name
+    other_roots: Vec<(String, Arc<CatalystIgnore>)>,
+}
+
+impl WorkspaceSandbox {
+    /// Create a sandbox for the workspace the ignore rules belong to
+    pub fn new(ignore: Arc<CatalystIgnore>) -> Self {
+        Self {
+            ignore,
+            other_roots: Vec::new(),
+        }
+    }
+
+    /// Also allow access to another root of the workspace, which relative
+    /// paths starting with `name` resolve against
+    pub fn with_root(mut self, name: &str, ignore: Arc<CatalystIgnore>) -> Self {
+        self.other_roots.push((name.to_string(), ignore));
+        self
+    }
+
+    /// Get the workspace root, or the primary root of a multi-root workspace
+    pub fn root(&self) -> &Path {
+        self.ignore.root()
+    }
+
+    /// Get every root of the workspace, the primary root first
+    pub fn roots(&self) -> Vec<&Path> {
+        self.ignores().map(|ignore| ignore.root()).collect()
+    }
+
+    /// Resolve a tool-supplied path and check that it may be accessed,
+    /// returning the absolute path on success
+    pub fn check_access(&self, path: &Path) -> Result<PathBuf> {
+        let (resolved, ignore) = self.resolve(path);
+        if !resolved.starts_with(ignore.root()) {
+            return Err(anyhow::anyhow!(
+                "Access to '{}' is denied: path is outside of the workspace",
+                path.display()
+            ));
+        }
+
+        if ignore.is_ignored(&resolved, resolved.is_dir()) {
+            return Err(anyhow::anyhow!(
+                "Access to '{}' is denied by .catalystignore rules",
+                path.display()
+            ));
+        }
+
+        if let Some(link) = first_symlink(ignore.root(), &resolved) {
+            self.check_symlink_target(path, &resolved, &link, ignore)?;
+        }
+
+        Ok(resolved)
+    }
+
+    /// Check if a path may be accessed
+    pub fn is_allowed(&self, path: &Path) -> bool {
+        self.check_access(path).is_ok()
+    }
+
+    /// Check a path going through the symbolic link `link` against the
+    /// policy of its root
+    fn check_symlink_target(
+        &self,
+        path: &Path,
+        resolved: &Path,
+        link: &Path,
+        ignore: &CatalystIgnore,
+    ) -> Result<()> {
+        if ignore.symlink_policy() == SymlinkPolicy::Reject {
+            return Err(anyhow::anyhow!(
+                "Access to '{}' is denied: path goes through the symbolic link \
+                 '{}'",
+                path.display(),
+                link.display()
+            ));
+        }
+        let outside = || {
+            anyhow::anyhow!(
+                "Access to '{}' is denied: path resolves outside of the \
+                 workspace through a symbolic link",
+                path.display()
+            )
+        };
+        let target = canonicalize_existing(resolved).ok_or_else(outside)?;
+        let (root, target_ignore) = self
+            .ignores()
+            .filter_map(|ignore| {
+                std::fs::canonicalize(ignore.root())
+                    .ok()
+                    .map(|root| (root, ignore))
+            })
+            .filter(|(root, _)| target.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+            .ok_or_else(outside)?;
+        let relative = target.strip_prefix(&root).unwrap_or(Path::new(""));
+        let target = target_ignore.root().join(relative);
+        if target_ignore.is_ignored(&target, target.is_dir()) {
+            return Err(anyhow::anyhow!(
+                "Access to '{}' is denied by .catalystignore rules of its link \
+                 target '{}'",
+                path.display(),
+                target.display()
+            ));
+        }
+        Ok(())
+    }
+
+    fn ignores(&self) -> impl Iterator<Item = &Arc<CatalystIgnore>> {
+        std::iter::once(&self.ignore)
+            .chain(self.other_roots.iter().map(|(_, ignore)| ignore))
+    }
+
+    /// Resolve a path against its root, returning the rules of that root
+    fn resolve(&self, path: &Path) -> (PathBuf, &Arc<CatalystIgnore>) {
+        if path.is_absolute() {
+            let resolved = normalize_path(path);
+            let ignore = self
+                .ignores()
+                .filter(|ignore| resolved.starts_with(ignore.root()))
+                .max_by_key(|ignore| ignore.root().components().count())
+                .unwrap_or(&self.ignore);
+            return (resolved, ignore);
+        }
+        let mut components = path.components();
+        let first = components.next().map(|first| first.as_os_str());
+        let named = self
+            .other_roots
+            .iter()
+            .find(|(name, _)| first.is_some_and(|first| first == name.as_str()))
+            // A folder of the primary root wins over a root of the same name
+            .filter(|_| first.is_none_or(|first| !self.root().join(first).exists()));
+        match named {
+            Some((_, ignore)) => (
+                normalize_path(&ignore.root().join(components.as_path())),
+                ignore,
+            ),
+            None => (normalize_path(&self.root().join(path)), &self.ignore),
+        }
+    }
+}
+
+/// Lexically resolve `.` and `..` components without touching the filesystem
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Find the first symbolic link on the way from `root` down to `path`
+fn first_symlink(root: &Path, path: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Some(current);
+            }
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Canonicalize the longest existing ancestor of a path and append the rest,
+/// so paths of files yet to be created resolve too
+///
+/// Returns `None` if a dangling link is in the way.
+fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        match std::fs::canonicalize(existing) {
+            Ok(canonical) => {
+                return Some(
+                    rest.iter()
+                        .rev()
+                        .fold(canonical, |path, name| path.join(name)),
+                );
+            }
+            Err(_) if std::fs::symlink_metadata(existing).is_ok() => return None,
+            Err(_) => {
+                rest.push(existing.file_name()?);
+                existing = existing.parent()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::CatalystIgnoreConfig;
+
+    fn sandbox(root: &Path, symlinks: SymlinkPolicy) -> WorkspaceSandbox {
+        let config = CatalystIgnoreConfig {
+            symlinks,
+            ..Default::default()
+        };
+        WorkspaceSandbox::new(Arc::new(CatalystIgnore::new(root, &config).unwrap()))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("secrets")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(outside.join("passwd"), "root").unwrap();
+        std::fs::write(root.join(".catalystignore"), "secrets/\n").unwrap();
+        symlink(root.join("src"), root.join("source")).unwrap();
+        symlink(&outside, root.join("escape")).unwrap();
+        symlink(root.join("secrets"), root.join("hidden")).unwrap();
+        symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        let resolve = sandbox(&root, SymlinkPolicy::Resolve);
+        assert_eq!(
+            resolve.check_access(Path::new("source/main.rs")).unwrap(),
+            root.join("source/main.rs")
+        );
+        assert!(resolve.is_allowed(Path::new("source/new.rs")));
+        assert!(!resolve.is_allowed(Path::new("escape/passwd")));
+        assert!(!resolve.is_allowed(Path::new("hidden/key")));
+        assert!(!resolve.is_allowed(Path::new("dangling/file")));
+        assert!(resolve.is_allowed(Path::new("src/main.rs")));
+
+        let reject = sandbox(&root, SymlinkPolicy::Reject);
+        assert!(!reject.is_allowed(Path::new("source/main.rs")));
+        assert!(reject.is_allowed(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn test_primary_folder_wins_over_root_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let primary = dir.path().join("app");
+        let web = dir.path().join("web");
+        std::fs::create_dir_all(primary.join("docs")).unwrap();
+        std::fs::create_dir_all(&web).unwrap();
+        std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+        let config = CatalystIgnoreConfig::default();
+        let ignore =
+            |root: &Path| Arc::new(CatalystIgnore::new(root, &config).unwrap());
+        let sandbox = WorkspaceSandbox::new(ignore(&primary))
+            .with_root("web", ignore(&web))
+            .with_root("docs", ignore(&dir.path().join("docs")));
+
+        assert_eq!(
+            sandbox.check_access(Path::new("web/index.html")).unwrap(),
+            web.join("index.html")
+        );
+        assert_eq!(
+            sandbox.check_access(Path::new("docs/guide.md")).unwrap(),
+            primary.join("docs/guide.md")
+        );
+    }
+}
@@ -0,0 +1,542 @@
+//! Job Scheduler
+//!
+//! This module runs background work (index rebuilds, embedding, workspace
+//! scans) on thread pools sized from the user's concurrency settings. CPU
+//! bound jobs are queued and dispatched up to a concurrency limit that
+//! drops in low-power mode, so indexing a large workspace on battery
+//! trickles along instead of spinning up every core. Blocking I/O runs on
+//! a separate pool so it never starves the CPU-bound jobs. Utilization is
+//! published to the [`MetricsRegistry`] under `scheduler.*`.
+//!
+//! The editor's own background tasks, such as syntax highlighting and diffs,
+//! run on rayon's global pool, which [`configure_global_pool`] sizes once at
+//! startup.
+
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::plugin_api::{MetricsRegistry, is_deterministic};
+
+static GLOBAL_SCHEDULER: Lazy<JobScheduler> = Lazy::new(|| {
+    let scheduler = JobScheduler::new(ConcurrencySettings::default());
+    scheduler.watch_power_source(POWER_POLL_INTERVAL);
+    scheduler
+});
+
+/// How often the global scheduler checks whether the power source changed
+pub const POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+thread_local! {
+    /// Set while the thread runs a CPU-bound job
+    static IN_JOB: Cell<bool> = const { Cell::new(false) };
+}
+
+/// When background concurrency is reduced to save power
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LowPowerMode {
+    /// Reduce concurrency while running on battery
+    #[default]
+    Auto,
+    /// Always reduce concurrency
+    On,
+    /// Never reduce concurrency
+    Off,
+}
+
+/// Thread counts used for background work
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencySettings {
+    /// Threads for CPU-bound jobs, `None` for one less than the number of
+    /// cores so the UI thread keeps a core to itself
+    pub worker_threads: Option<usize>,
+    /// Threads for jobs blocking on files, processes or the network
+    pub blocking_threads: usize,
+    /// Threads for the editor's background tasks, `None` for as many as the
+    /// worker threads; only read at startup
+    pub background_threads: Option<usize>,
+    pub low_power: LowPowerMode,
+    /// CPU-bound jobs allowed to run at once in low-power mode
+    pub low_power_jobs: usize,
+}
+
+impl Default for ConcurrencySettings {
+    fn default() -> Self {
+        Self {
+            worker_threads: None,
+            blocking_threads: 16,
+            background_threads: None,
+            low_power: LowPowerMode::Auto,
+            low_power_jobs: 1,
+        }
+    }
+}
+
+impl ConcurrencySettings {
+    /// Get the number of worker threads to start
+    pub fn effective_worker_threads(&self) -> usize {
+        self.worker_threads
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|cores| cores.get().saturating_sub(1))
+                    .unwrap_or(1)
+            })
+            .max(1)
+    }
+
+    /// Get the number of threads of rayon's global pool
+    pub fn effective_background_threads(&self) -> usize {
+        self.background_threads
+            .unwrap_or_else(|| self.effective_worker_threads())
+            .max(1)
+    }
+}
+
+/// Size rayon's global pool, which runs the editor's background tasks
+///
+/// The global pool can only be built once, before its first use, so this
+/// fails if it was already built; later settings apply after a restart.
+pub fn configure_global_pool(settings: &ConcurrencySettings) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(settings.effective_background_threads())
+        .thread_name(|i| format!("catalyst-background-{i}"))
+        .build_global()?;
+    Ok(())
+}
+
+/// Check if the machine is running on battery
+///
+/// Only Linux is detected, through the power supplies in sysfs; everywhere
+/// else the machine is assumed to be plugged in.
+pub fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+        let mut has_battery = false;
+        for supply in supplies.flatten() {
+            let path = supply.path();
+            let kind =
+                std::fs::read_to_string(path.join("type")).unwrap_or_default();
+            match kind.trim() {
+                "Mains" | "USB" => {
+                    let online = std::fs::read_to_string(path.join("online"))
+                        .unwrap_or_default();
+                    if online.trim() == "1" {
+                        return false;
+                    }
+                }
+                "Battery" => has_battery = true,
+                _ => {}
+            }
+        }
+        has_battery
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+/// Current load of the scheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerUtilization {
+    pub worker_threads: usize,
+    /// CPU-bound jobs allowed to run at once
+    pub job_limit: usize,
+    pub running_jobs: usize,
+    pub queued_jobs: usize,
+    pub blocking_threads: usize,
+    pub running_blocking_jobs: usize,
+    pub low_power: bool,
+}
+
+/// Completion of a scheduled job
+pub struct JobHandle<T> {
+    receiver: Receiver<Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Wait for the job to finish
+    pub fn join(self) -> Result<T> {
+        self.receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("The job panicked or was dropped"))?
+    }
+
+    /// Get the result if the job has finished
+    pub fn try_join(&self) -> Option<Result<T>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pools {
+    workers: Arc<rayon::ThreadPool>,
+    blocking: Arc<rayon::ThreadPool>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<Job>,
+    running: usize,
+    running_blocking: usize,
+    low_power: bool,
+}
+
+struct SchedulerInner {
+    settings: RwLock<ConcurrencySettings>,
+    pools: RwLock<Pools>,
+    queue: Mutex<Queue>,
+}
+
+/// Runs background jobs within the configured concurrency
+#[derive(Clone)]
+pub struct JobScheduler {
+    inner: Arc<SchedulerInner>,
+}
+
+impl JobScheduler {
+    /// Create a scheduler with its own thread pools
+    pub fn new(settings: ConcurrencySettings) -> Self {
+        let scheduler = Self {
+            inner: Arc::new(SchedulerInner {
+                pools: RwLock::new(build_pools(&settings)),
+                settings: RwLock::new(settings),
+                queue: Mutex::new(Queue::default()),
+            }),
+        };
+        scheduler.refresh_power_state();
+        scheduler
+    }
+
+    /// Get the scheduler shared by the whole application
+    pub fn global() -> &'static JobScheduler {
+        &GLOBAL_SCHEDULER
+    }
+
+    /// Apply new settings
+    ///
+    /// Pools are rebuilt when their size changes; jobs already running
+    /// finish on the old pools.
+    pub fn configure(&self, settings: ConcurrencySettings) {
+        {
+            let mut current = self.inner.settings.write();
+            if *current == settings {
+                return;
+            }
+            if current.effective_worker_threads()
+                != settings.effective_worker_threads()
+                || current.blocking_threads != settings.blocking_threads
+            {
+                *self.inner.pools.write() = build_pools(&settings);
+            }
+            *current = settings;
+        }
+        self.refresh_power_state();
+    }
+
+    /// Get the current settings
+    pub fn settings(&self) -> ConcurrencySettings {
+        self.inner.settings.read().clone()
+    }
+
+    /// Re-evaluate whether low-power mode applies every `interval`, so
+    /// unplugging the machine reduces concurrency without a restart
+    ///
+    /// The thread stops once the scheduler is dropped.
+    pub fn watch_power_source(&self, interval: Duration) {
+        let inner = Arc::downgrade(&self.inner);
+        let result = std::thread::Builder::new()
+            .name("PowerSourceWatcher".to_owned())
+            .spawn(move || watch_power_source(inner, interval));
+        if let Err(err) = result {
+            tracing::error!("Failed to watch the power source: {err}");
+        }
+    }
+
+    /// Re-evaluate whether low-power mode applies, e.g. after the power
+    /// source changed
+    pub fn refresh_power_state(&self) {
+        let low_power = match self.inner.settings.read().low_power {
+            LowPowerMode::Auto => on_battery(),
+            LowPowerMode::On => true,
+            LowPowerMode::Off => false,
+        };
+        let changed = {
+            let mut queue = self.inner.queue.lock();
+            std::mem::replace(&mut queue.low_power, low_power) != low_power
+        };
+        if changed {
+            tracing::info!(
+                "Background concurrency {} for low-power mode",
+                if low_power { "reduced" } else { "restored" }
+            );
+        }
+        self.dispatch();
+    }
+
+    /// Queue a CPU-bound job
+    ///
+    /// In deterministic mode the job runs right away on the calling thread,
+    /// so jobs run in the order they were spawned.
+    pub fn spawn<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        if is_deterministic() {
+            let _ = sender.send(job());
+            return JobHandle { receiver };
+        }
+        self.inner.queue.lock().pending.push_back(Box::new(move || {
+            let _ = sender.send(job());
+        }));
+        self.dispatch();
+        JobHandle { receiver }
+    }
+
+    /// Run a job that blocks on I/O on the blocking pool
+    ///
+    /// Blocking jobs are not throttled in low-power mode since they mostly
+    /// wait and are usually awaited by the user. In deterministic mode they
+    /// run on the calling thread like CPU-bound jobs.
+    pub fn spawn_blocking<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        if is_deterministic() {
+            let _ = sender.send(job());
+            return JobHandle { receiver };
+        }
+        self.inner.queue.lock().running_blocking += 1;
+        self.publish_metrics();
+        let scheduler = self.clone();
+        let pool = self.inner.pools.read().blocking.clone();
+        pool.spawn(move || {
+            let _guard = BlockingGuard(scheduler);
+            let _ = sender.send(job());
+        });
+        JobHandle { receiver }
+    }
+
+    /// Run `f` as a CPU-bound job and wait for it
+    ///
+    /// Parallel iterators inside `f` use the worker pool. Called from a job,
+    /// `f` runs right away in the job's slot: queued behind the job, it
+    /// would wait forever for the slot at a limit of one.
+    pub fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        if IN_JOB.get() {
+            return Ok(f());
+        }
+        self.spawn(move || Ok(f())).join()
+    }
+
+    /// Drop the queued CPU-bound jobs, returning how many were dropped
+    ///
+    /// Running jobs are not interrupted. Joining a dropped job fails.
+    pub fn cancel_pending(&self) -> usize {
+        let pending = std::mem::take(&mut self.inner.queue.lock().pending);
+        self.publish_metrics();
+        pending.len()
+    }
+
+    /// Get the current load
+    pub fn utilization(&self) -> SchedulerUtilization {
+        let settings = self.inner.settings.read();
+        let queue = self.inner.queue.lock();
+        SchedulerUtilization {
+            worker_threads: settings.effective_worker_threads(),
+            job_limit: job_limit(&settings, queue.low_power),
+            running_jobs: queue.running,
+            queued_jobs: queue.pending.len(),
+            blocking_threads: settings.blocking_threads,
+            running_blocking_jobs: queue.running_blocking,
+            low_power: queue.low_power,
+        }
+    }
+
+    /// Start queued jobs while below the job limit
+    fn dispatch(&self) {
+        loop {
+            let job = {
+                let limit = {
+                    let settings = self.inner.settings.read();
+                    job_limit(&settings, self.inner.queue.lock().low_power)
+                };
+                let mut queue = self.inner.queue.lock();
+                if queue.running >= limit {
+                    break;
+                }
+                let Some(job) = queue.pending.pop_front() else {
+                    break;
+                };
+                queue.running += 1;
+                job
+            };
+            let scheduler = self.clone();
+            let pool = self.inner.pools.read().workers.clone();
+            pool.spawn(move || {
+                let _guard = JobGuard(scheduler);
+                IN_JOB.set(true);
+                job();
+            });
+        }
+        self.publish_metrics();
+    }
+
+    fn publish_metrics(&self) {
+        let utilization = self.utilization();
+        let metrics = MetricsRegistry::global();
+        let gauges = [
+            ("scheduler.worker_threads", utilization.worker_threads),
+            ("scheduler.job_limit", utilization.job_limit),
+            ("scheduler.running_jobs", utilization.running_jobs),
+            ("scheduler.queued_jobs", utilization.queued_jobs),
+            ("scheduler.blocking_threads", utilization.blocking_threads),
+            (
+                "scheduler.running_blocking_jobs",
+                utilization.running_blocking_jobs,
+            ),
+            ("scheduler.low_power", utilization.low_power as usize),
+        ];
+        for (name, value) in gauges {
+            metrics.set_gauge(name, value as f64);
+        }
+    }
+}
+
+/// Releases a job slot even if the job panics
+struct JobGuard(JobScheduler);
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        IN_JOB.set(false);
+        self.0.inner.queue.lock().running -= 1;
+        self.0.dispatch();
+    }
+}
+
+struct BlockingGuard(JobScheduler);
+
+impl Drop for BlockingGuard {
+    fn drop(&mut self) {
+        self.0.inner.queue.lock().running_blocking -= 1;
+        self.0.publish_metrics();
+    }
+}
+
+fn watch_power_source(inner: Weak<SchedulerInner>, interval: Duration) {
+    loop {
+        std::thread::sleep(interval);
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        JobScheduler { inner }.refresh_power_state();
+    }
+}
+
+fn job_limit(settings: &ConcurrencySettings, low_power: bool) -> usize {
+    let workers = settings.effective_worker_threads();
+    if low_power {
+        settings.low_power_jobs.clamp(1, workers)
+    } else {
+        workers
+    }
+}
+
+fn build_pools(settings: &ConcurrencySettings) -> Pools {
+    let workers = rayon::ThreadPoolBuilder::new()
+        .num_threads(settings.effective_worker_threads())
+        .thread_name(|i| format!("catalyst-worker-{i}"))
+        .build()
+        .expect("failed to build the worker thread pool");
+    let blocking = rayon::ThreadPoolBuilder::new()
+        .num_threads(settings.blocking_threads.max(1))
+        .thread_name(|i| format!("catalyst-blocking-{i}"))
+        .build()
+        .expect("failed to build the blocking thread pool");
+    Pools {
+        workers: Arc::new(workers),
+        blocking: Arc::new(blocking),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_low_power_limits_concurrent_jobs() {
+        let scheduler = JobScheduler::new(ConcurrencySettings {
+            worker_threads: Some(4),
+            blocking_threads: 2,
+            background_threads: None,
+            low_power: LowPowerMode::On,
+            low_power_jobs: 1,
+        });
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let running = running.clone();
+                let peak = peak.clone();
+                scheduler.spawn(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(i)
+                })
+            })
+            .collect();
+        assert_eq!(scheduler.utilization().job_limit, 1);
+        let results: Vec<usize> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![0, 1, 2, 3]);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+
+        let mut settings = scheduler.settings();
+        settings.low_power = LowPowerMode::Off;
+        scheduler.configure(settings);
+        let utilization = scheduler.utilization();
+        assert_eq!(utilization.job_limit, 4);
+        assert!(!utilization.low_power);
+    }
+
+    #[test]
+    fn test_nested_run_at_limit_one() {
+        let scheduler = JobScheduler::new(ConcurrencySettings {
+            worker_threads: Some(2),
+            low_power: LowPowerMode::On,
+            low_power_jobs: 1,
+            ..Default::default()
+        });
+        let inner = scheduler.clone();
+        let result = scheduler
+            .run(move || inner.run(|| 42).unwrap() + 1)
+            .unwrap();
+        assert_eq!(result, 43);
+        assert_eq!(scheduler.utilization().running_jobs, 0);
+    }
+}
@@ -0,0 +1,585 @@
+//! Semantic Diff
+//!
+//! This module compares two versions of a structured file key by key
+//! instead of line by line, so reformatting or reordering a JSON, YAML or
+//! TOML file shows no changes and a moved block doesn't drown the one value
+//! that changed. Lockfiles are compared by package: a `Cargo.lock` or
+//! `package-lock.json` diff lists the packages added, removed or moved to
+//! another version.
+//!
+//! The diff viewer shows it above the line diff of such files, and the
+//! assistant gets it from the built-in `semantic_diff` tool when reasoning
+//! about configuration changes.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::plugin_api::{ToolCall, ToolDefinition, ToolEffect, WorkspaceSandbox};
+
+/// Name of the tool the assistant uses to get the semantic diff of a file
+pub const SEMANTIC_DIFF_TOOL_NAME: &str = "semantic_diff";
+
+/// Changes listed in a summary before the rest are only counted
+const MAX_SUMMARY_CHANGES: usize = 200;
+/// Length from which values are shortened in a summary
+const MAX_VALUE_CHARS: usize = 80;
+
+/// Structured file format a semantic diff can be made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+    Toml,
+    CargoLock,
+    /// `package-lock.json` or `npm-shrinkwrap.json`
+    NpmLock,
+}
+
+impl StructuredFormat {
+    /// Detect the format of a file from its name
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.file_name()?.to_str()? {
+            "Cargo.lock" => return Some(Self::CargoLock),
+            "package-lock.json" | "npm-shrinkwrap.json" => {
+                return Some(Self::NpmLock);
+            }
+            _ => {}
+        }
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Parse a version of a file; an empty file, as before it was added or
+    /// after it was deleted, is an empty document
+    fn parse(self, text: &str) -> Result<Value> {
+        if text.trim().is_empty() {
+            return Ok(Value::Object(Default::default()));
+        }
+        Ok(match self {
+            Self::Json | Self::NpmLock => serde_json::from_str(text)?,
+            Self::Yaml => serde_yaml::from_str(text)?,
+            Self::Toml | Self::CargoLock => {
+                serde_json::to_value(toml::from_str::<toml::Value>(text)?)?
+            }
+        })
+    }
+}
+
+/// Change of one key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemanticChange {
+    /// Dotted key path, with `[name]` or `[index]` for array items, or the
+    /// package name in a lockfile
+    pub path: String,
+    /// `None` if the key was added
+    pub old: Option<Value>,
+    /// `None` if the key was removed
+    pub new: Option<Value>,
+}
+
+impl SemanticChange {
+    /// Describe the change on one line
+    pub fn describe(&self) -> String {
+        match (&self.old, &self.new) {
+            (None, Some(new)) => format!("+ {}: {}", self.path, short_value(new)),
+            (Some(old), None) => format!("- {}: {}", self.path, short_value(old)),
+            (Some(old), Some(new)) => format!(
+                "~ {}: {} → {}",
+                self.path,
+                short_value(old),
+                short_value(new)
+            ),
+            (None, None) => format!("~ {}", self.path),
+        }
+    }
+}
+
+/// Key-level changes between two versions of a structured file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemanticDiff {
+    pub path: PathBuf,
+    pub format: StructuredFormat,
+    pub changes: Vec<SemanticChange>,
+}
+
+impl SemanticDiff {
+    /// Compare two versions of the file at `path`
+    pub fn compute(path: &Path, old: &str, new: &str) -> Result<Self> {
+        let format = StructuredFormat::detect(path).ok_or_else(|| {
+            anyhow!("'{}' is not a JSON, YAML or TOML file", path.display())
+        })?;
+        let parse = |text: &str, version: &str| {
+            format.parse(text).map_err(|err| {
+                anyhow!(
+                    "Failed to parse the {} '{}': {}",
+                    version,
+                    path.display(),
+                    err
+                )
+            })
+        };
+        let old = parse(old, "old")?;
+        let new = parse(new, "new")?;
+
+        let mut changes = Vec::new();
+        match format {
+            StructuredFormat::CargoLock | StructuredFormat::NpmLock => {
+                diff_packages(
+                    &lock_packages(format, &old),
+                    &lock_packages(format, &new),
+                    &mut changes,
+                );
+            }
+            _ => diff_values("", &old, &new, &mut changes),
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            changes,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Describe the changes, one per line
+    pub fn summary(&self) -> String {
+        if self.changes.is_empty() {
+            return format!("{}: no semantic changes", self.path.display());
+        }
+        let mut lines = vec![format!(
+            "{}: {} {}",
+            self.path.display(),
+            self.changes.len(),
+            if self.changes.len() == 1 {
+                "change"
+            } else {
+                "changes"
+            }
+        )];
+        lines.extend(
+            self.changes
+                .iter()
+                .take(MAX_SUMMARY_CHANGES)
+                .map(SemanticChange::describe),
+        );
+        if self.changes.len() > MAX_SUMMARY_CHANGES {
+            lines.push(format!(
+                "… and {} more",
+                self.changes.len() - MAX_SUMMARY_CHANGES
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Compare two values, recording the changed leaves under `path`
+fn diff_values(
+    path: &str,
+    old: &Value,
+    new: &Value,
+    changes: &mut Vec<SemanticChange>,
+) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let keys: BTreeSet<_> =
+                old_fields.keys().chain(new_fields.keys()).collect();
+            for key in keys {
+                let child = child_path(path, key);
+                match (old_fields.get(key), new_fields.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&child, old, new, changes),
+                    (old, new) => changes.push(SemanticChange {
+                        path: child,
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            match (named_items(old_items), named_items(new_items)) {
+                (Some(old_items), Some(new_items)) => {
+                    diff_named_items(path, &old_items, &new_items, changes);
+                }
+                _ => {
+                    for index in 0..old_items.len().max(new_items.len()) {
+                        let child = format!("{path}[{index}]");
+                        match (old_items.get(index), new_items.get(index)) {
+                            (Some(old), Some(new)) => {
+                                diff_values(&child, old, new, changes)
+                            }
+                            (old, new) => changes.push(SemanticChange {
+                                path: child,
+                                old: old.cloned(),
+                                new: new.cloned(),
+                            }),
+                        }
+                    }
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(SemanticChange {
+            path: path.to_string(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// Key the items of an array of objects by their `name`, or `id`, so
+/// inserting an item doesn't change every item after it
+fn named_items(items: &[Value]) -> Option<BTreeMap<String, &Value>> {
+    let key = ["name", "id"].into_iter().find(|key| {
+        !items.is_empty()
+            && items
+                .iter()
+                .all(|item| item.get(key).is_some_and(Value::is_string))
+    })?;
+    let named: BTreeMap<_, _> = items
+        .iter()
+        .filter_map(|item| Some((item.get(key)?.as_str()?.to_string(), item)))
+        .collect();
+    // Duplicate names can't be matched up
+    (named.len() == items.len()).then_some(named)
+}
+
+fn diff_named_items(
+    path: &str,
+    old: &BTreeMap<String, &Value>,
+    new: &BTreeMap<String, &Value>,
+    changes: &mut Vec<SemanticChange>,
+) {
+    let names: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let child = format!("{path}[{name}]");
+        match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) => diff_values(&child, old, new, changes),
+            (old, new) => changes.push(SemanticChange {
+                path: child,
+                old: old.map(|value| (*value).clone()),
+                new: new.map(|value| (*value).clone()),
+            }),
+        }
+    }
+}
+
+/// Get the versions of every package in a lockfile
+fn lock_packages(
+    format: StructuredFormat,
+    lockfile: &Value,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut packages: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut add = |name: &str, package: &Value| {
+        if let Some(version) = package.get("version").and_then(Value::as_str) {
+            packages
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+        }
+    };
+    match format {
+        StructuredFormat::CargoLock => {
+            for package in lockfile
+                .get("package")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                if let Some(name) = package.get("name").and_then(Value::as_str) {
+                    add(name, package);
+                }
+            }
+        }
+        StructuredFormat::NpmLock => {
+            // Version 2 and 3 key packages by their path in node_modules,
+            // version 1 by name
+            if let Some(entries) =
+                lockfile.get("packages").and_then(Value::as_object)
+            {
+                for (path, package) in entries {
+                    if let Some((_, name)) = path.rsplit_once("node_modules/") {
+                        add(name, package);
+                    }
+                }
+            } else if let Some(entries) =
+                lockfile.get("dependencies").and_then(Value::as_object)
+            {
+                for (name, package) in entries {
+                    add(name, package);
+                }
+            }
+        }
+        _ => {}
+    }
+    packages
+}
+
+fn diff_packages(
+    old: &BTreeMap<String, BTreeSet<String>>,
+    new: &BTreeMap<String, BTreeSet<String>>,
+    changes: &mut Vec<SemanticChange>,
+) {
+    let versions = |versions: Option<&BTreeSet<String>>| {
+        versions.map(|versions| {
+            Value::String(versions.iter().cloned().collect::<Vec<_>>().join(", "))
+        })
+    };
+    let names: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    for name in names {
+        let (old, new) = (old.get(name), new.get(name));
+        if old != new {
+            changes.push(SemanticChange {
+                path: name.clone(),
+                old: versions(old),
+                new: versions(new),
+            });
+        }
+    }
+}
+
+/// Append a key to a path, quoting keys that aren't plain words
+fn child_path(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    let key = if plain {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    };
+    if path.is_empty() {
+        key
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn short_value(value: &Value) -> String {
+    let text = value.to_string();
+    if text.chars().count() <= MAX_VALUE_CHARS {
+        return text;
+    }
+    let short: String = text.chars().take(MAX_VALUE_CHARS).collect();
+    format!("{short}…")
+}
+
+/// Semantic diffs of workspace files against a git revision, for the
+/// assistant
+pub struct SemanticDiffTool {
+    sandbox: WorkspaceSandbox,
+}
+
+impl SemanticDiffTool {
+    pub fn new(sandbox: WorkspaceSandbox) -> Self {
+        Self { sandbox }
+    }
+
+    /// Compare a workspace file with its version at `base`
+    ///
+    /// A file missing at `base` or in the workspace compares as empty.
+    pub fn diff_against(&self, path: &Path, base: &str) -> Result<SemanticDiff> {
+        if base.starts_with('-') || base.contains(char::is_whitespace) {
+            return Err(anyhow!("'{}' is not a valid git ref", base));
+        }
+        let resolved = self.sandbox.check_access(path)?;
+        let relative = resolved
+            .strip_prefix(self.sandbox.root())
+            .unwrap_or(&resolved)
+            .to_path_buf();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(self.sandbox.root())
+                .args(args)
+                .output()
+        };
+        let verified =
+            git(&["rev-parse", "--verify", &format!("{base}^{{commit}}")])?;
+        if !verified.status.success() {
+            return Err(anyhow!("Unknown git revision '{}'", base));
+        }
+        let spec =
+            format!("{}:{}", base, relative.to_string_lossy().replace('\\', "/"));
+        let shown = git(&["show", &spec])?;
+        let old = if shown.status.success() {
+            String::from_utf8_lossy(&shown.stdout).into_owned()
+        } else {
+            String::new()
+        };
+        let new = if resolved.exists() {
+            std::fs::read_to_string(&resolved)?
+        } else {
+            String::new()
+        };
+        SemanticDiff::compute(&relative, &old, &new)
+    }
+
+    /// Definition of the `semantic_diff` tool offered to the assistant
+    pub fn tool_definition() -> ToolDefinition {
+        ToolDefinition {
+            name: SEMANTIC_DIFF_TOOL_NAME.to_string(),
+            description: "List the key-level changes of a JSON, YAML, TOML or \
+                          lockfile in the workspace since a git revision, \
+                          instead of a line diff. Lockfiles list the packages \
+                          whose versions changed."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path relative to the workspace root"
+                    },
+                    "base": {
+                        "type": "string",
+                        "description": "Git revision to compare with; defaults to HEAD"
+                    }
+                },
+                "required": ["path"]
+            }),
+            effect: ToolEffect::ReadOnly,
+        }
+    }
+
+    /// Execute a `semantic_diff` tool call from the assistant
+    pub fn handle_tool_call(&self, call: &ToolCall) -> Result<String> {
+        if call.name != SEMANTIC_DIFF_TOOL_NAME {
+            return Err(anyhow!("Unknown diff tool '{}'", call.name));
+        }
+        let path = call
+            .arguments
+            .get("path")
+            .and_then(|path| path.as_str())
+            .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+        let base = call
+            .arguments
+            .get("base")
+            .and_then(|base| base.as_str())
+            .unwrap_or("HEAD");
+        Ok(self.diff_against(Path::new(path), base)?.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(path: &str, old: &str, new: &str) -> Vec<String> {
+        SemanticDiff::compute(Path::new(path), old, new)
+            .unwrap()
+            .changes
+            .iter()
+            .map(SemanticChange::describe)
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_diff() {
+        // Reformatting and reordering are not changes
+        assert!(describe(
+            "package.json",
+            r#"{"name": "app", "scripts": {"build": "vite build", "test": "jest"}}"#,
+            "{\n  \"scripts\": {\"test\": \"jest\", \"build\": \"vite build\"},\n  \"name\": \"app\"\n}",
+        )
+        .is_empty());
+
+        assert_eq!(
+            describe(
+                "package.json",
+                r#"{"scripts": {"build": "vite build"}, "dependencies": {"react": "^18.2.0"}}"#,
+                r#"{"scripts": {"build": "vite build --mode prod"}, "dependencies": {"react": "^18.2.0", "@types/node": "^20"}}"#,
+            ),
+            [
+                r#"+ dependencies."@types/node": "^20""#,
+                r#"~ scripts.build: "vite build" → "vite build --mode prod""#,
+            ]
+        );
+
+        // Items with a name are matched by it, wherever they moved
+        assert_eq!(
+            describe(
+                ".github/workflows/ci.yml",
+                "jobs:\n  test:\n    steps:\n      - name: checkout\n        uses: actions/checkout@v3\n      - name: test\n        run: cargo test\n",
+                "jobs:\n  test:\n    steps:\n      - name: lint\n        run: cargo clippy\n      - name: checkout\n        uses: actions/checkout@v4\n      - name: test\n        run: cargo test\n",
+            ),
+            [
+                r#"~ jobs.test.steps[checkout].uses: "actions/checkout@v3" → "actions/checkout@v4""#,
+                r#"+ jobs.test.steps[lint]: {"name":"lint","run":"cargo clippy"}"#,
+            ]
+        );
+
+        assert_eq!(
+            describe(
+                "Cargo.toml",
+                "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1.0\"\nlog = \"0.4\"\n",
+                "[dependencies]\nserde = { version = \"1.0\", features = [\"derive\"] }\n\n[package]\nname = \"app\"\n",
+            ),
+            [
+                r#"- dependencies.log: "0.4""#,
+                r#"~ dependencies.serde: "1.0" → {"features":["derive"],"version":"1.0"}"#,
+            ]
+        );
+
+        let lock = |packages: &[(&str, &str)]| {
+            packages
+                .iter()
+                .map(|(name, version)| {
+                    format!(
+                        "[[package]]\nname = \"{name}\"\nversion = \"{version}\"\n\n"
+                    )
+                })
+                .collect::<String>()
+        };
+        assert_eq!(
+            describe(
+                "Cargo.lock",
+                &lock(&[
+                    ("libc", "0.2.150"),
+                    ("serde", "1.0.190"),
+                    ("syn", "1.0.109")
+                ]),
+                &lock(&[
+                    ("libc", "0.2.150"),
+                    ("serde", "1.0.193"),
+                    ("syn", "1.0.109"),
+                    ("syn", "2.0.39"),
+                    ("zerocopy", "0.7.0"),
+                ]),
+            ),
+            [
+                r#"~ serde: "1.0.190" → "1.0.193""#,
+                r#"~ syn: "1.0.109" → "1.0.109, 2.0.39""#,
+                r#"+ zerocopy: "0.7.0""#,
+            ]
+        );
+        assert_eq!(
+            describe(
+                "package-lock.json",
+                r#"{"lockfileVersion": 3, "packages": {"": {"name": "app"}, "node_modules/react": {"version": "18.2.0"}}}"#,
+                r#"{"lockfileVersion": 3, "packages": {"": {"name": "app"}, "node_modules/react": {"version": "18.3.1"}}}"#,
+            ),
+            [r#"~ react: "18.2.0" → "18.3.1""#]
+        );
+
+        assert!(SemanticDiff::compute(Path::new("main.rs"), "", "").is_err());
+        let invalid = SemanticDiff::compute(Path::new("a.json"), "{}", "{");
+        assert!(
+            invalid
+                .unwrap_err()
+                .to_string()
+                .contains("the new 'a.json'")
+        );
+    }
+}
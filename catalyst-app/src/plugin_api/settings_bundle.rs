@@ -0,0 +1,534 @@
+//! Settings Bundle
+//!
+//! This module packs user settings, keymaps, the prompt library and
+//! optionally credentials into a single file for moving a setup between
+//! machines. Bundles can be encrypted with a passphrase (Argon2id key
+//! derivation, XChaCha20-Poly1305); bundles containing credentials always are.
+//! Credentials are read from and imported into a [`CredentialStore`] rather
+//! than copied as a file, so they never land on disk in plaintext.
+
+use anyhow::Result;
+use argon2::Argon2;
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::plugin_api::CredentialStore;
+
+/// Prefix of encrypted bundles
+const ENCRYPTED_MAGIC: &[u8] = b"CATBNDL1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const BUNDLE_VERSION: u32 = 1;
+
+/// Name of the credentials entry in bundles, and of the file in the config
+/// directory holding them where no system keychain is available
+pub const CREDENTIALS_FILE: &str = "credentials.toml";
+/// Directory in the config directory holding the prompt library
+pub const PROMPT_LIBRARY_DIR: &str = "prompts";
+const SETTINGS_FILE: &str = "settings.toml";
+const KEYMAPS_FILE: &str = "keymaps.toml";
+
+/// Kind of data in a bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleCategory {
+    Settings,
+    Keymaps,
+    Prompts,
+    Credentials,
+}
+
+impl BundleCategory {
+    pub const ALL: [BundleCategory; 4] = [
+        BundleCategory::Settings,
+        BundleCategory::Keymaps,
+        BundleCategory::Prompts,
+        BundleCategory::Credentials,
+    ];
+
+    /// Parse a category name as used on the command line
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "settings" => Ok(Self::Settings),
+            "keymaps" => Ok(Self::Keymaps),
+            "prompts" => Ok(Self::Prompts),
+            "credentials" => Ok(Self::Credentials),
+            other => Err(anyhow::anyhow!(
+                "Unknown bundle category '{}', expected one of settings, keymaps, \
+                 prompts, credentials",
+                other
+            )),
+        }
+    }
+}
+
+/// A file stored in a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub category: BundleCategory,
+    /// Path relative to the config directory
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl BundleEntry {
+    /// Get the path relative to the config directory the entry belongs at,
+    /// which its category decides
+    ///
+    /// Fails if the entry's path is anywhere else, so a crafted bundle
+    /// can't write settings over the credentials file or any other file of
+    /// the config directory.
+    pub fn destination(&self) -> Result<PathBuf> {
+        let destination = match self.category {
+            BundleCategory::Settings => Some(PathBuf::from(SETTINGS_FILE)),
+            BundleCategory::Keymaps => Some(PathBuf::from(KEYMAPS_FILE)),
+            BundleCategory::Credentials => Some(PathBuf::from(CREDENTIALS_FILE)),
+            BundleCategory::Prompts => self
+                .path
+                .file_name()
+                .map(|name| Path::new(PROMPT_LIBRARY_DIR).join(name)),
+        };
+        destination
+            .filter(|destination| *destination == self.path)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The bundle contains an invalid path for {:?}: {}",
+                    self.category,
+                    self.path.display()
+                )
+            })
+    }
+}
+
+/// How to handle a bundle entry whose destination already has different
+/// content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    Overwrite,
+    Skip,
+    /// Write the entry next to the existing file with an `.imported` suffix,
+    /// or for credentials only add the secrets that are not stored yet
+    KeepBoth,
+}
+
+/// Result of importing a bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub written: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub unchanged: Vec<PathBuf>,
+}
+
+/// A collection of configuration files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsBundle {
+    pub version: u32,
+    pub created_at: SystemTime,
+    pub entries: Vec<BundleEntry>,
+}
+
+impl SettingsBundle {
+    /// Collect the files of the given categories from a config directory,
+    /// and the secrets of `credentials` if they are selected
+    pub fn collect(
+        config_dir: &Path,
+        credentials: &CredentialStore,
+        categories: &[BundleCategory],
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+        for category in categories {
+            if *category == BundleCategory::Credentials {
+                let secrets = credentials.entries();
+                if !secrets.is_empty() {
+                    entries.push(BundleEntry {
+                        category: *category,
+                        path: PathBuf::from(CREDENTIALS_FILE),
+                        content: toml::to_string(&secrets)?,
+                    });
+                }
+                continue;
+            }
+
+            let mut add = |path: PathBuf| -> Result<()> {
+                let full = config_dir.join(&path);
+                if full.is_file() {
+                    entries.push(BundleEntry {
+                        category: *category,
+                        path,
+                        content: std::fs::read_to_string(&full)?,
+                    });
+                }
+                Ok(())
+            };
+
+            match category {
+                BundleCategory::Settings => add(PathBuf::from(SETTINGS_FILE))?,
+                BundleCategory::Keymaps => add(PathBuf::from(KEYMAPS_FILE))?,
+                BundleCategory::Credentials => {}
+                BundleCategory::Prompts => {
+                    let dir = config_dir.join(PROMPT_LIBRARY_DIR);
+                    if dir.is_dir() {
+                        let mut names = std::fs::read_dir(&dir)?
+                            .filter_map(|entry| entry.ok())
+                            .map(|entry| entry.file_name())
+                            .collect::<Vec<_>>();
+                        names.sort();
+                        for name in names {
+                            add(Path::new(PROMPT_LIBRARY_DIR).join(name))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            version: BUNDLE_VERSION,
+            created_at: SystemTime::now(),
+            entries,
+        })
+    }
+
+    /// Check if the bundle contains credentials
+    pub fn has_credentials(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.category == BundleCategory::Credentials)
+    }
+
+    /// Serialize the bundle, encrypting it if a passphrase is given
+    pub fn to_bytes(&self, passphrase: Option<&str>) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec_pretty(self)?;
+        let Some(passphrase) = passphrase else {
+            if self.has_credentials() {
+                return Err(anyhow::anyhow!(
+                    "Bundles containing credentials must be encrypted"
+                ));
+            }
+            return Ok(json);
+        };
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt)?.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, json.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt bundle"))?;
+
+        let mut bytes = Vec::with_capacity(
+            ENCRYPTED_MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len(),
+        );
+        bytes.extend_from_slice(ENCRYPTED_MAGIC);
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    /// Check if serialized bundle bytes are encrypted
+    pub fn is_encrypted(bytes: &[u8]) -> bool {
+        bytes.starts_with(ENCRYPTED_MAGIC)
+    }
+
+    /// Read a bundle, decrypting it with the passphrase if it is encrypted
+    pub fn from_bytes(bytes: &[u8], passphrase: Option<&str>) -> Result<Self> {
+        let bundle: Self = if Self::is_encrypted(bytes) {
+            let passphrase = passphrase.ok_or_else(|| {
+                anyhow::anyhow!("The bundle is encrypted, a passphrase is required")
+            })?;
+            let rest = &bytes[ENCRYPTED_MAGIC.len()..];
+            if rest.len() < SALT_LEN + NONCE_LEN {
+                return Err(anyhow::anyhow!("The bundle is truncated"));
+            }
+            let (salt, rest) = rest.split_at(SALT_LEN);
+            let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+            let cipher =
+                XChaCha20Poly1305::new(&derive_key(passphrase, salt)?.into());
+            let json = cipher
+                .decrypt(XNonce::from_slice(nonce), ciphertext)
+                .map_err(|_| {
+                    anyhow::anyhow!("Wrong passphrase or corrupted bundle")
+                })?;
+            serde_json::from_slice(&json)?
+        } else {
+            serde_json::from_slice(bytes)?
+        };
+
+        if bundle.version > BUNDLE_VERSION {
+            return Err(anyhow::anyhow!(
+                "The bundle was created by a newer version of Catalyst"
+            ));
+        }
+        for entry in &bundle.entries {
+            entry.destination()?;
+        }
+        Ok(bundle)
+    }
+
+    /// Get the entries of the given categories whose destination already
+    /// exists with different content
+    pub fn conflicts<'a>(
+        &'a self,
+        config_dir: &Path,
+        credentials: &CredentialStore,
+        categories: &'a [BundleCategory],
+    ) -> Vec<&'a BundleEntry> {
+        let stored = credentials.entries();
+        self.selected(categories)
+            .filter(|entry| {
+                if entry.category == BundleCategory::Credentials {
+                    return parse_secrets(entry)
+                        .is_ok_and(|secrets| overrides(&secrets, &stored));
+                }
+                entry
+                    .destination()
+                    .and_then(|path| {
+                        Ok(std::fs::read_to_string(config_dir.join(path))?)
+                    })
+                    .is_ok_and(|existing| existing != entry.content)
+            })
+            .collect()
+    }
+
+    /// Write the entries of the given categories to a config directory and
+    /// their secrets to `credentials`, asking `resolve` what to do about
+    /// each conflicting entry
+    pub fn import(
+        &self,
+        config_dir: &Path,
+        credentials: &CredentialStore,
+        categories: &[BundleCategory],
+        mut resolve: impl FnMut(&BundleEntry) -> Result<ConflictResolution>,
+    ) -> Result<ImportSummary> {
+        // Check every entry first, so a bad one doesn't leave the import
+        // half done
+        for entry in self.selected(categories) {
+            entry.destination()?;
+        }
+
+        let mut summary = ImportSummary::default();
+        for entry in self.selected(categories) {
+            if entry.category == BundleCategory::Credentials {
+                import_secrets(entry, credentials, &mut resolve, &mut summary)?;
+                continue;
+            }
+
+            let mut destination = config_dir.join(entry.destination()?);
+            if let Ok(existing) = std::fs::read_to_string(&destination) {
+                if existing == entry.content {
+                    summary.unchanged.push(entry.path.clone());
+                    continue;
+                }
+                match resolve(entry)? {
+                    ConflictResolution::Overwrite => {}
+                    ConflictResolution::Skip => {
+                        summary.skipped.push(entry.path.clone());
+                        continue;
+                    }
+                    ConflictResolution::KeepBoth => {
+                        let mut name = destination.as_os_str().to_owned();
+                        name.push(".imported");
+                        destination = PathBuf::from(name);
+                    }
+                }
+            }
+
+            if let Some(parent) = destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&destination, &entry.content)?;
+            summary.written.push(
+                destination
+                    .strip_prefix(config_dir)
+                    .unwrap_or(&destination)
+                    .to_path_buf(),
+            );
+        }
+
+        tracing::info!(
+            "Imported settings bundle: {} written, {} skipped",
+            summary.written.len(),
+            summary.skipped.len()
+        );
+        Ok(summary)
+    }
+
+    fn selected<'a>(
+        &'a self,
+        categories: &'a [BundleCategory],
+    ) -> impl Iterator<Item = &'a BundleEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| categories.contains(&entry.category))
+    }
+}
+
+fn import_secrets(
+    entry: &BundleEntry,
+    credentials: &CredentialStore,
+    resolve: &mut impl FnMut(&BundleEntry) -> Result<ConflictResolution>,
+    summary: &mut ImportSummary,
+) -> Result<()> {
+    let secrets = parse_secrets(entry)?;
+    let stored = credentials.entries();
+    if secrets
+        .iter()
+        .all(|(key, value)| stored.get(key) == Some(value))
+    {
+        summary.unchanged.push(entry.path.clone());
+        return Ok(());
+    }
+
+    let secrets = if overrides(&secrets, &stored) {
+        match resolve(entry)? {
+            ConflictResolution::Overwrite => secrets,
+            ConflictResolution::Skip => {
+                summary.skipped.push(entry.path.clone());
+                return Ok(());
+            }
+            ConflictResolution::KeepBoth => secrets
+                .into_iter()
+                .filter(|(key, _)| !stored.contains_key(key))
+                .collect(),
+        }
+    } else {
+        secrets
+    };
+    credentials.extend(secrets)?;
+    summary.written.push(entry.path.clone());
+    Ok(())
+}
+
+fn parse_secrets(entry: &BundleEntry) -> Result<BTreeMap<String, String>> {
+    toml::from_str(&entry.content)
+        .map_err(|e| anyhow::anyhow!("Invalid credentials in the bundle: {}", e))
+}
+
+/// Check if importing `secrets` would replace a different stored value
+fn overrides(
+    secrets: &BTreeMap<String, String>,
+    stored: &BTreeMap<String, String>,
+) -> bool {
+    secrets
+        .iter()
+        .any(|(key, value)| stored.get(key).is_some_and(|old| old != value))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive bundle key: {}", e))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_round_trip_and_conflicts() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("settings.toml"), "a = 1").unwrap();
+        std::fs::create_dir_all(source.path().join(PROMPT_LIBRARY_DIR)).unwrap();
+        std::fs::write(source.path().join("prompts/review.md"), "Review").unwrap();
+        let credentials =
+            CredentialStore::open(&source.path().join("secrets.toml")).unwrap();
+        credentials.set("key", "x").unwrap();
+
+        let bundle = SettingsBundle::collect(
+            source.path(),
+            &credentials,
+            &BundleCategory::ALL,
+        )
+        .unwrap();
+        assert_eq!(bundle.entries.len(), 3);
+        assert!(bundle.to_bytes(None).is_err());
+
+        let bytes = bundle.to_bytes(Some("hunter2")).unwrap();
+        assert!(SettingsBundle::is_encrypted(&bytes));
+        assert!(SettingsBundle::from_bytes(&bytes, Some("wrong")).is_err());
+        let bundle = SettingsBundle::from_bytes(&bytes, Some("hunter2")).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("settings.toml"), "a = 2").unwrap();
+        let stored =
+            CredentialStore::open(&target.path().join("secrets.toml")).unwrap();
+        let selected = [BundleCategory::Settings, BundleCategory::Prompts];
+        assert_eq!(bundle.conflicts(target.path(), &stored, &selected).len(), 1);
+
+        let summary = bundle
+            .import(target.path(), &stored, &selected, |_| {
+                Ok(ConflictResolution::KeepBoth)
+            })
+            .unwrap();
+        assert_eq!(summary.written.len(), 2);
+        assert!(target.path().join("settings.toml.imported").is_file());
+        assert!(!stored.contains("key"));
+
+        stored.set("key", "y").unwrap();
+        stored.set("other", "z").unwrap();
+        let summary = bundle
+            .import(
+                target.path(),
+                &stored,
+                &[BundleCategory::Credentials],
+                |_| Ok(ConflictResolution::Overwrite),
+            )
+            .unwrap();
+        assert_eq!(summary.written, vec![PathBuf::from(CREDENTIALS_FILE)]);
+        assert_eq!(stored.get("key").as_deref(), Some("x"));
+        assert_eq!(stored.get("other").as_deref(), Some("z"));
+        assert!(!target.path().join(CREDENTIALS_FILE).exists());
+    }
+
+    #[test]
+    fn test_entries_are_only_written_where_their_category_belongs() {
+        let entry = |category, path: &str| BundleEntry {
+            category,
+            path: PathBuf::from(path),
+            content: "token = \"stolen\"".to_string(),
+        };
+        let malicious = [
+            entry(BundleCategory::Settings, CREDENTIALS_FILE),
+            entry(BundleCategory::Keymaps, "settings.toml"),
+            entry(BundleCategory::Prompts, "prompts/../credentials.toml"),
+            entry(BundleCategory::Prompts, "prompts/nested/review.md"),
+            entry(BundleCategory::Prompts, "/etc/review.md"),
+            entry(BundleCategory::Credentials, "secrets.toml"),
+        ];
+        let target = tempfile::tempdir().unwrap();
+        let stored =
+            CredentialStore::open(&target.path().join("secrets.toml")).unwrap();
+        for entry in malicious {
+            let bundle = SettingsBundle {
+                version: BUNDLE_VERSION,
+                created_at: SystemTime::now(),
+                entries: vec![entry.clone()],
+            };
+            let bytes = serde_json::to_vec(&bundle).unwrap();
+            assert!(
+                SettingsBundle::from_bytes(&bytes, None).is_err(),
+                "{entry:?} was accepted"
+            );
+            assert!(
+                bundle
+                    .import(target.path(), &stored, &BundleCategory::ALL, |_| {
+                        Ok(ConflictResolution::Overwrite)
+                    })
+                    .is_err()
+            );
+        }
+        assert_eq!(std::fs::read_dir(target.path()).unwrap().count(), 0);
+
+        let prompt = entry(BundleCategory::Prompts, "prompts/review.md");
+        assert_eq!(prompt.destination().unwrap(), prompt.path);
+    }
+}
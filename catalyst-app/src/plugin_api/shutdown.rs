@@ -0,0 +1,364 @@
+//! Shutdown Coordination
+//!
+//! This module runs the shutdown sequence: logs and stores are flushed
+//! first, then session state is persisted, then MCP servers are stopped and
+//! finally background jobs are cancelled. Every step has its own deadline
+//! and the whole sequence a global one, after which the process exits even
+//! if a step is stuck. Each step's outcome is logged and returned in a
+//! [`ShutdownReport`] so a slow or hung shutdown can be diagnosed later.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Phase of the shutdown sequence; steps run in phase order, then in the
+/// order they were added
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownPhase {
+    /// Flush append-only logs and stores, such as the audit log and usage
+    /// statistics, so nothing already recorded is lost
+    Flush,
+    /// Persist panel and session state
+    PersistState,
+    /// Stop MCP servers and other child processes
+    StopServers,
+    /// Cancel background jobs that have not started
+    CancelJobs,
+}
+
+/// Deadlines of the shutdown sequence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownSettings {
+    /// Time after which the process exits regardless of pending steps
+    pub global_timeout_ms: u64,
+    /// Time each step may take by default
+    pub step_timeout_ms: u64,
+    /// Time each MCP server gets to stop
+    pub server_stop_timeout_ms: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            global_timeout_ms: 10_000,
+            step_timeout_ms: 3_000,
+            server_stop_timeout_ms: 3_000,
+        }
+    }
+}
+
+impl ShutdownSettings {
+    pub fn global_timeout(&self) -> Duration {
+        Duration::from_millis(self.global_timeout_ms)
+    }
+
+    pub fn step_timeout(&self) -> Duration {
+        Duration::from_millis(self.step_timeout_ms)
+    }
+
+    pub fn server_stop_timeout(&self) -> Duration {
+        Duration::from_millis(self.server_stop_timeout_ms)
+    }
+}
+
+/// Outcome of a shutdown step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ShutdownOutcome {
+    Completed,
+    Failed {
+        error: String,
+    },
+    /// The step was still running at its deadline and was left behind
+    TimedOut,
+    /// The global deadline passed before the step could start
+    Skipped,
+}
+
+/// Record of one shutdown step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownStepReport {
+    pub name: String,
+    pub phase: ShutdownPhase,
+    pub outcome: ShutdownOutcome,
+    pub elapsed_ms: u64,
+}
+
+/// Record of a whole shutdown sequence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub steps: Vec<ShutdownStepReport>,
+    pub elapsed_ms: u64,
+    /// Whether the global deadline was reached
+    pub timed_out: bool,
+}
+
+impl ShutdownReport {
+    /// Check if every step completed
+    pub fn is_clean(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.outcome == ShutdownOutcome::Completed)
+    }
+
+    /// Write the report as JSON, e.g. next to the logs for post-mortems
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+type ShutdownAction = Box<dyn FnOnce() -> Result<()> + Send>;
+
+struct ShutdownStep {
+    name: String,
+    phase: ShutdownPhase,
+    timeout: Duration,
+    action: ShutdownAction,
+}
+
+/// Ordered shutdown sequence with per-step and global deadlines
+pub struct ShutdownCoordinator {
+    settings: ShutdownSettings,
+    steps: Vec<ShutdownStep>,
+}
+
+impl ShutdownCoordinator {
+    /// Create an empty sequence
+    pub fn new(settings: ShutdownSettings) -> Self {
+        Self {
+            settings,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Add a step with the default step deadline
+    pub fn add_step(
+        &mut self,
+        phase: ShutdownPhase,
+        name: impl Into<String>,
+        action: impl FnOnce() -> Result<()> + Send + 'static,
+    ) -> &mut Self {
+        let timeout = self.settings.step_timeout();
+        self.add_step_with_timeout(phase, name, timeout, action)
+    }
+
+    /// Add a step with its own deadline
+    pub fn add_step_with_timeout(
+        &mut self,
+        phase: ShutdownPhase,
+        name: impl Into<String>,
+        timeout: Duration,
+        action: impl FnOnce() -> Result<()> + Send + 'static,
+    ) -> &mut Self {
+        self.steps.push(ShutdownStep {
+            name: name.into(),
+            phase,
+            timeout,
+            action: Box::new(action),
+        });
+        self
+    }
+
+    /// Run the steps in order
+    ///
+    /// Each step runs on its own thread so a step that misses its deadline
+    /// can be abandoned. Once the global deadline passes, the remaining
+    /// steps are skipped.
+    pub fn run(self) -> ShutdownReport {
+        let started = Instant::now();
+        let global_deadline = started + self.settings.global_timeout();
+        let mut steps = self.steps;
+        // Stable, so steps of the same phase keep the order they were added
+        steps.sort_by_key(|step| step.phase);
+
+        tracing::info!("Shutting down ({} steps)", steps.len());
+        let mut reports = Vec::with_capacity(steps.len());
+        for step in steps {
+            let step_started = Instant::now();
+            let remaining = global_deadline.saturating_duration_since(step_started);
+            let outcome = if remaining.is_zero() {
+                ShutdownOutcome::Skipped
+            } else {
+                run_step(&step.name, step.action, step.timeout.min(remaining))
+            };
+            let elapsed = step_started.elapsed();
+            match &outcome {
+                ShutdownOutcome::Completed => {
+                    tracing::info!(
+                        "Shutdown step '{}' done in {:?}",
+                        step.name,
+                        elapsed
+                    )
+                }
+                ShutdownOutcome::Failed { error } => {
+                    tracing::error!(
+                        "Shutdown step '{}' failed: {}",
+                        step.name,
+                        error
+                    )
+                }
+                ShutdownOutcome::TimedOut => {
+                    tracing::warn!("Shutdown step '{}' timed out", step.name)
+                }
+                ShutdownOutcome::Skipped => {
+                    tracing::warn!("Shutdown step '{}' skipped", step.name)
+                }
+            }
+            reports.push(ShutdownStepReport {
+                name: step.name,
+                phase: step.phase,
+                outcome,
+                elapsed_ms: elapsed.as_millis() as u64,
+            });
+        }
+
+        let report = ShutdownReport {
+            timed_out: Instant::now() >= global_deadline,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            steps: reports,
+        };
+        tracing::info!(
+            "Shutdown finished in {} ms{}",
+            report.elapsed_ms,
+            if report.is_clean() {
+                ""
+            } else {
+                " with problems"
+            }
+        );
+        report
+    }
+
+    /// Run the steps, then exit the process
+    ///
+    /// A watchdog forces the exit shortly after the global deadline in case
+    /// the sequence itself gets stuck. The report is written to
+    /// `report_path` first when given.
+    pub fn run_and_exit(self, report_path: Option<&Path>, exit_code: i32) -> ! {
+        let watchdog = self.settings.global_timeout() + Duration::from_secs(1);
+        let _ = std::thread::Builder::new()
+            .name("shutdown-watchdog".to_string())
+            .spawn(move || {
+                std::thread::sleep(watchdog);
+                tracing::error!("Shutdown did not finish in time, forcing exit");
+                std::process::exit(exit_code);
+            });
+
+        let report = self.run();
+        if let Some(path) = report_path {
+            if let Err(err) = report.write(path) {
+                tracing::error!("Failed to write the shutdown report: {err:#}");
+            }
+        }
+        std::process::exit(exit_code)
+    }
+}
+
+fn run_step(
+    name: &str,
+    action: ShutdownAction,
+    timeout: Duration,
+) -> ShutdownOutcome {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    let spawned = std::thread::Builder::new()
+        .name(format!("shutdown-{name}"))
+        .spawn(move || {
+            let _ = tx.send(action());
+        });
+    if let Err(err) = spawned {
+        return ShutdownOutcome::Failed {
+            error: err.to_string(),
+        };
+    }
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(())) => ShutdownOutcome::Completed,
+        Ok(Err(err)) => ShutdownOutcome::Failed {
+            error: format!("{err:#}"),
+        },
+        Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+            ShutdownOutcome::TimedOut
+        }
+        Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+            ShutdownOutcome::Failed {
+                error: "The step panicked".to_string(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_runs_steps_in_phase_order_within_deadlines() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut coordinator = ShutdownCoordinator::new(ShutdownSettings {
+            global_timeout_ms: 300,
+            step_timeout_ms: 100,
+            server_stop_timeout_ms: 100,
+        });
+        let record = |name: &'static str| {
+            let order = order.clone();
+            move || {
+                order.lock().push(name);
+                Ok(())
+            }
+        };
+        coordinator
+            .add_step(ShutdownPhase::CancelJobs, "jobs", record("jobs"))
+            .add_step(ShutdownPhase::Flush, "audit", record("audit"))
+            .add_step(ShutdownPhase::StopServers, "hung", || {
+                std::thread::sleep(Duration::from_secs(5));
+                Ok(())
+            })
+            .add_step(ShutdownPhase::Flush, "usage", || {
+                Err(anyhow::anyhow!("disk full"))
+            })
+            .add_step_with_timeout(
+                ShutdownPhase::StopServers,
+                "slow",
+                Duration::from_secs(5),
+                || {
+                    std::thread::sleep(Duration::from_secs(5));
+                    Ok(())
+                },
+            );
+
+        let report = coordinator.run();
+        let outcomes: Vec<_> = report
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("audit", ShutdownOutcome::Completed),
+                (
+                    "usage",
+                    ShutdownOutcome::Failed {
+                        error: "disk full".to_string()
+                    }
+                ),
+                ("hung", ShutdownOutcome::TimedOut),
+                ("slow", ShutdownOutcome::TimedOut),
+                ("jobs", ShutdownOutcome::Skipped),
+            ]
+        );
+        assert!(report.timed_out);
+        assert!(!report.is_clean());
+        assert_eq!(*order.lock(), vec!["audit"]);
+    }
+}
@@ -2,10 +2,31 @@
 //!
 //! This module defines the plugin interface for custom sidebar panels
 //! that can be added to Catalyst IDE.
+//!
+//! Registering a panel is cheap: it is only initialized and its view only
+//! created the first time it becomes visible, so hidden panels cost nothing
+//! at startup. Panels with long content should render it through
+//! [`virtual_list_view`] so only the visible rows are built.
 
 use anyhow::Result;
-use floem::View;
+use dashmap::{DashMap, mapref::entry::Entry};
+use floem::{
+    IntoView, View,
+    reactive::create_memo,
+    views::{
+        Decorators, VirtualVector, dyn_container, empty, label, scroll,
+        virtual_stack,
+    },
+};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::plugin_api::write_atomic;
 
 /// Trait that sidebar panel plugins must implement
 pub trait SidebarPanelPlugin: Send + Sync + 'static {
@@ -16,6 +37,8 @@ pub trait SidebarPanelPlugin: Send + Sync + 'static {
     fn panel_info(&self) -> SidebarPanelInfo;
 
     /// Create the panel view
+    ///
+    /// Called once, the first time the panel becomes visible.
     fn create_view(&self) -> Box<dyn View>;
 
     /// Handle panel activation
@@ -38,6 +61,83 @@ fn handle_command(
         &mut self,
         command: PanelCommand,
     ) -> Result<PanelCommandResult>;
+
+    /// Release caches and other memory that can be rebuilt on demand
+    fn on_low_memory(&mut self, _pressure: MemoryPressure) {}
+}
+
+/// How urgently memory should be released
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryPressure {
+    /// Drop caches that are cheap to rebuild
+    Moderate,
+    /// Drop everything not needed to show the current state
+    Critical,
+}
+
+/// Create a scrollable, read-only text view for panels that present their
+/// state as plain text
+pub fn text_panel_view(text: impl Fn() -> String + 'static) -> Box<dyn View> {
+    Box::new(scroll(label(text).style(|s| s.padding(10.0))).style(|s| s.size_full()))
+}
+
+/// Create a view of a long list that only builds the rows in view
+///
+/// All rows must be `row_height` tall. `key` identifies a row across
+/// updates so rows that stay in the list keep their view.
+pub fn virtual_list_view<T, K, V>(
+    items: impl Fn() -> im::Vector<T> + 'static,
+    key: impl Fn(&(usize, T)) -> K + 'static,
+    view: impl Fn((usize, T)) -> V + 'static,
+    row_height: f64,
+) -> Box<dyn View>
+where
+    T: Clone + 'static,
+    K: Eq + Hash + 'static,
+    V: IntoView + 'static,
+{
+    Box::new(
+        scroll(
+            virtual_stack(move || VirtualRows(items()), key, view)
+                .item_size_fixed(move || row_height)
+                .style(|s| s.width_full()),
+        )
+        .style(|s| s.size_full()),
+    )
+}
+
+/// Create a view of many lines of text, such as a log tail, that only
+/// builds the lines in view
+pub fn virtual_text_view(
+    lines: impl Fn() -> im::Vector<String> + 'static,
+    line_height: f64,
+) -> Box<dyn View> {
+    virtual_list_view(
+        lines,
+        |(index, _)| *index,
+        move |(_, line)| {
+            label(move || line.clone())
+                .style(move |s| s.height(line_height).padding_horiz(10.0))
+        },
+        line_height,
+    )
+}
+
+struct VirtualRows<T>(im::Vector<T>);
+
+impl<T: Clone + 'static> VirtualVector<(usize, T)> for VirtualRows<T> {
+    fn total_len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn slice(&mut self, range: Range<usize>) -> impl Iterator<Item = (usize, T)> {
+        let start = range.start;
+        self.0
+            .slice(range)
+            .into_iter()
+            .enumerate()
+            .map(move |(i, item)| (i + start, item))
+    }
 }
 
 /// Information about a sidebar panel plugin
@@ -77,73 +177,298 @@ pub struct PanelCommandResult {
     pub error: Option<String>,
 }
 
+/// Shared handle to a registered sidebar panel
+///
+/// Handles are cheap to clone, so the UI can keep the panel it shows
+/// without holding the registry.
+#[derive(Clone)]
+pub struct SidebarPanelHandle {
+    id: Arc<str>,
+    panel: Arc<RwLock<Box<dyn SidebarPanelPlugin>>>,
+    /// Whether the panel has been initialized, set on first visibility
+    instantiated: Arc<Mutex<bool>>,
+}
+
+impl SidebarPanelHandle {
+    /// Create a handle for a panel
+    pub fn new(id: &str, panel: Box<dyn SidebarPanelPlugin>) -> Self {
+        Self {
+            id: Arc::from(id),
+            panel: Arc::new(RwLock::new(panel)),
+            instantiated: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// Get the id the panel is registered under
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Lock the panel for reading its info, state or view
+    pub fn read(&self) -> RwLockReadGuard<'_, Box<dyn SidebarPanelPlugin>> {
+        self.panel.read()
+    }
+
+    /// Lock the panel for lifecycle events and commands
+    pub fn write(&self) -> RwLockWriteGuard<'_, Box<dyn SidebarPanelPlugin>> {
+        self.panel.write()
+    }
+
+    /// Check if the panel has been initialized
+    pub fn is_instantiated(&self) -> bool {
+        *self.instantiated.lock()
+    }
+
+    /// Initialize the panel if this hasn't happened yet
+    pub fn instantiate(&self) -> Result<()> {
+        let mut instantiated = self.instantiated.lock();
+        if !*instantiated {
+            tracing::debug!("Instantiating panel {}", self.id);
+            self.panel.write().initialize()?;
+            *instantiated = true;
+        }
+        Ok(())
+    }
+
+    /// Notify the panel that it was shown or hidden, initializing it the
+    /// first time it is shown
+    ///
+    /// Panels that were never shown are not notified when hidden.
+    pub fn set_visible(&self, visible: bool) -> Result<()> {
+        if visible {
+            self.instantiate()?;
+        } else if !self.is_instantiated() {
+            return Ok(());
+        }
+        self.panel.write().on_visibility_changed(visible)
+    }
+
+    /// Create the panel view, deferring `create_view` until `visible` first
+    /// returns true
+    ///
+    /// `visible` is tracked reactively; an empty view stands in for the
+    /// panel until then.
+    pub fn lazy_view(&self, visible: impl Fn() -> bool + 'static) -> Box<dyn View> {
+        let shown = create_memo(move |shown: Option<&bool>| {
+            shown.copied().unwrap_or(false) || visible()
+        });
+        let handle = self.clone();
+        Box::new(dyn_container(
+            move || shown.get(),
+            move |shown| {
+                if !shown {
+                    return Box::new(empty()) as Box<dyn View>;
+                }
+                if let Err(err) = handle.instantiate() {
+                    tracing::error!(
+                        "Failed to initialize panel {}: {err:#}",
+                        handle.id()
+                    );
+                    return Box::new(label(move || {
+                        format!("Failed to initialize panel: {err:#}")
+                    }));
+                }
+                handle.read().create_view()
+            },
+        ))
+    }
+}
+
 /// Registry for managing sidebar panels
+///
+/// Cloning the registry gives another view of the same panels.
+#[derive(Clone, Default)]
 pub struct SidebarPanelRegistry {
-    panels: std::collections::HashMap<String, Box<dyn SidebarPanelPlugin>>,
+    panels: Arc<DashMap<String, SidebarPanelHandle>>,
 }
 
 impl SidebarPanelRegistry {
     /// Create a new panel registry
     pub fn new() -> Self {
-        Self {
-            panels: std::collections::HashMap::new(),
-        }
+        Self::default()
     }
 
     /// Register a new sidebar panel
     pub fn register_panel(
-        &mut self,
+        &self,
         id: String,
         panel: Box<dyn SidebarPanelPlugin>,
-    ) -> Result<()> {
-        if self.panels.contains_key(&id) {
-            return Err(anyhow::anyhow!(
+    ) -> Result<SidebarPanelHandle> {
+        match self.panels.entry(id) {
+            Entry::Occupied(entry) => Err(anyhow::anyhow!(
                 "Panel with id '{}' is already registered",
-                id
-            ));
+                entry.key()
+            )),
+            Entry::Vacant(entry) => {
+                let handle = SidebarPanelHandle::new(entry.key(), panel);
+                entry.insert(handle.clone());
+                Ok(handle)
+            }
         }
-
-        self.panels.insert(id, panel);
-        Ok(())
     }
 
     /// Unregister a sidebar panel
-    pub fn unregister_panel(&mut self, id: &str) -> Result<()> {
-        self.panels.remove(id).ok_or_else(|| {
-            anyhow::anyhow!("Panel with id '{}' is not registered", id)
-        })?;
-        Ok(())
+    pub fn unregister_panel(&self, id: &str) -> Result<SidebarPanelHandle> {
+        self.panels
+            .remove(id)
+            .map(|(_, handle)| handle)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Panel with id '{}' is not registered", id)
+            })
     }
 
     /// Get a panel by id
-    pub fn get_panel(&self, id: &str) -> Option<&dyn SidebarPanelPlugin> {
-        self.panels.get(id).map(|panel| panel.as_ref())
+    pub fn get_panel(&self, id: &str) -> Option<SidebarPanelHandle> {
+        self.panels.get(id).map(|handle| handle.clone())
     }
 
-    /// Get a mutable panel by id
-    pub fn get_panel_mut(
-        &mut self,
-        id: &str,
-    ) -> Option<&mut dyn SidebarPanelPlugin> {
-        self.panels.get_mut(id).map(|panel| panel.as_mut())
+    /// Get handles to all registered panels
+    pub fn handles(&self) -> Vec<SidebarPanelHandle> {
+        self.panels
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
     }
 
     /// Get all registered panel IDs
     pub fn get_panel_ids(&self) -> Vec<String> {
-        self.panels.keys().cloned().collect()
+        self.panels
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Ask instantiated panels to release memory
+    pub fn notify_low_memory(&self, pressure: MemoryPressure) {
+        for handle in self.handles() {
+            if handle.is_instantiated() {
+                handle.write().on_low_memory(pressure);
+            }
+        }
+    }
+
+    /// Write the state of instantiated panels to a JSON file, keyed by
+    /// panel id
+    pub fn save_states(&self, path: &Path) -> Result<()> {
+        let states: BTreeMap<String, serde_json::Value> = self
+            .handles()
+            .into_iter()
+            .filter(|handle| handle.is_instantiated())
+            .map(|handle| (handle.id().to_string(), handle.read().get_state()))
+            .collect();
+        write_atomic(path, &serde_json::to_vec_pretty(&states)?)
     }
 
     /// Get panel info for all registered panels
     pub fn get_all_panel_info(&self) -> Vec<SidebarPanelInfo> {
-        self.panels
-            .values()
-            .map(|panel| panel.panel_info())
+        self.handles()
+            .iter()
+            .map(|handle| handle.read().panel_info())
             .collect()
     }
 }
 
-impl Default for SidebarPanelRegistry {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct CountingPanel {
+        initialized: usize,
+        visibility_changes: Vec<bool>,
+        cache: Vec<u8>,
+    }
+
+    impl SidebarPanelPlugin for CountingPanel {
+        fn initialize(&mut self) -> Result<()> {
+            self.initialized += 1;
+            self.cache = vec![0; 1024];
+            Ok(())
+        }
+
+        fn panel_info(&self) -> SidebarPanelInfo {
+            SidebarPanelInfo {
+                id: "counting".to_string(),
+                name: "Counting".to_string(),
+                description: String::new(),
+                icon: None,
+                position: SidebarPosition::Left,
+                default_visible: false,
+                resizable: true,
+                minimum_width: None,
+                maximum_width: None,
+            }
+        }
+
+        fn create_view(&self) -> Box<dyn View> {
+            text_panel_view(String::new)
+        }
+
+        fn on_activate(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_deactivate(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_visibility_changed(&mut self, visible: bool) -> Result<()> {
+            self.visibility_changes.push(visible);
+            Ok(())
+        }
+
+        fn get_state(&self) -> serde_json::Value {
+            serde_json::json!({
+                "initialized": self.initialized,
+                "visibility_changes": self.visibility_changes,
+                "cache": self.cache.len(),
+            })
+        }
+
+        fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_command(
+            &mut self,
+            _command: PanelCommand,
+        ) -> Result<PanelCommandResult> {
+            Ok(PanelCommandResult {
+                success: true,
+                result: Some(self.get_state()),
+                error: None,
+            })
+        }
+
+        fn on_low_memory(&mut self, _pressure: MemoryPressure) {
+            self.cache = Vec::new();
+        }
+    }
+
+    #[test]
+    fn test_panels_initialize_on_first_visibility() {
+        let registry = SidebarPanelRegistry::new();
+        let handle = registry
+            .register_panel("counting".to_string(), Box::<CountingPanel>::default())
+            .unwrap();
+
+        handle.set_visible(false).unwrap();
+        registry.notify_low_memory(MemoryPressure::Moderate);
+        assert!(!handle.is_instantiated());
+        assert_eq!(handle.read().get_state()["initialized"], 0);
+
+        handle.set_visible(true).unwrap();
+        handle.set_visible(false).unwrap();
+        handle.set_visible(true).unwrap();
+        let state = handle.read().get_state();
+        assert_eq!(state["initialized"], 1);
+        assert_eq!(
+            state["visibility_changes"],
+            serde_json::json!([true, false, true])
+        );
+        assert_eq!(state["cache"], 1024);
+
+        registry.notify_low_memory(MemoryPressure::Critical);
+        assert_eq!(handle.read().get_state()["cache"], 0);
     }
 }
@@ -0,0 +1,221 @@
+//! Single Flight
+//!
+//! This module coalesces identical concurrent requests: while a fetch for a
+//! key is running, further callers asking for the same key wait for it and
+//! share its result instead of starting their own. Nothing is cached once
+//! the fetch completes, so callers never see data older than the request
+//! they joined. Bursty agent turns, where several panels and providers ask
+//! for the same file, status or embedding at once, then cost one fetch.
+
+use anyhow::Result;
+use parking_lot::{Condvar, Mutex};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::plugin_api::{Embedding, EmbeddingProvider, EmbeddingProviderInfo};
+
+/// Outcome shared with the callers that joined a fetch; errors are shared
+/// by message since `anyhow::Error` can't be cloned
+type SharedResult<V> = std::result::Result<V, String>;
+
+struct Call<V> {
+    result: Mutex<Option<SharedResult<V>>>,
+    done: Condvar,
+}
+
+/// Coalesces concurrent fetches of the same key
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Arc<Call<V>>>>,
+    coalesced: AtomicU64,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    /// Create a new single-flight group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `fetch` for `key`, or wait for the fetch already running for it
+    pub fn run(&self, key: K, fetch: impl FnOnce() -> Result<V>) -> Result<V> {
+        let (call, leader) = {
+            let mut calls = self.calls.lock();
+            match calls.get(&key) {
+                Some(call) => (call.clone(), false),
+                None => {
+                    let call = Arc::new(Call {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    calls.insert(key.clone(), call.clone());
+                    (call, true)
+                }
+            }
+        };
+
+        if !leader {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            let mut result = call.result.lock();
+            while result.is_none() {
+                call.done.wait(&mut result);
+            }
+            return result.clone().unwrap().map_err(|err| anyhow::anyhow!(err));
+        }
+
+        // Publishes a result even if `fetch` panics, so waiters never hang
+        let mut guard = LeaderGuard {
+            flight: self,
+            key: Some(key),
+            call: &call,
+        };
+        let result = fetch();
+        guard.finish(match &result {
+            Ok(value) => Ok(value.clone()),
+            Err(err) => Err(format!("{err:#}")),
+        });
+        result
+    }
+
+    /// Get the number of requests that were served by joining another
+    /// request's fetch
+    pub fn coalesced_count(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+struct LeaderGuard<'a, K: Hash + Eq, V> {
+    flight: &'a SingleFlight<K, V>,
+    key: Option<K>,
+    call: &'a Call<V>,
+}
+
+impl<K: Hash + Eq, V> LeaderGuard<'_, K, V> {
+    fn finish(&mut self, result: SharedResult<V>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        // Remove the key first so callers arriving from now on start a new
+        // fetch rather than joining a finished one
+        self.flight.calls.lock().remove(&key);
+        *self.call.result.lock() = Some(result);
+        self.call.done.notify_all();
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for LeaderGuard<'_, K, V> {
+    fn drop(&mut self) {
+        self.finish(Err("The shared request was abandoned".to_string()));
+    }
+}
+
+/// Reads files, sharing reads of the same path that overlap in time
+#[derive(Default)]
+pub struct SharedFileReader {
+    flight: SingleFlight<PathBuf, Arc<str>>,
+}
+
+impl SharedFileReader {
+    /// Create a new shared file reader
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a file as text
+    pub fn read_to_string(&self, path: &Path) -> Result<Arc<str>> {
+        self.flight.run(path.to_path_buf(), || {
+            Ok(Arc::from(std::fs::read_to_string(path)?))
+        })
+    }
+}
+
+/// Embedding provider wrapper that shares identical in-flight batches
+pub struct SingleFlightEmbeddingProvider {
+    inner: Arc<dyn EmbeddingProvider>,
+    flight: SingleFlight<[u8; 32], Arc<Vec<Embedding>>>,
+}
+
+impl SingleFlightEmbeddingProvider {
+    /// Wrap a provider
+    pub fn new(inner: Arc<dyn EmbeddingProvider>) -> Self {
+        Self {
+            inner,
+            flight: SingleFlight::new(),
+        }
+    }
+
+    /// Get the number of batches served by another caller's request
+    pub fn coalesced_count(&self) -> u64 {
+        self.flight.coalesced_count()
+    }
+}
+
+impl EmbeddingProvider for SingleFlightEmbeddingProvider {
+    fn provider_info(&self) -> EmbeddingProviderInfo {
+        self.inner.provider_info()
+    }
+
+    fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>> {
+        // Hash the inputs instead of keeping a copy of every chunk as a key
+        let mut hasher = Sha256::new();
+        for input in inputs {
+            hasher.update((input.len() as u64).to_le_bytes());
+            hasher.update(input.as_bytes());
+        }
+        let key: [u8; 32] = hasher.finalize().into();
+        let embeddings = self
+            .flight
+            .run(key, || Ok(Arc::new(self.inner.embed_batch(inputs)?)))?;
+        Ok(embeddings.as_ref().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    #[test]
+    fn test_concurrent_identical_requests_share_one_fetch() {
+        let flight = Arc::new(SingleFlight::<String, usize>::new());
+        let fetches = Arc::new(AtomicU64::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let flight = flight.clone();
+                let fetches = fetches.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    flight.run("status".to_string(), || {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(100));
+                        Ok(42)
+                    })
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().unwrap(), 42);
+        }
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(flight.coalesced_count(), 7);
+        // Completed fetches are not cached
+        flight.run("status".to_string(), || Ok(0)).unwrap();
+        assert_eq!(flight.run("status".to_string(), || Ok(1)).unwrap(), 1);
+    }
+}
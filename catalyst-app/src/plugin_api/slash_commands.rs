@@ -0,0 +1,644 @@
+//! Slash Commands
+//!
+//! This module resolves `/commands` typed in the chat input. Each command
+//! declares its arguments and maps to a prompt template, sent as a plain
+//! question, or to an agent task run with tools from the listed servers.
+//! Diff prompts are asked with the diff of a range of changes attached.
+//! `/test`, `/explain`, `/commit`, `/review`, `/changes` and `/changelog`
+//! are built in; plugins add their own through the registry held by the
+//! plugin manager.
+
+use anyhow::Result;
+use dashmap::{DashMap, mapref::entry::Entry};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    DiffRange, MentionCompletion, MentionCompletionSources, complete_mention,
+};
+
+/// Argument of diff prompts holding the range of changes
+pub const DIFF_RANGE_ARGUMENT: &str = "range";
+
+/// Id of the source of the built-in commands
+pub const BUILTIN_COMMAND_SOURCE: &str = "builtin";
+
+/// What an argument accepts, used for validation and completion
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "values", rename_all = "snake_case")]
+pub enum SlashArgumentKind {
+    Text,
+    /// A workspace path
+    File,
+    /// A symbol name
+    Symbol,
+    /// One of a fixed set of values
+    Choice(Vec<String>),
+}
+
+/// Declared argument of a command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashArgument {
+    pub name: String,
+    pub description: String,
+    pub kind: SlashArgumentKind,
+    pub required: bool,
+    /// Used in the template when an optional argument is left out
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// What a command does
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SlashAction {
+    /// Ask the rendered prompt without tools
+    Prompt { template: String },
+    /// Run the rendered prompt as an agent task with the tools of the
+    /// listed servers, or of all servers when the list is empty
+    AgentTask {
+        template: String,
+        #[serde(default)]
+        servers: Vec<String>,
+    },
+    /// Ask the rendered prompt without tools, with the diff of the range
+    /// given in the `range` argument attached
+    DiffPrompt { template: String },
+}
+
+/// A command available in the chat input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashCommand {
+    /// Name typed after the slash
+    pub name: String,
+    pub description: String,
+    /// Arguments in order; a trailing text argument takes the rest of the
+    /// input
+    #[serde(default)]
+    pub arguments: Vec<SlashArgument>,
+    pub action: SlashAction,
+    /// Id of the plugin that registered the command
+    #[serde(default)]
+    pub source: String,
+}
+
+impl SlashCommand {
+    /// Get the usage line, e.g. `/review [target]`
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for argument in &self.arguments {
+            if argument.required {
+                usage.push_str(&format!(" <{}>", argument.name));
+            } else {
+                usage.push_str(&format!(" [{}]", argument.name));
+            }
+        }
+        usage
+    }
+
+    /// Get the JSON schema of the arguments
+    pub fn arguments_schema(&self) -> serde_json::Value {
+        let properties: serde_json::Map<String, serde_json::Value> = self
+            .arguments
+            .iter()
+            .map(|argument| {
+                let mut schema = serde_json::json!({
+                    "type": "string",
+                    "description": argument.description,
+                });
+                if let SlashArgumentKind::Choice(values) = &argument.kind {
+                    schema["enum"] = serde_json::json!(values);
+                }
+                (argument.name.clone(), schema)
+            })
+            .collect();
+        let required: Vec<&str> = self
+            .arguments
+            .iter()
+            .filter(|argument| argument.required)
+            .map(|argument| argument.name.as_str())
+            .collect();
+        serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Match arguments to the declared ones and render the action
+    pub fn invoke(&self, input: &str) -> Result<ResolvedSlashCommand> {
+        let values = self.parse_arguments(input)?;
+        let (template, servers, diff) = match &self.action {
+            SlashAction::Prompt { template } => (template, None, None),
+            SlashAction::AgentTask { template, servers } => {
+                (template, Some(servers.clone()), None)
+            }
+            SlashAction::DiffPrompt { template } => {
+                let range = values
+                    .get(DIFF_RANGE_ARGUMENT)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                (template, None, Some(DiffRange::parse(range)?))
+            }
+        };
+        let mut prompt = template.clone();
+        for argument in &self.arguments {
+            let value = values
+                .get(&argument.name)
+                .or(argument.default.as_ref())
+                .map(String::as_str)
+                .unwrap_or_default();
+            prompt = prompt.replace(&format!("{{{{{}}}}}", argument.name), value);
+        }
+        Ok(ResolvedSlashCommand {
+            name: self.name.clone(),
+            prompt: prompt.trim().to_string(),
+            agent_servers: servers,
+            diff,
+            arguments: values,
+        })
+    }
+
+    fn parse_arguments(&self, input: &str) -> Result<HashMap<String, String>> {
+        let words = split_arguments(input);
+        let mut values = HashMap::new();
+        let mut words = words.into_iter();
+        for (i, argument) in self.arguments.iter().enumerate() {
+            let is_last = i + 1 == self.arguments.len();
+            let value = if is_last && argument.kind == SlashArgumentKind::Text {
+                let rest: Vec<String> =
+                    words.by_ref().map(|(_, word)| word).collect();
+                (!rest.is_empty()).then(|| rest.join(" "))
+            } else {
+                words.next().map(|(_, word)| word)
+            };
+            match value {
+                Some(value) => {
+                    if let SlashArgumentKind::Choice(choices) = &argument.kind {
+                        if !choices.contains(&value) {
+                            return Err(anyhow::anyhow!(
+                                "'{}' is not a valid {}; expected one of {}",
+                                value,
+                                argument.name,
+                                choices.join(", ")
+                            ));
+                        }
+                    }
+                    values.insert(argument.name.clone(), value);
+                }
+                None if argument.required => {
+                    return Err(anyhow::anyhow!(
+                        "Missing {}; usage: {}",
+                        argument.name,
+                        self.usage()
+                    ));
+                }
+                None => {}
+            }
+        }
+        if let Some((_, extra)) = words.next() {
+            return Err(anyhow::anyhow!(
+                "Unexpected argument '{}'; usage: {}",
+                extra,
+                self.usage()
+            ));
+        }
+        Ok(values)
+    }
+}
+
+/// A command with its arguments filled in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedSlashCommand {
+    pub name: String,
+    /// The rendered template
+    pub prompt: String,
+    /// Servers whose tools the agent task may use, or `None` for a plain
+    /// prompt
+    pub agent_servers: Option<Vec<String>>,
+    /// Changes whose diff is attached to the prompt
+    #[serde(default)]
+    pub diff: Option<DiffRange>,
+    pub arguments: HashMap<String, String>,
+}
+
+/// Split arguments at whitespace, keeping double-quoted text together,
+/// with the byte range of each
+fn split_arguments(input: &str) -> Vec<(Range<usize>, String)> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut quoted = false;
+    for (i, c) in input.char_indices() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert((i, String::new()));
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some((start, word)) = current.take() {
+                    words.push((start..i, word));
+                }
+            }
+            c => current.get_or_insert((i, String::new())).1.push(c),
+        }
+    }
+    if let Some((start, word)) = current {
+        words.push((start..input.len(), word));
+    }
+    words
+}
+
+/// Split a chat input starting with a slash into the command name and the
+/// rest
+pub fn parse_slash_input(input: &str) -> Option<(&str, &str)> {
+    let input = input.trim_start().strip_prefix('/')?;
+    let end = input.find(char::is_whitespace).unwrap_or(input.len());
+    let name = &input[..end];
+    (!name.is_empty()).then(|| (name, input[end..].trim_start()))
+}
+
+/// Registry of the commands available in the chat input
+///
+/// Cloning the registry gives another view of the same commands.
+#[derive(Clone, Default)]
+pub struct SlashCommandRegistry {
+    commands: Arc<DashMap<String, SlashCommand>>,
+}
+
+impl SlashCommandRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in commands
+    pub fn with_builtin_commands() -> Self {
+        let registry = Self::new();
+        for command in builtin_commands() {
+            let _ = registry.register_command(command);
+        }
+        registry
+    }
+
+    /// Register a command
+    pub fn register_command(&self, command: SlashCommand) -> Result<()> {
+        if command.name.is_empty() || command.name.contains(char::is_whitespace) {
+            return Err(anyhow::anyhow!(
+                "'{}' is not a valid command name",
+                command.name
+            ));
+        }
+        match self.commands.entry(command.name.clone()) {
+            Entry::Occupied(entry) => Err(anyhow::anyhow!(
+                "Command '/{}' is already registered by '{}'",
+                entry.key(),
+                entry.get().source
+            )),
+            Entry::Vacant(entry) => {
+                entry.insert(command);
+                Ok(())
+            }
+        }
+    }
+
+    /// Unregister a command
+    pub fn unregister_command(&self, name: &str) -> Result<SlashCommand> {
+        self.commands
+            .remove(name)
+            .map(|(_, command)| command)
+            .ok_or_else(|| anyhow::anyhow!("Command '/{}' is not registered", name))
+    }
+
+    /// Unregister every command of a plugin, e.g. when it is disabled
+    pub fn unregister_source(&self, source: &str) {
+        self.commands.retain(|_, command| command.source != source);
+    }
+
+    pub fn get_command(&self, name: &str) -> Option<SlashCommand> {
+        self.commands.get(name).map(|command| command.clone())
+    }
+
+    /// Get all commands, sorted by name
+    pub fn commands(&self) -> Vec<SlashCommand> {
+        let mut commands: Vec<SlashCommand> = self
+            .commands
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        commands.sort_by(|a, b| a.name.cmp(&b.name));
+        commands
+    }
+
+    /// Resolve a chat input, or return `None` if it is not a command
+    pub fn resolve(&self, input: &str) -> Option<Result<ResolvedSlashCommand>> {
+        let (name, rest) = parse_slash_input(input)?;
+        Some(match self.get_command(name) {
+            Some(command) => command.invoke(rest),
+            None => Err(anyhow::anyhow!("Unknown command '/{}'", name)),
+        })
+    }
+
+    /// Complete the command name or argument being typed at the end of
+    /// the input
+    pub fn complete(
+        &self,
+        input: &str,
+        sources: &MentionCompletionSources,
+        limit: usize,
+    ) -> Vec<MentionCompletion> {
+        let offset = input.len() - input.trim_start().len();
+        let Some(typed) = input.trim_start().strip_prefix('/') else {
+            return Vec::new();
+        };
+        let Some(name_end) = typed.find(char::is_whitespace) else {
+            return self
+                .commands()
+                .into_iter()
+                .filter(|command| command.name.starts_with(typed))
+                .take(limit)
+                .map(|command| MentionCompletion {
+                    label: command.usage(),
+                    detail: Some(command.description.clone()),
+                    insert_text: format!("/{} ", command.name),
+                    replace: offset..input.len(),
+                })
+                .collect();
+        };
+        let Some(command) = self.get_command(&typed[..name_end]) else {
+            return Vec::new();
+        };
+
+        // The argument being typed is the last word, or a new one after
+        // trailing whitespace
+        let args_start = offset + 1 + name_end;
+        let mut words = split_arguments(&input[args_start..]);
+        let current = match words.last() {
+            Some((range, _)) if args_start + range.end == input.len() => words
+                .pop()
+                .map(|(range, word)| (args_start + range.start..input.len(), word)),
+            _ => None,
+        };
+        let (replace, typed) =
+            current.unwrap_or((input.len()..input.len(), String::new()));
+        let Some(argument) = command.arguments.get(words.len()) else {
+            return Vec::new();
+        };
+        let completion = |label: String, detail: Option<String>| MentionCompletion {
+            insert_text: format!("{label} "),
+            label,
+            detail,
+            replace: replace.clone(),
+        };
+        match &argument.kind {
+            SlashArgumentKind::Choice(values) => values
+                .iter()
+                .filter(|value| value.starts_with(&typed))
+                .take(limit)
+                .map(|value| completion(value.clone(), None))
+                .collect(),
+            SlashArgumentKind::File => {
+                let mention = format!("@file:{typed}");
+                complete_mention(&mention, mention.len(), sources, limit)
+                    .into_iter()
+                    .map(|mention| completion(mention.label, mention.detail))
+                    .collect()
+            }
+            SlashArgumentKind::Symbol if !typed.is_empty() => sources
+                .symbols
+                .complete(&typed, limit)
+                .into_iter()
+                .map(|definition| {
+                    completion(
+                        definition.name.clone(),
+                        Some(format!(
+                            "{}:{}",
+                            definition.path.display(),
+                            definition.line
+                        )),
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn builtin_commands() -> Vec<SlashCommand> {
+    let target =
+        |description: &str, kind: SlashArgumentKind, default: &str| SlashArgument {
+            name: "target".to_string(),
+            description: description.to_string(),
+            kind,
+            required: false,
+            default: Some(default.to_string()),
+        };
+    let range = |description: &str| SlashArgument {
+        name: DIFF_RANGE_ARGUMENT.to_string(),
+        description: format!(
+            "Branch to compare with, commit range such as v1.0..v1.1, or \
+             'staged'; defaults to {}",
+            description
+        ),
+        kind: SlashArgumentKind::Text,
+        required: false,
+        default: None,
+    };
+    let command = |name: &str, description: &str, arguments, action| SlashCommand {
+        name: name.to_string(),
+        description: description.to_string(),
+        arguments,
+        action,
+        source: BUILTIN_COMMAND_SOURCE.to_string(),
+    };
+    vec![
+        command(
+            "test",
+            "Run the tests and fix failures",
+            vec![target(
+                "File whose tests to run",
+                SlashArgumentKind::File,
+                "the whole project",
+            )],
+            SlashAction::AgentTask {
+                template: "Run the tests for {{target}}. If any fail, find the \
+                           cause and fix it, then run them again."
+                    .to_string(),
+                servers: Vec::new(),
+            },
+        ),
+        command(
+            "explain",
+            "Explain code",
+            vec![target(
+                "Symbol or question to explain",
+                SlashArgumentKind::Text,
+                "the selected code",
+            )],
+            SlashAction::Prompt {
+                template: "Explain {{target}}: what it does, how it works and \
+                           anything surprising about it."
+                    .to_string(),
+            },
+        ),
+        command(
+            "commit",
+            "Commit the staged changes with a generated message",
+            vec![SlashArgument {
+                name: "hint".to_string(),
+                description: "What the change is about".to_string(),
+                kind: SlashArgumentKind::Text,
+                required: false,
+                default: None,
+            }],
+            SlashAction::AgentTask {
+                template: "Look at the staged changes, write a concise commit \
+                           message following the repository's conventions and \
+                           commit them. {{hint}}"
+                    .to_string(),
+                servers: vec!["git".to_string()],
+            },
+        ),
+        command(
+            "review",
+            "Review changes for bugs and style",
+            vec![target(
+                "File to review",
+                SlashArgumentKind::File,
+                "the uncommitted changes",
+            )],
+            SlashAction::AgentTask {
+                template: "Review {{target}}. Point out bugs, risky changes \
+                           and style problems, most important first."
+                    .to_string(),
+                servers: vec!["git".to_string()],
+            },
+        ),
+        command(
+            "changes",
+            "Summarize changes",
+            vec![range("the uncommitted changes")],
+            SlashAction::DiffPrompt {
+                template: "Summarize these changes for a reviewer: what changed \
+                           and why, grouped by area, and anything that looks \
+                           risky or unfinished."
+                    .to_string(),
+            },
+        ),
+        command(
+            "changelog",
+            "Write a changelog entry for changes",
+            vec![range("the uncommitted changes")],
+            SlashAction::DiffPrompt {
+                template: "Write a changelog entry for these changes in the \
+                           style of the project's changelog. Keep to changes \
+                           users notice, grouped as Added, Changed and Fixed."
+                    .to_string(),
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::SymbolGraph;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_resolves_and_completes_commands() {
+        let registry = SlashCommandRegistry::with_builtin_commands();
+        registry
+            .register_command(SlashCommand {
+                name: "bench".to_string(),
+                description: "Run a benchmark".to_string(),
+                arguments: vec![
+                    SlashArgument {
+                        name: "profile".to_string(),
+                        description: String::new(),
+                        kind: SlashArgumentKind::Choice(vec![
+                            "quick".to_string(),
+                            "full".to_string(),
+                        ]),
+                        required: true,
+                        default: None,
+                    },
+                    SlashArgument {
+                        name: "filter".to_string(),
+                        description: String::new(),
+                        kind: SlashArgumentKind::Text,
+                        required: false,
+                        default: None,
+                    },
+                ],
+                action: SlashAction::Prompt {
+                    template: "Run the {{profile}} benchmark for {{filter}}"
+                        .to_string(),
+                },
+                source: "bench-plugin".to_string(),
+            })
+            .unwrap();
+        assert!(
+            registry
+                .register_command(registry.get_command("test").unwrap())
+                .is_err()
+        );
+
+        let resolved = registry.resolve("/bench full \"parse json\" fast").unwrap();
+        let resolved = resolved.unwrap();
+        assert_eq!(
+            resolved.prompt,
+            "Run the full benchmark for parse json fast"
+        );
+        assert_eq!(resolved.agent_servers, None);
+        assert!(registry.resolve("/bench slow").unwrap().is_err());
+        assert!(registry.resolve("/bench").unwrap().is_err());
+        assert!(registry.resolve("/nope").unwrap().is_err());
+        assert!(registry.resolve("not a command").is_none());
+
+        let review = registry.resolve("/review src/lib.rs").unwrap().unwrap();
+        assert_eq!(
+            review.prompt,
+            "Review src/lib.rs. Point out bugs, risky changes and style \
+             problems, most important first."
+        );
+        assert_eq!(review.agent_servers, Some(vec!["git".to_string()]));
+        let changelog = registry.resolve("/changelog main").unwrap().unwrap();
+        assert_eq!(
+            changelog.diff,
+            Some(DiffRange::Branch {
+                base: "main".to_string()
+            })
+        );
+        assert!(registry.resolve("/review a b").unwrap().is_err());
+        assert_eq!(
+            registry.get_command("bench").unwrap().arguments_schema()["required"],
+            serde_json::json!(["profile"])
+        );
+
+        let files = vec![PathBuf::from("src/lib.rs")];
+        let symbols = SymbolGraph::new();
+        let sources = MentionCompletionSources {
+            files: &files,
+            symbols: &symbols,
+            servers: &[],
+            untitled: &[],
+        };
+        let complete = |input: &str| -> Vec<String> {
+            registry
+                .complete(input, &sources, 10)
+                .into_iter()
+                .map(|completion| completion.insert_text)
+                .collect()
+        };
+        assert_eq!(complete("/b"), vec!["/bench "]);
+        assert_eq!(complete("/bench f"), vec!["full "]);
+        assert_eq!(complete("/review li"), vec!["src/lib.rs "]);
+        assert!(complete("/bench full x").is_empty());
+
+        registry.unregister_source("bench-plugin");
+        assert!(registry.get_command("bench").is_none());
+        assert_eq!(registry.commands().len(), 6);
+    }
+}
@@ -0,0 +1,579 @@
+//! Sparse Indexing
+//!
+//! Indexing every file of a repository with a million files takes minutes
+//! and more memory than the editor should use. In sparse mode the
+//! [`SparseIndex`] indexes only the configured focus paths and the files
+//! the user worked on recently at startup, and expands the index on demand:
+//! when the user opens a file outside of it, or when the assistant asks for
+//! another area with the `expand_index` tool.
+//!
+//! The index holds at most [`SparseIndexConfig::max_files`] files. Past
+//! that, the areas expanded least recently are dropped again; the focus
+//! paths are never dropped.
+
+use anyhow::{Result, anyhow};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::plugin_api::{
+    AllocTag, AttentionTracker, BuiltinToolProvider, CatalystIgnore, ContextScope,
+    ToolCall, ToolDefinition, ToolEffect, TrigramIndex, WorkspaceCrawler,
+    WorkspaceSandbox, alloc_scope, chunk_text, normalize_path,
+};
+
+/// Name of the tool the assistant uses to index another area
+pub const EXPAND_INDEX_TOOL_NAME: &str = "expand_index";
+
+/// Lines per indexed chunk
+const SPARSE_CHUNK_LINES: usize = 40;
+
+/// What a sparse index covers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SparseIndexConfig {
+    /// Directories or files always indexed, relative to the root
+    pub focus_paths: Vec<PathBuf>,
+    /// Files with the most attention indexed at startup
+    pub recent_files: usize,
+    /// Files indexed at most
+    pub max_files: usize,
+    /// Files larger than this many bytes are skipped
+    pub max_file_size: u64,
+}
+
+impl Default for SparseIndexConfig {
+    fn default() -> Self {
+        Self {
+            focus_paths: Vec::new(),
+            recent_files: 50,
+            max_files: 50_000,
+            max_file_size: 1024 * 1024,
+        }
+    }
+}
+
+/// A file or directory indexed with everything below it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedArea {
+    /// Absolute path of the area
+    pub path: PathBuf,
+    /// Whether the area is a focus path, which is never dropped
+    pub focus: bool,
+    /// Files indexed for the area
+    pub files: usize,
+}
+
+/// Result of expanding the index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseExpansion {
+    /// Files indexed
+    pub indexed: usize,
+    /// Whether files were left out because the area alone exceeds the budget
+    pub truncated: bool,
+    /// Areas dropped to stay within the budget
+    pub dropped: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct Area {
+    path: PathBuf,
+    focus: bool,
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct SparseState {
+    /// Indexed areas, the one used least recently first
+    areas: Vec<Area>,
+    indexed: HashSet<PathBuf>,
+}
+
+/// Index covering the parts of a workspace in use
+pub struct SparseIndex {
+    ignore: Arc<CatalystIgnore>,
+    sandbox: WorkspaceSandbox,
+    index: Arc<TrigramIndex>,
+    config: SparseIndexConfig,
+    state: Mutex<SparseState>,
+}
+
+impl SparseIndex {
+    /// Create an empty sparse index of the workspace the ignore rules
+    /// belong to, adding the files it indexes to `index`
+    pub fn new(
+        ignore: Arc<CatalystIgnore>,
+        index: Arc<TrigramIndex>,
+        config: SparseIndexConfig,
+    ) -> Self {
+        Self {
+            sandbox: WorkspaceSandbox::new(ignore.clone()),
+            ignore,
+            index,
+            config,
+            state: Mutex::new(SparseState::default()),
+        }
+    }
+
+    /// Index the focus paths and the files with the most attention
+    ///
+    /// Focus paths that can't be indexed are skipped with a warning.
+    pub fn load_initial(&self, attention: &AttentionTracker) -> SparseExpansion {
+        let mut loaded = SparseExpansion::default();
+        let focus = self.config.focus_paths.iter().map(|path| (path, true));
+        let recent = attention.recent_files(self.config.recent_files);
+        for (path, is_focus) in focus.chain(recent.iter().map(|path| (path, false)))
+        {
+            match self.expand_area(path, is_focus) {
+                Ok(expansion) => {
+                    loaded.indexed += expansion.indexed;
+                    loaded.truncated |= expansion.truncated;
+                    loaded.dropped.extend(expansion.dropped);
+                }
+                Err(err) if is_focus => {
+                    tracing::warn!("Skipping focus path of the index: {:#}", err);
+                }
+                Err(err) => {
+                    tracing::debug!("Skipping recent file: {:#}", err);
+                }
+            }
+        }
+        loaded
+    }
+
+    /// Index a file or directory, with everything below it, if it isn't
+    /// indexed yet
+    ///
+    /// Call this when the user opens a file, or for the assistant's
+    /// `expand_index` calls.
+    pub fn expand(&self, path: &Path) -> Result<SparseExpansion> {
+        self.expand_area(path, false)
+    }
+
+    /// Get the index the files are added to, for retrieval over them
+    pub fn index(&self) -> &Arc<TrigramIndex> {
+        &self.index
+    }
+
+    /// Check if a file is indexed
+    pub fn is_indexed(&self, path: &Path) -> bool {
+        self.sandbox
+            .check_access(path)
+            .is_ok_and(|resolved| self.state.lock().indexed.contains(&resolved))
+    }
+
+    /// Get the number of files indexed
+    pub fn indexed_files(&self) -> usize {
+        self.state.lock().indexed.len()
+    }
+
+    /// Get the indexed areas, the one used least recently first
+    pub fn areas(&self) -> Vec<IndexedArea> {
+        self.state
+            .lock()
+            .areas
+            .iter()
+            .map(|area| IndexedArea {
+                path: area.path.clone(),
+                focus: area.focus,
+                files: area.files.len(),
+            })
+            .collect()
+    }
+
+    /// Bring the indexed areas up to date with paths changed on disk, as
+    /// reported after a branch switch, returning the number of files
+    /// indexed again, added or dropped
+    ///
+    /// New files inside an indexed area are added while the budget allows.
+    /// Paths outside of the indexed areas are skipped.
+    pub fn refresh(&self, paths: &[PathBuf]) -> usize {
+        let _scope = alloc_scope(AllocTag::Index);
+        let mut refreshed = 0;
+        for path in paths {
+            let path = normalize_path(path);
+            let (in_area, stale) = {
+                let state = self.state.lock();
+                let in_area =
+                    state.areas.iter().any(|area| path.starts_with(&area.path));
+                // A removed directory takes the files below it along
+                let stale: Vec<PathBuf> = if path.exists() {
+                    state.indexed.get(&path).cloned().into_iter().collect()
+                } else {
+                    state
+                        .indexed
+                        .iter()
+                        .filter(|file| file.starts_with(&path))
+                        .cloned()
+                        .collect()
+                };
+                (in_area, stale)
+            };
+            if !in_area {
+                continue;
+            }
+            for file in &stale {
+                self.index.remove_path(file);
+            }
+
+            let is_indexed = !stale.is_empty();
+            let fits = is_indexed
+                || self.state.lock().indexed.len() < self.config.max_files;
+            let indexed = fits
+                && path.is_file()
+                && std::fs::metadata(&path).is_ok_and(|metadata| {
+                    metadata.len() <= self.config.max_file_size
+                })
+                && self.sandbox.is_allowed(&path)
+                && self.index_file(&path);
+
+            let mut state = self.state.lock();
+            for file in &stale {
+                if !(indexed && *file == path) {
+                    state.indexed.remove(file);
+                    for area in &mut state.areas {
+                        area.files.retain(|indexed| indexed != file);
+                    }
+                }
+            }
+            if indexed && !is_indexed {
+                state.indexed.insert(path.clone());
+                if let Some(area) = state
+                    .areas
+                    .iter_mut()
+                    .find(|area| path.starts_with(&area.path))
+                {
+                    area.files.push(path);
+                }
+                refreshed += 1;
+            } else {
+                refreshed += stale.len();
+            }
+        }
+        refreshed
+    }
+
+    fn expand_area(&self, path: &Path, focus: bool) -> Result<SparseExpansion> {
+        let resolved = self.sandbox.check_access(path)?;
+        if !resolved.exists() {
+            return Err(anyhow!("'{}' does not exist", path.display()));
+        }
+        let budget = {
+            let mut state = self.state.lock();
+            // An area inside an indexed one is only marked as used
+            if let Some(position) = state
+                .areas
+                .iter()
+                .position(|area| resolved.starts_with(&area.path))
+            {
+                let mut area = state.areas.remove(position);
+                area.focus |= focus;
+                state.areas.push(area);
+                return Ok(SparseExpansion::default());
+            }
+            let pinned: usize = state
+                .areas
+                .iter()
+                .filter(|area| area.focus && !area.path.starts_with(&resolved))
+                .map(|area| area.files.len())
+                .sum();
+            self.config.max_files.saturating_sub(pinned)
+        };
+
+        // Crawl and index without the lock, so lookups and other expansions
+        // don't wait for a large area
+        let _scope = alloc_scope(AllocTag::Index);
+        let mut expansion = SparseExpansion::default();
+        let mut counted = 0;
+        let mut added = Vec::new();
+        let crawler = WorkspaceCrawler::new(self.ignore.clone())
+            .with_scope(ContextScope {
+                roots: vec![resolved.clone()],
+                max_tokens: usize::MAX,
+                latency_budget: Duration::MAX,
+            })
+            .with_max_file_size(self.config.max_file_size);
+        let _ = crawler.try_for_each_file(|file| {
+            // Files of the areas inside the new one count towards it
+            let indexed = self.state.lock().indexed.contains(file);
+            if !indexed && counted >= budget {
+                expansion.truncated = true;
+                return ControlFlow::Break(());
+            }
+            if indexed {
+                counted += 1;
+            } else if self.index_file(file) {
+                counted += 1;
+                added.push(file.to_path_buf());
+            }
+            ControlFlow::Continue(())
+        });
+
+        let mut state = self.state.lock();
+        // Areas inside the new one become part of it
+        let mut files = Vec::new();
+        let mut absorbed_focus = false;
+        state.areas.retain_mut(|area| {
+            if !area.path.starts_with(&resolved) {
+                return true;
+            }
+            absorbed_focus |= area.focus;
+            files.append(&mut area.files);
+            false
+        });
+        for file in added {
+            if state.indexed.insert(file.clone()) {
+                files.push(file);
+                expansion.indexed += 1;
+            }
+        }
+
+        // Drop the areas used least recently until the new one fits
+        let mut total = state.indexed.len();
+        while total > self.config.max_files {
+            let Some(position) = state.areas.iter().position(|area| !area.focus)
+            else {
+                break;
+            };
+            let area = state.areas.remove(position);
+            for file in &area.files {
+                self.index.remove_path(file);
+                state.indexed.remove(file);
+            }
+            total -= area.files.len();
+            expansion.dropped.push(area.path);
+        }
+        state.areas.push(Area {
+            path: resolved,
+            focus: focus || absorbed_focus,
+            files,
+        });
+        Ok(expansion)
+    }
+
+    /// Add a file to the index, returning false if it isn't text
+    fn index_file(&self, path: &Path) -> bool {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return false;
+        };
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        self.index.insert_chunks(chunk_text(
+            path,
+            &content,
+            SPARSE_CHUNK_LINES,
+            modified,
+        ));
+        true
+    }
+}
+
+impl BuiltinToolProvider for SparseIndex {
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        vec![ToolDefinition {
+            name: EXPAND_INDEX_TOOL_NAME.to_string(),
+            description: "Add a directory or file of the workspace to the search \
+                          index. Only parts of this workspace are indexed; use \
+                          this before searching code outside of them."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path relative to the workspace root"
+                    }
+                },
+                "required": ["path"]
+            }),
+            effect: ToolEffect::ReadOnly,
+        }]
+    }
+
+    fn handle_tool_call(&self, call: &ToolCall) -> Result<String> {
+        if call.name != EXPAND_INDEX_TOOL_NAME {
+            return Err(anyhow!("Unknown index tool '{}'", call.name));
+        }
+        let path = call
+            .arguments
+            .get("path")
+            .and_then(|path| path.as_str())
+            .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+        let expansion = self.expand(Path::new(path))?;
+        if expansion.indexed == 0 && !expansion.truncated {
+            return Ok(format!("{path} is already indexed"));
+        }
+        let mut message =
+            format!("Indexed {} files under {path}", expansion.indexed);
+        if expansion.truncated {
+            message.push_str("; the index is full, so some files were left out");
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::CatalystIgnoreConfig;
+
+    #[test]
+    fn test_sparse_index_expands_on_demand() {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["core", "services/a", "services/b"] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        std::fs::write(root.path().join("core/lib.rs"), "fn core_fn() {}").unwrap();
+        for (dir, count) in [("services/a", 2), ("services/b", 3)] {
+            for i in 0..count {
+                std::fs::write(
+                    root.path().join(dir).join(format!("f{i}.rs")),
+                    format!("fn service_fn_{i}() {{}}"),
+                )
+                .unwrap();
+            }
+        }
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let index = Arc::new(TrigramIndex::new());
+        let sparse = SparseIndex::new(
+            Arc::new(ignore),
+            index.clone(),
+            SparseIndexConfig {
+                focus_paths: vec![PathBuf::from("core")],
+                max_files: 4,
+                ..Default::default()
+            },
+        );
+        let attention = AttentionTracker::new();
+        attention.record_view(&root.path().join("services/a/f0.rs"));
+
+        let loaded = sparse.load_initial(&attention);
+        assert_eq!(loaded.indexed, 2);
+        assert!(sparse.is_indexed(Path::new("core/lib.rs")));
+        assert!(sparse.is_indexed(Path::new("services/a/f0.rs")));
+        assert!(!sparse.is_indexed(Path::new("services/a/f1.rs")));
+        assert!(
+            index.search("core_fn", 5)[0]
+                .0
+                .path
+                .ends_with("core/lib.rs")
+        );
+
+        // The directory takes over the file expanded before
+        let expansion = sparse.expand(Path::new("services/a")).unwrap();
+        assert_eq!(expansion.indexed, 1);
+        assert_eq!(sparse.areas().len(), 2);
+        assert_eq!(sparse.indexed_files(), 3);
+        assert_eq!(
+            sparse.expand(Path::new("services/a/f1.rs")).unwrap(),
+            SparseExpansion::default()
+        );
+
+        // Over the budget, the least recent area goes but the focus stays
+        let call = ToolCall {
+            id: "1".to_string(),
+            name: EXPAND_INDEX_TOOL_NAME.to_string(),
+            arguments: serde_json::json!({ "path": "services/b" }),
+        };
+        assert_eq!(
+            sparse.handle_tool_call(&call).unwrap(),
+            "Indexed 3 files under services/b"
+        );
+        assert!(sparse.is_indexed(Path::new("core/lib.rs")));
+        assert!(!sparse.is_indexed(Path::new("services/a/f0.rs")));
+        let services_a = root.path().join("services/a");
+        assert!(
+            index
+                .search("service_fn_0", 10)
+                .iter()
+                .all(|(chunk, _)| !chunk.path.starts_with(&services_a))
+        );
+        assert_eq!(sparse.indexed_files(), 4);
+        assert_eq!(
+            sparse.handle_tool_call(&call).unwrap(),
+            "services/b is already indexed"
+        );
+        assert!(sparse.expand(Path::new("../outside")).is_err());
+    }
+
+    #[test]
+    fn test_expansion_stops_at_budget() {
+        let root = tempfile::tempdir().unwrap();
+        for i in 0..5 {
+            std::fs::write(root.path().join(format!("f{i}.rs")), "fn f() {}")
+                .unwrap();
+        }
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sparse = SparseIndex::new(
+            Arc::new(ignore),
+            Arc::new(TrigramIndex::new()),
+            SparseIndexConfig {
+                max_files: 2,
+                ..Default::default()
+            },
+        );
+
+        let expansion = sparse.expand(root.path()).unwrap();
+        assert_eq!(expansion.indexed, 2);
+        assert!(expansion.truncated);
+        assert_eq!(sparse.indexed_files(), 2);
+    }
+
+    #[test]
+    fn test_refresh_follows_changed_files() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("src")).unwrap();
+        std::fs::create_dir(root.path().join("other")).unwrap();
+        let file = |name: &str| root.path().join(name);
+        std::fs::write(file("src/a.rs"), "fn before_switch() {}").unwrap();
+        std::fs::write(file("src/b.rs"), "fn removed_fn() {}").unwrap();
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let index = Arc::new(TrigramIndex::new());
+        let sparse = SparseIndex::new(
+            Arc::new(ignore),
+            index.clone(),
+            SparseIndexConfig::default(),
+        );
+        sparse.expand(Path::new("src")).unwrap();
+
+        std::fs::write(file("src/a.rs"), "fn after_switch() {}").unwrap();
+        std::fs::remove_file(file("src/b.rs")).unwrap();
+        std::fs::write(file("src/c.rs"), "fn added_fn() {}").unwrap();
+        std::fs::write(file("other/d.rs"), "fn outside_fn() {}").unwrap();
+        let changed = ["src/a.rs", "src/b.rs", "src/c.rs", "other/d.rs"]
+            .map(file)
+            .to_vec();
+        assert_eq!(sparse.refresh(&changed), 3);
+
+        let top = |query: &str| {
+            index
+                .search(query, 5)
+                .first()
+                .map(|(chunk, _)| chunk.path.to_path_buf())
+        };
+        assert_eq!(top("after_switch"), Some(file("src/a.rs")));
+        assert_eq!(top("added_fn"), Some(file("src/c.rs")));
+        assert!(
+            index
+                .search("before_switch", 5)
+                .iter()
+                .all(|(chunk, _)| !chunk.content.contains("before_switch"))
+        );
+        assert!(!sparse.is_indexed(Path::new("src/b.rs")));
+        assert!(sparse.is_indexed(Path::new("src/c.rs")));
+        assert!(!sparse.is_indexed(Path::new("other/d.rs")));
+        assert_eq!(sparse.indexed_files(), 2);
+        assert_eq!(sparse.areas()[0].files, 2);
+    }
+}
@@ -0,0 +1,845 @@
+//! Speech Readout
+//!
+//! This module reads assistant responses aloud. Speech comes either from
+//! the platform's text-to-speech (`say` on macOS, `espeak-ng` or `spd-say`
+//! on Linux, `System.Speech` on Windows) or from a provider's speech API
+//! when the `NetworkPolicy` allows it. A response is split into segments of
+//! a few sentences, with code blocks left out, so a readout can be paused
+//! and resumed at the segment it stopped in.
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+use floem::{
+    View,
+    ext_event::create_signal_from_channel,
+    reactive::SignalGet,
+    views::{Decorators, h_stack, label},
+};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::plugin_api::{CommandResolver, NetworkPolicy};
+
+/// Characters from which sentences are not added to a segment
+const MAX_SEGMENT_CHARS: usize = 400;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Readout settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeechSettings {
+    pub enabled: bool,
+    /// Voice name understood by the engine; the default voice when `None`
+    pub voice: Option<String>,
+    /// Speaking rate in words per minute
+    pub words_per_minute: u32,
+    /// Speech API used when the network policy allows it
+    pub api: Option<ApiSpeechConfig>,
+}
+
+impl Default for SpeechSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            voice: None,
+            words_per_minute: 180,
+            api: None,
+        }
+    }
+}
+
+/// Trait that speech backends must implement
+pub trait SpeechProvider: Send + Sync {
+    /// Get provider information
+    fn provider_info(&self) -> SpeechProviderInfo;
+
+    /// Start speaking text, returning the playback without waiting for it
+    fn speak(&self, text: &str, settings: &SpeechSettings)
+    -> Result<SpeechPlayback>;
+}
+
+/// Information about a speech provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeechProviderInfo {
+    pub id: String,
+    pub requires_network: bool,
+}
+
+/// Speech being played by a child process
+pub struct SpeechPlayback {
+    child: Child,
+    /// Synthesized audio, removed once playback is dropped
+    _audio: Option<tempfile::TempPath>,
+}
+
+impl SpeechPlayback {
+    pub fn new(child: Child) -> Self {
+        Self {
+            child,
+            _audio: None,
+        }
+    }
+
+    /// Check if playback has ended
+    pub fn is_finished(&mut self) -> Result<bool> {
+        Ok(self.child.try_wait()?.is_some())
+    }
+
+    /// Stop playback
+    pub fn stop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Platform text-to-speech program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlatformSpeechEngine {
+    /// macOS `say`
+    Say(PathBuf),
+    /// `espeak-ng` or `espeak`
+    Espeak(PathBuf),
+    /// speech-dispatcher's `spd-say`
+    SpdSay(PathBuf),
+    /// PowerShell with `System.Speech`
+    WindowsSpeech(PathBuf),
+}
+
+impl PlatformSpeechEngine {
+    /// Find the engine available on this machine
+    pub fn detect(resolver: &CommandResolver) -> Option<Self> {
+        if cfg!(windows) {
+            return ["pwsh", "powershell"]
+                .iter()
+                .find_map(|name| resolver.find_executable(name))
+                .map(Self::WindowsSpeech);
+        }
+        if cfg!(target_os = "macos") {
+            if let Some(path) = resolver.find_executable("say") {
+                return Some(Self::Say(path));
+            }
+        }
+        resolver
+            .find_executable("espeak-ng")
+            .or_else(|| resolver.find_executable("espeak"))
+            .map(Self::Espeak)
+            .or_else(|| resolver.find_executable("spd-say").map(Self::SpdSay))
+    }
+
+    /// Build the command speaking text, and the input to write to its
+    /// stdin, if any
+    pub fn command(
+        &self,
+        text: &str,
+        settings: &SpeechSettings,
+    ) -> (Command, Option<String>) {
+        let wpm = settings.words_per_minute.clamp(80, 450);
+        match self {
+            Self::Say(program) => {
+                let mut command = Command::new(program);
+                command.args(["-r", &wpm.to_string()]);
+                if let Some(voice) = &settings.voice {
+                    command.args(["-v", voice]);
+                }
+                command.args(["-f", "-"]);
+                (command, Some(text.to_string()))
+            }
+            Self::Espeak(program) => {
+                let mut command = Command::new(program);
+                command.args(["-s", &wpm.to_string()]);
+                if let Some(voice) = &settings.voice {
+                    command.args(["-v", voice]);
+                }
+                command.arg("--stdin");
+                (command, Some(text.to_string()))
+            }
+            Self::SpdSay(program) => {
+                // Rate is -100 to 100 around the default of about 180 wpm
+                let rate = ((wpm as i64 - 180) / 2).clamp(-100, 100);
+                let mut command = Command::new(program);
+                command.args(["-w", "-r", &rate.to_string()]);
+                if let Some(voice) = &settings.voice {
+                    command.args(["-y", voice]);
+                }
+                command.args(["--", text]);
+                (command, None)
+            }
+            Self::WindowsSpeech(program) => {
+                // Rate is -10 to 10 around the default of about 180 wpm
+                let rate = ((wpm as i64 - 180) / 20).clamp(-10, 10);
+                let voice = settings
+                    .voice
+                    .as_ref()
+                    .map(|voice| {
+                        format!("$s.SelectVoice('{}'); ", voice.replace('\'', "''"))
+                    })
+                    .unwrap_or_default();
+                let script = format!(
+                    "Add-Type -AssemblyName System.Speech; \
+                     $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+                     $s.Rate = {rate}; {voice}$s.Speak([Console]::In.ReadToEnd())"
+                );
+                let mut command = Command::new(program);
+                command.args(["-NoLogo", "-NoProfile", "-Command", &script]);
+                (command, Some(text.to_string()))
+            }
+        }
+    }
+}
+
+/// Build the command playing an audio file with the first player found
+fn audio_player_command(resolver: &CommandResolver, path: &str) -> Option<Command> {
+    if cfg!(windows) {
+        let powershell = ["pwsh", "powershell"]
+            .iter()
+            .find_map(|name| resolver.find_executable(name))?;
+        let mut command = Command::new(powershell);
+        command.args([
+            "-NoLogo",
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "(New-Object Media.SoundPlayer '{}').PlaySync()",
+                path.replace('\'', "''")
+            ),
+        ]);
+        return Some(command);
+    }
+    let players: [(&str, &[&str]); 4] = [
+        ("afplay", &[]),
+        ("paplay", &[]),
+        ("aplay", &["-q"]),
+        ("ffplay", &["-nodisp", "-autoexit", "-loglevel", "quiet"]),
+    ];
+    players.iter().find_map(|(name, args)| {
+        let mut command = Command::new(resolver.find_executable(name)?);
+        command.args(*args).arg(path);
+        Some(command)
+    })
+}
+
+/// Speech from the platform's text-to-speech
+pub struct PlatformSpeechProvider {
+    engine: PlatformSpeechEngine,
+}
+
+impl PlatformSpeechProvider {
+    /// Create a provider for the engine found on this machine
+    pub fn detect() -> Option<Self> {
+        PlatformSpeechEngine::detect(&CommandResolver::from_env())
+            .map(|engine| Self { engine })
+    }
+}
+
+impl SpeechProvider for PlatformSpeechProvider {
+    fn provider_info(&self) -> SpeechProviderInfo {
+        SpeechProviderInfo {
+            id: "platform".to_string(),
+            requires_network: false,
+        }
+    }
+
+    fn speak(
+        &self,
+        text: &str,
+        settings: &SpeechSettings,
+    ) -> Result<SpeechPlayback> {
+        let (command, input) = self.engine.command(text, settings);
+        spawn_speech(command, input).map(SpeechPlayback::new)
+    }
+}
+
+/// Configuration for an OpenAI-compatible speech API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiSpeechConfig {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub voice: String,
+    pub timeout_seconds: u64,
+}
+
+impl Default for ApiSpeechConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://api.openai.com/v1/audio/speech".to_string(),
+            api_key: None,
+            model: "tts-1".to_string(),
+            voice: "alloy".to_string(),
+            timeout_seconds: 30,
+        }
+    }
+}
+
+/// Speech synthesized by a remote API and played locally
+pub struct ApiSpeechProvider {
+    config: ApiSpeechConfig,
+    client: reqwest::blocking::Client,
+    /// Used to find an audio player
+    resolver: CommandResolver,
+}
+
+impl ApiSpeechProvider {
+    /// Create a new API speech provider
+    pub fn new(config: ApiSpeechConfig) -> Result<Self> {
+        let client = catalyst_proxy::http::client_builder()?
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()?;
+        Ok(Self {
+            config,
+            client,
+            resolver: CommandResolver::from_env(),
+        })
+    }
+}
+
+impl SpeechProvider for ApiSpeechProvider {
+    fn provider_info(&self) -> SpeechProviderInfo {
+        SpeechProviderInfo {
+            id: "api".to_string(),
+            requires_network: true,
+        }
+    }
+
+    fn speak(
+        &self,
+        text: &str,
+        settings: &SpeechSettings,
+    ) -> Result<SpeechPlayback> {
+        let speed = settings.words_per_minute as f64 / 180.0;
+        let mut request =
+            self.client
+                .post(&self.config.endpoint)
+                .json(&serde_json::json!({
+                    "model": self.config.model,
+                    "voice": settings.voice.as_ref().unwrap_or(&self.config.voice),
+                    "input": text,
+                    "response_format": "wav",
+                    "speed": speed.clamp(0.25, 4.0),
+                }));
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let audio = request.send()?.error_for_status()?.bytes()?;
+
+        let mut file = tempfile::Builder::new().suffix(".wav").tempfile()?;
+        file.write_all(&audio)?;
+        let path = file.into_temp_path();
+        let play = audio_player_command(&self.resolver, &path.to_string_lossy())
+            .ok_or_else(|| anyhow::anyhow!("No audio player is available"))?;
+        Ok(SpeechPlayback {
+            child: spawn_speech(play, None)?,
+            _audio: Some(path),
+        })
+    }
+}
+
+fn spawn_speech(mut command: Command, input: Option<String>) -> Result<Child> {
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let mut child = command.spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // Dropping stdin closes it, which ends the text
+        stdin.write_all(input.as_bytes())?;
+    }
+    Ok(child)
+}
+
+/// Choose the speech provider allowed by the network policy
+pub fn select_speech_provider(
+    policy: NetworkPolicy,
+    remote: Option<Arc<dyn SpeechProvider>>,
+    local: Option<Arc<dyn SpeechProvider>>,
+) -> Result<Arc<dyn SpeechProvider>> {
+    let remote = remote.filter(|_| policy.allows_remote_processing());
+    let selected = if policy.prefers_local() {
+        local.or(remote)
+    } else {
+        remote.or(local)
+    };
+
+    selected.ok_or_else(|| {
+        anyhow::anyhow!(
+            "No speech provider is available under the {:?} network policy",
+            policy
+        )
+    })
+}
+
+/// Get the provider of readouts, or `None` when speech is off or neither
+/// the platform engine nor the speech API is available
+pub fn configured_speech_provider(
+    settings: &SpeechSettings,
+    policy: NetworkPolicy,
+) -> Option<Arc<dyn SpeechProvider>> {
+    if !settings.enabled {
+        return None;
+    }
+    let remote =
+        settings.api.clone().and_then(|config| {
+            match ApiSpeechProvider::new(config) {
+                Ok(provider) => Some(Arc::new(provider) as Arc<dyn SpeechProvider>),
+                Err(err) => {
+                    tracing::error!("Failed to set up the speech API: {err:#}");
+                    None
+                }
+            }
+        });
+    let local = PlatformSpeechProvider::detect()
+        .map(|provider| Arc::new(provider) as Arc<dyn SpeechProvider>);
+    match select_speech_provider(policy, remote, local) {
+        Ok(provider) => Some(provider),
+        Err(err) => {
+            tracing::warn!("Responses can't be read aloud: {err:#}");
+            None
+        }
+    }
+}
+
+/// Split a markdown response into segments to speak
+///
+/// Code blocks are replaced with a short notice, formatting and citation
+/// markers are removed, and sentences are grouped up to
+/// `MAX_SEGMENT_CHARS`.
+pub fn speech_segments(markdown: &str) -> Vec<String> {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+    let mut in_code = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            if !in_code {
+                paragraphs.push(std::mem::take(&mut paragraph));
+                paragraphs.push("Code block omitted.".to_string());
+            }
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if trimmed.is_empty() {
+            paragraphs.push(std::mem::take(&mut paragraph));
+            continue;
+        }
+        let text = trimmed
+            .trim_start_matches('#')
+            .trim_start_matches(['-', '*', '>'])
+            .trim();
+        if !paragraph.is_empty() {
+            paragraph.push(' ');
+        }
+        paragraph.push_str(&strip_markup(text));
+    }
+    paragraphs.push(paragraph);
+
+    let mut segments = Vec::new();
+    for paragraph in paragraphs.iter().filter(|p| !p.trim().is_empty()) {
+        let mut segment = String::new();
+        for sentence in sentences(paragraph) {
+            if !segment.is_empty()
+                && segment.len() + sentence.len() > MAX_SEGMENT_CHARS
+            {
+                segments.push(std::mem::take(&mut segment));
+            }
+            if !segment.is_empty() {
+                segment.push(' ');
+            }
+            segment.push_str(sentence);
+        }
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+    }
+    segments
+}
+
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    let mut start = 0;
+    let mut ends = Vec::new();
+    let bytes = text.as_bytes();
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(byte, b'.' | b'!' | b'?')
+            && bytes.get(i + 1).is_none_or(|next| *next == b' ')
+        {
+            ends.push(i + 1);
+        }
+    }
+    ends.push(text.len());
+    ends.into_iter().filter_map(move |end| {
+        let sentence = text[start..end].trim();
+        start = end;
+        (!sentence.is_empty()).then_some(sentence)
+    })
+}
+
+/// Remove emphasis, inline code ticks, link targets and citation markers
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '`' => {}
+            // `[1]` and `[1, 2]` citation markers
+            '[' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let rest: String = chars.clone().take_while(|c| *c != ']').collect();
+                if rest
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == ' ')
+                {
+                    for _ in 0..=rest.chars().count() {
+                        chars.next();
+                    }
+                    let next_is_word =
+                        chars.peek().is_some_and(|c| c.is_alphanumeric());
+                    if out.ends_with(' ') && !next_is_word {
+                        out.pop();
+                    }
+                } else {
+                    out.push(c);
+                }
+            }
+            // Keep link text, drop `(target)`
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            '[' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Where a readout is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReadoutState {
+    Idle,
+    Playing,
+    Paused,
+    Finished,
+    Failed,
+}
+
+struct ReadoutInner {
+    segments: Vec<String>,
+    /// Segment being spoken, or the next one to speak
+    next: usize,
+    state: ReadoutState,
+    /// Incremented to tell the playback thread to stop
+    generation: u64,
+    playback: Option<SpeechPlayback>,
+    listeners: Vec<Sender<ReadoutState>>,
+}
+
+impl ReadoutInner {
+    fn set_state(&mut self, state: ReadoutState) {
+        self.state = state;
+        self.listeners.retain(|tx| tx.send(state).is_ok());
+    }
+
+    /// Stop the playback thread and the segment being spoken
+    fn interrupt(&mut self) {
+        self.generation += 1;
+        if let Some(mut playback) = self.playback.take() {
+            playback.stop();
+        }
+    }
+}
+
+/// Readout of one response with play, pause and stop
+///
+/// Pausing stops the segment being spoken; resuming speaks it again from
+/// its start.
+pub struct SpeechReadout {
+    provider: Arc<dyn SpeechProvider>,
+    settings: SpeechSettings,
+    inner: Arc<Mutex<ReadoutInner>>,
+}
+
+impl SpeechReadout {
+    pub fn new(
+        provider: Arc<dyn SpeechProvider>,
+        settings: SpeechSettings,
+        markdown: &str,
+    ) -> Self {
+        Self {
+            provider,
+            settings,
+            inner: Arc::new(Mutex::new(ReadoutInner {
+                segments: speech_segments(markdown),
+                next: 0,
+                state: ReadoutState::Idle,
+                generation: 0,
+                playback: None,
+                listeners: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn state(&self) -> ReadoutState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Get the segments and the index of the one being spoken
+    pub fn position(&self) -> (usize, usize) {
+        let inner = self.inner.lock().unwrap();
+        (inner.next, inner.segments.len())
+    }
+
+    /// Read another response from its start, unless it is the one loaded
+    pub fn load(&self, markdown: &str) {
+        let segments = speech_segments(markdown);
+        let mut inner = self.inner.lock().unwrap();
+        if inner.segments == segments {
+            return;
+        }
+        inner.interrupt();
+        inner.segments = segments;
+        inner.next = 0;
+        inner.set_state(ReadoutState::Idle);
+    }
+
+    /// Get a channel receiving every state change
+    pub fn subscribe(&self) -> Receiver<ReadoutState> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.inner.lock().unwrap().listeners.push(tx);
+        rx
+    }
+
+    /// Start or resume speaking on a background thread
+    pub fn play(&self) {
+        let generation = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.state == ReadoutState::Playing {
+                return;
+            }
+            if inner.state == ReadoutState::Finished {
+                inner.next = 0;
+            }
+            inner.interrupt();
+            inner.set_state(ReadoutState::Playing);
+            inner.generation
+        };
+        let provider = self.provider.clone();
+        let settings = self.settings.clone();
+        let inner = self.inner.clone();
+        std::thread::spawn(move || {
+            run_readout(provider, settings, inner, generation)
+        });
+    }
+
+    pub fn pause(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == ReadoutState::Playing {
+            inner.interrupt();
+            inner.set_state(ReadoutState::Paused);
+        }
+    }
+
+    /// Play when paused or stopped, pause when playing
+    pub fn toggle(&self) {
+        if self.state() == ReadoutState::Playing {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Stop and go back to the start
+    pub fn stop(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.interrupt();
+        inner.next = 0;
+        inner.set_state(ReadoutState::Idle);
+    }
+}
+
+impl Drop for SpeechReadout {
+    fn drop(&mut self) {
+        self.inner.lock().unwrap().interrupt();
+    }
+}
+
+fn run_readout(
+    provider: Arc<dyn SpeechProvider>,
+    settings: SpeechSettings,
+    inner: Arc<Mutex<ReadoutInner>>,
+    generation: u64,
+) {
+    loop {
+        {
+            let mut guard = inner.lock().unwrap();
+            if guard.generation != generation {
+                return;
+            }
+            let Some(segment) = guard.segments.get(guard.next).cloned() else {
+                guard.set_state(ReadoutState::Finished);
+                return;
+            };
+            match provider.speak(&segment, &settings) {
+                Ok(playback) => guard.playback = Some(playback),
+                Err(err) => {
+                    tracing::error!("Failed to speak: {err:#}");
+                    guard.set_state(ReadoutState::Failed);
+                    return;
+                }
+            }
+        }
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let mut guard = inner.lock().unwrap();
+            if guard.generation != generation {
+                return;
+            }
+            let finished = match guard.playback.as_mut() {
+                Some(playback) => playback.is_finished().unwrap_or(true),
+                None => true,
+            };
+            if finished {
+                guard.playback = None;
+                guard.next += 1;
+                break;
+            }
+        }
+    }
+}
+
+/// Create the play/pause and stop controls reading out the response given
+/// by `response`
+pub fn speech_controls_view(
+    readout: Arc<SpeechReadout>,
+    response: impl Fn() -> Option<String> + 'static,
+) -> Box<dyn View> {
+    let initial = readout.state();
+    let state = create_signal_from_channel(readout.subscribe());
+    let toggle = readout.clone();
+    Box::new(
+        h_stack((
+            label(move || {
+                match state.get().unwrap_or(initial) {
+                    ReadoutState::Playing => "Pause",
+                    ReadoutState::Paused => "Resume",
+                    ReadoutState::Failed => "Retry",
+                    ReadoutState::Idle | ReadoutState::Finished => "Read aloud",
+                }
+                .to_string()
+            })
+            .on_click_stop(move |_| {
+                if let Some(response) = response() {
+                    toggle.load(&response);
+                }
+                toggle.toggle();
+            }),
+            label(|| "Stop".to_string())
+                .on_click_stop(move |_| readout.stop())
+                .style(move |s| {
+                    let state = state.get().unwrap_or(initial);
+                    s.apply_if(
+                        !matches!(
+                            state,
+                            ReadoutState::Playing | ReadoutState::Paused
+                        ),
+                        |s| s.hide(),
+                    )
+                }),
+        ))
+        .style(|s| s.gap(10.0)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SilentProvider;
+
+    impl SpeechProvider for SilentProvider {
+        fn provider_info(&self) -> SpeechProviderInfo {
+            SpeechProviderInfo {
+                id: "silent".to_string(),
+                requires_network: false,
+            }
+        }
+
+        fn speak(
+            &self,
+            _text: &str,
+            _settings: &SpeechSettings,
+        ) -> Result<SpeechPlayback> {
+            Err(anyhow::anyhow!("Nothing to speak with"))
+        }
+    }
+
+    #[test]
+    fn test_segments_responses_and_builds_engine_commands() {
+        let markdown = "## Summary\n\nThe parser **skips** `nul` bytes [1, 2]. \
+                        See [the docs](https://example.com).\n\n\
+                        ```rust\nfn main() {}\n```\n\n- Done!";
+        assert_eq!(
+            speech_segments(markdown),
+            vec![
+                "Summary",
+                "The parser skips nul bytes. See the docs.",
+                "Code block omitted.",
+                "Done!",
+            ]
+        );
+
+        let long = "One sentence here. ".repeat(40);
+        let segments = speech_segments(&long);
+        assert!(segments.len() > 1);
+        assert!(segments.iter().all(|s| s.len() <= MAX_SEGMENT_CHARS));
+
+        let settings = SpeechSettings {
+            voice: Some("en-us".to_string()),
+            words_per_minute: 220,
+            ..Default::default()
+        };
+        let engine = PlatformSpeechEngine::Espeak(PathBuf::from("espeak-ng"));
+        let (command, input) = engine.command("Hello", &settings);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-s", "220", "-v", "en-us", "--stdin"]);
+        assert_eq!(input.as_deref(), Some("Hello"));
+
+        let engine = PlatformSpeechEngine::SpdSay(PathBuf::from("spd-say"));
+        let (command, input) = engine.command("Hello", &settings);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-w", "-r", "20", "-y", "en-us", "--", "Hello"]);
+        assert!(input.is_none());
+    }
+    #[test]
+    fn test_loading_another_response_starts_over() {
+        let readout = SpeechReadout::new(
+            Arc::new(SilentProvider),
+            SpeechSettings::default(),
+            "",
+        );
+        assert_eq!(readout.position(), (0, 0));
+        let response = "The answer.\n\n```\nfn main() {}\n```";
+        readout.load(response);
+        assert_eq!(readout.position(), (0, 2));
+
+        // Loading the same response keeps the readout where it is
+        let states = readout.subscribe();
+        readout.load(response);
+        assert!(states.try_recv().is_err());
+
+        readout.load("Another answer.");
+        assert_eq!(readout.position(), (0, 1));
+        assert_eq!(states.try_recv(), Ok(ReadoutState::Idle));
+    }
+}
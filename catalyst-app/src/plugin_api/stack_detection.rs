@@ -0,0 +1,693 @@
+//! Stack Detection
+//!
+//! This module looks at the files at the root of a workspace for signs of
+//! the tools a project uses, such as a compose file, CI workflows or a
+//! Sentry configuration, and recommends the catalog MCP servers and the
+//! automations that go with them. The stack panel lists the
+//! recommendations and enables one with a single command.
+//!
+//! Recommendations only ever point at entries of the built-in
+//! [`MCP_CATALOG`](crate::plugin_api::MCP_CATALOG); nothing read from the
+//! workspace ends up in a command. Before enabling a server a
+//! [`TrustCheck`] makes sure its programs are installed and that the
+//! repository does not define a server with the same id but another
+//! command, which enabling would otherwise hand it.
+
+use anyhow::{Result, anyhow};
+use floem::View;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    Automation, AutomationAction, AutomationStore, AutomationTrigger,
+    CommandResolver, CredentialStore, EVENT_PLACEHOLDER, GITHUB_SERVER_ID,
+    McpCatalogEntry, PanelCommand, PanelCommandResult, SidebarPanelInfo,
+    SidebarPanelPlugin, SidebarPosition, TEAM_CONFIG_DIR, TEAM_SETTINGS_FILE,
+    TeamConfig, add_local_mcp_servers, github_repository_from_remote,
+    mcp_catalog_entry, text_panel_view,
+};
+
+const COMPOSE_FILES: &[&str] = &[
+    "docker-compose.yml",
+    "docker-compose.yaml",
+    "compose.yml",
+    "compose.yaml",
+];
+const SENTRY_FILES: &[&str] = &[".sentryclirc", "sentry.properties"];
+
+/// Sign of a tool used by the project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StackSignal {
+    DockerCompose,
+    Cargo,
+    Npm,
+    GithubWorkflows,
+    GitlabCi,
+    Sentry,
+}
+
+impl StackSignal {
+    /// Get the file the signal was found in, for the reason shown
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::DockerCompose => "a Docker Compose file",
+            Self::Cargo => "Cargo.toml",
+            Self::Npm => "package.json",
+            Self::GithubWorkflows => "GitHub Actions workflows",
+            Self::GitlabCi => ".gitlab-ci.yml",
+            Self::Sentry => "a Sentry configuration",
+        }
+    }
+}
+
+/// Find the signals in the root of a workspace
+pub fn detect_stack(workspace_root: &Path) -> BTreeSet<StackSignal> {
+    let exists = |name: &str| workspace_root.join(name).exists();
+    let read = |name: &str| {
+        std::fs::read_to_string(workspace_root.join(name)).unwrap_or_default()
+    };
+    let mut signals = BTreeSet::new();
+
+    if COMPOSE_FILES.iter().any(|name| exists(name)) {
+        signals.insert(StackSignal::DockerCompose);
+    }
+    let cargo = read("Cargo.toml");
+    if exists("Cargo.toml") {
+        signals.insert(StackSignal::Cargo);
+    }
+    let package = read("package.json");
+    if exists("package.json") {
+        signals.insert(StackSignal::Npm);
+    }
+    let has_workflows = std::fs::read_dir(workspace_root.join(".github/workflows"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|entry| {
+            let path = entry.path();
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yml" | "yaml")
+            )
+        });
+    if has_workflows {
+        signals.insert(StackSignal::GithubWorkflows);
+    }
+    if exists(".gitlab-ci.yml") {
+        signals.insert(StackSignal::GitlabCi);
+    }
+
+    let sentry_config = std::fs::read_dir(workspace_root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            SENTRY_FILES.contains(&name.as_str())
+                || (name.starts_with("sentry.") && name.contains(".config."))
+        });
+    let sentry_dependency = package.contains("\"@sentry/")
+        || cargo
+            .lines()
+            .any(|line| line.trim_start().starts_with("sentry"));
+    if sentry_config || sentry_dependency {
+        signals.insert(StackSignal::Sentry);
+    }
+
+    signals
+}
+
+/// What a recommendation enables
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecommendedItem {
+    McpServer { entry: &'static McpCatalogEntry },
+    Automation { automation: Automation },
+}
+
+/// Something worth enabling for the detected stack
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StackRecommendation {
+    /// `mcp:<server id>` or `automation:<name>`
+    pub id: String,
+    pub signal: StackSignal,
+    pub item: RecommendedItem,
+}
+
+impl StackRecommendation {
+    fn server(signal: StackSignal, id: &str) -> Option<Self> {
+        let entry = mcp_catalog_entry(id)?;
+        Some(Self {
+            id: format!("mcp:{}", entry.id),
+            signal,
+            item: RecommendedItem::McpServer { entry },
+        })
+    }
+
+    fn automation(
+        signal: StackSignal,
+        name: &str,
+        trigger: AutomationTrigger,
+        prompt: String,
+        servers: &[&str],
+    ) -> Self {
+        Self {
+            id: format!("automation:{}", name),
+            signal,
+            item: RecommendedItem::Automation {
+                automation: Automation {
+                    id: 0,
+                    name: name.to_string(),
+                    trigger,
+                    action: AutomationAction::AgentTask {
+                        prompt,
+                        servers: servers.iter().map(|s| s.to_string()).collect(),
+                    },
+                    enabled: true,
+                    max_concurrent_runs: 1,
+                },
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.item {
+            RecommendedItem::McpServer { entry } => entry.name,
+            RecommendedItem::Automation { automation } => &automation.name,
+        }
+    }
+}
+
+/// Get the recommendations for the signals of a workspace
+pub fn recommend_for_stack(
+    workspace_root: &Path,
+    signals: &BTreeSet<StackSignal>,
+) -> Vec<StackRecommendation> {
+    let mut recommendations = Vec::new();
+    let fix_errors = |signal, name: &str, globs: &[&str]| {
+        StackRecommendation::automation(
+            signal,
+            name,
+            AutomationTrigger::DiagnosticsReceived {
+                min_errors: 1,
+                globs: globs.iter().map(|glob| glob.to_string()).collect(),
+            },
+            format!("Explain the errors in {EVENT_PLACEHOLDER} and propose a fix"),
+            &[],
+        )
+    };
+    for signal in signals {
+        match signal {
+            StackSignal::DockerCompose => {
+                recommendations
+                    .extend(StackRecommendation::server(*signal, "docker"));
+            }
+            StackSignal::Cargo => recommendations.push(fix_errors(
+                *signal,
+                "Explain Rust build errors",
+                &["**/*.rs"],
+            )),
+            StackSignal::Npm => recommendations.push(fix_errors(
+                *signal,
+                "Explain TypeScript and JavaScript errors",
+                &["**/*.ts", "**/*.tsx", "**/*.js", "**/*.jsx"],
+            )),
+            StackSignal::GithubWorkflows => {
+                recommendations
+                    .extend(StackRecommendation::server(*signal, GITHUB_SERVER_ID));
+                if let Some(repository) = origin_remote_url(workspace_root)
+                    .as_deref()
+                    .and_then(github_repository_from_remote)
+                {
+                    recommendations.push(StackRecommendation::automation(
+                        *signal,
+                        "Review new pull requests",
+                        AutomationTrigger::PullRequestOpened { repository },
+                        format!("Review the pull request {EVENT_PLACEHOLDER}"),
+                        &[GITHUB_SERVER_ID],
+                    ));
+                }
+            }
+            StackSignal::GitlabCi => {
+                recommendations
+                    .extend(StackRecommendation::server(*signal, "gitlab"));
+            }
+            StackSignal::Sentry => {
+                recommendations
+                    .extend(StackRecommendation::server(*signal, "sentry"));
+            }
+        }
+    }
+    recommendations
+}
+
+/// Get the URL of the `origin` remote from `.git/config`
+pub(crate) fn origin_remote_url(workspace_root: &Path) -> Option<String> {
+    let config = std::fs::read_to_string(workspace_root.join(".git/config")).ok()?;
+    let mut section = "";
+    config.lines().map(str::trim).find_map(|line| {
+        if line.starts_with('[') {
+            section = line;
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        (section == "[remote \"origin\"]" && key.trim() == "url")
+            .then(|| value.trim().to_string())
+    })
+}
+
+/// Whether a recommendation can be enabled
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TrustCheck {
+    pub already_enabled: bool,
+    /// Problems that keep the recommendation from being enabled
+    pub blockers: Vec<String>,
+    /// Problems to fix after enabling, e.g. missing credentials
+    pub warnings: Vec<String>,
+}
+
+impl TrustCheck {
+    pub fn allows_enabling(&self) -> bool {
+        !self.already_enabled && self.blockers.is_empty()
+    }
+}
+
+/// Recommends and enables servers and automations for a workspace
+pub struct StackAdvisor<'a> {
+    workspace_root: PathBuf,
+    resolver: CommandResolver,
+    credentials: &'a CredentialStore,
+    env: HashMap<String, String>,
+    automations: Arc<AutomationStore>,
+}
+
+impl StackAdvisor<'static> {
+    /// Create an advisor using the environment of the current process
+    pub fn from_env(
+        workspace_root: &Path,
+        automations: Arc<AutomationStore>,
+    ) -> Self {
+        Self::new(
+            workspace_root,
+            CommandResolver::from_env(),
+            CredentialStore::global(),
+            std::env::vars().collect(),
+            automations,
+        )
+    }
+}
+
+impl<'a> StackAdvisor<'a> {
+    pub fn new(
+        workspace_root: &Path,
+        resolver: CommandResolver,
+        credentials: &'a CredentialStore,
+        env: HashMap<String, String>,
+        automations: Arc<AutomationStore>,
+    ) -> Self {
+        Self {
+            workspace_root: workspace_root.to_path_buf(),
+            resolver,
+            credentials,
+            env,
+            automations,
+        }
+    }
+
+    /// Detect the stack and check each recommendation
+    pub fn recommendations(&self) -> Result<Vec<(StackRecommendation, TrustCheck)>> {
+        let team = TeamConfig::load(&self.workspace_root)?;
+        let signals = detect_stack(&self.workspace_root);
+        Ok(recommend_for_stack(&self.workspace_root, &signals)
+            .into_iter()
+            .map(|recommendation| {
+                let check = self.trust_check(&recommendation, &team);
+                (recommendation, check)
+            })
+            .collect())
+    }
+
+    fn trust_check(
+        &self,
+        recommendation: &StackRecommendation,
+        team: &TeamConfig,
+    ) -> TrustCheck {
+        let mut check = TrustCheck::default();
+        match &recommendation.item {
+            RecommendedItem::McpServer { entry } => {
+                let server = entry.team_server();
+                match team.settings.mcp_servers.get(entry.id) {
+                    Some(configured)
+                        if configured.command == server.command
+                            && configured.args == server.args =>
+                    {
+                        check.already_enabled = configured.enabled;
+                    }
+                    Some(_) => check.blockers.push(format!(
+                        "The workspace defines a server '{}' with another \
+                         command; review {} before replacing it",
+                        entry.id,
+                        Path::new(TEAM_CONFIG_DIR)
+                            .join(TEAM_SETTINGS_FILE)
+                            .display()
+                    )),
+                    None => {}
+                }
+                let health = entry.health_check(&self.resolver, |name| {
+                    self.env.get(name).is_some_and(|value| !value.is_empty())
+                        || self.credentials.get_env(name).is_some()
+                });
+                check.blockers.extend(
+                    health
+                        .missing_programs
+                        .iter()
+                        .map(|program| format!("{} is not installed", program)),
+                );
+                check.warnings.extend(
+                    health
+                        .missing_credentials
+                        .iter()
+                        .map(|name| format!("Set {} to use the server", name)),
+                );
+            }
+            RecommendedItem::Automation { automation } => {
+                check.already_enabled = self
+                    .automations
+                    .list()
+                    .iter()
+                    .any(|existing| existing.name == automation.name);
+                if let AutomationAction::AgentTask { servers, .. } =
+                    &automation.action
+                {
+                    check.warnings.extend(
+                        servers
+                            .iter()
+                            .filter(|id| {
+                                !team.settings.mcp_servers.contains_key(*id)
+                            })
+                            .map(|id| format!("Needs the {} server", id)),
+                    );
+                }
+            }
+        }
+        check
+    }
+
+    /// Enable a recommendation by id after checking it
+    pub fn enable(&self, id: &str) -> Result<StackRecommendation> {
+        let (recommendation, check) = self
+            .recommendations()?
+            .into_iter()
+            .find(|(recommendation, _)| recommendation.id == id)
+            .ok_or_else(|| {
+                anyhow!("No recommendation '{}' for this workspace", id)
+            })?;
+        if check.already_enabled {
+            return Err(anyhow!("{} is already enabled", recommendation.name()));
+        }
+        if !check.blockers.is_empty() {
+            return Err(anyhow!(
+                "Cannot enable {}: {}",
+                recommendation.name(),
+                check.blockers.join("; ")
+            ));
+        }
+        match &recommendation.item {
+            RecommendedItem::McpServer { entry } => add_local_mcp_servers(
+                &self.workspace_root,
+                BTreeMap::from([(entry.id.to_string(), entry.team_server())]),
+            )?,
+            RecommendedItem::Automation { automation } => {
+                self.automations.add(automation.clone())?;
+            }
+        }
+        Ok(recommendation)
+    }
+}
+
+/// Sidebar panel listing the recommendations for the workspace's stack
+///
+/// Commands: `list` and `enable` with the recommendation `id`.
+pub struct StackPanel {
+    advisor: Arc<StackAdvisor<'static>>,
+}
+
+impl StackPanel {
+    pub const ID: &'static str = "catalyst.stack";
+
+    pub fn new(workspace_root: &Path, automations: Arc<AutomationStore>) -> Self {
+        Self {
+            advisor: Arc::new(StackAdvisor::from_env(workspace_root, automations)),
+        }
+    }
+
+    fn list(&self) -> Result<serde_json::Value> {
+        let recommendations: Vec<_> = self
+            .advisor
+            .recommendations()?
+            .into_iter()
+            .map(|(recommendation, check)| {
+                serde_json::json!({
+                    "recommendation": recommendation,
+                    "check": check,
+                })
+            })
+            .collect();
+        Ok(serde_json::json!(recommendations))
+    }
+}
+
+impl SidebarPanelPlugin for StackPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Connect Your Stack".to_string(),
+            description: "MCP servers and automations for this project".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(250),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let advisor = self.advisor.clone();
+        text_panel_view(move || match advisor.recommendations() {
+            Ok(recommendations) if recommendations.is_empty() => {
+                "Nothing to recommend for this workspace".to_string()
+            }
+            Ok(recommendations) => recommendations
+                .iter()
+                .map(|(recommendation, check)| {
+                    let state = if check.already_enabled {
+                        "enabled".to_string()
+                    } else if !check.blockers.is_empty() {
+                        check.blockers.join("; ")
+                    } else {
+                        "available".to_string()
+                    };
+                    format!(
+                        "{}  (found {})  {}",
+                        recommendation.name(),
+                        recommendation.signal.description(),
+                        state
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Err(err) => format!("{err:#}"),
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        self.list().unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        let outcome = match command.command_id.as_str() {
+            "list" => self.list(),
+            "enable" => command
+                .parameters
+                .get("id")
+                .and_then(|id| id.as_str())
+                .ok_or_else(|| anyhow!("Missing parameter 'id'"))
+                .and_then(|id| self.advisor.enable(id))
+                .and_then(|_| self.list()),
+            other => Err(anyhow!("Unknown command '{}'", other)),
+        };
+        Ok(match outcome {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::CommandPlatform;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stack_recommendations() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        std::fs::write(root.join("docker-compose.yml"), "services: {}\n").unwrap();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"dependencies": {"@sentry/node": "^8"}}"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join(".github/workflows")).unwrap();
+        std::fs::write(root.join(".github/workflows/ci.yml"), "on: push\n").unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(
+            root.join(".git/config"),
+            "[core]\n\tbare = false\n[remote \"origin\"]\n\
+             \turl = git@github.com:acme/app.git\n",
+        )
+        .unwrap();
+        // The repository claims the sentry id for its own command
+        std::fs::create_dir_all(root.join(TEAM_CONFIG_DIR)).unwrap();
+        std::fs::write(
+            root.join(TEAM_CONFIG_DIR).join(TEAM_SETTINGS_FILE),
+            "[mcp_servers.sentry]\ncommand = [\"./scripts/sentry.sh\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            detect_stack(root).into_iter().collect::<Vec<_>>(),
+            [
+                StackSignal::DockerCompose,
+                StackSignal::Npm,
+                StackSignal::GithubWorkflows,
+                StackSignal::Sentry,
+            ]
+        );
+
+        let bin = tempfile::tempdir().unwrap();
+        for program in ["npx", "uvx"] {
+            let path = bin.path().join(program);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+        let resolver = CommandResolver::new(
+            CommandPlatform::Unix,
+            Some(bin.path().as_os_str().to_owned()),
+            None,
+            None,
+        );
+        let data = tempfile::tempdir().unwrap();
+        let credentials =
+            CredentialStore::open(&data.path().join("credentials.toml")).unwrap();
+        let automations = Arc::new(
+            AutomationStore::open(&data.path().join("automations.json")).unwrap(),
+        );
+        let advisor = StackAdvisor::new(
+            root,
+            resolver,
+            &credentials,
+            HashMap::new(),
+            automations.clone(),
+        );
+
+        let recommendations: BTreeMap<String, TrustCheck> = advisor
+            .recommendations()
+            .unwrap()
+            .into_iter()
+            .map(|(r, c)| (r.id, c))
+            .collect();
+        assert_eq!(
+            recommendations.keys().collect::<Vec<_>>(),
+            [
+                "automation:Explain TypeScript and JavaScript errors",
+                "automation:Review new pull requests",
+                "mcp:docker",
+                "mcp:github",
+                "mcp:sentry",
+            ]
+        );
+        assert_eq!(
+            recommendations["mcp:docker"].blockers,
+            ["docker is not installed"]
+        );
+        assert!(recommendations["mcp:github"].allows_enabling());
+        assert_eq!(
+            recommendations["mcp:github"].warnings,
+            ["Set GITHUB_PERSONAL_ACCESS_TOKEN to use the server"]
+        );
+        assert!(!recommendations["mcp:sentry"].allows_enabling());
+        assert!(advisor.enable("mcp:sentry").is_err());
+        assert!(advisor.enable("mcp:docker").is_err());
+
+        advisor.enable("mcp:github").unwrap();
+        advisor
+            .enable("automation:Review new pull requests")
+            .unwrap();
+        let team = TeamConfig::load(root).unwrap();
+        assert!(team.settings.mcp_servers.contains_key("github"));
+        let automation = &automations.list()[0];
+        assert_eq!(
+            automation.trigger,
+            AutomationTrigger::PullRequestOpened {
+                repository: "acme/app".to_string()
+            }
+        );
+
+        let recommendations: BTreeMap<String, TrustCheck> = advisor
+            .recommendations()
+            .unwrap()
+            .into_iter()
+            .map(|(r, c)| (r.id, c))
+            .collect();
+        assert!(recommendations["mcp:github"].already_enabled);
+        assert!(
+            recommendations["automation:Review new pull requests"].already_enabled
+        );
+        assert!(
+            recommendations["automation:Review new pull requests"]
+                .warnings
+                .is_empty()
+        );
+        assert!(advisor.enable("mcp:github").is_err());
+    }
+}
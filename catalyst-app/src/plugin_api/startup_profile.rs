@@ -0,0 +1,303 @@
+//! Startup Profiling
+//!
+//! This module records how long startup phases take and, when launched with
+//! `--profile-startup`, samples what every instrumented thread is doing
+//! during the first seconds of execution. Samples are written in the folded
+//! stack format (`thread;phase;subphase count` per line) that `flamegraph.pl`,
+//! `inferno-flamegraph` and speedscope read directly, so contributors can
+//! ask users for a profile of a slow start instead of guessing from their
+//! own machine.
+//!
+//! Stacks are built from [`startup_scope`] guards rather than native
+//! frames, which keeps sampling portable and safe at the cost of only
+//! seeing instrumented code.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Default time between two samples
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+static SAMPLING: AtomicBool = AtomicBool::new(false);
+static THREADS: Lazy<Mutex<Vec<ThreadStack>>> = Lazy::new(Default::default);
+static PHASES: Lazy<Mutex<Vec<PhaseTiming>>> = Lazy::new(Default::default);
+static PROCESS_START: Lazy<Instant> = Lazy::new(Instant::now);
+
+thread_local! {
+    static STACK: RefCell<Option<Arc<Mutex<Vec<&'static str>>>>> =
+        const { RefCell::new(None) };
+}
+
+struct ThreadStack {
+    name: String,
+    stack: Arc<Mutex<Vec<&'static str>>>,
+}
+
+/// Duration of a completed startup phase
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseTiming {
+    pub name: &'static str,
+    pub thread: String,
+    /// Time since the process started when the phase began
+    pub started_at: Duration,
+    pub duration: Duration,
+}
+
+/// Marks a running phase until dropped
+pub struct ScopeGuard {
+    name: &'static str,
+    started: Instant,
+    sampled: bool,
+}
+
+/// Enter a named phase on the current thread
+///
+/// Nested scopes become nested frames in the profile. Timing is always
+/// recorded; the scope only shows up in samples while a profile runs.
+pub fn startup_scope(name: &'static str) -> ScopeGuard {
+    Lazy::force(&PROCESS_START);
+    let sampled = SAMPLING.load(Ordering::Relaxed);
+    if sampled {
+        with_thread_stack(|stack| stack.lock().push(name));
+    }
+    ScopeGuard {
+        name,
+        started: Instant::now(),
+        sampled,
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        if self.sampled {
+            with_thread_stack(|stack| {
+                stack.lock().pop();
+            });
+        }
+        PHASES.lock().push(PhaseTiming {
+            name: self.name,
+            thread: current_thread_name(),
+            started_at: self.started.duration_since(*PROCESS_START),
+            duration: self.started.elapsed(),
+        });
+    }
+}
+
+/// Get the phases completed so far, in completion order
+pub fn startup_phases() -> Vec<PhaseTiming> {
+    PHASES.lock().clone()
+}
+
+fn with_thread_stack(f: impl FnOnce(&Arc<Mutex<Vec<&'static str>>>)) {
+    STACK.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let stack = slot.get_or_insert_with(|| {
+            let stack = Arc::new(Mutex::new(Vec::new()));
+            THREADS.lock().push(ThreadStack {
+                name: current_thread_name(),
+                stack: stack.clone(),
+            });
+            stack
+        });
+        f(stack);
+    });
+}
+
+fn current_thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+/// Samples instrumented threads into a folded stack file
+///
+/// Dropping the profiler stops sampling early and writes the profile, so
+/// an early exit still leaves one behind.
+pub struct StartupProfiler {
+    stop: Arc<AtomicBool>,
+    /// Taken once the profile is written
+    sampler: Option<JoinHandle<Result<PathBuf>>>,
+}
+
+impl StartupProfiler {
+    /// Start sampling for `duration`, writing the profile to `output` once
+    /// done
+    pub fn start(
+        output: PathBuf,
+        duration: Duration,
+        interval: Duration,
+    ) -> Result<Self> {
+        Lazy::force(&PROCESS_START);
+        SAMPLING.store(true, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        let sampler = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("startup-profiler".to_string())
+                .spawn(move || {
+                    let samples = sample(&stop, duration, interval);
+                    SAMPLING.store(false, Ordering::Relaxed);
+                    write_folded(&output, &samples)?;
+                    tracing::info!(
+                        "Startup profile written to {}",
+                        output.display()
+                    );
+                    Ok(output)
+                })?
+        };
+        Ok(Self {
+            stop,
+            sampler: Some(sampler),
+        })
+    }
+
+    /// Stop sampling early and wait for the profile to be written
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<PathBuf> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.sampler
+            .take()
+            .ok_or_else(|| {
+                anyhow::anyhow!("The startup profile was already written")
+            })?
+            .join()
+            .map_err(|_| anyhow::anyhow!("The startup profiler panicked"))?
+    }
+
+    /// Get the default location of a startup profile
+    pub fn default_output(logs_directory: &Path) -> PathBuf {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        logs_directory.join(format!("startup-{timestamp}.folded"))
+    }
+}
+
+impl Drop for StartupProfiler {
+    fn drop(&mut self) {
+        if self.sampler.is_none() {
+            return;
+        }
+        if let Err(err) = self.flush() {
+            tracing::error!("Failed to write the startup profile: {err:#}");
+        }
+    }
+}
+
+fn sample(
+    stop: &AtomicBool,
+    duration: Duration,
+    interval: Duration,
+) -> HashMap<String, u64> {
+    let deadline = Instant::now() + duration;
+    let mut samples: HashMap<String, u64> = HashMap::new();
+    while Instant::now() < deadline && !stop.load(Ordering::Relaxed) {
+        for thread in THREADS.lock().iter() {
+            let stack = thread.stack.lock();
+            // Threads outside any scope are idle or uninstrumented
+            if stack.is_empty() {
+                continue;
+            }
+            let mut folded = thread.name.replace(';', ":");
+            for frame in stack.iter() {
+                folded.push(';');
+                folded.push_str(frame);
+            }
+            *samples.entry(folded).or_default() += 1;
+        }
+        std::thread::sleep(interval);
+    }
+    samples
+}
+
+fn write_folded(output: &Path, samples: &HashMap<String, u64>) -> Result<()> {
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut lines: Vec<_> = samples.iter().collect();
+    lines.sort();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
+    for (stack, count) in lines {
+        writeln!(file, "{stack} {count}")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sampling is global, so profiles of the tests must not overlap
+    static PROFILING: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_writes_folded_stacks_of_nested_scopes() {
+        let _profiling = PROFILING.lock();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("startup.folded");
+        let profiler = StartupProfiler::start(
+            output.clone(),
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+        )
+        .unwrap();
+
+        std::thread::Builder::new()
+            .name("loader".to_string())
+            .spawn(|| {
+                let _config = startup_scope("load_config");
+                let _themes = startup_scope("load_themes");
+                std::thread::sleep(Duration::from_millis(50));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let path = profiler.finish().unwrap();
+        let profile = std::fs::read_to_string(path).unwrap();
+        assert!(profile.lines().any(|line| {
+            line.starts_with("loader;load_config;load_themes ")
+                && line.rsplit(' ').next().unwrap().parse::<u64>().unwrap() > 0
+        }));
+        assert!(
+            startup_phases()
+                .iter()
+                .any(|phase| phase.name == "load_config")
+        );
+    }
+
+    #[test]
+    fn test_dropping_the_profiler_writes_the_profile() {
+        let _profiling = PROFILING.lock();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("startup.folded");
+        let profiler = StartupProfiler::start(
+            output.clone(),
+            Duration::from_secs(60),
+            Duration::from_millis(1),
+        )
+        .unwrap();
+        {
+            let _scope = startup_scope("early_exit");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        drop(profiler);
+        assert!(output.exists());
+    }
+}
@@ -0,0 +1,407 @@
+//! Stdio MCP Servers
+//!
+//! This module runs the MCP servers configured by the user and the team as
+//! child processes speaking JSON-RPC over stdin and stdout. Every server is
+//! spawned with [`ManagedChild::spawn_server`], so its command is resolved
+//! for the platform by the [`CommandResolver`], its resource limits are
+//! applied and it is recorded in the [`ProcessRegistry`] until it exits.
+//! Anything the server writes to stderr ends up in the log.
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use parking_lot::{Mutex, RwLock};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::plugin_api::{
+    CommandResolver, DEFAULT_SHUTDOWN_GRACE, LineConnection, ManagedChild,
+    McpCaller, McpClientError, McpNotification, McpNotificationSink, McpRequest,
+    McpRequestHandler, McpResource, McpResourceContent, McpResponse,
+    McpServerHealth, McpServerInfo, McpServerPlugin, McpServerStatus, McpTool,
+    McpToolResult, ProcessRegistry, TransportCaller, new_correlation_id,
+};
+
+type StdinConnection = LineConnection<Box<dyn Write + Send>>;
+
+/// MCP server running as a child process
+pub struct StdioMcpServer {
+    info: McpServerInfo,
+    resolver: CommandResolver,
+    processes: ProcessRegistry,
+    notification_sink: Option<McpNotificationSink>,
+    request_handler: Option<McpRequestHandler>,
+    process: Mutex<Option<ManagedChild>>,
+    connection: Option<StdinConnection>,
+    caller: Option<Arc<TransportCaller>>,
+    started_at: Option<Instant>,
+    last_error: RwLock<Option<String>>,
+    request_count: AtomicU64,
+    error_count: AtomicU64,
+}
+
+impl StdioMcpServer {
+    /// Create a server spawning the command of `info`, recorded in
+    /// `processes` while it runs
+    pub fn new(
+        info: McpServerInfo,
+        resolver: CommandResolver,
+        processes: ProcessRegistry,
+    ) -> Self {
+        Self {
+            info,
+            resolver,
+            processes,
+            notification_sink: None,
+            request_handler: None,
+            process: Mutex::new(None),
+            connection: None,
+            caller: None,
+            started_at: None,
+            last_error: RwLock::new(None),
+            request_count: AtomicU64::new(0),
+            error_count: AtomicU64::new(0),
+        }
+    }
+
+    fn connection(&self) -> Result<&StdinConnection> {
+        self.connection
+            .as_ref()
+            .ok_or_else(|| anyhow!("MCP server '{}' is not running", self.info.id))
+    }
+
+    /// Send a request and get its result, counting failures
+    fn request(&self, method: &str, params: Option<Value>) -> Result<Value> {
+        let result = self
+            .send_request(McpRequest {
+                jsonrpc: "2.0".to_string(),
+                id: new_correlation_id().into(),
+                method: method.to_string(),
+                params,
+            })
+            .and_then(|response| {
+                McpClientError::result_of(method, response).map_err(Into::into)
+            });
+        if let Err(err) = &result {
+            self.record_error(err);
+        }
+        result
+    }
+
+    fn record_error(&self, err: &anyhow::Error) {
+        self.error_count.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.write() = Some(format!("{err:#}"));
+    }
+}
+
+/// Log what a server writes to stderr until it exits
+fn forward_stderr(server_id: &str, stderr: impl std::io::Read + Send + 'static) {
+    let server_id = server_id.to_string();
+    let spawned = std::thread::Builder::new()
+        .name(format!("mcp-stderr-{server_id}"))
+        .spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                tracing::debug!(server_id = &*server_id, "{}", line);
+            }
+        });
+    if let Err(err) = spawned {
+        tracing::warn!("Failed to read the stderr of MCP server: {err}");
+    }
+}
+
+fn resource_from_wire(resource: &Value) -> Option<McpResource> {
+    Some(McpResource {
+        uri: resource["uri"].as_str()?.to_string(),
+        name: resource["name"].as_str().unwrap_or_default().to_string(),
+        description: resource["description"].as_str().map(str::to_string),
+        mime_type: resource["mimeType"].as_str().map(str::to_string),
+    })
+}
+
+impl McpServerPlugin for StdioMcpServer {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn server_info(&self) -> McpServerInfo {
+        self.info.clone()
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if self.is_running() {
+            return Ok(());
+        }
+        self.stop()?;
+
+        let mut process =
+            ManagedChild::spawn_server(&self.info, &self.resolver, &self.processes)?;
+        let child = process.child_mut();
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take())
+        else {
+            return Err(anyhow!(
+                "MCP server '{}' was started without stdio",
+                self.info.id
+            ));
+        };
+        if let Some(stderr) = child.stderr.take() {
+            forward_stderr(&self.info.id, stderr);
+        }
+
+        let connection = LineConnection::spawn(
+            BufReader::new(stdout),
+            Box::new(stdin) as Box<dyn Write + Send>,
+            self.notification_sink
+                .as_ref()
+                .map(McpNotificationSink::callback),
+            self.request_handler
+                .as_ref()
+                .map(McpRequestHandler::callback),
+        )?;
+        self.caller = Some(Arc::new(TransportCaller::new(connection.clone())));
+        self.connection = Some(connection);
+        *self.process.lock() = Some(process);
+        self.started_at = Some(Instant::now());
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.caller = None;
+        self.connection = None;
+        self.started_at = None;
+        let Some(process) = self.process.lock().take() else {
+            return Ok(());
+        };
+        process.shutdown(DEFAULT_SHUTDOWN_GRACE)?;
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.process
+            .lock()
+            .as_mut()
+            .is_some_and(|process| matches!(process.try_wait(), Ok(None)))
+    }
+
+    fn health_check(&self) -> McpServerHealth {
+        let status = match self.process.lock().as_mut() {
+            None => McpServerStatus::Stopped,
+            Some(process) => match process.try_wait() {
+                Ok(None) => McpServerStatus::Running,
+                Ok(Some(exit)) => process.exit_status(&exit),
+                Err(err) => {
+                    self.record_error(&err);
+                    McpServerStatus::Error
+                }
+            },
+        };
+        McpServerHealth {
+            status,
+            last_error: self.last_error.read().clone(),
+            uptime: self.started_at.map(|started_at| started_at.elapsed()),
+            request_count: self.request_count.load(Ordering::Relaxed),
+            error_count: self.error_count.load(Ordering::Relaxed),
+        }
+    }
+
+    fn send_request(&self, request: McpRequest) -> Result<McpResponse> {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        self.connection()?.request(&request)
+    }
+
+    fn send_batch(
+        &self,
+        requests: Vec<McpRequest>,
+    ) -> Result<Vec<Result<McpResponse>>> {
+        self.request_count
+            .fetch_add(requests.len() as u64, Ordering::Relaxed);
+        self.connection()?.request_batch(&requests)
+    }
+
+    fn get_tools(&self) -> Result<Vec<McpTool>> {
+        let result = self.request("tools/list", None)?;
+        result["tools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|tool| McpTool::from_wire(tool.clone()).map_err(Into::into))
+            .collect()
+    }
+
+    fn get_resources(&self) -> Result<Vec<McpResource>> {
+        if !self.info.capabilities.resources {
+            return Ok(Vec::new());
+        }
+        let result = self.request("resources/list", None)?;
+        Ok(result["resources"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(resource_from_wire)
+            .collect())
+    }
+
+    fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<McpToolResult> {
+        let caller = self.caller().ok_or_else(|| {
+            anyhow!("MCP server '{}' is not running", self.info.id)
+        })?;
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        let result = caller.call_tool(&new_correlation_id(), tool_name, arguments);
+        if let Err(err) = &result {
+            self.record_error(err);
+        }
+        result
+    }
+
+    fn caller(&self) -> Option<Arc<dyn McpCaller>> {
+        self.caller
+            .clone()
+            .map(|caller| caller as Arc<dyn McpCaller>)
+    }
+
+    fn read_resource(&self, resource_uri: &str) -> Result<McpResourceContent> {
+        let result =
+            self.request("resources/read", Some(json!({ "uri": resource_uri })))?;
+        let content = &result["contents"][0];
+        let blob = match content["blob"].as_str() {
+            Some(blob) => Some(general_purpose::STANDARD.decode(blob)?),
+            None => None,
+        };
+        Ok(McpResourceContent {
+            uri: content["uri"].as_str().unwrap_or(resource_uri).to_string(),
+            mime_type: content["mimeType"].as_str().map(str::to_string),
+            text: content["text"].as_str().map(str::to_string),
+            blob,
+        })
+    }
+
+    fn subscribe_to_resource(&self, resource_uri: &str) -> Result<()> {
+        self.request("resources/subscribe", Some(json!({ "uri": resource_uri })))?;
+        Ok(())
+    }
+
+    fn unsubscribe_from_resource(&self, resource_uri: &str) -> Result<()> {
+        self.request(
+            "resources/unsubscribe",
+            Some(json!({ "uri": resource_uri })),
+        )?;
+        Ok(())
+    }
+
+    fn send_notification(&self, notification: McpNotification) -> Result<()> {
+        self.connection()?
+            .notify(&notification.method, notification.params)
+    }
+
+    fn set_notification_sink(&mut self, sink: McpNotificationSink) {
+        self.notification_sink = Some(sink);
+    }
+
+    fn set_request_handler(&mut self, handler: McpRequestHandler) {
+        self.request_handler = Some(handler);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{
+        MCP_PROTOCOL_VERSION, McpResourceLimits, McpServerCapabilities,
+    };
+    use std::collections::HashMap;
+
+    /// Answers `initialize` and `tools/list`, ignoring everything else
+    fn fake_server_script() -> String {
+        format!(
+            r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\("[^"]*"\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) printf '{{"jsonrpc":"2.0","id":%s,"result":{{"protocolVersion":"{}","capabilities":{{}}}}}}\n' "$id" ;;
+    *'"tools/list"'*) printf '{{"jsonrpc":"2.0","id":%s,"result":{{"tools":[{{"name":"echo","inputSchema":{{"type":"object"}}}}]}}}}\n' "$id" ;;
+  esac
+done"#,
+            MCP_PROTOCOL_VERSION
+        )
+    }
+
+    fn server_info(script: String) -> McpServerInfo {
+        McpServerInfo {
+            id: "fake".to_string(),
+            name: "Fake".to_string(),
+            description: String::new(),
+            version: String::new(),
+            command: vec!["sh".to_string(), "-c".to_string(), script],
+            args: Vec::new(),
+            env: HashMap::new(),
+            working_directory: None,
+            auto_start: false,
+            keep_warm: false,
+            capabilities: McpServerCapabilities {
+                tools: true,
+                resources: false,
+                prompts: false,
+                logging: false,
+                experimental: HashMap::new(),
+            },
+            resource_limits: McpResourceLimits::default(),
+            call_limits: Default::default(),
+            request_timeout_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_server_process_is_registered_while_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let processes = ProcessRegistry::open(dir.path()).unwrap();
+        let mut server = StdioMcpServer::new(
+            server_info(fake_server_script()),
+            CommandResolver::from_env(),
+            processes.clone(),
+        );
+
+        server.start().unwrap();
+        assert!(server.is_running());
+        let records = processes.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].server_id, "fake");
+        assert_eq!(records[0].owner_pid, std::process::id());
+
+        server.handshake().unwrap();
+        let tools = server.get_tools().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+
+        server.stop().unwrap();
+        assert!(!server.is_running());
+        assert!(processes.records().is_empty());
+        assert_eq!(server.health_check().status, McpServerStatus::Stopped);
+    }
+
+    #[test]
+    fn test_exited_server_is_reported_as_crashed() {
+        let dir = tempfile::tempdir().unwrap();
+        let processes = ProcessRegistry::open(dir.path()).unwrap();
+        let mut server = StdioMcpServer::new(
+            server_info("exit 3".to_string()),
+            CommandResolver::from_env(),
+            processes.clone(),
+        );
+
+        server.start().unwrap();
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while server.is_running() && Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!server.is_running());
+        assert_eq!(server.health_check().status, McpServerStatus::Crashed);
+        assert!(processes.records().is_empty());
+    }
+}
@@ -0,0 +1,668 @@
+//! Style Profile
+//!
+//! This module infers the conventions of a codebase from a sample of its
+//! files, per language: indentation, how functions are named, how code is
+//! documented, the preferred string quotes, the usual line width and the
+//! test framework. The profile is turned into a few compact rules added to
+//! the system prompt, so generated code matches the project without anyone
+//! writing the rules by hand; rules in `.catalyst/rules/` still take
+//! precedence. The rules also ask the assistant to reply in the language
+//! the user writes in.
+//!
+//! Extraction reads up to a few hundred files, so run it in the background
+//! and keep the profile until the workspace changes substantially.
+
+use anyhow::Result;
+use ignore::WalkBuilder;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    AiMessage, AiMessageRequest, MessageRole, WorkspaceSandbox,
+};
+
+/// Files sampled per language
+const MAX_SAMPLE_FILES: usize = 50;
+/// Files larger than this are skipped, as they are usually generated
+const MAX_SAMPLE_BYTES: u64 = 256 * 1024;
+/// Observations needed before a convention is inferred
+const MIN_EVIDENCE: usize = 3;
+/// Share of the lines the inferred line width covers
+const LINE_WIDTH_PERCENTILE: f64 = 0.95;
+
+const REPLY_LANGUAGE_RULE: &str = "Reply in the language the user writes in; \
+    keep code, identifiers and comments in the project's language.";
+
+static RUST_FUNCTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\bfn\s+([A-Za-z_]\w*)").unwrap());
+static PYTHON_FUNCTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*(?:async\s+)?def\s+([A-Za-z_]\w*)").unwrap());
+static JS_FUNCTION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"\bfunction\s*\*?\s*([A-Za-z_$][\w$]*)|\b(?:const|let)\s+([A-Za-z_$][\w$]*)\s*=\s*(?:async\s*)?(?:\([^)]*\)|[A-Za-z_$][\w$]*)\s*=>",
+    )
+    .unwrap()
+});
+static JAVA_METHOD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\s*(?:(?:public|private|protected|static|final|abstract|synchronized)\s+)+[\w<>\[\], ?]+\s+([A-Za-z_]\w*)\s*\(",
+    )
+    .unwrap()
+});
+static GO_FUNCTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^func\s+(?:\([^)]*\)\s*)?([A-Za-z_]\w*)").unwrap());
+static SINGLE_QUOTED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"'(?:[^'\\\n]|\\.)*'").unwrap());
+static DOUBLE_QUOTED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#""(?:[^"\\\n]|\\.)*""#).unwrap());
+
+/// A language the style of which can be inferred
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleLanguage {
+    Rust,
+    Python,
+    Go,
+    JavaScript,
+    TypeScript,
+    Java,
+}
+
+impl StyleLanguage {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Some(match path.extension()?.to_str()? {
+            "rs" => Self::Rust,
+            "py" => Self::Python,
+            "go" => Self::Go,
+            "js" | "jsx" | "mjs" | "cjs" => Self::JavaScript,
+            "ts" | "tsx" | "mts" | "cts" => Self::TypeScript,
+            "java" => Self::Java,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rust => "Rust",
+            Self::Python => "Python",
+            Self::Go => "Go",
+            Self::JavaScript => "JavaScript",
+            Self::TypeScript => "TypeScript",
+            Self::Java => "Java",
+        }
+    }
+
+    fn uses_quotes(&self) -> bool {
+        matches!(self, Self::Python | Self::JavaScript | Self::TypeScript)
+    }
+}
+
+/// Indentation of a language's files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "width", rename_all = "snake_case")]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+/// Naming convention of functions
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NamingCase {
+    Snake,
+    Camel,
+    Pascal,
+}
+
+impl NamingCase {
+    /// Classify a name; single lowercase words fit any case and give `None`
+    fn of(name: &str) -> Option<Self> {
+        let name = name.trim_matches('_');
+        let first = name.chars().next()?;
+        let has_upper = name.chars().skip(1).any(|c| c.is_uppercase());
+        let has_lower = name.chars().any(|c| c.is_lowercase());
+        if name.contains('_') {
+            (!name.chars().any(|c| c.is_uppercase())).then_some(Self::Snake)
+        } else if first.is_uppercase() && has_lower {
+            Some(Self::Pascal)
+        } else if first.is_lowercase() && has_upper {
+            Some(Self::Camel)
+        } else {
+            None
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Snake => "snake_case",
+            Self::Camel => "camelCase",
+            Self::Pascal => "PascalCase",
+        }
+    }
+}
+
+/// Conventions of the files of one language
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageStyle {
+    pub language: StyleLanguage,
+    /// Files the conventions were inferred from
+    pub files: usize,
+    pub indent: Option<Indent>,
+    pub function_case: Option<NamingCase>,
+    /// How items are documented, e.g. "`///` doc comments"
+    pub doc_style: Option<String>,
+    /// Preferred quote of string literals
+    pub quote: Option<char>,
+    /// Columns almost all lines fit in
+    pub line_width: Option<usize>,
+    pub test_framework: Option<String>,
+}
+
+impl LanguageStyle {
+    /// Get the conventions as one compact rule, or `None` if none were
+    /// inferred
+    pub fn rule(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        match self.indent {
+            Some(Indent::Tabs) => parts.push("tab indent".to_string()),
+            Some(Indent::Spaces(width)) => {
+                parts.push(format!("{}-space indent", width))
+            }
+            None => {}
+        }
+        if let Some(case) = self.function_case {
+            parts.push(format!("{} functions", case.label()));
+        }
+        if let Some(doc_style) = &self.doc_style {
+            parts.push(doc_style.clone());
+        }
+        match self.quote {
+            Some('\'') => parts.push("single quotes".to_string()),
+            Some('"') => parts.push("double quotes".to_string()),
+            _ => {}
+        }
+        if let Some(width) = self.line_width {
+            parts.push(format!("lines under {} columns", width));
+        }
+        if let Some(framework) = &self.test_framework {
+            parts.push(format!("tests with {}", framework));
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        Some(format!("- {}: {}", self.language.name(), parts.join(", ")))
+    }
+}
+
+/// Observations collected from the sampled files of a language
+#[derive(Default)]
+struct Evidence {
+    files: usize,
+    tab_lines: usize,
+    /// Indentation increases between consecutive lines, by width
+    indent_steps: HashMap<usize, usize>,
+    cases: HashMap<NamingCase, usize>,
+    docs: HashMap<&'static str, usize>,
+    single_quotes: usize,
+    double_quotes: usize,
+    line_widths: Vec<usize>,
+    frameworks: HashMap<&'static str, usize>,
+}
+
+impl Evidence {
+    fn add_file(&mut self, language: StyleLanguage, path: &Path, text: &str) {
+        self.files += 1;
+        let mut previous_indent = 0;
+        let mut previous_line = "";
+        let mut in_docstring = false;
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                continue;
+            }
+            self.line_widths.push(line.chars().count());
+
+            let leading = &line[..line.len() - trimmed.len()];
+            if leading.starts_with('\t') {
+                self.tab_lines += 1;
+            } else {
+                let indent = leading.len();
+                if indent > previous_indent {
+                    *self
+                        .indent_steps
+                        .entry(indent - previous_indent)
+                        .or_default() += 1;
+                }
+                previous_indent = indent;
+            }
+
+            if let Some(name) = function_name(language, line) {
+                if let Some(case) = NamingCase::of(name) {
+                    *self.cases.entry(case).or_default() += 1;
+                }
+                if language == StyleLanguage::Go
+                    && previous_line.starts_with(&format!("// {}", name))
+                {
+                    *self.docs.entry("`// Name ...` doc comments").or_default() += 1;
+                }
+            }
+            self.add_doc_line(language, trimmed, &mut in_docstring);
+
+            if language.uses_quotes()
+                && !trimmed.starts_with('#')
+                && !trimmed.starts_with("//")
+                && !trimmed.contains("\"\"\"")
+                && !trimmed.contains("'''")
+            {
+                self.single_quotes += SINGLE_QUOTED.find_iter(trimmed).count();
+                self.double_quotes += DOUBLE_QUOTED.find_iter(trimmed).count();
+            }
+            self.add_framework_line(language, trimmed);
+            previous_line = trimmed;
+        }
+
+        if language == StyleLanguage::Rust {
+            if text.contains("#[cfg(test)]") {
+                *self
+                    .frameworks
+                    .entry("inline `#[cfg(test)]` modules")
+                    .or_default() += 1;
+            } else if path.components().any(|c| c.as_os_str() == "tests") {
+                *self
+                    .frameworks
+                    .entry("integration tests in `tests/`")
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    fn add_doc_line(
+        &mut self,
+        language: StyleLanguage,
+        line: &str,
+        in_docstring: &mut bool,
+    ) {
+        let style = match language {
+            StyleLanguage::Rust if line.starts_with("///") => "`///` doc comments",
+            StyleLanguage::Rust if line.starts_with("/**") => {
+                "`/** */` doc comments"
+            }
+            StyleLanguage::JavaScript | StyleLanguage::TypeScript
+                if line.starts_with("/**") =>
+            {
+                "JSDoc `/** */` comments"
+            }
+            StyleLanguage::Java if line.starts_with("/**") => "Javadoc comments",
+            StyleLanguage::Python => {
+                let quotes =
+                    line.matches("\"\"\"").count() + line.matches("'''").count();
+                if *in_docstring {
+                    let style = if line.starts_with("Args:")
+                        || line.starts_with("Returns:")
+                    {
+                        Some("Google-style docstrings")
+                    } else if line.starts_with(":param")
+                        || line.starts_with(":return")
+                    {
+                        Some("Sphinx-style docstrings")
+                    } else if line.starts_with("----") {
+                        Some("NumPy-style docstrings")
+                    } else {
+                        None
+                    };
+                    if let Some(style) = style {
+                        *self.docs.entry(style).or_default() += 1;
+                    }
+                }
+                if quotes % 2 == 1 {
+                    *in_docstring = !*in_docstring;
+                }
+                return;
+            }
+            _ => return,
+        };
+        *self.docs.entry(style).or_default() += 1;
+    }
+
+    fn add_framework_line(&mut self, language: StyleLanguage, line: &str) {
+        let framework = match language {
+            StyleLanguage::Python if line.starts_with("import pytest") => "pytest",
+            StyleLanguage::Python
+                if line.starts_with("import unittest")
+                    || line.starts_with("from unittest") =>
+            {
+                "unittest"
+            }
+            StyleLanguage::Go if line.contains("github.com/stretchr/testify") => {
+                "testify"
+            }
+            StyleLanguage::Go
+                if line == "\"testing\"" || line == "import \"testing\"" =>
+            {
+                "the standard `testing` package"
+            }
+            StyleLanguage::JavaScript | StyleLanguage::TypeScript
+                if line.contains("from 'vitest'")
+                    || line.contains("from \"vitest\"") =>
+            {
+                "vitest"
+            }
+            StyleLanguage::JavaScript | StyleLanguage::TypeScript
+                if line.contains("@jest/globals") =>
+            {
+                "jest"
+            }
+            StyleLanguage::Java if line.starts_with("import org.junit.jupiter") => {
+                "JUnit 5"
+            }
+            StyleLanguage::Java if line.starts_with("import org.junit.") => {
+                "JUnit 4"
+            }
+            StyleLanguage::Java if line.starts_with("import org.testng") => "TestNG",
+            _ => return,
+        };
+        *self.frameworks.entry(framework).or_default() += 1;
+    }
+
+    fn style(&self, language: StyleLanguage) -> LanguageStyle {
+        let space_lines: usize = self.indent_steps.values().sum();
+        let indent =
+            if self.tab_lines >= MIN_EVIDENCE && self.tab_lines > space_lines {
+                Some(Indent::Tabs)
+            } else {
+                // Larger steps are usually continuation lines
+                [2, 3, 4, 8]
+                    .into_iter()
+                    .map(|width| {
+                        (width, self.indent_steps.get(&width).copied().unwrap_or(0))
+                    })
+                    .filter(|(_, count)| *count >= MIN_EVIDENCE)
+                    .max_by_key(|(width, count)| (*count, std::cmp::Reverse(*width)))
+                    .map(|(width, _)| Indent::Spaces(width))
+            };
+
+        let quote = if !language.uses_quotes() {
+            None
+        } else if self.single_quotes >= MIN_EVIDENCE
+            && self.single_quotes > self.double_quotes * 2
+        {
+            Some('\'')
+        } else if self.double_quotes >= MIN_EVIDENCE
+            && self.double_quotes > self.single_quotes * 2
+        {
+            Some('"')
+        } else {
+            None
+        };
+
+        let line_width = if self.line_widths.len() >= MIN_EVIDENCE {
+            let mut widths = self.line_widths.clone();
+            widths.sort_unstable();
+            let index = ((widths.len() - 1) as f64 * LINE_WIDTH_PERCENTILE) as usize;
+            // Round up to a multiple of ten, as configured widths usually are
+            Some(widths[index].div_ceil(10) * 10)
+        } else {
+            None
+        };
+
+        LanguageStyle {
+            language,
+            files: self.files,
+            indent,
+            // Go's case carries visibility, so it isn't a convention
+            function_case: if language == StyleLanguage::Go {
+                None
+            } else {
+                majority(&self.cases, MIN_EVIDENCE).copied()
+            },
+            doc_style: majority(&self.docs, MIN_EVIDENCE)
+                .map(|style| style.to_string()),
+            quote,
+            line_width,
+            // Framework evidence is per file, so one file is enough
+            test_framework: majority(&self.frameworks, 1)
+                .map(|framework| framework.to_string()),
+        }
+    }
+}
+
+/// Get the most common observation, if it was seen often enough
+fn majority<T: Ord>(counts: &HashMap<T, usize>, min: usize) -> Option<&T> {
+    counts
+        .iter()
+        .filter(|(_, count)| **count >= min)
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(value, _)| value)
+}
+
+fn function_name(language: StyleLanguage, line: &str) -> Option<&str> {
+    let regex = match language {
+        StyleLanguage::Rust => &RUST_FUNCTION,
+        StyleLanguage::Python => &PYTHON_FUNCTION,
+        StyleLanguage::Go => &GO_FUNCTION,
+        StyleLanguage::JavaScript | StyleLanguage::TypeScript => &JS_FUNCTION,
+        StyleLanguage::Java => &JAVA_METHOD,
+    };
+    let captures = regex.captures(line)?;
+    captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .next()
+        .map(|name| name.as_str())
+}
+
+/// Test frameworks named in the dependencies of a `package.json`
+fn package_frameworks(text: &str) -> Vec<&'static str> {
+    let Ok(package) = serde_json::from_str::<serde_json::Value>(text) else {
+        return Vec::new();
+    };
+    ["vitest", "jest", "mocha", "ava"]
+        .into_iter()
+        .filter(|framework| {
+            ["dependencies", "devDependencies"]
+                .iter()
+                .any(|key| package[key].get(framework).is_some())
+        })
+        .collect()
+}
+
+/// Conventions of a project, per language
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StyleProfile {
+    /// Languages with the most files first
+    pub languages: Vec<LanguageStyle>,
+}
+
+impl StyleProfile {
+    /// Infer the conventions of a workspace from a sample of its files
+    pub fn extract(sandbox: &WorkspaceSandbox) -> Result<Self> {
+        let mut candidates: BTreeMap<StyleLanguage, Vec<PathBuf>> = BTreeMap::new();
+        let mut packages = Vec::new();
+        let walker_sandbox = sandbox.clone();
+        let walker = WalkBuilder::new(sandbox.root())
+            .filter_entry(move |entry| walker_sandbox.is_allowed(entry.path()))
+            .build();
+        for entry in walker.flatten() {
+            if !entry.file_type().is_some_and(|t| t.is_file())
+                || entry
+                    .metadata()
+                    .map_or(true, |metadata| metadata.len() > MAX_SAMPLE_BYTES)
+            {
+                continue;
+            }
+            let path = entry.path();
+            if path.file_name().is_some_and(|name| name == "package.json") {
+                packages.push(path.to_path_buf());
+            } else if let Some(language) = StyleLanguage::from_path(path) {
+                candidates
+                    .entry(language)
+                    .or_default()
+                    .push(path.to_path_buf());
+            }
+        }
+
+        let mut files = Vec::new();
+        for mut paths in candidates.into_values() {
+            paths.sort();
+            // Spread the sample over the whole tree
+            let step = paths.len().div_ceil(MAX_SAMPLE_FILES).max(1);
+            for path in paths.into_iter().step_by(step) {
+                if let Ok(text) = std::fs::read_to_string(&path) {
+                    files.push((path, text));
+                }
+            }
+        }
+        for path in packages {
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                files.push((path, text));
+            }
+        }
+        Ok(Self::from_files(&files))
+    }
+
+    /// Infer the conventions from files and their contents
+    ///
+    /// `package.json` files among them name the JavaScript test framework
+    /// when the sources don't.
+    pub fn from_files(files: &[(PathBuf, String)]) -> Self {
+        let mut evidence: BTreeMap<StyleLanguage, Evidence> = BTreeMap::new();
+        let mut package_frameworks_seen = Vec::new();
+        for (path, text) in files {
+            if path.file_name().is_some_and(|name| name == "package.json") {
+                package_frameworks_seen.extend(package_frameworks(text));
+            } else if let Some(language) = StyleLanguage::from_path(path) {
+                evidence
+                    .entry(language)
+                    .or_default()
+                    .add_file(language, path, text);
+            }
+        }
+
+        let mut languages: Vec<LanguageStyle> = evidence
+            .iter()
+            .map(|(language, evidence)| {
+                let mut style = evidence.style(*language);
+                if style.test_framework.is_none()
+                    && matches!(
+                        language,
+                        StyleLanguage::JavaScript | StyleLanguage::TypeScript
+                    )
+                {
+                    style.test_framework = package_frameworks_seen
+                        .first()
+                        .map(|framework| framework.to_string());
+                }
+                style
+            })
+            .collect();
+        languages
+            .sort_by(|a, b| b.files.cmp(&a.files).then(a.language.cmp(&b.language)));
+        Self { languages }
+    }
+
+    /// Get the compact rules added to the system prompt
+    pub fn rules(&self) -> String {
+        let mut rules = Vec::new();
+        let styles: Vec<String> = self
+            .languages
+            .iter()
+            .filter_map(|style| style.rule())
+            .collect();
+        if !styles.is_empty() {
+            rules.push(
+                "Code style of this project, inferred from its files; follow it \
+                 in code you write unless the user or the project rules say \
+                 otherwise:"
+                    .to_string(),
+            );
+            rules.extend(styles);
+        }
+        rules.push(REPLY_LANGUAGE_RULE.to_string());
+        rules.join("\n")
+    }
+
+    /// Add the rules to the system prompt of a request
+    pub fn apply(&self, request: &mut AiMessageRequest) {
+        let rules = self.rules();
+        match request
+            .messages
+            .iter_mut()
+            .find(|message| matches!(message.role, MessageRole::System))
+        {
+            Some(system) => {
+                system.content.push_str("\n\n");
+                system.content.push_str(&rules);
+            }
+            None => request.messages.insert(
+                0,
+                AiMessage {
+                    role: MessageRole::System,
+                    content: rules,
+                    timestamp: None,
+                    images: Vec::new(),
+                },
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_project_conventions() {
+        let rust = "/// Parse the config\n\
+                    pub fn parse_config() {\n    let x = 1;\n    if x > 0 {\n        \
+                    run_all();\n    }\n}\n\n\
+                    /// Load it\nfn load_file() {\n    read_all();\n}\n\n\
+                    #[cfg(test)]\nmod tests {\n    fn test_it() {}\n}\n";
+        let ts = "/** Fetch a user */\n\
+                  export function fetchUser(id) {\n  return get('/users/' + id);\n}\n\
+                  /** Save it */\n\
+                  export const saveUser = (user) => {\n  if (user) {\n    \
+                  put('/users', user);\n  }\n};\n";
+        let package = r#"{"devDependencies": {"vitest": "^1.0.0"}}"#;
+        let files = vec![
+            (PathBuf::from("/w/src/lib.rs"), rust.to_string()),
+            (PathBuf::from("/w/src/main.rs"), rust.to_string()),
+            (PathBuf::from("/w/web/user.ts"), ts.to_string()),
+            (PathBuf::from("/w/web/admin.ts"), ts.to_string()),
+            (PathBuf::from("/w/package.json"), package.to_string()),
+        ];
+        let profile = StyleProfile::from_files(&files);
+
+        let rust = &profile.languages[0];
+        assert_eq!(rust.language, StyleLanguage::Rust);
+        assert_eq!(rust.indent, Some(Indent::Spaces(4)));
+        assert_eq!(rust.function_case, Some(NamingCase::Snake));
+        assert_eq!(rust.doc_style.as_deref(), Some("`///` doc comments"));
+
+        let ts = &profile.languages[1];
+        assert_eq!(ts.indent, Some(Indent::Spaces(2)));
+        assert_eq!(ts.function_case, Some(NamingCase::Camel));
+        assert_eq!(ts.quote, Some('\''));
+        assert_eq!(ts.test_framework.as_deref(), Some("vitest"));
+
+        let mut request = AiMessageRequest {
+            messages: Vec::new(),
+            context: None,
+            tools: None,
+            model: None,
+            max_tokens: None,
+            temperature: None,
+        };
+        profile.apply(&mut request);
+        let rules = &request.messages[0].content;
+        assert!(rules.contains("- Rust: 4-space indent, snake_case functions"));
+        assert!(rules.ends_with(REPLY_LANGUAGE_RULE));
+    }
+}
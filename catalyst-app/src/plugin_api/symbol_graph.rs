@@ -0,0 +1,334 @@
+//! Symbol Graph
+//!
+//! This module extracts symbol definitions (functions, types, modules, ...)
+//! from source files and records which files reference them, so retrieval
+//! can answer queries that name an identifier.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    ContextChunk, PathId, PathInterner, RetrievalSource, Retriever, SharedPath,
+};
+
+static DEFINITION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?m)^\s*(?:export\s+)?(?:pub(?:\([^)]*\))?\s+)?(?:async\s+)?(?:default\s+)?(fn|struct|enum|trait|type|mod|const|static|class|interface|def|function)\s+([A-Za-z_][A-Za-z0-9_]*)",
+    )
+    .unwrap()
+});
+
+static IDENTIFIER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[A-Za-z_][A-Za-z0-9_]{2,}").unwrap());
+
+/// Kind of symbol definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolKind {
+    Function,
+    Type,
+    Module,
+    Constant,
+}
+
+/// A symbol definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolDefinition {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub path: SharedPath,
+    /// 1-based line of the definition
+    pub line: usize,
+}
+
+#[derive(Default)]
+struct SymbolGraphInner {
+    /// Definitions keyed by lowercase name
+    definitions: HashMap<String, Vec<SymbolDefinition>>,
+    /// Files referencing an identifier, keyed by lowercase name
+    references: HashMap<String, HashSet<PathId>>,
+    chunks: HashMap<PathId, Vec<ContextChunk>>,
+    /// Indexed files, keeping their ids interned
+    paths: HashMap<PathId, SharedPath>,
+}
+
+/// Definitions and references of symbols in the workspace
+#[derive(Default)]
+pub struct SymbolGraph {
+    inner: RwLock<SymbolGraphInner>,
+}
+
+impl SymbolGraph {
+    /// Create an empty symbol graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index a file, replacing anything previously indexed for it
+    pub fn index_file(&self, path: &Path, content: &str, chunks: Vec<ContextChunk>) {
+        self.remove_path(path);
+
+        let path = SharedPath::new(path);
+        let id = path.id();
+        let mut inner = self.inner.write();
+        for captures in DEFINITION.captures_iter(content) {
+            let (Some(keyword), Some(name)) = (captures.get(1), captures.get(2))
+            else {
+                continue;
+            };
+            let kind = match keyword.as_str() {
+                "fn" | "def" | "function" => SymbolKind::Function,
+                "mod" => SymbolKind::Module,
+                "const" | "static" => SymbolKind::Constant,
+                _ => SymbolKind::Type,
+            };
+            let line = content[..name.start()].matches('\n').count() + 1;
+            inner
+                .definitions
+                .entry(name.as_str().to_lowercase())
+                .or_default()
+                .push(SymbolDefinition {
+                    name: name.as_str().to_string(),
+                    kind,
+                    path: path.clone(),
+                    line,
+                });
+        }
+
+        for identifier in IDENTIFIER.find_iter(content) {
+            inner
+                .references
+                .entry(identifier.as_str().to_lowercase())
+                .or_default()
+                .insert(id);
+        }
+
+        inner.chunks.insert(id, chunks);
+        inner.paths.insert(id, path);
+        RetrievalSource::Symbol.mark_updated();
+    }
+
+    /// Remove everything indexed for a file
+    pub fn remove_path(&self, path: &Path) {
+        let id = PathInterner::global().id(path);
+        let mut inner = self.inner.write();
+        inner.definitions.retain(|_, definitions| {
+            definitions.retain(|definition| definition.path != path);
+            !definitions.is_empty()
+        });
+        inner.references.retain(|_, paths| {
+            paths.remove(&id);
+            !paths.is_empty()
+        });
+        inner.chunks.remove(&id);
+        inner.paths.remove(&id);
+    }
+
+    /// Get the definitions of a symbol
+    pub fn definitions(&self, name: &str) -> Vec<SymbolDefinition> {
+        self.inner
+            .read()
+            .definitions
+            .get(&name.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the files referencing a symbol
+    pub fn references(&self, name: &str) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .inner
+            .read()
+            .references
+            .get(&name.to_lowercase())
+            .map(|paths| {
+                let interner = PathInterner::global();
+                paths
+                    .iter()
+                    .map(|id| interner.resolve(*id).to_path_buf())
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    }
+
+    /// Get definitions whose name starts with a prefix, for completion
+    pub fn complete(&self, prefix: &str, limit: usize) -> Vec<SymbolDefinition> {
+        let prefix = prefix.to_lowercase();
+        let inner = self.inner.read();
+        let mut matches: Vec<SymbolDefinition> = inner
+            .definitions
+            .iter()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .flat_map(|(_, definitions)| definitions.iter().cloned())
+            .collect();
+        matches.sort_by(|a, b| {
+            a.name
+                .len()
+                .cmp(&b.name.len())
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        matches.truncate(limit);
+        matches
+    }
+
+    /// Get the indexed chunk containing a definition
+    pub fn definition_chunk(
+        &self,
+        definition: &SymbolDefinition,
+    ) -> Option<ContextChunk> {
+        self.inner
+            .read()
+            .chunks
+            .get(&definition.path.id())?
+            .iter()
+            .find(|chunk| chunk.contains_line(definition.line))
+            .cloned()
+    }
+
+    /// Find definitions matching the identifiers in a query, best first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(SymbolDefinition, f32)> {
+        let inner = self.inner.read();
+        let tokens: Vec<String> = IDENTIFIER
+            .find_iter(query)
+            .map(|token| token.as_str().to_lowercase())
+            .collect();
+
+        let mut scored: Vec<(SymbolDefinition, f32)> = Vec::new();
+        for (name, definitions) in &inner.definitions {
+            let score = tokens
+                .iter()
+                .map(|token| {
+                    if name == token {
+                        1.0
+                    } else if name.starts_with(token.as_str()) {
+                        0.6
+                    } else if name.contains(token.as_str()) {
+                        0.3
+                    } else {
+                        0.0
+                    }
+                })
+                .fold(0.0f32, f32::max);
+            if score == 0.0 {
+                continue;
+            }
+
+            // Widely referenced symbols are more central to the codebase
+            let references =
+                inner.references.get(name).map(|p| p.len()).unwrap_or(0);
+            let score = score * (1.0 + (references as f32).ln_1p() * 0.1);
+            for definition in definitions {
+                scored.push((definition.clone(), score));
+            }
+        }
+
+        scored.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| a.0.path.cmp(&b.0.path))
+                .then(a.0.line.cmp(&b.0.line))
+        });
+        scored.truncate(limit);
+        scored
+    }
+}
+
+impl Retriever for SymbolGraph {
+    fn source(&self) -> RetrievalSource {
+        RetrievalSource::Symbol
+    }
+
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>> {
+        let definitions = self.search(query, limit);
+        let inner = self.inner.read();
+
+        let mut seen = HashSet::new();
+        let mut chunks = Vec::new();
+        for (definition, _) in definitions {
+            let chunk = inner.chunks.get(&definition.path.id()).and_then(|chunks| {
+                chunks
+                    .iter()
+                    .find(|chunk| chunk.contains_line(definition.line))
+            });
+            if let Some(chunk) = chunk {
+                if seen.insert(chunk.key()) {
+                    chunks.push(chunk.clone());
+                }
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(graph: &SymbolGraph, path: &str, content: &str) {
+        let chunk = ContextChunk {
+            path: SharedPath::from(path),
+            start_line: 1,
+            end_line: content.lines().count(),
+            content: content.to_string(),
+            modified: None,
+        };
+        graph.index_file(Path::new(path), content, vec![chunk]);
+    }
+
+    #[test]
+    fn test_definitions_and_references() {
+        let graph = SymbolGraph::new();
+        index(
+            &graph,
+            "src/config.rs",
+            "pub struct Config;\n\npub(crate) fn load_config() -> Config {\n    Config\n}\n",
+        );
+        index(
+            &graph,
+            "src/main.rs",
+            "fn main() {\n    load_config();\n}\n",
+        );
+
+        let definitions = graph.definitions("LOAD_CONFIG");
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].kind, SymbolKind::Function);
+        assert_eq!(definitions[0].line, 3);
+        assert_eq!(graph.definitions("Config")[0].kind, SymbolKind::Type);
+        assert_eq!(
+            graph.references("load_config"),
+            [PathBuf::from("src/config.rs"), PathBuf::from("src/main.rs")]
+        );
+
+        let completions: Vec<_> = graph
+            .complete("load", 10)
+            .into_iter()
+            .map(|definition| definition.name)
+            .collect();
+        assert_eq!(completions, ["load_config"]);
+        let chunk = graph.definition_chunk(&definitions[0]).unwrap();
+        assert_eq!(chunk.path, Path::new("src/config.rs"));
+
+        // Exact names rank above partial matches
+        let found = graph.search("where is config loaded", 10);
+        assert_eq!(found[0].0.name, "Config");
+        let chunks = graph.retrieve("load_config", 10).unwrap();
+        assert_eq!(chunks.len(), 1);
+
+        // Reindexing replaces a file's symbols, removing drops them
+        index(&graph, "src/config.rs", "pub struct Settings;\n");
+        assert!(graph.definitions("Config").is_empty());
+        assert_eq!(
+            graph.references("load_config"),
+            [PathBuf::from("src/main.rs")]
+        );
+        graph.remove_path(Path::new("src/main.rs"));
+        assert!(graph.references("load_config").is_empty());
+        assert!(graph.definitions("main").is_empty());
+    }
+}
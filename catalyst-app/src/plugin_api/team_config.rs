@@ -0,0 +1,589 @@
+//! Team Configuration
+//!
+//! This module loads agent setup that a team commits to the repository under
+//! `.catalyst/`: the MCP server catalog and task definitions in
+//! `settings.toml`, prompt templates in `prompts/` and rules files in
+//! `rules/`. Each developer can override any of it in `.catalyst/local/`,
+//! which is kept out of version control, and can add MCP servers for every
+//! workspace in `mcp_servers.toml` in the config directory. Credentials never
+//! live in the shared files; environment values refer to variables with
+//! `${NAME}` instead, which may be defined for the workspace in
+//! `.catalyst/env`.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::plugin_api::{
+    CredentialStore, McpCallLimits, McpResourceLimits, McpServerCapabilities,
+    McpServerInfo, ProjectTask, apply_workspace_env, workspace_env,
+};
+
+/// Directory holding the shared team configuration
+pub const TEAM_CONFIG_DIR: &str = ".catalyst";
+/// Settings file inside the team configuration directory
+pub const TEAM_SETTINGS_FILE: &str = "settings.toml";
+/// Git-ignored directory with per-developer overrides
+pub const LOCAL_OVERRIDE_DIR: &str = "local";
+/// File in the config directory with the user's MCP servers for every
+/// workspace
+pub const USER_MCP_SETTINGS_FILE: &str = "mcp_servers.toml";
+
+const PROMPTS_DIR: &str = "prompts";
+const RULES_DIR: &str = "rules";
+const SECRET_MARKERS: &[&str] =
+    &["TOKEN", "KEY", "SECRET", "PASSWORD", "CREDENTIAL", "AUTH"];
+
+/// Part of the configuration that can be shared with the team
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TeamConfigSection {
+    McpCatalog,
+    PromptTemplates,
+    Rules,
+    Tasks,
+}
+
+/// An MCP server entry of the team catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamMcpServer {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment values, usually `${NAME}` references
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub working_directory: Option<String>,
+    #[serde(default)]
+    pub auto_start: bool,
+    /// Start the server after the window is up and keep it running
+    #[serde(default)]
+    pub keep_warm: bool,
+    /// Set to false in a local override to opt out of a team server
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub resource_limits: McpResourceLimits,
+    /// Concurrency and rate limits of the tool calls to the server
+    #[serde(default)]
+    pub call_limits: McpCallLimits,
+    /// Seconds a tool call may take before it is cancelled
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A task definition shared by the team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamTask {
+    pub command: Vec<String>,
+    /// Relative to the workspace root
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+}
+
+/// Contents of `settings.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TeamSettings {
+    #[serde(default)]
+    pub mcp_servers: BTreeMap<String, TeamMcpServer>,
+    #[serde(default)]
+    pub tasks: BTreeMap<String, TeamTask>,
+}
+
+/// Configuration of one layer, the shared files or the local overrides
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TeamLayer {
+    pub settings: TeamSettings,
+    /// Prompt templates keyed by file stem
+    pub prompt_templates: BTreeMap<String, String>,
+    /// Rules files keyed by file stem
+    pub rules: BTreeMap<String, String>,
+}
+
+/// Team configuration merged with the local overrides
+#[derive(Debug, Clone, Default)]
+pub struct TeamConfig {
+    pub workspace_root: PathBuf,
+    /// The shared files alone, without local overrides; this is what
+    /// [`write_shared`](Self::write_shared) writes
+    pub shared: TeamLayer,
+    pub settings: TeamSettings,
+    /// Prompt templates keyed by file stem
+    pub prompt_templates: BTreeMap<String, String>,
+    /// Rules files keyed by file stem
+    pub rules: BTreeMap<String, String>,
+    /// Problems found while loading, e.g. credentials removed from shared files
+    pub warnings: Vec<String>,
+    /// Resolved variables of `.catalyst/env`
+    pub env: HashMap<String, String>,
+}
+
+impl TeamConfig {
+    /// Load the team configuration of a workspace, with the user's MCP
+    /// servers in the config directory
+    pub fn load(workspace_root: &Path) -> Result<Self> {
+        Self::load_with_user_settings(
+            workspace_root,
+            user_mcp_settings_path().as_deref(),
+        )
+    }
+
+    /// Load the team configuration of a workspace, with the user's MCP
+    /// servers in `user_settings`
+    ///
+    /// Tables in the local settings are merged key by key into the shared
+    /// ones, so an override only needs the values it changes. Local prompt
+    /// templates and rules replace shared ones with the same name. The
+    /// user's servers are added unless the workspace has a server with the
+    /// same id.
+    pub fn load_with_user_settings(
+        workspace_root: &Path,
+        user_settings: Option<&Path>,
+    ) -> Result<Self> {
+        let dir = workspace_root.join(TEAM_CONFIG_DIR);
+        let local_dir = dir.join(LOCAL_OVERRIDE_DIR);
+        let mut warnings = Vec::new();
+
+        let mut shared: TeamSettings =
+            read_toml(&dir.join(TEAM_SETTINGS_FILE))?.try_into()?;
+        for key in strip_credentials(&mut shared) {
+            warnings.push(format!(
+                "Ignored literal credential {} in {}/{}; use a ${{VARIABLE}} \
+                 reference or {}/{} instead",
+                key,
+                TEAM_CONFIG_DIR,
+                TEAM_SETTINGS_FILE,
+                TEAM_CONFIG_DIR,
+                LOCAL_OVERRIDE_DIR
+            ));
+        }
+
+        let mut merged = toml::Value::try_from(&shared)?;
+        merge_toml(&mut merged, read_toml(&local_dir.join(TEAM_SETTINGS_FILE))?);
+        let mut settings: TeamSettings = merged.try_into()?;
+        if let Some(path) = user_settings {
+            let user: TeamSettings = read_toml(path)?.try_into()?;
+            for (id, server) in user.mcp_servers {
+                settings.mcp_servers.entry(id).or_insert(server);
+            }
+        }
+
+        let shared = TeamLayer {
+            settings: shared,
+            prompt_templates: read_markdown_dir(&dir.join(PROMPTS_DIR))?,
+            rules: read_markdown_dir(&dir.join(RULES_DIR))?,
+        };
+        let mut prompt_templates = shared.prompt_templates.clone();
+        prompt_templates.extend(read_markdown_dir(&local_dir.join(PROMPTS_DIR))?);
+        let mut rules = shared.rules.clone();
+        rules.extend(read_markdown_dir(&local_dir.join(RULES_DIR))?);
+
+        for warning in &warnings {
+            tracing::warn!("{}", warning);
+        }
+
+        Ok(Self {
+            workspace_root: workspace_root.to_path_buf(),
+            shared,
+            settings,
+            prompt_templates,
+            rules,
+            warnings,
+            env: workspace_env(workspace_root),
+        })
+    }
+
+    /// Get the enabled MCP servers with environment references resolved and
+    /// the workspace environment added
+    pub fn mcp_servers(&self) -> Vec<McpServerInfo> {
+        let lookup = |name: &str| {
+            self.env
+                .get(name)
+                .cloned()
+                .or_else(|| std::env::var(name).ok())
+                .or_else(|| CredentialStore::global().get_env(name))
+        };
+        self.settings
+            .mcp_servers
+            .iter()
+            .filter(|(_, server)| server.enabled)
+            .map(|(id, server)| McpServerInfo {
+                id: id.clone(),
+                name: server.name.clone().unwrap_or_else(|| id.clone()),
+                description: server.description.clone(),
+                version: String::new(),
+                command: server.command.clone(),
+                args: server.args.clone(),
+                env: server
+                    .env
+                    .iter()
+                    .map(|(key, value)| {
+                        (key.clone(), resolve_env_references_with(value, lookup))
+                    })
+                    .collect::<HashMap<_, _>>(),
+                working_directory: server.working_directory.clone(),
+                auto_start: server.auto_start,
+                keep_warm: server.keep_warm,
+                capabilities: McpServerCapabilities {
+                    tools: true,
+                    resources: true,
+                    prompts: true,
+                    logging: false,
+                    experimental: HashMap::new(),
+                },
+                resource_limits: server.resource_limits.clone(),
+                call_limits: server.call_limits,
+                request_timeout_secs: server.request_timeout_secs,
+            })
+            .map(|mut info| {
+                apply_workspace_env(&mut info, &self.env);
+                info
+            })
+            .collect()
+    }
+
+    /// Get the shared tasks
+    pub fn project_tasks(&self) -> Vec<ProjectTask> {
+        self.settings
+            .tasks
+            .iter()
+            .map(|(label, task)| ProjectTask {
+                label: label.clone(),
+                command: task.command.clone(),
+                working_directory: match &task.working_directory {
+                    Some(dir) => self.workspace_root.join(dir),
+                    None => self.workspace_root.clone(),
+                },
+            })
+            .collect()
+    }
+
+    /// Concatenate the rules files in name order
+    pub fn rules_text(&self) -> String {
+        self.rules
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Write the selected sections of the [`shared`](Self::shared) layer to
+    /// the shared `.catalyst/` directory so they can be committed
+    ///
+    /// Local overrides are never written there. Fails without writing
+    /// anything if a selected section contains a literal credential.
+    pub fn write_shared(&self, sections: &[TeamConfigSection]) -> Result<()> {
+        let dir = self.workspace_root.join(TEAM_CONFIG_DIR);
+        let settings_path = dir.join(TEAM_SETTINGS_FILE);
+
+        let mut shared: TeamSettings = read_toml(&settings_path)?.try_into()?;
+        if sections.contains(&TeamConfigSection::McpCatalog) {
+            shared.mcp_servers = self.shared.settings.mcp_servers.clone();
+        }
+        if sections.contains(&TeamConfigSection::Tasks) {
+            shared.tasks = self.shared.settings.tasks.clone();
+        }
+
+        let credentials = strip_credentials(&mut shared.clone());
+        if !credentials.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Refusing to share literal credentials: {}",
+                credentials.join(", ")
+            ));
+        }
+
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&settings_path, toml::to_string_pretty(&shared)?)?;
+        if sections.contains(&TeamConfigSection::PromptTemplates) {
+            write_markdown_dir(
+                &dir.join(PROMPTS_DIR),
+                &self.shared.prompt_templates,
+            )?;
+        }
+        if sections.contains(&TeamConfigSection::Rules) {
+            write_markdown_dir(&dir.join(RULES_DIR), &self.shared.rules)?;
+        }
+
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, format!("/{}/\n", LOCAL_OVERRIDE_DIR))?;
+        }
+
+        tracing::info!("Wrote team configuration to {}", dir.display());
+        Ok(())
+    }
+}
+
+/// Get the file with the user's MCP servers for every workspace
+pub fn user_mcp_settings_path() -> Option<PathBuf> {
+    Directory::config_directory().map(|dir| dir.join(USER_MCP_SETTINGS_FILE))
+}
+
+/// Add MCP servers to the local overrides of a workspace, replacing servers
+/// with the same id
+pub fn add_local_mcp_servers(
+    workspace_root: &Path,
+    servers: BTreeMap<String, TeamMcpServer>,
+) -> Result<()> {
+    let settings_path = workspace_root
+        .join(TEAM_CONFIG_DIR)
+        .join(LOCAL_OVERRIDE_DIR)
+        .join(TEAM_SETTINGS_FILE);
+    add_mcp_servers(&settings_path, servers)
+}
+
+/// Add MCP servers to a settings file, such as the user's
+/// [`USER_MCP_SETTINGS_FILE`], replacing servers with the same id
+pub fn add_mcp_servers(
+    settings_path: &Path,
+    servers: BTreeMap<String, TeamMcpServer>,
+) -> Result<()> {
+    let dir = settings_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid {}", settings_path.display()))?;
+    let mut settings = read_toml(settings_path)?;
+    let table = settings
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("Invalid {}", settings_path.display()))?
+        .entry("mcp_servers")
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    let toml::Value::Table(table) = table else {
+        return Err(anyhow::anyhow!(
+            "mcp_servers in {} is not a table",
+            settings_path.display()
+        ));
+    };
+    for (id, server) in servers {
+        table.insert(id, toml::Value::try_from(server)?);
+    }
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(settings_path, toml::to_string_pretty(&settings)?)?;
+    tracing::info!("Added MCP servers to {}", settings_path.display());
+    Ok(())
+}
+
+/// Replace `${NAME}` references with the value of the environment variable
+pub fn resolve_env_references(value: &str) -> String {
+    resolve_env_references_with(value, |name| std::env::var(name).ok())
+}
+
+/// Replace `${NAME}` references with values from `lookup`, or nothing for
+/// unknown names
+pub fn resolve_env_references_with(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + end];
+        result.push_str(&lookup(name).unwrap_or_default());
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Remove environment values that look like literal credentials, returning
+/// their keys
+fn strip_credentials(settings: &mut TeamSettings) -> Vec<String> {
+    let mut removed = Vec::new();
+    for (id, server) in settings.mcp_servers.iter_mut() {
+        server.env.retain(|key, value| {
+            let upper = key.to_uppercase();
+            let is_secret = SECRET_MARKERS.iter().any(|m| upper.contains(m));
+            let is_reference = value.trim().is_empty()
+                || (value.starts_with("${") && value.ends_with('}'));
+            if is_secret && !is_reference {
+                removed.push(format!("mcp_servers.{}.env.{}", id, key));
+                false
+            } else {
+                true
+            }
+        });
+    }
+    removed
+}
+
+/// Merge `overlay` into `base`, recursing into tables and replacing
+/// everything else
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn read_toml(path: &Path) -> Result<toml::Value> {
+    if !path.is_file() {
+        return Ok(toml::Value::Table(Default::default()));
+    }
+    toml::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+}
+
+fn read_markdown_dir(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+            files.insert(stem.to_string(), std::fs::read_to_string(&path)?);
+        }
+    }
+    Ok(files)
+}
+
+fn write_markdown_dir(dir: &Path, files: &BTreeMap<String, String>) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (name, content) in files {
+        std::fs::write(dir.join(format!("{}.md", name)), content)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_overrides_and_credentials() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join(TEAM_CONFIG_DIR);
+        std::fs::create_dir_all(dir.join("local/rules")).unwrap();
+        std::fs::create_dir_all(dir.join("rules")).unwrap();
+        std::fs::write(
+            dir.join(TEAM_SETTINGS_FILE),
+            r#"
+[mcp_servers.github]
+command = ["github-mcp"]
+env = { GITHUB_TOKEN = "ghp_literal", GITHUB_HOST = "github.com" }
+
+[mcp_servers.docs]
+command = ["docs-mcp"]
+
+[tasks.test]
+command = ["cargo", "test"]
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("local").join(TEAM_SETTINGS_FILE),
+            r#"
+[mcp_servers.docs]
+enabled = false
+
+[mcp_servers.github.env]
+GITHUB_TOKEN = "${MY_TOKEN}"
+"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("rules/style.md"), "Team style").unwrap();
+        std::fs::write(dir.join("local/rules/style.md"), "My style").unwrap();
+
+        let config = TeamConfig::load(root.path()).unwrap();
+        assert_eq!(config.warnings.len(), 1);
+
+        let github = &config.settings.mcp_servers["github"];
+        assert_eq!(github.command, vec!["github-mcp"]);
+        assert_eq!(github.env["GITHUB_TOKEN"], "${MY_TOKEN}");
+        assert_eq!(github.env["GITHUB_HOST"], "github.com");
+        assert_eq!(config.mcp_servers().len(), 1);
+        assert_eq!(config.project_tasks()[0].label, "test");
+        assert_eq!(config.rules_text(), "My style");
+
+        // Sharing writes the shared layer, never the local overrides
+        let mut config = config;
+        config.shared.settings.tasks.insert(
+            "lint".to_string(),
+            TeamTask {
+                command: vec!["cargo".to_string(), "clippy".to_string()],
+                working_directory: None,
+            },
+        );
+        config
+            .write_shared(&[
+                TeamConfigSection::McpCatalog,
+                TeamConfigSection::Rules,
+                TeamConfigSection::Tasks,
+            ])
+            .unwrap();
+        let text = std::fs::read_to_string(dir.join(TEAM_SETTINGS_FILE)).unwrap();
+        assert!(!text.contains("MY_TOKEN") && !text.contains("ghp_literal"));
+        assert!(!text.contains("enabled = false"));
+        assert!(text.contains("clippy"));
+        let rules = std::fs::read_to_string(dir.join("rules/style.md")).unwrap();
+        assert_eq!(rules, "Team style");
+        let config = TeamConfig::load(root.path()).unwrap();
+        assert_eq!(config.settings.mcp_servers["github"].env.len(), 2);
+        assert!(!config.settings.mcp_servers["docs"].enabled);
+    }
+
+    #[test]
+    fn test_user_mcp_servers() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join(TEAM_CONFIG_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(TEAM_SETTINGS_FILE),
+            r#"
+[mcp_servers.github]
+command = ["github-mcp"]
+"#,
+        )
+        .unwrap();
+
+        let config = tempfile::tempdir().unwrap();
+        let user_settings =
+            config.path().join("nested").join(USER_MCP_SETTINGS_FILE);
+        let server = |command: &str| -> TeamMcpServer {
+            toml::from_str(&format!("command = [\"{command}\"]")).unwrap()
+        };
+        add_mcp_servers(
+            &user_settings,
+            BTreeMap::from([
+                ("github".to_string(), server("my-github-mcp")),
+                ("fetch".to_string(), server("fetch-mcp")),
+            ]),
+        )
+        .unwrap();
+
+        // The workspace's servers win over the user's
+        let config =
+            TeamConfig::load_with_user_settings(root.path(), Some(&user_settings))
+                .unwrap();
+        let servers = &config.settings.mcp_servers;
+        assert_eq!(servers["github"].command, vec!["github-mcp"]);
+        assert_eq!(servers["fetch"].command, vec!["fetch-mcp"]);
+        // and sharing the workspace's settings leaves the user's out
+        assert!(!config.shared.settings.mcp_servers.contains_key("fetch"));
+    }
+}
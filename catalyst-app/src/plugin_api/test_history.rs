@@ -0,0 +1,670 @@
+//! Test History
+//!
+//! This module keeps the results of test task runs over time and finds the
+//! tests that fail intermittently. Results are parsed from the output of
+//! the test task (the libtest format of `cargo test`, or a JUnit XML report
+//! as written by most other runners) and stored per workspace. A test is
+//! flaky when it both passed and failed on the same commit, or keeps
+//! flipping between passing and failing. The flaky test panel lists them
+//! and turns one into an agent task with its failure history attached.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    JournalOptions, JournalState, JournaledStore, PanelCommand, PanelCommandResult,
+    SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition, text_panel_view,
+};
+
+/// Runs kept in the history
+const MAX_RECORDED_RUNS: usize = 200;
+/// Longest failure message kept per test result
+const MAX_FAILURE_MESSAGE: usize = 4000;
+/// Runs of a test looked at when deciding whether it is flaky
+pub const DEFAULT_FLAKY_WINDOW: usize = 30;
+/// Failures included in an investigation task
+const INVESTIGATION_FAILURES: usize = 5;
+
+static LIBTEST_RESULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^test (\S+) \.\.\. (ok|FAILED|ignored)").unwrap());
+static LIBTEST_OUTPUT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^---- (\S+) stdout ----$").unwrap());
+static JUNIT_TESTCASE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<testcase\b([^>]*?)(?:/>|>(.*?)</testcase>)").unwrap()
+});
+static XML_ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([\w:-]+)\s*=\s*"([^"]*)""#).unwrap());
+static JUNIT_PROBLEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<(failure|error|skipped)\b([^>]*?)(?:/>|>(.*?)</(?:failure|error|skipped)>)")
+        .unwrap()
+});
+
+/// Result of one test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+/// Result of one test in a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestCaseResult {
+    /// Fully qualified name, e.g. `parser::tests::test_nested`
+    pub name: String,
+    pub outcome: TestOutcome,
+    #[serde(default)]
+    pub duration: Option<Duration>,
+    /// Panic message or assertion output of a failed test
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// A recorded run of a test task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestRunRecord {
+    pub id: u64,
+    pub task_label: String,
+    /// Commit the tests ran on, if known
+    #[serde(default)]
+    pub commit: Option<String>,
+    pub finished_at: SystemTime,
+    pub results: Vec<TestCaseResult>,
+}
+
+/// Parse test results from the output of a test task, trying the libtest
+/// format first and then JUnit XML
+pub fn parse_test_output(output: &str) -> Vec<TestCaseResult> {
+    let results = parse_libtest_output(output);
+    if !results.is_empty() {
+        return results;
+    }
+    parse_junit_xml(output)
+}
+
+/// Parse the human-readable output of `cargo test`
+pub fn parse_libtest_output(output: &str) -> Vec<TestCaseResult> {
+    let mut results: Vec<TestCaseResult> = Vec::new();
+    let mut messages: HashMap<String, String> = HashMap::new();
+    let mut capturing: Option<(String, Vec<&str>)> = None;
+
+    for line in output.lines() {
+        if let Some(captures) = LIBTEST_OUTPUT.captures(line) {
+            if let Some((name, lines)) = capturing.take() {
+                messages.insert(name, lines.join("\n"));
+            }
+            capturing = Some((captures[1].to_string(), Vec::new()));
+            continue;
+        }
+        if let Some((name, lines)) = &mut capturing {
+            // The captured output ends at the list of failures or the
+            // next test's output
+            if line == "failures:" || line.starts_with("test result:") {
+                messages.insert(name.clone(), lines.join("\n"));
+                capturing = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+        if let Some(captures) = LIBTEST_RESULT.captures(line) {
+            results.push(TestCaseResult {
+                name: captures[1].to_string(),
+                outcome: match &captures[2] {
+                    "ok" => TestOutcome::Passed,
+                    "FAILED" => TestOutcome::Failed,
+                    _ => TestOutcome::Skipped,
+                },
+                duration: None,
+                message: None,
+            });
+        }
+    }
+    if let Some((name, lines)) = capturing {
+        messages.insert(name, lines.join("\n"));
+    }
+
+    for result in &mut results {
+        if result.outcome == TestOutcome::Failed {
+            result.message = messages
+                .remove(&result.name)
+                .map(|message| truncate_message(message.trim()));
+        }
+    }
+    results
+}
+
+/// Parse a JUnit XML report
+pub fn parse_junit_xml(report: &str) -> Vec<TestCaseResult> {
+    JUNIT_TESTCASE
+        .captures_iter(report)
+        .filter_map(|testcase| {
+            let attributes = xml_attributes(&testcase[1]);
+            let name = attributes.get("name")?;
+            let name = match attributes.get("classname") {
+                Some(class) if !class.is_empty() => format!("{}::{}", class, name),
+                _ => name.clone(),
+            };
+            let duration = attributes
+                .get("time")
+                .and_then(|time| time.parse::<f64>().ok())
+                .filter(|time| time.is_finite() && *time >= 0.0)
+                .map(Duration::from_secs_f64);
+
+            let body = testcase
+                .get(2)
+                .map(|body| body.as_str())
+                .unwrap_or_default();
+            let (outcome, message) = match JUNIT_PROBLEM.captures(body) {
+                Some(problem) if &problem[1] == "skipped" => {
+                    (TestOutcome::Skipped, None)
+                }
+                Some(problem) => {
+                    let attributes = xml_attributes(&problem[2]);
+                    let text = problem
+                        .get(3)
+                        .map(|text| xml_unescape(text.as_str().trim()))
+                        .filter(|text| !text.is_empty());
+                    let message =
+                        text.or_else(|| attributes.get("message").cloned());
+                    (TestOutcome::Failed, message.map(|m| truncate_message(&m)))
+                }
+                None => (TestOutcome::Passed, None),
+            };
+            Some(TestCaseResult {
+                name,
+                outcome,
+                duration,
+                message,
+            })
+        })
+        .collect()
+}
+
+fn xml_attributes(text: &str) -> HashMap<String, String> {
+    XML_ATTRIBUTE
+        .captures_iter(text)
+        .map(|captures| (captures[1].to_string(), xml_unescape(&captures[2])))
+        .collect()
+}
+
+fn xml_unescape(text: &str) -> String {
+    let text = text
+        .strip_prefix("<![CDATA[")
+        .and_then(|text| text.strip_suffix("]]>"))
+        .unwrap_or(text);
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn truncate_message(message: &str) -> String {
+    if message.len() <= MAX_FAILURE_MESSAGE {
+        return message.to_string();
+    }
+    let mut end = MAX_FAILURE_MESSAGE;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &message[..end])
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TestHistoryFile {
+    next_id: u64,
+    runs: Vec<TestRunRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum TestHistoryOp {
+    Record(TestRunRecord),
+    Clear,
+}
+
+impl JournalState for TestHistoryFile {
+    type Op = TestHistoryOp;
+
+    fn apply(&mut self, op: &TestHistoryOp) {
+        match op {
+            TestHistoryOp::Record(run) => {
+                self.next_id = self.next_id.max(run.id);
+                self.runs.push(run.clone());
+                if self.runs.len() > MAX_RECORDED_RUNS {
+                    let excess = self.runs.len() - MAX_RECORDED_RUNS;
+                    self.runs.drain(..excess);
+                }
+            }
+            TestHistoryOp::Clear => self.runs.clear(),
+        }
+    }
+}
+
+/// A test that fails intermittently
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlakyTest {
+    pub name: String,
+    /// Runs of the test looked at
+    pub runs: usize,
+    pub failures: usize,
+    /// Changes between passing and failing from one run to the next
+    pub flips: usize,
+    /// Whether it both passed and failed on the same commit
+    pub failed_on_passing_commit: bool,
+    /// Share of consecutive runs with a different outcome, from 0 to 1
+    pub score: f64,
+}
+
+/// A failure of a test, for its history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestFailure {
+    pub run_id: u64,
+    pub finished_at: SystemTime,
+    #[serde(default)]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Per-workspace history of test runs
+pub struct TestHistoryStore {
+    inner: JournaledStore<TestHistoryFile>,
+}
+
+impl TestHistoryStore {
+    /// Open the test history of a workspace, kept in the local data directory
+    pub fn open_for_workspace(workspace_root: &Path) -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?
+            .join("test-history");
+        Self::open(&dir.join(format!("{}.json", workspace_key(workspace_root))))
+    }
+
+    /// Open a test history backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            inner: JournaledStore::open(path, JournalOptions::default())?,
+        })
+    }
+
+    /// Record the results of a run
+    pub fn record(
+        &self,
+        task_label: &str,
+        commit: Option<String>,
+        results: Vec<TestCaseResult>,
+    ) -> Result<TestRunRecord> {
+        if results.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No test results found in the output of '{}'",
+                task_label
+            ));
+        }
+        self.inner.transact(|inner| {
+            let run = TestRunRecord {
+                id: inner.next_id + 1,
+                task_label: task_label.to_string(),
+                commit,
+                finished_at: SystemTime::now(),
+                results,
+            };
+            Ok((Some(TestHistoryOp::Record(run.clone())), run))
+        })
+    }
+
+    /// Parse the output of a test task and record its results
+    pub fn record_output(
+        &self,
+        task_label: &str,
+        commit: Option<String>,
+        output: &str,
+    ) -> Result<TestRunRecord> {
+        self.record(task_label, commit, parse_test_output(output))
+    }
+
+    /// Get the recorded runs, oldest first
+    pub fn runs(&self) -> Vec<TestRunRecord> {
+        self.inner.read().runs.clone()
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.inner.apply(TestHistoryOp::Clear)
+    }
+
+    /// Find the tests that failed intermittently in their last `window`
+    /// runs, most flaky first
+    ///
+    /// Tests that failed every time are broken rather than flaky and are
+    /// left out.
+    pub fn flaky_tests(&self, window: usize) -> Vec<FlakyTest> {
+        let inner = self.inner.read();
+        let mut outcomes: BTreeMap<&str, Vec<(TestOutcome, Option<&str>)>> =
+            BTreeMap::new();
+        for run in &inner.runs {
+            for result in &run.results {
+                if result.outcome != TestOutcome::Skipped {
+                    outcomes
+                        .entry(result.name.as_str())
+                        .or_default()
+                        .push((result.outcome, run.commit.as_deref()));
+                }
+            }
+        }
+
+        let mut flaky: Vec<FlakyTest> = outcomes
+            .into_iter()
+            .filter_map(|(name, outcomes)| {
+                let outcomes = &outcomes[outcomes.len().saturating_sub(window)..];
+                let failures = outcomes
+                    .iter()
+                    .filter(|(outcome, _)| *outcome == TestOutcome::Failed)
+                    .count();
+                if failures == 0 || failures == outcomes.len() {
+                    return None;
+                }
+                let flips = outcomes
+                    .windows(2)
+                    .filter(|pair| pair[0].0 != pair[1].0)
+                    .count();
+                let mut by_commit: HashMap<&str, (bool, bool)> = HashMap::new();
+                for (outcome, commit) in outcomes {
+                    if let Some(commit) = commit {
+                        let seen = by_commit.entry(*commit).or_default();
+                        match outcome {
+                            TestOutcome::Passed => seen.0 = true,
+                            _ => seen.1 = true,
+                        }
+                    }
+                }
+                let failed_on_passing_commit = by_commit
+                    .values()
+                    .any(|(passed, failed)| *passed && *failed);
+                // A single change from passing to failing (or back) is a
+                // regression or a fix, not flakiness
+                if flips < 2 && !failed_on_passing_commit {
+                    return None;
+                }
+                Some(FlakyTest {
+                    name: name.to_string(),
+                    runs: outcomes.len(),
+                    failures,
+                    flips,
+                    failed_on_passing_commit,
+                    score: flips as f64 / (outcomes.len() - 1) as f64,
+                })
+            })
+            .collect();
+        flaky.sort_by(|a, b| {
+            b.failed_on_passing_commit
+                .cmp(&a.failed_on_passing_commit)
+                .then_with(|| b.score.total_cmp(&a.score))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        flaky
+    }
+
+    /// Get the failures of a test, newest first
+    pub fn failures(&self, name: &str, limit: usize) -> Vec<TestFailure> {
+        self.inner
+            .read()
+            .runs
+            .iter()
+            .rev()
+            .filter_map(|run| {
+                let result = run.results.iter().find(|result| {
+                    result.name == name && result.outcome == TestOutcome::Failed
+                })?;
+                Some(TestFailure {
+                    run_id: run.id,
+                    finished_at: run.finished_at,
+                    commit: run.commit.clone(),
+                    message: result.message.clone(),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+
+    /// Build the prompt of an agent task investigating a flaky test, with
+    /// its recent failures attached
+    pub fn investigation_prompt(&self, name: &str) -> Result<String> {
+        let flaky = self
+            .flaky_tests(DEFAULT_FLAKY_WINDOW)
+            .into_iter()
+            .find(|test| test.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Test '{}' is not flaky", name))?;
+
+        let mut prompt = format!(
+            "The test `{}` is flaky: it failed {} of its last {} runs and \
+             changed outcome {} times{}. Find the source of the \
+             nondeterminism (timing, ordering, shared state, external \
+             resources) and propose a fix that makes it reliable.\n\n\
+             Recent failures:\n",
+            flaky.name,
+            flaky.failures,
+            flaky.runs,
+            flaky.flips,
+            if flaky.failed_on_passing_commit {
+                ", including on commits where it also passed"
+            } else {
+                ""
+            }
+        );
+        for failure in self.failures(name, INVESTIGATION_FAILURES) {
+            let when = chrono::DateTime::<chrono::Local>::from(failure.finished_at)
+                .format("%Y-%m-%d %H:%M");
+            prompt.push_str(&format!(
+                "\n### Run {} at {}{}\n```\n{}\n```\n",
+                failure.run_id,
+                when,
+                failure
+                    .commit
+                    .map(|commit| format!(" on {}", commit))
+                    .unwrap_or_default(),
+                failure.message.as_deref().unwrap_or("(no output captured)")
+            ));
+        }
+        Ok(prompt)
+    }
+}
+
+/// Stable file name for a workspace root
+fn workspace_key(workspace_root: &Path) -> String {
+    let root = workspace_root
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_root.to_path_buf());
+    format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()))
+}
+
+/// Sidebar panel listing flaky tests
+pub struct FlakyTestPanel {
+    store: Arc<TestHistoryStore>,
+}
+
+impl FlakyTestPanel {
+    pub const ID: &'static str = "catalyst.flaky_tests";
+
+    pub fn new(store: Arc<TestHistoryStore>) -> Self {
+        Self { store }
+    }
+
+    fn run_command(&self, command: &PanelCommand) -> Result<serde_json::Value> {
+        let name = || {
+            command
+                .parameters
+                .get("name")
+                .and_then(|name| name.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'name' parameter"))
+        };
+
+        match command.command_id.as_str() {
+            "list" => Ok(serde_json::to_value(
+                self.store.flaky_tests(DEFAULT_FLAKY_WINDOW),
+            )?),
+            "failures" => {
+                Ok(serde_json::to_value(self.store.failures(name()?, 20))?)
+            }
+            "investigate" => Ok(serde_json::json!({
+                "prompt": self.store.investigation_prompt(name()?)?,
+            })),
+            "clear" => {
+                self.store.clear()?;
+                Ok(serde_json::Value::Null)
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown flaky test panel command '{}'",
+                other
+            )),
+        }
+    }
+}
+
+impl SidebarPanelPlugin for FlakyTestPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Flaky Tests".to_string(),
+            description: "Tests that fail intermittently across recorded runs"
+                .to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn floem::View> {
+        let store = self.store.clone();
+        text_panel_view(move || {
+            let flaky = store.flaky_tests(DEFAULT_FLAKY_WINDOW);
+            if flaky.is_empty() {
+                return "No flaky tests found in the recorded runs.".to_string();
+            }
+            flaky
+                .iter()
+                .map(|test| {
+                    format!(
+                        "{}  failed {}/{} runs",
+                        test.name, test.failures, test.runs
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::to_value(self.store.flaky_tests(DEFAULT_FLAKY_WINDOW))
+            .unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        Ok(match self.run_command(&command) {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_runs_and_detects_flaky_tests() {
+        let passing = "running 2 tests\n\
+                       test net::tests::test_retry ... ok\n\
+                       test parser::tests::test_nested ... ok\n\n\
+                       test result: ok. 2 passed; 0 failed";
+        let failing = "running 2 tests\n\
+                       test net::tests::test_retry ... FAILED\n\
+                       test parser::tests::test_nested ... ok\n\n\
+                       failures:\n\n\
+                       ---- net::tests::test_retry stdout ----\n\
+                       thread 'net::tests::test_retry' panicked: timed out\n\n\
+                       failures:\n    net::tests::test_retry\n\n\
+                       test result: FAILED. 1 passed; 1 failed";
+        let results = parse_libtest_output(failing);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].outcome, TestOutcome::Failed);
+        assert!(results[0].message.as_deref().unwrap().contains("timed out"));
+
+        let junit = r#"<testsuite><testcase classname="app.Login" name="works" time="0.5"/>
+            <testcase classname="app.Login" name="fails"><failure message="expected &lt;1&gt;"/></testcase>
+            </testsuite>"#;
+        let results = parse_junit_xml(junit);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].duration, Some(Duration::from_millis(500)));
+        assert_eq!(results[1].message.as_deref(), Some("expected <1>"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tests.json");
+        let store = TestHistoryStore::open(&path).unwrap();
+        for output in [passing, failing, passing, failing] {
+            store
+                .record_output("cargo test", Some("abc".to_string()), output)
+                .unwrap();
+        }
+        assert!(store.record_output("cargo test", None, "no tests").is_err());
+
+        let store = TestHistoryStore::open(&path).unwrap();
+        let flaky = store.flaky_tests(DEFAULT_FLAKY_WINDOW);
+        assert_eq!(flaky.len(), 1);
+        assert_eq!(flaky[0].name, "net::tests::test_retry");
+        assert_eq!(flaky[0].flips, 3);
+        assert!(flaky[0].failed_on_passing_commit);
+
+        let prompt = store
+            .investigation_prompt("net::tests::test_retry")
+            .unwrap();
+        assert!(prompt.contains("failed 2 of its last 4 runs"));
+        assert!(prompt.contains("panicked: timed out"));
+        assert!(
+            store
+                .investigation_prompt("parser::tests::test_nested")
+                .is_err()
+        );
+    }
+}
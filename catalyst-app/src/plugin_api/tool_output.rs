@@ -0,0 +1,432 @@
+//! Tool Output
+//!
+//! This module caps how much of a tool result is given to the model at
+//! once, so one `list_files` on a huge repository doesn't fill the context
+//! window. A result over [`ToolOutputLimits::page_bytes`] is cut at a line
+//! boundary and ends with a marker naming a cursor; the model follows it
+//! with the built-in `read_more_output` tool to get the next page.
+//!
+//! Only text is paged. Images, audio and embedded resources are given to
+//! the model as they are, next to the first page of the text.
+//!
+//! Pages not read yet are kept in memory up to
+//! [`ToolOutputLimits::max_pending_bytes`]. The oldest outputs are dropped
+//! first, and a single output larger than that is cut for good, without
+//! copying more of it than is kept.
+
+use anyhow::{Result, anyhow};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use crate::plugin_api::{
+    McpContent, McpToolResult, ToolCall, ToolDefinition, ToolEffect,
+};
+
+/// Name of the tool the assistant uses to read the next page of an output
+pub const READ_MORE_OUTPUT_TOOL_NAME: &str = "read_more_output";
+
+/// Size limits of the tool results given to the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolOutputLimits {
+    /// Bytes of output given to the model at once
+    pub page_bytes: usize,
+    /// Bytes of unread pages kept for `read_more_output`
+    pub max_pending_bytes: usize,
+}
+
+impl Default for ToolOutputLimits {
+    fn default() -> Self {
+        Self {
+            page_bytes: 32 * 1024,
+            max_pending_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// The unread rest of an output
+#[derive(Debug)]
+struct PendingOutput {
+    cursor: String,
+    text: String,
+    /// Offset of `text` in the whole output
+    offset: usize,
+    total: usize,
+    /// Bytes cut off the end because the output was too large to keep
+    dropped: usize,
+}
+
+#[derive(Debug, Default)]
+struct PagerState {
+    next_cursor: u64,
+    /// Oldest first
+    pending: VecDeque<PendingOutput>,
+    pending_bytes: usize,
+}
+
+/// Splits long tool results into pages and serves the following pages
+#[derive(Debug, Default)]
+pub struct ToolOutputPager {
+    limits: Mutex<ToolOutputLimits>,
+    state: Mutex<PagerState>,
+}
+
+impl ToolOutputPager {
+    pub fn new(limits: ToolOutputLimits) -> Self {
+        Self {
+            limits: Mutex::new(limits),
+            state: Mutex::new(PagerState::default()),
+        }
+    }
+
+    pub fn limits(&self) -> ToolOutputLimits {
+        *self.limits.lock()
+    }
+
+    pub fn set_limits(&self, limits: ToolOutputLimits) {
+        *self.limits.lock() = limits;
+    }
+
+    /// Cut the text of a tool result to its first page if it is over the
+    /// page size
+    ///
+    /// A result whose text fits is returned unchanged. Otherwise its text
+    /// content becomes a single text content holding the first page and
+    /// the marker, in place of the first text; other content is kept.
+    pub fn limit(&self, result: McpToolResult) -> McpToolResult {
+        let limits = self.limits();
+        let keep = limits.page_bytes.saturating_add(limits.max_pending_bytes);
+        let mut text = String::new();
+        let mut total = 0;
+        let mut first_text = None;
+        for (index, content) in result.content.iter().enumerate() {
+            if !is_text(content) {
+                continue;
+            }
+            let separator = if first_text.is_some() { "\n" } else { "" };
+            first_text.get_or_insert(index);
+            for part in [separator, &content_text(content)] {
+                total += part.len();
+                let room = keep.saturating_sub(text.len());
+                text.push_str(&part[..floor_char_boundary(part, room)]);
+            }
+        }
+        if total <= limits.page_bytes {
+            return result;
+        }
+
+        let dropped = total - text.len();
+        let mut page = Some(text_content(self.take_page(text, 0, total, dropped)));
+        let content = result
+            .content
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, content)| {
+                if Some(index) == first_text {
+                    page.take()
+                } else if is_text(&content) {
+                    None
+                } else {
+                    Some(content)
+                }
+            })
+            .collect();
+        McpToolResult {
+            content,
+            is_error: result.is_error,
+        }
+    }
+
+    /// Get the next page of the output a cursor refers to
+    pub fn read_more(&self, cursor: &str) -> Result<String> {
+        let mut state = self.state.lock();
+        let index = state
+            .pending
+            .iter()
+            .position(|pending| pending.cursor == cursor)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Output cursor '{}' is unknown or has expired; run the tool \
+                     again to get its output",
+                    cursor
+                )
+            })?;
+        let pending = state.pending.remove(index).expect("index is in range");
+        state.pending_bytes -= pending.text.len();
+        drop(state);
+        Ok(self.take_page(
+            pending.text,
+            pending.offset,
+            pending.total,
+            pending.dropped,
+        ))
+    }
+
+    /// Definition of the `read_more_output` tool offered to the assistant
+    pub fn tool_definition() -> ToolDefinition {
+        ToolDefinition {
+            name: READ_MORE_OUTPUT_TOOL_NAME.to_string(),
+            description: "Read the next page of a tool output that was cut \
+                          short. Pass the cursor named in the output's \
+                          truncation marker."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "cursor": {
+                        "type": "string",
+                        "description": "Cursor from the truncation marker"
+                    }
+                },
+                "required": ["cursor"]
+            }),
+            effect: ToolEffect::ReadOnly,
+        }
+    }
+
+    /// Execute a `read_more_output` tool call from the assistant
+    pub fn handle_tool_call(&self, call: &ToolCall) -> Result<McpToolResult> {
+        if call.name != READ_MORE_OUTPUT_TOOL_NAME {
+            return Err(anyhow!("Unknown output tool '{}'", call.name));
+        }
+        let cursor = call
+            .arguments
+            .get("cursor")
+            .and_then(|cursor| cursor.as_str())
+            .ok_or_else(|| anyhow!("Missing 'cursor' argument"))?;
+        let page = self.read_more(cursor)?;
+        Ok(McpToolResult {
+            content: vec![text_content(page)],
+            is_error: false,
+        })
+    }
+
+    /// Split the first page off `text`, keep the rest under a cursor and
+    /// return the page with its marker
+    fn take_page(
+        &self,
+        mut text: String,
+        offset: usize,
+        total: usize,
+        mut dropped: usize,
+    ) -> String {
+        let limits = self.limits();
+        let split = page_end(&text, limits.page_bytes);
+        let start = offset;
+        let end = offset + split;
+        let mut rest = text.split_off(split);
+        let mut page = text;
+        if rest.is_empty() {
+            if dropped > 0 {
+                page.push_str(&format!(
+                    "\n[end of kept output: bytes {start}-{end} of {total}; the \
+                     last {dropped} bytes were dropped]"
+                ));
+            } else {
+                page.push_str(&format!(
+                    "\n[end of output: bytes {start}-{end} of {total}]"
+                ));
+            }
+            return page;
+        }
+
+        if rest.len() > limits.max_pending_bytes {
+            let keep = floor_char_boundary(&rest, limits.max_pending_bytes);
+            dropped += rest.len() - keep;
+            rest.truncate(keep);
+        }
+        let mut state = self.state.lock();
+        while state.pending_bytes + rest.len() > limits.max_pending_bytes {
+            let Some(oldest) = state.pending.pop_front() else {
+                break;
+            };
+            state.pending_bytes -= oldest.text.len();
+        }
+        state.next_cursor += 1;
+        let cursor = format!("out-{}", state.next_cursor);
+        page.push_str(&format!(
+            "\n[output truncated: showing bytes {start}-{end} of {total}; call \
+             `{READ_MORE_OUTPUT_TOOL_NAME}` with cursor \"{cursor}\" to \
+             continue]"
+        ));
+        state.pending_bytes += rest.len();
+        state.pending.push_back(PendingOutput {
+            cursor,
+            text: rest,
+            offset: end,
+            total,
+            dropped,
+        });
+        page
+    }
+}
+
+/// Check if content is paged as text, rather than media or an embedded
+/// resource given to the model whole
+fn is_text(content: &McpContent) -> bool {
+    !matches!(
+        content.content_type.as_str(),
+        "image" | "audio" | "resource"
+    )
+}
+
+/// Get the text of a content item, with JSON data in compact form
+fn content_text(content: &McpContent) -> Cow<'_, str> {
+    match &content.data {
+        serde_json::Value::String(text) => Cow::Borrowed(text),
+        data => Cow::Owned(data.to_string()),
+    }
+}
+
+fn text_content(text: String) -> McpContent {
+    McpContent {
+        content_type: "text".to_string(),
+        data: serde_json::Value::String(text),
+        hint: None,
+    }
+}
+
+/// Find where a page of at most `max_bytes` ends, after the last line break
+/// in its second half if there is one
+fn page_end(text: &str, max_bytes: usize) -> usize {
+    if text.len() <= max_bytes {
+        return text.len();
+    }
+    let end = floor_char_boundary(text, max_bytes);
+    let line_end = text[..end]
+        .rfind('\n')
+        .map(|newline| newline + 1)
+        .filter(|line_end| *line_end > end / 2);
+    // Always make progress, even on a page smaller than one character
+    line_end
+        .unwrap_or(end)
+        .max(text.chars().next().map_or(0, char::len_utf8))
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    (0..=index.min(text.len()))
+        .rev()
+        .find(|index| text.is_char_boundary(*index))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::McpTypedContent;
+
+    fn result_text(result: &McpToolResult) -> &str {
+        result.content[0].data.as_str().unwrap()
+    }
+
+    fn marker_cursor(page: &str) -> Option<String> {
+        let (_, rest) = page.split_once("cursor \"")?;
+        Some(rest.split_once('"')?.0.to_string())
+    }
+
+    #[test]
+    fn test_paginates_long_output() {
+        let pager = ToolOutputPager::new(ToolOutputLimits {
+            page_bytes: 100,
+            max_pending_bytes: 1000,
+        });
+        let short = McpToolResult {
+            content: vec![text_content("src/main.rs".to_string())],
+            is_error: false,
+        };
+        assert_eq!(result_text(&pager.limit(short)), "src/main.rs");
+
+        let files: String =
+            (0..30).map(|i| format!("src/file_{i:02}.rs\n")).collect();
+        let limited = pager.limit(McpToolResult {
+            content: vec![text_content(files.clone())],
+            is_error: false,
+        });
+        let first = result_text(&limited);
+        assert!(first.starts_with("src/file_00.rs\n"));
+        assert!(first.contains("[output truncated: showing bytes 0-90 of 450"));
+
+        // Following the cursors gives back the whole output, cut at lines
+        let mut cursor = marker_cursor(first);
+        let mut pages = vec![first.split_once("\n[").unwrap().0.to_string()];
+        while let Some(current) = cursor {
+            let call = ToolCall {
+                id: "1".to_string(),
+                name: READ_MORE_OUTPUT_TOOL_NAME.to_string(),
+                arguments: serde_json::json!({ "cursor": current }),
+            };
+            let page = pager.handle_tool_call(&call).unwrap();
+            let page = result_text(&page);
+            cursor = marker_cursor(page);
+            pages.push(page.split_once("\n[").unwrap().0.to_string());
+            // A cursor can only be followed once
+            assert!(pager.read_more(&current).is_err());
+        }
+        assert_eq!(pages.len(), 5);
+        assert_eq!(pages.concat(), files);
+
+        // Outputs over the pending limit are cut and the oldest expire
+        let huge = "x".repeat(3000);
+        let limited = pager.limit(McpToolResult {
+            content: vec![text_content(huge.clone())],
+            is_error: true,
+        });
+        assert!(limited.is_error);
+        let old_cursor = marker_cursor(result_text(&limited)).unwrap();
+        let limited = pager.limit(McpToolResult {
+            content: vec![text_content(huge)],
+            is_error: false,
+        });
+        assert!(pager.read_more(&old_cursor).is_err());
+        let mut cursor = marker_cursor(result_text(&limited));
+        let mut last = String::new();
+        while let Some(current) = cursor {
+            last = pager.read_more(&current).unwrap();
+            cursor = marker_cursor(&last);
+        }
+        assert!(last.ends_with("the last 1900 bytes were dropped]"));
+    }
+
+    #[test]
+    fn test_pages_only_text() {
+        let pager = ToolOutputPager::new(ToolOutputLimits {
+            page_bytes: 100,
+            max_pending_bytes: 1000,
+        });
+        let screenshot = McpContent::from(McpTypedContent::Image {
+            data: "i".repeat(500),
+            mime_type: "image/png".to_string(),
+        });
+        let short = McpToolResult {
+            content: vec![screenshot.clone(), McpContent::text("Took a screenshot")],
+            is_error: false,
+        };
+        let typed = |result: &McpToolResult| -> Vec<_> {
+            result.content.iter().map(McpContent::typed).collect()
+        };
+        assert_eq!(typed(&pager.limit(short.clone())), typed(&short));
+
+        let log: String = (0..30).map(|i| format!("line {i:02}\n")).collect();
+        let limited = pager.limit(McpToolResult {
+            content: vec![
+                McpContent::text(log.clone()),
+                screenshot.clone(),
+                McpContent::text("done"),
+            ],
+            is_error: false,
+        });
+        assert_eq!(limited.content.len(), 2);
+        assert_eq!(limited.content[1].typed(), screenshot.typed());
+        let page = result_text(&limited);
+        let mut cursor = marker_cursor(page);
+        let mut text = page.split_once("\n[").unwrap().0.to_string();
+        while let Some(current) = cursor {
+            let page = pager.read_more(&current).unwrap();
+            cursor = marker_cursor(&page);
+            text.push_str(page.split_once("\n[").unwrap().0);
+        }
+        assert_eq!(text, format!("{log}\ndone"));
+    }
+}
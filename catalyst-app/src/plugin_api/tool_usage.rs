@@ -0,0 +1,418 @@
+//! Tool Usage
+//!
+//! This module counts the tool calls made to every MCP server, kept in the
+//! local data directory and never sent anywhere. A server that has not been
+//! called for a while still costs memory and startup time, so servers idle
+//! for longer than [`DEFAULT_UNUSED_AFTER`] are suggested for disabling,
+//! which the panel does in one click through the [`McpServerRegistry`].
+
+use anyhow::{Result, anyhow};
+use catalyst_core::directory::Directory;
+use floem::View;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::plugin_api::{
+    FsyncPolicy, JournalOptions, JournalState, JournaledStore, McpServerRegistry,
+    PanelCommand, PanelCommandResult, SidebarPanelInfo, SidebarPanelPlugin,
+    SidebarPosition, current_time, text_panel_view,
+};
+
+/// Time without calls after which a server is suggested for disabling
+pub const DEFAULT_UNUSED_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Tool calls made to one server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerUsage {
+    pub calls: u64,
+    /// Calls per tool name
+    pub tools: BTreeMap<String, u64>,
+    /// When the server was first registered with usage tracking on
+    pub first_seen: SystemTime,
+    pub last_used: Option<SystemTime>,
+}
+
+impl ServerUsage {
+    fn new(at: SystemTime) -> Self {
+        Self {
+            calls: 0,
+            tools: BTreeMap::new(),
+            first_seen: at,
+            last_used: None,
+        }
+    }
+
+    /// Get how long the server has gone without a call, counting from when
+    /// it was first seen if it was never called
+    pub fn idle_for(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.last_used.unwrap_or(self.first_seen))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ToolUsageFile {
+    servers: BTreeMap<String, ServerUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ToolUsageOp {
+    Seen {
+        server_id: String,
+        at: SystemTime,
+    },
+    Called {
+        server_id: String,
+        tool: String,
+        at: SystemTime,
+    },
+    Forget {
+        server_id: String,
+    },
+}
+
+impl JournalState for ToolUsageFile {
+    type Op = ToolUsageOp;
+
+    fn apply(&mut self, op: &ToolUsageOp) {
+        match op {
+            ToolUsageOp::Seen { server_id, at } => {
+                self.servers
+                    .entry(server_id.clone())
+                    .or_insert_with(|| ServerUsage::new(*at));
+            }
+            ToolUsageOp::Called {
+                server_id,
+                tool,
+                at,
+            } => {
+                let usage = self
+                    .servers
+                    .entry(server_id.clone())
+                    .or_insert_with(|| ServerUsage::new(*at));
+                usage.calls += 1;
+                *usage.tools.entry(tool.clone()).or_default() += 1;
+                usage.last_used = Some(*at);
+            }
+            ToolUsageOp::Forget { server_id } => {
+                self.servers.remove(server_id);
+            }
+        }
+    }
+}
+
+/// A server suggested for disabling
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnusedServer {
+    pub server_id: String,
+    pub idle_days: u64,
+    pub never_used: bool,
+}
+
+impl UnusedServer {
+    /// Get the suggestion shown to the user
+    pub fn message(&self) -> String {
+        if self.never_used {
+            format!(
+                "The {} server hasn't been used since it was added {} days ago \
+                 — disable it to save memory and startup time",
+                self.server_id, self.idle_days
+            )
+        } else {
+            format!(
+                "The {} server hasn't been used in {} days — disable it to save \
+                 memory and startup time",
+                self.server_id, self.idle_days
+            )
+        }
+    }
+}
+
+/// Persistent count of the tool calls made to each server
+pub struct ToolUsageStore {
+    store: JournaledStore<ToolUsageFile>,
+}
+
+impl ToolUsageStore {
+    /// Open the tool usage kept in the local data directory
+    pub fn open_default() -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow!("Local data directory is unavailable"))?;
+        Self::open(&dir.join("tool_usage.json"))
+    }
+
+    /// Open a tool usage store backed by the given file
+    ///
+    /// Calls are recorded on the tool call path, so the log is flushed at
+    /// most once a second instead of after every call.
+    pub fn open(path: &Path) -> Result<Self> {
+        let options = JournalOptions {
+            fsync: FsyncPolicy::Interval { millis: 1000 },
+            ..JournalOptions::default()
+        };
+        Ok(Self {
+            store: JournaledStore::open(path, options)?,
+        })
+    }
+
+    /// Start tracking a server, keeping its usage if it was seen before
+    pub fn record_registered(&self, server_id: &str) -> Result<()> {
+        if self.store.read().servers.contains_key(server_id) {
+            return Ok(());
+        }
+        self.store.apply(ToolUsageOp::Seen {
+            server_id: server_id.to_string(),
+            at: current_time(),
+        })
+    }
+
+    /// Record a call of a tool
+    pub fn record_call(&self, server_id: &str, tool: &str) -> Result<()> {
+        self.store.apply(ToolUsageOp::Called {
+            server_id: server_id.to_string(),
+            tool: tool.to_string(),
+            at: current_time(),
+        })
+    }
+
+    /// Get the usage of a server
+    pub fn usage(&self, server_id: &str) -> Option<ServerUsage> {
+        self.store.read().servers.get(server_id).cloned()
+    }
+
+    /// Get the usage of all tracked servers
+    pub fn all(&self) -> BTreeMap<String, ServerUsage> {
+        self.store.read().servers.clone()
+    }
+
+    /// Write calls recorded since the last flush to disk
+    pub fn sync(&self) -> Result<()> {
+        self.store.sync()
+    }
+
+    /// Drop the usage of a server
+    pub fn forget(&self, server_id: &str) -> Result<()> {
+        self.store.apply(ToolUsageOp::Forget {
+            server_id: server_id.to_string(),
+        })
+    }
+
+    /// Find the servers among `server_ids` without a call for at least
+    /// `unused_after`, the longest idle first
+    pub fn unused_servers(
+        &self,
+        server_ids: &[String],
+        unused_after: Duration,
+        now: SystemTime,
+    ) -> Vec<UnusedServer> {
+        let state = self.store.read();
+        let mut unused: Vec<_> = server_ids
+            .iter()
+            .filter_map(|id| {
+                let usage = state.servers.get(id)?;
+                let idle_for = usage.idle_for(now);
+                (idle_for >= unused_after).then(|| UnusedServer {
+                    server_id: id.clone(),
+                    idle_days: idle_for.as_secs() / SECONDS_PER_DAY,
+                    never_used: usage.last_used.is_none(),
+                })
+            })
+            .collect();
+        unused.sort_by(|a, b| {
+            b.idle_days
+                .cmp(&a.idle_days)
+                .then_with(|| a.server_id.cmp(&b.server_id))
+        });
+        unused
+    }
+}
+
+/// Panel listing the tool usage of the registered servers and the unused
+/// ones that can be disabled
+pub struct ToolUsagePanel {
+    registry: McpServerRegistry,
+    unused_after: Duration,
+}
+
+impl ToolUsagePanel {
+    pub const ID: &'static str = "catalyst.tool_usage";
+
+    pub fn new(registry: McpServerRegistry, unused_after: Duration) -> Self {
+        Self {
+            registry,
+            unused_after,
+        }
+    }
+
+    fn list(&self) -> Result<serde_json::Value> {
+        let usage = self
+            .registry
+            .usage_store()
+            .map(|store| store.all())
+            .unwrap_or_default();
+        Ok(serde_json::json!({
+            "usage": usage,
+            "suggestions": self.registry.unused_servers(self.unused_after),
+        }))
+    }
+}
+
+impl SidebarPanelPlugin for ToolUsagePanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: Self::ID.to_string(),
+            name: "Tool Usage".to_string(),
+            description: "How often each MCP server is used".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(250),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let registry = self.registry.clone();
+        let unused_after = self.unused_after;
+        text_panel_view(move || {
+            let Some(store) = registry.usage_store() else {
+                return "Tool usage is not being recorded".to_string();
+            };
+            let now = current_time();
+            let mut lines: Vec<_> = registry
+                .unused_servers(unused_after)
+                .iter()
+                .map(UnusedServer::message)
+                .collect();
+            for id in registry.get_server_ids() {
+                let line = match store.usage(&id) {
+                    Some(usage) => format!(
+                        "{}  {} calls, idle {} days",
+                        id,
+                        usage.calls,
+                        usage.idle_for(now).as_secs() / SECONDS_PER_DAY
+                    ),
+                    None => format!("{id}  no calls recorded"),
+                };
+                lines.push(line);
+            }
+            if lines.is_empty() {
+                "No MCP servers are registered".to_string()
+            } else {
+                lines.join("\n")
+            }
+        })
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        self.list().unwrap_or_default()
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        let outcome = match command.command_id.as_str() {
+            "list" => self.list(),
+            "disable" => command
+                .parameters
+                .get("server_id")
+                .and_then(|id| id.as_str())
+                .ok_or_else(|| anyhow!("Missing parameter 'server_id'"))
+                .and_then(|id| self.registry.disable_server(id))
+                .and_then(|_| self.list()),
+            other => Err(anyhow!("Unknown command '{}'", other)),
+        };
+        Ok(match outcome {
+            Ok(result) => PanelCommandResult {
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tool_usage.json");
+        let store = ToolUsageStore::open(&path).unwrap();
+        store.record_registered("github").unwrap();
+        store.record_registered("zapier").unwrap();
+        store.record_call("github", "list_issues").unwrap();
+        store.record_call("github", "list_issues").unwrap();
+        store.record_call("github", "get_pull_request").unwrap();
+        store.store.sync().unwrap();
+
+        let store = ToolUsageStore::open(&path).unwrap();
+        let github = store.usage("github").unwrap();
+        assert_eq!(github.calls, 3);
+        assert_eq!(github.tools["list_issues"], 2);
+        assert!(github.last_used.is_some());
+        // Registering again keeps the usage
+        store.record_registered("github").unwrap();
+        assert_eq!(store.usage("github").unwrap().calls, 3);
+
+        let ids = vec!["github".to_string(), "zapier".to_string()];
+        let now = current_time();
+        assert!(
+            store
+                .unused_servers(&ids, DEFAULT_UNUSED_AFTER, now)
+                .is_empty()
+        );
+        let later = now + Duration::from_secs(31 * SECONDS_PER_DAY);
+        let unused = store.unused_servers(&ids, DEFAULT_UNUSED_AFTER, later);
+        assert_eq!(unused.len(), 2);
+        assert_eq!(unused[0].idle_days, 31);
+        assert_eq!(
+            unused[1].message(),
+            "The zapier server hasn't been used since it was added 31 days ago \
+             — disable it to save memory and startup time"
+        );
+        // Servers no longer registered are not suggested
+        assert_eq!(
+            store
+                .unused_servers(&ids[1..], DEFAULT_UNUSED_AFTER, later)
+                .len(),
+            1
+        );
+
+        store.forget("zapier").unwrap();
+        assert!(store.usage("zapier").is_none());
+    }
+}
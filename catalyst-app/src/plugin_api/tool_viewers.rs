@@ -0,0 +1,1224 @@
+//! Tool Result Viewers
+//!
+//! This module picks how a tool result is shown in chat. Servers can set
+//! the `hint` of their content; without one, the viewer is guessed from the
+//! content: JSON becomes a collapsible tree, unified diffs a side-by-side
+//! diff that can be applied to the workspace, and long output a searchable
+//! log view. Everything else stays a plain text block. Diffs of JSON, YAML,
+//! TOML and lockfiles also list their key-level changes, see
+//! [`SemanticDiff`].
+//!
+//! Images, such as browser screenshots, are shown inline, and audio, such
+//! as recordings, gets a button playing it. Resources embedded in a result
+//! are shown like the content they hold.
+
+use anyhow::Result;
+use base64::{Engine as _, engine::general_purpose};
+use floem::{
+    View,
+    reactive::{SignalGet, SignalUpdate, SignalWith, create_rw_signal},
+    views::{Decorators, h_stack, img, label, stack_from_iter, text_input, v_stack},
+};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    McpContent, McpContentHint, McpEmbeddedResource, McpToolResult, McpTypedContent,
+    SemanticDiff, StructuredFormat, WorkspaceSandbox, virtual_list_view,
+    write_atomic,
+};
+
+/// Number of lines from which plain output is shown as a log
+pub const LOG_VIEWER_MIN_LINES: usize = 40;
+/// Depth from which JSON containers start collapsed
+const JSON_COLLAPSED_DEPTH: usize = 2;
+/// Lines a hunk may have moved from its line number and still be applied
+const MAX_HUNK_DRIFT: usize = 200;
+const ROW_HEIGHT: f64 = 20.0;
+const INDENT: f64 = 14.0;
+
+/// A tool result prepared for its viewer
+#[derive(Debug, Clone)]
+pub enum ToolResultViewer {
+    Text(String),
+    Json(JsonTree),
+    Diff(DiffView),
+    Log(LogView),
+    Image(MediaContent),
+    Audio(MediaContent),
+}
+
+/// Decoded image or audio content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaContent {
+    pub mime_type: String,
+    pub bytes: Arc<[u8]>,
+}
+
+impl MediaContent {
+    /// Decode base64 encoded media
+    pub fn decode(data: &str, mime_type: &str) -> Result<Self> {
+        Ok(Self {
+            mime_type: mime_type.to_string(),
+            bytes: general_purpose::STANDARD.decode(data.trim())?.into(),
+        })
+    }
+
+    /// Describe the media, e.g. `audio/wav, 12.0 KB`
+    pub fn summary(&self) -> String {
+        format!(
+            "{}, {:.1} KB",
+            self.mime_type,
+            self.bytes.len() as f64 / 1024.0
+        )
+    }
+
+    /// File extension of a playable audio type, e.g. `mp3` for
+    /// `audio/mpeg`
+    ///
+    /// The file is handed to the system's default application, so only
+    /// known audio formats get an extension; anything else, such as
+    /// `audio/bat`, could make it run a script.
+    fn extension(&self) -> Option<&'static str> {
+        let mime_type = self.mime_type.split(';').next().unwrap_or("");
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "audio/mpeg" | "audio/mp3" => Some("mp3"),
+            "audio/wav" | "audio/x-wav" | "audio/wave" | "audio/vnd.wave" => {
+                Some("wav")
+            }
+            "audio/ogg" | "audio/opus" => Some("ogg"),
+            "audio/flac" | "audio/x-flac" => Some("flac"),
+            "audio/mp4" | "audio/m4a" | "audio/x-m4a" => Some("m4a"),
+            "audio/webm" => Some("webm"),
+            _ => None,
+        }
+    }
+}
+
+impl ToolResultViewer {
+    /// Prepare content for the viewer its hint selects, falling back to a
+    /// text or log view when the content doesn't parse
+    pub fn for_content(content: &McpContent) -> Self {
+        let content = viewed_content(content);
+        let content = content.as_ref();
+        match content.typed() {
+            Some(McpTypedContent::Image { data, mime_type }) => {
+                return MediaContent::decode(&data, &mime_type).map_or_else(
+                    |_| Self::Text("Invalid image".to_string()),
+                    Self::Image,
+                );
+            }
+            Some(McpTypedContent::Audio { data, mime_type }) => {
+                return MediaContent::decode(&data, &mime_type).map_or_else(
+                    |_| Self::Text("Invalid audio".to_string()),
+                    Self::Audio,
+                );
+            }
+            _ => {}
+        }
+        let text = content_text(content);
+        let fallback = |text: String| {
+            if text.lines().count() >= LOG_VIEWER_MIN_LINES {
+                Self::Log(LogView::new(&text))
+            } else {
+                Self::Text(text)
+            }
+        };
+        match content_hint(content) {
+            McpContentHint::Json => match &content.data {
+                Value::Object(_) | Value::Array(_) => {
+                    Self::Json(JsonTree::new(content.data.clone()))
+                }
+                _ => match serde_json::from_str(&text) {
+                    Ok(value) => Self::Json(JsonTree::new(value)),
+                    Err(_) => fallback(text),
+                },
+            },
+            McpContentHint::Diff => match DiffView::parse(&text) {
+                Ok(diff) => Self::Diff(diff),
+                Err(_) => fallback(text),
+            },
+            McpContentHint::Log => Self::Log(LogView::new(&text)),
+            McpContentHint::Text | McpContentHint::Image | McpContentHint::Audio => {
+                fallback(text)
+            }
+        }
+    }
+
+    /// Get the viewer kind
+    pub fn hint(&self) -> McpContentHint {
+        match self {
+            Self::Text(_) => McpContentHint::Text,
+            Self::Json(_) => McpContentHint::Json,
+            Self::Diff(_) => McpContentHint::Diff,
+            Self::Log(_) => McpContentHint::Log,
+            Self::Image(_) => McpContentHint::Image,
+            Self::Audio(_) => McpContentHint::Audio,
+        }
+    }
+}
+
+/// Get the content an embedded resource holds, to be shown in its place
+///
+/// Text resources become text content of the resource's type, and binary
+/// images and audio become image and audio content.
+fn viewed_content(content: &McpContent) -> Cow<'_, McpContent> {
+    let Some(McpTypedContent::Resource { resource }) = content.typed() else {
+        return Cow::Borrowed(content);
+    };
+    let McpEmbeddedResource {
+        uri,
+        mime_type,
+        text,
+        blob,
+    } = resource;
+    let mime_type = mime_type.unwrap_or_default();
+    let viewed = match (text, blob) {
+        (Some(text), _) => McpContent {
+            content_type: if mime_type.is_empty() {
+                "text".to_string()
+            } else {
+                mime_type
+            },
+            data: Value::String(text),
+            hint: content.hint,
+        },
+        (None, Some(data)) if mime_type.starts_with("image/") => {
+            McpTypedContent::Image { data, mime_type }.into()
+        }
+        (None, Some(data)) if mime_type.starts_with("audio/") => {
+            McpTypedContent::Audio { data, mime_type }.into()
+        }
+        _ => McpContent::text(uri),
+    };
+    Cow::Owned(viewed)
+}
+
+/// Get the viewer for content: its own hint if it has one, otherwise a
+/// guess from the content type and the content itself
+pub fn content_hint(content: &McpContent) -> McpContentHint {
+    if let Some(hint) = content.hint {
+        return hint;
+    }
+    let content = viewed_content(content);
+    let content = content.as_ref();
+    match content.content_type.as_str() {
+        "image" => return McpContentHint::Image,
+        "audio" => return McpContentHint::Audio,
+        "json" | "application/json" => return McpContentHint::Json,
+        "diff" | "patch" | "text/x-diff" | "text/x-patch" => {
+            return McpContentHint::Diff;
+        }
+        _ => {}
+    }
+    if matches!(content.data, Value::Object(_) | Value::Array(_))
+        && content.data.get("text").is_none()
+    {
+        return McpContentHint::Json;
+    }
+
+    let text = content_text(content);
+    let trimmed = text.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<Value>(trimmed)
+            .is_ok_and(|value| value.is_object() || value.is_array())
+    {
+        McpContentHint::Json
+    } else if looks_like_diff(&text) {
+        McpContentHint::Diff
+    } else if text.lines().count() >= LOG_VIEWER_MIN_LINES {
+        McpContentHint::Log
+    } else {
+        McpContentHint::Text
+    }
+}
+
+/// Get the text of content, which is either a string or an MCP text item
+pub fn content_text(content: &McpContent) -> String {
+    match &content.data {
+        Value::String(text) => text.clone(),
+        data => match data.get("text").and_then(Value::as_str) {
+            Some(text) => text.to_string(),
+            None => serde_json::to_string_pretty(data).unwrap_or_default(),
+        },
+    }
+}
+
+fn looks_like_diff(text: &str) -> bool {
+    let mut has_header = false;
+    let mut has_hunk = false;
+    for line in text.lines() {
+        has_header |= line.starts_with("--- ") || line.starts_with("+++ ");
+        has_hunk |= line.starts_with("@@ -");
+        if has_header && has_hunk {
+            return true;
+        }
+    }
+    false
+}
+
+/// A visible row of a JSON tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonRow {
+    /// JSON pointer of the value
+    pub pointer: String,
+    pub key: Option<String>,
+    pub depth: usize,
+    /// The value for scalars, a summary for objects and arrays
+    pub text: String,
+    pub expandable: bool,
+    pub expanded: bool,
+}
+
+/// JSON value shown as a tree whose objects and arrays can be collapsed
+#[derive(Debug, Clone)]
+pub struct JsonTree {
+    value: Value,
+    collapsed: HashSet<String>,
+}
+
+impl JsonTree {
+    /// Create a tree with containers below the top levels collapsed
+    pub fn new(value: Value) -> Self {
+        let mut collapsed = HashSet::new();
+        collect_containers(&value, String::new(), 0, &mut |pointer, depth| {
+            if depth >= JSON_COLLAPSED_DEPTH {
+                collapsed.insert(pointer);
+            }
+        });
+        Self { value, collapsed }
+    }
+
+    /// Get the value
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Expand or collapse the container at a pointer
+    pub fn toggle(&mut self, pointer: &str) {
+        if !self.collapsed.remove(pointer) {
+            self.collapsed.insert(pointer.to_string());
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        self.collapsed.clear();
+    }
+
+    pub fn collapse_all(&mut self) {
+        let collapsed = &mut self.collapsed;
+        collect_containers(&self.value, String::new(), 0, &mut |pointer, _| {
+            collapsed.insert(pointer);
+        });
+    }
+
+    /// Get the rows that are visible with the current collapsed state
+    pub fn rows(&self) -> Vec<JsonRow> {
+        let mut rows = Vec::new();
+        self.push_rows(&self.value, String::new(), None, 0, &mut rows);
+        rows
+    }
+
+    fn push_rows(
+        &self,
+        value: &Value,
+        pointer: String,
+        key: Option<String>,
+        depth: usize,
+        rows: &mut Vec<JsonRow>,
+    ) {
+        let (text, children): (String, Vec<(String, &Value)>) = match value {
+            Value::Object(map) => (
+                format!("{{{} keys}}", map.len()),
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value))
+                    .collect(),
+            ),
+            Value::Array(items) => (
+                format!("[{} items]", items.len()),
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| (index.to_string(), value))
+                    .collect(),
+            ),
+            scalar => (scalar.to_string(), Vec::new()),
+        };
+        let expandable = !children.is_empty();
+        let expanded = expandable && !self.collapsed.contains(&pointer);
+        rows.push(JsonRow {
+            pointer: pointer.clone(),
+            key,
+            depth,
+            text,
+            expandable,
+            expanded,
+        });
+        if expanded {
+            for (key, child) in children {
+                let child_pointer = format!("{}/{}", pointer, escape_pointer(&key));
+                self.push_rows(child, child_pointer, Some(key), depth + 1, rows);
+            }
+        }
+    }
+}
+
+fn collect_containers(
+    value: &Value,
+    pointer: String,
+    depth: usize,
+    f: &mut impl FnMut(String, usize),
+) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (index.to_string(), value))
+            .collect(),
+        _ => return,
+    };
+    if children.is_empty() {
+        return;
+    }
+    for (key, child) in children {
+        collect_containers(
+            child,
+            format!("{}/{}", pointer, escape_pointer(&key)),
+            depth + 1,
+            f,
+        );
+    }
+    f(pointer, depth);
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Line of a diff hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Hunk of a unified diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub header: String,
+    /// First line of the hunk in the old file, starting at 1
+    pub old_start: usize,
+    /// First line of the hunk in the new file, starting at 1
+    pub new_start: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Changes to one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    /// `None` for a new file
+    pub old_path: Option<PathBuf>,
+    /// `None` for a deleted file
+    pub new_path: Option<PathBuf>,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl DiffFile {
+    /// Get the path shown for the file
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.new_path.as_ref().or(self.old_path.as_ref())
+    }
+
+    /// Apply the hunks to the old content of the file
+    ///
+    /// Hunks are located at their line numbers, or at the nearest place
+    /// their context matches if the file has shifted by up to
+    /// [`MAX_HUNK_DRIFT`] lines since the diff was made. Files with CRLF
+    /// line endings keep them.
+    pub fn apply_to(&self, original: &str) -> Result<String> {
+        let mut lines: Vec<&str> = original.lines().collect();
+        let mut offset: isize = 0;
+        for hunk in &self.hunks {
+            let old: Vec<&str> = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    DiffLine::Context(text) | DiffLine::Removed(text) => {
+                        Some(text.as_str())
+                    }
+                    DiffLine::Added(_) => None,
+                })
+                .collect();
+            let new: Vec<&str> = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    DiffLine::Context(text) | DiffLine::Added(text) => {
+                        Some(text.as_str())
+                    }
+                    DiffLine::Removed(_) => None,
+                })
+                .collect();
+            let expected =
+                (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+            let start = find_block(&lines, &old, expected).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Hunk {} doesn't match {}",
+                    hunk.header,
+                    self.path()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_default()
+                )
+            })?;
+            lines.splice(start..start + old.len(), new.iter().copied());
+            offset += new.len() as isize - old.len() as isize;
+        }
+        let line_ending = if original.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut text = lines.join(line_ending);
+        if !lines.is_empty() && (original.ends_with('\n') || original.is_empty()) {
+            text.push_str(line_ending);
+        }
+        Ok(text)
+    }
+}
+
+/// Find `block` in `lines`, preferring the position closest to `expected`
+/// and looking no further than [`MAX_HUNK_DRIFT`] lines from it
+fn find_block(lines: &[&str], block: &[&str], expected: usize) -> Option<usize> {
+    if block.is_empty() {
+        return Some(expected.min(lines.len()));
+    }
+    let last = lines.len().checked_sub(block.len())?;
+    let first = expected.saturating_sub(MAX_HUNK_DRIFT);
+    let last = last.min(expected.saturating_add(MAX_HUNK_DRIFT));
+    (first..=last)
+        .filter(|&start| lines[start..start + block.len()] == *block)
+        .min_by_key(|&start| start.abs_diff(expected))
+}
+
+/// Kind of a side-by-side diff row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffRowKind {
+    /// Hunk header
+    Hunk,
+    Context,
+    /// A removed line next to the line that replaced it
+    Modified,
+    Removed,
+    Added,
+}
+
+/// Row of a side-by-side diff, with line numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRow {
+    pub kind: DiffRowKind,
+    pub old: Option<(usize, String)>,
+    pub new: Option<(usize, String)>,
+}
+
+/// Parsed unified diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffView {
+    pub files: Vec<DiffFile>,
+}
+
+impl DiffView {
+    /// Parse a unified diff, as produced by `git diff` or `diff -u`
+    ///
+    /// Lines are read as part of a hunk until it has as many old and new
+    /// lines as its header says, so a removed `-- comment` isn't taken for
+    /// a file header.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut files: Vec<DiffFile> = Vec::new();
+        let mut old_path = None;
+        // Old and new lines left in the current hunk
+        let mut remaining: (usize, usize) = (0, 0);
+        for line in text.lines() {
+            let in_hunk = remaining != (0, 0)
+                && matches!(
+                    line.chars().next(),
+                    None | Some('+' | '-' | ' ' | '\\')
+                );
+            if in_hunk {
+                let Some(hunk) =
+                    files.last_mut().and_then(|file| file.hunks.last_mut())
+                else {
+                    continue;
+                };
+                let (old, new) = &mut remaining;
+                let diff_line = match line.chars().next() {
+                    Some('+') => {
+                        *new = new.saturating_sub(1);
+                        DiffLine::Added(line[1..].to_string())
+                    }
+                    Some('-') => {
+                        *old = old.saturating_sub(1);
+                        DiffLine::Removed(line[1..].to_string())
+                    }
+                    // `\ No newline at end of file`
+                    Some('\\') => continue,
+                    // Some tools strip the space of empty context lines
+                    _ => {
+                        *old = old.saturating_sub(1);
+                        *new = new.saturating_sub(1);
+                        DiffLine::Context(
+                            line.get(1..).unwrap_or_default().to_string(),
+                        )
+                    }
+                };
+                hunk.lines.push(diff_line);
+                continue;
+            }
+            // A hunk shorter than its header says ends at the next header
+            remaining = (0, 0);
+            if let Some(path) = line.strip_prefix("--- ") {
+                old_path = Some(diff_path(path, "a/"));
+            } else if let Some(path) = line.strip_prefix("+++ ") {
+                files.push(DiffFile {
+                    old_path: old_path.take().flatten(),
+                    new_path: diff_path(path, "b/"),
+                    hunks: Vec::new(),
+                });
+            } else if line.starts_with("@@ ") {
+                let file = files.last_mut().ok_or_else(|| {
+                    anyhow::anyhow!("Hunk before a file header: {}", line)
+                })?;
+                let header = parse_hunk_header(line)?;
+                remaining = (header.old_len, header.new_len);
+                file.hunks.push(DiffHunk {
+                    header: line.to_string(),
+                    old_start: header.old_start,
+                    new_start: header.new_start,
+                    lines: Vec::new(),
+                });
+            }
+            // Anything else is git extended headers or `\ No newline at end
+            // of file` after the last line of a hunk
+        }
+        files.retain(|file| !file.hunks.is_empty());
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("No hunks found in the diff"));
+        }
+        Ok(Self { files })
+    }
+
+    /// Get the rows of a file shown side by side
+    ///
+    /// Runs of removed and added lines are paired up, so a changed line
+    /// shows its old and new version on the same row.
+    pub fn side_by_side(&self, file: usize) -> Vec<DiffRow> {
+        let mut rows = Vec::new();
+        let Some(file) = self.files.get(file) else {
+            return rows;
+        };
+        for hunk in &file.hunks {
+            rows.push(DiffRow {
+                kind: DiffRowKind::Hunk,
+                old: None,
+                new: Some((0, hunk.header.clone())),
+            });
+            let mut old_line = hunk.old_start;
+            let mut new_line = hunk.new_start;
+            let mut removed = Vec::new();
+            let mut added = Vec::new();
+            let flush = |removed: &mut Vec<(usize, String)>,
+                         added: &mut Vec<(usize, String)>,
+                         rows: &mut Vec<DiffRow>| {
+                let len = removed.len().max(added.len());
+                let mut removed = removed.drain(..);
+                let mut added = added.drain(..);
+                for _ in 0..len {
+                    let old = removed.next();
+                    let new = added.next();
+                    let kind = match (&old, &new) {
+                        (Some(_), Some(_)) => DiffRowKind::Modified,
+                        (Some(_), None) => DiffRowKind::Removed,
+                        _ => DiffRowKind::Added,
+                    };
+                    rows.push(DiffRow { kind, old, new });
+                }
+            };
+            for line in &hunk.lines {
+                match line {
+                    DiffLine::Removed(text) => {
+                        removed.push((old_line, text.clone()));
+                        old_line += 1;
+                    }
+                    DiffLine::Added(text) => {
+                        added.push((new_line, text.clone()));
+                        new_line += 1;
+                    }
+                    DiffLine::Context(text) => {
+                        flush(&mut removed, &mut added, &mut rows);
+                        rows.push(DiffRow {
+                            kind: DiffRowKind::Context,
+                            old: Some((old_line, text.clone())),
+                            new: Some((new_line, text.clone())),
+                        });
+                        old_line += 1;
+                        new_line += 1;
+                    }
+                }
+            }
+            flush(&mut removed, &mut added, &mut rows);
+        }
+        rows
+    }
+
+    /// Get the key-level changes of the structured files in the diff,
+    /// between the workspace and the workspace with the diff applied
+    ///
+    /// Files that can't be read, patched or parsed are left out.
+    pub fn semantic_diffs(&self, sandbox: &WorkspaceSandbox) -> Vec<SemanticDiff> {
+        self.files
+            .iter()
+            .filter_map(|file| {
+                let path = file.path()?;
+                StructuredFormat::detect(path)?;
+                let original = match &file.old_path {
+                    Some(old_path) => {
+                        std::fs::read_to_string(sandbox.check_access(old_path).ok()?)
+                            .ok()?
+                    }
+                    None => String::new(),
+                };
+                let patched = match &file.new_path {
+                    Some(_) => file.apply_to(&original).ok()?,
+                    None => String::new(),
+                };
+                SemanticDiff::compute(path, &original, &patched).ok()
+            })
+            .collect()
+    }
+
+    /// Apply the diff to the workspace, returning the changed files
+    ///
+    /// Every file is checked and patched in memory first, so a hunk that
+    /// doesn't match leaves the workspace untouched.
+    pub fn apply(&self, sandbox: &WorkspaceSandbox) -> Result<Vec<PathBuf>> {
+        let mut changes: Vec<(PathBuf, Option<String>)> = Vec::new();
+        for file in &self.files {
+            match (&file.old_path, &file.new_path) {
+                (_, Some(new_path)) => {
+                    let target = sandbox.check_access(new_path)?;
+                    let original = match &file.old_path {
+                        Some(old_path) => {
+                            std::fs::read_to_string(sandbox.check_access(old_path)?)?
+                        }
+                        None => String::new(),
+                    };
+                    changes.push((target, Some(file.apply_to(&original)?)));
+                }
+                (Some(old_path), None) => {
+                    changes.push((sandbox.check_access(old_path)?, None));
+                }
+                (None, None) => {}
+            }
+        }
+        for (path, content) in &changes {
+            match content {
+                Some(content) => write_atomic(path, content.as_bytes())?,
+                None => std::fs::remove_file(path)?,
+            }
+        }
+        Ok(changes.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+fn diff_path(path: &str, prefix: &str) -> Option<PathBuf> {
+    // Timestamps of `diff -u` follow a tab
+    let path = path.split('\t').next().unwrap_or(path).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(PathBuf::from(path.strip_prefix(prefix).unwrap_or(path)))
+}
+
+/// Line ranges of a hunk, from `@@ -old,len +new,len @@`
+struct HunkHeader {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+}
+
+/// Parse the line ranges of a hunk header; a range without a length has
+/// one line
+fn parse_hunk_header(line: &str) -> Result<HunkHeader> {
+    let invalid = || anyhow::anyhow!("Invalid hunk header: {}", line);
+    let mut ranges = line.trim_start_matches("@@ ").split_whitespace();
+    let mut range = |sign: char| -> Result<(usize, usize)> {
+        let range = ranges
+            .next()
+            .and_then(|range| range.strip_prefix(sign))
+            .ok_or_else(invalid)?;
+        let (start, len) = range.split_once(',').unwrap_or((range, "1"));
+        match (start.parse(), len.parse()) {
+            (Ok(start), Ok(len)) => Ok((start, len)),
+            _ => Err(invalid()),
+        }
+    };
+    let (old_start, old_len) = range('-')?;
+    let (new_start, new_len) = range('+')?;
+    Ok(HunkHeader {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+    })
+}
+
+/// Long tool output with search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogView {
+    lines: Vec<String>,
+}
+
+impl LogView {
+    pub fn new(text: &str) -> Self {
+        Self {
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Get the indices of lines containing `query`, ignoring case unless
+    /// the query has uppercase letters
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        self.filter(query)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Get the lines containing `query` with their indices; an empty query
+    /// matches every line
+    pub fn filter(&self, query: &str) -> Vec<(usize, &str)> {
+        let ignore_case = !query.chars().any(char::is_uppercase);
+        let lowercase_query = query.to_lowercase();
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                if ignore_case {
+                    line.to_lowercase().contains(&lowercase_query)
+                } else {
+                    line.contains(query)
+                }
+            })
+            .map(|(index, line)| (index, line.as_str()))
+            .collect()
+    }
+}
+
+/// Create the view of a tool result
+///
+/// Diffs can only be applied when a sandbox for the workspace is given.
+pub fn tool_result_view(
+    content: &McpContent,
+    sandbox: Option<WorkspaceSandbox>,
+) -> Box<dyn View> {
+    match ToolResultViewer::for_content(content) {
+        ToolResultViewer::Text(text) => Box::new(label(move || text.clone())),
+        ToolResultViewer::Json(tree) => json_tree_view(tree),
+        ToolResultViewer::Diff(diff) => diff_view(diff, sandbox),
+        ToolResultViewer::Log(log) => log_view(log),
+        ToolResultViewer::Image(image) => image_view(image),
+        ToolResultViewer::Audio(audio) => audio_view(audio),
+    }
+}
+
+/// Create the view of a tool call in chat: the tool's name, then every
+/// content of its result in its own viewer
+pub fn tool_call_view(
+    name: &str,
+    result: &McpToolResult,
+    sandbox: Option<WorkspaceSandbox>,
+) -> Box<dyn View> {
+    let title = if result.is_error {
+        format!("{} failed", name)
+    } else {
+        name.to_string()
+    };
+    let contents = result
+        .content
+        .iter()
+        .map(|content| tool_result_view(content, sandbox.clone()))
+        .collect::<Vec<_>>();
+    Box::new(
+        v_stack((
+            label(move || title.clone()),
+            stack_from_iter(contents).style(|s| s.flex_col().gap(6.0)),
+        ))
+        .style(|s| s.gap(4.0)),
+    )
+}
+
+/// Create an inline image view, scaled down to the width of the chat
+pub fn image_view(image: MediaContent) -> Box<dyn View> {
+    Box::new(
+        img(move || image.bytes.to_vec())
+            .style(|s| s.max_width_full().max_height(480.0)),
+    )
+}
+
+/// Create a view of an audio clip, played by the system's player
+pub fn audio_view(audio: MediaContent) -> Box<dyn View> {
+    let status = create_rw_signal(String::new());
+    let summary = format!("Audio ({})", audio.summary());
+    let play_button = label(|| "Play".to_string()).on_click_stop(move |_| {
+        let Some(extension) = audio.extension() else {
+            status.set(format!("Can't play {}", audio.mime_type));
+            return;
+        };
+        let mut hasher = DefaultHasher::new();
+        audio.bytes.hash(&mut hasher);
+        let path = std::env::temp_dir().join(format!(
+            "catalyst-audio-{:016x}.{}",
+            hasher.finish(),
+            extension
+        ));
+        let played = std::fs::write(&path, &audio.bytes)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(open::that(&path)?));
+        if let Err(err) = played {
+            status.set(format!("Failed to play: {err:#}"));
+        }
+    });
+    Box::new(
+        h_stack((
+            label(move || summary.clone()),
+            play_button,
+            label(move || status.get()),
+        ))
+        .style(|s| s.gap(10.0)),
+    )
+}
+
+/// Create a collapsible JSON tree view
+pub fn json_tree_view(tree: JsonTree) -> Box<dyn View> {
+    let tree = create_rw_signal(tree);
+    virtual_list_view(
+        move || tree.with(|tree| tree.rows().into_iter().collect()),
+        |(_, row): &(usize, JsonRow)| row.pointer.clone(),
+        move |(_, row)| {
+            let marker = match (row.expandable, row.expanded) {
+                (false, _) => "  ",
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+            };
+            let text = match &row.key {
+                Some(key) => format!("{}{}: {}", marker, key, row.text),
+                None => format!("{}{}", marker, row.text),
+            };
+            let pointer = row.pointer.clone();
+            let expandable = row.expandable;
+            label(move || text.clone())
+                .style(move |s| s.padding_left(row.depth as f64 * INDENT))
+                .on_click_stop(move |_| {
+                    if expandable {
+                        tree.update(|tree| tree.toggle(&pointer));
+                    }
+                })
+        },
+        ROW_HEIGHT,
+    )
+}
+
+/// Create a side-by-side diff view with an apply button
+pub fn diff_view(
+    diff: DiffView,
+    sandbox: Option<WorkspaceSandbox>,
+) -> Box<dyn View> {
+    let rows: im::Vector<(String, DiffRow)> = diff
+        .files
+        .iter()
+        .enumerate()
+        .flat_map(|(index, file)| {
+            let name = file
+                .path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            diff.side_by_side(index)
+                .into_iter()
+                .map(move |row| (name.clone(), row))
+        })
+        .collect();
+    let semantic = sandbox
+        .as_ref()
+        .map(|sandbox| {
+            diff.semantic_diffs(sandbox)
+                .iter()
+                .map(SemanticDiff::summary)
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        })
+        .unwrap_or_default();
+    let status = create_rw_signal(String::new());
+    let diff = Rc::new(diff);
+    let can_apply = sandbox.is_some();
+
+    let apply_button = label(|| "Apply".to_string())
+        .on_click_stop(move |_| {
+            let Some(sandbox) = &sandbox else {
+                return;
+            };
+            status.set(match diff.apply(sandbox) {
+                Ok(paths) => format!("Applied to {} files", paths.len()),
+                Err(err) => format!("Failed to apply: {err:#}"),
+            });
+        })
+        .disabled(move || !can_apply);
+    let header =
+        h_stack((apply_button, label(move || status.get()))).style(|s| s.gap(10.0));
+
+    let rows = virtual_list_view(
+        move || rows.clone(),
+        |(index, _): &(usize, (String, DiffRow))| *index,
+        |(_, (file, row))| {
+            let side = |line: &Option<(usize, String)>, marker: &str| match line {
+                Some((number, text)) => format!("{:>5} {}{}", number, marker, text),
+                None => String::new(),
+            };
+            let (left, right) = match row.kind {
+                DiffRowKind::Hunk => {
+                    (file, row.new.map(|(_, header)| header).unwrap_or_default())
+                }
+                DiffRowKind::Context => (side(&row.old, " "), side(&row.new, " ")),
+                _ => (side(&row.old, "-"), side(&row.new, "+")),
+            };
+            h_stack((
+                label(move || left.clone())
+                    .style(|s| s.flex_basis(0.0).flex_grow(1.0)),
+                label(move || right.clone())
+                    .style(|s| s.flex_basis(0.0).flex_grow(1.0)),
+            ))
+            .style(|s| s.width_full())
+        },
+        ROW_HEIGHT,
+    );
+    let has_semantic = !semantic.is_empty();
+    let semantic = label(move || semantic.clone())
+        .style(move |s| s.apply_if(!has_semantic, |s| s.hide()));
+    Box::new(v_stack((header, semantic, rows)).style(|s| s.size_full()))
+}
+
+/// Create a log view filtered by a search field
+pub fn log_view(log: LogView) -> Box<dyn View> {
+    let query = create_rw_signal(String::new());
+    let log = Rc::new(log);
+    let lines = virtual_list_view(
+        move || {
+            let query = query.get();
+            log.filter(&query)
+                .into_iter()
+                .map(|(index, line)| (index, line.to_string()))
+                .collect()
+        },
+        |(_, (index, _)): &(usize, (usize, String))| *index,
+        |(_, (index, line))| {
+            let text = format!("{:>6}  {}", index + 1, line);
+            label(move || text.clone())
+        },
+        ROW_HEIGHT,
+    );
+    Box::new(
+        v_stack((text_input(query).style(|s| s.width_full()), lines))
+            .style(|s| s.size_full()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(data: &str) -> McpContent {
+        McpContent {
+            content_type: "text".to_string(),
+            data: Value::String(data.to_string()),
+            hint: None,
+        }
+    }
+
+    #[test]
+    fn test_selects_viewers_and_applies_diffs() {
+        let diff_text = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                         --- a/src/lib.rs\n\
+                         +++ b/src/lib.rs\n\
+                         @@ -2,3 +2,3 @@\n \
+                         fn a() {}\n\
+                         -fn b() {}\n\
+                         +fn b() -> u32 { 1 }\n \
+                         fn c() {}\n";
+        let log_text = (0..50).map(|i| format!("line {i}\n")).collect::<String>();
+        assert_eq!(content_hint(&content(diff_text)), McpContentHint::Diff);
+        assert_eq!(
+            content_hint(&content(r#"{"a":[1,2]}"#)),
+            McpContentHint::Json
+        );
+        assert_eq!(content_hint(&content(&log_text)), McpContentHint::Log);
+        assert_eq!(content_hint(&content("done")), McpContentHint::Text);
+        let mut hinted = content("done");
+        hinted.hint = Some(McpContentHint::Log);
+        assert_eq!(
+            ToolResultViewer::for_content(&hinted).hint(),
+            McpContentHint::Log
+        );
+
+        let diff = DiffView::parse(diff_text).unwrap();
+        let kinds: Vec<_> =
+            diff.side_by_side(0).iter().map(|row| row.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiffRowKind::Hunk,
+                DiffRowKind::Context,
+                DiffRowKind::Modified,
+                DiffRowKind::Context
+            ]
+        );
+        // The file gained a line at the top since the diff was made
+        let original = "use x;\nfn z() {}\nfn a() {}\nfn b() {}\nfn c() {}\n";
+        assert_eq!(
+            diff.files[0].apply_to(original).unwrap(),
+            "use x;\nfn z() {}\nfn a() {}\nfn b() -> u32 { 1 }\nfn c() {}\n"
+        );
+        assert!(diff.files[0].apply_to("fn a() {}\n").is_err());
+        // Hunks aren't looked for far from their line numbers
+        let moved = format!(
+            "{}fn a() {{}}\nfn b() {{}}\nfn c() {{}}\n",
+            "\n".repeat(MAX_HUNK_DRIFT + 2)
+        );
+        assert!(diff.files[0].apply_to(&moved).is_err());
+
+        // Lines looking like file headers belong to the hunk until it has
+        // as many lines as its header says
+        let sql = DiffView::parse(
+            "--- a/q.sql\n\
+             +++ b/q.sql\n\
+             @@ -1,2 +1,2 @@\n\
+             --- old note\n\
+             +-- new note\n \
+             select 1;\n\
+             --- a/r.sql\n\
+             +++ b/r.sql\n\
+             @@ -1 +1 @@\n\
+             -select 2;\n\
+             +select 3;\n",
+        )
+        .unwrap();
+        assert_eq!(sql.files.len(), 2);
+        assert_eq!(
+            sql.files[0].hunks[0].lines[0],
+            DiffLine::Removed("-- old note".to_string())
+        );
+        assert_eq!(sql.files[1].path(), Some(&PathBuf::from("r.sql")));
+        // Files with CRLF line endings keep them
+        assert_eq!(
+            sql.files[0]
+                .apply_to("-- old note\r\nselect 1;\r\n")
+                .unwrap(),
+            "-- new note\r\nselect 1;\r\n"
+        );
+
+        let mut tree = JsonTree::new(serde_json::json!({"a": {"b": {"c": 1}}}));
+        let pointers: Vec<_> =
+            tree.rows().into_iter().map(|row| row.pointer).collect();
+        assert_eq!(pointers, vec!["", "/a", "/a/b"]);
+        tree.toggle("/a/b");
+        assert_eq!(tree.rows().last().unwrap().text, "1");
+
+        let log = LogView::new(&log_text);
+        assert_eq!(log.search("LINE 4"), Vec::<usize>::new());
+        assert_eq!(
+            log.search("line 4"),
+            vec![4, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49]
+        );
+    }
+
+    #[test]
+    fn test_views_media_and_resources() {
+        let screenshot = McpContent::from(McpTypedContent::Image {
+            data: general_purpose::STANDARD.encode(b"\x89PNG"),
+            mime_type: "image/png".to_string(),
+        });
+        assert_eq!(content_hint(&screenshot), McpContentHint::Image);
+        let ToolResultViewer::Image(image) =
+            ToolResultViewer::for_content(&screenshot)
+        else {
+            panic!("not an image");
+        };
+        assert_eq!(&*image.bytes, b"\x89PNG");
+
+        let recording = McpContent::from(McpTypedContent::Audio {
+            data: general_purpose::STANDARD.encode([0u8; 2048]),
+            mime_type: "audio/mpeg".to_string(),
+        });
+        let ToolResultViewer::Audio(audio) =
+            ToolResultViewer::for_content(&recording)
+        else {
+            panic!("not audio");
+        };
+        assert_eq!(audio.summary(), "audio/mpeg, 2.0 KB");
+        assert_eq!(audio.extension(), Some("mp3"));
+        for (mime_type, extension) in [
+            ("audio/ogg; codecs=opus", Some("ogg")),
+            ("audio/x-wav", Some("wav")),
+            ("audio/bat", None),
+            ("audio/exe", None),
+            ("audio/x-msdownload", None),
+        ] {
+            let audio = MediaContent {
+                mime_type: mime_type.to_string(),
+                bytes: Arc::from(&b""[..]),
+            };
+            assert_eq!(audio.extension(), extension, "{mime_type}");
+        }
+
+        let broken = McpContent::from(McpTypedContent::Image {
+            data: "not base64!".to_string(),
+            mime_type: "image/png".to_string(),
+        });
+        assert_eq!(
+            ToolResultViewer::for_content(&broken).hint(),
+            McpContentHint::Text
+        );
+
+        let resource = |mime_type: &str, text: Option<&str>, blob: Option<&str>| {
+            McpContent::from(McpTypedContent::Resource {
+                resource: McpEmbeddedResource {
+                    uri: "file:///report".to_string(),
+                    mime_type: Some(mime_type.to_string()),
+                    text: text.map(str::to_string),
+                    blob: blob.map(str::to_string),
+                },
+            })
+        };
+        let json = resource("application/json", Some(r#"{"a":1}"#), None);
+        assert_eq!(content_hint(&json), McpContentHint::Json);
+        let image = resource("image/gif", None, Some("R0lGOA=="));
+        assert_eq!(
+            ToolResultViewer::for_content(&image).hint(),
+            McpContentHint::Image
+        );
+        let archive = resource("application/zip", None, Some("UEsDBA=="));
+        assert!(matches!(
+            ToolResultViewer::for_content(&archive),
+            ToolResultViewer::Text(uri) if uri == "file:///report"
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+//! Trigram Index
+//!
+//! This module implements case-insensitive content search over context
+//! chunks using a trigram posting list, ranking chunks by how many of the
+//! query's trigrams they contain. Removing a file drops its chunks from the
+//! posting lists, and their slots are reused by the next chunks indexed, so
+//! the index doesn't grow as files change.
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::plugin_api::{
+    AllocTag, ContextChunk, IndexKind, IndexLoad, RetrievalSource, Retriever,
+    alloc_scope, read_index, write_index,
+};
+
+#[derive(Default)]
+struct TrigramIndexInner {
+    /// Chunks by slot, `None` once removed
+    chunks: Vec<Option<ContextChunk>>,
+    /// Slots of removed chunks, reused first
+    free: Vec<usize>,
+    postings: HashMap<u32, Vec<usize>>,
+}
+
+/// Trigram content index for context chunks
+#[derive(Default)]
+pub struct TrigramIndex {
+    inner: RwLock<TrigramIndexInner>,
+}
+
+impl TrigramIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add chunks to the index
+    pub fn insert_chunks(&self, chunks: Vec<ContextChunk>) {
+        let _scope = alloc_scope(AllocTag::Index);
+        let mut inner = self.inner.write();
+        for chunk in chunks {
+            for trigram in trigrams(&chunk.content) {
+                let slot = inner.free.last().copied().unwrap_or(inner.chunks.len());
+                inner.postings.entry(trigram).or_default().push(slot);
+            }
+            match inner.free.pop() {
+                Some(slot) => inner.chunks[slot] = Some(chunk),
+                None => inner.chunks.push(Some(chunk)),
+            }
+        }
+        RetrievalSource::Trigram.mark_updated();
+    }
+
+    /// Remove all chunks of a file
+    pub fn remove_path(&self, path: &Path) {
+        let mut inner = self.inner.write();
+        let inner = &mut *inner;
+        for (slot, entry) in inner.chunks.iter_mut().enumerate() {
+            let Some(chunk) = entry.take_if(|chunk| chunk.path == path) else {
+                continue;
+            };
+            for trigram in trigrams(&chunk.content) {
+                let Some(slots) = inner.postings.get_mut(&trigram) else {
+                    continue;
+                };
+                slots.retain(|other| *other != slot);
+                if slots.is_empty() {
+                    inner.postings.remove(&trigram);
+                }
+            }
+            inner.free.push(slot);
+        }
+    }
+
+    /// Persist the indexed chunks; postings are rebuilt on load
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let inner = self.inner.read();
+        let chunks: Vec<&ContextChunk> = inner.chunks.iter().flatten().collect();
+        write_index(path, IndexKind::FileIndex, &chunks)
+    }
+
+    /// Load an index written by [`TrigramIndex::save`]
+    pub fn load(path: &Path) -> IndexLoad<Self> {
+        read_index::<Vec<ContextChunk>>(path, IndexKind::FileIndex).map(|chunks| {
+            let index = Self::new();
+            index.insert_chunks(chunks);
+            IndexLoad::Loaded(index)
+        })
+    }
+
+    /// Search for chunks matching the query text
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(ContextChunk, f32)> {
+        let query_trigrams = trigrams(query);
+        if query_trigrams.is_empty() {
+            return Vec::new();
+        }
+
+        let inner = self.inner.read();
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+        for trigram in &query_trigrams {
+            if let Some(slots) = inner.postings.get(trigram) {
+                for slot in slots {
+                    *hits.entry(*slot).or_default() += 1;
+                }
+            }
+        }
+
+        let query_lowercase = query.to_lowercase();
+        let mut scored: Vec<(usize, f32)> = hits
+            .into_iter()
+            .filter(|(slot, _)| inner.chunks[*slot].is_some())
+            .map(|(slot, hits)| {
+                let mut score = hits as f32 / query_trigrams.len() as f32;
+                let chunk = inner.chunks[slot].as_ref().unwrap();
+                if chunk.content.to_lowercase().contains(&query_lowercase) {
+                    score += 1.0;
+                }
+                (slot, score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(limit);
+
+        scored
+            .into_iter()
+            .filter_map(|(slot, score)| {
+                inner.chunks[slot].clone().map(|chunk| (chunk, score))
+            })
+            .collect()
+    }
+}
+
+impl Retriever for TrigramIndex {
+    fn source(&self) -> RetrievalSource {
+        RetrievalSource::Trigram
+    }
+
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>> {
+        Ok(self
+            .search(query, limit)
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect())
+    }
+}
+
+/// Distinct lowercase byte trigrams of a text, packed into integers
+fn trigrams(text: &str) -> HashSet<u32> {
+    let bytes: Vec<u8> = text.bytes().map(|b| b.to_ascii_lowercase()).collect();
+    bytes
+        .windows(3)
+        .filter(|window| !window.iter().all(|b| b.is_ascii_whitespace()))
+        .map(|window| {
+            (window[0] as u32) << 16 | (window[1] as u32) << 8 | window[2] as u32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::SharedPath;
+
+    fn chunk(path: &str, content: &str) -> ContextChunk {
+        ContextChunk {
+            path: SharedPath::from(path),
+            start_line: 1,
+            end_line: 1,
+            content: content.to_string(),
+            modified: None,
+        }
+    }
+
+    fn paths(hits: &[(ContextChunk, f32)]) -> Vec<String> {
+        hits.iter()
+            .map(|(chunk, _)| chunk.path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_and_remove() {
+        let index = TrigramIndex::new();
+        index.insert_chunks(vec![
+            chunk("src/parser.rs", "fn parse_config(input: &str)"),
+            chunk("src/main.rs", "fn main() { run() }"),
+        ]);
+        let hits = index.search("Parse_Config", 10);
+        assert_eq!(paths(&hits), ["src/parser.rs"]);
+        // Exact matches get a bonus on top of their trigram share
+        assert!(hits[0].1 > 1.0);
+        assert!(index.search("  ", 10).is_empty());
+
+        index.remove_path(Path::new("src/parser.rs"));
+        assert!(index.search("parse_config", 10).is_empty());
+        assert_eq!(paths(&index.search("main", 10)), ["src/main.rs"]);
+
+        // The freed slot is reused and only live postings remain
+        index.insert_chunks(vec![chunk("src/lexer.rs", "fn lex()")]);
+        assert_eq!(paths(&index.search("lex", 10)), ["src/lexer.rs"]);
+        index.remove_path(Path::new("src/lexer.rs"));
+        index.remove_path(Path::new("src/main.rs"));
+        let inner = index.inner.read();
+        assert_eq!(inner.chunks.len(), 2);
+        assert!(inner.postings.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("trigrams.bin");
+        let index = TrigramIndex::new();
+        index.insert_chunks(vec![
+            chunk("a.rs", "struct Alpha;"),
+            chunk("b.rs", "struct Beta;"),
+        ]);
+        index.remove_path(Path::new("a.rs"));
+        index.save(&file).unwrap();
+
+        let IndexLoad::Loaded(loaded) = TrigramIndex::load(&file) else {
+            panic!("the index wasn't loaded");
+        };
+        assert_eq!(paths(&loaded.search("beta", 10)), ["b.rs"]);
+        assert!(loaded.search("alpha", 10).is_empty());
+    }
+}
@@ -0,0 +1,535 @@
+//! Usage Simulation
+//!
+//! This module backs `catalyst usage simulate`, which replays the requests
+//! of a recorded session against other ways of routing them to models and
+//! estimates what the session would have cost and how long it would have
+//! taken. It helps pick the default models: a cheaper model for small
+//! requests may save most of the cost while adding little waiting time.
+//!
+//! Prices and speeds come from the [`ModelCatalog`]. The built-in entries
+//! are list prices and rough speeds of common models; entries in
+//! `models.toml` in the config directory add models, such as local ones, or
+//! replace built-in entries of the same name. Latency is estimated as the
+//! time to the first token plus the output tokens at the model's speed, with
+//! the requests of a session running one after another.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::plugin_api::{Report, ReportSection, UsageRecord};
+
+/// File in the config directory with additional catalog entries
+pub const MODEL_CATALOG_FILE: &str = "models.toml";
+
+/// Name, provider, dollars per million input and output tokens, output
+/// tokens per second and milliseconds to the first token of common models
+const BUILTIN_MODELS: &[(&str, &str, f64, f64, f64, u64)] = &[
+    ("claude-opus-4", "anthropic", 15.0, 75.0, 40.0, 2000),
+    ("claude-sonnet-4", "anthropic", 3.0, 15.0, 60.0, 1200),
+    ("claude-3-5-haiku", "anthropic", 0.8, 4.0, 100.0, 700),
+    ("gpt-4o", "openai", 2.5, 10.0, 80.0, 800),
+    ("gpt-4o-mini", "openai", 0.15, 0.6, 100.0, 600),
+];
+
+/// Price and speed of a model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    /// Model name as sent to the provider, e.g. `claude-sonnet-4`
+    pub name: String,
+    pub provider: String,
+    /// Dollars per million input tokens
+    pub input_cost_per_million: f64,
+    /// Dollars per million output tokens
+    pub output_cost_per_million: f64,
+    /// Output tokens generated per second once the answer started
+    pub output_tokens_per_sec: f64,
+    /// Milliseconds until the first token arrives
+    pub first_token_ms: u64,
+}
+
+impl ModelPricing {
+    fn new(
+        name: &str,
+        provider: &str,
+        input_cost_per_million: f64,
+        output_cost_per_million: f64,
+        output_tokens_per_sec: f64,
+        first_token_ms: u64,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            provider: provider.to_string(),
+            input_cost_per_million,
+            output_cost_per_million,
+            output_tokens_per_sec,
+            first_token_ms,
+        }
+    }
+
+    /// Estimate the cost of a request
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_cost_per_million
+            + output_tokens as f64 * self.output_cost_per_million)
+            / 1e6
+    }
+
+    /// Estimate how long a request takes to answer
+    pub fn latency(&self, output_tokens: u32) -> Duration {
+        let generation = if self.output_tokens_per_sec > 0.0 {
+            output_tokens as f64 / self.output_tokens_per_sec
+        } else {
+            0.0
+        };
+        Duration::from_millis(self.first_token_ms)
+            + Duration::from_secs_f64(generation)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CatalogFile {
+    #[serde(default)]
+    models: Vec<ModelPricing>,
+}
+
+/// Known models with their prices and speeds
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCatalog {
+    pub models: Vec<ModelPricing>,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ModelCatalog {
+    /// Get the built-in catalog of common models
+    pub fn builtin() -> Self {
+        Self {
+            models: BUILTIN_MODELS
+                .iter()
+                .map(|&(name, provider, input, output, speed, first_token_ms)| {
+                    ModelPricing::new(
+                        name,
+                        provider,
+                        input,
+                        output,
+                        speed,
+                        first_token_ms,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Load the catalog with the entries of `models.toml` in the config
+    /// directory
+    pub fn load_default() -> Result<Self> {
+        match Directory::config_directory() {
+            Some(dir) => Self::load(&dir.join(MODEL_CATALOG_FILE)),
+            None => Ok(Self::builtin()),
+        }
+    }
+
+    /// Load the built-in catalog with the entries of a catalog file, which
+    /// replace built-in entries of the same name
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut catalog = Self::builtin();
+        if !path.is_file() {
+            return Ok(catalog);
+        }
+        let file: CatalogFile = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))?;
+        for model in file.models {
+            catalog
+                .models
+                .retain(|existing| existing.name != model.name);
+            catalog.models.push(model);
+        }
+        Ok(catalog)
+    }
+
+    /// Find the entry of a model
+    ///
+    /// Providers often report a dated version of a model, e.g.
+    /// `claude-sonnet-4-20250514`, which matches the longest entry its name
+    /// starts with.
+    pub fn find(&self, model: &str) -> Option<&ModelPricing> {
+        self.models
+            .iter()
+            .find(|pricing| pricing.name == model)
+            .or_else(|| {
+                self.models
+                    .iter()
+                    .filter(|pricing| model.starts_with(&pricing.name))
+                    .max_by_key(|pricing| pricing.name.len())
+            })
+    }
+}
+
+/// How the requests of a session are routed to models
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoutingPolicy {
+    /// Every request goes to the model it was recorded with
+    Recorded,
+    /// Every request goes to one model
+    Model(String),
+    /// Requests with up to `max_small_input_tokens` input tokens go to the
+    /// small model, all others to the large one
+    Tiered {
+        small: String,
+        large: String,
+        max_small_input_tokens: u32,
+    },
+}
+
+impl RoutingPolicy {
+    /// Parse a policy as given on the command line: `recorded`, a model
+    /// name, or `tiered:<small>,<large>,<max small input tokens>`
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec == "recorded" {
+            return Ok(Self::Recorded);
+        }
+        let Some(tiers) = spec.strip_prefix("tiered:") else {
+            let model = spec.strip_prefix("model:").unwrap_or(spec);
+            if model.is_empty() {
+                return Err(anyhow::anyhow!("Routing policy names no model"));
+            }
+            return Ok(Self::Model(model.to_string()));
+        };
+        let parts: Vec<&str> = tiers.split(',').map(str::trim).collect();
+        match parts.as_slice() {
+            [small, large, max_tokens] if !small.is_empty() && !large.is_empty() => {
+                Ok(Self::Tiered {
+                    small: small.to_string(),
+                    large: large.to_string(),
+                    max_small_input_tokens: max_tokens.parse().map_err(|_| {
+                        anyhow::anyhow!("Invalid token count '{}'", max_tokens)
+                    })?,
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "Invalid routing policy '{}', expected \
+                 tiered:<small>,<large>,<max small input tokens>",
+                spec
+            )),
+        }
+    }
+
+    /// Describe the policy for reports
+    pub fn name(&self) -> String {
+        match self {
+            Self::Recorded => "recorded models".to_string(),
+            Self::Model(model) => model.clone(),
+            Self::Tiered {
+                small,
+                large,
+                max_small_input_tokens,
+            } => format!(
+                "{} up to {} input tokens, else {}",
+                small, max_small_input_tokens, large
+            ),
+        }
+    }
+
+    /// Get the model a request is routed to
+    pub fn route<'a>(&'a self, record: &'a UsageRecord) -> &'a str {
+        match self {
+            Self::Recorded => &record.model,
+            Self::Model(model) => model,
+            Self::Tiered {
+                small,
+                large,
+                max_small_input_tokens,
+            } => {
+                if record.input_tokens <= *max_small_input_tokens {
+                    small
+                } else {
+                    large
+                }
+            }
+        }
+    }
+
+    /// Get the models the policy routes to, other than the recorded ones
+    fn models(&self) -> Vec<&str> {
+        match self {
+            Self::Recorded => Vec::new(),
+            Self::Model(model) => vec![model],
+            Self::Tiered { small, large, .. } => vec![small, large],
+        }
+    }
+}
+
+/// Estimated cost and latency of a session under one routing policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyEstimate {
+    pub policy: RoutingPolicy,
+    pub cost: f64,
+    pub latency_ms: u64,
+    /// Requests routed to a model missing from the catalog, which are left
+    /// out of the estimate
+    pub unpriced_requests: usize,
+}
+
+/// Estimates of a recorded session under several routing policies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSimulation {
+    pub session: String,
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub estimates: Vec<PolicyEstimate>,
+}
+
+impl UsageSimulation {
+    /// Estimate the cost and latency of the requests of a session under each
+    /// policy; every policy but [`RoutingPolicy::Recorded`] must only name
+    /// models of the catalog
+    ///
+    /// Under the recorded policy, the cost a provider reported is used
+    /// where there is one.
+    pub fn simulate(
+        session: &str,
+        records: &[UsageRecord],
+        catalog: &ModelCatalog,
+        policies: &[RoutingPolicy],
+    ) -> Result<Self> {
+        for model in policies.iter().flat_map(RoutingPolicy::models) {
+            if catalog.find(model).is_none() {
+                return Err(anyhow::anyhow!(
+                    "Model '{}' is not in the model catalog, add it to {}",
+                    model,
+                    MODEL_CATALOG_FILE
+                ));
+            }
+        }
+
+        let estimates = policies
+            .iter()
+            .map(|policy| {
+                let mut estimate = PolicyEstimate {
+                    policy: policy.clone(),
+                    cost: 0.0,
+                    latency_ms: 0,
+                    unpriced_requests: 0,
+                };
+                let mut latency = Duration::ZERO;
+                for record in records {
+                    let pricing = catalog.find(policy.route(record));
+                    let reported =
+                        record.cost.filter(|_| *policy == RoutingPolicy::Recorded);
+                    match (pricing, reported) {
+                        (Some(pricing), reported) => {
+                            estimate.cost += reported.unwrap_or_else(|| {
+                                pricing
+                                    .cost(record.input_tokens, record.output_tokens)
+                            });
+                            latency += pricing.latency(record.output_tokens);
+                        }
+                        (None, Some(reported)) => estimate.cost += reported,
+                        (None, None) => estimate.unpriced_requests += 1,
+                    }
+                }
+                estimate.latency_ms = latency.as_millis() as u64;
+                estimate
+            })
+            .collect();
+
+        Ok(Self {
+            session: session.to_string(),
+            requests: records.len(),
+            input_tokens: records.iter().map(|r| r.input_tokens as u64).sum(),
+            output_tokens: records.iter().map(|r| r.output_tokens as u64).sum(),
+            estimates,
+        })
+    }
+
+    /// Get the policies to compare when none are picked: the recorded
+    /// models and each model of the catalog on its own
+    pub fn default_policies(catalog: &ModelCatalog) -> Vec<RoutingPolicy> {
+        std::iter::once(RoutingPolicy::Recorded)
+            .chain(
+                catalog
+                    .models
+                    .iter()
+                    .map(|model| RoutingPolicy::Model(model.name.clone())),
+            )
+            .collect()
+    }
+
+    /// Get the cheapest estimate that priced every request
+    pub fn cheapest(&self) -> Option<&PolicyEstimate> {
+        self.complete_estimates()
+            .min_by(|a, b| a.cost.total_cmp(&b.cost))
+    }
+
+    /// Get the fastest estimate that priced every request
+    pub fn fastest(&self) -> Option<&PolicyEstimate> {
+        self.complete_estimates()
+            .min_by_key(|estimate| estimate.latency_ms)
+    }
+
+    fn complete_estimates(&self) -> impl Iterator<Item = &PolicyEstimate> {
+        self.estimates
+            .iter()
+            .filter(|estimate| estimate.unpriced_requests == 0)
+    }
+}
+
+impl Report for UsageSimulation {
+    fn title(&self) -> String {
+        "Usage simulation".to_string()
+    }
+
+    fn subtitle(&self) -> Option<String> {
+        Some(format!(
+            "Session {}: {} requests, {} input and {} output tokens",
+            self.session, self.requests, self.input_tokens, self.output_tokens
+        ))
+    }
+
+    fn sections(&self) -> Vec<ReportSection> {
+        let recorded = self
+            .estimates
+            .iter()
+            .find(|estimate| estimate.policy == RoutingPolicy::Recorded);
+        let policies = self.estimates.iter().fold(
+            ReportSection::new("Estimates by routing policy"),
+            |section, estimate| {
+                let mut value = format!(
+                    "${:.4}, {:.1}s",
+                    estimate.cost,
+                    estimate.latency_ms as f64 / 1000.0
+                );
+                if let Some(recorded) = recorded.filter(|recorded| {
+                    recorded.policy != estimate.policy && recorded.cost > 0.0
+                }) {
+                    value.push_str(&format!(
+                        " ({:+.0}% cost vs recorded)",
+                        (estimate.cost / recorded.cost - 1.0) * 100.0
+                    ));
+                }
+                if estimate.unpriced_requests > 0 {
+                    value.push_str(&format!(
+                        ", {} requests of unknown models left out",
+                        estimate.unpriced_requests
+                    ));
+                }
+                section.row(estimate.policy.name(), value)
+            },
+        );
+        let mut recommendation = ReportSection::new("Recommendation");
+        if let Some(cheapest) = self.cheapest() {
+            recommendation = recommendation.row("Cheapest", cheapest.policy.name());
+        }
+        if let Some(fastest) = self.fastest() {
+            recommendation = recommendation.row("Fastest", fastest.policy.name());
+        }
+        vec![policies, recommendation]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::ReportFormat;
+    use std::time::SystemTime;
+
+    fn record(model: &str, input_tokens: u32, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            key: "project_chat/7".to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens: 1000,
+            cost,
+            timestamp: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_simulates_routing_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MODEL_CATALOG_FILE);
+        std::fs::write(
+            &path,
+            "[[models]]\nname = \"local\"\nprovider = \"ollama\"\n\
+             input_cost_per_million = 0.0\noutput_cost_per_million = 0.0\n\
+             output_tokens_per_sec = 20.0\nfirst_token_ms = 100\n",
+        )
+        .unwrap();
+        let catalog = ModelCatalog::load(&path).unwrap();
+        assert_eq!(
+            catalog.find("claude-sonnet-4-20250514").unwrap().name,
+            "claude-sonnet-4"
+        );
+        assert_eq!(catalog.find("local").unwrap().provider, "ollama");
+        assert!(catalog.find("unknown").is_none());
+
+        assert_eq!(
+            RoutingPolicy::from_spec("tiered:gpt-4o-mini,claude-sonnet-4,2000")
+                .unwrap(),
+            RoutingPolicy::Tiered {
+                small: "gpt-4o-mini".to_string(),
+                large: "claude-sonnet-4".to_string(),
+                max_small_input_tokens: 2000,
+            }
+        );
+        assert!(RoutingPolicy::from_spec("tiered:a,b").is_err());
+
+        let records = [
+            record("claude-sonnet-4-20250514", 1000, Some(0.5)),
+            record("claude-sonnet-4-20250514", 9000, None),
+            record("unknown", 1000, None),
+        ];
+        let policies = [
+            RoutingPolicy::Recorded,
+            RoutingPolicy::from_spec("local").unwrap(),
+            RoutingPolicy::from_spec("tiered:gpt-4o-mini,claude-sonnet-4,2000")
+                .unwrap(),
+        ];
+        let simulation = UsageSimulation::simulate(
+            "project_chat/7",
+            &records,
+            &catalog,
+            &policies,
+        )
+        .unwrap();
+        assert_eq!(simulation.requests, 3);
+        assert_eq!(simulation.input_tokens, 11_000);
+
+        let recorded = &simulation.estimates[0];
+        assert!((recorded.cost - (0.5 + 0.042)).abs() < 1e-9);
+        assert_eq!(recorded.latency_ms, 35_733);
+        assert_eq!(recorded.unpriced_requests, 1);
+
+        let local = &simulation.estimates[1];
+        assert_eq!(local.cost, 0.0);
+        assert_eq!(local.latency_ms, 3 * (100 + 50_000));
+
+        let tiered = &simulation.estimates[2];
+        let expected = 2.0 * (1000.0 * 0.15 + 1000.0 * 0.6) / 1e6 + 0.042;
+        assert!((tiered.cost - expected).abs() < 1e-9);
+        assert_eq!(tiered.unpriced_requests, 0);
+
+        assert_eq!(simulation.cheapest().unwrap().policy, policies[1]);
+        assert_eq!(simulation.fastest().unwrap().policy, policies[2]);
+        let text = simulation.render(ReportFormat::Text).unwrap();
+        assert!(text.contains("Cheapest  local"));
+
+        assert!(
+            UsageSimulation::simulate(
+                "project_chat/7",
+                &records,
+                &catalog,
+                &[RoutingPolicy::Model("unknown".to_string())],
+            )
+            .is_err()
+        );
+    }
+}
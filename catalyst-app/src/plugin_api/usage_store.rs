@@ -0,0 +1,259 @@
+//! Usage Store
+//!
+//! This module records the tokens and cost of every assistant request,
+//! keyed by what the request was for, e.g. the answer of a chat message.
+//! Features that send several requests for one result, such as alternative
+//! drafts of an answer, can then show what each of them cost, and the
+//! totals show what a feature costs overall.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::plugin_api::{
+    AiMessageResponse, Clock, JournalOptions, JournalState, JournaledStore,
+    SystemClock, UsageReport,
+};
+
+/// Records kept; the oldest are dropped first
+const MAX_RECORDS: usize = 20_000;
+
+/// Usage of one request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// What the request was for, e.g. `project_chat/12`
+    pub key: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Cost reported by the provider, if it reports one
+    pub cost: Option<f64>,
+    pub timestamp: SystemTime,
+}
+
+/// Sum of the usage of several requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Cost of the requests whose provider reported one
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    /// Add the usage of a request
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.input_tokens += record.input_tokens as u64;
+        self.output_tokens += record.output_tokens as u64;
+        self.cost += record.cost.unwrap_or(0.0);
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageFile {
+    records: Vec<UsageRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum UsageOp {
+    Record(UsageRecord),
+    Clear,
+}
+
+impl JournalState for UsageFile {
+    type Op = UsageOp;
+
+    fn apply(&mut self, op: &UsageOp) {
+        match op {
+            UsageOp::Record(record) => {
+                self.records.push(record.clone());
+                if self.records.len() > MAX_RECORDS {
+                    let excess = self.records.len() - MAX_RECORDS;
+                    self.records.drain(..excess);
+                }
+            }
+            UsageOp::Clear => self.records.clear(),
+        }
+    }
+}
+
+/// Persistent record of assistant usage
+pub struct UsageStore {
+    store: JournaledStore<UsageFile>,
+    /// Stamps the records and dates the reports
+    clock: Arc<dyn Clock>,
+}
+
+impl UsageStore {
+    /// Open the usage store kept in the local data directory
+    pub fn open_default() -> Result<Self> {
+        let dir = Directory::data_local_directory()
+            .ok_or_else(|| anyhow::anyhow!("Local data directory is unavailable"))?;
+        Self::open(&dir.join("usage.json"))
+    }
+
+    /// Open a usage store backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            store: JournaledStore::open(path, JournalOptions::default())?,
+            clock: SystemClock::shared(),
+        })
+    }
+
+    /// Stamp records and date reports with the time of `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Record the usage of a response
+    ///
+    /// Responses without usage information are recorded as a request with
+    /// no tokens, so request counts stay right.
+    pub fn record(&self, key: &str, response: &AiMessageResponse) -> Result<()> {
+        let usage = response.usage.as_ref();
+        self.store.apply(UsageOp::Record(UsageRecord {
+            key: key.to_string(),
+            model: response.model.clone(),
+            input_tokens: usage.map(|usage| usage.input_tokens).unwrap_or(0),
+            output_tokens: usage.map(|usage| usage.output_tokens).unwrap_or(0),
+            cost: usage.and_then(|usage| usage.cost),
+            timestamp: self.clock.now(),
+        }))
+    }
+
+    /// Get the records of a key
+    pub fn records(&self, key: &str) -> Vec<UsageRecord> {
+        self.store
+            .read()
+            .records
+            .iter()
+            .filter(|record| record.key == key)
+            .cloned()
+            .collect()
+    }
+
+    /// Get the records of a session, kept under its key and the keys below
+    /// it, e.g. `project_chat/12` and `project_chat/12/draft`
+    pub fn session_records(&self, session: &str) -> Vec<UsageRecord> {
+        self.store
+            .read()
+            .records
+            .iter()
+            .filter(|record| {
+                record
+                    .key
+                    .strip_prefix(session)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Sum the usage of a key
+    pub fn usage(&self, key: &str) -> UsageTotals {
+        let mut totals = UsageTotals::default();
+        for record in self.store.read().records.iter() {
+            if record.key == key {
+                totals.add(record);
+            }
+        }
+        totals
+    }
+
+    /// Sum the usage of the keys starting with a prefix, e.g. `project_chat/`
+    pub fn totals(&self, prefix: &str) -> UsageTotals {
+        let mut totals = UsageTotals::default();
+        for record in self.store.read().records.iter() {
+            if record.key.starts_with(prefix) {
+                totals.add(record);
+            }
+        }
+        totals
+    }
+
+    /// Break the usage of all recorded requests down by feature and model
+    pub fn report(&self) -> UsageReport {
+        UsageReport::from_records(&self.store.read().records, self.clock.now())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.store.apply(UsageOp::Clear)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{ManualClock, UsageInfo};
+    use std::time::Duration;
+
+    #[test]
+    fn test_usage_totals_by_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        let store = UsageStore::open(&path).unwrap();
+        let response = |input_tokens, cost| AiMessageResponse {
+            content: String::new(),
+            tool_calls: None,
+            usage: Some(UsageInfo {
+                input_tokens,
+                output_tokens: 10,
+                cost,
+            }),
+            model: "model".to_string(),
+            finish_reason: None,
+        };
+        store
+            .record("project_chat/2", &response(100, Some(0.5)))
+            .unwrap();
+        store.record("project_chat/3", &response(50, None)).unwrap();
+        store.record("rerank", &response(20, Some(0.1))).unwrap();
+
+        assert_eq!(store.records("project_chat/2").len(), 1);
+        assert_eq!(store.usage("project_chat/3").input_tokens, 50);
+        let totals = UsageStore::open(&path).unwrap().totals("project_chat/");
+        assert_eq!(totals.requests, 2);
+        assert_eq!(totals.input_tokens, 150);
+        assert_eq!(totals.output_tokens, 20);
+        assert_eq!(totals.cost, 0.5);
+
+        store
+            .record("project_chat/2/draft", &response(30, None))
+            .unwrap();
+        store
+            .record("project_chat/20", &response(30, None))
+            .unwrap();
+        assert_eq!(store.session_records("project_chat/2").len(), 2);
+    }
+
+    #[test]
+    fn test_records_are_stamped_by_the_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Arc::new(ManualClock::new(start));
+        let store = UsageStore::open(&dir.path().join("usage.json"))
+            .unwrap()
+            .with_clock(clock.clone());
+        let response = AiMessageResponse {
+            content: String::new(),
+            tool_calls: None,
+            usage: None,
+            model: "model".to_string(),
+            finish_reason: None,
+        };
+        store.record("project_chat/1", &response).unwrap();
+        clock.advance(Duration::from_secs(60));
+        store.record("project_chat/1", &response).unwrap();
+
+        let records = store.records("project_chat/1");
+        assert_eq!(records[0].timestamp, start);
+        assert_eq!(records[1].timestamp, start + Duration::from_secs(60));
+        assert_eq!(store.report().generated_at, start + Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,489 @@
+//! Vector Index
+//!
+//! This module stores embeddings of context chunks in memory and answers
+//! nearest-neighbour queries by cosine similarity.
+//!
+//! Vectors are keyed by the SHA-256 hash of the chunk content, so chunks
+//! with the same content share one vector: when a file changes only its
+//! changed chunks are embedded again, and code moved to another file or
+//! duplicated reuses the vector it already has. Removing a file only drops
+//! its chunks; the vectors nobody uses any more are garbage-collected on a
+//! schedule, so a branch switch removing and adding back the same files
+//! doesn't embed them again.
+
+use anyhow::Result;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin_api::{
+    AllocTag, ContextChunk, Embedding, EmbeddingProvider, IndexKind, IndexLoad,
+    RetrievalSource, Retriever, SharedPath, alloc_scope, embed_all,
+    negotiate_dimensions, read_index, write_index,
+};
+
+/// How often vectors of deleted files are garbage-collected by default
+pub const DEFAULT_VECTOR_GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Vectors on disk with the model that produced them
+#[derive(Serialize, Deserialize)]
+struct VectorStoreFile {
+    provider: String,
+    model: String,
+    dimensions: usize,
+    chunks: Vec<(ContextChunk, String)>,
+    vectors: HashMap<String, Embedding>,
+}
+
+/// Chunks with the hash of their content, and the vectors by content hash
+#[derive(Default)]
+struct VectorEntries {
+    chunks: Vec<(ContextChunk, String)>,
+    vectors: HashMap<String, Embedding>,
+}
+
+/// What updating the chunks of a file did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VectorUpdate {
+    /// Chunks whose content was embedded
+    pub embedded: usize,
+    /// Chunks that reused the vector of a chunk with the same content
+    pub reused: usize,
+}
+
+/// Get the hash vectors are keyed by
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// In-memory vector store for context chunks
+pub struct VectorIndex {
+    provider: Arc<dyn EmbeddingProvider>,
+    dimensions: usize,
+    entries: RwLock<VectorEntries>,
+    gc_interval: Duration,
+    last_gc: Mutex<Instant>,
+}
+
+impl VectorIndex {
+    /// Create an empty index, negotiating the vector dimensions with the
+    /// provider
+    pub fn new(
+        provider: Arc<dyn EmbeddingProvider>,
+        dimensions: Option<usize>,
+    ) -> Result<Self> {
+        let dimensions =
+            negotiate_dimensions(&provider.provider_info(), dimensions)?;
+        Ok(Self {
+            provider,
+            dimensions,
+            entries: RwLock::new(VectorEntries::default()),
+            gc_interval: DEFAULT_VECTOR_GC_INTERVAL,
+            last_gc: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Garbage-collect unused vectors at most this often
+    pub fn with_gc_interval(mut self, interval: Duration) -> Self {
+        self.gc_interval = interval;
+        self
+    }
+
+    /// Get the dimensions of the stored vectors
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Get the number of stored chunks
+    pub fn len(&self) -> usize {
+        self.entries.read().chunks.len()
+    }
+
+    /// Check if the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().chunks.is_empty()
+    }
+
+    /// Get the number of stored vectors, including the unused ones not
+    /// garbage-collected yet
+    pub fn vector_count(&self) -> usize {
+        self.entries.read().vectors.len()
+    }
+
+    /// Embed and store chunks, reusing the vectors of chunks with the same
+    /// content
+    pub fn insert_chunks(&self, chunks: Vec<ContextChunk>) -> Result<VectorUpdate> {
+        let _scope = alloc_scope(AllocTag::Index);
+        let hashed: Vec<(ContextChunk, String)> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let hash = content_hash(&chunk.content);
+                (chunk, hash)
+            })
+            .collect();
+
+        let mut update = VectorUpdate::default();
+        loop {
+            let mut seen = HashSet::new();
+            let missing: Vec<&(ContextChunk, String)> = {
+                let entries = self.entries.read();
+                hashed
+                    .iter()
+                    .filter(|(_, hash)| !entries.vectors.contains_key(hash))
+                    .filter(|(_, hash)| seen.insert(hash))
+                    .collect()
+            };
+            let inputs: Vec<String> = missing
+                .iter()
+                .map(|(chunk, _)| chunk.content.clone())
+                .collect();
+            let embeddings =
+                embed_all(self.provider.as_ref(), &inputs, self.dimensions)?;
+            update.embedded += missing.len();
+
+            let mut entries = self.entries.write();
+            for ((_, hash), embedding) in missing.into_iter().zip(embeddings) {
+                entries.vectors.insert(hash.clone(), embedding);
+            }
+            // A garbage collection while embedding may have dropped a
+            // vector found above, which is embedded again
+            if hashed
+                .iter()
+                .all(|(_, hash)| entries.vectors.contains_key(hash))
+            {
+                update.reused = hashed.len().saturating_sub(update.embedded);
+                entries.chunks.extend(hashed);
+                break;
+            }
+        }
+        RetrievalSource::Vector.mark_updated();
+        Ok(update)
+    }
+
+    /// Replace the chunks of a changed file, embedding only the chunks
+    /// whose content isn't stored yet
+    pub fn update_path(
+        &self,
+        path: &Path,
+        chunks: Vec<ContextChunk>,
+    ) -> Result<VectorUpdate> {
+        self.remove_path(path);
+        let update = self.insert_chunks(chunks)?;
+        self.collect_garbage_if_due();
+        Ok(update)
+    }
+
+    /// Remove all chunks of a file
+    ///
+    /// Their vectors are kept until the next garbage collection, for the
+    /// same content showing up again elsewhere.
+    pub fn remove_path(&self, path: &Path) {
+        self.entries
+            .write()
+            .chunks
+            .retain(|(chunk, _)| &*chunk.path != path);
+    }
+
+    /// Drop the chunks of files that no longer exist and the vectors no
+    /// chunk uses, returning the number of vectors dropped
+    ///
+    /// Files are checked without holding the lock, so searches go on
+    /// meanwhile; only files found missing are checked again under it.
+    pub fn collect_garbage(&self) -> usize {
+        *self.last_gc.lock() = Instant::now();
+        let paths: HashSet<SharedPath> = self
+            .entries
+            .read()
+            .chunks
+            .iter()
+            .map(|(chunk, _)| chunk.path.clone())
+            .collect();
+        let deleted: HashSet<SharedPath> =
+            paths.into_iter().filter(|path| !path.exists()).collect();
+
+        let mut entries = self.entries.write();
+        entries.chunks.retain(|(chunk, _)| {
+            !deleted.contains(&chunk.path) || chunk.path.exists()
+        });
+        let used: HashSet<String> = entries
+            .chunks
+            .iter()
+            .map(|(_, hash)| hash.clone())
+            .collect();
+        let before = entries.vectors.len();
+        entries.vectors.retain(|hash, _| used.contains(hash));
+        before - entries.vectors.len()
+    }
+
+    /// Garbage-collect if the last collection is older than the interval,
+    /// returning the number of vectors dropped if it ran
+    pub fn collect_garbage_if_due(&self) -> Option<usize> {
+        if self.last_gc.lock().elapsed() < self.gc_interval {
+            return None;
+        }
+        let dropped = self.collect_garbage();
+        if dropped > 0 {
+            tracing::debug!("Garbage-collected {} unused vectors", dropped);
+        }
+        Some(dropped)
+    }
+
+    /// Persist the stored vectors
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let info = self.provider.provider_info();
+        let entries = self.entries.read();
+        write_index(
+            path,
+            IndexKind::VectorStore,
+            &VectorStoreFile {
+                provider: info.id,
+                model: info.model,
+                dimensions: self.dimensions,
+                chunks: entries.chunks.clone(),
+                vectors: entries.vectors.clone(),
+            },
+        )
+    }
+
+    /// Load vectors written by [`VectorIndex::save`]
+    ///
+    /// Vectors of a different model or dimensions can't be compared with
+    /// new queries, so they are reported as incompatible.
+    pub fn load(
+        path: &Path,
+        provider: Arc<dyn EmbeddingProvider>,
+        dimensions: Option<usize>,
+    ) -> IndexLoad<Self> {
+        read_index::<VectorStoreFile>(path, IndexKind::VectorStore).map(|file| {
+            let index = match Self::new(provider, dimensions) {
+                Ok(index) => index,
+                Err(err) => return IndexLoad::Corrupt(format!("{err:#}")),
+            };
+            let info = index.provider.provider_info();
+            if file.provider != info.id
+                || file.model != info.model
+                || file.dimensions != index.dimensions
+                || file
+                    .vectors
+                    .values()
+                    .any(|embedding| embedding.len() != index.dimensions)
+            {
+                return IndexLoad::Incompatible(format!(
+                    "vectors are from {}/{} with {} dimensions",
+                    file.provider, file.model, file.dimensions
+                ));
+            }
+            if let Some((chunk, _)) = file
+                .chunks
+                .iter()
+                .find(|(_, hash)| !file.vectors.contains_key(hash))
+            {
+                return IndexLoad::Corrupt(format!(
+                    "no vector for chunk {}",
+                    chunk.key()
+                ));
+            }
+            *index.entries.write() = VectorEntries {
+                chunks: file.chunks,
+                vectors: file.vectors,
+            };
+            IndexLoad::Loaded(index)
+        })
+    }
+
+    /// Find the chunks closest to an embedding
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(ContextChunk, f32)> {
+        let entries = self.entries.read();
+        let mut scored: Vec<(usize, f32)> = entries
+            .chunks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, hash))| {
+                let embedding = entries.vectors.get(hash)?;
+                Some((i, cosine_similarity(query, embedding)))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+            .into_iter()
+            .map(|(i, score)| (entries.chunks[i].0.clone(), score))
+            .collect()
+    }
+}
+
+impl Retriever for VectorIndex {
+    fn source(&self) -> RetrievalSource {
+        RetrievalSource::Vector
+    }
+
+    fn retrieve(&self, query: &str, limit: usize) -> Result<Vec<ContextChunk>> {
+        let query = embed_all(
+            self.provider.as_ref(),
+            &[query.to_string()],
+            self.dimensions,
+        )?
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Failed to embed query"))?;
+
+        Ok(self
+            .search(&query, limit)
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect())
+    }
+}
+
+/// Cosine similarity of two vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm_a = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{EmbeddingProviderInfo, chunk_text};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Provider counting the inputs it embeds
+    #[derive(Default)]
+    struct CountingProvider {
+        embedded: AtomicUsize,
+        /// Run once on the next embedding, while the index isn't locked
+        before_embed: parking_lot::Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    }
+
+    impl EmbeddingProvider for CountingProvider {
+        fn provider_info(&self) -> EmbeddingProviderInfo {
+            EmbeddingProviderInfo {
+                id: "counting".to_string(),
+                model: "counting".to_string(),
+                dimensions: 2,
+                supports_truncation: false,
+                max_batch_size: 8,
+                requires_network: false,
+            }
+        }
+
+        fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Embedding>> {
+            if let Some(before_embed) = self.before_embed.lock().take() {
+                before_embed();
+            }
+            self.embedded.fetch_add(inputs.len(), Ordering::SeqCst);
+            Ok(inputs
+                .iter()
+                .map(|input| vec![input.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_incremental_updates_reuse_vectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.rs");
+        let b = dir.path().join("b.rs");
+        std::fs::write(&a, "").unwrap();
+        std::fs::write(&b, "").unwrap();
+        let provider = Arc::new(CountingProvider::default());
+        let index = VectorIndex::new(provider.clone(), None).unwrap();
+
+        let update = index
+            .update_path(&a, chunk_text(&a, "one\ntwo\none\n", 1, None))
+            .unwrap();
+        assert_eq!((update.embedded, update.reused), (2, 1));
+
+        // Only the changed chunk is embedded again
+        let update = index
+            .update_path(&a, chunk_text(&a, "one\nthree\none\n", 1, None))
+            .unwrap();
+        assert_eq!((update.embedded, update.reused), (1, 2));
+        assert_eq!(index.len(), 3);
+
+        // Code moved to another file keeps its vector
+        index.remove_path(&a);
+        std::fs::remove_file(&a).unwrap();
+        let update = index
+            .update_path(&b, chunk_text(&b, "three\n", 1, None))
+            .unwrap();
+        assert_eq!((update.embedded, update.reused), (0, 1));
+        assert_eq!(provider.embedded.load(Ordering::SeqCst), 3);
+
+        assert_eq!(index.collect_garbage_if_due(), None);
+        assert_eq!(index.vector_count(), 3);
+        assert_eq!(index.collect_garbage(), 2);
+        assert_eq!(index.vector_count(), 1);
+        assert_eq!(index.search(&[1.0, 0.0], 5)[0].0.content, "three");
+
+        let path = dir.path().join(IndexKind::VectorStore.file_name());
+        index.save(&path).unwrap();
+        let loaded = VectorIndex::load(&path, provider, None).loaded().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.vector_count(), 1);
+    }
+
+    #[test]
+    fn test_garbage_collects_deleted_files_on_schedule() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.rs");
+        let b = dir.path().join("b.rs");
+        std::fs::write(&a, "").unwrap();
+        std::fs::write(&b, "").unwrap();
+        let index = VectorIndex::new(Arc::new(CountingProvider::default()), None)
+            .unwrap()
+            .with_gc_interval(Duration::ZERO);
+
+        index
+            .update_path(&a, chunk_text(&a, "deleted\n", 1, None))
+            .unwrap();
+        std::fs::remove_file(&a).unwrap();
+        index
+            .update_path(&b, chunk_text(&b, "kept\n", 1, None))
+            .unwrap();
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.vector_count(), 1);
+    }
+
+    #[test]
+    fn test_garbage_collection_while_embedding() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.rs");
+        let b = dir.path().join("b.rs");
+        std::fs::write(&b, "").unwrap();
+        let provider = Arc::new(CountingProvider::default());
+        let index = Arc::new(VectorIndex::new(provider.clone(), None).unwrap());
+        index
+            .insert_chunks(chunk_text(&a, "shared\n", 1, None))
+            .unwrap();
+        index.remove_path(&a);
+
+        // The vector of `shared` is found, then collected while `new` is
+        // embedded
+        let collecting = Arc::downgrade(&index);
+        *provider.before_embed.lock() = Some(Box::new(move || {
+            assert_eq!(collecting.upgrade().unwrap().collect_garbage(), 1);
+        }));
+        let update = index
+            .update_path(&b, chunk_text(&b, "shared\nnew\n", 1, None))
+            .unwrap();
+        assert_eq!((update.embedded, update.reused), (2, 0));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.vector_count(), 2);
+
+        let path = dir.path().join(IndexKind::VectorStore.file_name());
+        index.save(&path).unwrap();
+        let loaded = VectorIndex::load(&path, provider, None).loaded().unwrap();
+        assert_eq!(loaded.len(), 2);
+    }
+}
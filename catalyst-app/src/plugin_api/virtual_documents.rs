@@ -0,0 +1,213 @@
+//! Virtual Documents
+//!
+//! Not everything the user looks at is a file of the workspace: untitled
+//! buffers live only in the editor, and `git show` opens a file as it was
+//! at some revision. Both can be attached to a request like files, by uri:
+//!
+//! - `untitled:<name>` for an untitled buffer
+//! - `git-show:<rev>:<path>` for a file at a revision, e.g.
+//!   `git-show:HEAD~1:src/main.rs`
+//!
+//! Reading a revision goes through the same sandbox checks as reading the
+//! file itself, so ignored files stay hidden in the history too.
+
+use anyhow::{Result, anyhow};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::plugin_api::WorkspaceSandbox;
+
+/// Address of a virtual document
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VirtualDocumentUri {
+    Untitled {
+        name: String,
+    },
+    /// A file at a revision, the path relative to the workspace
+    GitShow {
+        rev: String,
+        path: PathBuf,
+    },
+}
+
+impl VirtualDocumentUri {
+    pub fn parse(uri: &str) -> Option<Self> {
+        let (scheme, rest) = uri.split_once(':')?;
+        match scheme {
+            "untitled" if !rest.is_empty() => Some(Self::Untitled {
+                name: rest.to_string(),
+            }),
+            "git-show" => {
+                let (rev, path) = rest.split_once(':')?;
+                (!rev.is_empty() && !path.is_empty()).then(|| Self::GitShow {
+                    rev: rev.to_string(),
+                    path: PathBuf::from(path),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VirtualDocumentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Untitled { name } => write!(f, "untitled:{}", name),
+            Self::GitShow { rev, path } => {
+                write!(f, "git-show:{}:{}", rev, path.display())
+            }
+        }
+    }
+}
+
+/// The virtual documents of a window
+///
+/// The editor keeps the untitled buffers up to date here; `git show`
+/// documents are read on demand.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualDocuments {
+    untitled: Arc<RwLock<BTreeMap<String, String>>>,
+}
+
+impl VirtualDocuments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the text of an untitled buffer
+    pub fn set_untitled(&self, name: &str, text: impl Into<String>) {
+        self.untitled.write().insert(name.to_string(), text.into());
+    }
+
+    /// Forget an untitled buffer, e.g. when it is closed or saved
+    pub fn remove_untitled(&self, name: &str) {
+        self.untitled.write().remove(name);
+    }
+
+    /// Get the names of the untitled buffers
+    pub fn untitled_names(&self) -> Vec<String> {
+        self.untitled.read().keys().cloned().collect()
+    }
+
+    /// Read a virtual document
+    pub fn read(
+        &self,
+        uri: &VirtualDocumentUri,
+        sandbox: &WorkspaceSandbox,
+    ) -> Result<String> {
+        match uri {
+            VirtualDocumentUri::Untitled { name } => self
+                .untitled
+                .read()
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("No untitled buffer '{}' is open", name)),
+            VirtualDocumentUri::GitShow { rev, path } => {
+                git_show(rev, path, sandbox)
+            }
+        }
+    }
+}
+
+/// Read a file at a revision with `git show`
+fn git_show(rev: &str, path: &Path, sandbox: &WorkspaceSandbox) -> Result<String> {
+    if rev.starts_with('-') {
+        return Err(anyhow!("'{}' is not a revision", rev));
+    }
+    let resolved = sandbox.check_access(path)?;
+    let root = sandbox
+        .roots()
+        .into_iter()
+        .filter(|root| resolved.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .ok_or_else(|| {
+            anyhow!("'{}' is outside of the workspace", path.display())
+        })?;
+    let relative = resolved
+        .strip_prefix(root)?
+        .to_string_lossy()
+        .replace('\\', "/");
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .arg("show")
+        .arg(format!("{}:{}", rev, relative))
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_api::{CatalystIgnore, CatalystIgnoreConfig};
+
+    fn git(root: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_virtual_documents() {
+        let uri = VirtualDocumentUri::parse("git-show:HEAD~1:src/main.rs").unwrap();
+        assert_eq!(
+            uri,
+            VirtualDocumentUri::GitShow {
+                rev: "HEAD~1".to_string(),
+                path: PathBuf::from("src/main.rs"),
+            }
+        );
+        assert_eq!(uri.to_string(), "git-show:HEAD~1:src/main.rs");
+        assert!(VirtualDocumentUri::parse("untitled:").is_none());
+        assert!(VirtualDocumentUri::parse("git-show:HEAD").is_none());
+        assert!(VirtualDocumentUri::parse("file:src/main.rs").is_none());
+
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("src")).unwrap();
+        std::fs::write(root.path().join(".catalystignore"), ".env\n").unwrap();
+        std::fs::write(root.path().join("src/main.rs"), "old\n").unwrap();
+        std::fs::write(root.path().join(".env"), "TOKEN=1\n").unwrap();
+        git(root.path(), &["init", "-q"]);
+        git(root.path(), &["add", "-A"]);
+        git(root.path(), &["commit", "-q", "-m", "init"]);
+        std::fs::write(root.path().join("src/main.rs"), "new\n").unwrap();
+        let ignore =
+            CatalystIgnore::new(root.path(), &CatalystIgnoreConfig::default())
+                .unwrap();
+        let sandbox = WorkspaceSandbox::new(Arc::new(ignore));
+
+        let documents = VirtualDocuments::new();
+        assert!(documents.read(&uri, &sandbox).is_err());
+        let head = VirtualDocumentUri::parse("git-show:HEAD:src/main.rs").unwrap();
+        assert_eq!(documents.read(&head, &sandbox).unwrap(), "old\n");
+        let env = VirtualDocumentUri::parse("git-show:HEAD:.env").unwrap();
+        assert!(documents.read(&env, &sandbox).is_err());
+        let option = VirtualDocumentUri::parse("git-show:--output=x:a").unwrap();
+        assert!(documents.read(&option, &sandbox).is_err());
+
+        documents.set_untitled("Untitled-1", "draft");
+        let untitled = VirtualDocumentUri::parse("untitled:Untitled-1").unwrap();
+        assert_eq!(documents.read(&untitled, &sandbox).unwrap(), "draft");
+        assert_eq!(documents.untitled_names(), vec!["Untitled-1".to_string()]);
+        documents.remove_untitled("Untitled-1");
+        assert!(documents.read(&untitled, &sandbox).is_err());
+    }
+}
@@ -0,0 +1,576 @@
+//! Webhook Listener
+//!
+//! This module implements an optional HTTP receiver that lets external
+//! systems reach the editor: CI jobs, alerting services, or GitHub webhooks
+//! forwarded through a tunnel. It only binds to the loopback interface and
+//! every request must carry the webhook token, either as a bearer token or,
+//! for senders that can't set headers such as GitHub, as the secret of an
+//! `X-Hub-Signature-256` signature. It is off unless enabled under
+//! `[webhook]` in the [plugin settings](crate::plugin_api::PLUGIN_SETTINGS_FILE).
+//!
+//! Endpoints:
+//! - `POST /hooks/<name>` triggers the automations with a webhook trigger of
+//!   that name, passing the JSON body as the event payload
+//! - `POST /github` accepts GitHub webhook deliveries; opened pull requests
+//!   trigger pull request automations without waiting for the next poll
+//! - `POST /notify` posts `{"title", "message", "level"}` to the
+//!   notification center
+
+use anyhow::Result;
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use crossbeam_channel::Sender;
+use lsp_types::{MessageType, ShowMessageParams};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::plugin_api::{AutomationEngine, AutomationEvent, CredentialStore};
+
+/// Credential holding the token webhook senders must present
+pub const WEBHOOK_TOKEN_CREDENTIAL: &str = "webhook.token";
+/// Port the listener binds to unless configured otherwise
+pub const DEFAULT_WEBHOOK_PORT: u16 = 17_325;
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// Longest request line or header line accepted
+const MAX_LINE_BYTES: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+/// Time a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most connections served at once; more are refused until one finishes
+const MAX_CONNECTIONS: usize = 16;
+
+/// Webhook listener settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    /// The listener is off unless enabled
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_WEBHOOK_PORT
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_WEBHOOK_PORT,
+        }
+    }
+}
+
+/// Get the webhook token, generating and storing one on first use
+pub fn webhook_token(credentials: &CredentialStore) -> Result<String> {
+    if let Some(token) = credentials.get(WEBHOOK_TOKEN_CREDENTIAL) {
+        return Ok(token);
+    }
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    credentials.set(WEBHOOK_TOKEN_CREDENTIAL, &token)?;
+    Ok(token)
+}
+
+/// Severity of a posted notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationLevel {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+/// A notification posted by an external system
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    pub title: String,
+    pub message: String,
+    #[serde(default)]
+    pub level: NotificationLevel,
+}
+
+impl WebhookNotification {
+    /// Get the message shown in the notification center
+    pub fn show_message_params(&self) -> ShowMessageParams {
+        ShowMessageParams {
+            typ: match self.level {
+                NotificationLevel::Info => MessageType::INFO,
+                NotificationLevel::Warning => MessageType::WARNING,
+                NotificationLevel::Error => MessageType::ERROR,
+            },
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// A parsed HTTP request
+#[derive(Debug, Clone, Default)]
+pub struct WebhookRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl WebhookRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+}
+
+/// Response to a webhook request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookResponse {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+impl WebhookResponse {
+    fn new(status: u16, body: serde_json::Value) -> Self {
+        Self { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::new(status, serde_json::json!({ "error": message }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "Error",
+        }
+    }
+
+    fn write_to(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        let body = self.body.to_string();
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason(),
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
+
+/// Local HTTP receiver for webhooks
+pub struct WebhookListener {
+    token: String,
+    engine: Option<Arc<AutomationEngine>>,
+    notifications: Sender<WebhookNotification>,
+}
+
+impl WebhookListener {
+    /// Create a listener accepting `token`
+    ///
+    /// Without an automation engine, hook and GitHub deliveries are refused.
+    /// Notifications are sent to `notifications` for the UI to show.
+    pub fn new(
+        token: String,
+        engine: Option<Arc<AutomationEngine>>,
+        notifications: Sender<WebhookNotification>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            token,
+            engine,
+            notifications,
+        })
+    }
+
+    /// Bind to `port` on the loopback interface; port 0 picks a free port
+    pub fn bind(port: u16) -> Result<TcpListener> {
+        let listener =
+            TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        tracing::info!("Webhook listener bound to {}", listener.local_addr()?);
+        Ok(listener)
+    }
+
+    /// Serve connections, each on its own thread, until the listener fails
+    ///
+    /// At most [`MAX_CONNECTIONS`] are served at once, so a sender that
+    /// opens connections without finishing its requests can't exhaust the
+    /// threads; further connections are answered with 503 and closed.
+    pub fn serve(self: &Arc<Self>, listener: TcpListener) -> Result<()> {
+        let active = Arc::new(AtomicUsize::new(0));
+        for mut stream in listener.incoming().flatten() {
+            let Some(slot) = ConnectionSlot::acquire(&active) else {
+                tracing::warn!("Too many webhook connections, refusing one");
+                let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+                let _ = WebhookResponse::error(503, "Too many open connections")
+                    .write_to(&mut stream);
+                continue;
+            };
+            let webhook = self.clone();
+            std::thread::spawn(move || {
+                let _slot = slot;
+                if let Err(err) = webhook.serve_connection(stream) {
+                    tracing::warn!("Webhook request failed: {err:#}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn serve_connection(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let response = match read_request(&mut BufReader::new(&mut stream)) {
+            Ok(request) => self.handle_request(&request),
+            Err(RequestError::TooLarge) => {
+                WebhookResponse::error(413, "Request body is too large")
+            }
+            Err(RequestError::Invalid(err)) => {
+                WebhookResponse::error(400, &err.to_string())
+            }
+        };
+        response.write_to(&mut stream)?;
+        Ok(())
+    }
+
+    /// Authenticate and dispatch a request
+    pub fn handle_request(&self, request: &WebhookRequest) -> WebhookResponse {
+        if !self.is_authorized(request) {
+            return WebhookResponse::error(401, "Missing or invalid webhook token");
+        }
+        if request.method != "POST" {
+            return WebhookResponse::error(405, "Only POST is supported");
+        }
+        let path = request.path.split('?').next().unwrap_or_default();
+        let payload = if request.body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            match serde_json::from_slice(&request.body) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    return WebhookResponse::error(
+                        400,
+                        &format!("Body is not valid JSON: {}", err),
+                    );
+                }
+            }
+        };
+
+        if path == "/notify" {
+            return match serde_json::from_value::<WebhookNotification>(payload) {
+                Ok(notification) => {
+                    let _ = self.notifications.send(notification);
+                    WebhookResponse::new(202, serde_json::json!({}))
+                }
+                Err(err) => WebhookResponse::error(400, &err.to_string()),
+            };
+        }
+
+        let event = if let Some(hook) = path.strip_prefix("/hooks/") {
+            AutomationEvent::Webhook {
+                hook: hook.to_string(),
+                payload,
+            }
+        } else if path == "/github" {
+            match github_event(request.header("x-github-event"), &payload) {
+                Some(event) => event,
+                None => {
+                    return WebhookResponse::new(
+                        200,
+                        serde_json::json!({ "ignored": true }),
+                    );
+                }
+            }
+        } else {
+            return WebhookResponse::error(404, "Unknown webhook endpoint");
+        };
+
+        let Some(engine) = &self.engine else {
+            return WebhookResponse::error(404, "Automations are not available");
+        };
+        match engine.handle_event(&event) {
+            Ok(handles) => WebhookResponse::new(
+                202,
+                serde_json::json!({ "started": handles.len() }),
+            ),
+            Err(err) => WebhookResponse::error(500, &err.to_string()),
+        }
+    }
+
+    fn is_authorized(&self, request: &WebhookRequest) -> bool {
+        if let Some(token) = request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            return constant_time_eq(token.trim().as_bytes(), self.token.as_bytes());
+        }
+        if let Some(signature) = request
+            .header("x-hub-signature-256")
+            .and_then(|value| value.strip_prefix("sha256="))
+        {
+            let expected = hmac_sha256_hex(self.token.as_bytes(), &request.body);
+            return constant_time_eq(
+                signature.trim().to_ascii_lowercase().as_bytes(),
+                expected.as_bytes(),
+            );
+        }
+        false
+    }
+}
+
+/// One of the [`MAX_CONNECTIONS`] served at once, given back when dropped
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn acquire(active: &Arc<AtomicUsize>) -> Option<Self> {
+        active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_CONNECTIONS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(active.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Get the automation event of a GitHub webhook delivery, if it triggers one
+fn github_event(
+    kind: Option<&str>,
+    payload: &serde_json::Value,
+) -> Option<AutomationEvent> {
+    if kind != Some("pull_request")
+        || payload.get("action").and_then(|action| action.as_str()) != Some("opened")
+    {
+        return None;
+    }
+    let pull_request = payload.get("pull_request")?;
+    Some(AutomationEvent::PullRequestOpened {
+        repository: payload
+            .get("repository")?
+            .get("full_name")?
+            .as_str()?
+            .to_string(),
+        number: pull_request.get("number")?.as_u64()?,
+        title: pull_request.get("title")?.as_str()?.to_string(),
+        url: pull_request
+            .get("html_url")
+            .and_then(|url| url.as_str())
+            .map(|url| url.to_string()),
+    })
+}
+
+#[derive(Debug)]
+enum RequestError {
+    TooLarge,
+    Invalid(anyhow::Error),
+}
+
+impl From<std::io::Error> for RequestError {
+    fn from(err: std::io::Error) -> Self {
+        RequestError::Invalid(err.into())
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, RequestError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_BYTES as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.len() > MAX_LINE_BYTES {
+        return Err(RequestError::TooLarge);
+    }
+    String::from_utf8(line)
+        .map(|line| line.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|err| RequestError::Invalid(err.into()))
+}
+
+/// Read an HTTP/1.1 request with a `Content-Length` body
+fn read_request(reader: &mut impl BufRead) -> Result<WebhookRequest, RequestError> {
+    let invalid =
+        |message: &str| RequestError::Invalid(anyhow::anyhow!("{}", message));
+
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line"));
+    };
+    let mut request = WebhookRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        ..Default::default()
+    };
+
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if request.headers.len() >= MAX_HEADERS {
+            return Err(RequestError::TooLarge);
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("Malformed header"))?;
+        request
+            .headers
+            .insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length: usize = match request.header("content-length") {
+        Some(length) => length
+            .parse()
+            .map_err(|_| invalid("Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(RequestError::TooLarge);
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize();
+    format!("{:x}", outer)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_authenticates_and_dispatches_requests() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let raw = "POST /notify HTTP/1.1\r\nAuthorization: Bearer secret\r\n\
+                   Content-Length: 33\r\n\r\n{\"title\":\"CI\",\"message\":\"Failed\"}";
+        let request = read_request(&mut Cursor::new(raw.as_bytes())).unwrap();
+        assert_eq!(request.path, "/notify");
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let listener = WebhookListener::new("secret".to_string(), None, sender);
+        assert_eq!(listener.handle_request(&request).status, 202);
+        assert_eq!(receiver.try_recv().unwrap().message, "Failed");
+
+        let mut forged = request.clone();
+        forged
+            .headers
+            .insert("authorization".to_string(), "Bearer wrong".to_string());
+        assert_eq!(listener.handle_request(&forged).status, 401);
+
+        let mut signed = WebhookRequest {
+            method: "POST".to_string(),
+            path: "/github".to_string(),
+            body: br#"{"action":"closed"}"#.to_vec(),
+            ..Default::default()
+        };
+        signed.headers.insert(
+            "x-hub-signature-256".to_string(),
+            format!("sha256={}", hmac_sha256_hex(b"secret", &signed.body)),
+        );
+        signed
+            .headers
+            .insert("x-github-event".to_string(), "pull_request".to_string());
+        assert_eq!(listener.handle_request(&signed).status, 200);
+
+        let payload = serde_json::json!({
+            "action": "opened",
+            "repository": { "full_name": "acme/app" },
+            "pull_request": { "number": 7, "title": "Add CI" }
+        });
+        assert!(matches!(
+            github_event(Some("pull_request"), &payload),
+            Some(AutomationEvent::PullRequestOpened { number: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_refuses_connections_over_the_limit() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let webhook = WebhookListener::new("secret".to_string(), None, sender);
+        let listener = WebhookListener::bind(0).unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || webhook.serve(listener));
+
+        // Connections that never send their request hold their slot
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        let mut refused = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        refused.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+
+        // Slots are given back once their connections close
+        drop(idle);
+        let body = r#"{"title":"CI","message":"Passed"}"#;
+        let request = format!(
+            "POST /notify HTTP/1.1\r\nAuthorization: Bearer secret\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let _ = stream.write_all(request.as_bytes());
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            if response.starts_with("HTTP/1.1 202") {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "{response}");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(receiver.recv().unwrap().message, "Passed");
+    }
+}
@@ -0,0 +1,775 @@
+//! Workspace Analyzer
+//!
+//! This module detects monorepo structures (cargo workspaces, pnpm/npm
+//! workspaces, nx and bazel) and models the sub-projects they contain, so the
+//! AI assistant and the context indexer can operate on a single sub-project
+//! instead of the whole repository.
+
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::plugin_api::ProjectContext;
+
+/// Directories that are never descended into while looking for sub-projects
+const SKIPPED_DIRECTORIES: &[&str] = &["node_modules", "target", "dist", "build"];
+
+/// Detects one kind of monorepo, returning `None` if it does not apply
+type Detector = fn(&WorkspaceAnalyzer, &Path) -> Result<Option<WorkspaceLayout>>;
+
+/// Kind of monorepo detected at the workspace root
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MonorepoKind {
+    /// A plain repository with a single project
+    Single,
+    CargoWorkspace,
+    PnpmWorkspace,
+    NpmWorkspaces,
+    Nx,
+    Bazel,
+}
+
+/// Build system a sub-project belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubProjectKind {
+    Cargo,
+    Node,
+    Bazel,
+    Unknown,
+}
+
+/// A build or test task that can be run for a sub-project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectTask {
+    pub label: String,
+    pub command: Vec<String>,
+    pub working_directory: PathBuf,
+}
+
+/// The portion of the workspace the agent and indexer may look at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextScope {
+    pub roots: Vec<PathBuf>,
+    pub max_tokens: usize,
+    pub latency_budget: Duration,
+}
+
+impl ContextScope {
+    /// Check if a path falls inside this scope
+    pub fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+/// A project inside a (possibly mono-) repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubProject {
+    pub name: String,
+    pub root: PathBuf,
+    pub kind: SubProjectKind,
+    pub build_task: Option<ProjectTask>,
+    pub test_task: Option<ProjectTask>,
+    pub scope: ContextScope,
+}
+
+/// Result of analyzing a workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceLayout {
+    pub root: PathBuf,
+    pub kind: MonorepoKind,
+    pub sub_projects: Vec<SubProject>,
+}
+
+impl WorkspaceLayout {
+    /// Check if the workspace contains more than one project
+    pub fn is_monorepo(&self) -> bool {
+        self.kind != MonorepoKind::Single
+    }
+
+    /// Get a sub-project by name
+    pub fn get_sub_project(&self, name: &str) -> Option<&SubProject> {
+        self.sub_projects
+            .iter()
+            .find(|project| project.name == name)
+    }
+
+    /// Get the innermost sub-project containing the given path
+    pub fn sub_project_for_path(&self, path: &Path) -> Option<&SubProject> {
+        let path = if path.is_relative() {
+            self.root.join(path)
+        } else {
+            path.to_path_buf()
+        };
+
+        self.sub_projects
+            .iter()
+            .filter(|project| path.starts_with(&project.root))
+            .max_by_key(|project| project.root.components().count())
+    }
+
+    /// Build the project context sent to the AI assistant for a path, scoped
+    /// to the sub-project that contains it
+    pub fn project_context(&self, path: &Path) -> ProjectContext {
+        let sub_project = self.sub_project_for_path(path);
+        let root = sub_project
+            .map(|project| project.root.clone())
+            .unwrap_or_else(|| self.root.clone());
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        ProjectContext {
+            root_path: root.to_string_lossy().to_string(),
+            name,
+            language: sub_project.and_then(|project| match project.kind {
+                SubProjectKind::Cargo => Some("rust".to_string()),
+                SubProjectKind::Node => Some("javascript".to_string()),
+                SubProjectKind::Bazel | SubProjectKind::Unknown => None,
+            }),
+            dependencies: Vec::new(),
+            sub_project: sub_project.map(|project| project.name.clone()),
+        }
+    }
+}
+
+/// Configuration for the workspace analyzer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceAnalyzerConfig {
+    /// Maximum directory depth searched for nx/bazel packages
+    pub max_search_depth: usize,
+    /// Token budget given to each sub-project scope
+    pub max_tokens_per_scope: usize,
+    /// Latency budget for context queries within a scope
+    pub latency_budget_ms: u64,
+}
+
+impl Default for WorkspaceAnalyzerConfig {
+    fn default() -> Self {
+        Self {
+            max_search_depth: 4,
+            max_tokens_per_scope: 32_000,
+            latency_budget_ms: 500,
+        }
+    }
+}
+
+/// Detects monorepo structures and their sub-projects
+pub struct WorkspaceAnalyzer {
+    config: WorkspaceAnalyzerConfig,
+}
+
+impl WorkspaceAnalyzer {
+    /// Create a new workspace analyzer
+    pub fn new(config: WorkspaceAnalyzerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Analyze the workspace rooted at `root`
+    pub fn analyze(&self, root: &Path) -> Result<WorkspaceLayout> {
+        if !root.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Workspace root '{}' is not a directory",
+                root.display()
+            ));
+        }
+
+        let detectors: [Detector; 5] = [
+            Self::detect_cargo_workspace,
+            Self::detect_pnpm_workspace,
+            Self::detect_nx_workspace,
+            Self::detect_npm_workspaces,
+            Self::detect_bazel_workspace,
+        ];
+        for detect in detectors {
+            if let Some(layout) = detect(self, root)? {
+                tracing::info!(
+                    "Detected {:?} at '{}' with {} sub-projects",
+                    layout.kind,
+                    root.display(),
+                    layout.sub_projects.len()
+                );
+                return Ok(layout);
+            }
+        }
+
+        let kind = if root.join("Cargo.toml").is_file() {
+            SubProjectKind::Cargo
+        } else if root.join("package.json").is_file() {
+            SubProjectKind::Node
+        } else {
+            SubProjectKind::Unknown
+        };
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (build_task, test_task) = match kind {
+            SubProjectKind::Cargo => (
+                Some(self.task("build", &["cargo", "build"], root)),
+                Some(self.task("test", &["cargo", "test"], root)),
+            ),
+            SubProjectKind::Node => (
+                Some(self.task("build", &["npm", "run", "build"], root)),
+                Some(self.task("test", &["npm", "test"], root)),
+            ),
+            SubProjectKind::Bazel | SubProjectKind::Unknown => (None, None),
+        };
+
+        Ok(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::Single,
+            sub_projects: vec![SubProject {
+                name,
+                root: root.to_path_buf(),
+                kind,
+                build_task,
+                test_task,
+                scope: self.scope(root),
+            }],
+        })
+    }
+
+    fn detect_cargo_workspace(
+        &self,
+        root: &Path,
+    ) -> Result<Option<WorkspaceLayout>> {
+        let manifest = root.join("Cargo.toml");
+        if !manifest.is_file() {
+            return Ok(None);
+        }
+
+        let manifest: toml::Value =
+            toml::from_str(&std::fs::read_to_string(&manifest)?)?;
+        let Some(workspace) = manifest.get("workspace") else {
+            return Ok(None);
+        };
+        let members = string_array(workspace.get("members"));
+        let exclude = string_array(workspace.get("exclude"));
+
+        let mut dirs = self.expand_globs(root, &members, &exclude)?;
+        // A root manifest with a package of its own is a member too
+        if manifest.get("package").is_some() && !dirs.iter().any(|dir| dir == root) {
+            dirs.insert(0, root.to_path_buf());
+        }
+
+        let mut sub_projects = Vec::new();
+        for dir in dirs {
+            let member_manifest = dir.join("Cargo.toml");
+            if !member_manifest.is_file() {
+                continue;
+            }
+            let member: toml::Value =
+                toml::from_str(&std::fs::read_to_string(&member_manifest)?)?;
+            let Some(name) = member
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str())
+            else {
+                continue;
+            };
+
+            sub_projects.push(SubProject {
+                name: name.to_string(),
+                build_task: Some(self.task(
+                    "build",
+                    &["cargo", "build", "-p", name],
+                    root,
+                )),
+                test_task: Some(self.task(
+                    "test",
+                    &["cargo", "test", "-p", name],
+                    root,
+                )),
+                scope: self.scope(&dir),
+                root: dir,
+                kind: SubProjectKind::Cargo,
+            });
+        }
+
+        Ok(Some(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::CargoWorkspace,
+            sub_projects,
+        }))
+    }
+
+    fn detect_pnpm_workspace(&self, root: &Path) -> Result<Option<WorkspaceLayout>> {
+        let workspace_file = root.join("pnpm-workspace.yaml");
+        if !workspace_file.is_file() {
+            return Ok(None);
+        }
+
+        let (include, exclude) =
+            parse_pnpm_packages(&std::fs::read_to_string(&workspace_file)?);
+        let sub_projects =
+            self.node_sub_projects(root, &include, &exclude, |name| {
+                (
+                    command(&["pnpm", "--filter", name, "run", "build"]),
+                    command(&["pnpm", "--filter", name, "run", "test"]),
+                )
+            })?;
+
+        Ok(Some(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::PnpmWorkspace,
+            sub_projects,
+        }))
+    }
+
+    fn detect_nx_workspace(&self, root: &Path) -> Result<Option<WorkspaceLayout>> {
+        if !root.join("nx.json").is_file() {
+            return Ok(None);
+        }
+
+        let mut sub_projects = Vec::new();
+        for dir in self.find_dirs_containing(root, &["project.json"]) {
+            let project: serde_json::Value = serde_json::from_str(
+                &std::fs::read_to_string(dir.join("project.json"))?,
+            )?;
+            let name = project
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(|name| name.to_string())
+                .or_else(|| {
+                    dir.file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                })
+                .unwrap_or_default();
+
+            sub_projects.push(SubProject {
+                build_task: Some(self.task("build", &["nx", "build", &name], root)),
+                test_task: Some(self.task("test", &["nx", "test", &name], root)),
+                name,
+                scope: self.scope(&dir),
+                root: dir,
+                kind: SubProjectKind::Node,
+            });
+        }
+
+        Ok(Some(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::Nx,
+            sub_projects,
+        }))
+    }
+
+    fn detect_npm_workspaces(&self, root: &Path) -> Result<Option<WorkspaceLayout>> {
+        let manifest = root.join("package.json");
+        if !manifest.is_file() {
+            return Ok(None);
+        }
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&manifest)?)?;
+        // Both `"workspaces": [..]` and yarn's `"workspaces": { "packages": [..] }`
+        let workspaces = match manifest.get("workspaces") {
+            Some(serde_json::Value::Object(object)) => object.get("packages"),
+            other => other,
+        };
+        let Some(serde_json::Value::Array(patterns)) = workspaces else {
+            return Ok(None);
+        };
+        let patterns: Vec<String> = patterns
+            .iter()
+            .filter_map(|pattern| pattern.as_str().map(|s| s.to_string()))
+            .collect();
+
+        let sub_projects = self.node_sub_projects(root, &patterns, &[], |name| {
+            (
+                command(&["npm", "run", "build", "--workspace", name]),
+                command(&["npm", "run", "test", "--workspace", name]),
+            )
+        })?;
+
+        Ok(Some(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::NpmWorkspaces,
+            sub_projects,
+        }))
+    }
+
+    fn detect_bazel_workspace(
+        &self,
+        root: &Path,
+    ) -> Result<Option<WorkspaceLayout>> {
+        let markers = ["WORKSPACE", "WORKSPACE.bazel", "MODULE.bazel"];
+        if !markers.iter().any(|marker| root.join(marker).is_file()) {
+            return Ok(None);
+        }
+
+        let mut sub_projects = Vec::new();
+        for dir in self.find_dirs_containing(root, &["BUILD", "BUILD.bazel"]) {
+            let Ok(relative) = dir.strip_prefix(root) else {
+                continue;
+            };
+            let package = relative.to_string_lossy().replace('\\', "/");
+            let target = format!("//{package}/...");
+
+            sub_projects.push(SubProject {
+                name: format!("//{package}"),
+                build_task: Some(self.task(
+                    "build",
+                    &["bazel", "build", &target],
+                    root,
+                )),
+                test_task: Some(self.task(
+                    "test",
+                    &["bazel", "test", &target],
+                    root,
+                )),
+                scope: self.scope(&dir),
+                root: dir,
+                kind: SubProjectKind::Bazel,
+            });
+        }
+
+        Ok(Some(WorkspaceLayout {
+            root: root.to_path_buf(),
+            kind: MonorepoKind::Bazel,
+            sub_projects,
+        }))
+    }
+
+    /// Collect node packages matched by workspace globs
+    fn node_sub_projects(
+        &self,
+        root: &Path,
+        include: &[String],
+        exclude: &[String],
+        commands: impl Fn(&str) -> (Vec<String>, Vec<String>),
+    ) -> Result<Vec<SubProject>> {
+        let mut sub_projects = Vec::new();
+        for dir in self.expand_globs(root, include, exclude)? {
+            let manifest = dir.join("package.json");
+            if !manifest.is_file() {
+                continue;
+            }
+            let manifest: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&manifest)?)?;
+            let Some(name) = manifest.get("name").and_then(|name| name.as_str())
+            else {
+                continue;
+            };
+
+            let (build, test) = commands(name);
+            sub_projects.push(SubProject {
+                name: name.to_string(),
+                build_task: Some(self.task("build", &build, root)),
+                test_task: Some(self.task("test", &test, root)),
+                scope: self.scope(&dir),
+                root: dir,
+                kind: SubProjectKind::Node,
+            });
+        }
+        Ok(sub_projects)
+    }
+
+    /// Expand workspace member globs into existing directories
+    fn expand_globs(
+        &self,
+        root: &Path,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        // `.` names the root itself, which the walk doesn't visit
+        if include.iter().any(|pattern| is_root_pattern(pattern)) {
+            dirs.push(root.to_path_buf());
+        }
+
+        let include = build_glob_set(include)?;
+        let exclude = build_glob_set(exclude)?;
+        self.walk_dirs(root, root, 0, &mut |dir, relative| {
+            if include.is_match(relative) && !exclude.is_match(relative) {
+                dirs.push(dir.to_path_buf());
+            }
+        });
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    /// Find directories below `root` containing any of the given file names
+    fn find_dirs_containing(
+        &self,
+        root: &Path,
+        file_names: &[&str],
+    ) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        self.walk_dirs(root, root, 0, &mut |dir, _| {
+            if file_names.iter().any(|name| dir.join(name).is_file()) {
+                dirs.push(dir.to_path_buf());
+            }
+        });
+        dirs.sort();
+        dirs
+    }
+
+    fn walk_dirs(
+        &self,
+        root: &Path,
+        dir: &Path,
+        depth: usize,
+        visit: &mut dyn FnMut(&Path, &Path),
+    ) {
+        if depth >= self.config.max_search_depth {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if file_name.starts_with('.')
+                || file_name.starts_with("bazel-")
+                || SKIPPED_DIRECTORIES.contains(&file_name.as_ref())
+            {
+                continue;
+            }
+
+            if let Ok(relative) = path.strip_prefix(root) {
+                visit(&path, relative);
+            }
+            self.walk_dirs(root, &path, depth + 1, visit);
+        }
+    }
+
+    fn task(
+        &self,
+        label: &str,
+        command: &[impl AsRef<str>],
+        working_directory: &Path,
+    ) -> ProjectTask {
+        ProjectTask {
+            label: label.to_string(),
+            command: command.iter().map(|arg| arg.as_ref().to_string()).collect(),
+            working_directory: working_directory.to_path_buf(),
+        }
+    }
+
+    fn scope(&self, root: &Path) -> ContextScope {
+        ContextScope {
+            roots: vec![root.to_path_buf()],
+            max_tokens: self.config.max_tokens_per_scope,
+            latency_budget: Duration::from_millis(self.config.latency_budget_ms),
+        }
+    }
+}
+
+impl Default for WorkspaceAnalyzer {
+    fn default() -> Self {
+        Self::new(WorkspaceAnalyzerConfig::default())
+    }
+}
+
+fn command(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn string_array(value: Option<&toml::Value>) -> Vec<String> {
+    value
+        .and_then(|value| value.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Check if a workspace glob names the workspace root
+fn is_root_pattern(pattern: &str) -> bool {
+    matches!(pattern.trim_end_matches('/'), "." | "")
+}
+
+/// Build a glob set matching like cargo and the node package managers do,
+/// where `*` stays within one directory and only `**` crosses into others
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.iter().filter(|pattern| !is_root_pattern(pattern)) {
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Parse the `packages` list of a pnpm-workspace.yaml file, returning the
+/// included and the excluded (`!`-prefixed) globs
+fn parse_pnpm_packages(content: &str) -> (Vec<String>, Vec<String>) {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut in_packages = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = trimmed == "packages:";
+            continue;
+        }
+        if !in_packages {
+            continue;
+        }
+        let Some(item) = trimmed.strip_prefix('-') else {
+            continue;
+        };
+        let item = item.trim().trim_matches(['\'', '"']).to_string();
+        match item.strip_prefix('!') {
+            Some(excluded) => exclude.push(excluded.to_string()),
+            None => include.push(item),
+        }
+    }
+
+    (include, exclude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_cargo_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/skip\"]\n",
+        );
+        write(root, "crates/a/Cargo.toml", "[package]\nname = \"a\"\n");
+        write(root, "crates/b/Cargo.toml", "[package]\nname = \"b\"\n");
+        write(
+            root,
+            "crates/skip/Cargo.toml",
+            "[package]\nname = \"skip\"\n",
+        );
+
+        let layout = WorkspaceAnalyzer::default().analyze(root).unwrap();
+        assert_eq!(layout.kind, MonorepoKind::CargoWorkspace);
+        let names: Vec<_> = layout
+            .sub_projects
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a", "b"]);
+
+        let project = layout
+            .sub_project_for_path(Path::new("crates/b/src/lib.rs"))
+            .unwrap();
+        assert_eq!(project.name, "b");
+        assert_eq!(
+            project.test_task.as_ref().unwrap().command,
+            vec!["cargo", "test", "-p", "b"]
+        );
+        assert!(project.scope.contains(&root.join("crates/b/src/lib.rs")));
+        assert!(!project.scope.contains(&root.join("crates/a/src/lib.rs")));
+    }
+
+    #[test]
+    fn test_member_globs_stay_within_one_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        );
+        write(root, "crates/a/Cargo.toml", "[package]\nname = \"a\"\n");
+        write(
+            root,
+            "crates/a/fixtures/nested/Cargo.toml",
+            "[package]\nname = \"nested\"\n",
+        );
+
+        let layout = WorkspaceAnalyzer::default().analyze(root).unwrap();
+        let names: Vec<_> = layout
+            .sub_projects
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a"]);
+    }
+
+    #[test]
+    fn test_root_package_is_a_member() {
+        // A root manifest with a package is a member without being listed
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[package]\nname = \"app\"\n\n[workspace]\nmembers = [\"crates/*\"]\n",
+        );
+        write(root, "crates/a/Cargo.toml", "[package]\nname = \"a\"\n");
+
+        let layout = WorkspaceAnalyzer::default().analyze(root).unwrap();
+        let names: Vec<_> = layout
+            .sub_projects
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["app", "a"]);
+        let project = layout
+            .sub_project_for_path(Path::new("src/main.rs"))
+            .unwrap();
+        assert_eq!(project.name, "app");
+        let project = layout
+            .sub_project_for_path(Path::new("crates/a/src/lib.rs"))
+            .unwrap();
+        assert_eq!(project.name, "a");
+
+        // And so is one listed as `.`, once
+        write(
+            root,
+            "Cargo.toml",
+            concat!(
+                "[package]\nname = \"app\"\n\n",
+                "[workspace]\nmembers = [\".\", \"crates/*\"]\n",
+            ),
+        );
+        let layout = WorkspaceAnalyzer::default().analyze(root).unwrap();
+        let names: Vec<_> = layout
+            .sub_projects
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["app", "a"]);
+    }
+
+    #[test]
+    fn test_pnpm_packages() {
+        let (include, exclude) = parse_pnpm_packages(
+            "packages:\n  - 'packages/*'\n  - \"apps/**\"\n  - '!**/test/**'\n",
+        );
+        assert_eq!(include, vec!["packages/*", "apps/**"]);
+        assert_eq!(exclude, vec!["**/test/**"]);
+    }
+
+    #[test]
+    fn test_single_project() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "package.json", "{\"name\": \"app\"}");
+
+        let layout = WorkspaceAnalyzer::default().analyze(dir.path()).unwrap();
+        assert!(!layout.is_monorepo());
+        assert_eq!(layout.sub_projects.len(), 1);
+        assert_eq!(layout.sub_projects[0].kind, SubProjectKind::Node);
+    }
+}
@@ -0,0 +1,703 @@
+//! Workspace Environment
+//!
+//! This module manages environment variables defined for one workspace in
+//! `.catalyst/env`. They are injected into the workspace's MCP servers,
+//! tasks and terminals, and nowhere else. The file uses `NAME=value` lines
+//! with `#` comments; values may refer to earlier variables or the process
+//! environment with `${NAME}`. Secret variables are written as
+//! `NAME=!secret` and their values are kept in the [`CredentialStore`], so
+//! the file itself can be committed.
+//!
+//! Since a cloned repository brings its own `.catalyst/env`, the file is
+//! only applied once the user trusted the workspace, see
+//! [`WorkspaceTrust`]. Variables that make programs load or run other code,
+//! such as `LD_PRELOAD`, `PATH` or `NODE_OPTIONS`, are never applied.
+
+use anyhow::Result;
+use catalyst_core::directory::Directory;
+use floem::View;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    CredentialStore, McpServerInfo, PanelCommand, PanelCommandResult,
+    SidebarPanelInfo, SidebarPanelPlugin, SidebarPosition, TEAM_CONFIG_DIR,
+    resolve_env_references_with, virtual_text_view, write_atomic,
+};
+
+/// Environment file inside the team configuration directory
+pub const WORKSPACE_ENV_FILE: &str = "env";
+
+/// File in the config directory listing the trusted workspaces
+pub const TRUSTED_WORKSPACES_FILE: &str = "trusted_workspaces.json";
+
+const SECRET_MARKER: &str = "!secret";
+
+/// Variables a workspace environment never sets: the search path of
+/// programs, and the variables shells and interpreters read code or options
+/// from
+const BLOCKED_VARS: &[&str] = &[
+    "PATH",
+    "BASH_ENV",
+    "ENV",
+    "ZDOTDIR",
+    "PROMPT_COMMAND",
+    "SHELLOPTS",
+    "BASHOPTS",
+    "NODE_OPTIONS",
+    "NODE_PATH",
+    "PYTHONPATH",
+    "PYTHONHOME",
+    "PYTHONSTARTUP",
+    "PYTHONINSPECT",
+    "PERL5OPT",
+    "PERL5LIB",
+    "PERLLIB",
+    "RUBYOPT",
+    "RUBYLIB",
+    "JAVA_TOOL_OPTIONS",
+    "JDK_JAVA_OPTIONS",
+    "_JAVA_OPTIONS",
+    "DOTNET_STARTUP_HOOKS",
+    "ELECTRON_RUN_AS_NODE",
+    "GIT_SSH_COMMAND",
+    "GIT_EXEC_PATH",
+];
+
+/// Prefixes of the dynamic loader variables, e.g. `LD_PRELOAD` and
+/// `DYLD_INSERT_LIBRARIES`
+const BLOCKED_PREFIXES: &[&str] = &["LD_", "DYLD_"];
+
+static GLOBAL_TRUST: Lazy<WorkspaceTrust> = Lazy::new(|| {
+    let path = Directory::config_directory()
+        .unwrap_or_default()
+        .join(TRUSTED_WORKSPACES_FILE);
+    WorkspaceTrust::open(&path).unwrap_or_else(|err| {
+        tracing::error!("Failed to read {}: {err:#}", path.display());
+        WorkspaceTrust {
+            path,
+            roots: RwLock::new(BTreeSet::new()),
+        }
+    })
+});
+const ROW_HEIGHT: f64 = 24.0;
+
+/// Value of a workspace variable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum WorkspaceEnvValue {
+    Plain(String),
+    /// Kept in the credential store
+    Secret,
+}
+
+/// A variable of the workspace environment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceEnvVar {
+    pub name: String,
+    pub value: WorkspaceEnvValue,
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Var(WorkspaceEnvVar),
+    /// Comment or blank line, kept as written
+    Other(String),
+}
+
+/// Environment variables of a workspace
+#[derive(Debug, Clone)]
+pub struct WorkspaceEnv {
+    workspace_root: PathBuf,
+    lines: Vec<Line>,
+}
+
+impl WorkspaceEnv {
+    /// Load the environment of a workspace; a missing file is empty
+    pub fn load(workspace_root: &Path) -> Result<Self> {
+        let path = env_path(workspace_root);
+        let text = if path.is_file() {
+            std::fs::read_to_string(&path)?
+        } else {
+            String::new()
+        };
+        let mut lines = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                lines.push(Line::Other(line.to_string()));
+                continue;
+            }
+            let assignment = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+            let Some((name, value)) = assignment.split_once('=') else {
+                return Err(anyhow::anyhow!(
+                    "{}:{}: expected NAME=value",
+                    path.display(),
+                    number + 1
+                ));
+            };
+            let name = name.trim();
+            check_name(name)?;
+            let value = unquote(value.trim());
+            lines.push(Line::Var(WorkspaceEnvVar {
+                name: name.to_string(),
+                value: if value == SECRET_MARKER {
+                    WorkspaceEnvValue::Secret
+                } else {
+                    WorkspaceEnvValue::Plain(value.to_string())
+                },
+            }));
+        }
+        Ok(Self {
+            workspace_root: workspace_root.to_path_buf(),
+            lines,
+        })
+    }
+
+    /// Get the path of the environment file
+    pub fn path(&self) -> PathBuf {
+        env_path(&self.workspace_root)
+    }
+
+    /// Get the variables in file order
+    pub fn vars(&self) -> impl Iterator<Item = &WorkspaceEnvVar> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Var(var) => Some(var),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Set a plain variable, replacing a secret of the same name
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: &str,
+        credentials: &CredentialStore,
+    ) -> Result<()> {
+        check_name(name)?;
+        check_allowed(name)?;
+        if self.is_secret(name) {
+            credentials.remove(&self.secret_key(name))?;
+        }
+        self.put(name, WorkspaceEnvValue::Plain(value.to_string()));
+        Ok(())
+    }
+
+    /// Set a secret variable, storing its value in the credential store
+    pub fn set_secret(
+        &mut self,
+        name: &str,
+        value: &str,
+        credentials: &CredentialStore,
+    ) -> Result<()> {
+        check_name(name)?;
+        check_allowed(name)?;
+        credentials.set(&self.secret_key(name), value)?;
+        self.put(name, WorkspaceEnvValue::Secret);
+        Ok(())
+    }
+
+    /// Remove a variable and its stored secret, returning whether it existed
+    pub fn remove(
+        &mut self,
+        name: &str,
+        credentials: &CredentialStore,
+    ) -> Result<bool> {
+        if self.is_secret(name) {
+            credentials.remove(&self.secret_key(name))?;
+        }
+        let len = self.lines.len();
+        self.lines
+            .retain(|line| !matches!(line, Line::Var(var) if var.name == name));
+        Ok(self.lines.len() != len)
+    }
+
+    /// Check if a secret variable has a value in the credential store
+    pub fn has_secret_value(
+        &self,
+        name: &str,
+        credentials: &CredentialStore,
+    ) -> bool {
+        credentials.contains(&self.secret_key(name))
+    }
+
+    /// Write the file, keeping comments; secret values are never written
+    pub fn save(&self) -> Result<()> {
+        let mut text = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Var(var) => {
+                    let value = match &var.value {
+                        WorkspaceEnvValue::Plain(value) => quote(value),
+                        WorkspaceEnvValue::Secret => SECRET_MARKER.to_string(),
+                    };
+                    text.push_str(&format!("{}={}\n", var.name, value));
+                }
+                Line::Other(line) => {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+        }
+        write_atomic(&self.path(), text.as_bytes())
+    }
+
+    /// Resolve the variables to their values
+    ///
+    /// Secrets without a stored value are left out with a warning, so a
+    /// fresh checkout still starts its servers and tasks. Blocked variables
+    /// are left out as well.
+    pub fn resolve(&self, credentials: &CredentialStore) -> HashMap<String, String> {
+        let mut resolved = HashMap::new();
+        for var in self.vars() {
+            if is_blocked_env_var(&var.name) {
+                tracing::warn!(
+                    "Ignoring workspace variable {}, which may run other code",
+                    var.name
+                );
+                continue;
+            }
+            let value = match &var.value {
+                WorkspaceEnvValue::Plain(value) => {
+                    resolve_env_references_with(value, |name| {
+                        resolved
+                            .get(name)
+                            .cloned()
+                            .or_else(|| std::env::var(name).ok())
+                    })
+                }
+                WorkspaceEnvValue::Secret => {
+                    match credentials.get(&self.secret_key(&var.name)) {
+                        Some(value) => value,
+                        None => {
+                            tracing::warn!(
+                                "Secret workspace variable {} has no stored value",
+                                var.name
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+            resolved.insert(var.name.clone(), value);
+        }
+        resolved
+    }
+
+    fn is_secret(&self, name: &str) -> bool {
+        self.vars()
+            .any(|var| var.name == name && var.value == WorkspaceEnvValue::Secret)
+    }
+
+    fn put(&mut self, name: &str, value: WorkspaceEnvValue) {
+        let existing = self.lines.iter_mut().find_map(|line| match line {
+            Line::Var(var) if var.name == name => Some(var),
+            _ => None,
+        });
+        match existing {
+            Some(var) => var.value = value,
+            None => self.lines.push(Line::Var(WorkspaceEnvVar {
+                name: name.to_string(),
+                value,
+            })),
+        }
+    }
+
+    fn secret_key(&self, name: &str) -> String {
+        let root = self
+            .workspace_root
+            .canonicalize()
+            .unwrap_or_else(|_| self.workspace_root.clone());
+        format!("workspace-env:{}:{}", root.display(), name)
+    }
+}
+
+/// Workspaces whose environment file the user allowed to be applied
+pub struct WorkspaceTrust {
+    path: PathBuf,
+    roots: RwLock<BTreeSet<PathBuf>>,
+}
+
+impl WorkspaceTrust {
+    /// Get the trusted workspaces listed in the config directory
+    pub fn global() -> &'static WorkspaceTrust {
+        &GLOBAL_TRUST
+    }
+
+    /// Open a list of trusted workspaces backed by the given file
+    pub fn open(path: &Path) -> Result<Self> {
+        let roots = if path.is_file() {
+            serde_json::from_slice(&std::fs::read(path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))?
+        } else {
+            BTreeSet::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            roots: RwLock::new(roots),
+        })
+    }
+
+    /// Check if the environment file of a workspace may be applied
+    pub fn is_trusted(&self, workspace_root: &Path) -> bool {
+        self.roots.read().contains(&trust_key(workspace_root))
+    }
+
+    /// Trust a workspace
+    pub fn trust(&self, workspace_root: &Path) -> Result<()> {
+        let mut roots = self.roots.write();
+        if roots.insert(trust_key(workspace_root)) {
+            write_atomic(&self.path, &serde_json::to_vec_pretty(&*roots)?)?;
+        }
+        Ok(())
+    }
+
+    /// Stop trusting a workspace
+    pub fn revoke(&self, workspace_root: &Path) -> Result<()> {
+        let mut roots = self.roots.write();
+        if roots.remove(&trust_key(workspace_root)) {
+            write_atomic(&self.path, &serde_json::to_vec_pretty(&*roots)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check if the environment of a workspace waits for the user's trust:
+/// its environment file sets variables, and the workspace isn't trusted
+pub fn workspace_env_needs_trust(workspace_root: &Path) -> bool {
+    !WorkspaceTrust::global().is_trusted(workspace_root)
+        && WorkspaceEnv::load(workspace_root)
+            .is_ok_and(|env| env.vars().next().is_some())
+}
+
+/// Check if a variable may make programs load or run other code, so that
+/// a workspace environment never sets it
+pub fn is_blocked_env_var(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    BLOCKED_VARS.contains(&name.as_str())
+        || BLOCKED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Get the resolved environment of a workspace, empty if it has none, it
+/// can't be read or the workspace isn't trusted
+pub fn workspace_env(workspace_root: &Path) -> HashMap<String, String> {
+    if !WorkspaceTrust::global().is_trusted(workspace_root) {
+        return HashMap::new();
+    }
+    match WorkspaceEnv::load(workspace_root) {
+        Ok(env) => env.resolve(CredentialStore::global()),
+        Err(err) => {
+            tracing::error!("Failed to load the workspace environment: {err:#}");
+            HashMap::new()
+        }
+    }
+}
+
+/// Add workspace variables to an environment without overriding the
+/// values it already sets
+pub fn merge_workspace_env(
+    env: &mut HashMap<String, String>,
+    workspace_env: &HashMap<String, String>,
+) {
+    for (name, value) in workspace_env {
+        env.entry(name.clone()).or_insert_with(|| value.clone());
+    }
+}
+
+/// Add workspace variables to an MCP server, whose own values win
+pub fn apply_workspace_env(
+    server: &mut McpServerInfo,
+    workspace_env: &HashMap<String, String>,
+) {
+    merge_workspace_env(&mut server.env, workspace_env);
+}
+
+/// Sidebar panel for editing the workspace environment
+///
+/// Commands: `list`, `set` and `set_secret` with `name` and `value`,
+/// `remove` with `name`, and `reload`. Changes are saved immediately.
+pub struct WorkspaceEnvPanel {
+    env: Arc<RwLock<WorkspaceEnv>>,
+}
+
+impl WorkspaceEnvPanel {
+    pub fn new(workspace_root: &Path) -> Result<Self> {
+        Ok(Self {
+            env: Arc::new(RwLock::new(WorkspaceEnv::load(workspace_root)?)),
+        })
+    }
+
+    fn list(&self) -> serde_json::Value {
+        let env = self.env.read();
+        let credentials = CredentialStore::global();
+        let vars: BTreeMap<_, _> = env
+            .vars()
+            .map(|var| {
+                let entry = match &var.value {
+                    WorkspaceEnvValue::Plain(value) => serde_json::json!({
+                        "secret": false,
+                        "value": value,
+                    }),
+                    WorkspaceEnvValue::Secret => serde_json::json!({
+                        "secret": true,
+                        "stored": env.has_secret_value(&var.name, credentials),
+                    }),
+                };
+                (var.name.clone(), entry)
+            })
+            .collect();
+        serde_json::json!(vars)
+    }
+}
+
+impl SidebarPanelPlugin for WorkspaceEnvPanel {
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn panel_info(&self) -> SidebarPanelInfo {
+        SidebarPanelInfo {
+            id: "workspace-env".to_string(),
+            name: "Environment".to_string(),
+            description: "Environment variables of this workspace".to_string(),
+            icon: None,
+            position: SidebarPosition::Right,
+            default_visible: false,
+            resizable: true,
+            minimum_width: Some(200),
+            maximum_width: None,
+        }
+    }
+
+    fn create_view(&self) -> Box<dyn View> {
+        let env = self.env.clone();
+        virtual_text_view(
+            move || {
+                env.read()
+                    .vars()
+                    .map(|var| match &var.value {
+                        WorkspaceEnvValue::Plain(value) => {
+                            format!("{}={}", var.name, value)
+                        }
+                        WorkspaceEnvValue::Secret => format!("{}=••••••", var.name),
+                    })
+                    .collect()
+            },
+            ROW_HEIGHT,
+        )
+    }
+
+    fn on_activate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_deactivate(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_visibility_changed(&mut self, _visible: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_state(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    fn set_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn handle_command(
+        &mut self,
+        command: PanelCommand,
+    ) -> Result<PanelCommandResult> {
+        let param = |key: &str| {
+            command
+                .parameters
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Missing parameter '{}'", key))
+        };
+        let credentials = CredentialStore::global();
+        let outcome = match command.command_id.as_str() {
+            "list" => Ok(()),
+            "reload" => {
+                let root = self.env.read().workspace_root.clone();
+                WorkspaceEnv::load(&root).map(|env| *self.env.write() = env)
+            }
+            "set" => param("name").and_then(|name| {
+                let value = param("value")?;
+                let mut env = self.env.write();
+                env.set(&name, &value, credentials)?;
+                env.save()
+            }),
+            "set_secret" => param("name").and_then(|name| {
+                let value = param("value")?;
+                let mut env = self.env.write();
+                env.set_secret(&name, &value, credentials)?;
+                env.save()
+            }),
+            "remove" => param("name").and_then(|name| {
+                let mut env = self.env.write();
+                env.remove(&name, credentials)?;
+                env.save()
+            }),
+            other => Err(anyhow::anyhow!("Unknown command '{}'", other)),
+        };
+        Ok(match outcome {
+            Ok(()) => PanelCommandResult {
+                success: true,
+                result: Some(self.list()),
+                error: None,
+            },
+            Err(err) => PanelCommandResult {
+                success: false,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        })
+    }
+}
+
+fn env_path(workspace_root: &Path) -> PathBuf {
+    workspace_root
+        .join(TEAM_CONFIG_DIR)
+        .join(WORKSPACE_ENV_FILE)
+}
+
+fn trust_key(workspace_root: &Path) -> PathBuf {
+    workspace_root
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_root.to_path_buf())
+}
+
+fn check_allowed(name: &str) -> Result<()> {
+    if is_blocked_env_var(name) {
+        return Err(anyhow::anyhow!(
+            "{} can't be set for a workspace, since it may run other code",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid environment variable name '{}'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) || value.contains('#') {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_stay_out_of_the_env_file() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join(TEAM_CONFIG_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(WORKSPACE_ENV_FILE),
+            "# Local services\nDB_HOST=localhost\n\
+             export DB_URL=\"postgres://${DB_HOST}/dev\"\nAPI_TOKEN=!secret\n",
+        )
+        .unwrap();
+        let credentials =
+            CredentialStore::open(&root.path().join("credentials.toml")).unwrap();
+
+        let mut env = WorkspaceEnv::load(root.path()).unwrap();
+        let resolved = env.resolve(&credentials);
+        assert_eq!(resolved["DB_URL"], "postgres://localhost/dev");
+        assert!(!resolved.contains_key("API_TOKEN"));
+
+        env.set_secret("API_TOKEN", "s3cret", &credentials).unwrap();
+        env.set_secret("DB_PASSWORD", "hunter2", &credentials)
+            .unwrap();
+        env.set("DB_HOST", "db.internal", &credentials).unwrap();
+        assert!(env.set("1BAD", "x", &credentials).is_err());
+        env.save().unwrap();
+
+        let text = std::fs::read_to_string(env.path()).unwrap();
+        assert!(text.starts_with("# Local services\nDB_HOST=db.internal\n"));
+        assert!(text.contains("DB_PASSWORD=!secret"));
+        assert!(!text.contains("s3cret") && !text.contains("hunter2"));
+
+        let env = WorkspaceEnv::load(root.path()).unwrap();
+        let resolved = env.resolve(&credentials);
+        assert_eq!(resolved["API_TOKEN"], "s3cret");
+        assert_eq!(resolved["DB_URL"], "postgres://db.internal/dev");
+
+        let mut server_env =
+            HashMap::from([("DB_HOST".to_string(), "x".to_string())]);
+        merge_workspace_env(&mut server_env, &resolved);
+        assert_eq!(server_env["DB_HOST"], "x");
+        assert_eq!(server_env["DB_PASSWORD"], "hunter2");
+    }
+
+    #[test]
+    fn test_untrusted_and_blocked_variables() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join(TEAM_CONFIG_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(WORKSPACE_ENV_FILE),
+            "LD_PRELOAD=/tmp/evil.so\nPath=/tmp/bin\nNODE_OPTIONS=--require x\n\
+             DYLD_INSERT_LIBRARIES=x\nDB_HOST=localhost\n",
+        )
+        .unwrap();
+        let credentials =
+            CredentialStore::open(&root.path().join("credentials.toml")).unwrap();
+
+        let mut env = WorkspaceEnv::load(root.path()).unwrap();
+        let resolved = env.resolve(&credentials);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved["DB_HOST"], "localhost");
+        assert!(env.set("LD_LIBRARY_PATH", "/tmp", &credentials).is_err());
+        assert!(env.set_secret("PYTHONPATH", "/tmp", &credentials).is_err());
+
+        // Nothing is applied before the workspace is trusted
+        assert!(!WorkspaceTrust::global().is_trusted(root.path()));
+        assert!(workspace_env(root.path()).is_empty());
+
+        let path = root.path().join(TRUSTED_WORKSPACES_FILE);
+        let trust = WorkspaceTrust::open(&path).unwrap();
+        trust.trust(root.path()).unwrap();
+        let trust = WorkspaceTrust::open(&path).unwrap();
+        assert!(trust.is_trusted(root.path()));
+        trust.revoke(root.path()).unwrap();
+        assert!(!WorkspaceTrust::open(&path).unwrap().is_trusted(root.path()));
+    }
+}
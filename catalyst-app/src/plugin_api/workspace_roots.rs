@@ -0,0 +1,481 @@
+//! Multi-Root Workspaces
+//!
+//! A window may hold several folders at once, often separate repositories
+//! opened side by side. [`WorkspaceRoots`] lists them in the order they
+//! were added; the first is the primary root, which relative paths resolve
+//! against unless they start with the name of another root.
+//!
+//! The indexer crawls every root with its own `.catalystignore` rules, the
+//! sandbox lets tools into any of them, diffs are read per repository, and
+//! MCP servers are told the roots through `roots/list`. A server can be
+//! disabled for single roots, so a server set up for one repository doesn't
+//! see the others. Opening or closing a project, a [`PluginHook`], adds or
+//! removes its root, and the running servers are told to list them again.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugin_api::{
+    CatalystIgnore, CatalystIgnoreConfig, EditorContext, McpRoot, ProjectContext,
+    WorkspaceCrawler, WorkspaceSandbox,
+};
+
+/// Tool arguments holding paths, checked against the roots the called
+/// server is disabled for
+pub const PATH_ARGUMENTS: &[&str] = &[
+    "path",
+    "paths",
+    "file",
+    "files",
+    "uri",
+    "directory",
+    "cwd",
+    "root",
+];
+
+/// Event of the editor plugins are told about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginHook {
+    /// A project folder was opened in the window
+    ProjectOpened { root: PathBuf },
+    /// A project folder was closed
+    ProjectClosed { root: PathBuf },
+}
+
+/// A folder of the workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceRoot {
+    pub path: PathBuf,
+    /// Name shown to the user and the assistant, the folder name unless
+    /// another root has it too
+    pub name: String,
+    /// MCP servers that don't see this root and aren't offered for its
+    /// files
+    #[serde(default)]
+    pub disabled_servers: BTreeSet<String>,
+}
+
+impl WorkspaceRoot {
+    pub fn is_server_enabled(&self, server_id: &str) -> bool {
+        !self.disabled_servers.contains(server_id)
+    }
+
+    /// Get the root as the servers see it
+    pub fn mcp_root(&self) -> McpRoot {
+        McpRoot {
+            uri: url::Url::from_directory_path(&self.path)
+                .map(|url| url.to_string())
+                .unwrap_or_else(|()| format!("file://{}", self.path.display())),
+            name: Some(self.name.clone()),
+        }
+    }
+
+    /// Get the project context of the root as a whole
+    pub fn project_context(&self) -> ProjectContext {
+        ProjectContext {
+            root_path: self.path.to_string_lossy().to_string(),
+            name: self.name.clone(),
+            language: None,
+            dependencies: Vec::new(),
+            sub_project: None,
+        }
+    }
+}
+
+/// The folders of a workspace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceRoots {
+    roots: Vec<WorkspaceRoot>,
+}
+
+impl WorkspaceRoots {
+    /// Create the roots of a workspace, skipping folders given twice
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut roots = Self::default();
+        for path in paths {
+            if let Err(err) = roots.add(&path) {
+                tracing::debug!("Skipping workspace root: {:#}", err);
+            }
+        }
+        roots
+    }
+
+    pub fn roots(&self) -> &[WorkspaceRoot] {
+        &self.roots
+    }
+
+    /// Get the root relative paths resolve against
+    pub fn primary(&self) -> Option<&WorkspaceRoot> {
+        self.roots.first()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Add a folder to the workspace
+    pub fn add(&mut self, path: &Path) -> Result<&WorkspaceRoot> {
+        if self.roots.iter().any(|root| root.path == path) {
+            return Err(anyhow!(
+                "'{}' is already a root of the workspace",
+                path.display()
+            ));
+        }
+        let folder = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        let mut name = folder.clone();
+        let mut suffix = 2;
+        while self.get_by_name(&name).is_some() {
+            name = format!("{} ({})", folder, suffix);
+            suffix += 1;
+        }
+        self.roots.push(WorkspaceRoot {
+            path: path.to_path_buf(),
+            name,
+            disabled_servers: BTreeSet::new(),
+        });
+        Ok(self.roots.last().unwrap())
+    }
+
+    /// Remove a folder from the workspace
+    pub fn remove(&mut self, path: &Path) -> Result<WorkspaceRoot> {
+        let index = self
+            .roots
+            .iter()
+            .position(|root| root.path == path)
+            .ok_or_else(|| not_a_root(path))?;
+        Ok(self.roots.remove(index))
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&WorkspaceRoot> {
+        self.roots.iter().find(|root| root.name == name)
+    }
+
+    /// Add or remove the root of a project opened or closed, returning
+    /// whether the roots changed
+    pub fn apply_hook(&mut self, hook: &PluginHook) -> bool {
+        match hook {
+            PluginHook::ProjectOpened { root } => self.add(root).is_ok(),
+            PluginHook::ProjectClosed { root } => self.remove(root).is_ok(),
+        }
+    }
+
+    /// Take the roots from an editor context: its workspace roots, or the
+    /// root of its project if it lists none, returning whether they changed
+    ///
+    /// Roots that stay keep the servers disabled for them.
+    pub fn sync_with_context(&mut self, context: &EditorContext) -> bool {
+        let projects = if context.workspace_roots.is_empty() {
+            context.project.as_slice()
+        } else {
+            context.workspace_roots.as_slice()
+        };
+        let mut synced = Self::new(
+            projects
+                .iter()
+                .map(|project| PathBuf::from(&project.root_path)),
+        );
+        for root in &mut synced.roots {
+            if let Some(known) =
+                self.roots.iter().find(|known| known.path == root.path)
+            {
+                root.disabled_servers = known.disabled_servers.clone();
+            }
+        }
+        let changed = synced != *self;
+        *self = synced;
+        changed
+    }
+
+    /// Get the innermost root containing an absolute path
+    pub fn root_for_path(&self, path: &Path) -> Option<&WorkspaceRoot> {
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
+    /// Resolve a path given by the user or the assistant to an absolute
+    /// path and the root it belongs to
+    ///
+    /// Relative paths starting with the name of a root other than the
+    /// primary one, like `web/src/main.ts`, resolve inside that root, unless
+    /// the primary root has a folder of that name.
+    pub fn resolve(&self, path: &Path) -> Option<(PathBuf, &WorkspaceRoot)> {
+        if path.is_absolute() {
+            let root = self.root_for_path(path)?;
+            return Some((path.to_path_buf(), root));
+        }
+        let mut components = path.components();
+        let primary = self.primary()?;
+        let named = components
+            .next()
+            .filter(|first| !primary.path.join(first).exists())
+            .and_then(|first| self.get_by_name(&first.as_os_str().to_string_lossy()))
+            .filter(|root| *root != primary);
+        match named {
+            Some(root) => Some((root.path.join(components.as_path()), root)),
+            None => Some((primary.path.join(path), primary)),
+        }
+    }
+
+    /// Enable or disable an MCP server for the files of a root
+    pub fn set_server_enabled(
+        &mut self,
+        root: &Path,
+        server_id: &str,
+        enabled: bool,
+    ) -> Result<()> {
+        let root = self
+            .roots
+            .iter_mut()
+            .find(|candidate| candidate.path == root)
+            .ok_or_else(|| not_a_root(root))?;
+        if enabled {
+            root.disabled_servers.remove(server_id);
+        } else {
+            root.disabled_servers.insert(server_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Check if a server may be used for a file; files outside of the
+    /// roots are not restricted
+    pub fn is_server_enabled_for_path(&self, server_id: &str, path: &Path) -> bool {
+        self.root_for_path(path)
+            .is_none_or(|root| root.is_server_enabled(server_id))
+    }
+
+    /// Find a root a tool call may not touch because the server is disabled
+    /// for it, going by the paths in the call's [`PATH_ARGUMENTS`]
+    pub fn disabled_root_in_call(
+        &self,
+        server_id: &str,
+        arguments: &Value,
+    ) -> Option<&WorkspaceRoot> {
+        let Value::Object(arguments) = arguments else {
+            return None;
+        };
+        arguments
+            .iter()
+            .filter(|(key, _)| PATH_ARGUMENTS.contains(&key.as_str()))
+            .flat_map(|(_, value)| match value {
+                Value::String(path) => vec![path.as_str()],
+                Value::Array(paths) => {
+                    paths.iter().filter_map(Value::as_str).collect()
+                }
+                _ => Vec::new(),
+            })
+            .filter_map(|path| {
+                let path = path.strip_prefix("file://").unwrap_or(path);
+                self.resolve(Path::new(path))
+            })
+            .map(|(_, root)| root)
+            .find(|root| !root.is_server_enabled(server_id))
+    }
+
+    /// Get the roots a server may see
+    pub fn mcp_roots(&self, server_id: &str) -> Vec<McpRoot> {
+        self.roots
+            .iter()
+            .filter(|root| root.is_server_enabled(server_id))
+            .map(WorkspaceRoot::mcp_root)
+            .collect()
+    }
+
+    /// Get the roots a server may see, keeping their names
+    pub fn for_server(&self, server_id: &str) -> WorkspaceRoots {
+        WorkspaceRoots {
+            roots: self
+                .roots
+                .iter()
+                .filter(|root| root.is_server_enabled(server_id))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Get the project context of every root, for
+    /// [`EditorContext::workspace_roots`](crate::plugin_api::EditorContext)
+    pub fn project_contexts(&self) -> Vec<ProjectContext> {
+        self.roots
+            .iter()
+            .map(WorkspaceRoot::project_context)
+            .collect()
+    }
+
+    /// Load the ignore rules of every root
+    pub fn ignores(
+        &self,
+        config: &CatalystIgnoreConfig,
+    ) -> Result<Vec<Arc<CatalystIgnore>>> {
+        self.roots
+            .iter()
+            .map(|root| Ok(Arc::new(CatalystIgnore::new(&root.path, config)?)))
+            .collect()
+    }
+
+    /// Create a sandbox allowing the tools into every root
+    pub fn sandbox(
+        &self,
+        config: &CatalystIgnoreConfig,
+    ) -> Result<WorkspaceSandbox> {
+        let mut ignores = self.ignores(config)?.into_iter();
+        let primary = ignores.next().ok_or_else(no_roots)?;
+        Ok(self.roots[1..]
+            .iter()
+            .zip(ignores)
+            .fold(WorkspaceSandbox::new(primary), |sandbox, (root, ignore)| {
+                sandbox.with_root(&root.name, ignore)
+            }))
+    }
+
+    /// Create a crawler walking every root with its own ignore rules
+    pub fn crawler(
+        &self,
+        config: &CatalystIgnoreConfig,
+    ) -> Result<WorkspaceCrawler> {
+        let mut ignores = self.ignores(config)?.into_iter();
+        let mut crawler =
+            WorkspaceCrawler::new(ignores.next().ok_or_else(no_roots)?);
+        for ignore in ignores {
+            crawler = crawler.with_root(ignore);
+        }
+        Ok(crawler)
+    }
+}
+
+fn not_a_root(path: &Path) -> anyhow::Error {
+    anyhow!("'{}' is not a root of the workspace", path.display())
+}
+
+fn no_roots() -> anyhow::Error {
+    anyhow!("The workspace has no roots")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_root_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = dir.path().join("api");
+        let web = dir.path().join("web");
+        let other_api = dir.path().join("vendor").join("api");
+        for root in [&api, &web, &other_api] {
+            std::fs::create_dir_all(root.join("src")).unwrap();
+            std::fs::write(root.join("src").join("main.rs"), "fn main() {}")
+                .unwrap();
+        }
+        std::fs::write(web.join(".catalystignore"), "src/\n").unwrap();
+
+        let mut roots =
+            WorkspaceRoots::new([api.clone(), web.clone(), api.clone(), other_api]);
+        assert_eq!(roots.len(), 3);
+        let names: Vec<&str> = roots
+            .roots()
+            .iter()
+            .map(|root| root.name.as_str())
+            .collect();
+        assert_eq!(names, ["api", "web", "api (2)"]);
+
+        let (path, root) = roots.resolve(Path::new("web/src/main.rs")).unwrap();
+        assert_eq!(path, web.join("src/main.rs"));
+        assert_eq!(root.name, "web");
+        let (path, _) = roots.resolve(Path::new("src/main.rs")).unwrap();
+        assert_eq!(path, api.join("src/main.rs"));
+        // A folder of the primary root wins over a root of the same name
+        std::fs::create_dir(api.join("web")).unwrap();
+        let (path, root) = roots.resolve(Path::new("web/index.html")).unwrap();
+        assert_eq!(path, api.join("web/index.html"));
+        assert_eq!(root.name, "api");
+        std::fs::remove_dir(api.join("web")).unwrap();
+
+        roots.set_server_enabled(&web, "github", false).unwrap();
+        assert!(!roots.is_server_enabled_for_path("github", &web.join("src")));
+        let call = |path: &str| serde_json::json!({ "path": path, "query": "web" });
+        assert_eq!(
+            roots
+                .disabled_root_in_call("github", &call("web/src/main.rs"))
+                .map(|root| root.path.clone()),
+            Some(web.clone())
+        );
+        assert!(
+            roots
+                .disabled_root_in_call("github", &call("src/main.rs"))
+                .is_none()
+        );
+        assert!(roots.is_server_enabled_for_path("github", &api.join("src")));
+        assert_eq!(roots.mcp_roots("github").len(), 2);
+        assert_eq!(roots.mcp_roots("search").len(), 3);
+        assert!(roots.mcp_roots("search")[1].uri.ends_with("/web/"));
+        assert!(
+            roots
+                .set_server_enabled(dir.path(), "github", false)
+                .is_err()
+        );
+
+        let config = CatalystIgnoreConfig::default();
+        let files = roots.crawler(&config).unwrap().collect_files();
+        assert!(files.contains(&api.join("src/main.rs")));
+        assert!(!files.contains(&web.join("src/main.rs")));
+        assert!(files.contains(&dir.path().join("vendor/api/src/main.rs")));
+
+        let sandbox = roots.sandbox(&config).unwrap();
+        assert_eq!(
+            sandbox
+                .check_access(Path::new("api (2)/src/main.rs"))
+                .unwrap(),
+            dir.path().join("vendor/api/src/main.rs")
+        );
+        assert!(sandbox.is_allowed(&api.join("src/main.rs")));
+        assert!(!sandbox.is_allowed(&web.join("src/main.rs")));
+        assert!(!sandbox.is_allowed(&dir.path().join("vendor/other.rs")));
+        assert!(!sandbox.is_allowed(Path::new("web/../../vendor/other.rs")));
+        assert!(WorkspaceRoots::default().sandbox(&config).is_err());
+
+        let docs = dir.path().join("docs");
+        let opened = PluginHook::ProjectOpened { root: docs.clone() };
+        assert!(roots.apply_hook(&opened));
+        assert!(!roots.apply_hook(&opened));
+        assert_eq!(roots.roots()[3].name, "docs");
+        let closed = PluginHook::ProjectClosed { root: docs.clone() };
+        assert!(roots.apply_hook(&closed));
+        assert!(!roots.apply_hook(&closed));
+
+        let context = |roots: &[&PathBuf]| EditorContext {
+            current_file: None,
+            selection: None,
+            project: None,
+            workspace_roots: roots
+                .iter()
+                .map(|root| ProjectContext {
+                    root_path: root.to_string_lossy().to_string(),
+                    name: String::new(),
+                    language: None,
+                    dependencies: Vec::new(),
+                    sub_project: None,
+                })
+                .collect(),
+            open_files: Vec::new(),
+            memories: Vec::new(),
+            sources: Vec::new(),
+        };
+        assert!(roots.sync_with_context(&context(&[&web, &docs])));
+        assert!(!roots.sync_with_context(&context(&[&web, &docs])));
+        assert_eq!(roots.primary().unwrap().path, web);
+        // The server disabled for the web root stays disabled
+        assert_eq!(roots.mcp_roots("github").len(), 1);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{path::PathBuf, rc::Rc};
+use std::rc::Rc;
 
 use catalyst_core::mode::Mode;
 use catalyst_rpc::source_control::FileDiff;
@@ -13,13 +13,14 @@
     editor::EditorData,
     keypress::{KeyPressFocus, condition::Condition},
     main_split::Editors,
+    plugin_api::SharedPath,
     window_tab::CommonData,
 };
 
 #[derive(Clone, Debug)]
 pub struct SourceControlData {
     // VCS modified files & whether they should be included in the next commit
-    pub file_diffs: RwSignal<IndexMap<PathBuf, (FileDiff, bool)>>,
+    pub file_diffs: RwSignal<IndexMap<SharedPath, (FileDiff, bool)>>,
     pub branch: RwSignal<String>,
     pub branches: RwSignal<im::Vector<String>>,
     pub tags: RwSignal<im::Vector<String>>,
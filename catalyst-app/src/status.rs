@@ -11,7 +11,7 @@
         Memo, ReadSignal, RwSignal, SignalGet, SignalUpdate, SignalWith, create_memo,
     },
     style::{AlignItems, CursorStyle, Display},
-    views::{Decorators, dyn_stack, label, stack, svg},
+    views::{Decorators, dyn_container, dyn_stack, empty, label, stack, svg},
 };
 use indexmap::IndexMap;
 use lsp_types::{DiagnosticSeverity, ProgressToken};
@@ -24,6 +24,7 @@
     listener::Listener,
     palette::kind::PaletteKind,
     panel::{kind::PanelKind, position::PanelContainerPosition},
+    plugin_api::{FocusMode, ci_status_view, focus_mode_view},
     source_control::SourceControlData,
     window_tab::{WindowTabData, WorkProgress},
 };
@@ -57,6 +58,7 @@ pub fn status(
         (errors, warnings)
     });
     let branch = source_control.branch;
+    let ci_status = window_tab_data.ci_status;
     let file_diffs = source_control.file_diffs;
     let branch = move || {
         format!(
@@ -226,6 +228,22 @@ pub fn status(
                 })
             },
             progress_view(config, progresses),
+            focus_mode_view(FocusMode::global().clone()).style(move |s| {
+                s.color(config.get().color(LapceColor::STATUS_FOREGROUND))
+                    .hover(|s| s.cursor(CursorStyle::Pointer))
+            }),
+            dyn_container(
+                move || ci_status.get(),
+                move |ci_status| match ci_status {
+                    None => Box::new(empty()) as Box<dyn View>,
+                    Some(ci_status) => ci_status_view(ci_status.service.clone()),
+                },
+            )
+            .style(move |s| {
+                s.height_pct(100.0)
+                    .items_center()
+                    .color(config.get().color(LapceColor::STATUS_FOREGROUND))
+            }),
         ))
         .style(|s| {
             s.height_pct(100.0)
@@ -34,6 +34,7 @@
     command::{CommandExecuted, CommandKind, InternalCommand},
     debug::{RunDebugMode, RunDebugProcess},
     keypress::{KeyPressFocus, condition::Condition},
+    plugin_api::{merge_workspace_env, workspace_env},
     window_tab::CommonData,
     workspace::LapceWorkspace,
 };
@@ -415,6 +416,16 @@ fn new_raw_terminal(
             profile.arguments = run_debug.args;
         }
 
+        if let Some(path) = workspace.path.as_deref() {
+            let env = workspace_env(path);
+            if !env.is_empty() {
+                merge_workspace_env(
+                    profile.environment.get_or_insert_with(HashMap::new),
+                    &env,
+                );
+            }
+        }
+
         {
             let raw = raw.clone();
             if let Err(err) =
@@ -688,6 +699,13 @@ fn toggle_visual(&self, visual_mode: VisualMode) {
         }
     }
 
+    /// Get the text selected in the terminal, if any
+    pub fn selected_text(&self) -> Option<String> {
+        let raw = self.raw.get_untracked();
+        let raw = raw.read();
+        raw.term.selection_to_string()
+    }
+
     pub fn toggle_selection(
         &self,
         term: &mut Term<EventProxy>,
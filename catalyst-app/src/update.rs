@@ -4,6 +4,8 @@
 use catalyst_core::{directory::Directory, meta};
 use serde::Deserialize;
 
+use crate::plugin_api::CatalystError;
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct ReleaseInfo {
     pub tag_name: String,
@@ -32,7 +34,11 @@ pub fn get_latest_release() -> Result<ReleaseInfo> {
 
     let resp = catalyst_proxy::get_url(url, Some("Lapce"))?;
     if !resp.status().is_success() {
-        return Err(anyhow!("get release info failed {}", resp.text()?));
+        return Err(CatalystError::from_status(
+            resp.status().as_u16(),
+            format!("get release info failed {}", resp.text()?),
+        )
+        .into());
     }
     let mut release: ReleaseInfo = serde_json::from_str(&resp.text()?)?;
 
@@ -75,7 +81,11 @@ pub fn download_release(release: &ReleaseInfo) -> Result<PathBuf> {
             let mut resp =
                 catalyst_proxy::get_url(&asset.browser_download_url, None)?;
             if !resp.status().is_success() {
-                return Err(anyhow!("download file error {}", resp.text()?));
+                return Err(CatalystError::from_status(
+                    resp.status().as_u16(),
+                    format!("download file error {}", resp.text()?),
+                )
+                .into());
             }
             let mut out = std::fs::File::create(&file_path)?;
             resp.copy_to(&mut out)?;
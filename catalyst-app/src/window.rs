@@ -234,7 +234,7 @@ pub fn run_window_command(&self, cmd: WindowCommand) {
                             active,
                             (self.scope.create_rw_signal(0), window_tab),
                         );
-                        old_window_tab.proxy.shutdown();
+                        old_window_tab.close();
                     }
                 })
             }
@@ -281,7 +281,7 @@ pub fn run_window_command(&self, cmd: WindowCommand) {
 
                     if index < window_tabs.len() {
                         let (_, old_window_tab) = window_tabs.remove(index);
-                        old_window_tab.proxy.shutdown();
+                        old_window_tab.close();
                         let db: Arc<LapceDb> = use_context().unwrap();
                         if let Err(err) = db.save_window_tab(old_window_tab) {
                             tracing::error!("{:?}", err);
@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     collections::{BTreeMap, HashSet},
     env,
     path::{Path, PathBuf},
@@ -84,6 +85,16 @@
         position::PanelContainerPosition,
     },
     plugin::PluginData,
+    plugin_api::{
+        AttachmentLimits, AttentionTracker, BuiltinTools, CatalystIgnore,
+        CatalystIgnoreConfig, ChatAttachment, ConversationPolicy, CredentialStore,
+        ErrorAction, FocusMode, HybridRetriever, JobScheduler, MonorepoKind,
+        PluginHook, PluginManager, PresentedError, ProjectChat, ProjectChatPanel,
+        Redactor, RetrievalWeights, SharedPath, SparseIndex, SparseIndexConfig,
+        TrigramIndex, WorkspaceAnalyzer, WorkspaceAnalyzerConfig, WorkspaceCiStatus,
+        WorkspaceLayout, WorkspaceMemoryStore, WorkspaceSandbox, WorkspaceTrust,
+        is_coverage_report, workspace_env_needs_trust,
+    },
     proxy::{new_proxy, ProxyData},
     rename::RenameData,
     source_control::SourceControlData,
@@ -155,6 +166,16 @@ pub struct CommonData {
     // the current focused view which will receive keyboard events
     pub keyboard_focus: RwSignal<Option<ViewId>>,
     pub window_common: Rc<WindowCommonData>,
+    /// Files and lines the user has been working on, for context ranking
+    pub attention: Arc<AttentionTracker>,
+    /// Tools Catalyst implements for the assistant in this workspace
+    pub builtin_tools: BuiltinTools,
+    /// Index of the parts of the workspace in use, expanded as files are
+    /// opened; `None` without a workspace folder
+    pub sparse_index: Option<Arc<SparseIndex>>,
+    /// Chat about the workspace, which also holds the attachments the user
+    /// shared for the next question
+    pub project_chat: Option<Arc<ProjectChat>>,
 }
 
 impl std::fmt::Debug for CommonData {
@@ -192,6 +213,11 @@ pub struct WindowTabData {
     pub update_in_progress: RwSignal<bool>,
     pub progresses: RwSignal<IndexMap<ProgressToken, WorkProgress>>,
     pub messages: RwSignal<Vec<(String, ShowMessageParams)>>,
+    /// CI status of a workspace on GitHub, shown in the status bar
+    pub ci_status: RwSignal<Option<Arc<WorkspaceCiStatus>>>,
+    /// Whether the project chat panel shows the chat of this workspace; the
+    /// first open workspace with a chat registers it
+    pub owns_project_chat_panel: RwSignal<bool>,
     pub common: Rc<CommonData>,
 }
 
@@ -362,6 +388,16 @@ pub fn new(
             text_layout.size().height
         });
 
+        let attention = Arc::new(AttentionTracker::new());
+        let builtin_tools = BuiltinTools::default();
+        let sparse_index = open_sparse_index(&workspace, &attention);
+        if let Some(sparse_index) = &sparse_index {
+            builtin_tools.register(sparse_index.clone());
+        }
+        let project_chat = sparse_index.as_ref().and_then(|sparse_index| {
+            open_project_chat(&workspace, sparse_index, &attention)
+        });
+
         let common = Rc::new(CommonData {
             workspace: workspace.clone(),
             scope: cx,
@@ -389,6 +425,10 @@ pub fn new(
             breakpoints: cx.create_rw_signal(BTreeMap::new()),
             keyboard_focus: cx.create_rw_signal(None),
             window_common: window_common.clone(),
+            attention,
+            builtin_tools,
+            sparse_index,
+            project_chat,
         });
 
         let main_split = MainSplitData::new(cx, common.clone());
@@ -518,7 +558,6 @@ pub fn new(
             HashSet::from_iter(workspace_disabled_volts),
             main_split.editors,
             common.clone(),
-            proxy.core_rpc.clone(),
         );
 
         {
@@ -573,6 +612,8 @@ pub fn new(
             update_in_progress: cx.create_rw_signal(false),
             progresses: cx.create_rw_signal(IndexMap::new()),
             messages: cx.create_rw_signal(Vec::new()),
+            ci_status: cx.create_rw_signal(None),
+            owns_project_chat_panel: cx.create_rw_signal(false),
             common,
         };
 
@@ -626,9 +667,138 @@ pub fn new(
             });
         }
 
+        window_tab_data.prompt_workspace_trust();
+        if let Some(root) = window_tab_data.project_root() {
+            window_tab_data.main_split.reload_coverage(&root);
+            emit_project_hook(PluginHook::ProjectOpened { root: root.clone() });
+            window_tab_data.start_ci_status(&root);
+        }
+        window_tab_data.register_project_chat_panel();
+
         window_tab_data
     }
 
+    /// Shut down the proxy of a workspace tab that is closed or replaced,
+    /// and tell the plugins the project is closed
+    pub fn close(&self) {
+        self.proxy.shutdown();
+        if let Some(manager) = PluginManager::global() {
+            let panels = manager.read().get_sidebar_registry().clone();
+            if let Some(ci_status) = self.ci_status.get_untracked() {
+                ci_status.stop(&panels);
+            }
+            if self.owns_project_chat_panel.get_untracked() {
+                if let Err(err) = panels.unregister_panel(ProjectChatPanel::ID) {
+                    error!("failed to remove the project chat panel: {err:#}");
+                }
+            }
+        }
+        if let Some(root) = self.project_root() {
+            emit_project_hook(PluginHook::ProjectClosed { root });
+        }
+    }
+
+    /// Show the CI status of a workspace on GitHub once its MCP servers are
+    /// registered, following the checked out branch
+    fn start_ci_status(&self, root: &Path) {
+        let Some(manager) = PluginManager::global() else {
+            return;
+        };
+        let branch = self.source_control.branch;
+        let ci_status = {
+            let manager = manager.read();
+            WorkspaceCiStatus::start(
+                manager.get_mcp_registry(),
+                manager.get_sidebar_registry(),
+                root,
+                &branch.get_untracked(),
+            )
+        };
+        let Some(ci_status) = ci_status else {
+            return;
+        };
+        let service = ci_status.service.clone();
+        self.scope.create_effect(move |_| {
+            service.set_branch(&branch.get());
+        });
+        self.ci_status.set(Some(Arc::new(ci_status)));
+    }
+
+    /// Show the project chat in the sidebar, with the controls reading the
+    /// answers aloud when speech is enabled and the diffs tools return
+    /// applied to the workspace
+    fn register_project_chat_panel(&self) {
+        let (Some(chat), Some(manager)) =
+            (self.common.project_chat.clone(), PluginManager::global())
+        else {
+            return;
+        };
+        let manager = manager.read();
+        let mut panel = ProjectChatPanel::new(chat, manager.active_ai_assistant());
+        if let Some(provider) = manager.speech_provider() {
+            panel = panel.with_speech(provider, manager.speech_settings().clone());
+        }
+        if let Some(root) = self.project_root() {
+            match CatalystIgnore::new(&root, &CatalystIgnoreConfig::default()) {
+                Ok(ignore) => {
+                    let sandbox = WorkspaceSandbox::new(Arc::new(ignore));
+                    panel = panel.with_sandbox(sandbox);
+                }
+                Err(err) => {
+                    error!("failed to load ignore rules of {root:?}: {err:#}")
+                }
+            }
+        }
+        let registered = manager
+            .get_sidebar_registry()
+            .register_panel(ProjectChatPanel::ID.to_string(), Box::new(panel))
+            .is_ok();
+        self.owns_project_chat_panel.set(registered);
+    }
+
+    /// Get the folder of a local workspace, which MCP servers get as a root
+    fn project_root(&self) -> Option<PathBuf> {
+        if !self.workspace.kind.is_local() {
+            return None;
+        }
+        self.workspace.path.clone()
+    }
+
+    /// Ask before applying the `.catalyst/env` of a workspace that isn't
+    /// trusted yet, since a cloned repository can set variables that run
+    /// code in every terminal and MCP server
+    fn prompt_workspace_trust(&self) {
+        if self.workspace.kind.is_remote() {
+            return;
+        }
+        let Some(root) = self.workspace.path.clone() else {
+            return;
+        };
+        if !workspace_env_needs_trust(&root) {
+            return;
+        }
+        let internal_command = self.common.internal_command;
+        self.show_alert(
+            "Do you trust the authors of this workspace?".to_string(),
+            "Its .catalyst/env sets environment variables for terminals, tasks \
+             and MCP servers. They are only applied once you trust it."
+                .to_string(),
+            vec![AlertButton {
+                text: "Trust Workspace".to_string(),
+                action: Rc::new(move || {
+                    internal_command.send(InternalCommand::HideAlert);
+                    if let Err(err) = WorkspaceTrust::global().trust(&root) {
+                        internal_command.send(InternalCommand::ShowError {
+                            error: PresentedError::new(
+                                &err.context("Failed to trust the workspace"),
+                            ),
+                        });
+                    }
+                }),
+            }],
+        );
+    }
+
     pub fn reload_config(&self) {
         let db: Arc<LapceDb> = use_context().unwrap();
 
@@ -859,6 +1029,29 @@ pub fn run_workbench_command(
                     open_uri(&dir);
                 }
             }
+            OpenCredentialsFile => {
+                let Some(path) = CredentialStore::global().path() else {
+                    self.show_message(
+                        "Credentials",
+                        &ShowMessageParams {
+                            typ: lsp_types::MessageType::INFO,
+                            message: "Secrets are stored in the system keychain"
+                                .to_string(),
+                        },
+                    );
+                    return;
+                };
+                self.main_split.jump_to_location(
+                    EditorLocation {
+                        path: path.to_path_buf(),
+                        position: None,
+                        scroll_offset: None,
+                        ignore_unconfirmed: false,
+                        same_editor_tab: false,
+                    },
+                    None,
+                );
+            }
             OpenThemeColorSettings => {
                 self.main_split.open_theme_color_settings();
             }
@@ -1081,6 +1274,19 @@ pub fn run_workbench_command(
                 }
                 self.common.focus.set(Focus::Panel(PanelKind::Terminal));
             }
+            ShareTerminalSelection => {
+                self.share_terminal_selection();
+            }
+            ToggleFocusMode => {
+                if let Some(manager) = PluginManager::global() {
+                    let manager = manager.read();
+                    if FocusMode::global().is_active() {
+                        manager.stop_focus_mode();
+                    } else {
+                        manager.start_focus_mode(None);
+                    }
+                }
+            }
 
             // ==== Remote ====
             ConnectSshHost => {
@@ -1140,6 +1346,7 @@ pub fn run_workbench_command(
                 self.palette.run(PaletteKind::LineEnding);
             }
             DiffFiles => self.palette.run(PaletteKind::DiffFiles),
+            UpdateCredentials => self.palette.run(PaletteKind::Credential),
 
             // ==== Running / Debugging ====
             RunAndDebugRestart => {
@@ -1365,10 +1572,20 @@ pub fn run_workbench_command(
                     if release.version != *meta::VERSION {
                         if let Ok(process_path) = env::current_exe() {
                             update_in_progress.set(true);
+                            let internal_command = self.common.internal_command;
                             let send = create_ext_action(
                                 self.common.scope,
-                                move |_started| {
+                                move |result: anyhow::Result<()>| {
                                     update_in_progress.set(false);
+                                    if let Err(err) = result {
+                                        internal_command.send(
+                                            InternalCommand::ShowError {
+                                                error: PresentedError::new(
+                                                    &err.context("Failed to update"),
+                                                ),
+                                            },
+                                        );
+                                    }
                                 },
                             );
                             std::thread::Builder::new().name("RestartToUpdate".to_owned()).spawn(move || {
@@ -1384,11 +1601,7 @@ pub fn run_workbench_command(
                                     Ok(())
                                 };
 
-                                if let Err(err) = do_update() {
-                                    error!("Failed to update: {err}");
-                                }
-
-                                send(false);
+                                send(do_update());
                             }).unwrap();
                         }
                     }
@@ -1981,6 +2194,12 @@ pub fn run_internal_command(&self, cmd: InternalCommand) {
             } => {
                 self.show_alert(title, msg, buttons);
             }
+            InternalCommand::ShowError { error } => {
+                self.show_error(error);
+            }
+            InternalCommand::NotifyError { error } => {
+                self.notify_error(error);
+            }
             InternalCommand::HideAlert => {
                 self.alert_data.active.set(false);
             }
@@ -2099,9 +2318,10 @@ fn handle_core_notification(&self, rpc: &CoreNotification) {
                         .iter()
                         .cloned()
                         .map(|diff| {
-                            let checked =
-                                file_diffs.get(diff.path()).is_none_or(|(_, c)| *c);
-                            (diff.path().clone(), (diff, checked))
+                            let checked = file_diffs
+                                .get(diff.path().as_path())
+                                .is_none_or(|(_, c)| *c);
+                            (SharedPath::new(diff.path()), (diff, checked))
                         })
                         .collect();
                 });
@@ -2321,6 +2541,23 @@ fn handle_core_notification(&self, rpc: &CoreNotification) {
             CoreNotification::WorkspaceFileChange => {
                 self.file_explorer.reload();
             }
+            CoreNotification::WorkspaceBatchChange { paths } => {
+                self.file_explorer.reload();
+                // Test tasks write their coverage reports into the workspace
+                if let Some(root) = self.project_root() {
+                    if paths.iter().any(|path| is_coverage_report(&root, path)) {
+                        self.main_split.reload_coverage(&root);
+                    }
+                }
+                if let Some(sparse_index) = self.common.sparse_index.clone() {
+                    let paths = paths.clone();
+                    JobScheduler::global().spawn_blocking(move || {
+                        let refreshed = sparse_index.refresh(&paths);
+                        debug!("refreshed {refreshed} files of the sparse index");
+                        Ok(())
+                    });
+                }
+            }
             _ => {}
         }
     }
@@ -2853,6 +3090,55 @@ pub fn show_alert(&self, title: String, msg: String, buttons: Vec<AlertButton>)
         self.alert_data.active.set(true);
     }
 
+    /// Show an error with buttons for the actions that may fix it
+    pub fn show_error(&self, error: PresentedError) {
+        tracing::error!("{}: {}", error.title, error.detail);
+        let buttons = error
+            .actions
+            .into_iter()
+            .map(|(action, text)| {
+                let command = match action {
+                    ErrorAction::OpenSettings => {
+                        LapceWorkbenchCommand::OpenSettings
+                    }
+                    ErrorAction::Reauthenticate => {
+                        LapceWorkbenchCommand::UpdateCredentials
+                    }
+                    ErrorAction::ViewLogs => LapceWorkbenchCommand::OpenLogFile,
+                };
+                let internal_command = self.common.internal_command;
+                let lapce_command = self.common.lapce_command;
+                AlertButton {
+                    text,
+                    action: Rc::new(move || {
+                        internal_command.send(InternalCommand::HideAlert);
+                        lapce_command.send(LapceCommand {
+                            kind: CommandKind::Workbench(command.clone()),
+                            data: None,
+                        });
+                    }),
+                }
+            })
+            .collect();
+        self.show_alert(
+            error.title,
+            format!("{}\n\n{}", error.message, error.detail),
+            buttons,
+        );
+    }
+
+    /// Show an error as a notification, which doesn't interrupt the user
+    pub fn notify_error(&self, error: PresentedError) {
+        tracing::error!("{}: {}", error.title, error.detail);
+        self.show_message(
+            &error.title,
+            &ShowMessageParams {
+                typ: lsp_types::MessageType::ERROR,
+                message: format!("{}\n\n{}", error.message, error.detail),
+            },
+        );
+    }
+
     fn update_progress(&self, progress: &ProgressParams) {
         let token = progress.token.clone();
         match &progress.value {
@@ -2885,7 +3171,91 @@ fn update_progress(&self, progress: &ProgressParams) {
         }
     }
 
-    fn show_message(&self, title: &str, message: &ShowMessageParams) {
+    /// Show the terminal selection as it will be sent, with its secrets
+    /// redacted, and attach it to the next chat message once the user
+    /// confirms
+    fn share_terminal_selection(&self) {
+        let Some(project_chat) = self.common.project_chat.clone() else {
+            self.show_message(
+                "Share Terminal Selection",
+                &ShowMessageParams {
+                    typ: lsp_types::MessageType::WARNING,
+                    message: "Open a folder to share terminal output with the \
+                              agent"
+                        .to_string(),
+                },
+            );
+            return;
+        };
+        let Some(terminal) = self
+            .terminal
+            .active_tab(false)
+            .and_then(|tab| tab.active_terminal(false))
+        else {
+            return;
+        };
+        let redactor =
+            Redactor::new().with_known_secrets(CredentialStore::global().values());
+        let attachment = match ChatAttachment::from_terminal(
+            &terminal.title.get_untracked(),
+            &terminal.selected_text().unwrap_or_default(),
+            &redactor,
+            &AttachmentLimits::default(),
+        ) {
+            Ok(attachment) => attachment,
+            Err(err) => {
+                self.show_message(
+                    "Share Terminal Selection",
+                    &ShowMessageParams {
+                        typ: lsp_types::MessageType::WARNING,
+                        message: err.to_string(),
+                    },
+                );
+                return;
+            }
+        };
+        let ChatAttachment::Terminal {
+            content,
+            redactions,
+            truncated,
+            ..
+        } = &attachment
+        else {
+            return;
+        };
+        let redacted: usize =
+            redactions.iter().map(|redaction| redaction.count).sum();
+        let mut msg = match redacted {
+            0 => "No secrets were found.".to_string(),
+            1 => "1 secret was redacted.".to_string(),
+            n => format!("{} secrets were redacted.", n),
+        };
+        if *truncated {
+            msg.push_str(" Only the end of the selection is shared.");
+        }
+        msg.push_str("\n\n");
+        msg.push_str(content);
+
+        let internal_command = self.common.internal_command;
+        let share = AlertButton {
+            text: "Share with Agent".to_string(),
+            action: Rc::new(move || {
+                internal_command.send(InternalCommand::HideAlert);
+                if let Err(err) =
+                    project_chat.attach_terminal_output(attachment.clone())
+                {
+                    error!("failed to attach terminal output: {err:#}");
+                }
+            }),
+        };
+        self.show_alert(
+            "Share Terminal Output with the Agent".to_string(),
+            msg,
+            vec![share],
+        );
+    }
+
+    pub fn show_message(&self, title: &str, message: &ShowMessageParams) {
         self.messages.update(|messages| {
             messages.push((title.to_string(), message.clone()));
         });
@@ -2993,3 +3363,128 @@ fn open_uri(path: &Path) {
         }
     }
 }
+
+/// Open the sparse index of a local workspace folder and load its focus
+/// paths and recently used files in the background
+fn open_sparse_index(
+    workspace: &LapceWorkspace,
+    attention: &Arc<AttentionTracker>,
+) -> Option<Arc<SparseIndex>> {
+    if !workspace.kind.is_local() {
+        return None;
+    }
+    let root = workspace.path.as_ref()?;
+    let config = CatalystIgnoreConfig::default();
+    let ignore = match CatalystIgnore::new(root, &config) {
+        Ok(ignore) => Arc::new(ignore),
+        Err(err) => {
+            error!("failed to load ignore rules of {root:?}: {err:#}");
+            return None;
+        }
+    };
+    let index = Arc::new(SparseIndex::new(
+        ignore,
+        Arc::new(TrigramIndex::new()),
+        SparseIndexConfig::default(),
+    ));
+    let loading = index.clone();
+    let attention = attention.clone();
+    JobScheduler::global().spawn_blocking(move || {
+        let loaded = loading.load_initial(&attention);
+        debug!("loaded {} files into the sparse index", loaded.indexed);
+        Ok(())
+    });
+    Some(index)
+}
+
+/// Open the chat about a workspace, retrieving from its sparse index, and
+/// analyze the workspace layout it summarizes in the background
+fn open_project_chat(
+    workspace: &LapceWorkspace,
+    sparse_index: &Arc<SparseIndex>,
+    attention: &Arc<AttentionTracker>,
+) -> Option<Arc<ProjectChat>> {
+    let root = workspace.path.clone()?;
+    let memory = match WorkspaceMemoryStore::open_for_workspace(&root) {
+        Ok(memory) => Arc::new(memory),
+        Err(err) => {
+            error!("failed to open the memories of {root:?}: {err:#}");
+            return None;
+        }
+    };
+    let mut retriever = HybridRetriever::new(RetrievalWeights::default());
+    retriever.add_retriever(sparse_index.index().clone());
+    retriever.set_attention(Some(attention.clone()));
+    let layout = WorkspaceLayout {
+        root: root.clone(),
+        kind: MonorepoKind::Single,
+        sub_projects: Vec::new(),
+    };
+    let chat = match ProjectChat::open_for_workspace(
+        &layout,
+        memory,
+        Arc::new(retriever),
+    ) {
+        // Offered once the chat panel switches to Agent mode
+        Ok(chat) => Arc::new(match PluginManager::global() {
+            Some(manager) => chat.with_tools(
+                ConversationPolicy::default(),
+                manager.read().get_mcp_registry().clone(),
+            ),
+            None => chat,
+        }),
+        Err(err) => {
+            error!("failed to open the project chat of {root:?}: {err:#}");
+            return None;
+        }
+    };
+    let analyzed = chat.clone();
+    JobScheduler::global().spawn_blocking(move || {
+        let analyzer = WorkspaceAnalyzer::new(WorkspaceAnalyzerConfig::default());
+        analyzed.set_layout(&analyzer.analyze(&root)?);
+        Ok(())
+    });
+    Some(chat)
+}
+
+/// Hand a project hook to the plugin manager's MCP servers, which list the
+/// workspace roots again if they changed
+///
+/// A folder open in several workspace tabs stays a root until the last of
+/// them is closed.
+fn emit_project_hook(hook: PluginHook) {
+    thread_local! {
+        static OPEN_PROJECTS: RefCell<std::collections::HashMap<PathBuf, usize>> =
+            RefCell::default();
+    }
+    let first_or_last = OPEN_PROJECTS.with_borrow_mut(|open| match &hook {
+        PluginHook::ProjectOpened { root } => {
+            let count = open.entry(root.clone()).or_default();
+            *count += 1;
+            *count == 1
+        }
+        PluginHook::ProjectClosed { root } => {
+            let Some(count) = open.get_mut(root) else {
+                return false;
+            };
+            *count -= 1;
+            if *count > 0 {
+                return false;
+            }
+            open.remove(root);
+            true
+        }
+    });
+    if !first_or_last {
+        return;
+    }
+    if let Some(manager) = PluginManager::global() {
+        let manager = manager.read();
+        if let PluginHook::ProjectOpened { root } = &hook {
+            if let Err(err) = manager.register_workspace_servers(root) {
+                error!("failed to register the MCP servers of {root:?}: {err:#}");
+            }
+        }
+        manager.get_mcp_registry().handle_hook(&hook);
+    }
+}
@@ -0,0 +1,237 @@
+use lapce_xi_rope::Rope;
+use tree_sitter::Node;
+
+use crate::language::LapceLanguage;
+
+/// A public item of a file, as found in its syntax tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxItem {
+    pub name: String,
+    /// The tree-sitter node kind, e.g. `function_item`
+    pub kind: String,
+    /// Line of the item, including its attributes or decorators
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Line of the first statement of the item's body, where docstrings go
+    pub body_line: Option<usize>,
+    pub documented: bool,
+}
+
+/// Collect the public items of a syntax tree
+///
+/// Only Rust, Python, Go, JavaScript and TypeScript are understood; other
+/// languages have no items.
+pub(crate) fn public_items(
+    language: LapceLanguage,
+    root: Node,
+    text: &Rope,
+) -> Vec<SyntaxItem> {
+    let mut items = Vec::new();
+    match language {
+        LapceLanguage::Rust => rust_items(root, text, false, &mut items),
+        LapceLanguage::Python => python_items(root, text, &mut items),
+        LapceLanguage::Go => go_items(root, text, &mut items),
+        LapceLanguage::Javascript
+        | LapceLanguage::Jsx
+        | LapceLanguage::Typescript
+        | LapceLanguage::Tsx => js_items(root, text, &mut items),
+        _ => {}
+    }
+    items
+}
+
+fn node_text(node: Node, text: &Rope) -> String {
+    text.slice_to_cow(node.byte_range()).to_string()
+}
+
+fn field_text(node: Node, field: &str, text: &Rope) -> Option<String> {
+    node.child_by_field_name(field)
+        .map(|child| node_text(child, text))
+}
+
+/// Find the doc comment above a node, skipping attributes and decorators,
+/// and return the line the item starts on with them
+fn leading_doc(
+    node: Node,
+    text: &Rope,
+    skip: &[&str],
+    is_doc: impl Fn(&str) -> bool,
+) -> (usize, bool) {
+    let mut start_line = node.start_position().row;
+    let mut sibling = node.prev_named_sibling();
+    while let Some(prev) = sibling {
+        if prev.end_position().row + 1 < start_line {
+            break;
+        }
+        if skip.contains(&prev.kind()) {
+            start_line = prev.start_position().row;
+            sibling = prev.prev_named_sibling();
+            continue;
+        }
+        let documented =
+            prev.kind().contains("comment") && is_doc(&node_text(prev, text));
+        return (start_line, documented);
+    }
+    (start_line, false)
+}
+
+fn push_item(
+    items: &mut Vec<SyntaxItem>,
+    node: Node,
+    name: String,
+    start_line: usize,
+    documented: bool,
+) {
+    items.push(SyntaxItem {
+        name,
+        kind: node.kind().to_string(),
+        start_line,
+        end_line: node.end_position().row,
+        body_line: node
+            .child_by_field_name("body")
+            .and_then(|body| body.named_child(0))
+            .map(|child| child.start_position().row),
+        documented,
+    });
+}
+
+fn rust_items(node: Node, text: &Rope, in_trait: bool, items: &mut Vec<SyntaxItem>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let public = in_trait
+            || child.named_children(&mut child.walk()).any(|c| {
+                c.kind() == "visibility_modifier" && node_text(c, text) == "pub"
+            });
+        match child.kind() {
+            "function_item"
+            | "function_signature_item"
+            | "struct_item"
+            | "enum_item"
+            | "union_item"
+            | "type_item"
+            | "const_item"
+            | "static_item"
+            | "trait_item"
+            | "mod_item" => {
+                if public {
+                    if let Some(name) = field_text(child, "name", text) {
+                        let (start_line, documented) = leading_doc(
+                            child,
+                            text,
+                            &["attribute_item"],
+                            |comment| {
+                                comment.starts_with("///")
+                                    || comment.starts_with("/**")
+                            },
+                        );
+                        push_item(items, child, name, start_line, documented);
+                    }
+                }
+                if let Some(body) = child.child_by_field_name("body") {
+                    match child.kind() {
+                        "trait_item" if public => {
+                            rust_items(body, text, true, items)
+                        }
+                        "mod_item" if public => rust_items(body, text, false, items),
+                        _ => {}
+                    }
+                }
+            }
+            // Items of trait impls are documented on the trait
+            "impl_item" if child.child_by_field_name("trait").is_none() => {
+                if let Some(body) = child.child_by_field_name("body") {
+                    rust_items(body, text, false, items);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn python_items(node: Node, text: &Rope, items: &mut Vec<SyntaxItem>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let (definition, start_line) = if child.kind() == "decorated_definition" {
+            match child.child_by_field_name("definition") {
+                Some(definition) => (definition, child.start_position().row),
+                None => continue,
+            }
+        } else {
+            (child, child.start_position().row)
+        };
+        if !matches!(
+            definition.kind(),
+            "function_definition" | "class_definition"
+        ) {
+            continue;
+        }
+        let Some(name) = field_text(definition, "name", text) else {
+            continue;
+        };
+        if name.starts_with('_') {
+            continue;
+        }
+        let body = definition.child_by_field_name("body");
+        let documented = body
+            .and_then(|body| body.named_child(0))
+            .filter(|statement| statement.kind() == "expression_statement")
+            .and_then(|statement| statement.named_child(0))
+            .is_some_and(|expression| expression.kind() == "string");
+        push_item(items, definition, name, start_line, documented);
+        if definition.kind() == "class_definition" {
+            if let Some(body) = body {
+                python_items(body, text, items);
+            }
+        }
+    }
+}
+
+fn go_items(node: Node, text: &Rope, items: &mut Vec<SyntaxItem>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let name = match child.kind() {
+            "function_declaration" | "method_declaration" => {
+                field_text(child, "name", text)
+            }
+            "type_declaration" => child
+                .named_child(0)
+                .and_then(|spec| field_text(spec, "name", text)),
+            _ => None,
+        };
+        let Some(name) = name else {
+            continue;
+        };
+        if !name.starts_with(|c: char| c.is_uppercase()) {
+            continue;
+        }
+        let (start_line, documented) =
+            leading_doc(child, text, &[], |comment| comment.starts_with("//"));
+        push_item(items, child, name, start_line, documented);
+    }
+}
+
+fn js_items(node: Node, text: &Rope, items: &mut Vec<SyntaxItem>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() != "export_statement" {
+            continue;
+        }
+        let Some(declaration) = child.child_by_field_name("declaration") else {
+            continue;
+        };
+        let name = field_text(declaration, "name", text).or_else(|| {
+            // `export const name = ...`
+            declaration
+                .named_child(0)
+                .and_then(|declarator| field_text(declarator, "name", text))
+        });
+        let Some(name) = name else {
+            continue;
+        };
+        let (start_line, documented) =
+            leading_doc(child, text, &["decorator"], |comment| {
+                comment.starts_with("/**")
+            });
+        push_item(items, child, name, start_line, documented);
+    }
+}
@@ -35,6 +35,7 @@
         HighlightIterLayer, IncludedChildren, LocalScope, get_highlight_config,
         intersect_ranges,
     },
+    items::SyntaxItem,
     util::RopeProvider,
 };
 use crate::{
@@ -46,6 +47,7 @@
 };
 pub mod edit;
 pub mod highlight;
+pub mod items;
 pub mod util;
 
 const TREE_SITTER_MATCH_LIMIT: u32 = 256;
@@ -1239,6 +1241,17 @@ pub fn find_enclosing_pair(&self, offset: usize) -> Option<(usize, usize)> {
             }
         }
     }
+
+    /// Get the public items of the parsed text, or `None` without a syntax
+    /// tree
+    pub fn public_items(&self) -> Option<Vec<SyntaxItem>> {
+        let tree = self.layers.as_ref()?.try_tree()?;
+        Some(items::public_items(
+            self.language,
+            tree.root_node(),
+            &self.text,
+        ))
+    }
 }
 
 #[cfg(test)]
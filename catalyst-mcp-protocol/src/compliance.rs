@@ -0,0 +1,288 @@
+//! Compliance Suite
+//!
+//! This module checks that an MCP server answers the requests Catalyst
+//! sends the way the protocol requires: the handshake reports a protocol
+//! version Catalyst speaks, capabilities and server name, tools are listed with a name and
+//! an object input schema, calling a missing tool fails cleanly, unknown
+//! methods get the JSON-RPC "method not found" error, and every response
+//! carries the id of its request. Server authors run it from their tests
+//! with a function handling one request, which is how the projects created
+//! by `catalyst mcp new` test themselves.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fmt;
+
+use crate::{
+    METHOD_NOT_FOUND, McpRequest, McpRequestId, McpResponse,
+    SUPPORTED_PROTOCOL_VERSIONS, initialize_params, is_supported_protocol_version,
+};
+
+/// Outcome of one compliance check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpComplianceCheck {
+    pub name: String,
+    pub passed: bool,
+    /// Why the check failed
+    pub detail: Option<String>,
+}
+
+/// Outcome of the compliance suite
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpComplianceReport {
+    pub checks: Vec<McpComplianceCheck>,
+}
+
+impl McpComplianceReport {
+    pub fn is_compliant(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &McpComplianceCheck> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+
+    fn check(&mut self, name: &str, result: Result<(), String>) {
+        self.checks.push(McpComplianceCheck {
+            name: name.to_string(),
+            passed: result.is_ok(),
+            detail: result.err(),
+        });
+    }
+}
+
+impl fmt::Display for McpComplianceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.detail {
+                Some(detail) => writeln!(f, "FAIL {}: {}", check.name, detail)?,
+                None => writeln!(f, "ok   {}", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the compliance suite against a server
+///
+/// `handle` answers one request; for a server running in another process
+/// this is usually [`McpTransport::request`](crate::McpTransport::request).
+pub fn check_mcp_compliance(
+    mut handle: impl FnMut(McpRequest) -> Result<McpResponse>,
+) -> McpComplianceReport {
+    let mut report = McpComplianceReport::default();
+    let mut next_id = 0;
+    let mut send = |method: &str, params: Option<Value>| {
+        next_id += 1;
+        let id = McpRequestId::from(format!("compliance-{}", next_id));
+        let response = handle(McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: id.clone(),
+            method: method.to_string(),
+            params,
+        })
+        .map_err(|err| format!("{} failed: {:#}", method, err))?;
+        if response.jsonrpc != "2.0" {
+            return Err(format!(
+                "{} answered with jsonrpc '{}' instead of '2.0'",
+                method, response.jsonrpc
+            ));
+        }
+        if response.id != id {
+            return Err(format!(
+                "{} answered with id '{}' instead of '{}'",
+                method, response.id, id
+            ));
+        }
+        Ok(response)
+    };
+
+    let initialize = send(
+        "initialize",
+        Some(initialize_params("catalyst-compliance", "1")),
+    );
+    report.check(
+        "initialize",
+        initialize.and_then(|response| {
+            let result = success(&response, "initialize")?;
+            let Some(version) = result["protocolVersion"].as_str() else {
+                return Err("initialize result has no protocolVersion".to_string());
+            };
+            if !is_supported_protocol_version(version) {
+                return Err(format!(
+                    "initialize answered with protocol version {}; Catalyst \
+                     speaks {}",
+                    version,
+                    SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+                ));
+            }
+            if !result["capabilities"].is_object() {
+                return Err(
+                    "initialize result has no capabilities object".to_string()
+                );
+            }
+            if !result["serverInfo"]["name"].is_string() {
+                return Err("initialize result has no serverInfo.name".to_string());
+            }
+            Ok(())
+        }),
+    );
+
+    let tools = send("tools/list", None);
+    report.check(
+        "tools/list",
+        tools.and_then(|response| {
+            let result = success(&response, "tools/list")?;
+            let tools = result["tools"]
+                .as_array()
+                .ok_or("tools/list result has no tools array")?;
+            for tool in tools {
+                let name = tool["name"]
+                    .as_str()
+                    .filter(|name| !name.is_empty())
+                    .ok_or("a tool has no name")?;
+                if tool["inputSchema"]["type"] != "object" {
+                    return Err(format!(
+                        "tool '{}' has no inputSchema of type object",
+                        name
+                    ));
+                }
+            }
+            Ok(())
+        }),
+    );
+
+    let missing_tool = send(
+        "tools/call",
+        Some(json!({"name": "catalyst-compliance-missing-tool", "arguments": {}})),
+    );
+    report.check(
+        "tools/call of a missing tool",
+        missing_tool.and_then(|response| {
+            match (&response.error, &response.result) {
+                (Some(_), _) => Ok(()),
+                (None, Some(result)) if result["isError"] == true => Ok(()),
+                _ => Err("calling a missing tool succeeded".to_string()),
+            }
+        }),
+    );
+
+    let unknown = send("catalyst/compliance-unknown-method", None);
+    report.check(
+        "unknown method",
+        unknown.and_then(|response| match &response.error {
+            Some(error) if error.code == METHOD_NOT_FOUND => Ok(()),
+            Some(error) => Err(format!(
+                "unknown method failed with code {} instead of {}",
+                error.code, METHOD_NOT_FOUND
+            )),
+            None => Err("unknown method succeeded".to_string()),
+        }),
+    );
+
+    report
+}
+
+fn success<'a>(
+    response: &'a McpResponse,
+    method: &str,
+) -> Result<&'a Value, String> {
+    if let Some(error) = &response.error {
+        return Err(format!("{} failed: {}", method, error.message));
+    }
+    response
+        .result
+        .as_ref()
+        .ok_or_else(|| format!("{} has neither a result nor an error", method))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{McpError, negotiate_protocol_version};
+
+    #[test]
+    fn test_compliance_suite() {
+        let reply =
+            |request: &McpRequest, result: Option<Value>, code: Option<i32>| {
+                McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id.clone(),
+                    result,
+                    error: code.map(|code| McpError {
+                        code,
+                        message: "error".to_string(),
+                        data: None,
+                    }),
+                }
+            };
+        let server = |request: McpRequest| {
+            Ok(match request.method.as_str() {
+                "initialize" => reply(
+                    &request,
+                    Some(json!({
+                        "protocolVersion":
+                            negotiate_protocol_version(request.params.as_ref()),
+                        "capabilities": {"tools": {}},
+                        "serverInfo": {"name": "test", "version": "1"},
+                    })),
+                    None,
+                ),
+                "tools/list" => reply(
+                    &request,
+                    Some(json!({"tools": [{
+                        "name": "echo",
+                        "inputSchema": {"type": "object"},
+                    }]})),
+                    None,
+                ),
+                "tools/call" => reply(&request, None, Some(-32602)),
+                _ => reply(&request, None, Some(METHOD_NOT_FOUND)),
+            })
+        };
+        let report = check_mcp_compliance(server);
+        assert!(report.is_compliant(), "{}", report);
+        assert_eq!(report.checks.len(), 4);
+
+        // A server speaking only a version Catalyst doesn't know fails the
+        // handshake
+        let report = check_mcp_compliance(|request| {
+            let result = match request.method.as_str() {
+                "initialize" => json!({
+                    "protocolVersion": "2099-01-01",
+                    "capabilities": {},
+                    "serverInfo": {"name": "future"},
+                }),
+                _ => json!({}),
+            };
+            Ok(reply(&request, Some(result), None))
+        });
+        let initialize = report.failures().next().unwrap();
+        assert_eq!(initialize.name, "initialize");
+        assert!(
+            initialize
+                .detail
+                .as_deref()
+                .unwrap()
+                .contains("protocol version 2099-01-01")
+        );
+
+        // A server that succeeds at everything misses the error cases
+        let report = check_mcp_compliance(|request| {
+            Ok(reply(&request, Some(json!({})), None))
+        });
+        assert_eq!(
+            report
+                .failures()
+                .map(|check| check.name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "initialize",
+                "tools/list",
+                "tools/call of a missing tool",
+                "unknown method"
+            ]
+        );
+    }
+}
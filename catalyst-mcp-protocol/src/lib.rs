@@ -0,0 +1,888 @@
+//! Model Context Protocol core shared by Catalyst and MCP server authors
+//!
+//! This crate holds the protocol messages Catalyst exchanges with MCP
+//! servers, the transports carrying them, and the compliance suite servers
+//! are checked against. The IDE and servers built with `catalyst mcp new`
+//! depend on it, so both sides read and write the same messages.
+
+pub mod compliance;
+pub mod transport;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+pub use compliance::*;
+pub use transport::*;
+
+/// JSON-RPC error code of a message that is not valid JSON
+pub const PARSE_ERROR: i32 = -32700;
+/// JSON-RPC error code of a message that is not a valid request
+pub const INVALID_REQUEST: i32 = -32600;
+/// JSON-RPC error code of an unknown method
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// JSON-RPC error code of invalid method parameters
+pub const INVALID_PARAMS: i32 = -32602;
+/// JSON-RPC error code of a request that failed while being handled
+pub const INTERNAL_ERROR: i32 = -32603;
+/// Newest protocol version Catalyst speaks, asked for in `initialize`
+pub const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+/// Protocol versions Catalyst speaks, newest first
+///
+/// A server answering `initialize` with an older version of this list is
+/// talked to in that version; any other version is refused. A version is
+/// only listed once the client implements it, so `2025-03-26` is not.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 1] = [MCP_PROTOCOL_VERSION];
+
+/// Notification of a server whose list of resources changed
+pub const RESOURCES_LIST_CHANGED: &str = "notifications/resources/list_changed";
+/// Notification of a server whose subscribed resource changed
+pub const RESOURCE_UPDATED: &str = "notifications/resources/updated";
+/// Notification of a server whose list of tools changed
+pub const TOOLS_LIST_CHANGED: &str = "notifications/tools/list_changed";
+/// Notification carrying a log message of a server
+pub const LOGGING_MESSAGE: &str = "notifications/message";
+/// Notification asking the server to abort a request, with its
+/// `requestId` and an optional `reason`
+pub const CANCELLED: &str = "notifications/cancelled";
+/// Request of a server asking the client for a model completion
+pub const SAMPLING_CREATE_MESSAGE: &str = "sampling/createMessage";
+/// Request of a server asking for the folders it may work in
+pub const ROOTS_LIST: &str = "roots/list";
+/// Notification telling servers the folders they may work in changed
+pub const ROOTS_LIST_CHANGED: &str = "notifications/roots/list_changed";
+/// Notification of a client that accepted the server's `initialize` result
+pub const INITIALIZED: &str = "notifications/initialized";
+
+/// Id of a request, a number or a string as its sender chose
+///
+/// A response carries the id of its request exactly as it was sent, so a
+/// client numbering its requests gets numbers back.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum McpRequestId {
+    Number(i64),
+    String(String),
+}
+
+impl fmt::Display for McpRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(id) => write!(f, "{}", id),
+            Self::String(id) => f.write_str(id),
+        }
+    }
+}
+
+impl From<String> for McpRequestId {
+    fn from(id: String) -> Self {
+        Self::String(id)
+    }
+}
+
+impl From<&str> for McpRequestId {
+    fn from(id: &str) -> Self {
+        Self::String(id.to_string())
+    }
+}
+
+impl From<i64> for McpRequestId {
+    fn from(id: i64) -> Self {
+        Self::Number(id)
+    }
+}
+
+impl PartialEq<&str> for McpRequestId {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, Self::String(id) if id == other)
+    }
+}
+
+/// Request to send to an MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpRequest {
+    pub jsonrpc: String,
+    pub id: McpRequestId,
+    pub method: String,
+    pub params: Option<serde_json::Value>,
+}
+
+/// Response from an MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpResponse {
+    pub jsonrpc: String,
+    pub id: McpRequestId,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<McpError>,
+}
+
+/// Message sent without expecting a response, such as a server telling
+/// the client its tools changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+impl McpNotification {
+    pub fn new(method: &str, params: Option<serde_json::Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    /// Get the notification cancelling the request with the given id
+    ///
+    /// The server doesn't answer a cancelled request, so the client stops
+    /// waiting for its response once this is sent.
+    pub fn cancelled(id: &McpRequestId, reason: Option<&str>) -> Self {
+        let mut params = serde_json::json!({ "requestId": id });
+        if let Some(reason) = reason {
+            params["reason"] = reason.into();
+        }
+        Self::new(CANCELLED, Some(params))
+    }
+}
+
+/// Error from an MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+}
+
+/// Failure of a request to an MCP server, by class
+///
+/// Transports and server methods return `anyhow::Result`; MCP failures
+/// carry one of these in their error chain, found with
+/// [`McpClientError::of`], so callers can tell a dropped connection worth
+/// retrying from a request the server refused.
+#[derive(Debug, thiserror::Error)]
+pub enum McpClientError {
+    /// The connection to the server failed or was closed
+    #[error("MCP transport failed: {0}")]
+    Transport(String),
+    /// The server didn't answer in time
+    #[error("MCP server did not answer within {0:?}")]
+    Timeout(Duration),
+    /// The server answered with something that isn't valid MCP
+    #[error("MCP protocol violation: {0}")]
+    ProtocolViolation(String),
+    /// The server speaks a protocol version Catalyst doesn't
+    #[error(
+        "MCP server speaks protocol version {0}, but Catalyst supports only {}",
+        SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+    )]
+    UnsupportedProtocolVersion(String),
+    /// The server answered the request with an error
+    #[error("MCP server error {}: {}", .0.code, .0.message)]
+    ServerError(McpError),
+    /// A message couldn't be encoded or decoded
+    #[error("Invalid MCP message: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl McpClientError {
+    /// Find the MCP failure in the chain of an error
+    pub fn of(err: &anyhow::Error) -> Option<&Self> {
+        err.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+
+    /// Check if sending the request again may succeed
+    ///
+    /// Transport failures and timeouts are transient, and so is an internal
+    /// error of the server. Refused requests and malformed messages fail
+    /// the same way again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(_) | Self::Timeout(_) => true,
+            Self::ServerError(error) => error.code == INTERNAL_ERROR,
+            Self::ProtocolViolation(_)
+            | Self::UnsupportedProtocolVersion(_)
+            | Self::Serialization(_) => false,
+        }
+    }
+
+    /// Check if the failure is the server's fault rather than a request
+    /// it refused, and so should mark the server as degraded
+    pub fn is_server_failure(&self) -> bool {
+        !matches!(self, Self::ServerError(_))
+    }
+
+    /// Get the result of a response to `method`, or the error it carries
+    pub fn result_of(
+        method: &str,
+        response: McpResponse,
+    ) -> std::result::Result<serde_json::Value, Self> {
+        if let Some(error) = response.error {
+            return Err(Self::ServerError(error));
+        }
+        response.result.ok_or_else(|| {
+            Self::ProtocolViolation(format!("{} returned no result", method))
+        })
+    }
+}
+
+/// Tool available from an MCP server
+///
+/// Serde reads and writes the plugin API form; [`McpTool::from_wire`] and
+/// [`McpTool::to_wire`] convert from and to the form of MCP's `tools/list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpTool {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+    /// Whether the tool changes anything, from the server's `readOnlyHint`
+    /// annotation; tools that don't say are treated as mutating
+    #[serde(default)]
+    pub effect: ToolEffect,
+}
+
+/// Tool as MCP's `tools/list` describes it
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WireTool {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    input_schema: serde_json::Value,
+    #[serde(default)]
+    annotations: WireToolAnnotations,
+}
+
+/// Hints a server gives about a tool; only those Catalyst acts on
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WireToolAnnotations {
+    #[serde(default)]
+    read_only_hint: bool,
+}
+
+impl McpTool {
+    /// Read a tool as listed by a server, e.g. `{"name": "...",
+    /// "inputSchema": {...}, "annotations": {"readOnlyHint": true}}`
+    pub fn from_wire(tool: serde_json::Value) -> Result<Self, McpClientError> {
+        let tool: WireTool = serde_json::from_value(tool)?;
+        Ok(Self {
+            name: tool.name,
+            description: tool.description,
+            input_schema: tool.input_schema,
+            effect: if tool.annotations.read_only_hint {
+                ToolEffect::ReadOnly
+            } else {
+                ToolEffect::Mutating
+            },
+        })
+    }
+
+    /// Write the tool as `tools/list` lists it
+    pub fn to_wire(&self) -> serde_json::Value {
+        serde_json::to_value(WireTool {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            input_schema: self.input_schema.clone(),
+            annotations: WireToolAnnotations {
+                read_only_hint: self.effect == ToolEffect::ReadOnly,
+            },
+        })
+        .unwrap_or_default()
+    }
+}
+
+/// Whether calling a tool can change anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolEffect {
+    /// Only reads, so it is safe to run while planning
+    ReadOnly,
+    /// May change files, repositories or anything outside Catalyst
+    #[default]
+    Mutating,
+}
+
+/// Resource available from an MCP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+/// Prompt template offered by an MCP server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+/// Argument a prompt template is filled in with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Message of a prompt filled in by a server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptMessage {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: McpContent,
+}
+
+/// Completion a server asks the client for with
+/// [`SAMPLING_CREATE_MESSAGE`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpSamplingRequest {
+    pub messages: Vec<McpSamplingMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    pub max_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    /// Hints and priorities for picking a model, which the client may ignore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<serde_json::Value>,
+}
+
+/// Message of a conversation in a sampling request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpSamplingMessage {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: McpSamplingContent,
+}
+
+/// Content of a sampling message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum McpSamplingContent {
+    Text {
+        text: String,
+    },
+    /// Base64 encoded image
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+}
+
+/// Completion returned to the server for a sampling request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpSamplingResult {
+    pub role: String,
+    pub content: McpSamplingContent,
+    /// Model that wrote the completion
+    pub model: String,
+    /// `endTurn`, `stopSequence`, `maxTokens` or another reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+/// Result of calling a tool
+///
+/// Serde reads and writes the plugin API form; [`McpToolResult::from_wire`]
+/// and [`McpToolResult::to_wire`] convert from and to the form of MCP's
+/// `tools/call`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpToolResult {
+    pub content: Vec<McpContent>,
+    pub is_error: bool,
+}
+
+impl McpToolResult {
+    /// Read a tool result as sent by a server, e.g.
+    /// `{"content": [{"type": "text", "text": "..."}], "isError": false}`
+    pub fn from_wire(result: &serde_json::Value) -> Result<Self, McpClientError> {
+        let content = result
+            .get("content")
+            .and_then(|content| content.as_array())
+            .ok_or_else(|| {
+                McpClientError::ProtocolViolation(
+                    "Tool result has no content".into(),
+                )
+            })?;
+        Ok(Self {
+            content: content.iter().cloned().map(McpContent::from_wire).collect(),
+            is_error: result
+                .get("isError")
+                .and_then(|is_error| is_error.as_bool())
+                .unwrap_or(false),
+        })
+    }
+
+    /// Write the result as `tools/call` answers it; display hints are
+    /// Catalyst's own and left out
+    pub fn to_wire(&self) -> serde_json::Value {
+        let content: Vec<_> = self.content.iter().map(McpContent::to_wire).collect();
+        serde_json::json!({ "content": content, "isError": self.is_error })
+    }
+}
+
+/// Content returned by MCP operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpContent {
+    pub content_type: String,
+    pub data: serde_json::Value,
+    /// How the content should be displayed; guessed from the content when
+    /// the server doesn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<McpContentHint>,
+}
+
+/// Viewer a tool result should be displayed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpContentHint {
+    Text,
+    /// Collapsible JSON tree
+    Json,
+    /// Unified diff, shown side by side with an apply button
+    Diff,
+    /// Long output, shown in a searchable log view
+    Log,
+    /// Image, such as a browser screenshot, shown inline
+    Image,
+    /// Audio, such as a recording, with a button playing it
+    Audio,
+}
+
+/// Content of a tool result or prompt message as MCP types it, e.g.
+/// `{"type": "image", "data": "...", "mimeType": "image/png"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum McpTypedContent {
+    Text {
+        text: String,
+    },
+    /// Base64 encoded image
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Base64 encoded audio
+    Audio {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    /// Resource embedded in the result instead of linked
+    Resource {
+        resource: McpEmbeddedResource,
+    },
+}
+
+/// Resource embedded in content, with either its text or its base64
+/// encoded bytes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpEmbeddedResource {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+impl McpContent {
+    /// Create text content
+    pub fn text(text: impl Into<String>) -> Self {
+        McpTypedContent::Text { text: text.into() }.into()
+    }
+
+    /// Read content as sent by a server
+    ///
+    /// Text content keeps only its text; other content keeps the whole
+    /// content object as its data.
+    pub fn from_wire(content: serde_json::Value) -> Self {
+        let content_type = content
+            .get("type")
+            .and_then(|content_type| content_type.as_str())
+            .unwrap_or("text")
+            .to_string();
+        let data = match content.get("text") {
+            Some(text) if content_type == "text" => text.clone(),
+            _ => content,
+        };
+        Self {
+            content_type,
+            data,
+            hint: None,
+        }
+    }
+
+    /// Get the content as MCP types it, if it is of one of its types
+    pub fn typed(&self) -> Option<McpTypedContent> {
+        match &self.data {
+            serde_json::Value::String(text) if self.content_type == "text" => {
+                Some(McpTypedContent::Text { text: text.clone() })
+            }
+            serde_json::Value::Object(object) => {
+                let mut object = object.clone();
+                object
+                    .entry("type")
+                    .or_insert_with(|| self.content_type.clone().into());
+                serde_json::from_value(object.into()).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Write the content as MCP sends it, as text if it has no MCP type
+    pub fn to_wire(&self) -> serde_json::Value {
+        let typed = self.typed().unwrap_or_else(|| McpTypedContent::Text {
+            text: match &self.data {
+                serde_json::Value::String(text) => text.clone(),
+                data => data.to_string(),
+            },
+        });
+        serde_json::to_value(typed).unwrap_or_default()
+    }
+}
+
+impl From<McpTypedContent> for McpContent {
+    fn from(content: McpTypedContent) -> Self {
+        let content_type = match &content {
+            McpTypedContent::Text { .. } => "text",
+            McpTypedContent::Image { .. } => "image",
+            McpTypedContent::Audio { .. } => "audio",
+            McpTypedContent::Resource { .. } => "resource",
+        };
+        let data = match content {
+            McpTypedContent::Text { text } => serde_json::Value::String(text),
+            content => serde_json::to_value(content).unwrap_or_default(),
+        };
+        Self {
+            content_type: content_type.to_string(),
+            data,
+            hint: None,
+        }
+    }
+}
+
+/// Folder a server may work in, answered to [`ROOTS_LIST`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpRoot {
+    /// `file://` URI of the folder
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Capabilities Catalyst declares in its `initialize` request
+///
+/// Servers may ask for the workspace roots, and are told when they change,
+/// and may ask for completions with [`SAMPLING_CREATE_MESSAGE`].
+pub fn client_capabilities() -> serde_json::Value {
+    serde_json::json!({
+        "roots": { "listChanged": true },
+        "sampling": {},
+    })
+}
+
+/// Check if Catalyst speaks a protocol version
+pub fn is_supported_protocol_version(version: &str) -> bool {
+    SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+}
+
+/// Parameters of the `initialize` request of a client
+///
+/// Besides the newest version, which the protocol asks for, the client
+/// lists every version it speaks under `experimental`, so a server can
+/// explain a mismatch.
+pub fn initialize_params(
+    client_name: &str,
+    client_version: &str,
+) -> serde_json::Value {
+    let mut capabilities = client_capabilities();
+    capabilities["experimental"] = serde_json::json!({
+        "protocolVersions": SUPPORTED_PROTOCOL_VERSIONS,
+    });
+    serde_json::json!({
+        "protocolVersion": MCP_PROTOCOL_VERSION,
+        "capabilities": capabilities,
+        "clientInfo": { "name": client_name, "version": client_version },
+    })
+}
+
+/// Pick the version a server answers `initialize` with: the one the client
+/// asked for if it is supported, the newest one otherwise, leaving it to
+/// the client to refuse
+pub fn negotiate_protocol_version(
+    params: Option<&serde_json::Value>,
+) -> &'static str {
+    let requested = params
+        .and_then(|params| params.get("protocolVersion"))
+        .and_then(|version| version.as_str());
+    SUPPORTED_PROTOCOL_VERSIONS
+        .into_iter()
+        .find(|version| Some(*version) == requested)
+        .unwrap_or(MCP_PROTOCOL_VERSION)
+}
+
+/// Content of a resource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpResourceContent {
+    pub uri: String,
+    pub mime_type: Option<String>,
+    pub text: Option<String>,
+    pub blob: Option<Vec<u8>>,
+}
+
+/// Part of a resource read by byte range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpResourceChunk {
+    pub uri: String,
+    pub mime_type: Option<String>,
+    /// Offset of `data` in the resource
+    pub offset: u64,
+    pub data: Vec<u8>,
+    /// Size of the whole resource, if the server knows it
+    pub total_size: Option<u64>,
+    /// Whether `data` reaches the end of the resource
+    pub eof: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::{Value, json};
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Value {
+        let json = serde_json::to_value(value).unwrap();
+        let back: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(back).unwrap(), json);
+        json
+    }
+
+    #[test]
+    fn test_protocol_round_trip() {
+        let request = round_trip(&McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "1".into(),
+            method: "tools/call".to_string(),
+            params: Some(json!({"name": "echo"})),
+        });
+        assert_eq!(request["method"], "tools/call");
+
+        let response = round_trip(&McpResponse {
+            jsonrpc: "2.0".to_string(),
+            id: "1".into(),
+            result: None,
+            error: Some(McpError {
+                code: METHOD_NOT_FOUND,
+                message: "Unknown method".to_string(),
+                data: None,
+            }),
+        });
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let tool = round_trip(&McpTool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: json!({"type": "object"}),
+            effect: ToolEffect::ReadOnly,
+        });
+        assert_eq!(tool["effect"], "read_only");
+        // Tools that don't say what they do are treated as mutating
+        let tool: McpTool = serde_json::from_value(json!({
+            "name": "rm",
+            "description": null,
+            "input_schema": {},
+        }))
+        .unwrap();
+        assert_eq!(tool.effect, ToolEffect::Mutating);
+
+        let result = round_trip(&McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                data: json!("done"),
+                hint: Some(McpContentHint::Log),
+            }],
+            is_error: false,
+        });
+        assert_eq!(result["content"][0]["hint"], "log");
+
+        round_trip(&McpResource {
+            uri: "file:///a".to_string(),
+            name: "a".to_string(),
+            description: None,
+            mime_type: Some("text/plain".to_string()),
+        });
+        round_trip(&McpResourceContent {
+            uri: "file:///a".to_string(),
+            mime_type: None,
+            text: None,
+            blob: Some(vec![0, 255]),
+        });
+        round_trip(&McpResourceChunk {
+            uri: "file:///a".to_string(),
+            mime_type: None,
+            offset: 4,
+            data: vec![1, 2],
+            total_size: Some(6),
+            eof: true,
+        });
+    }
+
+    #[test]
+    fn test_numeric_request_ids() {
+        let request: McpRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "ping",
+            "params": null,
+        }))
+        .unwrap();
+        assert_eq!(request.id, McpRequestId::Number(7));
+        assert_eq!(serde_json::to_value(&request).unwrap()["id"], 7);
+        assert_eq!(request.id.to_string(), "7");
+        assert_ne!(request.id, McpRequestId::from("7"));
+
+        let cancelled = McpNotification::cancelled(&request.id, None);
+        assert_eq!(cancelled.params.unwrap()["requestId"], 7);
+    }
+
+    #[test]
+    fn test_tool_wire_form() {
+        let listed = json!({
+            "name": "read",
+            "description": "Read a file",
+            "inputSchema": {"type": "object"},
+            "annotations": {"readOnlyHint": true, "title": "Read"},
+        });
+        let tool = McpTool::from_wire(listed).unwrap();
+        assert_eq!(tool.input_schema, json!({"type": "object"}));
+        assert_eq!(tool.effect, ToolEffect::ReadOnly);
+        assert_eq!(
+            tool.to_wire(),
+            json!({
+                "name": "read",
+                "description": "Read a file",
+                "inputSchema": {"type": "object"},
+                "annotations": {"readOnlyHint": true},
+            })
+        );
+        // Tools without the hint may change anything
+        let tool =
+            McpTool::from_wire(json!({"name": "rm", "inputSchema": {}})).unwrap();
+        assert_eq!(tool.effect, ToolEffect::Mutating);
+        assert!(McpTool::from_wire(json!({"name": "rm"})).is_err());
+
+        let result = McpToolResult::from_wire(&json!({
+            "content": [{"type": "text", "text": "failed"}],
+            "isError": true,
+        }))
+        .unwrap();
+        assert!(result.is_error);
+        assert_eq!(result.content[0].data, "failed");
+        assert_eq!(
+            result.to_wire(),
+            json!({
+                "content": [{"type": "text", "text": "failed"}],
+                "isError": true,
+            })
+        );
+        assert!(matches!(
+            McpToolResult::from_wire(&json!({"isError": false})),
+            Err(McpClientError::ProtocolViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_typed_content() {
+        let text = McpContent::text("done");
+        assert_eq!(text.data, "done");
+        assert_eq!(text.to_wire(), json!({"type": "text", "text": "done"}));
+
+        let screenshot = json!({
+            "type": "image",
+            "data": "iVBORw0KGgo=",
+            "mimeType": "image/png",
+        });
+        let content = McpContent::from_wire(screenshot.clone());
+        assert_eq!(content.content_type, "image");
+        assert_eq!(
+            content.typed(),
+            Some(McpTypedContent::Image {
+                data: "iVBORw0KGgo=".to_string(),
+                mime_type: "image/png".to_string(),
+            })
+        );
+        assert_eq!(content.to_wire(), screenshot);
+
+        let recording = McpContent::from(McpTypedContent::Audio {
+            data: "UklGRg==".to_string(),
+            mime_type: "audio/wav".to_string(),
+        });
+        assert_eq!(recording.content_type, "audio");
+        assert_eq!(recording.data["mimeType"], "audio/wav");
+
+        let resource = McpContent::from_wire(json!({
+            "type": "resource",
+            "resource": {"uri": "file:///a.json", "text": "{}"},
+        }));
+        let Some(McpTypedContent::Resource { resource }) = resource.typed() else {
+            panic!("not a resource");
+        };
+        assert_eq!(resource.text.as_deref(), Some("{}"));
+        assert!(resource.blob.is_none());
+
+        // Content without an MCP type is sent as text
+        let json = McpContent {
+            content_type: "json".to_string(),
+            data: json!({"a": 1}),
+            hint: None,
+        };
+        assert_eq!(json.typed(), None);
+        assert_eq!(json.to_wire(), json!({"type": "text", "text": "{\"a\":1}"}));
+    }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let params = initialize_params("catalyst", "1.0.0");
+        assert_eq!(params["protocolVersion"], MCP_PROTOCOL_VERSION);
+        assert_eq!(
+            params["capabilities"]["experimental"]["protocolVersions"],
+            json!(SUPPORTED_PROTOCOL_VERSIONS)
+        );
+        assert_eq!(params["capabilities"]["roots"]["listChanged"], true);
+
+        assert_eq!(
+            negotiate_protocol_version(Some(&params)),
+            MCP_PROTOCOL_VERSION
+        );
+        let old = json!({"protocolVersion": "2024-11-05"});
+        assert_eq!(negotiate_protocol_version(Some(&old)), "2024-11-05");
+        let future = json!({"protocolVersion": "2099-01-01"});
+        assert_eq!(
+            negotiate_protocol_version(Some(&future)),
+            MCP_PROTOCOL_VERSION
+        );
+        assert_eq!(negotiate_protocol_version(None), MCP_PROTOCOL_VERSION);
+        assert!(is_supported_protocol_version("2024-11-05"));
+        assert!(!is_supported_protocol_version("2025-03-26"));
+        assert!(!is_supported_protocol_version("2099-01-01"));
+    }
+}
@@ -0,0 +1,1341 @@
+//! Transports
+//!
+//! MCP messages travel as JSON-RPC objects, one per line. [`McpTransport`]
+//! sends requests and notifications to a server; [`LineTransport`] does so
+//! over any reader and writer, such as the stdout and stdin of a server
+//! process. [`serve_lines`] is the server side, answering the requests read
+//! from a reader. Request ids may be numbers or strings; [`McpRequestId`]
+//! keeps them as sent, so each side answers with the id it was sent.
+//!
+//! Several requests can be sent as one JSON-RPC batch, a JSON array, with
+//! [`McpTransport::request_batch`]. The server answers with an array in any
+//! order; [`demultiplex_batch`] puts the responses back in the order of the
+//! requests, so a request the server didn't answer fails on its own.
+//!
+//! Servers also push notifications, such as
+//! [`TOOLS_LIST_CHANGED`](crate::TOOLS_LIST_CHANGED), between responses.
+//! [`LineTransport`] hands those read while waiting for a response to the
+//! handler set with [`LineTransport::with_notification_handler`]. They
+//! may also send requests of their own, such as
+//! [`SAMPLING_CREATE_MESSAGE`](crate::SAMPLING_CREATE_MESSAGE) asking the
+//! client for a model completion; those are answered by the handler set
+//! with [`LineTransport::with_request_handler`], or refused with
+//! [`METHOD_NOT_FOUND`] without one.
+//!
+//! [`LineTransport`] only reads while a request waits, so a server's
+//! notifications wait for the next request. [`LineConnection`] reads on a
+//! thread of its own instead: notifications and server requests are
+//! handled as they arrive, and responses are routed by id to the requests
+//! waiting for them, from any number of threads.
+//!
+//! A long request is aborted with [`McpTransport::cancel`], which sends a
+//! [`CANCELLED`](crate::CANCELLED) notification. The server doesn't answer
+//! a cancelled request, and a response arriving anyway is skipped like any
+//! other response nobody waits for. A request blocks a [`LineTransport`]
+//! until it is answered, so requests that may need cancelling from another
+//! thread are sent on a [`LineConnection`], where
+//! [`LineConnection::cancel`] also stops the request from waiting.
+//!
+//! Failures of the transport itself carry a [`McpClientError`]: a closed
+//! connection is a [`Transport`](McpClientError::Transport) failure worth
+//! retrying, while a malformed or missing answer is not. A line that isn't
+//! valid JSON doesn't end a [`LineConnection`] or [`serve_lines`]: it is
+//! logged and skipped, failing the request it answers or answered with
+//! [`PARSE_ERROR`] when its id can still be read.
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Read, Write};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{
+    INVALID_REQUEST, METHOD_NOT_FOUND, McpClientError, McpError, McpNotification,
+    McpRequest, McpRequestId, McpResponse, PARSE_ERROR,
+};
+
+/// Longest message accepted, so a peer that never ends its line can't
+/// exhaust memory
+const MAX_MESSAGE_LEN: u64 = 64 * 1024 * 1024;
+
+/// Longest part of a malformed message that is logged
+const MAX_LOGGED_LEN: usize = 256;
+
+/// Connection to an MCP server
+pub trait McpTransport {
+    /// Send a request and wait for its response
+    fn request(&mut self, request: &McpRequest) -> Result<McpResponse>;
+
+    /// Send a notification, which gets no response
+    fn notify(&mut self, method: &str, params: Option<Value>) -> Result<()>;
+
+    /// Ask the server to abort the request with the given id, giving a
+    /// reason the server may log
+    fn cancel(&mut self, id: &McpRequestId, reason: Option<&str>) -> Result<()> {
+        let notification = McpNotification::cancelled(id, reason);
+        self.notify(&notification.method, notification.params)
+    }
+
+    /// Send several requests and wait for their responses, in the order of
+    /// `requests`
+    ///
+    /// The default sends the requests one by one; transports that can send
+    /// them as one batch override it.
+    fn request_batch(
+        &mut self,
+        requests: &[McpRequest],
+    ) -> Result<Vec<Result<McpResponse>>> {
+        Ok(requests
+            .iter()
+            .map(|request| self.request(request))
+            .collect())
+    }
+}
+
+/// Receives the notifications a server sends
+pub type NotificationCallback = Box<dyn FnMut(McpNotification) + Send>;
+
+/// Answers the requests a server sends
+pub type RequestCallback = Box<dyn FnMut(McpRequest) -> McpResponse + Send>;
+
+/// Transport sending one JSON-RPC message per line
+pub struct LineTransport<R, W> {
+    reader: R,
+    writer: W,
+    on_notification: Option<NotificationCallback>,
+    on_request: Option<RequestCallback>,
+}
+
+impl<R: BufRead, W: Write> LineTransport<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            on_notification: None,
+            on_request: None,
+        }
+    }
+
+    /// Pass the server's notifications to `handler` instead of dropping
+    /// them
+    pub fn with_notification_handler(
+        mut self,
+        handler: NotificationCallback,
+    ) -> Self {
+        self.on_notification = Some(handler);
+        self
+    }
+
+    /// Answer the server's requests with `handler` instead of refusing
+    /// them
+    pub fn with_request_handler(mut self, handler: RequestCallback) -> Self {
+        self.on_request = Some(handler);
+        self
+    }
+
+    /// Pass a message with a method on to the notification or request
+    /// handler, sending the response to a request
+    fn received(&mut self, message: Value) -> Result<()> {
+        let response = received(
+            message,
+            self.on_notification.as_mut(),
+            self.on_request.as_mut(),
+        )?;
+        if let Some(response) = response {
+            write_message(&mut self.writer, &response)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> McpTransport for LineTransport<R, W> {
+    fn request(&mut self, request: &McpRequest) -> Result<McpResponse> {
+        write_message(&mut self.writer, &serde_json::to_value(request)?)?;
+        loop {
+            let message = read_message(&mut self.reader)?.ok_or_else(|| {
+                McpClientError::Transport(
+                    "MCP server closed the connection before answering".into(),
+                )
+            })?;
+            if message.get("method").is_some() {
+                self.received(message)?;
+                continue;
+            }
+            if request_id(&message).as_ref() == Some(&request.id) {
+                return Ok(serde_json::from_value(message)?);
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Option<Value>) -> Result<()> {
+        let mut message = json!({"jsonrpc": "2.0", "method": method});
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        write_message(&mut self.writer, &message)
+    }
+
+    fn request_batch(
+        &mut self,
+        requests: &[McpRequest],
+    ) -> Result<Vec<Result<McpResponse>>> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut pending = HashSet::new();
+        for request in requests {
+            if !pending.insert(&request.id) {
+                return Err(anyhow!("Request id '{}' is used twice", request.id));
+            }
+        }
+        write_message(&mut self.writer, &serde_json::to_value(requests)?)?;
+        let mut responses = Vec::new();
+        // The server answers a batch with one array, but may also answer
+        // its items one by one
+        while !pending.is_empty() {
+            let Some(message) = read_message(&mut self.reader)? else {
+                break;
+            };
+            let is_batch = message.is_array();
+            let messages = match message {
+                Value::Array(messages) => messages,
+                message => vec![message],
+            };
+            for message in messages {
+                if message.get("method").is_some() {
+                    self.received(message)?;
+                    continue;
+                }
+                match message.get("id") {
+                    Some(Value::Null) => {
+                        return Err(anyhow!(
+                            "MCP server rejected the batch: {}",
+                            message
+                                .get("error")
+                                .and_then(|error| error.get("message"))
+                                .and_then(Value::as_str)
+                                .unwrap_or("no reason given")
+                        ));
+                    }
+                    Some(_) => {
+                        let answered = request_id(&message)
+                            .is_some_and(|id| pending.remove(&id));
+                        if answered {
+                            responses.push(serde_json::from_value(message)?);
+                        }
+                    }
+                    None => {}
+                }
+            }
+            if is_batch {
+                break;
+            }
+        }
+        Ok(demultiplex_batch(requests, responses))
+    }
+}
+
+/// Pass a message with a method on to the notification or request handler,
+/// returning the response to send back
+///
+/// Requests have an id as well and always get a response. Malformed
+/// notifications are skipped.
+fn received(
+    message: Value,
+    on_notification: Option<&mut NotificationCallback>,
+    on_request: Option<&mut RequestCallback>,
+) -> Result<Option<Value>> {
+    if message.get("id").is_some() {
+        return match on_request {
+            Some(handler) => answer(&message, handler),
+            None => answer(&message, &mut |request: McpRequest| McpResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(McpError {
+                    code: METHOD_NOT_FOUND,
+                    message: format!("Method '{}' not found", request.method),
+                    data: None,
+                }),
+            }),
+        };
+    }
+    let Some(handler) = on_notification else {
+        return Ok(None);
+    };
+    if let Ok(notification) = serde_json::from_value(message) {
+        handler(notification);
+    }
+    Ok(None)
+}
+
+/// Receives the response to a request sent on a [`LineConnection`]
+type Waiter = Sender<Result<McpResponse>>;
+
+#[derive(Default)]
+struct Pending {
+    /// Requests waiting for their response by id, with the batch they were
+    /// sent in
+    waiters: HashMap<McpRequestId, (Waiter, Option<u64>)>,
+    next_batch: u64,
+    /// Why the connection ended, once it has
+    closed: Option<String>,
+}
+
+impl Pending {
+    /// Fail the requests waiting in the batches `is_failed` picks
+    fn fail_batches(
+        &mut self,
+        is_failed: impl Fn(u64) -> bool,
+        error: impl Fn(&McpRequestId) -> anyhow::Error,
+    ) {
+        let failed: Vec<McpRequestId> = self
+            .waiters
+            .iter()
+            .filter(|(_, (_, batch))| batch.is_some_and(&is_failed))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in failed {
+            if let Some((waiter, _)) = self.waiters.remove(&id) {
+                let _ = waiter.send(Err(error(&id)));
+            }
+        }
+    }
+}
+
+/// Connection to a server sending one JSON-RPC message per line, read on a
+/// thread of its own
+///
+/// Clones share the connection. A request waits only for its own response,
+/// so requests can be sent from several threads at once, and a cancelled
+/// request stops waiting at once, since the server doesn't answer it.
+/// Notifications and server requests are handled on the reader thread, so
+/// handlers must not wait for a response from the same server.
+pub struct LineConnection<W> {
+    writer: Arc<Mutex<W>>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl<W> Clone for LineConnection<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> LineConnection<W> {
+    /// Start reading the server's messages from `reader`, handling its
+    /// notifications and requests with the given handlers
+    ///
+    /// Without a request handler the server's requests are refused with
+    /// [`METHOD_NOT_FOUND`]. The reader thread ends with the input, failing
+    /// the requests still waiting.
+    pub fn spawn(
+        reader: impl BufRead + Send + 'static,
+        writer: W,
+        on_notification: Option<NotificationCallback>,
+        on_request: Option<RequestCallback>,
+    ) -> Result<Self> {
+        let connection = Self {
+            writer: Arc::new(Mutex::new(writer)),
+            pending: Arc::default(),
+        };
+        let reading = connection.clone();
+        std::thread::Builder::new()
+            .name("mcp-reader".to_string())
+            .spawn(move || reading.read_all(reader, on_notification, on_request))?;
+        Ok(connection)
+    }
+
+    /// Send a request and wait for its response
+    pub fn request(&self, request: &McpRequest) -> Result<McpResponse> {
+        let mut receivers = self.send(std::slice::from_ref(request), false)?;
+        let (id, receiver) = receivers.remove(0);
+        wait(&id, receiver)
+    }
+
+    /// Send several requests as one batch and wait for their responses, in
+    /// the order of `requests`
+    pub fn request_batch(
+        &self,
+        requests: &[McpRequest],
+    ) -> Result<Vec<Result<McpResponse>>> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .send(requests, true)?
+            .into_iter()
+            .map(|(id, receiver)| wait(&id, receiver))
+            .collect())
+    }
+
+    /// Send a notification, which gets no response
+    pub fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
+        let mut message = json!({"jsonrpc": "2.0", "method": method});
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        write_message(&mut *lock(&self.writer), &message)
+    }
+
+    /// Ask the server to abort the request with the given id; the request
+    /// fails at once instead of waiting for a response that won't come
+    pub fn cancel(&self, id: &McpRequestId, reason: Option<&str>) -> Result<()> {
+        lock(&self.pending).waiters.remove(id);
+        let notification = McpNotification::cancelled(id, reason);
+        self.notify(&notification.method, notification.params)
+    }
+
+    /// Register the requests as waiting and write them, as one batch or
+    /// one message
+    fn send(
+        &self,
+        requests: &[McpRequest],
+        batch: bool,
+    ) -> Result<Vec<(McpRequestId, Receiver<Result<McpResponse>>)>> {
+        let mut receivers = Vec::new();
+        {
+            let mut pending = lock(&self.pending);
+            if let Some(reason) = &pending.closed {
+                return Err(McpClientError::Transport(reason.clone()).into());
+            }
+            let mut ids = HashSet::new();
+            for request in requests {
+                if !ids.insert(&request.id)
+                    || pending.waiters.contains_key(&request.id)
+                {
+                    return Err(anyhow!(
+                        "Request id '{}' is used twice",
+                        request.id
+                    ));
+                }
+            }
+            let tag = batch.then_some(pending.next_batch);
+            pending.next_batch += 1;
+            for request in requests {
+                let (sender, receiver) = channel();
+                pending.waiters.insert(request.id.clone(), (sender, tag));
+                receivers.push((request.id.clone(), receiver));
+            }
+        }
+        let message = if batch {
+            serde_json::to_value(requests)?
+        } else {
+            serde_json::to_value(&requests[0])?
+        };
+        if let Err(err) = write_message(&mut *lock(&self.writer), &message) {
+            let mut pending = lock(&self.pending);
+            for request in requests {
+                pending.waiters.remove(&request.id);
+            }
+            return Err(err);
+        }
+        Ok(receivers)
+    }
+
+    /// Read the server's messages until its output ends
+    fn read_all(
+        &self,
+        mut reader: impl BufRead,
+        mut on_notification: Option<NotificationCallback>,
+        mut on_request: Option<RequestCallback>,
+    ) {
+        let reason = loop {
+            let line = match read_line(&mut reader) {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    break "MCP server closed the connection".to_string();
+                }
+                Err(err) => break format!("{err:#}"),
+            };
+            let message = match parse_message(&line) {
+                Ok(message) => message,
+                Err(err) => {
+                    tracing::warn!(
+                        "skipping malformed message from MCP server: {err:#}: {}",
+                        excerpt(&line)
+                    );
+                    // The request it answers would wait forever otherwise
+                    let waiter = recover_id(&line)
+                        .and_then(|id| lock(&self.pending).waiters.remove(&id));
+                    if let Some((waiter, _)) = waiter {
+                        let _ = waiter.send(Err(err));
+                    }
+                    continue;
+                }
+            };
+            let is_batch = message.is_array();
+            let messages = match message {
+                Value::Array(messages) => messages,
+                message => vec![message],
+            };
+            let mut batches = HashSet::new();
+            for message in messages {
+                if message.get("method").is_some() {
+                    // A handler may wait a long time, as sampling does on
+                    // the user, so the writer is only locked to send its
+                    // response and other requests and cancellations go out
+                    // meanwhile
+                    let response = received(
+                        message,
+                        on_notification.as_mut(),
+                        on_request.as_mut(),
+                    );
+                    // A response that can't be written means the server is
+                    // gone, which the next read finds out
+                    if let Ok(Some(response)) = response {
+                        let _ = write_message(&mut *lock(&self.writer), &response);
+                    }
+                    continue;
+                }
+                let mut pending = lock(&self.pending);
+                match message.get("id") {
+                    // A batch the server couldn't read at all
+                    Some(Value::Null) => {
+                        let reason = format!(
+                            "MCP server rejected the batch: {}",
+                            message
+                                .get("error")
+                                .and_then(|error| error.get("message"))
+                                .and_then(Value::as_str)
+                                .unwrap_or("no reason given")
+                        );
+                        pending.fail_batches(|_| true, |_| anyhow!("{}", reason));
+                    }
+                    Some(_) => {
+                        // Responses nobody waits for, such as one to a
+                        // cancelled request, are skipped
+                        let Some((waiter, batch)) = request_id(&message)
+                            .and_then(|id| pending.waiters.remove(&id))
+                        else {
+                            continue;
+                        };
+                        batches.extend(batch);
+                        let _ = waiter.send(
+                            serde_json::from_value(message).map_err(Into::into),
+                        );
+                    }
+                    None => {}
+                }
+            }
+            // Requests of a batch answered without them got no answer
+            if is_batch {
+                lock(&self.pending).fail_batches(
+                    |batch| batches.contains(&batch),
+                    |id| {
+                        McpClientError::ProtocolViolation(format!(
+                            "MCP server did not answer request '{}'",
+                            id
+                        ))
+                        .into()
+                    },
+                );
+            }
+        };
+        let mut pending = lock(&self.pending);
+        for (_, (waiter, _)) in pending.waiters.drain() {
+            let _ =
+                waiter.send(Err(McpClientError::Transport(reason.clone()).into()));
+        }
+        pending.closed = Some(reason);
+    }
+}
+
+impl<W: Write + Send + 'static> McpTransport for LineConnection<W> {
+    fn request(&mut self, request: &McpRequest) -> Result<McpResponse> {
+        LineConnection::request(self, request)
+    }
+
+    fn notify(&mut self, method: &str, params: Option<Value>) -> Result<()> {
+        LineConnection::notify(self, method, params)
+    }
+
+    fn cancel(&mut self, id: &McpRequestId, reason: Option<&str>) -> Result<()> {
+        LineConnection::cancel(self, id, reason)
+    }
+
+    fn request_batch(
+        &mut self,
+        requests: &[McpRequest],
+    ) -> Result<Vec<Result<McpResponse>>> {
+        LineConnection::request_batch(self, requests)
+    }
+}
+
+/// Wait for the response to the request with the given id
+fn wait(
+    id: &McpRequestId,
+    receiver: Receiver<Result<McpResponse>>,
+) -> Result<McpResponse> {
+    receiver
+        .recv()
+        .unwrap_or_else(|_| Err(anyhow!("MCP request '{}' was cancelled", id)))
+}
+
+/// Lock a mutex, even if a thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Match the responses to a batch with its requests by id, in the order of
+/// the requests
+///
+/// A request without a response gets an error of its own.
+pub fn demultiplex_batch(
+    requests: &[McpRequest],
+    responses: Vec<McpResponse>,
+) -> Vec<Result<McpResponse>> {
+    let mut responses: HashMap<McpRequestId, McpResponse> = responses
+        .into_iter()
+        .map(|response| (response.id.clone(), response))
+        .collect();
+    requests
+        .iter()
+        .map(|request| {
+            responses.remove(&request.id).ok_or_else(|| {
+                McpClientError::ProtocolViolation(format!(
+                    "MCP server did not answer request '{}'",
+                    request.id
+                ))
+                .into()
+            })
+        })
+        .collect()
+}
+
+/// Answer the requests read from `reader` until it ends
+///
+/// Notifications and responses are skipped, since they get no answer. A
+/// batch is answered with an array of the responses to its requests. A line
+/// that isn't valid JSON is answered with [`PARSE_ERROR`], by id if its id
+/// can still be read.
+pub fn serve_lines(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    mut handle: impl FnMut(McpRequest) -> McpResponse,
+) -> Result<()> {
+    while let Some(line) = read_line(&mut reader)? {
+        let message = match parse_message(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!(
+                    "skipping malformed MCP message: {err:#}: {}",
+                    excerpt(&line)
+                );
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": recover_id(&line),
+                    "error": {"code": PARSE_ERROR, "message": format!("{err:#}")},
+                });
+                write_message(&mut writer, &error)?;
+                continue;
+            }
+        };
+        match message {
+            Value::Array(messages) if messages.is_empty() => {
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": INVALID_REQUEST, "message": "Empty batch"},
+                });
+                write_message(&mut writer, &error)?;
+            }
+            Value::Array(messages) => {
+                let responses = messages
+                    .iter()
+                    .filter_map(|message| answer(message, &mut handle).transpose())
+                    .collect::<Result<Vec<_>>>()?;
+                if !responses.is_empty() {
+                    write_message(&mut writer, &Value::Array(responses))?;
+                }
+            }
+            message => {
+                if let Some(response) = answer(&message, &mut handle)? {
+                    write_message(&mut writer, &response)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Answer a request, or nothing for other messages
+///
+/// A request whose id is neither a number nor a string is refused with
+/// [`INVALID_REQUEST`], as it can't be answered by id.
+fn answer(
+    message: &Value,
+    handle: &mut impl FnMut(McpRequest) -> McpResponse,
+) -> Result<Option<Value>> {
+    let (Some(_), Some(method)) = (
+        message.get("id"),
+        message.get("method").and_then(Value::as_str),
+    ) else {
+        return Ok(None);
+    };
+    let Some(id) = request_id(message) else {
+        return Ok(Some(json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": {
+                "code": INVALID_REQUEST,
+                "message": "Request id must be a number or a string",
+            },
+        })));
+    };
+    let request = McpRequest {
+        jsonrpc: "2.0".to_string(),
+        id,
+        method: method.to_string(),
+        params: message.get("params").cloned(),
+    };
+    let mut response = serde_json::to_value(handle(request))?;
+    // A response has either a result or an error, never a null one
+    if let Some(response) = response.as_object_mut() {
+        response.retain(|_, value| !value.is_null());
+    }
+    Ok(Some(response))
+}
+
+/// Read the next message, or `None` at the end of the input
+///
+/// Input that is not valid JSON or UTF-8, or longer than the message limit,
+/// is an error.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    read_line(reader)?
+        .map(|line| parse_message(&line))
+        .transpose()
+}
+
+/// Read the next line that isn't blank, or `None` at the end of the input
+///
+/// Only failing to read and a line longer than the message limit are
+/// errors; the reader can't be read any further after them.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader
+            .by_ref()
+            .take(MAX_MESSAGE_LEN)
+            .read_until(b'\n', &mut line)
+            .map_err(|err| McpClientError::Transport(err.to_string()))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if !line.ends_with(b"\n") && read as u64 == MAX_MESSAGE_LEN {
+            return Err(McpClientError::ProtocolViolation(format!(
+                "MCP message longer than {} bytes",
+                MAX_MESSAGE_LEN
+            ))
+            .into());
+        }
+        if !line.trim_ascii().is_empty() {
+            return Ok(Some(line));
+        }
+    }
+}
+
+/// Parse a line read with [`read_line`] as a message
+fn parse_message(line: &[u8]) -> Result<Value> {
+    let line = std::str::from_utf8(line).map_err(|err| {
+        McpClientError::ProtocolViolation(format!("MCP message is not UTF-8: {err}"))
+    })?;
+    serde_json::from_str(line)
+        .map_err(|err| McpClientError::Serialization(err).into())
+}
+
+/// Find the id of a message that isn't valid JSON, so it can still be
+/// answered
+///
+/// The id is the value after the first `"id"` key, if it is a number or a
+/// string.
+fn recover_id(line: &[u8]) -> Option<McpRequestId> {
+    let line = String::from_utf8_lossy(line);
+    let (_, rest) = line.split_once("\"id\"")?;
+    let rest = rest.trim_start().strip_prefix(':')?;
+    let id = serde_json::Deserializer::from_str(rest)
+        .into_iter::<Value>()
+        .next()?
+        .ok()?;
+    McpRequestId::deserialize(id).ok()
+}
+
+/// Start of a malformed message, to log
+fn excerpt(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(&line[..line.len().min(MAX_LOGGED_LEN)]);
+    line.trim_end().to_string()
+}
+
+/// Write a message as one line
+pub fn write_message(writer: &mut impl Write, message: &Value) -> Result<()> {
+    writeln!(writer, "{}", message)
+        .and_then(|()| writer.flush())
+        .map_err(|err| McpClientError::Transport(err.to_string()).into())
+}
+
+/// Get the id of a message, if it is a number or a string
+fn request_id(message: &Value) -> Option<McpRequestId> {
+    McpRequestId::deserialize(message.get("id")?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::io::{BufReader, Cursor};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    /// MCP server traffic, also the seed corpus of the `mcp_message` fuzz
+    /// target
+    fn corpus() -> Vec<Vec<u8>> {
+        let dir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus/mcp_message");
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+            .collect()
+    }
+
+    fn ping(id: impl Into<McpRequestId>) -> McpRequest {
+        McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: id.into(),
+            method: "ping".to_string(),
+            params: None,
+        }
+    }
+
+    #[test]
+    fn test_line_transport() {
+        let input = [
+            r#"{"jsonrpc":"2.0","id":7,"method":"ping"}"#,
+            "",
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":"b","method":"ping"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_lines(Cursor::new(input), &mut output, |request| McpResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(json!({})),
+            error: None,
+        })
+        .unwrap();
+        let responses: Vec<Value> = output
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 7);
+        assert!(responses[0].get("error").is_none());
+        assert_eq!(responses[1]["id"], "b");
+
+        // The client skips messages until the response to its request
+        let server_output = format!(
+            "{}\n{}",
+            r#"{"jsonrpc":"2.0","method":"notifications/progress"}"#,
+            String::from_utf8(output).unwrap()
+        );
+        let mut sent = Vec::new();
+        let mut transport =
+            LineTransport::new(Cursor::new(server_output), &mut sent);
+        let response = transport
+            .request(&McpRequest {
+                jsonrpc: "2.0".to_string(),
+                id: "b".into(),
+                method: "ping".to_string(),
+                params: None,
+            })
+            .unwrap();
+        assert_eq!(response.id, "b");
+        assert!(
+            transport
+                .request(&McpRequest {
+                    jsonrpc: "2.0".to_string(),
+                    id: "c".into(),
+                    method: "ping".to_string(),
+                    params: None,
+                })
+                .is_err()
+        );
+        transport.notify("notifications/cancelled", None).unwrap();
+        assert_eq!(String::from_utf8(sent).unwrap().lines().count(), 3);
+
+        for capture in corpus() {
+            let mut reader = Cursor::new(&capture);
+            while read_message(&mut reader).unwrap().is_some() {}
+            let mut transport =
+                LineTransport::new(Cursor::new(&capture), Vec::new());
+            assert!(transport.request(&ping(2)).unwrap().result.is_some());
+        }
+    }
+
+    #[test]
+    fn test_numeric_ids_are_kept() {
+        // A response with the id as a string doesn't answer a request sent
+        // with it as a number
+        let server_output = [
+            r#"{"jsonrpc":"2.0","id":"7","result":{"n":1}}"#,
+            r#"{"jsonrpc":"2.0","id":7,"result":{"n":2}}"#,
+        ]
+        .join("\n");
+        let mut transport =
+            LineTransport::new(Cursor::new(server_output), Vec::new());
+        let response = transport.request(&ping(7)).unwrap();
+        assert_eq!(response.id, McpRequestId::Number(7));
+        assert_eq!(response.result, Some(json!({"n": 2})));
+
+        // A request with an id that is neither is refused
+        let mut output = Vec::new();
+        serve_lines(
+            Cursor::new(r#"{"jsonrpc":"2.0","id":{"n":1},"method":"ping"}"#),
+            &mut output,
+            |_| unreachable!("the request has no valid id"),
+        )
+        .unwrap();
+        let refused: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(refused["id"], Value::Null);
+        assert_eq!(refused["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_malformed_lines_are_skipped() {
+        let input = [
+            "not json",
+            r#"{"jsonrpc":"2.0","id":3,"method":"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"ping"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_lines(Cursor::new(input), &mut output, |request| McpResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(json!({})),
+            error: None,
+        })
+        .unwrap();
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], Value::Null);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["id"], 3);
+        assert_eq!(responses[1]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[2]["id"], 4);
+        assert_eq!(responses[2]["result"], json!({}));
+
+        // The client fails the request a garbled response answers, and
+        // keeps reading
+        let (reader, mut server) = std::io::pipe().unwrap();
+        let sent = Shared::default();
+        let connection =
+            LineConnection::spawn(BufReader::new(reader), sent.clone(), None, None)
+                .unwrap();
+        let a = {
+            let connection = connection.clone();
+            std::thread::spawn(move || connection.request(&ping("a")))
+        };
+        sent.wait_for(1);
+        writeln!(server, "not json").unwrap();
+        writeln!(server, r#"{{"jsonrpc":"2.0","id":"a","result":"#).unwrap();
+        let garbled = a.join().unwrap().unwrap_err();
+        assert!(matches!(
+            McpClientError::of(&garbled),
+            Some(McpClientError::Serialization(_))
+        ));
+        let b = {
+            let connection = connection.clone();
+            std::thread::spawn(move || connection.request(&ping("b")))
+        };
+        sent.wait_for(2);
+        writeln!(server, r#"{{"jsonrpc":"2.0","id":"b","result":{{}}}}"#).unwrap();
+        assert_eq!(b.join().unwrap().unwrap().result, Some(json!({})));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut sent = Vec::new();
+        let mut transport = LineTransport::new(Cursor::new(""), &mut sent);
+        transport.cancel(&"c".into(), Some("Timed out")).unwrap();
+        transport.cancel(&"d".into(), None).unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        let lines: Vec<&str> = sent.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"reason":"Timed out","requestId":"c"}}"#,
+                r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":"d"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_requests() {
+        let input = [
+            concat!(
+                r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},"#,
+                r#"{"jsonrpc":"2.0","method":"notifications/initialized"},"#,
+                r#"{"jsonrpc":"2.0","id":"b","method":"fail"}]"#,
+            ),
+            "[]",
+            r#"[{"jsonrpc":"2.0","method":"notifications/initialized"}]"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve_lines(Cursor::new(input), &mut output, |request| McpResponse {
+            jsonrpc: "2.0".to_string(),
+            result: (request.method == "ping").then(|| json!({})),
+            error: (request.method == "fail").then(|| crate::McpError {
+                code: crate::METHOD_NOT_FOUND,
+                message: "Unknown method".to_string(),
+                data: None,
+            }),
+            id: request.id,
+        })
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][0]["id"], 1);
+        assert_eq!(lines[0][1]["error"]["code"], crate::METHOD_NOT_FOUND);
+        assert_eq!(lines[1]["error"]["code"], INVALID_REQUEST);
+
+        // Responses are matched by id, whatever their order
+        let server_output = [
+            r#"{"jsonrpc":"2.0","method":"notifications/progress"}"#,
+            concat!(
+                r#"[{"jsonrpc":"2.0","id":"c","result":{"n":3}},"#,
+                r#"{"jsonrpc":"2.0","id":"a","result":{"n":1}}]"#,
+            ),
+        ]
+        .join("\n");
+        let mut sent = Vec::new();
+        let mut transport =
+            LineTransport::new(Cursor::new(server_output), &mut sent);
+        let results = transport
+            .request_batch(&[ping("a"), ping("b"), ping("c")])
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().result, Some(json!({"n": 1})));
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().result, Some(json!({"n": 3})));
+        assert!(transport.request_batch(&[ping("a"), ping("a")]).is_err());
+        let sent: Value = serde_json::from_slice(&sent).unwrap();
+        assert_eq!(sent.as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_notifications_reach_handler() {
+        let server_output = [
+            r#"{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}"#,
+            r#"{"jsonrpc":"2.0","id":"s1","method":"roots/list"}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"indexing"}}"#,
+            r#"{"jsonrpc":"2.0","id":"1","result":{}}"#,
+        ]
+        .join("\n");
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut transport =
+            LineTransport::new(Cursor::new(server_output), Vec::new())
+                .with_notification_handler(Box::new({
+                    let received = received.clone();
+                    move |notification| received.lock().unwrap().push(notification)
+                }));
+        transport.request(&ping("1")).unwrap();
+
+        // The server's own request is not a notification
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[0],
+            McpNotification::new(crate::TOOLS_LIST_CHANGED, None)
+        );
+        assert_eq!(received[1].method, crate::LOGGING_MESSAGE);
+        assert_eq!(received[1].params.as_ref().unwrap()["data"], "indexing");
+    }
+
+    #[test]
+    fn test_server_requests_are_answered() {
+        let server_output = [
+            r#"{"jsonrpc":"2.0","id":3,"method":"sampling/createMessage","params":{"maxTokens":10}}"#,
+            r#"{"jsonrpc":"2.0","id":"1","result":{}}"#,
+        ]
+        .join("\n");
+        let mut sent = Vec::new();
+        let mut transport =
+            LineTransport::new(Cursor::new(server_output), &mut sent)
+                .with_request_handler(Box::new(|request| McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(json!({ "method": request.method })),
+                    error: None,
+                }));
+        transport.request(&ping("1")).unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        let lines: Vec<&str> = sent.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            r#"{"id":3,"jsonrpc":"2.0","result":{"method":"sampling/createMessage"}}"#
+        );
+
+        // Without a handler the request is refused
+        let mut sent = Vec::new();
+        let mut transport = LineTransport::new(
+            Cursor::new(r#"{"jsonrpc":"2.0","id":"s1","method":"roots/list"}"#),
+            &mut sent,
+        );
+        assert!(transport.request(&ping("2")).is_err());
+        let refused: Value = serde_json::from_slice(
+            sent.split(|byte| *byte == b'\n').nth(1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(refused["id"], "s1");
+        assert_eq!(refused["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    /// Writer whose output the test reads back
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            lock(&self.0).extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        /// Wait until `count` messages were sent
+        fn wait_for(&self, count: usize) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while String::from_utf8_lossy(&lock(&self.0)).lines().count() < count {
+                assert!(Instant::now() < deadline, "messages weren't sent");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_connection() {
+        let (reader, mut server) = std::io::pipe().unwrap();
+        let sent = Shared::default();
+        let (notified, notifications) = channel();
+        let connection = LineConnection::spawn(
+            BufReader::new(reader),
+            sent.clone(),
+            Some(Box::new(move |notification| {
+                let _ = notified.send(notification);
+            })),
+            None,
+        )
+        .unwrap();
+        let request = |id: &str| {
+            let connection = connection.clone();
+            let request = ping(id);
+            std::thread::spawn(move || connection.request(&request))
+        };
+
+        // Notifications are handled while no request waits
+        writeln!(
+            server,
+            r#"{{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}}"#
+        )
+        .unwrap();
+        let notification =
+            notifications.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(notification.method, crate::TOOLS_LIST_CHANGED);
+
+        // Responses reach the request they answer, whatever their order
+        let a = request("a");
+        sent.wait_for(1);
+        let b = request("b");
+        sent.wait_for(2);
+        writeln!(server, r#"{{"jsonrpc":"2.0","id":"b","result":{{"n":2}}}}"#)
+            .unwrap();
+        writeln!(server, r#"{{"jsonrpc":"2.0","id":"a","result":{{"n":1}}}}"#)
+            .unwrap();
+        assert_eq!(b.join().unwrap().unwrap().result, Some(json!({"n": 2})));
+        assert_eq!(a.join().unwrap().unwrap().result, Some(json!({"n": 1})));
+
+        // A cancelled request stops waiting, and a late answer is skipped
+        let c = request("c");
+        sent.wait_for(3);
+        connection.cancel(&"c".into(), None).unwrap();
+        assert!(c.join().unwrap().is_err());
+        writeln!(server, r#"{{"jsonrpc":"2.0","id":"c","result":{{}}}}"#).unwrap();
+
+        // Requests of a batch the server left out fail on their own
+        let batch = {
+            let connection = connection.clone();
+            std::thread::spawn(move || {
+                connection.request_batch(&[ping("d"), ping("e")])
+            })
+        };
+        sent.wait_for(5);
+        writeln!(server, r#"[{{"jsonrpc":"2.0","id":"e","result":{{}}}}]"#).unwrap();
+        let results = batch.join().unwrap().unwrap();
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+
+        // Requests fail once the server is gone
+        let f = request("f");
+        sent.wait_for(6);
+        drop(server);
+        assert!(f.join().unwrap().is_err());
+        assert!(connection.request(&ping("g")).is_err());
+    }
+
+    #[test]
+    fn test_blocked_request_handler_leaves_connection_writable() {
+        let (reader, mut server) = std::io::pipe().unwrap();
+        let sent = Shared::default();
+        let (entered, handler_entered) = channel();
+        let (release, released) = channel::<()>();
+        let connection = LineConnection::spawn(
+            BufReader::new(reader),
+            sent.clone(),
+            None,
+            Some(Box::new(move |request| {
+                let _ = entered.send(());
+                let _ = released.recv();
+                McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(json!({})),
+                    error: None,
+                }
+            })),
+        )
+        .unwrap();
+
+        // The server asks for a completion, which waits on the user
+        writeln!(
+            server,
+            r#"{{"jsonrpc":"2.0","id":"s1","method":"sampling/createMessage"}}"#
+        )
+        .unwrap();
+        handler_entered
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+
+        // Requests and cancellations still go out meanwhile
+        let waiting = {
+            let connection = connection.clone();
+            std::thread::spawn(move || connection.request(&ping("a")))
+        };
+        sent.wait_for(1);
+        connection.cancel(&"a".into(), Some("Timed out")).unwrap();
+        sent.wait_for(2);
+        assert!(waiting.join().unwrap().is_err());
+
+        release.send(()).unwrap();
+        sent.wait_for(3);
+        let output = String::from_utf8(lock(&sent.0).clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["id"], "a");
+        assert_eq!(lines[1]["method"], crate::CANCELLED);
+        assert_eq!(lines[2]["id"], "s1");
+    }
+
+    #[test]
+    fn test_transport_failures_are_classified() {
+        let failure = |server_output: &str| {
+            let mut transport =
+                LineTransport::new(Cursor::new(server_output), Vec::new());
+            transport.request(&ping("1")).unwrap_err()
+        };
+
+        // A server gone mid-request may be there again after a restart
+        let closed = failure("");
+        let error = McpClientError::of(&closed).unwrap();
+        assert!(matches!(error, McpClientError::Transport(_)));
+        assert!(error.is_retryable());
+
+        let garbled = failure("not json\n");
+        let error = McpClientError::of(&garbled).unwrap();
+        assert!(matches!(error, McpClientError::Serialization(_)));
+        assert!(!error.is_retryable());
+
+        let mut unanswered = LineTransport::new(
+            Cursor::new(
+                r#"[{"jsonrpc":"2.0","id":"a","result":{}}]"#.to_string() + "\n",
+            ),
+            Vec::new(),
+        );
+        let results = unanswered.request_batch(&[ping("a"), ping("b")]).unwrap();
+        assert!(matches!(
+            McpClientError::of(results[1].as_ref().unwrap_err()),
+            Some(McpClientError::ProtocolViolation(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn test_read_message_never_panics(
+            bytes in prop::collection::vec(any::<u8>(), 0..512),
+        ) {
+            let mut reader = Cursor::new(bytes);
+            while let Ok(Some(_)) = read_message(&mut reader) {}
+        }
+
+        #[test]
+        fn test_truncated_messages_are_errors(
+            text in "\\PC{0,32}",
+            cut in 0usize..64,
+        ) {
+            let message =
+                json!({"jsonrpc": "2.0", "id": 1, "result": {"text": text}});
+            let bytes = message.to_string().into_bytes();
+            let cut = cut.min(bytes.len().saturating_sub(1));
+            let mut reader = Cursor::new(bytes[..cut].to_vec());
+            prop_assert!(!matches!(read_message(&mut reader), Ok(Some(_))));
+        }
+
+        #[test]
+        fn test_response_found_between_notifications(
+            methods in prop::collection::vec("\\PC{0,16}", 0..8),
+            position in 0usize..8,
+        ) {
+            let mut lines: Vec<String> = methods
+                .iter()
+                .enumerate()
+                .map(|(i, method)| {
+                    if i % 2 == 0 {
+                        json!({"jsonrpc": "2.0", "method": method}).to_string()
+                    } else {
+                        json!({"jsonrpc": "2.0", "id": i, "result": {}})
+                            .to_string()
+                    }
+                })
+                .collect();
+            let response =
+                json!({"jsonrpc": "2.0", "id": "x", "result": {"ok": true}});
+            lines.insert(position.min(lines.len()), response.to_string());
+            let mut transport =
+                LineTransport::new(Cursor::new(lines.join("\n")), Vec::new());
+            let response = transport.request(&ping("x")).unwrap();
+            prop_assert_eq!(response.result, Some(json!({"ok": true})));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Branch switch detection for the workspace watcher.
+//!
+//! A `git checkout`, `git rebase` or `git pull` can touch tens of thousands
+//! of files at once. Reacting to each of their events reloads the file
+//! explorer and recomputes the git status over and over, so the watcher
+//! detects such operations and batches their events instead: a change of
+//! `HEAD`, a rebase in progress or a burst of events switches it into batch
+//! mode, in which events are only collected. Once the repository has been
+//! quiet for a while, the batch is handed over as a whole, for a single
+//! reload of the changed paths and a single git status refresh. A rebase
+//! that stops, e.g. on conflicts, ends its batch, so the user sees the
+//! state they resolve.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify::{Event, EventKind};
+
+/// Events within [`MASS_EVENT_WINDOW`] that start a batch even without a
+/// git operation, e.g. for a branch switched by another tool
+pub const MASS_EVENT_THRESHOLD: usize = 1000;
+pub const MASS_EVENT_WINDOW: Duration = Duration::from_secs(1);
+/// Time without events after which a batch is complete
+pub const BATCH_QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// What switched the watcher into batch mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchTrigger {
+    /// `HEAD` was rewritten, as by a checkout
+    HeadChanged,
+    /// A rebase is in progress
+    Rebase,
+    /// More than [`MASS_EVENT_THRESHOLD`] events arrived at once
+    MassEvents,
+}
+
+/// Workspace changes collected in batch mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileBatch {
+    pub trigger: BatchTrigger,
+    /// Changed paths outside of the git directory
+    pub paths: BTreeSet<PathBuf>,
+    /// Events received while batching
+    pub events: usize,
+}
+
+/// How the watcher should handle an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Observed {
+    /// Not part of a batch, handle it as usual
+    Passed,
+    /// The event started a batch; wait for it with
+    /// [`BranchSwitchDetector::poll`]
+    BatchStarted,
+    /// The event was added to the current batch
+    Batched,
+}
+
+#[derive(Debug)]
+struct PendingBatch {
+    trigger: BatchTrigger,
+    paths: BTreeSet<PathBuf>,
+    events: usize,
+    last_event: Instant,
+}
+
+/// Detects bulk changes of the workspace and batches their events
+#[derive(Debug)]
+pub struct BranchSwitchDetector {
+    /// Git directory of the workspace repository, if it has one
+    git_dir: Option<PathBuf>,
+    /// Times of the events within the last [`MASS_EVENT_WINDOW`]
+    recent: VecDeque<Instant>,
+    batch: Option<PendingBatch>,
+}
+
+impl BranchSwitchDetector {
+    pub fn new(git_dir: Option<PathBuf>) -> Self {
+        Self {
+            git_dir,
+            recent: VecDeque::new(),
+            batch: None,
+        }
+    }
+
+    pub fn is_batching(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    /// Look at a workspace event, collecting it if the watcher is batching
+    pub fn observe(&mut self, event: &Event, now: Instant) -> Observed {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return Observed::Passed;
+        }
+        self.recent.push_back(now);
+        while self
+            .recent
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > MASS_EVENT_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+
+        let paths: Vec<PathBuf> = event
+            .paths
+            .iter()
+            .filter(|path| !self.is_git_path(path))
+            .cloned()
+            .collect();
+        if let Some(batch) = self.batch.as_mut() {
+            batch.paths.extend(paths);
+            batch.events += 1;
+            batch.last_event = now;
+            return Observed::Batched;
+        }
+
+        let trigger = event
+            .paths
+            .iter()
+            .find_map(|path| self.git_trigger(path))
+            .or_else(|| {
+                (self.recent.len() >= MASS_EVENT_THRESHOLD)
+                    .then_some(BatchTrigger::MassEvents)
+            });
+        match trigger {
+            Some(trigger) => {
+                self.batch = Some(PendingBatch {
+                    trigger,
+                    paths: paths.into_iter().collect(),
+                    events: 1,
+                    last_event: now,
+                });
+                Observed::BatchStarted
+            }
+            None => Observed::Passed,
+        }
+    }
+
+    /// Take the batch once no event arrived for [`BATCH_QUIET_PERIOD`] and
+    /// no rebase is applying commits
+    pub fn poll(&mut self, now: Instant) -> Option<FileBatch> {
+        let batch = self.batch.as_ref()?;
+        if now.duration_since(batch.last_event) < BATCH_QUIET_PERIOD
+            || self.rebase_running()
+        {
+            return None;
+        }
+        let batch = self.batch.take()?;
+        self.recent.clear();
+        Some(FileBatch {
+            trigger: batch.trigger,
+            paths: batch.paths,
+            events: batch.events,
+        })
+    }
+
+    fn is_git_path(&self, path: &Path) -> bool {
+        self.git_dir
+            .as_ref()
+            .is_some_and(|git_dir| path.starts_with(git_dir))
+    }
+
+    fn git_trigger(&self, path: &Path) -> Option<BatchTrigger> {
+        let relative = path.strip_prefix(self.git_dir.as_ref()?).ok()?;
+        if relative == Path::new("HEAD") {
+            Some(BatchTrigger::HeadChanged)
+        } else if relative.starts_with("rebase-merge")
+            || relative.starts_with("rebase-apply")
+        {
+            Some(BatchTrigger::Rebase)
+        } else {
+            None
+        }
+    }
+
+    /// Check if a rebase is applying commits, rather than stopped for the
+    /// user to resolve conflicts or edit a commit
+    fn rebase_running(&self) -> bool {
+        self.git_dir.as_ref().is_some_and(|git_dir| {
+            let in_progress = git_dir.join("rebase-merge").exists()
+                || git_dir.join("rebase-apply").exists();
+            let stopped = git_dir.join("REBASE_HEAD").exists()
+                || git_dir.join("rebase-merge/stopped-sha").exists();
+            in_progress && !stopped
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, DataChange, ModifyKind};
+
+    use super::*;
+
+    fn modified(path: &Path) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+            .add_path(path.to_path_buf())
+    }
+
+    #[test]
+    fn test_checkout_is_batched_until_quiet() {
+        let workspace = tempfile::tempdir().unwrap();
+        let git_dir = workspace.path().join(".git");
+        std::fs::create_dir(&git_dir).unwrap();
+        let mut detector = BranchSwitchDetector::new(Some(git_dir.clone()));
+        let start = Instant::now();
+
+        let file = workspace.path().join("src/main.rs");
+        assert_eq!(detector.observe(&modified(&file), start), Observed::Passed);
+        assert_eq!(
+            detector.observe(&modified(&git_dir.join("HEAD")), start),
+            Observed::BatchStarted
+        );
+        for i in 0..100 {
+            let path = workspace.path().join(format!("src/file_{i}.rs"));
+            let event =
+                Event::new(EventKind::Create(CreateKind::File)).add_path(path);
+            assert_eq!(detector.observe(&event, start), Observed::Batched);
+        }
+        assert_eq!(
+            detector.observe(&modified(&git_dir.join("index")), start),
+            Observed::Batched
+        );
+
+        assert!(detector.poll(start).is_none());
+        let batch = detector.poll(start + BATCH_QUIET_PERIOD).unwrap();
+        assert_eq!(batch.trigger, BatchTrigger::HeadChanged);
+        assert_eq!(batch.events, 102);
+        assert_eq!(batch.paths.len(), 100);
+        assert!(!detector.is_batching());
+        assert_eq!(detector.observe(&modified(&file), start), Observed::Passed);
+    }
+
+    #[test]
+    fn test_rebase_and_mass_events_start_batches() {
+        let workspace = tempfile::tempdir().unwrap();
+        let git_dir = workspace.path().join(".git");
+        std::fs::create_dir_all(git_dir.join("rebase-merge")).unwrap();
+        let mut detector = BranchSwitchDetector::new(Some(git_dir.clone()));
+        let start = Instant::now();
+
+        let todo = git_dir.join("rebase-merge/git-rebase-todo");
+        assert_eq!(
+            detector.observe(&modified(&todo), start),
+            Observed::BatchStarted
+        );
+        // Not done while the rebase is still in progress
+        let later = start + BATCH_QUIET_PERIOD * 2;
+        assert!(detector.poll(later).is_none());
+        std::fs::remove_dir(git_dir.join("rebase-merge")).unwrap();
+        assert_eq!(detector.poll(later).unwrap().trigger, BatchTrigger::Rebase);
+
+        let mut detector = BranchSwitchDetector::new(None);
+        let file = workspace.path().join("a.rs");
+        for _ in 1..MASS_EVENT_THRESHOLD {
+            assert_eq!(detector.observe(&modified(&file), start), Observed::Passed);
+        }
+        assert_eq!(
+            detector.observe(&modified(&file), start),
+            Observed::BatchStarted
+        );
+        let batch = detector.poll(start + BATCH_QUIET_PERIOD).unwrap();
+        assert_eq!(batch.trigger, BatchTrigger::MassEvents);
+    }
+
+    #[test]
+    fn test_stopped_rebase_ends_batch() {
+        let workspace = tempfile::tempdir().unwrap();
+        let git_dir = workspace.path().join(".git");
+        std::fs::create_dir_all(git_dir.join("rebase-merge")).unwrap();
+        let mut detector = BranchSwitchDetector::new(Some(git_dir.clone()));
+        let start = Instant::now();
+
+        let todo = git_dir.join("rebase-merge/git-rebase-todo");
+        assert_eq!(
+            detector.observe(&modified(&todo), start),
+            Observed::BatchStarted
+        );
+        let file = workspace.path().join("src/main.rs");
+        assert_eq!(detector.observe(&modified(&file), start), Observed::Batched);
+        let later = start + BATCH_QUIET_PERIOD;
+        assert!(detector.poll(later).is_none());
+
+        // Stopped on a conflict, the rebase waits for the user
+        std::fs::write(git_dir.join("REBASE_HEAD"), "0000000").unwrap();
+        let batch = detector.poll(later).unwrap();
+        assert_eq!(batch.trigger, BatchTrigger::Rebase);
+        assert_eq!(batch.paths, BTreeSet::from([file.clone()]));
+        assert_eq!(detector.observe(&modified(&file), later), Observed::Passed);
+    }
+}
@@ -8,7 +8,7 @@
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use alacritty_terminal::{event::WindowSize, event_loop::Msg};
@@ -45,6 +45,7 @@
 use url::Url;
 
 use crate::{
+    branch_switch::{BranchSwitchDetector, Observed},
     buffer::{get_mod_time, load_file, Buffer},
     plugin::{catalog::PluginCatalog, PluginCatalogRpcHandler},
     terminal::{Terminal, TerminalSender},
@@ -1257,6 +1258,7 @@ struct FileWatchNotifier {
     workspace: Option<PathBuf>,
     workspace_fs_change_handler: Arc<Mutex<Option<Sender<bool>>>>,
     last_diff: Arc<Mutex<DiffInfo>>,
+    branch_switch: Arc<Mutex<BranchSwitchDetector>>,
 }
 
 impl Notify for FileWatchNotifier {
@@ -1271,12 +1273,19 @@ fn new(
         core_rpc: CoreRpcHandler,
         proxy_rpc: ProxyRpcHandler,
     ) -> Self {
+        let git_dir = workspace
+            .as_ref()
+            .and_then(|workspace| Repository::discover(workspace).ok())
+            .and_then(|repo| repo.path().canonicalize().ok());
         let notifier = Self {
             workspace,
             core_rpc,
             proxy_rpc,
             workspace_fs_change_handler: Arc::new(Mutex::new(None)),
             last_diff: Arc::new(Mutex::new(DiffInfo::default())),
+            branch_switch: Arc::new(Mutex::new(BranchSwitchDetector::new(
+                git_dir,
+            ))),
         };
 
         if let Some(workspace) = notifier.workspace.clone() {
@@ -1332,6 +1341,18 @@ fn handle_workspace_fs_event(&self, event: notify::Event) {
             _ => return,
         };
 
+        // A checkout or rebase is batched until it is done, instead of
+        // refreshing for every one of its events
+        let observed = self.branch_switch.lock().observe(&event, Instant::now());
+        match observed {
+            Observed::Passed => {}
+            Observed::BatchStarted => {
+                self.refresh_after_batch();
+                return;
+            }
+            Observed::Batched => return,
+        }
+
         let mut handler = self.workspace_fs_change_handler.lock();
         if let Some(sender) = handler.as_mut() {
             if explorer_change {
@@ -1381,6 +1402,37 @@ fn handle_workspace_fs_event(&self, event: notify::Event) {
         });
         *handler = Some(sender);
     }
+
+    /// Wait for the current batch of events to complete, then reload the
+    /// changed paths and refresh the git status once
+    fn refresh_after_batch(&self) {
+        let branch_switch = self.branch_switch.clone();
+        let core_rpc = self.core_rpc.clone();
+        let workspace = self.workspace.clone();
+        let last_diff = self.last_diff.clone();
+        thread::spawn(move || {
+            let batch = loop {
+                thread::sleep(Duration::from_millis(250));
+                if let Some(batch) = branch_switch.lock().poll(Instant::now()) {
+                    break batch;
+                }
+            };
+            tracing::info!(
+                "Batched {} workspace events ({:?}) touching {} paths",
+                batch.events,
+                batch.trigger,
+                batch.paths.len()
+            );
+            core_rpc.workspace_batch_change(batch.paths.into_iter().collect());
+            if let Some(diff) = workspace.as_deref().and_then(git_diff_new) {
+                let mut last_diff = last_diff.lock();
+                if diff != *last_diff {
+                    core_rpc.diff_info(diff.clone());
+                    *last_diff = diff;
+                }
+            }
+        });
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,309 @@
+//! Shared configuration for outbound HTTP.
+//!
+//! Every HTTP client (assistant providers, the plugin marketplace, the fetch
+//! server and remote MCP transports) is built from [`client_builder`] or
+//! [`async_client_builder`], so proxies, custom CA bundles and client
+//! certificates only need to be configured once. Settings are layered: the
+//! proxy environment variables, then the user's `[http]` settings, then the
+//! active profile, and finally the enterprise policy file, which always wins.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable pointing at an enterprise policy file, overriding
+/// the platform default location
+pub const POLICY_FILE_ENV: &str = "CATALYST_POLICY_FILE";
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+static HTTP_CONFIG: Lazy<RwLock<HttpClientConfig>> =
+    Lazy::new(|| RwLock::new(effective_config(&HttpSettings::default())));
+
+/// A client certificate for mutual TLS
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientCertificate {
+    /// PEM certificate chain, or a PKCS#12 archive when `key` is not set
+    pub certificate: PathBuf,
+    /// PKCS#8 PEM private key
+    #[serde(default)]
+    pub key: Option<PathBuf>,
+    /// Password of a PKCS#12 archive
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// HTTP client options; unset values fall through to the layer below
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HttpClientConfig {
+    /// Proxy for plain HTTP requests
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    /// Proxy for HTTPS requests
+    #[serde(default)]
+    pub https_proxy: Option<String>,
+    /// Comma separated hosts, domains and CIDR ranges that bypass the proxy
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// Extra PEM bundles of trusted root certificates
+    #[serde(default)]
+    pub ca_bundles: Option<Vec<PathBuf>>,
+    #[serde(default)]
+    pub client_certificate: Option<ClientCertificate>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+impl HttpClientConfig {
+    /// Read the conventional proxy environment variables
+    pub fn from_env() -> Self {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .filter(|value| !value.is_empty())
+        };
+        let all_proxy = var(&["ALL_PROXY", "all_proxy"]);
+        Self {
+            http_proxy: var(&["HTTP_PROXY", "http_proxy"]).or(all_proxy.clone()),
+            https_proxy: var(&["HTTPS_PROXY", "https_proxy"]).or(all_proxy),
+            no_proxy: var(&["NO_PROXY", "no_proxy"]),
+            ..Default::default()
+        }
+    }
+
+    /// Override the values of `self` with the ones set in `other`
+    pub fn merge(&mut self, other: &HttpClientConfig) {
+        if other.http_proxy.is_some() {
+            self.http_proxy = other.http_proxy.clone();
+        }
+        if other.https_proxy.is_some() {
+            self.https_proxy = other.https_proxy.clone();
+        }
+        if other.no_proxy.is_some() {
+            self.no_proxy = other.no_proxy.clone();
+        }
+        if other.ca_bundles.is_some() {
+            self.ca_bundles = other.ca_bundles.clone();
+        }
+        if other.client_certificate.is_some() {
+            self.client_certificate = other.client_certificate.clone();
+        }
+        if other.timeout_secs.is_some() {
+            self.timeout_secs = other.timeout_secs;
+        }
+    }
+}
+
+/// The `[http]` section of the settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HttpSettings {
+    #[serde(flatten)]
+    pub base: HttpClientConfig,
+    /// Name of the profile in `profiles` to apply on top of the base values
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, HttpClientConfig>,
+}
+
+/// The `[http]` section of the enterprise policy file
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    http: HttpClientConfig,
+}
+
+/// Get the location of the enterprise policy file
+pub fn policy_file_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(POLICY_FILE_ENV) {
+        return PathBuf::from(path);
+    }
+    if cfg!(windows) {
+        std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+            .join("Catalyst")
+            .join("policy.toml")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from("/Library/Application Support/Catalyst/policy.toml")
+    } else {
+        PathBuf::from("/etc/catalyst/policy.toml")
+    }
+}
+
+/// Read the HTTP section of an enterprise policy file, if there is one
+pub fn load_policy(path: &Path) -> Result<Option<HttpClientConfig>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path)?;
+    let policy: PolicyFile = toml::from_str(&content)
+        .with_context(|| format!("Invalid policy file {}", path.display()))?;
+    Ok(Some(policy.http))
+}
+
+/// Combine the environment, the user settings, the active profile and the
+/// enterprise policy into the configuration clients are built from
+pub fn effective_config(settings: &HttpSettings) -> HttpClientConfig {
+    let mut config = HttpClientConfig::from_env();
+    config.merge(&settings.base);
+    if let Some(name) = &settings.profile {
+        match settings.profiles.get(name) {
+            Some(profile) => config.merge(profile),
+            None => tracing::warn!("Unknown HTTP profile '{name}'"),
+        }
+    }
+    match load_policy(&policy_file_path()) {
+        Ok(Some(policy)) => config.merge(&policy),
+        Ok(None) => {}
+        Err(err) => tracing::error!("{err:#}"),
+    }
+    config
+}
+
+/// Apply new user settings to all clients built from now on
+pub fn set_http_settings(settings: &HttpSettings) {
+    *HTTP_CONFIG.write() = effective_config(settings);
+}
+
+/// Get the configuration clients are currently built from
+pub fn http_config() -> HttpClientConfig {
+    HTTP_CONFIG.read().clone()
+}
+
+/// Apply a configuration to a blocking or async client builder, which have
+/// the same methods but no common trait
+macro_rules! configure_builder {
+    ($builder:expr, $config:expr) => {{
+        let config: &HttpClientConfig = $config;
+        let mut builder = $builder.timeout(Duration::from_secs(
+            config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+        ));
+
+        // Explicit proxies replace the ones reqwest reads from the
+        // environment, which would otherwise ignore our NO_PROXY handling
+        builder = builder.no_proxy();
+        let no_proxy = config
+            .no_proxy
+            .as_deref()
+            .and_then(reqwest::NoProxy::from_string);
+        if let Some(proxy) = &config.http_proxy {
+            builder = builder
+                .proxy(reqwest::Proxy::http(proxy)?.no_proxy(no_proxy.clone()));
+        }
+        if let Some(proxy) = &config.https_proxy {
+            builder =
+                builder.proxy(reqwest::Proxy::https(proxy)?.no_proxy(no_proxy));
+        }
+
+        for bundle in config.ca_bundles.iter().flatten() {
+            let pem = std::fs::read(bundle).with_context(|| {
+                format!("Failed to read CA bundle {}", bundle.display())
+            })?;
+            for certificate in split_pem_certificates(&pem) {
+                builder = builder.add_root_certificate(
+                    reqwest::Certificate::from_pem(&certificate)?,
+                );
+            }
+        }
+
+        if let Some(identity) = &config.client_certificate {
+            builder = builder.identity(load_identity(identity)?);
+        }
+        Ok(builder)
+    }};
+}
+
+/// Create a blocking client builder with the shared configuration applied
+pub fn client_builder() -> Result<reqwest::blocking::ClientBuilder> {
+    configure_builder!(reqwest::blocking::Client::builder(), &http_config())
+}
+
+/// Create a blocking client with the shared configuration
+pub fn client() -> Result<reqwest::blocking::Client> {
+    Ok(client_builder()?.build()?)
+}
+
+/// Create an async client builder with the shared configuration applied
+pub fn async_client_builder() -> Result<reqwest::ClientBuilder> {
+    configure_builder!(reqwest::Client::builder(), &http_config())
+}
+
+fn load_identity(certificate: &ClientCertificate) -> Result<reqwest::Identity> {
+    let cert = std::fs::read(&certificate.certificate).with_context(|| {
+        format!(
+            "Failed to read client certificate {}",
+            certificate.certificate.display()
+        )
+    })?;
+    match &certificate.key {
+        Some(key) => {
+            let key = std::fs::read(key).with_context(|| {
+                format!("Failed to read client key {}", key.display())
+            })?;
+            Ok(reqwest::Identity::from_pkcs8_pem(&cert, &key)?)
+        }
+        None => Ok(reqwest::Identity::from_pkcs12_der(
+            &cert,
+            certificate.password.as_deref().unwrap_or_default(),
+        )?),
+    }
+}
+
+/// Split a PEM bundle into its individual certificates
+fn split_pem_certificates(pem: &[u8]) -> Vec<Vec<u8>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let text = String::from_utf8_lossy(pem);
+    text.split_inclusive(END)
+        .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|block| block.trim().as_bytes().to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_overrides_base_settings() {
+        let settings: HttpSettings = toml::from_str(
+            r#"
+https-proxy = "http://proxy.corp:3128"
+no-proxy = "localhost,.corp"
+profile = "home"
+
+[profiles.home]
+https-proxy = "http://127.0.0.1:8080"
+timeout-secs = 5
+"#,
+        )
+        .unwrap();
+
+        let mut config = HttpClientConfig::default();
+        config.merge(&settings.base);
+        config.merge(&settings.profiles["home"]);
+        assert_eq!(config.https_proxy.as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(config.no_proxy.as_deref(), Some("localhost,.corp"));
+        assert_eq!(config.timeout_secs, Some(5));
+    }
+
+    #[test]
+    fn test_split_pem_bundle() {
+        let bundle = b"-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+                       -----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        let certificates = split_pem_certificates(bundle);
+        assert_eq!(certificates.len(), 2);
+        assert!(certificates[1].starts_with(b"-----BEGIN CERTIFICATE-----\nBBB"));
+    }
+}
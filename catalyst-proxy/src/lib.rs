@@ -1,8 +1,10 @@
 #![allow(clippy::manual_clamp)]
 
+pub mod branch_switch;
 pub mod buffer;
 pub mod cli;
 pub mod dispatch;
+pub mod http;
 pub mod plugin;
 pub mod terminal;
 pub mod watcher;
@@ -190,15 +192,8 @@ pub fn get_url<T: reqwest::IntoUrl + Clone>(
     url: T,
     user_agent: Option<&str>,
 ) -> Result<reqwest::blocking::Response> {
-    let mut builder = if let Ok(proxy) = std::env::var("https_proxy") {
-        let proxy = reqwest::Proxy::all(proxy)?;
-        reqwest::blocking::Client::builder()
-            .proxy(proxy)
-            .timeout(std::time::Duration::from_secs(10))
-    } else {
-        reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(10))
-    };
+    let mut builder =
+        http::client_builder()?.timeout(std::time::Duration::from_secs(10));
     if let Some(user_agent) = user_agent {
         builder = builder.user_agent(user_agent);
     }
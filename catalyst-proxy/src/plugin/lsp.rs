@@ -1,7 +1,7 @@
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 use std::{
-    io::{BufRead, BufReader, BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
     process::{self, Child, Command, Stdio},
     str::FromStr,
@@ -39,6 +39,11 @@
 
 const HEADER_CONTENT_LENGTH: &str = "content-length";
 const HEADER_CONTENT_TYPE: &str = "content-type";
+/// Longest header line accepted from a server
+const MAX_HEADER_LEN: u64 = 1024;
+/// Largest message accepted from a server, so a broken content length
+/// doesn't make us allocate whatever it claims
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
 
 pub enum LspRpc {
     Request {
@@ -495,28 +500,39 @@ pub enum LspHeader {
 }
 
 fn parse_header(s: &str) -> Result<LspHeader> {
-    let split: Vec<String> =
-        s.splitn(2, ": ").map(|s| s.trim().to_lowercase()).collect();
-    if split.len() != 2 {
-        return Err(anyhow!("Malformed"));
-    };
-    match split[0].as_ref() {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Malformed header: {:?}", s.trim()))?;
+    match name.trim().to_lowercase().as_ref() {
         HEADER_CONTENT_TYPE => Ok(LspHeader::ContentType),
         HEADER_CONTENT_LENGTH => {
-            Ok(LspHeader::ContentLength(split[1].parse::<usize>()?))
+            Ok(LspHeader::ContentLength(value.trim().parse::<usize>()?))
         }
-        _ => Err(anyhow!("Unknown parse error occurred")),
+        _ => Err(anyhow!("Unknown header: {:?}", s.trim())),
     }
 }
 
+/// Read the next message framed by a `Content-Length` header
+///
+/// The input comes from a server process and is not trusted: malformed or
+/// oversized headers, bodies that are truncated or not UTF-8 and end of
+/// input are errors, never panics.
 pub fn read_message<T: BufRead>(reader: &mut T) -> Result<String> {
     let mut buffer = String::new();
     let mut content_length: Option<usize> = None;
 
     loop {
         buffer.clear();
-        let _ = reader.read_line(&mut buffer)?;
-        // eprin
+        let read = reader
+            .by_ref()
+            .take(MAX_HEADER_LEN)
+            .read_line(&mut buffer)?;
+        if read == 0 {
+            return Err(anyhow!("server closed the connection"));
+        }
+        if !buffer.ends_with('\n') && read as u64 == MAX_HEADER_LEN {
+            return Err(anyhow!("header longer than {} bytes", MAX_HEADER_LEN));
+        }
         match &buffer {
             s if s.trim().is_empty() => break,
             s => {
@@ -528,8 +544,15 @@ pub fn read_message<T: BufRead>(reader: &mut T) -> Result<String> {
         };
     }
 
-    let content_length = content_length
-        .ok_or_else(|| anyhow!("missing content-length header: {}", buffer))?;
+    let content_length =
+        content_length.ok_or_else(|| anyhow!("missing content-length header"))?;
+    if content_length > MAX_MESSAGE_LEN {
+        return Err(anyhow!(
+            "message of {} bytes exceeds the limit of {} bytes",
+            content_length,
+            MAX_MESSAGE_LEN
+        ));
+    }
 
     let mut body_buffer = vec![0; content_length];
     reader.read_exact(&mut body_buffer)?;
@@ -558,3 +581,89 @@ pub fn get_change_for_sync_kind(
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn frame(body: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    /// Language server and debug adapter traffic, also the seed corpus of
+    /// the `lsp_message` fuzz target
+    fn corpus() -> Vec<Vec<u8>> {
+        let dir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../fuzz/corpus/lsp_message");
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_message() {
+        for capture in corpus() {
+            let mut reader = Cursor::new(capture);
+            let mut count = 0;
+            while let Ok(message) = read_message(&mut reader) {
+                serde_json::from_str::<Value>(&message).unwrap();
+                count += 1;
+            }
+            assert!(count > 0);
+        }
+
+        let malformed: [&[u8]; 6] = [
+            b"Content-Length: 5\r\n\r\nab",
+            b"Content-Length: 4\r\n\r\n\xf0\x9f\xa6",
+            b"Content-Length: -1\r\n\r\n",
+            b"Content-Length: 99999999999999999999\r\n\r\n",
+            b"Content-Type: application/json\r\n\r\n{}",
+            b"X-Unknown\r\n\r\n{}",
+        ];
+        for input in malformed {
+            assert!(read_message(&mut Cursor::new(input)).is_err());
+        }
+        let huge = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_LEN + 1);
+        assert!(read_message(&mut Cursor::new(huge)).is_err());
+        let endless = vec![b'a'; MAX_HEADER_LEN as usize * 2];
+        assert!(read_message(&mut Cursor::new(endless)).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_read_message_never_panics(
+            bytes in prop::collection::vec(any::<u8>(), 0..512),
+        ) {
+            let mut reader = Cursor::new(bytes);
+            while read_message(&mut reader).is_ok() {}
+        }
+
+        #[test]
+        fn test_interleaved_messages_round_trip(
+            bodies in prop::collection::vec("\\PC*", 1..8),
+        ) {
+            let stream: Vec<u8> =
+                bodies.iter().flat_map(|body| frame(body)).collect();
+            let mut reader = Cursor::new(stream);
+            for body in &bodies {
+                prop_assert_eq!(&read_message(&mut reader).unwrap(), body);
+            }
+            prop_assert!(read_message(&mut reader).is_err());
+        }
+
+        #[test]
+        fn test_truncated_messages_are_errors(
+            body in "\\PC{1,64}",
+            cut in 0usize..128,
+        ) {
+            let framed = frame(&body);
+            let cut = cut.min(framed.len() - 1);
+            prop_assert!(read_message(&mut Cursor::new(&framed[..cut])).is_err());
+        }
+    }
+}
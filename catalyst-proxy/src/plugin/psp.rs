@@ -559,15 +559,30 @@ pub fn handle_plugin_server_message(
     match JsonRpc::parse(message) {
         Ok(value @ JsonRpc::Request(_)) => {
             let (tx, rx) = crossbeam_channel::bounded(1);
-            let id = value.get_id().unwrap();
+            let id = value.get_id()?;
+            // Answer a request the host can't dispatch, so the server
+            // isn't left waiting on it
+            let Some(method) = value.get_method() else {
+                return Some(JsonRpc::error(
+                    id,
+                    jsonrpc_lite::Error::invalid_params(),
+                ));
+            };
             let rpc = PluginServerRpc::HostRequest {
                 id: id.clone(),
-                method: value.get_method().unwrap().to_string(),
-                params: value.get_params().unwrap(),
+                method: method.to_string(),
+                // Params are optional, servers may leave them out
+                params: value.get_params().unwrap_or(Params::None(())),
                 resp: ResponseSender::new(tx),
             };
             server_rpc.handle_rpc(rpc);
-            let result = rx.recv().unwrap();
+            // A handler that went away never answers
+            let result = rx.recv().unwrap_or_else(|_| {
+                Err(RpcError {
+                    code: 0,
+                    message: "request was not handled".to_string(),
+                })
+            });
             let resp = match result {
                 Ok(v) => JsonRpc::success(id, &v),
                 Err(e) => JsonRpc::error(
@@ -583,22 +598,22 @@ pub fn handle_plugin_server_message(
         }
         Ok(value @ JsonRpc::Notification(_)) => {
             let rpc = PluginServerRpc::HostNotification {
-                method: value.get_method().unwrap().to_string(),
-                params: value.get_params().unwrap(),
+                method: value.get_method()?.to_string(),
+                params: value.get_params().unwrap_or(Params::None(())),
                 from: from.to_string(),
             };
             server_rpc.handle_rpc(rpc);
             None
         }
         Ok(value @ JsonRpc::Success(_)) => {
-            let result = value.get_result().unwrap().clone();
-            server_rpc.handle_server_response(value.get_id().unwrap(), Ok(result));
+            let result = value.get_result()?.clone();
+            server_rpc.handle_server_response(value.get_id()?, Ok(result));
             None
         }
         Ok(value @ JsonRpc::Error(_)) => {
-            let error = value.get_error().unwrap();
+            let error = value.get_error()?;
             server_rpc.handle_server_response(
-                value.get_id().unwrap(),
+                value.get_id()?,
                 Err(RpcError {
                     code: error.code,
                     message: error.message.clone(),
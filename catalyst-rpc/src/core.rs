@@ -66,6 +66,11 @@ pub enum CoreNotification {
         paths: Vec<PathObject>,
     },
     WorkspaceFileChange,
+    /// Files changed in bulk, as by a branch switch, reported once the
+    /// watcher's batch of their events is complete
+    WorkspaceBatchChange {
+        paths: Vec<PathBuf>,
+    },
     PublishDiagnostics {
         diagnostics: PublishDiagnosticsParams,
     },
@@ -254,6 +259,10 @@ pub fn workspace_file_change(&self) {
         self.notification(CoreNotification::WorkspaceFileChange);
     }
 
+    pub fn workspace_batch_change(&self, paths: Vec<PathBuf>) {
+        self.notification(CoreNotification::WorkspaceBatchChange { paths });
+    }
+
     pub fn diff_info(&self, diff: DiffInfo) {
         self.notification(CoreNotification::DiffInfo { diff });
     }
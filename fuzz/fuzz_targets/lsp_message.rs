@@ -0,0 +1,13 @@
+//! Content-Length framed messages of language servers and debug adapters
+#![no_main]
+
+use catalyst_proxy::plugin::lsp::read_message;
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Cursor::new(data);
+    while let Ok(message) = read_message(&mut reader) {
+        let _ = serde_json::from_str::<serde_json::Value>(&message);
+    }
+});
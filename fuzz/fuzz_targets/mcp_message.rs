@@ -0,0 +1,32 @@
+//! Line framed messages of MCP servers and clients
+#![no_main]
+
+use catalyst_mcp_protocol::{
+    LineTransport, McpRequest, McpResponse, McpTransport, serve_lines,
+};
+use libfuzzer_sys::fuzz_target;
+use std::io::{Cursor, sink};
+
+fuzz_target!(|data: &[u8]| {
+    // As a server answering whatever a client sends
+    let _ = serve_lines(Cursor::new(data), sink(), |request| McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request.id,
+        result: Some(serde_json::Value::Null),
+        error: None,
+    });
+
+    // As a client reading whatever a server answers
+    let mut transport = LineTransport::new(Cursor::new(data), sink());
+    for id in ["0", "1", "2"] {
+        let request = McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: id.into(),
+            method: "ping".to_string(),
+            params: None,
+        };
+        if transport.request(&request).is_err() {
+            break;
+        }
+    }
+});
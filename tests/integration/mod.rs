@@ -1,9 +1,15 @@
+use catalyst_app::plugin_api::{
+    Report, ReportFormat, ReportSection, ReportTestCase, TestReport,
+};
 use parking_lot::Mutex;
+use serde::Serialize;
 /// Integration Test Framework for Catalyst IDE
 ///
 /// Provides end-to-end testing capabilities for the complete Catalyst IDE system,
 /// including UI, backend, MCP servers, and Claude AI integration.
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -13,6 +19,14 @@
 pub mod mcp_integration_tests;
 pub mod performance_integration_tests;
 
+/// Environment variable naming the report directory, for runs through
+/// `cargo test` where the test harness owns the command line
+pub const REPORT_DIR_ENV: &str = "CATALYST_TEST_REPORT_DIR";
+
+/// Environment variable holding the seed Catalyst runs deterministically
+/// with, read by the app as well as the runner
+pub const DETERMINISTIC_SEED_ENV: &str = "CATALYST_DETERMINISTIC_SEED";
+
 /// Integration test configuration
 #[derive(Debug, Clone)]
 pub struct IntegrationTestConfig {
@@ -21,6 +35,12 @@ pub struct IntegrationTestConfig {
     pub ui_automation_enabled: bool,
     pub mcp_servers_enabled: bool,
     pub claude_ai_enabled: bool,
+    /// Directory to write the JUnit XML, HTML and text reports to
+    pub report_dir: Option<PathBuf>,
+    /// Seed the started Catalyst runs deterministically with; set
+    /// `CATALYST_DETERMINISTIC_SEED` to the seed of a failed run to
+    /// reproduce it
+    pub deterministic_seed: u64,
 }
 
 impl Default for IntegrationTestConfig {
@@ -31,14 +51,56 @@ fn default() -> Self {
             ui_automation_enabled: false, // Requires display server
             mcp_servers_enabled: true,
             claude_ai_enabled: false, // Requires API keys
+            report_dir: std::env::var_os(REPORT_DIR_ENV).map(PathBuf::from),
+            deterministic_seed: std::env::var(DETERMINISTIC_SEED_ENV)
+                .ok()
+                .and_then(|seed| seed.trim().parse().ok())
+                .unwrap_or_else(fresh_seed),
         }
     }
 }
 
+/// Pick a seed for a run that wasn't given one
+fn fresh_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+impl IntegrationTestConfig {
+    /// Build a configuration from command line arguments
+    ///
+    /// Supports `--report-dir <dir>`, `--report-dir=<dir>` and
+    /// `--seed <seed>`; other arguments are left to the caller.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--report-dir" {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| "--report-dir requires a directory".to_string())?;
+                config.report_dir = Some(PathBuf::from(dir));
+            } else if let Some(dir) = arg.strip_prefix("--report-dir=") {
+                config.report_dir = Some(PathBuf::from(dir));
+            } else if arg == "--seed" {
+                config.deterministic_seed = args
+                    .next()
+                    .and_then(|seed| seed.parse().ok())
+                    .ok_or_else(|| "--seed requires a number".to_string())?;
+            }
+        }
+        Ok(config)
+    }
+}
+
 /// Integration test runner
 pub struct IntegrationTestRunner {
     config: IntegrationTestConfig,
     catalyst_process: Arc<Mutex<Option<Child>>>,
+    /// Output of the test currently running
+    log: Arc<Mutex<Vec<String>>>,
 }
 
 impl IntegrationTestRunner {
@@ -46,9 +108,31 @@ pub fn new(config: IntegrationTestConfig) -> Self {
         Self {
             config,
             catalyst_process: Arc::new(Mutex::new(None)),
+            log: Arc::new(Mutex::new(Vec::new())),
         }
     }
     
+    /// Print a line and keep it in the log of the running test
+    fn log(&self, line: &str) {
+        println!("{}", line);
+        self.log.lock().push(line.to_string());
+    }
+    
+    /// Run one test, recording its result, wall time and log
+    fn run_test(
+        &self,
+        results: &mut IntegrationTestResults,
+        test_name: &str,
+        test: impl FnOnce(&Self) -> Result<Duration, String>,
+    ) {
+        self.log.lock().clear();
+        let start = Instant::now();
+        let result = test(self);
+        let elapsed = start.elapsed();
+        let log = std::mem::take(&mut *self.log.lock());
+        results.add_result_with_log(test_name, result, elapsed, log);
+    }
+    
     /// Start Catalyst IDE process for integration testing
     pub fn start_catalyst(&self) -> Result<(), String> {
         let mut process_guard = self.catalyst_process.lock();
@@ -57,10 +141,11 @@ pub fn start_catalyst(&self) -> Result<(), String> {
             return Ok(()); // Already running
         }
         
-        println!("Starting Catalyst IDE for integration testing...");
+        self.log("Starting Catalyst IDE for integration testing...");
         
         let child = Command::new(&self.config.catalyst_binary_path)
             .args(&["--test-mode", "--no-ui"]) // Hypothetical test flags
+            .env(DETERMINISTIC_SEED_ENV, self.config.deterministic_seed.to_string())
             .spawn()
             .map_err(|e| format!("Failed to start Catalyst: {}", e))?;
         
@@ -104,29 +189,35 @@ pub fn is_catalyst_running(&self) -> bool {
     pub fn run_full_integration_test(&self) -> Result<IntegrationTestResults, String> {
         let start_time = Instant::now();
         let mut results = IntegrationTestResults::new();
+        results.deterministic_seed = Some(self.config.deterministic_seed);
         
-        println!("Starting full integration test suite...");
+        println!(
+            "Starting full integration test suite with seed {}...",
+            self.config.deterministic_seed
+        );
         
         // Test 1: Basic startup and shutdown
-        let startup_result = self.test_basic_startup_shutdown();
-        results.add_result("basic_startup_shutdown", startup_result);
+        self.run_test(&mut results, "basic_startup_shutdown", Self::test_basic_startup_shutdown);
         
         // Test 2: MCP server integration (if enabled)
         if self.config.mcp_servers_enabled {
-            let mcp_result = self.test_mcp_integration();
-            results.add_result("mcp_integration", mcp_result);
+            self.run_test(&mut results, "mcp_integration", Self::test_mcp_integration);
         }
         
         // Test 3: Performance under load
-        let performance_result = self.test_performance_under_load();
-        results.add_result("performance_under_load", performance_result);
+        self.run_test(&mut results, "performance_under_load", Self::test_performance_under_load);
         
         // Test 4: Error handling and recovery
-        let error_handling_result = self.test_error_handling();
-        results.add_result("error_handling", error_handling_result);
+        self.run_test(&mut results, "error_handling", Self::test_error_handling);
         
         results.total_duration = start_time.elapsed();
         
+        if let Some(report_dir) = &self.config.report_dir {
+            for path in results.write_reports(report_dir)? {
+                println!("Wrote {}", path.display());
+            }
+        }
+        
         Ok(results)
     }
     
@@ -153,7 +244,7 @@ fn test_mcp_integration(&self) -> Result<Duration, String> {
         // This would test MCP server connectivity in a real integration test
         // For now, we'll simulate it
         
-        println!("Testing MCP server integration...");
+        self.log("Testing MCP server integration...");
         
         // Simulate MCP server tests
         std::thread::sleep(Duration::from_millis(50));
@@ -165,7 +256,7 @@ fn test_performance_under_load(&self) -> Result<Duration, String> {
         let start = Instant::now();
         
         // This would test performance under simulated load
-        println!("Testing performance under load...");
+        self.log("Testing performance under load...");
         
         // Simulate load testing
         std::thread::sleep(Duration::from_millis(100));
@@ -177,7 +268,7 @@ fn test_error_handling(&self) -> Result<Duration, String> {
         let start = Instant::now();
         
         // Test error handling and recovery
-        println!("Testing error handling and recovery...");
+        self.log("Testing error handling and recovery...");
         
         // Simulate error conditions
         std::thread::sleep(Duration::from_millis(30));
@@ -194,24 +285,66 @@ fn drop(&mut self) {
 }
 
 /// Integration test results
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct IntegrationTestResults {
-    pub results: std::collections::HashMap<String, Result<Duration, String>>,
+    pub results: HashMap<String, Result<Duration, String>>,
+    /// Wall time of each test, including the failed ones
+    pub timings: HashMap<String, Duration>,
+    /// Lines logged by each test
+    pub logs: HashMap<String, Vec<String>>,
     pub total_duration: Duration,
+    /// Seed Catalyst ran with, to reproduce the run
+    pub deterministic_seed: Option<u64>,
 }
 
 impl IntegrationTestResults {
     pub fn new() -> Self {
         Self {
-            results: std::collections::HashMap::new(),
+            results: HashMap::new(),
+            timings: HashMap::new(),
+            logs: HashMap::new(),
             total_duration: Duration::from_secs(0),
+            deterministic_seed: None,
         }
     }
     
     pub fn add_result(&mut self, test_name: &str, result: Result<Duration, String>) {
+        if let Ok(duration) = &result {
+            self.timings.insert(test_name.to_string(), *duration);
+        }
         self.results.insert(test_name.to_string(), result);
     }
     
+    pub fn add_result_with_log(
+        &mut self,
+        test_name: &str,
+        result: Result<Duration, String>,
+        elapsed: Duration,
+        log: Vec<String>,
+    ) {
+        self.add_result(test_name, result);
+        self.timings.insert(test_name.to_string(), elapsed);
+        self.logs.insert(test_name.to_string(), log);
+    }
+    
+    /// Test names in a stable order for the reports
+    fn test_names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.results.keys().collect();
+        names.sort();
+        names
+    }
+    
+    fn timing(&self, test_name: &str) -> Duration {
+        self.timings.get(test_name).copied().unwrap_or_default()
+    }
+    
+    fn log_text(&self, test_name: &str) -> String {
+        self.logs
+            .get(test_name)
+            .map(|log| log.join("\n"))
+            .unwrap_or_default()
+    }
+    
     pub fn passed_count(&self) -> usize {
         self.results.values().filter(|r| r.is_ok()).count()
     }
@@ -238,7 +371,14 @@ pub fn generate_report(&self) -> String {
         report.push_str(&format!("Passed: {}\n", self.passed_count()));
         report.push_str(&format!("Failed: {}\n", self.failed_count()));
         report.push_str(&format!("Success Rate: {:.1}%\n", self.success_rate() * 100.0));
-        report.push_str(&format!("Total Duration: {:?}\n\n", self.total_duration));
+        report.push_str(&format!("Total Duration: {:?}\n", self.total_duration));
+        if let Some(seed) = self.deterministic_seed {
+            report.push_str(&format!(
+                "Reproduce with: {}={}\n",
+                DETERMINISTIC_SEED_ENV, seed
+            ));
+        }
+        report.push('\n');
         
         for (test_name, result) in &self.results {
             match result {
@@ -253,6 +393,99 @@ pub fn generate_report(&self) -> String {
         
         report
     }
+    
+    /// Generate a JUnit XML report, as read by CI systems
+    pub fn generate_junit_xml(&self) -> String {
+        self.render_junit()
+    }
+    
+    /// Generate an HTML report with the timing and log of every test
+    pub fn generate_html_report(&self) -> Result<String, String> {
+        self.render(ReportFormat::Html)
+            .map_err(|e| format!("Failed to render the HTML report: {}", e))
+    }
+    
+    /// Write the JUnit XML, HTML and text reports to a directory, returning
+    /// the files written
+    pub fn write_reports(&self, dir: &Path) -> Result<Vec<PathBuf>, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        
+        let reports = [
+            ("junit.xml", self.generate_junit_xml()),
+            ("report.html", self.generate_html_report()?),
+            ("report.txt", self.generate_report()),
+        ];
+        let mut paths = Vec::new();
+        for (file_name, content) in reports {
+            let path = dir.join(file_name);
+            std::fs::write(&path, content)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            paths.push(path);
+        }
+        
+        Ok(paths)
+    }
+}
+
+impl Report for IntegrationTestResults {
+    fn title(&self) -> String {
+        "Integration Test Results".to_string()
+    }
+    
+    fn subtitle(&self) -> Option<String> {
+        let mut subtitle = format!(
+            "{} passed, {} failed, {:.1}% success rate, {:.3}s total",
+            self.passed_count(),
+            self.failed_count(),
+            self.success_rate() * 100.0,
+            self.total_duration.as_secs_f64()
+        );
+        if let Some(seed) = self.deterministic_seed {
+            subtitle.push_str(&format!(
+                ", reproduce with {}={}",
+                DETERMINISTIC_SEED_ENV, seed
+            ));
+        }
+        Some(subtitle)
+    }
+    
+    fn sections(&self) -> Vec<ReportSection> {
+        let mut summary = ReportSection::new("Tests");
+        for test_name in self.test_names() {
+            let time = self.timing(test_name).as_secs_f64();
+            let outcome = match &self.results[test_name] {
+                Ok(_) => format!("passed in {:.3}s", time),
+                Err(error) => format!("failed in {:.3}s: {}", time, error),
+            };
+            summary = summary.row(test_name.as_str(), outcome);
+        }
+        
+        let mut sections = vec![summary];
+        for test_name in self.test_names() {
+            let mut section = ReportSection::new(test_name);
+            let log = self.logs.get(test_name).into_iter().flatten();
+            for (line, text) in log.enumerate() {
+                section = section.row((line + 1).to_string(), text.as_str());
+            }
+            sections.push(section);
+        }
+        sections
+    }
+}
+
+impl TestReport for IntegrationTestResults {
+    fn test_cases(&self) -> Vec<ReportTestCase> {
+        self.test_names()
+            .into_iter()
+            .map(|test_name| ReportTestCase {
+                name: test_name.clone(),
+                time: self.timing(test_name),
+                failure: self.results[test_name].as_ref().err().cloned(),
+                output: self.log_text(test_name),
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -287,6 +520,72 @@ fn test_integration_test_results() {
         println!("✅ Integration test results test passed");
     }
     
+    #[test]
+    fn test_integration_test_report_files() {
+        let mut results = IntegrationTestResults::new();
+        results.add_result_with_log(
+            "startup",
+            Ok(Duration::from_millis(120)),
+            Duration::from_millis(125),
+            vec!["Starting Catalyst IDE for integration testing...".to_string()],
+        );
+        results.add_result_with_log(
+            "mcp_integration",
+            Err("server <github> exited".to_string()),
+            Duration::from_millis(40),
+            vec!["Testing MCP server integration...".to_string()],
+        );
+        
+        let junit = results.generate_junit_xml();
+        assert!(junit.contains("tests=\"2\" failures=\"1\""));
+        assert!(junit.contains("name=\"startup\" time=\"0.125\""));
+        assert!(junit.contains("<failure message=\"server &lt;github&gt; exited\">"));
+        assert!(junit.contains("<system-out>Testing MCP server integration...</system-out>"));
+        
+        let html = results.generate_html_report().unwrap();
+        assert!(html.contains("1 passed, 1 failed"));
+        assert!(html.contains("<td>failed in 0.040s: server &lt;github&gt; exited</td>"));
+        assert!(html.contains(
+            "<td>Starting Catalyst IDE for integration testing...</td>"
+        ));
+        
+        let config = IntegrationTestConfig::from_args(
+            ["--report-dir".to_string(), "target/integration".to_string()],
+        )
+        .unwrap();
+        assert_eq!(config.report_dir, Some(PathBuf::from("target/integration")));
+        assert!(IntegrationTestConfig::from_args(["--report-dir".to_string()]).is_err());
+        
+        let dir = std::env::temp_dir().join(format!("catalyst-report-{}", std::process::id()));
+        let paths = results.write_reports(&dir).unwrap();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.iter().all(|path| path.exists()));
+        let _ = std::fs::remove_dir_all(&dir);
+        
+        println!("✅ Integration test report files test passed");
+    }
+    
+    #[test]
+    fn test_deterministic_seed() {
+        let config = IntegrationTestConfig::from_args(
+            ["--seed".to_string(), "1234".to_string()],
+        )
+        .unwrap();
+        assert_eq!(config.deterministic_seed, 1234);
+        assert!(IntegrationTestConfig::from_args(
+            ["--seed".to_string(), "soon".to_string()],
+        )
+        .is_err());
+        
+        let mut results = IntegrationTestResults::new();
+        results.deterministic_seed = Some(config.deterministic_seed);
+        assert!(results
+            .generate_report()
+            .contains("Reproduce with: CATALYST_DETERMINISTIC_SEED=1234"));
+        
+        println!("✅ Deterministic seed test passed");
+    }
+    
     #[test]
     fn test_simulated_integration_test() {
         // This is a simulated integration test since we can't run the full Catalyst binary
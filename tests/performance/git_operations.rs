@@ -1,7 +1,7 @@
 /// Git Operations Performance Tests
 /// 
-/// Tests the critical git performance requirement:
-/// - Git Status: < 100ms for 10k files
+/// Times git status, diff and log on a 10k file repository against this
+/// machine's baseline
 
 use super::*;
 use std::fs;
@@ -111,13 +111,7 @@ fn test_git_status_performance_10k_files() {
             
         println!("Git status found {} modified files in {:?}", modified_files, git_status_time);
         
-        // This test will initially fail - implementing TDD
-        assert!(
-            git_status_time.as_millis() < GIT_STATUS_THRESHOLD_MS,
-            "Git status time {} ms exceeds threshold {} ms for repository with 10k files. Need to optimize git operations!",
-            git_status_time.as_millis(),
-            GIT_STATUS_THRESHOLD_MS
-        );
+        assert_no_regression("git_status", git_status_time);
     }
     
     #[test]
@@ -191,15 +185,7 @@ fn test_git_diff_performance() {
             
         println!("Git diff processed {} lines in {:?}", diff_lines, git_diff_time);
         
-        // Git diff should be reasonably fast
-        let diff_threshold_ms = 200; // More generous threshold for diff operations
-        
-        assert!(
-            git_diff_time.as_millis() < diff_threshold_ms,
-            "Git diff time {} ms exceeds threshold {} ms. Need to optimize git diff operations!",
-            git_diff_time.as_millis(),
-            diff_threshold_ms
-        );
+        assert_no_regression("git_diff", git_diff_time);
     }
     
     #[test]
@@ -268,14 +254,6 @@ fn test_git_log_performance() {
             
         println!("Git log retrieved {} entries in {:?}", log_entries, git_log_time);
         
-        // Git log should be very fast
-        let log_threshold_ms = 50; // Fast threshold for log operations
-        
-        assert!(
-            git_log_time.as_millis() < log_threshold_ms,
-            "Git log time {} ms exceeds threshold {} ms. Need to optimize git log operations!",
-            git_log_time.as_millis(),
-            log_threshold_ms
-        );
+        assert_no_regression("git_log", git_log_time);
     }
 }
\ No newline at end of file
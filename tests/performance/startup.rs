@@ -1,8 +1,6 @@
 /// Startup Performance Tests
 /// 
-/// Tests the critical startup performance requirements:
-/// - Cold start: < 500ms
-/// - Warm start: < 200ms
+/// Times cold and warm starts against this machine's baseline
 
 use super::*;
 use std::time::Instant;
@@ -43,13 +41,7 @@ fn test_cold_start_performance() {
         
         println!("Cold start time: {:?}", cold_start_time);
         
-        // This test will initially fail - implementing TDD
-        assert!(
-            cold_start_time.as_millis() < COLD_START_THRESHOLD_MS,
-            "Cold start time {} ms exceeds threshold {} ms. Need to optimize startup!",
-            cold_start_time.as_millis(),
-            COLD_START_THRESHOLD_MS
-        );
+        assert_no_regression("cold_start", cold_start_time);
     }
     
     #[test]
@@ -80,13 +72,7 @@ fn test_warm_start_performance() {
         
         println!("Warm start time: {:?}", warm_start_time);
         
-        // This test will initially fail - implementing TDD
-        assert!(
-            warm_start_time.as_millis() < WARM_START_THRESHOLD_MS,
-            "Warm start time {} ms exceeds threshold {} ms. Need to optimize warm startup!",
-            warm_start_time.as_millis(),
-            WARM_START_THRESHOLD_MS
-        );
+        assert_no_regression("warm_start", warm_start_time);
     }
     
     #[test]
@@ -120,12 +106,6 @@ fn test_startup_with_empty_project() {
         
         println!("Project startup time: {:?}", project_start_time);
         
-        // Project startup should be under cold start threshold
-        assert!(
-            project_start_time.as_millis() < COLD_START_THRESHOLD_MS,
-            "Project startup time {} ms exceeds cold start threshold {} ms",
-            project_start_time.as_millis(),
-            COLD_START_THRESHOLD_MS
-        );
+        assert_no_regression("project_start", project_start_time);
     }
 }
\ No newline at end of file